@@ -0,0 +1,175 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! Reads the answers to Rooster's questions, like confirmations or the number of a password in
+//! a list. They come from stdin, unless the master password was piped in there: then they come
+//! from the terminal, and can't be given without one. When stdin is the input of the command,
+//! like the password of `rooster add`, the master password is typed in the terminal too.
+//!
+//! With --non-interactive, Rooster exits instead of asking anything, so that scripts never hang.
+
+use exit_code::ExitCode;
+use output_format;
+use rpassword::prompt_password_stderr;
+use safe_string::SafeString;
+use askpass;
+use std::fs::{File, OpenOptions};
+use std::io::{stderr, stdin, BufRead, BufReader, Error as IoError, ErrorKind as IoErrorKind,
+              Result as IoResult, Write};
+use sys;
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(test)]
+use std::cell::RefCell;
+#[cfg(test)]
+use std::collections::VecDeque;
+
+static STDIN_TAKEN: AtomicBool = AtomicBool::new(false);
+static STDIN_IS_INPUT: AtomicBool = AtomicBool::new(false);
+static NON_INTERACTIVE: AtomicBool = AtomicBool::new(false);
+
+#[cfg(test)]
+thread_local! {
+    static SCRIPTED: RefCell<Option<VecDeque<String>>> = RefCell::new(None);
+}
+
+/// Answers the next questions asked on this thread with `answers`, one line each, instead of
+/// reading them. Passwords are answered too. Once they run out, it's as if stdin was closed.
+/// `None` goes back to stdin.
+#[cfg(test)]
+pub fn script(answers: Option<&[&str]>) {
+    let answers = answers.map(|answers| answers.iter().map(|a| format!("{}\n", a)).collect());
+    SCRIPTED.with(|scripted| *scripted.borrow_mut() = answers);
+}
+
+/// Whether the answers are scripted, in which case nothing should be read from the terminal.
+#[cfg(test)]
+pub fn is_scripted() -> bool {
+    SCRIPTED.with(|scripted| scripted.borrow().is_some())
+}
+
+#[cfg(not(test))]
+pub fn is_scripted() -> bool {
+    false
+}
+
+#[cfg(test)]
+fn read_scripted_line(line: &mut String) -> Option<usize> {
+    SCRIPTED.with(|scripted| {
+        scripted.borrow_mut().as_mut().map(|answers| {
+            let answer = answers.pop_front().unwrap_or_default();
+            line.push_str(&answer);
+            answer.len()
+        })
+    })
+}
+
+/// Set with --non-interactive or $ROOSTER_NON_INTERACTIVE.
+pub fn set_non_interactive(non_interactive: bool) {
+    NON_INTERACTIVE.store(non_interactive, Ordering::SeqCst);
+}
+
+pub fn is_non_interactive() -> bool {
+    NON_INTERACTIVE.load(Ordering::SeqCst)
+}
+
+/// Exits with `ExitCode::WouldAsk` and a line scripts can grep for, if Rooster may not
+/// ask for `what`.
+pub fn require_interactive(what: &str) {
+    if is_non_interactive() {
+        let message = format!("rooster: non-interactive: would ask for {}", what);
+        println_stderr_always!("{}", message);
+        output_format::record(message);
+        ExitCode::WouldAsk.exit();
+    }
+}
+
+/// Asks for a password without echoing it.
+pub fn prompt_password(prompt: &str, what: &str) -> IoResult<SafeString> {
+    require_interactive(what);
+    #[cfg(test)]
+    {
+        let mut line = String::new();
+        if read_scripted_line(&mut line).is_some() {
+            let len = line.trim_end_matches('\n').len();
+            line.truncate(len);
+            return Ok(SafeString::new(line));
+        }
+    }
+    if STDIN_IS_INPUT.load(Ordering::SeqCst) {
+        return prompt_password_in_terminal(prompt);
+    }
+    prompt_password_stderr(prompt).map(SafeString::new)
+}
+
+/// Asks for a password in the terminal without echoing it, for when stdin is the input of the
+/// command.
+fn prompt_password_in_terminal(prompt: &str) -> IoResult<SafeString> {
+    let tty = OpenOptions::new().read(true).write(true).open("/dev/tty").map_err(|_| {
+        IoError::new(IoErrorKind::Other,
+                     "stdin is the input of the command, and there is no terminal to ask in")
+    })?;
+    let no_echo = match sys::NoEcho::enter(&tty) {
+        Some(no_echo) => no_echo,
+        None => {
+            return Err(IoError::new(IoErrorKind::Other,
+                                    "I couldn't hide what is typed in the terminal"))
+        }
+    };
+    write!(stderr(), "{}", prompt)?;
+    stderr().flush()?;
+    let password = askpass::read_secret_line(&mut &tty);
+    drop(no_echo);
+    password.map_err(|reason| IoError::new(IoErrorKind::Other, reason))
+}
+
+/// Set when the master password is read from stdin.
+pub fn set_stdin_taken(taken: bool) {
+    STDIN_TAKEN.store(taken, Ordering::SeqCst);
+}
+
+pub fn is_stdin_taken() -> bool {
+    STDIN_TAKEN.load(Ordering::SeqCst)
+}
+
+/// Set when the command reads its input from the first line of stdin, so that the master
+/// password and the answers are read in the terminal instead.
+pub fn set_stdin_is_input(is_input: bool) {
+    STDIN_IS_INPUT.store(is_input, Ordering::SeqCst);
+}
+
+pub fn is_stdin_input() -> bool {
+    STDIN_IS_INPUT.load(Ordering::SeqCst)
+}
+
+pub fn read_line(line: &mut String) -> IoResult<usize> {
+    require_interactive("an answer");
+    #[cfg(test)]
+    {
+        if let Some(read) = read_scripted_line(line) {
+            return Ok(read);
+        }
+    }
+    let reason = if STDIN_TAKEN.load(Ordering::SeqCst) {
+        "stdin was used for the master password, and there is no terminal to ask in"
+    } else if STDIN_IS_INPUT.load(Ordering::SeqCst) {
+        "stdin is the input of the command, and there is no terminal to ask in"
+    } else {
+        return stdin().read_line(line);
+    };
+    match File::open("/dev/tty") {
+        Ok(tty) => BufReader::new(tty).read_line(line),
+        Err(_) => Err(IoError::new(IoErrorKind::Other, reason)),
+    }
+}
@@ -0,0 +1,245 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! Asks for the master password with another program, when there is no terminal to ask in,
+//! like when Rooster is started from a launcher or a git hook.
+//!
+//! $ROOSTER_ASKPASS is tried first, then $SSH_ASKPASS, which are given the prompt as their
+//! argument and print the password. Then comes pinentry, which speaks the Assuan protocol of
+//! GnuPG. Secrets are read byte by byte into a `SafeVec` with enough room, so that no buffer
+//! holds a copy of them after they are wiped.
+
+use quale::which;
+use safe_string::SafeString;
+use safe_vec::SafeVec;
+use std::env;
+use std::io::{Read, Write};
+use std::mem;
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+pub const ROOSTER_ASKPASS_ENV_VAR: &'static str = "ROOSTER_ASKPASS";
+pub const SSH_ASKPASS_ENV_VAR: &'static str = "SSH_ASKPASS";
+
+/// Tried in this order. `pinentry` is usually a link to the one that suits the desktop.
+const PINENTRY_PROGRAMS: &'static [&'static str] =
+    &["pinentry", "pinentry-gnome3", "pinentry-qt", "pinentry-gtk-2"];
+
+/// Longer lines are refused, which also keeps the buffers from growing (and leaving copies).
+const MAX_LINE_LEN: usize = 4096;
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Turned off with --no-askpass, for those who only want to type their password in a terminal.
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::SeqCst);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::SeqCst)
+}
+
+/// Asks for a secret with the first askpass program found, `None` if there is none.
+pub fn ask(prompt: &str) -> Option<Result<SafeString, String>> {
+    for var in &[ROOSTER_ASKPASS_ENV_VAR, SSH_ASKPASS_ENV_VAR] {
+        if let Some(program) = env::var_os(var).filter(|program| !program.is_empty()) {
+            return Some(ask_with_askpass(Command::new(program), prompt));
+        }
+    }
+    PINENTRY_PROGRAMS.iter()
+        .filter_map(|name| which(name))
+        .next()
+        .map(|pinentry| ask_with_pinentry(Command::new(pinentry), prompt))
+}
+
+/// Reads a line, without the newline, `Ok(false)` at the end of the input.
+fn read_line<R: Read>(reader: &mut R, line: &mut SafeVec) -> Result<bool, String> {
+    line.inner_mut().clear();
+    let mut byte = [0u8];
+    loop {
+        match reader.read(&mut byte) {
+            Ok(0) => return Ok(!line.is_empty()),
+            Ok(_) if byte[0] == b'\n' => break,
+            Ok(_) if line.expose().len() >= MAX_LINE_LEN => {
+                return Err("the answer is too long".to_owned());
+            }
+            Ok(_) => line.inner_mut().push(byte[0]),
+            Err(err) => return Err(format!("I couldn't read the answer (reason: {})", err)),
+        }
+    }
+    if line.expose().last() == Some(&b'\r') {
+        line.inner_mut().pop();
+    }
+    Ok(true)
+}
+
+fn new_line_buffer() -> SafeVec {
+    SafeVec::new(Vec::with_capacity(MAX_LINE_LEN + 1))
+}
+
+/// Moves the bytes into a string, without copying them.
+fn into_safe_string(mut bytes: SafeVec) -> Result<SafeString, String> {
+    let inner = mem::replace(&mut *bytes.inner_mut(), Vec::new());
+    String::from_utf8(inner).map(SafeString::new).map_err(|err| {
+        // Wiped when dropped.
+        SafeVec::new(err.into_bytes());
+        "the answer isn't valid UTF-8".to_owned()
+    })
+}
+
+/// Reads a secret from the first line of `reader`, for which an empty input is an empty secret.
+pub fn read_secret_line<R: Read>(reader: &mut R) -> Result<SafeString, String> {
+    let mut line = new_line_buffer();
+    read_line(reader, &mut line)?;
+    into_safe_string(line)
+}
+
+/// Waits for the program, so that it doesn't linger, and tells whether it succeeded.
+fn finish(mut child: Child) -> Result<(), String> {
+    match child.wait() {
+        Ok(status) if status.success() => Ok(()),
+        Ok(_) => Err("it was cancelled".to_owned()),
+        Err(err) => Err(format!("I couldn't wait for it (reason: {})", err)),
+    }
+}
+
+/// The ssh-askpass way: the prompt is the argument, the answer is the first line of stdout.
+fn ask_with_askpass(mut command: Command, prompt: &str) -> Result<SafeString, String> {
+    let mut child = command.arg(prompt)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|err| format!("I couldn't run the askpass program (reason: {})", err))?;
+
+    let secret = read_secret_line(child.stdout.as_mut().unwrap());
+    drop(child.stdout.take());
+    finish(child)?;
+    secret
+}
+
+/// Decodes the data of an Assuan "D" line, where %, CR and LF are percent-encoded.
+fn percent_decode(data: &[u8]) -> Result<SafeVec, String> {
+    let mut decoded = SafeVec::new(Vec::with_capacity(data.len()));
+    let mut i = 0;
+    while i < data.len() {
+        if data[i] == b'%' {
+            let hex = data.get(i + 1..i + 3)
+                .and_then(|hex| ::std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .ok_or_else(|| "pinentry sent a badly encoded answer".to_owned())?;
+            decoded.inner_mut().push(hex);
+            i += 3;
+        } else {
+            decoded.inner_mut().push(data[i]);
+            i += 1;
+        }
+    }
+    Ok(decoded)
+}
+
+/// Talks to pinentry: a greeting, a few settings, then GETPIN, which is answered with the PIN
+/// in a "D" line and then "OK", or "ERR" if the user cancelled.
+fn ask_with_pinentry(mut command: Command, prompt: &str) -> Result<SafeString, String> {
+    let mut child = command.stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|err| format!("I couldn't run pinentry (reason: {})", err))?;
+    let result = converse_with_pinentry(&mut child, prompt);
+    drop(child.stdin.take());
+    drop(child.stdout.take());
+    let _ = child.wait();
+    result
+}
+
+/// Skips comments and status lines up to the OK or ERR that answers a command.
+fn expect_ok<R: Read>(stdout: &mut R, line: &mut SafeVec) -> Result<(), String> {
+    loop {
+        if !read_line(stdout, line)? {
+            return Err("pinentry stopped answering".to_owned());
+        }
+        if line.expose().starts_with(b"OK") {
+            return Ok(());
+        }
+        if line.expose().starts_with(b"ERR") {
+            return Err("pinentry refused a command".to_owned());
+        }
+    }
+}
+
+fn send<W: Write>(stdin: &mut W, command: &str) -> Result<(), String> {
+    stdin.write_all(command.as_bytes())
+        .and_then(|_| stdin.write_all(b"\n"))
+        .and_then(|_| stdin.flush())
+        .map_err(|err| format!("I couldn't talk to pinentry (reason: {})", err))
+}
+
+fn converse_with_pinentry(child: &mut Child, prompt: &str) -> Result<SafeString, String> {
+    let stdin = child.stdin.as_mut().unwrap();
+    let stdout = child.stdout.as_mut().unwrap();
+    let mut line = new_line_buffer();
+
+    expect_ok(stdout, &mut line)?;
+    for command in &["SETTITLE Rooster".to_owned(),
+                     format!("SETDESC {}", prompt.replace('%', "%25")),
+                     "SETPROMPT Master password:".to_owned()] {
+        send(stdin, command)?;
+        expect_ok(stdout, &mut line)?;
+    }
+
+    send(stdin, "GETPIN")?;
+    let mut pin = None;
+    loop {
+        if !read_line(stdout, &mut line)? {
+            return Err("pinentry stopped answering".to_owned());
+        }
+        if line.expose().starts_with(b"D ") {
+            pin = Some(percent_decode(&line.expose()[2..])?);
+        } else if line.expose().starts_with(b"OK") {
+            break;
+        } else if line.expose().starts_with(b"ERR") {
+            return Err("it was cancelled".to_owned());
+        }
+    }
+    let _ = send(stdin, "BYE");
+
+    // No D line means an empty PIN.
+    into_safe_string(pin.unwrap_or_else(|| SafeVec::new(Vec::new())))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{percent_decode, read_line, new_line_buffer};
+
+    #[test]
+    fn reads_lines_without_newlines() {
+        let mut input: &[u8] = b"first\r\nsecond\nlast";
+        let mut line = new_line_buffer();
+        assert_eq!(read_line(&mut input, &mut line), Ok(true));
+        assert_eq!(line.expose(), b"first");
+        assert_eq!(read_line(&mut input, &mut line), Ok(true));
+        assert_eq!(line.expose(), b"second");
+        assert_eq!(read_line(&mut input, &mut line), Ok(true));
+        assert_eq!(line.expose(), b"last");
+        assert_eq!(read_line(&mut input, &mut line), Ok(false));
+    }
+
+    #[test]
+    fn decodes_assuan_data() {
+        assert_eq!(percent_decode(b"100%25 sure%0Aok").unwrap().expose(), b"100% sure\nok");
+        assert!(percent_decode(b"bad%2").is_err());
+        assert!(percent_decode(b"bad%zz").is_err());
+    }
+}
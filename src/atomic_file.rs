@@ -0,0 +1,210 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! Replaces files in one go: the new content goes to a temporary file next to the original,
+//! which is renamed over it once completely written. A crash, a full disk or a kill halfway
+//! through leaves the original as it was.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::Error as IoError;
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// How many symbolic links are followed at most, like the 40 of Linux.
+const MAX_LINKS: usize = 40;
+
+/// The file `path` stands for, once symbolic links are followed. Renaming over a link would
+/// replace it with a regular file, and a password file linked from elsewhere (like Dropbox)
+/// would stop being synced. Links to files that don't exist yet are followed too.
+fn resolve_links(path: &Path) -> PathBuf {
+    let mut path = path.to_path_buf();
+    for _ in 0..MAX_LINKS {
+        let target = match fs::read_link(&path) {
+            Ok(target) => target,
+            Err(_) => break,
+        };
+        path = match path.parent() {
+            Some(dir) => dir.join(target),
+            None => target,
+        };
+    }
+    path
+}
+
+/// Where the new content of `path` is written first, in the same directory so that renaming
+/// it doesn't have to copy it.
+fn temp_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().map(|name| name.to_os_string()).unwrap_or_default();
+    name.push(format!(".tmp-{}", process::id()));
+    path.with_file_name(name)
+}
+
+#[cfg(unix)]
+fn create_private(path: &Path) -> Result<File, IoError> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    OpenOptions::new().write(true).create_new(true).mode(0o600).open(path)
+}
+
+#[cfg(not(unix))]
+fn create_private(path: &Path) -> Result<File, IoError> {
+    OpenOptions::new().write(true).create_new(true).open(path)
+}
+
+/// Makes the rename itself survive a crash.
+#[cfg(unix)]
+fn sync_dir(path: &Path) -> Result<(), IoError> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    File::open(dir)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_path: &Path) -> Result<(), IoError> {
+    Ok(())
+}
+
+/// Replaces the content of `path` with what `write` writes. The file is only owner-readable
+/// afterwards. If anything fails, `path` is left untouched. If `path` is a symbolic link, the
+/// file it points to is replaced, and the link stays.
+pub fn write<F, E>(path: &Path, write: F) -> Result<(), E>
+    where F: FnOnce(&mut File) -> Result<(), E>,
+          E: From<IoError>
+{
+    let path = resolve_links(path);
+    let path = path.as_path();
+    let temp_path = temp_path(path);
+    // Left over by a Rooster that was killed with the same pid.
+    let _ = fs::remove_file(&temp_path);
+
+    let result = create_private(&temp_path).map_err(E::from).and_then(|mut file| {
+        write(&mut file)?;
+        file.sync_all()?;
+        fs::rename(&temp_path, path)?;
+        Ok(())
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+        return result;
+    }
+    sync_dir(path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{temp_path, write};
+    use password::PasswordError;
+    use password::v2::{Password, PasswordStore};
+    use safe_string::SafeString;
+    use std::env;
+    use std::fs;
+    use std::io::{self, Write};
+    use std::process;
+
+    /// Fails like a full disk once `left` bytes are written.
+    struct FailAfter<W> {
+        inner: W,
+        left: usize,
+    }
+
+    impl<W: Write> Write for FailAfter<W> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.left == 0 {
+                return Err(io::Error::new(io::ErrorKind::Other, "no space left on device"));
+            }
+            let n = self.inner.write(&buf[..buf.len().min(self.left)])?;
+            self.left -= n;
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.inner.flush()
+        }
+    }
+
+    fn store() -> PasswordStore {
+        let mut store = PasswordStore::new(SafeString::new("master".to_owned())).unwrap();
+        store.add_password(Password::new("YouTube".to_owned(),
+                                         "me".to_owned(),
+                                         SafeString::new("secret".to_owned())))
+            .unwrap();
+        store
+    }
+
+    #[test]
+    fn a_failed_write_leaves_the_file_untouched() {
+        let path = env::temp_dir().join(format!("rooster-atomic-test-{}", process::id()));
+        fs::write(&path, b"the original").unwrap();
+
+        let store = store();
+        let result = write(&path, |file| {
+            store.write_to(&mut FailAfter {
+                inner: file,
+                left: 20,
+            })
+        });
+        match result {
+            Err(PasswordError::Io(_)) => {}
+            other => panic!("unexpected outcome: {:?}", other),
+        }
+        assert_eq!(fs::read(&path).unwrap(), b"the original");
+        assert!(!temp_path(&path).exists());
+
+        store.sync(&path).unwrap();
+        let saved = fs::read(&path).unwrap();
+        assert!(saved.len() > 20 && saved != b"the original");
+        assert!(!temp_path(&path).exists());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symbolic_links_stay_links() {
+        use std::os::unix::fs::symlink;
+
+        let dir = env::temp_dir().join(format!("rooster-atomic-link-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("Dropbox")).unwrap();
+        let target = dir.join("Dropbox").join("passwords.rooster");
+        let link = dir.join("link.rooster");
+        fs::write(&target, b"the original").unwrap();
+        symlink("Dropbox/passwords.rooster", &link).unwrap();
+        // A link to a link is followed to the end.
+        let link_to_link = dir.join("link-to-link.rooster");
+        symlink(&link, &link_to_link).unwrap();
+
+        store().sync(&link_to_link).unwrap();
+        assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert!(fs::symlink_metadata(&link_to_link).unwrap().file_type().is_symlink());
+        assert_ne!(fs::read(&target).unwrap(), b"the original");
+        assert!(!temp_path(&target).exists());
+        assert!(!temp_path(&link_to_link).exists());
+
+        // The file the link points to may not exist yet.
+        fs::remove_file(&target).unwrap();
+        store().sync(&link).unwrap();
+        assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert!(target.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
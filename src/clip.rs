@@ -14,103 +14,497 @@
 
 use password;
 use safe_string::SafeString;
+use sys;
+use std::env;
 use std::io::Write;
-use std::ops::Deref;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Where passwords are copied to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Selection {
+    /// The clipboard, pasted with Ctrl+V.
+    Clipboard,
+    /// The X11 PRIMARY selection, pasted with a middle-click.
+    Primary,
+}
+
+impl Selection {
+    pub fn from_name(name: &str) -> Option<Selection> {
+        match name {
+            "clipboard" => Some(Selection::Clipboard),
+            "primary" => Some(Selection::Primary),
+            _ => None,
+        }
+    }
+}
+
+static USE_PRIMARY: AtomicBool = AtomicBool::new(false);
+
+/// Set once, with --primary or $ROOSTER_SELECTION.
+pub fn set_selection(selection: Selection) {
+    USE_PRIMARY.store(selection == Selection::Primary, Ordering::SeqCst);
+}
+
+pub fn selection() -> Selection {
+    if USE_PRIMARY.load(Ordering::SeqCst) {
+        Selection::Primary
+    } else {
+        Selection::Clipboard
+    }
+}
+
+/// Only X11 and Wayland have a PRIMARY selection, Windows and macOS just have the clipboard.
+#[cfg(all(unix, not(target_os = "macos")))]
+pub fn has_primary_selection() -> bool {
+    true
+}
+
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+pub fn has_primary_selection() -> bool {
+    false
+}
+
+/// How the password was copied.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Copied {
+    /// By the clipboard of the system.
+    Clipboard,
+    /// By the terminal, which was asked to with an OSC 52 escape sequence. Terminals that don't
+    /// support it ignore it, so there is no knowing whether it worked.
+    Terminal,
+    /// Into a tmux paste buffer.
+    Tmux,
+}
+
+/// The name of the tmux paste buffer holding the password, so that it can be found and deleted.
+const TMUX_BUFFER: &'static str = "rooster";
+
+/// The default key binding, which pastes the most recent buffer.
+const TMUX_PASTE_KEYS: &'static str = "your tmux prefix (Ctrl+B) then ]";
+
+static USE_OSC52: AtomicBool = AtomicBool::new(false);
+static USE_TMUX: AtomicBool = AtomicBool::new(false);
+
+/// Set once, with --osc52.
+pub fn set_osc52(enabled: bool) {
+    USE_OSC52.store(enabled, Ordering::SeqCst);
+}
+
+/// Set once, with --tmux.
+pub fn set_tmux(enabled: bool) {
+    USE_TMUX.store(enabled, Ordering::SeqCst);
+}
+
+/// Whether Rooster runs inside tmux.
+pub fn in_tmux() -> bool {
+    has_display("TMUX")
+}
+
+fn has_display(var: &str) -> bool {
+    env::var_os(var).map_or(false, |display| !display.is_empty())
+}
+
+/// Whether the clipboard tools have nothing to copy to, like over SSH.
+fn no_display() -> bool {
+    cfg!(all(unix, not(target_os = "macos"))) && !has_display("DISPLAY") &&
+    !has_display("WAYLAND_DISPLAY")
+}
+
+/// Copies with OSC 52 or into tmux if asked to. Over SSH and the like, where there is no
+/// display for the clipboard tools, tmux is used if Rooster runs inside it, and otherwise the
+/// terminal may have a clipboard of its own.
+pub fn copy_to_clipboard(s: &SafeString) -> Result<Copied, ()> {
+    if USE_OSC52.load(Ordering::SeqCst) {
+        return copy_with_osc52(s).map(|_| Copied::Terminal);
+    }
+    if USE_TMUX.load(Ordering::SeqCst) {
+        return copy_with_tmux(s).map(|_| Copied::Tmux);
+    }
+
+    if no_display() && in_tmux() {
+        copy_with_tmux(s).map(|_| Copied::Tmux)
+    } else if no_display() && sys::stderr_is_tty() {
+        copy_with_osc52(s).map(|_| Copied::Terminal)
+    } else {
+        copy_with_tools(s).map(|_| Copied::Clipboard)
+    }
+}
+
+/// Loads the password into a tmux paste buffer. It goes through the standard input of tmux,
+/// never its arguments, which anyone can see with `ps`.
+fn copy_with_tmux(s: &SafeString) -> Result<(), ()> {
+    use quale::which;
+    use std::process::{Command, Stdio};
+
+    let tmux = which("tmux").ok_or(())?;
+    let mut child = Command::new(tmux)
+        .args(&["load-buffer", "-b", TMUX_BUFFER, "-"])
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|_| ())?;
+    let written = child.stdin.take().ok_or(())?.write_all(s.expose().as_bytes());
+    // Wait for tmux even if it stopped reading, so that no zombie is left behind.
+    let status = child.wait().map_err(|_| ())?;
+    if written.is_ok() && status.success() {
+        Ok(())
+    } else {
+        Err(())
+    }
+}
+
+const BASE64_ALPHABET: &'static [u8] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Base64 with padding, into a string that is wiped when dropped.
+fn base64_encode(bytes: &[u8]) -> SafeString {
+    let mut encoded = SafeString::new(String::with_capacity((bytes.len() + 2) / 3 * 4));
+    for chunk in bytes.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (b[0] as usize) << 16 | (b[1] as usize) << 8 | b[2] as usize;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.inner_mut().push(BASE64_ALPHABET[(n >> (18 - 6 * i)) & 63] as char);
+            } else {
+                encoded.inner_mut().push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Asks the terminal to copy the password, by writing an OSC 52 escape sequence to it. This
+/// works over SSH, since the terminal is on the user's machine.
+#[cfg(unix)]
+fn copy_with_osc52(s: &SafeString) -> Result<(), ()> {
+    use std::fs::OpenOptions;
+
+    let target = match selection() {
+        Selection::Clipboard => "c",
+        Selection::Primary => "p",
+    };
+    let encoded = base64_encode(s.expose().as_bytes());
+    let mut sequence = SafeString::new(String::with_capacity(encoded.expose().len() + 8));
+    sequence.inner_mut().push_str("\x1b]52;");
+    sequence.inner_mut().push_str(target);
+    sequence.inner_mut().push(';');
+    sequence.inner_mut().push_str(encoded.expose());
+    sequence.inner_mut().push('\x07');
+
+    let mut tty = OpenOptions::new().write(true).open("/dev/tty").map_err(|_| ())?;
+    tty.write_all(sequence.expose().as_bytes()).map_err(|_| ())?;
+    tty.flush().map_err(|_| ())
+}
+
+#[cfg(not(unix))]
+fn copy_with_osc52(_s: &SafeString) -> Result<(), ()> {
+    Err(())
+}
+
+/// What `clear_clipboard` calls the clipboard of the terminal, which it can't check was emptied.
+pub const TERMINAL_CLIPBOARD: &'static str = "your terminal's clipboard";
+
+/// Empties everything Rooster may have copied passwords to: the clipboard, the PRIMARY
+/// selection, the tmux buffer and, when passwords are copied with OSC 52, the terminal's
+/// clipboard. Returns what was emptied, `Err` if nothing could be.
+pub fn clear_clipboard() -> Result<Vec<&'static str>, ()> {
+    let empty = SafeString::new(String::new());
+    let mut cleared = Vec::new();
+    // An empty OSC 52 sequence empties the terminal's clipboard.
+    let osc52 = USE_OSC52.load(Ordering::SeqCst) ||
+                (no_display() && !in_tmux() && sys::stderr_is_tty());
+    if osc52 && copy_with_osc52(&empty).is_ok() {
+        cleared.push(TERMINAL_CLIPBOARD);
+    }
+    if copy_with_tools_to(&empty, Selection::Clipboard).is_ok() {
+        cleared.push("the clipboard");
+    }
+    if has_primary_selection() && copy_with_tools_to(&empty, Selection::Primary).is_ok() {
+        cleared.push("the PRIMARY selection");
+    }
+    if in_tmux() && delete_tmux_buffer().is_ok() {
+        cleared.push("the tmux buffer");
+    }
+
+    if cleared.is_empty() {
+        Err(())
+    } else {
+        Ok(cleared)
+    }
+}
+
+fn delete_tmux_buffer() -> Result<(), ()> {
+    use quale::which;
+    use std::process::{Command, Stdio};
+
+    let tmux = which("tmux").ok_or(())?;
+    // Fails if there is no such buffer, which is just as good.
+    Command::new(tmux)
+        .args(&["delete-buffer", "-b", TMUX_BUFFER])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|_| ())
+        .map_err(|_| ())
+}
+
+fn copy_with_tools(s: &SafeString) -> Result<(), ()> {
+    copy_with_tools_to(s, selection())
+}
 
 // On Windows and Mac, we'll use the native solutions provided by the OS libraries
-#[cfg(any(windows, macos))]
-pub fn copy_to_clipboard(s: &SafeString) -> Result<(), ()> {
+#[cfg(any(windows, target_os = "macos"))]
+fn copy_with_tools_to(s: &SafeString, _selection: Selection) -> Result<(), ()> {
     use clipboard::ClipboardProvider;
     use clipboard::ClipboardContext;
 
     let mut context: ClipboardContext = ClipboardProvider::new().map_err(|_| ())?;
-    context.set_contents(s.deref().to_owned()).map_err(|_| ())?;
+    context.set_contents(s.expose().to_owned()).map_err(|_| ())?;
     Ok(())
 }
 
 // On UNIX, the most stable way to copy to the clipboard is using one of the existing
-// and battle tested tools: xsel and xclip.
-#[cfg(all(unix, not(macos)))]
-pub fn copy_to_clipboard(s: &SafeString) -> Result<(), ()> {
+// and battle tested tools: wl-copy on Wayland, xsel and xclip on X11.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn copy_with_tools_to(s: &SafeString, selection: Selection) -> Result<(), ()> {
     use shell_escape;
-    use quale::which;
     use std::process::Command;
 
-    let password = SafeString::new(shell_escape::escape(s.deref().into()).into());
-
-    match which("xsel") {
-        Some(xsel) => {
-            let shell = format!(
-                "printf '%s' {} | {} -ib",
-                password.deref(),
-                xsel.to_string_lossy()
-            );
-            if Command::new("sh")
-                .args(&["-c", shell.as_str()])
-                .status()
-                .map_err(|_| ())?
-                .success()
-            {
-                Ok(())
-            } else {
-                Err(())
-            }
-        }
-        None => {
-            match which("xclip") {
-                Some(xclip) => {
-                    let shell = format!(
-                        "printf '%s' {} | {} -selection clipboard",
-                        password.deref(),
-                        xclip.to_string_lossy()
-                    );
-                    if Command::new("sh")
-                        .args(&["-c", shell.as_str()])
-                        .status()
-                        .map_err(|_| ())?
-                        .success()
-                    {
-                        Ok(())
-                    } else {
-                        Err(())
-                    }
-                }
-                None => Err(()),
-            }
+    let wayland = has_display("WAYLAND_DISPLAY");
+    let tool = clipboard_tool(wayland, selection).ok_or(())?;
+
+    let password = SafeString::new(shell_escape::escape(s.expose().into()).into());
+    let shell = format!("printf '%s' {} | {}", password.expose(), tool);
+    if Command::new("sh")
+        .args(&["-c", shell.as_str()])
+        .status()
+        .map_err(|_| ())?
+        .success()
+    {
+        Ok(())
+    } else {
+        Err(())
+    }
+}
+
+/// The command that reads the password on its stdin and copies it. On Wayland, wl-copy is
+/// preferred, but xsel and xclip still work through XWayland when it isn't installed.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn clipboard_tool(wayland: bool, selection: Selection) -> Option<String> {
+    use quale::which;
+    use shell_escape;
+
+    let quote = |path: ::std::path::PathBuf| -> String {
+        shell_escape::escape(path.to_string_lossy().into_owned().into()).into_owned()
+    };
+
+    if wayland {
+        if let Some(wl_copy) = which("wl-copy") {
+            return Some(match selection {
+                Selection::Clipboard => quote(wl_copy),
+                Selection::Primary => format!("{} --primary", quote(wl_copy)),
+            });
         }
     }
+    if let Some(xsel) = which("xsel") {
+        return Some(match selection {
+            Selection::Clipboard => format!("{} -ib", quote(xsel)),
+            Selection::Primary => format!("{} -ip", quote(xsel)),
+        });
+    }
+    which("xclip").map(|xclip| {
+        match selection {
+            Selection::Clipboard => format!("{} -selection clipboard", quote(xclip)),
+            Selection::Primary => format!("{} -selection primary", quote(xclip)),
+        }
+    })
 }
 
 #[cfg(target_os = "macos")]
-pub fn paste_keys() -> String {
-    "Cmd+V".to_string()
+pub fn paste_keys(copied: Copied) -> String {
+    match copied {
+        Copied::Tmux => TMUX_PASTE_KEYS.to_string(),
+        _ => "Cmd+V".to_string(),
+    }
 }
 
 #[cfg(not(target_os = "macos"))]
-pub fn paste_keys() -> String {
-    "Ctrl+V".to_string()
+pub fn paste_keys(copied: Copied) -> String {
+    match (copied, selection()) {
+        (Copied::Tmux, _) => TMUX_PASTE_KEYS.to_string(),
+        (_, Selection::Clipboard) => "Ctrl+V".to_string(),
+        (_, Selection::Primary) => "middle-click".to_string(),
+    }
+}
+
+/// How long to wait before typing, so that the user can switch to the right window.
+pub const DEFAULT_TYPE_DELAY_SECS: u64 = 3;
+
+/// Types the value into the focused window with xdotool, for apps that can't be pasted into.
+/// The value goes through the standard input of xdotool, never its arguments or environment,
+/// which other users can see.
+pub fn type_with_xdotool(s: &SafeString, press_enter: bool) -> Result<(), String> {
+    use quale::which;
+    use std::process::{Command, Stdio};
+
+    let xdotool = which("xdotool").ok_or_else(|| {
+        "I need xdotool to type, but I can't find it. Install it (it's in the xdotool package \
+         of most distributions), or copy the password instead"
+            .to_owned()
+    })?;
+
+    let mut child = Command::new(&xdotool)
+        .args(&["type", "--clearmodifiers", "--file", "-"])
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|err| format!("I couldn't run xdotool (reason: {})", err))?;
+    let written = child.stdin.take().ok_or(()).and_then(|mut stdin| {
+        stdin.write_all(s.expose().as_bytes()).map_err(|_| ())
+    });
+    let status = child.wait().map_err(|err| format!("xdotool failed (reason: {})", err))?;
+    if written.is_err() || !status.success() {
+        return Err("xdotool couldn't type, is there an X11 display?".to_owned());
+    }
+
+    if press_enter {
+        let status = Command::new(&xdotool)
+            .args(&["key", "--clearmodifiers", "Return"])
+            .stdin(Stdio::null())
+            .status()
+            .map_err(|err| format!("I couldn't run xdotool (reason: {})", err))?;
+        if !status.success() {
+            return Err("xdotool typed the password, but couldn't press Enter".to_owned());
+        }
+    }
+    Ok(())
+}
+
+/// Like `confirm_password_retrieved`, for another field, like the username. The password isn't
+/// touched, so the clipboard never holds it. Shown fields go to stdout, so they can be piped.
+pub fn confirm_field_retrieved(show: bool,
+                               password: &password::v2::Password,
+                               field: &str,
+                               value: &SafeString) {
+    if show {
+        println_requested!("Alright! Here is your {} for {}:", field, password.name);
+        println_stdout!("{}", value.expose());
+    } else {
+        match copy_to_clipboard(value) {
+            Err(_) => {
+                println_err!(
+                    "Hmm, I tried to copy your {} to your clipboard, but something went \
+                             wrong. You can see it with `rooster get '{}' --field {} --show`",
+                    field,
+                    password.name,
+                    field,
+                );
+            }
+            Ok(Copied::Clipboard) => {
+                println_ok!(
+                    "Alright! You can paste your {} {} anywhere with {}.",
+                    password.name,
+                    field,
+                    paste_keys(Copied::Clipboard)
+                );
+            }
+            Ok(Copied::Tmux) => {
+                println_ok!(
+                    "Alright! You can paste your {} {} in tmux with {}.",
+                    password.name,
+                    field,
+                    paste_keys(Copied::Tmux)
+                );
+                explain_tmux();
+            }
+            Ok(Copied::Terminal) => {
+                println_ok!(
+                    "Alright! I asked your terminal to copy your {} {}, paste it with {}.",
+                    password.name,
+                    field,
+                    paste_keys(Copied::Terminal)
+                );
+                explain_copy(Copied::Terminal,
+                             &format!("rooster get '{}' --field {} --show", password.name, field));
+            }
+        }
+    }
+}
+
+/// Terminals that don't support OSC 52 ignore it without a word, so the user has to be told.
+fn explain_osc52(show_command: &str) {
+    println_stderr!("This uses OSC 52, which some terminals ignore (or tmux without \
+                     set-clipboard). If nothing gets pasted, use `{}` instead.",
+                    show_command);
+}
+
+/// Tells what the user should know about how the password was copied, if anything.
+pub fn explain_copy(copied: Copied, show_command: &str) {
+    match copied {
+        Copied::Clipboard => {}
+        Copied::Terminal => explain_osc52(show_command),
+        Copied::Tmux => explain_tmux(),
+    }
+}
+
+fn explain_tmux() {
+    println_stderr!("It stays in the tmux buffer \"{}\" until you run `tmux delete-buffer -b {}`.",
+                    TMUX_BUFFER,
+                    TMUX_BUFFER);
 }
 
 pub fn confirm_password_retrieved(show: bool, password: &password::v2::Password) {
     if show {
-        println_ok!(
+        println_requested!(
             "Alright! Here is your password for {}: {}",
             password.name,
-            password.password.deref()
+            password.password.expose()
         );
     } else {
-        if copy_to_clipboard(&password.password).is_err() {
-            println_ok!(
-                "Hmm, I tried to copy your new password to your clipboard, but \
-                         something went wrong. You can see it with `rooster get '{}' --show`",
-                password.name,
-            );
-        } else {
-            println_ok!(
-                "Alright! You can paste your {} password anywhere with {}.",
-                password.name,
-                paste_keys()
-            );
+        match copy_to_clipboard(&password.password) {
+            Err(_) => {
+                println_err!(
+                    "Hmm, I tried to copy your new password to your clipboard, but \
+                             something went wrong. You can see it with `rooster get '{}' --show`",
+                    password.name,
+                );
+            }
+            Ok(Copied::Clipboard) => {
+                println_ok!(
+                    "Alright! You can paste your {} password anywhere with {}.",
+                    password.name,
+                    paste_keys(Copied::Clipboard)
+                );
+            }
+            Ok(Copied::Tmux) => {
+                println_ok!(
+                    "Alright! You can paste your {} password in tmux with {}.",
+                    password.name,
+                    paste_keys(Copied::Tmux)
+                );
+                explain_tmux();
+            }
+            Ok(Copied::Terminal) => {
+                println_ok!(
+                    "Alright! I asked your terminal to copy your {} password, paste it with {}.",
+                    password.name,
+                    paste_keys(Copied::Terminal)
+                );
+                explain_osc52(&format!("rooster get '{}' --show", password.name));
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::base64_encode;
+
+    #[test]
+    fn encodes_base64() {
+        assert_eq!(base64_encode(b"").expose(), "");
+        assert_eq!(base64_encode(b"f").expose(), "Zg==");
+        assert_eq!(base64_encode(b"fo").expose(), "Zm8=");
+        assert_eq!(base64_encode(b"foo").expose(), "Zm9v");
+        assert_eq!(base64_encode(b"foobar").expose(), "Zm9vYmFy");
+        assert_eq!(base64_encode("pässword".as_bytes()).expose(), "cMOkc3N3b3Jk");
+    }
+}
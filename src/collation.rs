@@ -0,0 +1,216 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! Orders app names the way people expect in their language, rather than by code point, which
+//! puts "école" after "zebra". This is much simpler than the Unicode Collation Algorithm, but
+//! doesn't depend on the platform: accents are taken apart with canonical decomposition, then
+//! names are compared by script (Latin, Greek, Cyrillic...), then by base letter, then by accent
+//! and finally by case.
+
+use std::cmp::Ordering;
+
+/// How lists of passwords are sorted, see `rooster config`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Collation {
+    /// By code point, without regard to casing. Fast, but mixes up accented letters.
+    Codepoint,
+    /// By script, then by base letter, see `sort_key`.
+    Locale,
+}
+
+impl Default for Collation {
+    fn default() -> Collation {
+        Collation::Codepoint
+    }
+}
+
+impl Collation {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Collation::Codepoint => "codepoint",
+            Collation::Locale => "locale",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Collation> {
+        match name {
+            "codepoint" => Some(Collation::Codepoint),
+            "locale" => Some(Collation::Locale),
+            _ => None,
+        }
+    }
+
+    pub fn compare(&self, a: &str, b: &str) -> Ordering {
+        match *self {
+            Collation::Codepoint => a.to_lowercase().cmp(&b.to_lowercase()),
+            Collation::Locale => sort_key(a).cmp(&sort_key(b)),
+        }
+    }
+}
+
+/// Canonical decompositions of the precomposed letters of Latin-1, Latin Extended-A, Greek and
+/// Cyrillic: for each combining mark, the composed letters and their bases, in the same order.
+/// Some bases, like "ϊ", decompose further.
+const DECOMPOSITIONS: &'static [(char, &'static str, &'static str)] = &[
+    ('\u{300}', "ÀÈÌÒÙàèìòùЀЍѐѝ", "AEIOUaeiouЕИеи"),
+    ('\u{301}',
+     "ÁÉÍÓÚÝáéíóúýĆćĹĺŃńŔŕŚśŹźΆΈΉΊΌΎΏΐάέήίΰόύώЃЌѓќ",
+     "AEIOUYaeiouyCcLlNnRrSsZzΑΕΗΙΟΥΩϊαεηιϋουωГКгк"),
+    ('\u{302}', "ÂÊÎÔÛâêîôûĈĉĜĝĤĥĴĵŜŝŴŵŶŷ", "AEIOUaeiouCcGgHhJjSsWwYy"),
+    ('\u{303}', "ÃÑÕãñõĨĩŨũ", "ANOanoIiUu"),
+    ('\u{304}', "ĀāĒēĪīŌōŪū", "AaEeIiOoUu"),
+    ('\u{306}', "ĂăĔĕĞğĬĭŎŏŬŭЎЙйў", "AaEeGgIiOoUuУИиу"),
+    ('\u{307}', "ĊċĖėĠġİŻż", "CcEeGgIZz"),
+    ('\u{308}', "ÄËÏÖÜäëïöüÿŸΪΫϊϋЁЇёї", "AEIOUaeiouyYΙΥιυЕІеі"),
+    ('\u{30a}', "ÅåŮů", "AaUu"),
+    ('\u{30b}', "ŐőŰű", "OoUu"),
+    ('\u{30c}', "ČčĎďĚěĽľŇňŘřŠšŤťŽž", "CcDdEeLlNnRrSsTtZz"),
+    ('\u{327}', "ÇçĢģĶķĻļŅņŖŗŞşŢţ", "CcGgKkLlNnRrSsTt"),
+    ('\u{328}', "ĄąĘęĮįŲų", "AaEeIiUu"),
+];
+
+fn is_combining_mark(c: char) -> bool {
+    c >= '\u{300}' && c <= '\u{36f}'
+}
+
+fn decompose_once(c: char) -> Option<(char, char)> {
+    for &(mark, composed, bases) in DECOMPOSITIONS {
+        if let Some(i) = composed.chars().position(|composed| composed == c) {
+            return bases.chars().nth(i).map(|base| (base, mark));
+        }
+    }
+    None
+}
+
+/// The base letter and its combining marks, in canonical order.
+fn decompose(c: char) -> (char, Vec<char>) {
+    let mut base = c;
+    let mut marks = Vec::new();
+    while let Some((next, mark)) = decompose_once(base) {
+        base = next;
+        marks.insert(0, mark);
+    }
+    (base, marks)
+}
+
+/// Names are first ordered by the script of their letters, in this order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Script {
+    Symbol,
+    Digit,
+    Latin,
+    Greek,
+    Cyrillic,
+    Other,
+}
+
+fn script(c: char) -> Script {
+    match c {
+        '0'..='9' => Script::Digit,
+        'a'..='z' | '\u{c0}'..='\u{24f}' if c.is_alphabetic() => Script::Latin,
+        '\u{370}'..='\u{3ff}' => Script::Greek,
+        '\u{400}'..='\u{4ff}' => Script::Cyrillic,
+        _ if !c.is_alphanumeric() => Script::Symbol,
+        _ => Script::Other,
+    }
+}
+
+fn lowercase(c: char) -> char {
+    match c {
+        // The final sigma is the same letter as σ.
+        'ς' => 'σ',
+        _ => c.to_lowercase().next().unwrap_or(c),
+    }
+}
+
+/// Compares like the name for `Collation::Locale`. Each field only matters when the previous
+/// ones are equal, and the name itself makes the order total.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SortKey {
+    letters: Vec<(Script, char)>,
+    accents: Vec<Vec<char>>,
+    uppercase: Vec<bool>,
+    name: String,
+}
+
+pub fn sort_key(name: &str) -> SortKey {
+    let mut key = SortKey {
+        letters: Vec::new(),
+        accents: Vec::new(),
+        uppercase: Vec::new(),
+        name: name.to_owned(),
+    };
+
+    for c in name.chars() {
+        // Marks that are already apart go with the letter before them.
+        if is_combining_mark(c) {
+            if let Some(accents) = key.accents.last_mut() {
+                accents.push(c);
+                continue;
+            }
+        }
+
+        let (base, marks) = decompose(c);
+        let lower = lowercase(base);
+        key.letters.push((script(lower), lower));
+        key.accents.push(marks);
+        key.uppercase.push(lower != base);
+    }
+
+    key
+}
+
+#[cfg(test)]
+mod test {
+    use super::Collation;
+
+    /// Names from several scripts, in the expected order for each collation.
+    const CASES: &'static [(Collation, &'static [&'static str])] = &[
+        (Collation::Locale,
+         &["_old", "2fa", "bank", "Bank", "bänk", "Čapek", "car", "dog", "e\u{301}cole",
+           "école", "Ecole 2", "fish", "zebra", "Άλφα", "βήτα", "Ωμέγα", "Дом", "Ёж", "Жук",
+           "Яндекс", "日本"]),
+        (Collation::Codepoint,
+         &["2fa", "_old", "bank", "bänk", "car", "dog", "Ecole 2", "e\u{301}cole", "fish",
+           "zebra", "école", "Čapek", "Άλφα", "βήτα", "Ωμέγα", "Дом", "Жук", "Яндекс", "Ёж",
+           "日本"]),
+    ];
+
+    #[test]
+    fn sorts_mixed_scripts() {
+        for &(collation, expected) in CASES {
+            let mut names = expected.to_vec();
+            names.reverse();
+            names.sort_by(|a, b| collation.compare(a, b));
+            assert_eq!(names, expected, "{:?}", collation);
+        }
+    }
+
+    #[test]
+    fn decomposes_greek_and_cyrillic() {
+        assert_eq!(super::decompose('ΐ'), ('ι', vec!['\u{308}', '\u{301}']));
+        assert_eq!(super::decompose('й'), ('и', vec!['\u{306}']));
+        assert_eq!(super::decompose('x'), ('x', vec![]));
+    }
+
+    #[test]
+    fn precomposed_and_decomposed_letters_are_close() {
+        let precomposed = super::sort_key("école");
+        let decomposed = super::sort_key("e\u{301}cole");
+        assert_eq!(precomposed.letters, decomposed.letters);
+        assert_eq!(precomposed.accents, decomposed.accents);
+    }
+}
@@ -12,21 +12,30 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::sync::atomic::{AtomicBool, Ordering};
+
 // static COLOR_BLACK: &'static str   = "\x1b[30m";
 static COLOR_RED: &'static str = "\x1b[31m";
 static COLOR_GREEN: &'static str = "\x1b[32m";
-// static COLOR_YELLOW: &'static str  = "\x1b[33m";
+static COLOR_YELLOW: &'static str = "\x1b[33m";
 // static COLOR_BLUE: &'static str = "\x1b[34m";
 // static COLOR_MAGENTA: &'static str = "\x1b[35m";
 static COLOR_CYAN: &'static str = "\x1b[36m";
 // static COLOR_WHITE: &'static str   = "\x1b[37m";
 static COLOR_RESET: &'static str = "\x1b[39m";
 
+static COLORS_ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Colors are turned off for output that other programs read, like with --porcelain.
+pub fn set_enabled(enabled: bool) {
+    COLORS_ENABLED.store(enabled, Ordering::SeqCst);
+}
+
 pub enum Color {
     // Black,
     Red,
     Green,
-    // Yellow,
+    Yellow,
     // Blue,
     // Magenta,
     Cyan,
@@ -36,11 +45,14 @@ pub enum Color {
 
 impl Color {
     pub fn to_color_code(&self) -> &'static str {
+        if !COLORS_ENABLED.load(Ordering::SeqCst) {
+            return "";
+        }
         match *self {
             // Color::Black   => COLOR_BLACK,
             Color::Red => COLOR_RED,
             Color::Green => COLOR_GREEN,
-            // Color::Yellow  => COLOR_YELLOW,
+            Color::Yellow => COLOR_YELLOW,
             // Color::Blue => COLOR_BLUE,
             // Color::Magenta => COLOR_MAGENTA,
             Color::Cyan => COLOR_CYAN,
@@ -12,29 +12,205 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use exit_code::ExitCode;
+use crypto::util::fixed_time_eq;
 use getopts;
+use sys;
 use password;
-use rpassword::prompt_password_stderr;
+use answer;
 use safe_string::SafeString;
-use clip::{copy_to_clipboard, paste_keys};
-use std::io::Write;
+use strength::{Strength, MIN_VERDICT};
+use clip::{copy_to_clipboard, explain_copy, paste_keys};
+use list;
+use output_format;
+use super::batch;
+use super::generate::{check_generation_args, new_password_from_matches};
+use help::Help;
+use std::io::{stdin, Write};
 use std::ops::Deref;
 
-pub fn callback_help() {
-    println!("Usage:");
-    println!("    rooster add -h");
-    println!("    rooster add <app_name> <username>");
-    println!("");
-    println!("Example:");
-    println!("    rooster add YouTube me@example.com");
+pub fn callback_help() -> Help {
+    Help::new()
+        .usage(&["rooster add -h",
+                 "rooster add <app_name> <username> [--tag <tag>]...",
+                 "rooster add <app_name> <username> --generate [-l <length>] [--alnum]"])
+        .examples(&["rooster add YouTube me@example.com",
+                    "rooster add Slack me@work.com --tag work --tag chat"])
+        .text(&["With --generate, a random password is made instead of asking for one. It takes",
+                "the same options as `rooster generate`, see `rooster generate -h`."])
+        .commands(&["rooster add Forum me@example.com --generate -l 20 --alnum"])
+        .text(&["When standard input isn't a terminal, or with --password-stdin, the password is",
+                "the first line of stdin, without its line ending. The master password is then",
+                "typed in the terminal, or given in $ROOSTER_MASTER_PASSWORD or with",
+                "--master-password-file <path>, but never read from stdin."])
+        .commands(&["openssl rand -base64 24 | rooster add service deploy"])
+        .text(&["The password is asked twice, to catch typos. With --no-confirm, it is only asked",
+                "once, which is handy when pasting it."])
+        .text(&["When you type the password, its strength is shown. If it is easy to guess, you",
+                "have to confirm that you want it anyway, unless --force is given."])
 }
 
-pub fn check_args(matches: &getopts::Matches) -> Result<(), i32> {
+/// Whether the password is read from stdin.
+pub fn reads_stdin(matches: &getopts::Matches) -> bool {
+    matches.opt_present("password-stdin") || !sys::stdin_is_tty()
+}
+
+pub fn check_args(matches: &getopts::Matches) -> Result<(), ExitCode> {
     if matches.free.len() < 3 {
         println_err!("Woops, seems like the app name or the username is missing here. For help, \
                       try:");
         println_err!("    rooster add -h");
-        return Err(1);
+        return Err(ExitCode::Usage);
+    }
+
+    if matches.opt_present("generate") {
+        if matches.opt_present("password-stdin") {
+            println_err!("Woops, --generate and --password-stdin can't be used together. For \
+                          help, try:");
+            println_err!("    rooster add -h");
+            return Err(ExitCode::Usage);
+        }
+        check_generation_args(matches, "add")?;
+    }
+
+    Ok(())
+}
+
+/// Removes the line ending, "\n" or "\r\n" from files saved on Windows. Anything else, even
+/// another carriage return, is part of the password.
+fn trim_line_ending(line: &mut SafeString) {
+    let len = {
+        let line = line.expose();
+        if line.ends_with("\r\n") {
+            line.len() - 2
+        } else if line.ends_with('\n') {
+            line.len() - 1
+        } else {
+            line.len()
+        }
+    };
+    line.inner_mut().truncate(len);
+}
+
+/// Reads the password from the first line of stdin, without its line ending. The master
+/// password is never read from there: see `answer::set_stdin_is_input`.
+pub fn read_password_from_stdin() -> Result<SafeString, ExitCode> {
+    let mut line = SafeString::new(String::new());
+    stdin().read_line(&mut line.inner_mut()).map_err(|err| {
+        println_err!("I couldn't read the password from stdin (reason: {:?}).", err);
+        ExitCode::Error
+    })?;
+
+    trim_line_ending(&mut line);
+    if line.is_empty() {
+        println_err!("Woops, there is no password on the first line of stdin.");
+        return Err(ExitCode::Error);
+    }
+    Ok(line)
+}
+
+/// How many times a password is asked for before giving up, when the two copies don't match.
+const MAX_PASSWORD_ATTEMPTS: usize = 3;
+
+fn prompt_password(prompt: &str) -> Result<SafeString, ExitCode> {
+    answer::prompt_password(prompt, "a password").map_err(|err| {
+        println_err!("\nI couldn't read the app's password (reason: {:?}).", err);
+        ExitCode::Error
+    })
+}
+
+/// Asks for the new password of an app, twice unless --no-confirm is given or stdin isn't a
+/// terminal. The copies are compared in constant time.
+///
+/// When it's typed in a terminal, its strength is shown, and a weak one has to be confirmed
+/// unless --force is given.
+///
+/// Shared by `rooster add` and `rooster change`.
+pub fn ask_new_password(matches: &getopts::Matches,
+                        app_name: &str)
+                        -> Result<SafeString, ExitCode> {
+    if !sys::stdin_is_tty() {
+        return prompt_password(&format!("What password do you want for \"{}\"? ", app_name));
+    }
+
+    let password = if matches.opt_present("no-confirm") {
+        prompt_password(&format!("What password do you want for \"{}\"? ", app_name))?
+    } else {
+        ask_password_twice(app_name)?
+    };
+    check_strength(matches, &password)?;
+    Ok(password)
+}
+
+fn ask_password_twice(app_name: &str) -> Result<SafeString, ExitCode> {
+    for _ in 0..MAX_PASSWORD_ATTEMPTS {
+        let password = prompt_password(&format!("Type the password for \"{}\": ", app_name))?;
+        let again = prompt_password("Type it again: ")?;
+        if fixed_time_eq(password.expose().as_bytes(), again.expose().as_bytes()) {
+            return Ok(password);
+        }
+        println_err!("Woops, the passwords don't match. Let's try again.");
+    }
+
+    println_err!("The passwords didn't match {} times, so I didn't save anything.",
+                 MAX_PASSWORD_ATTEMPTS);
+    Err(ExitCode::Error)
+}
+
+/// Shows how strong a password typed by hand is, and makes sure a weak one is really wanted.
+fn check_strength(matches: &getopts::Matches, password: &SafeString) -> Result<(), ExitCode> {
+    let strength = Strength::of(password.expose());
+    println_stderr!("Strength of the password: {}.", strength.summary());
+    if strength.verdict() >= MIN_VERDICT || matches.opt_present("force") {
+        return Ok(());
+    }
+
+    print_stderr!("This password is easy to guess. Store it anyway (y/n)? ");
+    let mut line = String::new();
+    answer::read_line(&mut line).map_err(|err| {
+        println_err!("I couldn't read that (reason: {:?}).", err);
+        ExitCode::Error
+    })?;
+    if !line.starts_with('y') {
+        println_err!("Alright, I didn't save anything.");
+        return Err(ExitCode::Error);
+    }
+    Ok(())
+}
+
+/// Saves a new password, then copies it to the clipboard, or shows it with --show.
+///
+/// Shared by `rooster add` and `rooster generate`.
+pub fn save_new_password(matches: &getopts::Matches,
+                         store: &mut password::v2::PasswordStore,
+                         password: password::v2::Password)
+                         -> Result<(), ExitCode> {
+    let app_name = password.name.clone();
+    let password_as_string = password.password.clone();
+
+    if let Err(err) = store.add_password(password) {
+        println_err!("Woops, I couldn't add the password (reason: {:?}).", err);
+        return Err(ExitCode::from_password_error(&err));
+    }
+
+    if matches.opt_present("show") {
+        println_requested!("Alright! Here is your password: {}",
+                           password_as_string.expose());
+        return Ok(());
+    }
+
+    match copy_to_clipboard(&password_as_string) {
+        Err(_) => {
+            println_err!("Hmm, I tried to copy your new password to your clipboard, but something \
+                          went wrong. Don't worry, it's saved, and you can see it with \
+                          `rooster get {} --show`",
+                         app_name);
+        }
+        Ok(copied) => {
+            println_ok!("Alright! I've saved your new password. You can paste it with {}.",
+                        paste_keys(copied));
+            explain_copy(copied, &format!("rooster get '{}' --show", app_name));
+        }
     }
 
     Ok(())
@@ -42,54 +218,104 @@ pub fn check_args(matches: &getopts::Matches) -> Result<(), i32> {
 
 pub fn callback_exec(matches: &getopts::Matches,
                      store: &mut password::v2::PasswordStore)
-                     -> Result<(), i32> {
+                     -> Result<(), ExitCode> {
     check_args(matches)?;
 
     let app_name = matches.free[1].clone();
     let username = matches.free[2].clone();
+    let tags = list::tags_from_matches(matches)?;
 
     if store.has_password(app_name.deref()) {
+        output_format::set_app(app_name.deref());
         println_err!("Woops, there is already an app with that name.");
-        return Err(1);
-    }
-
-    match prompt_password_stderr(format!("What password do you want for \"{}\"? ", app_name)
-                                     .as_str()) {
-        Ok(password_as_string) => {
-            let password_as_string_clipboard = SafeString::new(password_as_string.clone());
-            let password = password::v2::Password::new(app_name.clone(),
-                                                       username,
-                                                       SafeString::new(password_as_string));
-            match store.add_password(password) {
-                Ok(_) => {
-                    if matches.opt_present("show") {
-                        println_ok!("Alright! Here is your password: {}",
-                                    password_as_string_clipboard.deref());
-                        return Ok(());
-                    }
-
-                    if copy_to_clipboard(&password_as_string_clipboard).is_err() {
-                        println_ok!("Hmm, I tried to copy your new password to your clipboard, \
-                                     but something went wrong. Don't worry, it's saved, and you \
-                                     can see it with `rooster get {} --show`",
-                                    app_name);
-                    } else {
-                        println_ok!("Alright! I've saved your new password. You can paste it \
-                                     anywhere with {}.",
-                                    paste_keys());
-                    }
-                }
-                Err(err) => {
-                    println_err!("Woops, I couldn't add the password (reason: {:?}).", err);
-                    return Err(1);
-                }
-            }
-
-            Ok(())
-        }
-        Err(err) => {
-            println_err!("\nI couldn't read the app's password (reason: {:?}).", err);
-            Err(1)
+        return Err(ExitCode::AlreadyExists);
+    }
+
+    let password_as_string = if matches.opt_present("generate") {
+        new_password_from_matches(matches, store)?
+    } else if let Some(password) = batch::password_argument(matches) {
+        password
+    } else if matches.opt_present("password-stdin") || !sys::stdin_is_tty() {
+        read_password_from_stdin()?
+    } else {
+        ask_new_password(matches, &app_name)?
+    };
+
+    let mut password = password::v2::Password::new(app_name, username, password_as_string);
+    password.tags = tags;
+    save_new_password(matches, store, password)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ask_password_twice, callback_exec, trim_line_ending};
+    use answer;
+    use commands::harness::{run, store};
+    use console;
+    use exit_code::ExitCode;
+    use safe_string::SafeString;
+
+    #[test]
+    fn generated_passwords_are_added() {
+        let mut store = store(&[]);
+        let (result, output) = run(callback_exec,
+                                   &["add", "YouTube", "me@example.com", "--generate", "--show"],
+                                   &[],
+                                   &mut store);
+        assert_eq!(result, Ok(()));
+
+        let password = store.get_password("YouTube").unwrap();
+        assert_eq!(password.username, "me@example.com");
+        assert!(output.stderr.contains(&format!("Here is your password: {}",
+                                                password.password.expose())));
+    }
+
+    #[test]
+    fn the_username_is_required() {
+        let mut store = store(&[]);
+        let (result, output) = run(callback_exec, &["add", "YouTube"], &[], &mut store);
+        assert_eq!(result, Err(ExitCode::Usage));
+        assert!(output.stderr.contains("rooster add -h"));
+        assert!(!store.has_password("YouTube"));
+    }
+
+    #[test]
+    fn existing_passwords_are_kept() {
+        let mut store = store(&[("YouTube", "me@example.com", "hunter2")]);
+        let (result, output) = run(callback_exec,
+                                   &["add", "youtube", "other@example.com", "--generate"],
+                                   &[],
+                                   &mut store);
+        assert_eq!(result, Err(ExitCode::AlreadyExists));
+        assert!(output.stderr.contains("there is already an app with that name"));
+        assert_eq!(store.get_password("YouTube").unwrap().password.expose(), "hunter2");
+    }
+
+    #[test]
+    fn only_line_endings_are_trimmed() {
+        for &(line, password) in &[("hunter2\n", "hunter2"),
+                                   ("hunter2\r\n", "hunter2"),
+                                   ("hunter2\r\r\n", "hunter2\r"),
+                                   ("hunter2", "hunter2"),
+                                   (" hunter2 \t\n", " hunter2 \t")] {
+            let mut line = SafeString::new(line.to_owned());
+            trim_line_ending(&mut line);
+            assert_eq!(line.expose(), password);
         }
     }
+
+    #[test]
+    fn the_password_is_asked_until_both_copies_match() {
+        answer::script(Some(&["hunter2", "hunter3", "hunter2", "hunter2"]));
+        let (result, output) = console::capture(|| ask_password_twice("YouTube"));
+        answer::script(None);
+        assert_eq!(result.unwrap().expose(), "hunter2");
+        assert!(output.stderr.contains("the passwords don't match"), "{}", output.stderr);
+
+        answer::script(Some(&["a", "b", "a", "b", "a", "b"]));
+        let (result, output) = console::capture(|| ask_password_twice("YouTube"));
+        answer::script(None);
+        assert!(result.is_err());
+        assert!(output.stderr.contains("didn't match 3 times"), "{}", output.stderr);
+    }
 }
@@ -0,0 +1,207 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+use exit_code::ExitCode;
+use crypto::util::fixed_time_eq;
+use getopts;
+use output_format;
+use password;
+use safe_string::SafeString;
+use serde_json;
+use ffi;
+use super::batch;
+use help::Help;
+use std::io::{stdin, Write};
+
+pub fn callback_help() -> Help {
+    Help::new()
+        .usage(&["rooster add-or-update -h",
+                 "rooster add-or-update <app_name> <username> --password-stdin [--json]"])
+        .text(&["Adds the password, or updates it if the app name is already taken. The app name",
+                "must match exactly (without regard to casing). Nothing is asked besides the",
+                "master password, so this is meant for scripts:"])
+        .commands(&["printf '%s\\n%s\\n' \"$MASTER\" \"$PASSWORD\" | \\",
+                    "    rooster add-or-update YouTube me@example.com --password-stdin"])
+        .text(&["With --json (or --format json), a line like this one is printed on stdout:"])
+        .commands(&["{\"result\":\"updated\",\"changed\":[\"password\"]}"])
+}
+
+/// Whether the password is read from stdin, because none was given as an argument.
+pub fn reads_stdin(matches: &getopts::Matches) -> bool {
+    batch::password_argument(matches).is_none()
+}
+
+/// Reads the password from the line that follows the master password on stdin. Every trailing
+/// carriage return and newline is removed, as add-or-update always did, so that files saved on
+/// Windows work too. Other whitespace is part of the password.
+fn read_password_from_stdin() -> Result<SafeString, ExitCode> {
+    let mut line = SafeString::new(String::new());
+    stdin().read_line(&mut line.inner_mut()).map_err(|err| {
+        println_err!("I couldn't read the password from stdin (reason: {:?}).", err);
+        ExitCode::Error
+    })?;
+
+    let len = line.expose().trim_end_matches(|c| c == '\n' || c == '\r').len();
+    line.inner_mut().truncate(len);
+    if line.is_empty() {
+        println_err!("Woops, there is no password on stdin after the master password.");
+        return Err(ExitCode::Error);
+    }
+    Ok(line)
+}
+
+pub fn check_args(matches: &getopts::Matches) -> Result<(), ExitCode> {
+    if matches.free.len() < 3 {
+        println_err!("Woops, seems like the app name or the username is missing here. For help, \
+                      try:");
+        println_err!("    rooster add-or-update -h");
+        return Err(ExitCode::Usage);
+    }
+
+    if !matches.opt_present("password-stdin") && batch::password_argument(matches).is_none() {
+        println_err!("Woops, add-or-update never prompts, so the password has to come from \
+                      --password-stdin. For help, try:");
+        println_err!("    rooster add-or-update -h");
+        return Err(ExitCode::Usage);
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Created,
+    Updated,
+    Unchanged,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Report {
+    pub result: Outcome,
+    pub changed: Vec<&'static str>,
+}
+
+/// The fields of `existing` that differ from the given ones. Passwords are compared in constant
+/// time.
+fn changed_fields(existing: &password::v2::Password,
+                  username: &str,
+                  password: &SafeString)
+                  -> Vec<&'static str> {
+    let mut changed = Vec::new();
+    if existing.username != username {
+        changed.push("username");
+    }
+    if !fixed_time_eq(existing.password.expose().as_bytes(), password.expose().as_bytes()) {
+        changed.push("password");
+    }
+    changed
+}
+
+pub fn add_or_update(store: &mut password::v2::PasswordStore,
+                     app_name: &str,
+                     username: &str,
+                     password: SafeString)
+                     -> Result<Report, password::PasswordError> {
+    let existing = match store.get_password(app_name) {
+        Some(existing) => existing,
+        None => {
+            store.add_password(password::v2::Password::new(app_name.to_owned(),
+                                                           username.to_owned(),
+                                                           password))?;
+            return Ok(Report { result: Outcome::Created, changed: Vec::new() });
+        }
+    };
+
+    let changed = changed_fields(&existing, username, &password);
+    if changed.is_empty() {
+        return Ok(Report { result: Outcome::Unchanged, changed: changed });
+    }
+
+    store.change_password(&existing.name, &|old| {
+        password::v2::Password {
+            username: username.to_owned(),
+            password: password.clone(),
+            updated_at: ffi::time(),
+            ..old
+        }
+    })?;
+    Ok(Report { result: Outcome::Updated, changed: changed })
+}
+
+pub fn callback_exec(matches: &getopts::Matches,
+                     store: &mut password::v2::PasswordStore)
+                     -> Result<(), ExitCode> {
+    check_args(matches)?;
+
+    let app_name = &matches.free[1];
+    let username = &matches.free[2];
+    let password = match batch::password_argument(matches) {
+        Some(password) => password,
+        None => read_password_from_stdin()?,
+    };
+
+    let report = add_or_update(store, app_name, username, password).map_err(|err| {
+        println_err!("Woops, I couldn't save the password (reason: {:?}).", err);
+        ExitCode::from_password_error(&err)
+    })?;
+
+    if output_format::json_requested(matches) {
+        let json = serde_json::to_string(&report).map_err(|err| {
+            println_err!("I couldn't write the report (reason: {:?}).", err);
+            ExitCode::Error
+        })?;
+        print_stdout!("{}\n", json);
+        return Ok(());
+    }
+
+    match report.result {
+        Outcome::Created => println_ok!("created"),
+        Outcome::Updated => println_ok!("updated ({})", report.changed.join(", ")),
+        Outcome::Unchanged => println_ok!("unchanged"),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{add_or_update, Outcome};
+    use password::v2::PasswordStore;
+    use safe_string::SafeString;
+
+    fn secret(s: &str) -> SafeString {
+        SafeString::new(s.to_owned())
+    }
+
+    #[test]
+    fn creates_updates_then_leaves_alone() {
+        let mut store = PasswordStore::new(secret("master")).unwrap();
+
+        let report = add_or_update(&mut store, "YouTube", "me", secret("one")).unwrap();
+        assert_eq!(report.result, Outcome::Created);
+
+        let report = add_or_update(&mut store, "youtube", "me", secret("one")).unwrap();
+        assert_eq!(report.result, Outcome::Unchanged);
+
+        let report = add_or_update(&mut store, "YouTube", "you", secret("two")).unwrap();
+        assert_eq!(report.result, Outcome::Updated);
+        assert_eq!(report.changed, vec!["username", "password"]);
+
+        let password = store.get_password("YouTube").unwrap();
+        assert_eq!(password.username, "you");
+        assert_eq!(password.password.expose(), "two");
+        assert_eq!(password.history.len(), 1);
+    }
+}
@@ -0,0 +1,87 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+use exit_code::ExitCode;
+use getopts;
+use password;
+use list;
+use help::Help;
+use std::io::Write;
+
+pub fn callback_help() -> Help {
+    Help::new()
+        .usage(&["rooster archive -h",
+                 "rooster archive <query>"])
+        .text(&["Archives a password you don't use anymore, instead of deleting it. Archived",
+                "passwords are kept and exported like the others, but commands like list and get",
+                "skip them, unless --archived is given. To bring one back, see:"])
+        .then_commands(&["rooster unarchive -h"])
+        .examples(&["rooster archive youtube",
+                    "rooster --archived list"])
+}
+
+pub fn check_args(matches: &getopts::Matches, command: &str) -> Result<(), ExitCode> {
+    if matches.free.len() < 2 {
+        println_err!("Woops, seems like the app name is missing here. For help, try:");
+        println_err!("    rooster {} -h", command);
+        return Err(ExitCode::Usage);
+    }
+
+    Ok(())
+}
+
+/// Archives or unarchives a password. Only the passwords that would change are offered.
+pub fn set_archived(matches: &getopts::Matches,
+                    store: &mut password::v2::PasswordStore,
+                    archived: bool)
+                    -> Result<(), ExitCode> {
+    let query = &matches.free[1];
+    let prompt = if archived {
+        "Which password would you like to archive?"
+    } else {
+        "Which password would you like to unarchive?"
+    };
+
+    println_stderr!("");
+    let password = list::search_and_choose_filtered_password(
+        store, query, |p| p.archived != archived, list::WITH_NUMBERS, prompt,
+    )?.clone();
+
+    // Like favorites, archiving doesn't change the password, so updated_at is left alone.
+    store.change_password(&password.name,
+                          &|old_password: password::v2::Password| {
+        password::v2::Password {
+            archived: archived,
+            ..old_password
+        }
+    }).map_err(|err| {
+        println_err!("Woops, I couldn't save the change (reason: {:?}).", err);
+        ExitCode::from_password_error(&err)
+    })?;
+
+    if archived {
+        println_ok!("Done! {} is now archived.", password.name);
+    } else {
+        println_ok!("Done! {} is not archived anymore.", password.name);
+    }
+    Ok(())
+}
+
+pub fn callback_exec(matches: &getopts::Matches,
+                     store: &mut password::v2::PasswordStore)
+                     -> Result<(), ExitCode> {
+    check_args(matches, "archive")?;
+    set_archived(matches, store, true)
+}
@@ -0,0 +1,406 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+use exit_code::ExitCode;
+use answer;
+use getopts;
+use password;
+use safe_string::SafeString;
+use {command_from_name, get_password_file_path, needs_store, save_password_store};
+use help::Help;
+use options::{self, Opt, OPTIONS};
+use std::io::{stdin, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// Whether a checkpoint wrote the password file, in which case the batch changed it even if
+/// the store has nothing left to save.
+static CHECKPOINTED: AtomicBool = AtomicBool::new(false);
+
+/// The `after_save` hooks of the lines that succeeded, until their changes are saved.
+static AFTER_SAVE: Mutex<Vec<fn(&str)>> = Mutex::new(Vec::new());
+
+pub fn callback_help() -> Help {
+    Help::new()
+        .usage(&["rooster batch -h",
+                 "rooster batch [--keep-going] [--checkpoint <n>] < commands.txt"])
+        .text(&["Runs the commands read from standard input, one per line, with a single unlock of",
+                "the password file, which is only written once at the end. Lines look like the",
+                "command line, without `rooster`, and arguments with spaces can be quoted:"])
+        .commands(&["add GitHub alice@example.com 'correct horse battery staple'",
+                    "generate \"Google Workspace\" alice@example.com --length 24",
+                    "tag GitHub +work"])
+        .text(&["In a batch, add and add-or-update take the password as a third argument.",
+                "Empty lines and lines starting with # are skipped. Nothing is asked while the",
+                "batch runs, so queries should name a single password. Options for the whole run,",
+                "like --file or --regex, go before `batch` and are refused on lines."])
+        .text(&["The batch stops at the first line that fails, and then nothing is saved. With",
+                "--keep-going, the other lines still run and their changes are saved. With",
+                "--checkpoint <n>, the password file is also written every <n> lines. In the end,",
+                "the lines that succeeded and the ones that failed are listed."])
+        .examples(&["rooster batch --keep-going < new-hire.txt"])
+}
+
+/// Whether commands are run from `rooster batch`, where some of them read more arguments.
+pub fn is_running() -> bool {
+    RUNNING.load(Ordering::SeqCst)
+}
+
+/// In a batch, add and add-or-update take the password after the app name and the username,
+/// since the line can't be seen by other users like the arguments of a process.
+pub fn password_argument(matches: &getopts::Matches) -> Option<SafeString> {
+    if !is_running() {
+        return None;
+    }
+    matches.free.get(3).map(|password| SafeString::new(password.clone()))
+}
+
+fn checkpoint_from_matches(matches: &getopts::Matches) -> Result<Option<usize>, ExitCode> {
+    match matches.opt_str("checkpoint") {
+        Some(lines) => {
+            match lines.trim().parse::<usize>() {
+                Ok(lines) if lines > 0 => Ok(Some(lines)),
+                _ => {
+                    println_err!("Woops, --checkpoint must be a number of lines, like 10, not \
+                                  \"{}\".",
+                                 lines);
+                    Err(ExitCode::Usage)
+                }
+            }
+        }
+        None => Ok(None),
+    }
+}
+
+/// Batches always read their lines from stdin.
+pub fn saved_checkpoint() -> bool {
+    CHECKPOINTED.load(Ordering::SeqCst)
+}
+
+/// Runs the hooks of the lines whose changes were just saved.
+pub fn after_save(path: &str) {
+    let hooks: Vec<fn(&str)> = AFTER_SAVE.lock().unwrap().drain(..).collect();
+    for hook in hooks.iter() {
+        hook(path);
+    }
+}
+
+pub fn reads_stdin(_matches: &getopts::Matches) -> bool {
+    true
+}
+
+pub fn check_args(matches: &getopts::Matches) -> Result<(), ExitCode> {
+    if matches.free.len() > 1 {
+        println_err!("Woops, batch reads its commands from stdin, not from arguments. For help, \
+                      try:");
+        println_err!("    rooster batch -h");
+        return Err(ExitCode::Usage);
+    }
+    checkpoint_from_matches(matches)?;
+    Ok(())
+}
+
+/// Splits a line into words like a shell would: single quotes keep everything as is, double
+/// quotes and backslashes let \", \\ and \$ through.
+pub fn split_line(line: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' | '\t' => {
+                if let Some(word) = word.take() {
+                    words.push(word);
+                }
+            }
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err("a single quote isn't closed".to_owned()),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => {
+                            match chars.next() {
+                                Some(c) if c == '"' || c == '\\' || c == '$' => word.push(c),
+                                Some(c) => {
+                                    word.push('\\');
+                                    word.push(c);
+                                }
+                                None => return Err("a double quote isn't closed".to_owned()),
+                            }
+                        }
+                        Some(c) => word.push(c),
+                        None => return Err("a double quote isn't closed".to_owned()),
+                    }
+                }
+            }
+            '\\' => {
+                match chars.next() {
+                    Some(c) => word.get_or_insert_with(String::new).push(c),
+                    None => return Err("the line ends with a backslash".to_owned()),
+                }
+            }
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if let Some(word) = word {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+/// Runs one line against the store, and tells which command it was.
+fn run_line(words: &[String],
+            store: &mut password::v2::PasswordStore)
+            -> Result<&'static str, ExitCode> {
+    let matches = options::build().parse(words).map_err(|err| {
+        println_err!("Woops, {}.", err);
+        ExitCode::Usage
+    })?;
+    let name = match matches.free.get(0) {
+        Some(name) => name,
+        None => {
+            println_err!("Woops, there is no command on this line.");
+            return Err(ExitCode::Usage);
+        }
+    };
+    // The whole run reads these before the batch starts, so they would be silently ignored. So
+    // is --regex, which is compiled before the password file is opened.
+    let read_before = |opt: &&Opt| opt.is_global() || opt.long == "regex";
+    let given = OPTIONS.iter().filter(read_before).find(|opt| matches.opt_present(opt.long));
+    if let Some(opt) = given {
+        println_err!("Woops, --{} applies to the whole batch, so it goes before `batch`.",
+                     opt.long);
+        return Err(ExitCode::Usage);
+    }
+    let command = match command_from_name(name) {
+        Some(command) => command,
+        None => {
+            println_err!("Woops, the command `{}` does not exist.", name);
+            return Err(ExitCode::Usage);
+        }
+    };
+    let callback_exec = match command.callback_exec {
+        Some(callback_exec) if command.name != "batch" => callback_exec,
+        _ => {
+            println_err!("Woops, {} can't be used in a batch.", command.name);
+            return Err(ExitCode::Usage);
+        }
+    };
+
+    // Like outside of a batch, some commands only need the store for some arguments.
+    if let Some(callback_without_store) = command.callback_without_store {
+        callback_without_store(&matches)?;
+    }
+    if needs_store(command, &matches) {
+        callback_exec(&matches, store)?;
+    }
+    if let Some(after_save) = command.after_save {
+        AFTER_SAVE.lock().unwrap().push(after_save);
+    }
+    Ok(command.name)
+}
+
+/// Writes the password file now, instead of when the batch is done.
+fn save(matches: &getopts::Matches,
+        store: &mut password::v2::PasswordStore)
+        -> Result<(), ExitCode> {
+    let path = get_password_file_path(matches).map_err(|_| {
+        println_err!("Woops, I could not determine where your password file is.");
+        ExitCode::Error
+    })?;
+    save_password_store(store, &path)?;
+    // Otherwise the undo file and the previous version would be written again at the end.
+    store.mark_saved();
+    CHECKPOINTED.store(true, Ordering::SeqCst);
+    after_save(&path);
+    Ok(())
+}
+
+fn join_numbers(numbers: &[usize]) -> String {
+    numbers.iter().map(|n| n.to_string()).collect::<Vec<String>>().join(", ")
+}
+
+pub fn callback_exec(matches: &getopts::Matches,
+                     store: &mut password::v2::PasswordStore)
+                     -> Result<(), ExitCode> {
+    check_args(matches)?;
+    let keep_going = matches.opt_present("keep-going");
+    let checkpoint = checkpoint_from_matches(matches)?;
+    AFTER_SAVE.lock().unwrap().clear();
+    CHECKPOINTED.store(false, Ordering::SeqCst);
+
+    let mut input = SafeString::new(String::new());
+    stdin().read_to_string(&mut input.inner_mut()).map_err(|err| {
+        println_err!("Woops, I couldn't read the commands from stdin (reason: {:?}).", err);
+        ExitCode::Io
+    })?;
+
+    // Lines are numbered as in the input, empty lines and comments included.
+    let lines: Vec<(usize, &str)> = input.expose().lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|&(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .collect();
+
+    // Questions would read the next lines of the batch.
+    let was_non_interactive = answer::is_non_interactive();
+    answer::set_non_interactive(true);
+    RUNNING.store(true, Ordering::SeqCst);
+
+    let mut succeeded = Vec::new();
+    let mut failed = Vec::new();
+    let mut saved_up_to = None;
+    for (i, &(number, line)) in lines.iter().enumerate() {
+        let result = split_line(line)
+            .map_err(|reason| {
+                println_err!("Woops, {}.", reason);
+                ExitCode::Usage
+            })
+            .and_then(|words| run_line(&words, store));
+        match result {
+            Ok(_) => succeeded.push(number),
+            Err(code) => {
+                println_err!("Line {} failed.", number);
+                failed.push((number, code));
+                if !keep_going {
+                    break;
+                }
+            }
+        }
+
+        let last = i + 1 == lines.len();
+        if checkpoint.map_or(false, |every| (i + 1) % every == 0 && !last) && store.is_modified() {
+            save(matches, store)?;
+            saved_up_to = Some(number);
+        }
+    }
+
+    RUNNING.store(false, Ordering::SeqCst);
+    answer::set_non_interactive(was_non_interactive);
+
+    let not_run: Vec<usize> = lines.iter()
+        .map(|&(number, _)| number)
+        .filter(|number| !succeeded.contains(number) && !failed.iter().any(|f| f.0 == *number))
+        .collect();
+
+    println_stderr!("");
+    if failed.is_empty() {
+        println_ok!("Done! All {} line(s) succeeded.", succeeded.len());
+        return Ok(());
+    }
+
+    if !succeeded.is_empty() {
+        println_stderr_always!("Lines that succeeded: {}.", join_numbers(&succeeded));
+    }
+    let failed_numbers: Vec<usize> = failed.iter().map(|f| f.0).collect();
+    println_err!("Lines that failed: {}.", join_numbers(&failed_numbers));
+    if !not_run.is_empty() {
+        println_stderr_always!("Lines that didn't run: {}.", join_numbers(&not_run));
+    }
+
+    if keep_going {
+        if store.is_modified() {
+            save(matches, store)?;
+        }
+        println_stderr_always!("The changes of the lines that succeeded were saved.");
+    } else {
+        match saved_up_to {
+            Some(number) => {
+                println_stderr_always!("Changes up to line {} were saved, the others weren't.",
+                                       number)
+            }
+            None => println_stderr_always!("Nothing was saved."),
+        }
+    }
+    Err(failed[0].1)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{run_line, split_line};
+    use commands::harness::store;
+    use console;
+    use exit_code::ExitCode;
+
+    fn words(line: &str) -> Vec<String> {
+        split_line(line).unwrap()
+    }
+
+    #[test]
+    fn lines_are_split_like_a_shell_would() {
+        assert_eq!(words("add GitHub  me"), vec!["add", "GitHub", "me"]);
+        assert_eq!(words("add 'My bank' me 'pass word'"),
+                   vec!["add", "My bank", "me", "pass word"]);
+        assert_eq!(words(r#"add "say \"hi\"" me a\ b"#),
+                   vec!["add", "say \"hi\"", "me", "a b"]);
+        assert_eq!(words(r#"add it' is 'fine "" x"#), vec!["add", "it is fine", "", "x"]);
+        assert_eq!(words(r#"add 'back\slash' "back\slash""#),
+                   vec!["add", "back\\slash", "back\\slash"]);
+        assert_eq!(words("  "), Vec::<String>::new());
+    }
+
+    #[test]
+    fn unclosed_quotes_are_errors() {
+        assert!(split_line("add 'GitHub me").is_err());
+        assert!(split_line("add \"GitHub me").is_err());
+        assert!(split_line("add GitHub me\\").is_err());
+    }
+
+    fn line(words: &[&str]) -> Vec<String> {
+        words.iter().map(|word| word.to_string()).collect()
+    }
+
+    #[test]
+    fn kdf_benchmark_lines_return() {
+        let mut store = store(&[("GitHub", "me", "hunter2")]);
+        let (result, _) = console::capture(|| run_line(&line(&["favorite", "GitHub"]), &mut store));
+        assert_eq!(result, Ok("favorite"));
+
+        let (result, output) = console::capture(|| {
+            run_line(&line(&["kdf-benchmark", "--target-ms", "1"]), &mut store)
+        });
+        assert_eq!(result, Ok("kdf-benchmark"));
+        assert!(output.stdout.contains("Closest to 1 ms"));
+        // The earlier line is still there to be saved.
+        assert!(store.is_modified());
+        assert!(store.get_password("GitHub").unwrap().favorite);
+    }
+
+    #[test]
+    fn global_options_are_refused_on_lines() {
+        let mut store = store(&[("GitHub", "me", "hunter2")]);
+        for words in &[&["list", "--porcelain"][..],
+                       &["get", "git", "--regex"],
+                       &["favorite", "GitHub", "--quiet"],
+                       &["get", "gh", "--fuzzy"]] {
+            let (result, output) = console::capture(|| run_line(&line(words), &mut store));
+            assert_eq!(result, Err(ExitCode::Usage), "{:?}", words);
+            assert!(output.stderr.contains("goes before `batch`"), "{}", output.stderr);
+        }
+        assert!(!store.get_password("GitHub").unwrap().favorite);
+    }
+}
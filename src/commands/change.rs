@@ -12,30 +12,42 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use exit_code::ExitCode;
 use getopts;
 use password;
-use rpassword::prompt_password_stderr;
-use safe_string::SafeString;
 use clip;
 use ffi;
 use list;
+use super::add::ask_new_password;
+use super::generate::{check_generation_args, new_password_from_matches};
+use help::Help;
 use std::io::Write;
 
-pub fn callback_help() {
-    println!("Usage:");
-    println!("    rooster change -h");
-    println!("    rooster change <query>");
-    println!("");
-    println!("Examples:");
-    println!("    rooster change youtube");
-    println!("    rooster change ytb");
+pub fn callback_help() -> Help {
+    Help::new()
+        .usage(&["rooster change -h",
+                 "rooster change <query> [--no-confirm] [--force]",
+                 "rooster change <query> --generate [-l <length>] [--alnum]"])
+        .examples(&["rooster change youtube",
+                    "rooster change ytb"])
+        .text(&["The new password is asked twice, to catch typos. With --no-confirm, it is only",
+                "asked once, which is handy when pasting it."])
+        .text(&["When you type the password, its strength is shown. If it is easy to guess, you",
+                "have to confirm that you want it anyway, unless --force is given."])
+        .text(&["With --generate, a random password is made instead of asking for one. It takes",
+                "the same options as `rooster generate`, see `rooster generate -h`."])
+        .commands(&["rooster change github --generate -l 20 --alnum"])
 }
 
-pub fn check_args(matches: &getopts::Matches) -> Result<(), i32> {
+pub fn check_args(matches: &getopts::Matches) -> Result<(), ExitCode> {
     if matches.free.len() < 2 {
         println_err!("Woops, seems like the app name is missing here. For help, try:");
         println_err!("    rooster change -h");
-        return Err(1);
+        return Err(ExitCode::Usage);
+    }
+
+    if matches.opt_present("generate") {
+        check_generation_args(matches, "change")?;
     }
 
     Ok(())
@@ -43,7 +55,7 @@ pub fn check_args(matches: &getopts::Matches) -> Result<(), i32> {
 
 pub fn callback_exec(matches: &getopts::Matches,
                      store: &mut password::v2::PasswordStore)
-                     -> Result<(), i32> {
+                     -> Result<(), ExitCode> {
     check_args(matches)?;
 
     let query = &matches.free[1];
@@ -52,33 +64,29 @@ pub fn callback_exec(matches: &getopts::Matches,
     let password = list::search_and_choose_password(
         store, query, list::WITH_NUMBERS,
         "Which password would like to update?",
-    ).ok_or(1)?.clone();
+    )?.clone();
 
     println_stderr!("");
-    let password_as_string = prompt_password_stderr(
-        format!("What password do you want for \"{}\"? ", password.name).as_str(),
-    ).map_err(|err| {
-        println_err!("\nI couldn't read the app's password (reason: {:?}).", err);
-        1
-    })?;
-
-    let password_as_string = SafeString::new(password_as_string);
+    let password_as_string = if matches.opt_present("generate") {
+        new_password_from_matches(matches, store)?
+    } else {
+        ask_new_password(matches, &password.name)?
+    };
 
     store.change_password(
         &password.name,
         &|old_password: password::v2::Password| {
         password::v2::Password {
-            name: old_password.name,
-            username: old_password.username,
             password: password_as_string.clone(),
-            created_at: old_password.created_at,
             updated_at: ffi::time(),
+            ..old_password
         }
     }).map_err(|err| {
         println_err!("Woops, I couldn't save the new password (reason: {:?}).", err);
-        1
+        ExitCode::from_password_error(&err)
     })?;
 
+    let password = store.get_password(&password.name).unwrap_or(password);
     let show = matches.opt_present("show");
     clip::confirm_password_retrieved(show, &password);
     Ok(())
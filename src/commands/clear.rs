@@ -0,0 +1,57 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+use exit_code::ExitCode;
+use getopts;
+use clip;
+use help::Help;
+use std::io::Write;
+
+pub fn callback_help() -> Help {
+    Help::new()
+        .usage(&["rooster clear -h",
+                 "rooster clear"])
+        .text(&["Empties the clipboard right away, along with the PRIMARY selection on X11 and",
+                "Wayland, and the tmux buffer Rooster copies to inside tmux. Your password file",
+                "isn't opened, so the master password isn't asked."])
+        .text(&["With --osc52, or over SSH where passwords are copied with OSC 52, your terminal",
+                "is asked to empty its clipboard too. Some terminals ignore that, and Rooster",
+                "can't tell, so paste somewhere to check."])
+        .examples(&["rooster clear"])
+}
+
+pub fn callback_exec(matches: &getopts::Matches) -> Result<(), ExitCode> {
+    if matches.free.len() > 1 {
+        println_err!("Woops, clear doesn't take any arguments. For help, try:");
+        println_err!("    rooster clear -h");
+        return Err(ExitCode::Usage);
+    }
+
+    match clip::clear_clipboard() {
+        Ok(cleared) => {
+            println_ok!("Done! I've emptied {}.", cleared.join(" and "));
+            if cleared.contains(&clip::TERMINAL_CLIPBOARD) {
+                println_stderr!("This uses OSC 52, which some terminals ignore. If a password \
+                                 still gets pasted, copy something else over it.");
+            }
+            Ok(())
+        }
+        Err(_) => {
+            println_err!("Woops, I couldn't reach any clipboard to empty. On Linux, I need \
+                          wl-copy, xsel or xclip.");
+            Err(ExitCode::Error)
+        }
+    }
+}
@@ -0,0 +1,103 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+use exit_code::ExitCode;
+use answer;
+use getopts;
+use password;
+use help::Help;
+use std::io::Write;
+use std::path::Path;
+
+/// What has to be typed to confirm, so that a stray "y" doesn't destroy everything.
+pub const CONFIRMATION: &'static str = "DELETE";
+
+pub fn callback_help() -> Help {
+    Help::new()
+        .usage(&["rooster clear-all -h",
+                 "rooster clear-all [--export-first <path>]"])
+        .text(&["Deletes every password, but keeps the password file and the master password. You",
+                &format!("have to type {} to confirm.", CONFIRMATION)])
+        .text(&["With --export-first, the passwords are first saved to a new file at <path>,",
+                "encrypted with the same master password. You can open it with:"])
+        .then_commands(&["ROOSTER_FILE=<path> rooster list"])
+        .examples(&["rooster clear-all",
+                    "rooster clear-all --export-first ~/passwords-backup.rooster"])
+}
+
+pub fn check_args(matches: &getopts::Matches) -> Result<(), ExitCode> {
+    if matches.free.len() > 1 {
+        println_err!("Woops, clear-all doesn't take any arguments. For help, try:");
+        println_err!("    rooster clear-all -h");
+        return Err(ExitCode::Usage);
+    }
+
+    if let Some(path) = matches.opt_str("export-first") {
+        if Path::new(&path).exists() {
+            println_err!("Woops, {} already exists. I won't overwrite it.", path);
+            return Err(ExitCode::Error);
+        }
+    }
+
+    Ok(())
+}
+
+fn confirm(count: usize) -> Result<bool, ExitCode> {
+    println_stderr!("This will destroy all your {} password(s). This can only be undone with",
+                    count);
+    println_stderr!("`rooster undo`, right after this command.");
+    print_stderr!("Type {} to confirm: ", CONFIRMATION);
+
+    let mut line = String::new();
+    answer::read_line(&mut line).map_err(|err| {
+        println_err!("I couldn't read that (reason: {:?}).", err);
+        ExitCode::Error
+    })?;
+    Ok(line.trim_end_matches(|c| c == '\n' || c == '\r') == CONFIRMATION)
+}
+
+/// Saves the store, still encrypted with the current master password, to a new file.
+fn export_to(store: &password::v2::PasswordStore, path: &str) -> Result<(), ExitCode> {
+    store.sync(Path::new(path)).map_err(|err| {
+        println_err!("Woops, I couldn't export your passwords to {} (reason: {:?}).", path, err);
+        ExitCode::Io
+    })
+}
+
+pub fn callback_exec(matches: &getopts::Matches,
+                     store: &mut password::v2::PasswordStore)
+                     -> Result<(), ExitCode> {
+    check_args(matches)?;
+
+    let count = store.get_all_passwords().len();
+    if count == 0 {
+        println_stderr!("There are no passwords to delete.");
+        return Ok(());
+    }
+
+    if !confirm(count)? {
+        println_stderr!("OK, I didn't delete anything.");
+        return Ok(());
+    }
+
+    if let Some(path) = matches.opt_str("export-first") {
+        export_to(store, &path)?;
+        println_ok!("I've saved a copy of your passwords to {}.", path);
+    }
+
+    store.clear_passwords();
+    println_ok!("Done! I've deleted all your {} password(s).", count);
+    Ok(())
+}
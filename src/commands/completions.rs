@@ -0,0 +1,300 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use exit_code::ExitCode;
+use getopts;
+use options::{Opt, OPTIONS};
+use {ALIASES, COMMANDS};
+use help::Help;
+use std::io::Write;
+
+/// The shells completions can be generated for.
+const SHELLS: &'static [&'static str] = &["bash", "zsh", "fish"];
+
+/// Lists the names of the passwords, without asking anything. This only works when the master
+/// password can be found without a prompt, like in $ROOSTER_MASTER_PASSWORD.
+const NAMES_COMMAND: &'static str = "rooster --non-interactive --quiet list --names-only \
+                                     --porcelain 2>/dev/null";
+
+pub fn callback_help() -> Help {
+    Help::new()
+        .usage(&["rooster completions -h",
+                 "rooster completions bash|zsh|fish"])
+        .text(&["Prints a completion script for your shell, which completes command names,",
+                "options and, when the master password can be found without asking (like in",
+                "$ROOSTER_MASTER_PASSWORD), the names of your passwords."])
+        .examples(&["rooster completions bash > ~/.local/share/bash-completion/completions/rooster",
+                    "rooster completions zsh > \"${fpath[1]}/_rooster\"",
+                    "rooster completions fish > ~/.config/fish/completions/rooster.fish"])
+}
+
+fn command_names() -> Vec<&'static str> {
+    COMMANDS.iter()
+        .map(|command| command.name)
+        .chain(ALIASES.iter().map(|&(alias, _)| alias))
+        .collect()
+}
+
+/// The command and its aliases.
+fn names_of(command: &'static str) -> Vec<&'static str> {
+    let mut names = vec![command];
+    names.extend(ALIASES.iter().filter(|&&(_, name)| name == command).map(|&(alias, _)| alias));
+    names
+}
+
+/// Every way to write these options, like "-f --file".
+fn flag_names<'a, I>(opts: I) -> Vec<String>
+    where I: Iterator<Item = &'a Opt>
+{
+    let mut names = Vec::new();
+    for opt in opts {
+        if !opt.short.is_empty() {
+            names.push(format!("-{}", opt.short));
+        }
+        names.push(format!("--{}", opt.long));
+    }
+    names
+}
+
+fn global_flags() -> Vec<String> {
+    flag_names(OPTIONS.iter().filter(|opt| opt.is_global()))
+}
+
+/// The options that take a value, whatever the command, since they are skipped to find it.
+fn value_flags() -> Vec<String> {
+    flag_names(OPTIONS.iter().filter(|opt| opt.takes_value()))
+}
+
+/// The options of each command, besides the global ones, with the names it can be run by.
+fn command_flags() -> Vec<(Vec<&'static str>, Vec<String>)> {
+    COMMANDS.iter()
+        .map(|command| {
+            let opts = OPTIONS.iter().filter(|opt| opt.commands.contains(&command.name));
+            (names_of(command.name), flag_names(opts))
+        })
+        .filter(|&(_, ref flags)| !flags.is_empty())
+        .collect()
+}
+
+fn bash_script(commands: &[&str]) -> String {
+    let cases: Vec<String> = command_flags()
+        .into_iter()
+        .map(|(names, flags)| {
+            format!("            {}) flags=\"$flags {}\" ;;", names.join("|"), flags.join(" "))
+        })
+        .collect();
+    format!(r#"_rooster() {{
+    local cur="${{COMP_WORDS[COMP_CWORD]}}"
+    local prev="${{COMP_WORDS[COMP_CWORD-1]}}"
+    local commands="{commands}"
+    local flags="{flags}"
+    local value_flags=" {value_flags} "
+
+    if [[ "$value_flags" == *" $prev "* ]]; then
+        COMPREPLY=($(compgen -f -- "$cur"))
+        return
+    fi
+
+    local i word command=""
+    for ((i = 1; i < COMP_CWORD; i++)); do
+        word="${{COMP_WORDS[i]}}"
+        if [[ "$value_flags" == *" $word "* ]]; then
+            ((i++))
+        elif [[ "$word" != -* ]]; then
+            command="$word"
+            break
+        fi
+    done
+
+    if [[ "$cur" == -* ]]; then
+        case "$command" in
+{cases}
+        esac
+        COMPREPLY=($(compgen -W "$flags" -- "$cur"))
+    elif [[ -z "$command" ]]; then
+        COMPREPLY=($(compgen -W "$commands" -- "$cur"))
+    elif [[ "$command" == completions ]]; then
+        COMPREPLY=($(compgen -W "{shells}" -- "$cur"))
+    else
+        local IFS=$'\n'
+        COMPREPLY=($(compgen -W "$({names})" -- "$cur"))
+    fi
+}}
+
+complete -F _rooster rooster
+"#,
+            commands = commands.join(" "),
+            flags = global_flags().join(" "),
+            value_flags = value_flags().join(" "),
+            cases = cases.join("\n"),
+            shells = SHELLS.join(" "),
+            names = NAMES_COMMAND)
+}
+
+fn zsh_script(commands: &[&str]) -> String {
+    let cases: Vec<String> = command_flags()
+        .into_iter()
+        .map(|(names, flags)| {
+            format!("            {}) flags+=({}) ;;", names.join("|"), flags.join(" "))
+        })
+        .collect();
+    format!(r#"#compdef rooster
+
+_rooster() {{
+    local -a commands flags value_flags names
+    commands=({commands})
+    flags=({flags})
+    value_flags=({value_flags})
+
+    if (( ${{value_flags[(Ie)${{words[CURRENT-1]}}]}} )); then
+        _files
+        return
+    fi
+
+    local i command=""
+    for ((i = 2; i < CURRENT; i++)); do
+        if (( ${{value_flags[(Ie)${{words[i]}}]}} )); then
+            ((i++))
+        elif [[ "${{words[i]}}" != -* ]]; then
+            command="${{words[i]}}"
+            break
+        fi
+    done
+
+    if [[ "$PREFIX" == -* ]]; then
+        case "$command" in
+{cases}
+        esac
+        compadd -a flags
+    elif [[ -z "$command" ]]; then
+        compadd -a commands
+    elif [[ "$command" == completions ]]; then
+        compadd {shells}
+    else
+        names=(${{(f)"$({names})"}})
+        compadd -a names
+    fi
+}}
+
+if [[ "${{zsh_eval_context[-1]}}" == loadautofunc ]]; then
+    _rooster "$@"
+else
+    compdef _rooster rooster
+fi
+"#,
+            commands = commands.join(" "),
+            flags = global_flags().join(" "),
+            value_flags = value_flags().join(" "),
+            cases = cases.join("\n"),
+            shells = SHELLS.join(" "),
+            names = NAMES_COMMAND)
+}
+
+fn fish_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+fn fish_line(opt: &Opt, condition: Option<&str>) -> String {
+    let mut line = "complete -c rooster".to_owned();
+    if let Some(condition) = condition {
+        line.push_str(&format!(" -n {}", fish_quote(condition)));
+    }
+    if !opt.short.is_empty() {
+        line.push_str(&format!(" -s {}", opt.short));
+    }
+    line.push_str(&format!(" -l {}", opt.long));
+    if opt.takes_value() {
+        line.push_str(" -r -F");
+    }
+    line.push_str(&format!(" -d {}\n", fish_quote(opt.description)));
+    line
+}
+
+fn fish_script(commands: &[&str]) -> String {
+    let mut script = String::new();
+    script.push_str("complete -c rooster -f\n");
+    script.push_str(&format!("complete -c rooster -n __fish_use_subcommand -a {}\n",
+                             fish_quote(&commands.join(" "))));
+    for opt in OPTIONS.iter() {
+        if opt.is_global() {
+            script.push_str(&fish_line(opt, None));
+            continue;
+        }
+        let mut names = Vec::new();
+        for command in opt.commands.iter() {
+            names.extend(names_of(command));
+        }
+        let condition = format!("__fish_seen_subcommand_from {}", names.join(" "));
+        script.push_str(&fish_line(opt, Some(&condition)));
+    }
+    script.push_str(&format!("complete -c rooster -n '__fish_seen_subcommand_from completions' \
+                              -a {}\n",
+                             fish_quote(&SHELLS.join(" "))));
+    script.push_str(&format!("complete -c rooster -n 'not __fish_use_subcommand; and not \
+                              __fish_seen_subcommand_from completions' -a {}\n",
+                             fish_quote(&format!("({})", NAMES_COMMAND))));
+    script
+}
+
+pub fn callback_exec(matches: &getopts::Matches) -> Result<(), ExitCode> {
+    if matches.free.len() != 2 {
+        println_err!("Woops, completions needs the name of your shell. For help, try:");
+        println_err!("    rooster completions -h");
+        return Err(ExitCode::Usage);
+    }
+
+    let commands = command_names();
+    let script = match matches.free[1].as_str() {
+        "bash" => bash_script(&commands),
+        "zsh" => zsh_script(&commands),
+        "fish" => fish_script(&commands),
+        shell => {
+            println_err!("Woops, I can only complete for {}, not \"{}\".",
+                         SHELLS.join(", "),
+                         shell);
+            return Err(ExitCode::Error);
+        }
+    };
+    print_stdout!("{}", script);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{bash_script, command_names};
+
+    #[test]
+    fn options_are_only_completed_for_their_commands() {
+        let script = bash_script(&command_names());
+        let global = script.lines().find(|line| line.contains("local flags=")).unwrap();
+        assert!(global.contains(" --file ") && !global.contains("--swap"), "{}", global);
+        let rename = script.lines().find(|line| line.trim().starts_with("rename|mv)")).unwrap();
+        assert!(rename.contains("--swap") && !rename.contains("--file"), "{}", rename);
+        // Options that take a value are skipped to find the command, whichever it is.
+        let values = script.lines().find(|line| line.contains("local value_flags=")).unwrap();
+        assert!(values.contains(" -f --file ") && values.contains(" --map-fields "),
+                "{}",
+                values);
+    }
+
+    #[test]
+    fn every_command_is_completed() {
+        let script = bash_script(&command_names());
+        let line = script.lines().find(|line| line.contains("local commands=")).unwrap();
+        for name in command_names() {
+            assert!(line.split(|c| c == '"' || c == ' ').any(|word| word == name), "{}", name);
+        }
+        assert!(command_names().contains(&"completions"));
+    }
+}
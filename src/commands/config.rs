@@ -0,0 +1,169 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+use exit_code::ExitCode;
+use collation::Collation;
+use getopts;
+use notify;
+use password;
+use password::v2::Matching;
+use help::Help;
+use std::io::Write;
+
+pub fn callback_help() -> Help {
+    Help::new()
+        .usage(&["rooster config -h",
+                 "rooster config",
+                 "rooster config matching [exact|fuzzy]",
+                 "rooster config collation [codepoint|locale]",
+                 "rooster config notify-done [on|off]"])
+        .text(&["Shows or changes the settings saved in your password file."])
+        .text(&["matching: how commands like `rooster get` find a password from your query."])
+        .then_table(7,
+                    &[("fuzzy",
+                       "The letters of the query must appear in the app name, in order. If\n\
+                        several passwords match, you are asked to choose (default)."),
+                      ("exact",
+                       "The query must be the app name, without regard to casing. Close\n\
+                        names are only suggested.")])
+        .then_text(&["The --fuzzy and --no-fuzzy options override this setting for one command."])
+        .text(&["collation: how lists of passwords are sorted."])
+        .then_table(11,
+                    &[("codepoint",
+                       "By the code points of the names, without regard to casing. Accented\n\
+                        letters come after z (default)."),
+                      ("locale",
+                       "By script (Latin, Greek, Cyrillic...), then by letter without\n\
+                        regard to accents, then by accent and casing.")])
+        .then_text(&["The --collate option overrides this setting for one command."])
+        .text(&["notify-done: whether commands that take long ring the terminal bell and show a",
+                "desktop notification (with notify-send) when they are done."])
+        .then_table(7,
+                    &[("off", "Only with the --notify-done option (default)."),
+                      ("on", "Always.")])
+        .then_text(&[&format!("Commands take long when they run for {} seconds or more. Another \
+                               number of",
+                              notify::DEFAULT_THRESHOLD.as_secs()),
+                     "seconds may be set in the $ROOSTER_NOTIFY_AFTER environment variable."])
+        .examples(&["rooster config",
+                    "rooster config matching exact",
+                    "rooster config collation locale",
+                    "rooster config notify-done on"])
+}
+
+const SETTINGS: &'static [&'static str] = &["matching", "collation", "notify-done"];
+
+fn on_off_name(value: bool) -> &'static str {
+    if value { "on" } else { "off" }
+}
+
+fn on_off_from_name(name: &str) -> Option<bool> {
+    match name {
+        "on" => Some(true),
+        "off" => Some(false),
+        _ => None,
+    }
+}
+
+pub fn check_args(matches: &getopts::Matches) -> Result<(), ExitCode> {
+    if matches.free.len() > 3 {
+        println_err!("Woops, there are too many arguments here. For help, try:");
+        println_err!("    rooster config -h");
+        return Err(ExitCode::Usage);
+    }
+
+    if let Some(setting) = matches.free.get(1) {
+        if !SETTINGS.contains(&setting.as_str()) {
+            println_err!("Woops, there is no setting called \"{}\". For help, try:", setting);
+            println_err!("    rooster config -h");
+            return Err(ExitCode::Usage);
+        }
+    }
+
+    match (matches.free.get(1).map(|s| s.as_str()), matches.free.get(2)) {
+        (Some("matching"), Some(value)) if Matching::from_name(value).is_none() => {
+            println_err!("Woops, matching can be \"exact\" or \"fuzzy\", not \"{}\".", value);
+            Err(ExitCode::Usage)
+        }
+        (Some("collation"), Some(value)) if Collation::from_name(value).is_none() => {
+            println_err!("Woops, collation can be \"codepoint\" or \"locale\", not \"{}\".",
+                         value);
+            Err(ExitCode::Usage)
+        }
+        (Some("notify-done"), Some(value)) if on_off_from_name(value).is_none() => {
+            println_err!("Woops, notify-done can be \"on\" or \"off\", not \"{}\".", value);
+            Err(ExitCode::Usage)
+        }
+        _ => Ok(()),
+    }
+}
+
+pub fn callback_exec(matches: &getopts::Matches,
+                     store: &mut password::v2::PasswordStore)
+                     -> Result<(), ExitCode> {
+    check_args(matches)?;
+
+    let value = match matches.free.get(2) {
+        Some(value) => value,
+        None => {
+            let settings = store.settings();
+            let setting = matches.free.get(1).map(|s| s.as_str());
+            if setting.unwrap_or("matching") == "matching" {
+                println_stdout!("matching = {}", settings.matching.name());
+            }
+            if setting.unwrap_or("collation") == "collation" {
+                println_stdout!("collation = {}", settings.collation.name());
+            }
+            if setting.unwrap_or("notify-done") == "notify-done" {
+                println_stdout!("notify-done = {}", on_off_name(settings.notify_done));
+            }
+            return Ok(());
+        }
+    };
+
+    if matches.free[1] == "collation" {
+        let collation = Collation::from_name(value).ok_or(ExitCode::Error)?;
+        if store.settings().collation == collation {
+            println_ok!("Collation is already {}.", collation.name());
+            return Ok(());
+        }
+
+        store.set_collation(collation);
+        println_ok!("Done! Collation is now {}.", collation.name());
+        return Ok(());
+    }
+
+    if matches.free[1] == "notify-done" {
+        let notify_done = on_off_from_name(value).ok_or(ExitCode::Error)?;
+        if store.settings().notify_done == notify_done {
+            println_ok!("Notify-done is already {}.", on_off_name(notify_done));
+            return Ok(());
+        }
+
+        store.set_notify_done(notify_done);
+        println_ok!("Done! Notify-done is now {}.", on_off_name(notify_done));
+        return Ok(());
+    }
+
+    let matching = Matching::from_name(value).ok_or(ExitCode::Error)?;
+    if store.settings().matching == matching {
+        println_ok!("Matching is already {}.", matching.name());
+        return Ok(());
+    }
+
+    store.set_matching(matching);
+    println_ok!("Done! Matching is now {}.", matching.name());
+    Ok(())
+}
@@ -0,0 +1,204 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use exit_code::ExitCode;
+use answer;
+use getopts;
+use password;
+use password::v2::{Password, PasswordIndex, PasswordStore};
+use timestamp;
+use help::Help;
+use std::io::Write;
+
+pub fn callback_help() -> Help {
+    Help::new()
+        .usage(&["rooster dedupe -h",
+                 "rooster dedupe [--dry-run]"])
+        .text(&["Finds passwords that are probably for the same account: their names only differ",
+                "in casing, or they have the same username and password. For each group, you can",
+                "keep one password and delete the others, merge them into the most recently",
+                "updated one, or skip the group. Nothing is changed until all groups are done."])
+        .text(&["With --dry-run, the groups are only listed."])
+        .examples(&["rooster dedupe",
+                    "rooster dedupe --dry-run"])
+}
+
+enum Choice {
+    Keep(usize),
+    Merge,
+    Skip,
+}
+
+fn parse_choice(answer: &str, group_len: usize) -> Option<Choice> {
+    match answer.trim() {
+        "m" | "merge" => Some(Choice::Merge),
+        "s" | "skip" | "" => Some(Choice::Skip),
+        number => {
+            match number.parse::<usize>() {
+                Ok(i) if i >= 1 && i <= group_len => Some(Choice::Keep(i - 1)),
+                _ => None,
+            }
+        }
+    }
+}
+
+fn ask_choice(group_len: usize) -> Result<Choice, ExitCode> {
+    loop {
+        print_stderr!("Type a number to keep that password and delete the others, m to merge \
+                       them, or s to skip: ");
+        let mut line = String::new();
+        match answer::read_line(&mut line) {
+            Ok(0) => {
+                println_err!("I couldn't read your answer, so I didn't change anything.");
+                return Err(ExitCode::Error);
+            }
+            Ok(_) => {}
+            Err(err) => {
+                println_err!("I couldn't read your answer (reason: {:?}), so I didn't change \
+                              anything.",
+                             err);
+                return Err(ExitCode::Error);
+            }
+        }
+
+        match parse_choice(line.as_str(), group_len) {
+            Some(choice) => return Ok(choice),
+            None => println_err!("I need a number between 1 and {}, m or s.", group_len),
+        }
+    }
+}
+
+fn print_group(store: &PasswordStore, number: usize, group: &[PasswordIndex]) {
+    let passwords: Vec<&Password> = group.iter().map(|&i| store.password_at(i)).collect();
+    let name_width = passwords.iter().map(|p| p.name.len()).max().unwrap_or(0);
+    let username_width = passwords.iter().map(|p| p.username.len()).max().unwrap_or(0);
+
+    println_title!("Group {}:", number);
+    for (i, p) in passwords.iter().enumerate() {
+        println_stderr!("    {} {:name_width$} {:username_width$} updated on {}",
+                        i + 1,
+                        p.name,
+                        p.username,
+                        timestamp::to_local_string(p.updated_at),
+                        name_width = name_width,
+                        username_width = username_width);
+    }
+}
+
+/// The most recently updated password of the group, with the others folded into it.
+fn merge_group(store: &PasswordStore, group: &[PasswordIndex]) -> (PasswordIndex, Password) {
+    let newest = *group.iter()
+        .max_by_key(|&&i| store.password_at(i).updated_at)
+        .unwrap();
+
+    let mut merged = store.password_at(newest).clone();
+    for &i in group.iter().filter(|&&i| i != newest) {
+        merged.merge_from(store.password_at(i).clone(), store.history_depth());
+    }
+    (newest, merged)
+}
+
+pub fn callback_exec(matches: &getopts::Matches,
+                     store: &mut password::v2::PasswordStore)
+                     -> Result<(), ExitCode> {
+    let groups = store.find_duplicates();
+    if groups.is_empty() {
+        println_ok!("I didn't find any duplicates.");
+        return Ok(());
+    }
+
+    let dry_run = matches.opt_present("dry-run");
+
+    // Changes are collected first and applied at the end, so that positions in the store stay
+    // valid while going through the groups.
+    let mut replacements = Vec::new();
+    let mut deletions = Vec::new();
+    for (n, group) in groups.iter().enumerate() {
+        println_stderr!("");
+        print_group(store, n + 1, group);
+        if dry_run {
+            continue;
+        }
+
+        match ask_choice(group.len())? {
+            Choice::Keep(keep) => {
+                deletions.extend(group.iter()
+                    .enumerate()
+                    .filter(|&(i, _)| i != keep)
+                    .map(|(_, &index)| index));
+            }
+            Choice::Merge => {
+                let (newest, merged) = merge_group(store, group);
+                deletions.extend(group.iter().cloned().filter(|&i| i != newest));
+                replacements.push((newest, merged));
+            }
+            Choice::Skip => {}
+        }
+    }
+
+    println_stderr!("");
+    if dry_run {
+        println_ok!("I found {} group(s) of duplicates. Nothing was changed.", groups.len());
+        return Ok(());
+    }
+
+    let deleted = deletions.len();
+    let merged = replacements.len();
+    store.replace_and_delete(replacements, deletions);
+    println_ok!("Done! I've merged {} group(s) and deleted {} password(s).", merged, deleted);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::merge_group;
+    use password::v2::{Password, PasswordStore};
+    use safe_string::SafeString;
+
+    fn password(name: &str, username: &str, password: &str, updated_at: u32) -> Password {
+        Password {
+            created_at: updated_at,
+            updated_at: updated_at,
+            ..Password::new(name.to_owned(),
+                            username.to_owned(),
+                            SafeString::new(password.to_owned()))
+        }
+    }
+
+    #[test]
+    fn merge_keeps_the_newest_password() {
+        let mut store = PasswordStore::new(SafeString::new("master".to_owned())).unwrap();
+        let mut oldest = password("GitHub", "me", "secret", 10);
+        oldest.add_tag("work".to_owned());
+        store.add_password(oldest).unwrap();
+        let mut newest = password("github.com", "me", "secret", 20);
+        newest.notes = Some(SafeString::new("2FA on phone".to_owned()));
+        store.add_password(newest).unwrap();
+        store.add_password(password("gh", "me", "secret", 15)).unwrap();
+        store.add_password(password("GitLab", "me", "other", 30)).unwrap();
+
+        let groups = store.find_duplicates();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].len(), 3);
+
+        let (newest, merged) = merge_group(&store, &groups[0]);
+        assert_eq!(store.password_at(newest).name, "github.com");
+        assert_eq!(merged.password.expose(), "secret");
+        assert_eq!(merged.notes.unwrap().expose(), "2FA on phone");
+        assert_eq!(merged.tags, vec!["work"]);
+        assert_eq!(merged.created_at, 10);
+        assert_eq!(merged.updated_at, 20);
+        assert!(merged.history.is_empty());
+    }
+}
@@ -12,43 +12,120 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use exit_code::ExitCode;
+use answer;
 use getopts;
 use list;
 use password;
+use help::Help;
 use std::io::Write;
 
-pub fn callback_help() {
-    println!("Usage:");
-    println!("    rooster delete -h");
-    println!("    rooster delete <query>");
-    println!("");
-    println!("Examples:");
-    println!("    rooster delete youtube");
-    println!("    rooster delete ytb");
+pub fn callback_help() -> Help {
+    Help::new()
+        .usage(&["rooster delete -h",
+                 "rooster delete <query>",
+                 "rooster delete <query> <query>... [--strict]"])
+        .text(&["With several queries, the passwords to delete are listed and you are asked to",
+                "confirm once. Queries that match nothing are reported and skipped, unless",
+                "--strict is given, in which case nothing is deleted."])
+        .examples(&["rooster delete youtube",
+                    "rooster delete ytb",
+                    "rooster delete youtube github gitlab"])
 }
 
-pub fn check_args(matches: &getopts::Matches) -> Result<(), i32> {
+pub fn check_args(matches: &getopts::Matches) -> Result<(), ExitCode> {
     if matches.free.len() < 2 {
         println_err!("Woops, seems like the app name is missing here. For help, try:");
         println_err!("    rooster delete -h");
-        return Err(1);
+        return Err(ExitCode::Usage);
     }
 
     Ok(())
 }
 
+/// Adds the name unless it's already there, for queries that resolve to the same password.
+fn push_unique(names: &mut Vec<String>, name: String) {
+    if !names.contains(&name) {
+        names.push(name);
+    }
+}
+
+fn confirm_deletion(names: &[String]) -> Result<bool, ExitCode> {
+    println_stderr!("");
+    println_stderr_always!("I'm about to delete these {} password(s):", names.len());
+    for name in names.iter() {
+        println_stderr_always!("    {}", name);
+    }
+    print_stderr!("Delete them (y/n)? ");
+
+    let mut line = String::new();
+    answer::read_line(&mut line).map_err(|err| {
+        println_err!("I couldn't read that (reason: {:?}).", err);
+        ExitCode::Error
+    })?;
+    Ok(line.starts_with('y'))
+}
+
+fn delete_several(matches: &getopts::Matches,
+                  store: &mut password::v2::PasswordStore)
+                  -> Result<(), ExitCode> {
+    let strict = matches.opt_present("strict");
+
+    let mut names: Vec<String> = Vec::new();
+    let mut not_found: Vec<&str> = Vec::new();
+    for query in matches.free[1..].iter() {
+        println_stderr!("");
+        match list::search_and_choose_password(
+            store, query, list::WITH_NUMBERS,
+            &format!("Which password would you like me to delete for \"{}\"?", query),
+        ) {
+            Ok(password) => push_unique(&mut names, password.name.clone()),
+            Err(code) if strict => {
+                println_err!("Nothing was deleted, since --strict is on.");
+                return Err(code);
+            }
+            Err(_) => not_found.push(query),
+        }
+    }
+
+    if names.is_empty() {
+        println_err!("None of your queries match a password, so I didn't delete anything.");
+        return Err(ExitCode::NotFound);
+    }
+
+    if !confirm_deletion(&names)? {
+        println_stderr!("OK, I didn't delete anything.");
+        return Ok(());
+    }
+
+    // All the passwords are deleted in memory, the file is only written once at the end.
+    for name in names.iter() {
+        store.delete_password(name).unwrap();
+    }
+
+    println_ok!("Done! I've deleted {} password(s).", names.len());
+    if !not_found.is_empty() {
+        println_stderr!("Nothing matched: {}", not_found.join(", "));
+    }
+    Ok(())
+}
+
 pub fn callback_exec(matches: &getopts::Matches,
                      store: &mut password::v2::PasswordStore)
-                     -> Result<(), i32> {
+                     -> Result<(), ExitCode> {
     check_args(matches)?;
 
+    if matches.free.len() > 2 {
+        return delete_several(matches, store);
+    }
+
     let query = &matches.free[1];
 
     println_stderr!("");
     let password = list::search_and_choose_password(
         store, query, list::WITH_NUMBERS,
         "Which password would you like me to delete?",
-    ).ok_or(1)?.clone();
+    )?.clone();
 
     // This should always unwrap successfully, since the password is guaranteed to exist.
     store.delete_password(&password.name).unwrap();
@@ -57,3 +134,70 @@ pub fn callback_exec(matches: &getopts::Matches,
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::{callback_exec, push_unique};
+    use commands::harness::{run, store};
+    use exit_code::ExitCode;
+
+    #[test]
+    fn names_are_only_deleted_once() {
+        let mut names = Vec::new();
+        push_unique(&mut names, "YouTube".to_owned());
+        push_unique(&mut names, "GitHub".to_owned());
+        push_unique(&mut names, "YouTube".to_owned());
+        assert_eq!(names, vec!["YouTube", "GitHub"]);
+    }
+
+    #[test]
+    fn passwords_are_deleted() {
+        let mut store = store(&[("YouTube", "me@example.com", "hunter2"),
+                                ("GitHub", "me", "octocat")]);
+        let (result, output) = run(callback_exec, &["delete", "youtube"], &[], &mut store);
+        assert_eq!(result, Ok(()));
+        assert!(output.stderr.contains("I've deleted the password for \"YouTube\""));
+        assert!(!store.has_password("YouTube"));
+        assert!(store.has_password("GitHub"));
+    }
+
+    #[test]
+    fn several_passwords_are_deleted_once_confirmed() {
+        let mut store = store(&[("YouTube", "me@example.com", "hunter2"),
+                                ("GitHub", "me", "octocat")]);
+        let (result, _) = run(callback_exec, &["delete", "youtube", "github"], &["n"], &mut store);
+        assert_eq!(result, Ok(()));
+        assert!(store.has_password("YouTube"));
+
+        let (result, output) = run(callback_exec,
+                                   &["delete", "youtube", "github", "gitlab"],
+                                   &["y"],
+                                   &mut store);
+        assert_eq!(result, Ok(()));
+        assert!(output.stderr.contains("Nothing matched: gitlab"));
+        assert!(!store.has_password("YouTube"));
+        assert!(!store.has_password("GitHub"));
+    }
+
+    #[test]
+    fn nothing_is_deleted_without_a_match() {
+        let mut store = store(&[("YouTube", "me@example.com", "hunter2")]);
+        let (result, _) = run(callback_exec, &["delete", "gitlab"], &[], &mut store);
+        assert_eq!(result, Err(ExitCode::NotFound));
+
+        let (result, _) = run(callback_exec,
+                              &["delete", "youtube", "gitlab", "--strict"],
+                              &["y"],
+                              &mut store);
+        assert_eq!(result, Err(ExitCode::NotFound));
+        assert!(store.has_password("YouTube"));
+    }
+
+    #[test]
+    fn the_query_is_required() {
+        let mut store = store(&[]);
+        let (result, output) = run(callback_exec, &["delete"], &[], &mut store);
+        assert_eq!(result, Err(ExitCode::Usage));
+        assert!(output.stderr.contains("rooster delete -h"));
+    }
+}
@@ -0,0 +1,272 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use exit_code::ExitCode;
+use answer;
+use getopts;
+use password;
+use ffi;
+use timestamp;
+use help::Help;
+use std::io::Write;
+
+/// How far in the future a timestamp can be before we consider it wrong. This leaves room for
+/// clocks that are a bit off between machines.
+const FUTURE_TOLERANCE_SECONDS: ffi::time_t = 24 * 60 * 60;
+
+pub fn callback_help() -> Help {
+    Help::new()
+        .usage(&["rooster doctor -h",
+                 "rooster doctor [--fix-timestamps]"])
+        .text(&["Looks for problems in your password file, like timestamps in the future."])
+        .text(&["With --fix-timestamps, broken timestamps left by older versions are repaired,",
+                "after listing every fix and asking you to confirm:"])
+        .then_commands(&["- a creation date of 0 becomes the earliest date found in the file, or \
+                          the",
+                         "  update date of the password if it is earlier",
+                         "- an update date of 0 becomes the creation date",
+                         "- an update date before the creation date is swapped with it",
+                         "- dates more than a day in the future become the current date"])
+        .then_text(&["When the file has no usable date at all, the current date is used."])
+        .examples(&["rooster doctor",
+                    "rooster doctor --fix-timestamps"])
+}
+
+/// New timestamps for a password, with what was wrong with the old ones.
+#[derive(Debug, PartialEq)]
+struct TimestampFix {
+    name: String,
+    created_at: ffi::time_t,
+    updated_at: ffi::time_t,
+    problems: Vec<String>,
+}
+
+fn is_plausible(t: ffi::time_t, now: ffi::time_t) -> bool {
+    t != 0 && t <= now.saturating_add(FUTURE_TOLERANCE_SECONDS)
+}
+
+/// Finds the passwords with a timestamp of 0, an update before the creation, or a timestamp
+/// more than a day in the future (which usually means a timezone was applied twice somewhere),
+/// and how to fix them.
+fn plan_timestamp_fixes(passwords: &[&password::v2::Password],
+                        now: ffi::time_t)
+                        -> Vec<TimestampFix> {
+    let earliest = passwords.iter()
+        .flat_map(|p| vec![p.created_at, p.updated_at])
+        .filter(|&t| is_plausible(t, now))
+        .min();
+
+    let mut fixes = Vec::new();
+    for p in passwords.iter() {
+        let mut created_at = p.created_at;
+        let mut updated_at = p.updated_at;
+        let mut problems = Vec::new();
+
+        if created_at != 0 && !is_plausible(created_at, now) {
+            problems.push(format!("created on {}, which is in the future (fix: {})",
+                                  timestamp::to_local_string(created_at),
+                                  timestamp::to_local_string(now)));
+            created_at = now;
+        }
+        if updated_at != 0 && !is_plausible(updated_at, now) {
+            problems.push(format!("updated on {}, which is in the future (fix: {})",
+                                  timestamp::to_local_string(updated_at),
+                                  timestamp::to_local_string(now)));
+            updated_at = now;
+        }
+
+        if created_at == 0 {
+            let candidates = [earliest, if updated_at != 0 { Some(updated_at) } else { None }];
+            created_at = match candidates.iter().filter_map(|&t| t).min() {
+                Some(t) => {
+                    problems.push(format!("has no creation date (fix: {})",
+                                          timestamp::to_local_string(t)));
+                    t
+                }
+                None => {
+                    problems.push(format!("has no creation date and the file has no other \
+                                           date (fix: {})",
+                                          timestamp::to_local_string(now)));
+                    now
+                }
+            };
+        }
+        if updated_at == 0 {
+            problems.push(format!("has no update date (fix: the creation date, {})",
+                                  timestamp::to_local_string(created_at)));
+            updated_at = created_at;
+        }
+
+        if updated_at < created_at {
+            problems.push(format!("updated on {}, before its creation on {} (fix: swap them)",
+                                  timestamp::to_local_string(updated_at),
+                                  timestamp::to_local_string(created_at)));
+            let created = created_at;
+            created_at = updated_at;
+            updated_at = created;
+        }
+
+        if !problems.is_empty() {
+            fixes.push(TimestampFix {
+                name: p.name.clone(),
+                created_at: created_at,
+                updated_at: updated_at,
+                problems: problems,
+            });
+        }
+    }
+    fixes
+}
+
+fn print_fixes(fixes: &[TimestampFix]) {
+    for fix in fixes.iter() {
+        for problem in fix.problems.iter() {
+            println_stderr_always!("    {}: {}", fix.name, problem);
+        }
+    }
+}
+
+fn fix_timestamps(store: &mut password::v2::PasswordStore,
+                  fixes: Vec<TimestampFix>)
+                  -> Result<(), ExitCode> {
+    println_stderr_always!("I'm about to fix these timestamps:");
+    print_fixes(&fixes);
+    print_stderr!("Fix them (y/n)? ");
+
+    let mut line = String::new();
+    answer::read_line(&mut line).map_err(|err| {
+        println_err!("I couldn't read that (reason: {:?}).", err);
+        ExitCode::Error
+    })?;
+    if !line.starts_with('y') {
+        println_stderr!("OK, I didn't change anything.");
+        return Ok(());
+    }
+
+    // All the passwords are changed in memory, the file is only written once at the end.
+    for fix in fixes.iter() {
+        store.change_password(&fix.name,
+                              &|old_password: password::v2::Password| {
+            password::v2::Password {
+                created_at: fix.created_at,
+                updated_at: fix.updated_at,
+                ..old_password
+            }
+        }).map_err(|err| {
+            println_err!("Woops, I couldn't fix {} (reason: {:?}).", fix.name, err);
+            ExitCode::from_password_error(&err)
+        })?;
+    }
+
+    println_ok!("Done! I've fixed the timestamps of {} password(s).", fixes.len());
+    Ok(())
+}
+
+pub fn callback_exec(matches: &getopts::Matches,
+                     store: &mut password::v2::PasswordStore)
+                     -> Result<(), ExitCode> {
+    let fixes = plan_timestamp_fixes(&store.get_all_passwords(), ffi::time());
+
+    if fixes.is_empty() {
+        println_ok!("Everything looks fine!");
+        return Ok(());
+    }
+
+    if matches.opt_present("fix-timestamps") {
+        return fix_timestamps(store, fixes);
+    }
+
+    let count = fixes.iter().map(|f| f.problems.len()).sum::<usize>();
+    println_err!("I found {} problem(s) in your password file:", count);
+    print_fixes(&fixes);
+    println_stderr!("To fix them, run `rooster doctor --fix-timestamps`.");
+    Err(ExitCode::Error)
+}
+
+#[cfg(test)]
+mod test {
+    use super::plan_timestamp_fixes;
+    use password::v2::Password;
+    use safe_string::SafeString;
+
+    fn password(name: &str, created_at: u32, updated_at: u32) -> Password {
+        Password {
+            created_at: created_at,
+            updated_at: updated_at,
+            ..Password::new(name.to_owned(), "me".to_owned(), SafeString::new("x".to_owned()))
+        }
+    }
+
+    const NOW: u32 = 1600000000;
+    const DAY: u32 = 24 * 60 * 60;
+
+    /// (name, created_at, updated_at) of each fix.
+    fn fixed(passwords: &[&Password]) -> Vec<(String, u32, u32)> {
+        plan_timestamp_fixes(passwords, NOW)
+            .into_iter()
+            .map(|f| (f.name, f.created_at, f.updated_at))
+            .collect()
+    }
+
+    #[test]
+    fn future_timestamps() {
+        let ok = password("ok", NOW - 10, NOW + DAY);
+        let created = password("created", NOW + DAY + 1, NOW);
+        let both = password("both", NOW + 2 * DAY, NOW + 3 * DAY);
+
+        assert!(plan_timestamp_fixes(&[&ok], NOW).is_empty());
+        let fixes = plan_timestamp_fixes(&[&ok, &created, &both], NOW);
+        assert_eq!(fixes.iter().map(|f| f.problems.len()).sum::<usize>(), 3);
+        assert_eq!(fixed(&[&ok, &created, &both]),
+                   vec![("created".to_owned(), NOW, NOW), ("both".to_owned(), NOW, NOW)]);
+    }
+
+    #[test]
+    fn zero_creation_dates() {
+        let old = password("old", NOW - 100, NOW - 50);
+        let zero = password("zero", 0, NOW - 10);
+        let zero_before = password("zero-before", 0, NOW - 200);
+        assert_eq!(fixed(&[&old, &zero, &zero_before]),
+                   vec![("zero".to_owned(), NOW - 200, NOW - 10),
+                        ("zero-before".to_owned(), NOW - 200, NOW - 200)]);
+    }
+
+    #[test]
+    fn zero_update_dates() {
+        let zero = password("zero", NOW - 100, 0);
+        assert_eq!(fixed(&[&zero]), vec![("zero".to_owned(), NOW - 100, NOW - 100)]);
+    }
+
+    #[test]
+    fn swapped_dates() {
+        let swapped = password("swapped", NOW - 10, NOW - 100);
+        assert_eq!(fixed(&[&swapped]), vec![("swapped".to_owned(), NOW - 100, NOW - 10)]);
+    }
+
+    #[test]
+    fn all_timestamps_are_zero() {
+        let a = password("a", 0, 0);
+        let b = password("b", 0, 0);
+        assert_eq!(fixed(&[&a, &b]),
+                   vec![("a".to_owned(), NOW, NOW), ("b".to_owned(), NOW, NOW)]);
+    }
+
+    #[test]
+    fn future_dates_are_not_used_for_zero_dates() {
+        let future = password("future", NOW + 2 * DAY, NOW + 2 * DAY);
+        let zero = password("zero", 0, 0);
+        assert_eq!(fixed(&[&future, &zero]),
+                   vec![("future".to_owned(), NOW, NOW), ("zero".to_owned(), NOW, NOW)]);
+    }
+}
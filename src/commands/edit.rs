@@ -0,0 +1,433 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use exit_code::ExitCode;
+use answer;
+use getopts;
+use password;
+use password::v2::normalize_tag;
+use ffi;
+use list;
+use rand::{Rng, OsRng};
+use safe_string::SafeString;
+use safe_vec::SafeVec;
+use help::Help;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write, Result as IoResult};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+pub fn callback_help() -> Help {
+    Help::new()
+        .usage(&["rooster edit -h",
+                 "rooster edit <query>"])
+        .text(&["Opens the password in your $EDITOR, so you can change several fields at once."])
+        .examples(&["rooster edit youtube",
+                    "rooster edit ytb"])
+}
+
+pub fn check_args(matches: &getopts::Matches) -> Result<(), ExitCode> {
+    if matches.free.len() < 2 {
+        println_err!("Woops, seems like the app name is missing here. For help, try:");
+        println_err!("    rooster edit -h");
+        return Err(ExitCode::Usage);
+    }
+
+    Ok(())
+}
+
+/// A temporary file that only the current user can read, which gets overwritten with zeros
+/// and deleted when dropped, whatever happens to the editor.
+struct SecretTempFile {
+    path: PathBuf,
+}
+
+impl SecretTempFile {
+    fn create(contents: &[u8]) -> IoResult<SecretTempFile> {
+        let mut rng = OsRng::new()?;
+        let name = format!("rooster-edit-{:016x}.txt", rng.next_u64());
+        let path = secret_temp_dir().join(name);
+
+        let mut file = open_new_private_file(&path)?;
+        let temp_file = SecretTempFile { path: path };
+        file.write_all(contents)?;
+        file.sync_all()?;
+
+        Ok(temp_file)
+    }
+
+    fn write(&self, contents: &[u8]) -> IoResult<()> {
+        let mut file = OpenOptions::new().write(true).truncate(true).open(&self.path)?;
+        file.write_all(contents)?;
+        file.sync_all()
+    }
+
+    fn read(&self) -> IoResult<SafeString> {
+        let mut contents = SafeVec::new(Vec::new());
+        File::open(&self.path)?.read_to_end(&mut contents.inner_mut())?;
+        Ok(SafeString::new(String::from_utf8_lossy(contents.expose()).into_owned()))
+    }
+
+    fn wipe(&self) -> IoResult<()> {
+        let len = fs::metadata(&self.path)?.len() as usize;
+        let mut file = OpenOptions::new().write(true).open(&self.path)?;
+        file.write_all(vec![0u8; len].as_ref())?;
+        file.sync_all()
+    }
+}
+
+impl Drop for SecretTempFile {
+    fn drop(&mut self) {
+        if let Err(err) = self.wipe() {
+            println_err!("Woops, I could not overwrite the temporary file {} (reason: {:?}).",
+                         self.path.to_string_lossy(),
+                         err);
+        }
+        if let Err(err) = fs::remove_file(&self.path) {
+            println_err!("Woops, I could not delete the temporary file {} (reason: {:?}). \
+                          Please delete it yourself.",
+                         self.path.to_string_lossy(),
+                         err);
+        }
+    }
+}
+
+/// Prefer memory-backed folders, so the password never touches the disk.
+fn secret_temp_dir() -> PathBuf {
+    if let Some(runtime_dir) = env::var_os("XDG_RUNTIME_DIR") {
+        let runtime_dir = PathBuf::from(runtime_dir);
+        if runtime_dir.is_dir() {
+            return runtime_dir;
+        }
+    }
+
+    let shm = Path::new("/dev/shm");
+    if shm.is_dir() {
+        return shm.to_path_buf();
+    }
+
+    env::temp_dir()
+}
+
+#[cfg(unix)]
+fn open_new_private_file(path: &Path) -> IoResult<File> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    OpenOptions::new().write(true).create_new(true).mode(0o600).open(path)
+}
+
+#[cfg(not(unix))]
+fn open_new_private_file(path: &Path) -> IoResult<File> {
+    OpenOptions::new().write(true).create_new(true).open(path)
+}
+
+/// Fields of a password, as the user edited them.
+struct EditedPassword {
+    name: String,
+    username: String,
+    password: SafeString,
+    url: Option<String>,
+    notes: Option<SafeString>,
+    tags: Vec<String>,
+}
+
+fn to_editable(password: &password::v2::Password) -> SafeString {
+    let mut s = String::new();
+    s.push_str("# Edit the password below, then save and close your editor.\n");
+    s.push_str("# Lines starting with '#' are ignored, except in the notes.\n");
+    s.push_str("# The notes go until the end of the file and may span several lines.\n");
+    s.push_str(format!("name: {}\n", password.name).as_str());
+    s.push_str(format!("username: {}\n", password.username).as_str());
+    s.push_str(format!("password: {}\n", password.password.expose()).as_str());
+    s.push_str(format!("url: {}\n", password.url.as_ref().map_or("", |url| url.as_str())).as_str());
+    s.push_str(format!("tags: {}\n", password.tags.join(", ")).as_str());
+    s.push_str("notes:");
+    if let Some(ref notes) = password.notes {
+        s.push_str("\n");
+        s.push_str(notes.expose());
+    }
+    s.push_str("\n");
+    SafeString::new(s)
+}
+
+fn from_editable(contents: &str) -> Result<EditedPassword, String> {
+    let mut name = None;
+    let mut username = None;
+    let mut password = None;
+    let mut url = None;
+    let mut notes: Option<String> = None;
+    let mut tags: Option<Vec<String>> = None;
+
+    // Editors on Windows may save the file with CRLF line endings.
+    for (i, line) in contents.lines().map(|line| line.trim_end_matches('\r')).enumerate() {
+        // Everything after the "notes:" line belongs to the notes, verbatim.
+        if let Some(ref mut notes) = notes {
+            notes.push_str(line);
+            notes.push('\n');
+            continue;
+        }
+
+        if line.starts_with('#') || line.trim().is_empty() {
+            continue;
+        }
+
+        let colon = line.find(':')
+            .ok_or(format!("line {} should look like \"field: value\"", i + 1))?;
+        let key = line[..colon].trim();
+        // Only remove the space that follows the colon, the rest of the value is kept as is.
+        let value = &line[colon + 1..];
+        let value = if value.starts_with(' ') {
+            &value[1..]
+        } else {
+            value
+        };
+
+        if key == "notes" {
+            let mut first_line = value.to_owned();
+            if !first_line.is_empty() {
+                first_line.push('\n');
+            }
+            notes = Some(first_line);
+            continue;
+        }
+
+        if key == "tags" {
+            if tags.is_some() {
+                return Err(format!("line {} sets \"tags\" a second time", i + 1));
+            }
+            let mut parsed_tags: Vec<String> = Vec::new();
+            for tag in value.split(',').filter(|t| !t.trim().is_empty()) {
+                let tag = normalize_tag(tag).map_err(|err| format!("line {}: {}", i + 1, err))?;
+                if !parsed_tags.contains(&tag) {
+                    parsed_tags.push(tag);
+                }
+            }
+            parsed_tags.sort();
+            tags = Some(parsed_tags);
+            continue;
+        }
+
+        let field = match key {
+            "name" => &mut name,
+            "username" => &mut username,
+            "password" => &mut password,
+            "url" => &mut url,
+            _ => return Err(format!("line {} has an unknown field \"{}\"", i + 1, key)),
+        };
+        if field.is_some() {
+            return Err(format!("line {} sets \"{}\" a second time", i + 1, key));
+        }
+        *field = Some(value.to_owned());
+    }
+
+    let name = name.ok_or("the \"name\" field is missing".to_owned())?;
+    if name.trim().is_empty() {
+        return Err("the app name can't be empty".to_owned());
+    }
+    let password = password.map(SafeString::new)
+        .ok_or("the \"password\" field is missing".to_owned())?;
+    if password.is_empty() {
+        return Err("the password can't be empty".to_owned());
+    }
+
+    let notes = notes.map(|notes| SafeString::new(notes.trim_end_matches('\n').to_owned()))
+        .and_then(|notes| if notes.expose().trim().is_empty() { None } else { Some(notes) });
+
+    Ok(EditedPassword {
+        name: name,
+        username: username.unwrap_or_default(),
+        password: password,
+        url: url.map(|url| url.trim().to_owned()).filter(|url| !url.is_empty()),
+        notes: notes,
+        tags: tags.unwrap_or_default(),
+    })
+}
+
+fn run_editor(path: &Path) -> IoResult<bool> {
+    answer::require_interactive("changes in your editor");
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or("vi".to_owned());
+
+    // Let the shell split the editor command, so that things like "code --wait" work.
+    Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg("sh")
+        .arg(path)
+        .status()
+        .map(|status| status.success())
+}
+
+fn ask_edit_again() -> bool {
+    loop {
+        print_stderr!("Would you like to edit it again (y/n)? ");
+        let mut line = String::new();
+        match answer::read_line(&mut line) {
+            Ok(_) if line.starts_with('y') => return true,
+            Ok(_) if line.starts_with('n') => return false,
+            Ok(_) => println_stderr!("I didn't get that."),
+            Err(_) => return false,
+        }
+    }
+}
+
+pub fn callback_exec(matches: &getopts::Matches,
+                     store: &mut password::v2::PasswordStore)
+                     -> Result<(), ExitCode> {
+    check_args(matches)?;
+
+    let query = &matches.free[1];
+
+    println_stderr!("");
+    let password = list::search_and_choose_password(
+        store, query, list::WITH_NUMBERS,
+        "Which password would you like to edit?",
+    )?.clone();
+
+    let editable = to_editable(&password);
+    let temp_file = SecretTempFile::create(editable.expose().as_bytes()).map_err(|err| {
+        println_err!("Woops, I couldn't create a temporary file (reason: {:?}).", err);
+        ExitCode::Io
+    })?;
+
+    let edited = loop {
+        match run_editor(&temp_file.path) {
+            Ok(true) => {}
+            Ok(false) => {
+                println_err!("Your editor exited with an error, I didn't change anything.");
+                return Err(ExitCode::Error);
+            }
+            Err(err) => {
+                println_err!("Woops, I couldn't start your editor (reason: {:?}). Try setting \
+                              the $EDITOR environment variable.",
+                             err);
+                return Err(ExitCode::Error);
+            }
+        }
+
+        let contents = temp_file.read().map_err(|err| {
+            println_err!("Woops, I couldn't read the temporary file (reason: {:?}).", err);
+            ExitCode::Io
+        })?;
+
+        let error = match from_editable(contents.expose()) {
+            Ok(edited) => {
+                if edited.name.to_lowercase() != password.name.to_lowercase() &&
+                   store.has_password(edited.name.as_str()) {
+                    format!("there is already an app named \"{}\"", edited.name)
+                } else {
+                    break edited;
+                }
+            }
+            Err(error) => error,
+        };
+
+        println_err!("Woops, I couldn't understand your changes: {}.", error);
+        if !ask_edit_again() {
+            println_err!("OK, I didn't change anything.");
+            return Err(ExitCode::Error);
+        }
+
+        // Put the user's own edits back in the file, so they don't get lost.
+        temp_file.write(contents.expose().as_bytes()).map_err(|err| {
+            println_err!("Woops, I couldn't write the temporary file (reason: {:?}).", err);
+            ExitCode::Io
+        })?;
+    };
+
+    // Drop early so the file is wiped as soon as we no longer need it.
+    drop(temp_file);
+
+    store.change_password(&password.name,
+                          &|old_password: password::v2::Password| {
+        password::v2::Password {
+            name: edited.name.clone(),
+            username: edited.username.clone(),
+            password: edited.password.clone(),
+            url: edited.url.clone(),
+            notes: edited.notes.clone(),
+            tags: edited.tags.clone(),
+            updated_at: ffi::time(),
+            ..old_password
+        }
+    }).map_err(|err| {
+        println_err!("Woops, I couldn't save your changes (reason: {:?}).", err);
+        ExitCode::from_password_error(&err)
+    })?;
+
+    println_ok!("Done! I've saved your changes to \"{}\".", edited.name);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{from_editable, to_editable};
+    use password::v2::Password;
+    use safe_string::SafeString;
+
+    fn youtube() -> Password {
+        let mut password = Password::new("YouTube".to_owned(),
+                                         "me@example.com".to_owned(),
+                                         SafeString::new(" p@ss: word ".to_owned()));
+        password.url = Some("https://youtube.com/login".to_owned());
+        password.notes = Some(SafeString::new("Recovery codes:\n# 1234\n\n5678".to_owned()));
+        password.tags = vec!["personal".to_owned(), "video".to_owned()];
+        password
+    }
+
+    #[test]
+    fn edited_passwords_round_trip() {
+        let password = youtube();
+        let edited = from_editable(to_editable(&password).expose()).unwrap();
+        assert_eq!(edited.name, password.name);
+        assert_eq!(edited.username, password.username);
+        assert_eq!(edited.password, password.password);
+        assert_eq!(edited.url, password.url);
+        assert_eq!(edited.notes, password.notes);
+        assert_eq!(edited.tags, password.tags);
+    }
+
+    #[test]
+    fn empty_fields_round_trip() {
+        let password = Password::new("YouTube".to_owned(),
+                                     "".to_owned(),
+                                     SafeString::new("xxx".to_owned()));
+        let edited = from_editable(to_editable(&password).expose()).unwrap();
+        assert_eq!(edited.username, "");
+        assert_eq!(edited.url, None);
+        assert!(edited.notes.is_none());
+        assert!(edited.tags.is_empty());
+    }
+
+    #[test]
+    fn crlf_line_endings_are_stripped() {
+        let password = youtube();
+        let windows = to_editable(&password).expose().replace('\n', "\r\n");
+        let edited = from_editable(&windows).unwrap();
+        assert_eq!(edited.password, password.password);
+        assert_eq!(edited.url, password.url);
+        assert_eq!(edited.notes, password.notes);
+    }
+
+    #[test]
+    fn url_can_be_changed_or_removed() {
+        let edited = from_editable("name: a\npassword: b\nurl: https://example.com \n").unwrap();
+        assert_eq!(edited.url, Some("https://example.com".to_owned()));
+        let edited = from_editable("name: a\npassword: b\nurl:\n").unwrap();
+        assert_eq!(edited.url, None);
+        assert!(from_editable("name: a\npassword: b\nurl: c\nurl: d\n").is_err());
+    }
+}
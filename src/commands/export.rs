@@ -12,36 +12,35 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use exit_code::ExitCode;
 use getopts;
 use password;
 use safe_string::SafeString;
 use serde_json;
-use std::ops::Deref;
+use help::Help;
 use std::io::Write;
 
-pub fn callback_help() {
-    println!("Usage:");
-    println!("    rooster export -h");
-    println!("    rooster export");
-    println!("");
-    println!("Example:");
-    println!("    rooster export");
+pub fn callback_help() -> Help {
+    Help::new()
+        .usage(&["rooster export -h",
+                 "rooster export"])
+        .examples(&["rooster export"])
 }
 
 pub fn callback_exec(_matches: &getopts::Matches,
                      store: &mut password::v2::PasswordStore)
-                     -> Result<(), i32> {
+                     -> Result<(), ExitCode> {
     let passwords_ref = store.get_all_passwords();
 
     let passwords_json = match serde_json::to_string(&passwords_ref) {
         Ok(passwords_json) => passwords_json,
         Err(json_err) => {
-            println_stderr!("Woops, I could not encode the passwords into JSON (reason: {:?}).",
-                            json_err);
-            return Err(1);
+            println_err!("Woops, I could not encode the passwords into JSON (reason: {:?}).",
+                         json_err);
+            return Err(ExitCode::Error);
         }
     };
     let passwords = SafeString::new(passwords_json);
-    println!("{}", passwords.deref());
+    println_stdout!("{}", passwords.expose());
     Ok(())
 }
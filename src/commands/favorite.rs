@@ -0,0 +1,73 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use exit_code::ExitCode;
+use getopts;
+use password;
+use list;
+use help::Help;
+use std::io::Write;
+
+pub fn callback_help() -> Help {
+    Help::new()
+        .usage(&["rooster favorite -h",
+                 "rooster favorite <query>"])
+        .text(&["Marks the password as a favorite, or unmarks it if it already is one. Favorites",
+                "are listed first by `rooster list` and when choosing between several passwords."])
+        .examples(&["rooster favorite youtube"])
+}
+
+pub fn check_args(matches: &getopts::Matches) -> Result<(), ExitCode> {
+    if matches.free.len() < 2 {
+        println_err!("Woops, seems like the app name is missing here. For help, try:");
+        println_err!("    rooster favorite -h");
+        return Err(ExitCode::Usage);
+    }
+
+    Ok(())
+}
+
+pub fn callback_exec(matches: &getopts::Matches,
+                     store: &mut password::v2::PasswordStore)
+                     -> Result<(), ExitCode> {
+    check_args(matches)?;
+
+    let query = &matches.free[1];
+
+    println_stderr!("");
+    let password = list::search_and_choose_password(
+        store, query, list::WITH_NUMBERS,
+        "Which password would you like to mark as a favorite?",
+    )?.clone();
+
+    // Favorites are a matter of display, so updated_at is left alone.
+    let favorite = !password.favorite;
+    store.change_password(&password.name,
+                          &|old_password: password::v2::Password| {
+        password::v2::Password {
+            favorite: favorite,
+            ..old_password
+        }
+    }).map_err(|err| {
+        println_err!("Woops, I couldn't save the change (reason: {:?}).", err);
+        ExitCode::from_password_error(&err)
+    })?;
+
+    if favorite {
+        println_ok!("Done! {} is now a favorite.", password.name);
+    } else {
+        println_ok!("Done! {} is not a favorite anymore.", password.name);
+    }
+    Ok(())
+}
@@ -0,0 +1,69 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+use exit_code::ExitCode;
+use getopts;
+use clip::{copy_to_clipboard, explain_copy, paste_keys};
+use super::generate::{check_generation_args, generate_password_from_matches};
+use help::Help;
+use std::io::Write;
+
+pub fn callback_help() -> Help {
+    Help::new()
+        .usage(&["rooster gen -h",
+                 "rooster gen [--show] [<generation options>]"])
+        .text(&["Generates a password without saving it, for things that aren't accounts, like a",
+                "Wi-Fi key. Your password file isn't opened, so the master password isn't asked."])
+        .text(&["The password is copied to the clipboard, or printed on stdout with --show. All",
+                "the options of `rooster generate` can be used, except --similar-to."])
+        .examples(&["rooster gen",
+                    "rooster gen -l 63 --alnum --show",
+                    "rooster gen --words 5 --show"])
+}
+
+pub fn callback_exec(matches: &getopts::Matches) -> Result<(), ExitCode> {
+    if matches.free.len() > 1 {
+        println_err!("Woops, gen doesn't take any app name or username. To save the password, \
+                      use `rooster generate`. For help, try:");
+        println_err!("    rooster gen -h");
+        return Err(ExitCode::Usage);
+    }
+
+    if matches.opt_present("similar-to") {
+        println_err!("Woops, --similar-to needs your password file, use `rooster generate` \
+                      instead.");
+        return Err(ExitCode::Usage);
+    }
+
+    check_generation_args(matches, "gen")?;
+
+    let password = generate_password_from_matches(matches)?;
+
+    if matches.opt_present("show") {
+        println_stdout!("{}", password.expose());
+        return Ok(());
+    }
+
+    let copied = copy_to_clipboard(&password).map_err(|_| {
+        println_err!("Woops, I couldn't copy the password to your clipboard. Try again with \
+                      --show to see it.");
+        ExitCode::Error
+    })?;
+    println_ok!("Alright! You can paste your new password with {}. It isn't saved.",
+                paste_keys(copied));
+    // It isn't saved, so it can't be shown again: the user will have to generate another.
+    explain_copy(copied, "rooster gen --show");
+    Ok(())
+}
@@ -12,92 +12,474 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use exit_code::ExitCode;
 use getopts;
 use password;
-use generate::{PasswordSpec, generate_hard_password};
-use clip::{copy_to_clipboard, paste_keys};
+use generate::{PassphraseSpec, PasswordShape, PasswordSpec, PronounceableSpec,
+               generate_hard_password, generate_passphrase, generate_password_with_shape,
+               generate_pin, generate_pronounceable_password, wordlist_len, DEFAULT_PIN_LENGTH,
+               SIMILAR_CHARS};
+use safe_string::SafeString;
+use super::add::save_new_password;
+use list;
+use output_format;
+use help::Help;
 use std::io::Write;
 use std::ops::Deref;
 
-pub fn callback_help() {
-    println!("Usage:");
-    println!("    rooster generate -h");
-    println!("    rooster generate <app_name> <username>");
-    println!("");
-    println!("Example:");
-    println!("    rooster generate YouTube me@example.com");
+pub fn callback_help() -> Help {
+    Help::new()
+        .usage(&["rooster generate -h",
+                 "rooster generate <app_name> <username> [--tag <tag>]...",
+                 "rooster generate <app_name> <username> [--charset <chars>] \
+                  [--exclude-chars <chars>] [--exclude-similar]",
+                 "rooster generate <app_name> <username> --similar-to <query>",
+                 "rooster generate <app_name> <username> --words <n> [--separator <sep>] \
+                  [--capitalize] [--add-digit]",
+                 "rooster generate <app_name> <username> --pronounceable [-l <length>] \
+                  [--add-digit]",
+                 "rooster generate <app_name> <username> --pin [-l <length>]"])
+        .examples(&["rooster generate YouTube me@example.com",
+                    "rooster generate Slack me@work.com --tag work"])
+        .text(&["With --charset, the password is only made of the given characters. With",
+                "--exclude-chars, the given characters are left out, and --exclude-similar leaves",
+                &format!("out {}, which look alike.", SIMILAR_CHARS)])
+        .commands(&["rooster generate Bank me --exclude-chars \"\\\"'\\\\&\"",
+                    "rooster generate Router admin --charset abcdef0123456789 -l 24",
+                    "rooster generate WiFi guest --alnum --exclude-similar"])
+        .text(&["The entropy of the new password is shown, and with --min-entropy <bits>, a",
+                "password with less entropy isn't saved."])
+        .commands(&["rooster generate Shop me -l 16 --exclude-chars \"&<>\" --min-entropy 90"])
+        .text(&["With --similar-to, the new password has the same length and the same kinds of",
+                "characters (lowercase, uppercase, digits, symbols) as the password for <query>."])
+        .commands(&["rooster generate GitHub2 me@example.com --similar-to github"])
+        .text(&["With --words, the password is a passphrase of <n> random words, which is easier",
+                "to type. Words are joined with dashes, or with --separator. --capitalize makes",
+                "the first letter of each word uppercase and --add-digit adds a digit at the end.",
+                &format!("Words come from the EFF large wordlist of {} words, so each one adds \
+                          about {:.1}",
+                         wordlist_len(),
+                         (wordlist_len() as f64).log2()),
+                "bits of entropy: use 6 words or more for a master password."])
+        .commands(&["rooster generate Laptop me --words 6 --separator ' ' --capitalize"])
+        .text(&["With --pronounceable, the password alternates consonants and vowels, so that it",
+                "can be read aloud. --length still counts every character, and --add-digit ends",
+                "it with a digit. Such passwords are easier to guess, so make them longer."])
+        .commands(&["rooster generate TV me --pronounceable -l 16 --add-digit"])
+        .text(&[&format!("With --pin, the password is made of digits only, {} unless --length says",
+                         DEFAULT_PIN_LENGTH),
+                "otherwise. PINs shorter than 4 digits are allowed, with a warning."])
+        .commands(&["rooster generate -l 4 --pin bank-card me"])
 }
 
-pub fn check_args(matches: &getopts::Matches) -> Result<(), i32> {
+/// Each of these decides on the characters of the password by itself.
+const MODES: &'static [&'static str] = &["similar-to", "words", "pronounceable", "pin"];
+
+/// The options that pick the characters of a random password.
+const CHARACTER_OPTIONS: &'static [&'static str] =
+    &["alnum", "charset", "exclude-chars", "exclude-similar"];
+
+/// Makes sure the generation options go together, so that none of them is silently ignored.
+pub fn check_generation_args(matches: &getopts::Matches, command: &str) -> Result<(), ExitCode> {
+    let present = |options: &[&'static str]| -> Vec<&'static str> {
+        options.iter().cloned().filter(|option| matches.opt_present(option)).collect()
+    };
+    let fail = |message: String| -> Result<(), ExitCode> {
+        println_err!("Woops, {}. For help, try:", message);
+        println_err!("    rooster {} -h", command);
+        Err(ExitCode::Usage)
+    };
+
+    let modes = present(MODES);
+    if modes.len() > 1 {
+        return fail(format!("--{} and --{} can't be used together", modes[0], modes[1]));
+    }
+
+    if let Some(mode) = modes.first() {
+        let mut conflicts = present(CHARACTER_OPTIONS);
+        // --similar-to and --words have a length of their own.
+        if (*mode == "similar-to" || *mode == "words") && matches.opt_present("length") {
+            conflicts.push("length");
+        }
+        if let Some(conflict) = conflicts.first() {
+            return fail(format!("--{} can't be used with --{}", mode, conflict));
+        }
+    }
+
+    if matches.opt_present("charset") && matches.opt_present("alnum") {
+        return fail("--charset can't be used with --alnum".to_owned());
+    }
+
+    if !matches.opt_present("words") &&
+       (matches.opt_present("separator") || matches.opt_present("capitalize")) {
+        return fail("--separator and --capitalize only go with --words".to_owned());
+    }
+
+    if matches.opt_present("add-digit") &&
+       !(matches.opt_present("words") || matches.opt_present("pronounceable")) {
+        return fail("--add-digit only goes with --words or --pronounceable".to_owned());
+    }
+
+    // Tell about a bad --charset or --min-entropy before asking for the master password.
+    if modes.is_empty() {
+        password_spec_from_matches(matches).ok_or(ExitCode::Error)?;
+    }
+    min_entropy_from_matches(matches)?;
+
+    Ok(())
+}
+
+pub fn check_args(matches: &getopts::Matches) -> Result<(), ExitCode> {
     if matches.free.len() < 3 {
         println_err!("Woops, seems like the app name or the username is missing here. For help, \
         try:");
         println_err!("    rooster generate -h");
-        return Err(1);
+        return Err(ExitCode::Usage);
+    }
+
+    check_generation_args(matches, "generate")?;
+
+    Ok(())
+}
+
+/// The --length option, `Err` if it isn't a number (after telling the user).
+fn length_from_matches(matches: &getopts::Matches) -> Result<Option<usize>, ()> {
+    match matches.opt_str("length") {
+        Some(len) => {
+            len.parse::<usize>().map(Some).map_err(|_| {
+                println_err!("Woops! The length option must be a valid number, for instance 8 or \
+                              16.");
+            })
+        }
+        None => Ok(None),
+    }
+}
+
+/// The characters allowed by --alnum, --charset, --exclude-chars and --exclude-similar.
+fn alphabet_from_matches(matches: &getopts::Matches) -> Vec<char> {
+    let mut alphabet: Vec<char> = match matches.opt_str("charset") {
+        Some(charset) => charset.chars().collect(),
+        None if matches.opt_present("alnum") => {
+            (b'0'..b'z' + 1).map(|b| b as char).filter(char::is_ascii_alphanumeric).collect()
+        }
+        None => (33u8..127).map(|b| b as char).collect(),
+    };
+
+    let mut excluded = matches.opt_str("exclude-chars").unwrap_or_default();
+    if matches.opt_present("exclude-similar") {
+        excluded.push_str(SIMILAR_CHARS);
+    }
+    alphabet.retain(|c| !excluded.contains(*c));
+
+    alphabet.sort();
+    alphabet.dedup();
+    alphabet
+}
+
+/// Custom alphabets must still make passwords as strong as the shortest --alnum password.
+fn min_entropy_bits() -> f64 {
+    4.0 * 62f64.log2()
+}
+
+/// The --length and alphabet options, `None` if they are wrong (after telling the user).
+fn password_spec_from_matches(matches: &getopts::Matches) -> Option<PasswordSpec> {
+    let mut password_len = 32;
+    if let Some(parsed_len) = length_from_matches(matches).ok()? {
+        // We want passwords to contain at least one uppercase letter, one lowercase
+        // letter and one digit. So we need at least 4 characters for each password.
+        // This checks makes sure we don't run into an infinite loop trying to generate
+        // a password of length <4 with 4 different kinds of characters (uppercase,
+        // lowercase, numeric, punctuation).
+        if parsed_len < 4 {
+            println_err!("Woops! The length of the password must be at least 4. This");
+            println_err!("allows us to make sure your password is secure.");
+            return None;
+        }
+        password_len = parsed_len;
+    }
+
+    let spec = PasswordSpec {
+        len: password_len,
+        alphabet: alphabet_from_matches(matches),
+    };
+    if spec.alphabet.is_empty() {
+        println_err!("Woops! There are no characters left to make a password with.");
+        return None;
+    }
+    if spec.entropy_bits() < min_entropy_bits() {
+        println_err!("Woops! With only {} different characters, a password of {} characters is \
+                      too easy to guess. Allow more characters, or make it longer with \
+                      --length.",
+                     spec.alphabet.len(),
+                     spec.len);
+        return None;
+    }
+    Some(spec)
+}
+
+/// The length of a PIN from --length. Short PINs are allowed, since some cards want them, but
+/// the user is warned.
+fn pin_length_from_matches(matches: &getopts::Matches) -> Option<usize> {
+    let len = length_from_matches(matches).ok()?.unwrap_or(DEFAULT_PIN_LENGTH);
+    if len == 0 {
+        println_err!("Woops! The length of the PIN must be at least 1.");
+        return None;
+    }
+    if len < 4 {
+        println_err!("WARNING: a {}-digit PIN is very weak, it can be guessed in at most {} tries.",
+                     len,
+                     10u32.pow(len as u32));
+        println_err!("WARNING: only use it if the card or the app locks after a few wrong tries.");
+    }
+    Some(len)
+}
+
+/// `None` if --words wasn't given, or was wrong (after telling the user).
+fn passphrase_spec_from_matches(matches: &getopts::Matches) -> Option<PassphraseSpec> {
+    let words = match matches.opt_str("words")?.parse::<usize>() {
+        Ok(words) if words > 0 => words,
+        _ => {
+            println_err!("Woops! The number of words must be a valid number, for instance 6.");
+            return None;
+        }
+    };
+    Some(PassphraseSpec {
+             words: words,
+             separator: matches.opt_str("separator").unwrap_or_else(|| "-".to_owned()),
+             capitalize: matches.opt_present("capitalize"),
+             add_digit: matches.opt_present("add-digit"),
+         })
+}
+
+/// The --min-entropy option, `Err` if it isn't a number (after telling the user).
+fn min_entropy_from_matches(matches: &getopts::Matches) -> Result<Option<f64>, ExitCode> {
+    match matches.opt_str("min-entropy") {
+        Some(bits) => {
+            match bits.parse::<f64>() {
+                Ok(bits) if bits >= 0.0 => Ok(Some(bits)),
+                _ => {
+                    println_err!("Woops! The minimum entropy must be a number of bits, for \
+                                  instance 80.");
+                    Err(ExitCode::Usage)
+                }
+            }
+        }
+        None => Ok(None),
     }
+}
+
+/// Tells how hard the new password is to guess, and refuses it if --min-entropy asks for more.
+fn check_entropy(matches: &getopts::Matches, bits: f64, details: String) -> Result<(), ExitCode> {
+    println_stderr!("~{:.0} bits of entropy ({})", bits, details);
 
+    if let Some(min_bits) = min_entropy_from_matches(matches)? {
+        if bits < min_bits {
+            println_err!("Woops, that's less than the {} bits asked for with --min-entropy, so I \
+                          didn't save it.",
+                         min_bits);
+            return Err(ExitCode::Error);
+        }
+    }
     Ok(())
 }
 
+/// How new passwords are made, following the generation options.
+pub enum Generator {
+    Pin(usize),
+    Passphrase(PassphraseSpec),
+    Pronounceable(PronounceableSpec),
+    Random(PasswordSpec),
+}
+
+impl Generator {
+    /// Reads the options, `None` if they are wrong (after telling the user).
+    fn from_matches(matches: &getopts::Matches) -> Option<Generator> {
+        if matches.opt_present("pin") {
+            return pin_length_from_matches(matches).map(Generator::Pin);
+        }
+
+        if matches.opt_present("words") {
+            return passphrase_spec_from_matches(matches).map(Generator::Passphrase);
+        }
+
+        let spec = password_spec_from_matches(matches)?;
+
+        if matches.opt_present("pronounceable") {
+            return Some(Generator::Pronounceable(PronounceableSpec {
+                len: spec.len,
+                add_digit: matches.opt_present("add-digit"),
+            }));
+        }
+
+        Some(Generator::Random(spec))
+    }
+
+    /// The entropy of the passwords, along with how it was computed.
+    fn entropy_bits(&self) -> (f64, String) {
+        match *self {
+            Generator::Pin(len) => {
+                (len as f64 * 10f64.log2(), format!("digits only, length {}", len))
+            }
+            Generator::Passphrase(ref spec) => {
+                (spec.entropy_bits(),
+                 format!("{} words from a list of {}", spec.words, wordlist_len()))
+            }
+            Generator::Pronounceable(ref spec) => {
+                (spec.entropy_bits(),
+                 format!("pronounceable, length {}, a random password would have ~{:.0}",
+                         spec.len,
+                         spec.random_entropy_bits()))
+            }
+            Generator::Random(ref spec) => {
+                (spec.entropy_bits(),
+                 format!("charset {}, length {}", spec.alphabet.len(), spec.len))
+            }
+        }
+    }
+
+    pub fn generate(&self) -> Result<SafeString, ExitCode> {
+        let (result, what) = match *self {
+            Generator::Pin(len) => (generate_pin(len), "PIN"),
+            Generator::Passphrase(ref spec) => (generate_passphrase(spec), "passphrase"),
+            Generator::Pronounceable(ref spec) => {
+                (generate_pronounceable_password(spec), "password")
+            }
+            Generator::Random(ref spec) => (generate_hard_password(spec), "password"),
+        };
+        result.map_err(|io_err| {
+            println_err!("Woops, I could not generate the {} (reason: {:?}).", what, io_err);
+            ExitCode::Error
+        })
+    }
+}
+
+/// Reads the options for a password, a passphrase following the --words options, a
+/// pronounceable password or a PIN, and tells how much entropy the result will have.
+pub fn generator_from_matches(matches: &getopts::Matches) -> Result<Generator, ExitCode> {
+    let generator = Generator::from_matches(matches).ok_or(ExitCode::Error)?;
+    let (bits, details) = generator.entropy_bits();
+    check_entropy(matches, bits, details)?;
+    Ok(generator)
+}
+
+/// Generates a password following the --alnum and --length options, a passphrase following the
+/// --words options, a pronounceable password or a PIN.
+pub fn generate_password_from_matches(matches: &getopts::Matches) -> Result<SafeString, ExitCode> {
+    generator_from_matches(matches)?.generate()
+}
+
+/// Generates a password with the same length and kinds of characters as another password.
+pub fn generate_similar_password(matches: &getopts::Matches,
+                                 store: &password::v2::PasswordStore,
+                                 query: &str)
+                                 -> Result<SafeString, ExitCode> {
+    println_stderr!("");
+    let model = list::search_and_choose_password(
+        store, query, list::WITH_NUMBERS,
+        "Which password should the new one look like?",
+    )?;
+
+    let shape = PasswordShape::of(&model.password);
+    if shape.len == 0 {
+        println_err!("Woops, the password for {} is empty, I can't copy its shape.", model.name);
+        return Err(ExitCode::Error);
+    }
+    println_stderr!("Using the shape of {}: {}.", model.name, shape.describe());
+
+    let password = generate_password_with_shape(&shape).map_err(|io_err| {
+        println_err!("Woops, I could not generate the password (reason: {:?}).", io_err);
+        ExitCode::Error
+    })?;
+    check_entropy(matches,
+                  shape.entropy_bits(),
+                  format!("charset {}, length {}", shape.alphabet_len(), shape.len))?;
+    Ok(password)
+}
+
+/// A new password following the generation options, including --similar-to.
+pub fn new_password_from_matches(matches: &getopts::Matches,
+                                 store: &password::v2::PasswordStore)
+                                 -> Result<SafeString, ExitCode> {
+    match matches.opt_str("similar-to") {
+        Some(query) => generate_similar_password(matches, store, query.as_str()),
+        None => generate_password_from_matches(matches),
+    }
+}
+
 pub fn callback_exec(matches: &getopts::Matches,
                      store: &mut password::v2::PasswordStore)
-                     -> Result<(), i32> {
+                     -> Result<(), ExitCode> {
     check_args(matches)?;
 
     let app_name = matches.free[1].clone();
     let username = matches.free[2].clone();
+    let tags = list::tags_from_matches(matches)?;
 
     if store.has_password(app_name.deref()) {
+        output_format::set_app(app_name.deref());
         println_err!("Woops, there is already an app with that name.");
-        return Err(1);
+        return Err(ExitCode::AlreadyExists);
     }
 
-    let password_spec = PasswordSpec::from_matches(matches);
+    let password_as_string = new_password_from_matches(matches, store)?;
 
-    let password_as_string = match password_spec {
-        None => {
-            return Err(1);
-        }
-        Some(spec) => {
-            match generate_hard_password(spec.alnum, spec.len) {
-                Ok(password_as_string) => password_as_string,
-                Err(io_err) => {
-                    println_stderr!("Woops, I could not generate the password (reason: {:?}).",
-                                    io_err);
-                    return Err(1);
-                }
-            }
-        }
-    };
+    let mut password = password::v2::Password::new(app_name, username, password_as_string);
+    password.tags = tags;
+    save_new_password(matches, store, password)
+}
 
-    // Read the master password and try to save the new password.
-    let password_as_string_clipboard = password_as_string.clone();
-    let password = password::v2::Password::new(app_name.clone(), username, password_as_string);
+#[cfg(test)]
+mod test {
+    use super::{alphabet_from_matches, password_spec_from_matches};
+    use generate::generate_hard_password;
+    use getopts;
 
-    match store.add_password(password) {
-        Ok(_) => {
-            if matches.opt_present("show") {
-                println_ok!("Alright! Here is your password: {}",
-                            password_as_string_clipboard.deref());
-                return Ok(());
-            }
+    fn matches(args: &[&str]) -> getopts::Matches {
+        let mut opts = getopts::Options::new();
+        opts.optflag("a", "alnum", "");
+        opts.optopt("l", "length", "", "");
+        opts.optopt("", "charset", "", "");
+        opts.optopt("", "exclude-chars", "", "");
+        opts.optflag("", "exclude-similar", "");
+        opts.parse(args).unwrap()
+    }
 
-            if copy_to_clipboard(&password_as_string_clipboard).is_err() {
-                println_ok!("Hmm, I tried to copy your new password to your clipboard, but \
-                             something went wrong. Don't worry, it's saved, and you can see it \
-                             with `rooster get {} --show`",
-                            app_name);
-            } else {
-                println_ok!("Alright! I've saved your new password. You can paste it anywhere \
-                             with {}.",
-                            paste_keys());
-            }
+    #[test]
+    fn alphabets_follow_the_options() {
+        assert_eq!(alphabet_from_matches(&matches(&[])).len(), 94);
+        assert_eq!(alphabet_from_matches(&matches(&["--alnum"])).len(), 62);
+        assert_eq!(alphabet_from_matches(&matches(&["--charset", "cabbage"])),
+                   vec!['a', 'b', 'c', 'e', 'g']);
+        assert_eq!(alphabet_from_matches(&matches(&["--exclude-chars", "\"'\\&"])).len(), 90);
+        assert_eq!(alphabet_from_matches(&matches(&["--alnum", "--exclude-similar"])).len(), 57);
+    }
 
-            Ok(())
+    #[test]
+    fn small_alphabets_need_long_passwords() {
+        assert!(password_spec_from_matches(&matches(&["--charset", "ab", "-l", "8"])).is_none());
+        let spec = password_spec_from_matches(&matches(&["--charset", "ab", "-l", "24"])).unwrap();
+        assert_eq!(spec.entropy_bits(), 24.0);
+        assert!(password_spec_from_matches(&matches(&["--charset", "ab",
+                                                     "--exclude-chars", "ab"]))
+            .is_none());
+    }
+
+    #[test]
+    fn excluded_characters_never_appear() {
+        let args = ["--exclude-chars", "\"'\\&abc", "--exclude-similar", "-l", "64"];
+        let spec = password_spec_from_matches(&matches(&args)).unwrap();
+        for _ in 0..200 {
+            let password = generate_hard_password(&spec).unwrap();
+            for c in "\"'\\&abc0O1lI|".chars() {
+                assert!(!password.expose().contains(c), "{}", password.expose());
+            }
         }
-        Err(err) => {
-            println_err!("\nI couldn't add this password (reason: {:?}).", err);
-            Err(1)
+
+        let spec = password_spec_from_matches(&matches(&["--charset", "xyz789", "-l", "16"]))
+            .unwrap();
+        for _ in 0..200 {
+            let password = generate_hard_password(&spec).unwrap();
+            assert!(password.expose().chars().all(|c| "xyz789".contains(c)));
         }
     }
 }
@@ -12,56 +12,491 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use exit_code::ExitCode;
 use clip;
+use super::list::JsonEntry;
+use super::note;
+use ffi;
 use getopts;
 use password;
 use list;
+use output_format;
+use safe_string::SafeString;
+use serde_json;
+use timestamp;
+use help::Help;
 use std::io::Write;
+use std::thread;
+use std::time::Duration;
 
-pub fn callback_help() {
-    println!("Usage:");
-    println!("    rooster get -h");
-    println!("    rooster get <query>");
-    println!("");
-    println!("Examples:");
-    println!("    rooster get youtube");
-    println!("    rooster get ytb");
+pub fn callback_help() -> Help {
+    Help::new()
+        .usage(&["rooster get -h",
+                 "rooster get <query>...",
+                 "rooster get <query> --notes",
+                 "rooster get <query> --username",
+                 "rooster get <query> --field <field>",
+                 "rooster get <query> --stdout [--no-newline]",
+                 "rooster get <query> --format json",
+                 "rooster get <query> --info [--json]",
+                 "rooster get <query> --type [--type-delay <seconds>] [--press-enter]",
+                 "rooster get <query> [--tag <tag>]...",
+                 "rooster get <regex> --regex"])
+        .text(&["The query is matched against app names, and then against usernames, so that a",
+                "password can be found from the email address it goes with. With several words,",
+                "the password has to match all of them, like `rooster get aws prod`."])
+        .text(&["With --exact, the query has to be the name of the password, without regard to",
+                "casing, and nothing is asked if it isn't (this also works for delete, change,",
+                "rename and transfer).",
+                "With --regex, the query is a regular expression, matched against names and",
+                "usernames without regard to casing, and you choose between the passwords it",
+                "matches, for instance `rooster get --regex '^(work|home) mail$'`."])
+        .text(&["With -u or --username, the username is copied (or shown with --show) instead",
+                "of the password. More generally, --field copies or shows another field:",
+                "username, password, url or notes."])
+        .text(&["With --stdout, only the password (or the field) and a newline are printed on",
+                "stdout, for use in scripts. --no-newline leaves out the newline. The query must",
+                "then match a single password, so that nothing is asked besides the master \
+                 password.",
+                "--format json does the same, but prints a JSON object with the name, the",
+                "username, the field and its value."])
+        .text(&["With --info, the name, the username, the creation and update dates, and the URL,",
+                "tags and whether there is a note are printed on stdout, but never the password.",
+                "With --json too, they are printed as a JSON object, like `rooster list --json`."])
+        .text(&["With --type, the password (or the field) is typed into the focused window with",
+                &format!("xdotool, for apps that can't be pasted into. Typing starts after {} \
+                          seconds, or",
+                         clip::DEFAULT_TYPE_DELAY_SECS),
+                "--type-delay, to leave time to switch windows. --press-enter presses Enter \
+                 after."])
+        .examples(&["rooster get youtube",
+                    "rooster get ytb",
+                    "rooster get billing@company.com",
+                    "rooster get aws prod",
+                    "rooster get youtube --show --notes",
+                    "rooster get youtube -u",
+                    "rooster get youtube --field url --show",
+                    "rooster get youtube --info",
+                    "rooster get --tag work slack",
+                    "curl -u \"me:$(rooster get --stdout api)\" https://example.com"])
 }
 
-pub fn check_args(matches: &getopts::Matches) -> Result<(), i32> {
+/// The fields that can be picked with --field.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Field {
+    Username,
+    Password,
+    Url,
+    Notes,
+}
+
+const FIELDS: &'static [Field] = &[Field::Username, Field::Password, Field::Url, Field::Notes];
+
+impl Field {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Field::Username => "username",
+            Field::Password => "password",
+            Field::Url => "url",
+            Field::Notes => "notes",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Field> {
+        FIELDS.iter().cloned().find(|field| field.name() == name)
+    }
+
+    /// The value of the field, or `None` if it isn't set for this password.
+    pub fn value(&self, password: &password::v2::Password) -> Option<SafeString> {
+        match *self {
+            Field::Username => Some(SafeString::new(password.username.clone())),
+            Field::Password => Some(password.password.clone()),
+            Field::Url => password.url.clone().map(SafeString::new),
+            Field::Notes => password.notes.clone(),
+        }
+    }
+}
+
+fn field_from_matches(matches: &getopts::Matches) -> Result<Field, ExitCode> {
+    let field = match matches.opt_str("field") {
+        Some(name) => {
+            Field::from_name(&name).ok_or_else(|| {
+                let names: Vec<&str> = FIELDS.iter().map(|field| field.name()).collect();
+                println_err!("Woops, there is no field named \"{}\". It can be one of: {}.",
+                             name,
+                             names.join(", "));
+                ExitCode::Usage
+            })?
+        }
+        None if matches.opt_present("username") => Field::Username,
+        None => Field::Password,
+    };
+
+    if matches.opt_present("username") && field != Field::Username {
+        println_err!("Woops, --username and --field {} don't go together.", field.name());
+        return Err(ExitCode::Usage);
+    }
+
+    Ok(field)
+}
+
+pub fn check_args(matches: &getopts::Matches) -> Result<(), ExitCode> {
     if matches.free.len() < 2 {
         println_err!("Woops, seems like the app name is missing here. For help, try:");
         println_err!("    rooster get -h");
-        return Err(1);
+        return Err(ExitCode::Usage);
+    }
+
+    field_from_matches(matches)?;
+    type_delay_from_matches(matches)?;
+
+    if matches.opt_present("info") {
+        for other in &["show", "stdout", "no-newline", "type", "field", "username", "notes"] {
+            if matches.opt_present(other) {
+                println_err!("Woops, --info and --{} can't be used together.", other);
+                return Err(ExitCode::Usage);
+            }
+        }
+    }
+
+    if matches.opt_present("type") {
+        for other in &["show", "stdout", "no-newline"] {
+            if matches.opt_present(other) {
+                println_err!("Woops, --type and --{} can't be used together.", other);
+                return Err(ExitCode::Usage);
+            }
+        }
+        if output_format::json_requested(matches) {
+            println_err!("Woops, --type and --format json can't be used together.");
+            return Err(ExitCode::Usage);
+        }
+    } else if matches.opt_present("press-enter") || matches.opt_present("type-delay") {
+        println_err!("Woops, --press-enter and --type-delay only go with --type.");
+        return Err(ExitCode::Usage);
     }
 
     Ok(())
 }
 
+/// How many seconds to wait before typing, from --type-delay.
+fn type_delay_from_matches(matches: &getopts::Matches) -> Result<u64, ExitCode> {
+    match matches.opt_str("type-delay") {
+        Some(seconds) => {
+            seconds.trim().parse::<u64>().map_err(|_| {
+                println_err!("Woops, --type-delay must be a number of seconds, like 5, not \
+                              \"{}\".",
+                             seconds);
+                ExitCode::Usage
+            })
+        }
+        None => Ok(clip::DEFAULT_TYPE_DELAY_SECS),
+    }
+}
+
+/// Waits for the user to switch to the window to type into, then types the value.
+fn type_value(matches: &getopts::Matches,
+              password: &password::v2::Password,
+              field: Field,
+              value: &SafeString)
+              -> Result<(), ExitCode> {
+    let delay = type_delay_from_matches(matches)?;
+    println_stderr_always!("Switch to the window to type into, typing the {} of {} in {} \
+                            seconds...",
+                           field.name(),
+                           password.name,
+                           delay);
+    thread::sleep(Duration::from_secs(delay));
+
+    clip::type_with_xdotool(value, matches.opt_present("press-enter")).map_err(|err| {
+        println_err!("Woops, {}.", err);
+        ExitCode::Error
+    })?;
+    println_ok!("Done! I've typed the {} of {}.", field.name(), password.name);
+    Ok(())
+}
+
+/// What `rooster get --info` prints, with dates in `zone` and relative to `now`.
+fn format_info(password: &password::v2::Password,
+               now: ffi::time_t,
+               zone: timestamp::Zone)
+               -> Vec<String> {
+    let created = timestamp::to_date_and_age(password.created_at, now, zone);
+    let updated = timestamp::to_date_and_age(password.updated_at, now, zone);
+    let mut lines = vec![format!("Name:     {}", password.name),
+                         format!("Username: {}", password.username),
+                         format!("Created:  {}", created),
+                         format!("Updated:  {}", updated)];
+    if let Some(ref url) = password.url {
+        lines.push(format!("URL:      {}", url));
+    }
+    if !password.tags.is_empty() {
+        lines.push(format!("Tags:     {}", password.tags.join(", ")));
+    }
+    if password.notes.is_some() {
+        lines.push("Notes:    yes".to_owned());
+    }
+    lines
+}
+
+/// Shows what there is to know about a password, but none of its secrets.
+fn print_info(password: &password::v2::Password, json: bool) -> Result<(), ExitCode> {
+    if !json {
+        for line in format_info(password, ffi::time(), timestamp::Zone::Local) {
+            println_stdout!("{}", line);
+        }
+        return Ok(());
+    }
+
+    let mut object = match serde_json::to_value(JsonEntry::of(password)) {
+        Ok(serde_json::Value::Object(object)) => object,
+        _ => {
+            println_err!("I couldn't write the details of {}.", password.name);
+            return Err(ExitCode::Error);
+        }
+    };
+    object.insert("has_notes".to_owned(), password.notes.is_some().into());
+    print_stdout!("{}\n", serde_json::Value::Object(object));
+    Ok(())
+}
+
+/// What `rooster get --format json` prints.
+fn print_json(password: &password::v2::Password,
+              field: Field,
+              value: &SafeString)
+              -> Result<(), ExitCode> {
+    let mut object = serde_json::Map::new();
+    object.insert("name".to_owned(), password.name.as_str().into());
+    object.insert("username".to_owned(), password.username.as_str().into());
+    object.insert("field".to_owned(), field.name().into());
+    object.insert("value".to_owned(), value.expose().into());
+    print_stdout!("{}\n", serde_json::Value::Object(object));
+    Ok(())
+}
+
 pub fn callback_exec(
     matches: &getopts::Matches,
     store: &mut password::v2::PasswordStore,
-) -> Result<(), i32> {
+) -> Result<(), ExitCode> {
     check_args(matches)?;
 
     let show = matches.opt_present("show");
+    let field = field_from_matches(matches)?;
+    let json = output_format::json_requested(matches);
+    let to_stdout = matches.opt_present("stdout") || matches.opt_present("no-newline") || json;
 
-    let query = &matches.free[1];
+    // Checked by `check_args`.
+    let query = list::query_from_matches(matches).unwrap();
+    let tags = list::tags_from_matches(matches)?;
 
-    let prompt = format!(
-        "Which password would you like {}? ",
-        if show {
-            "to see"
+    let password = if to_stdout {
+        list::resolve_unique_password(store, &query, |p| list::has_all_tags(p, &tags))?
+    } else {
+        let prompt = match (field, show) {
+            (_, _) if matches.opt_present("info") => {
+                "Which password would you like to see the details of? ".to_owned()
+            }
+            (_, _) if matches.opt_present("type") => {
+                format!("Which password would you like to type the {} of? ", field.name())
+            }
+            (Field::Password, true) => "Which password would you like to see? ".to_owned(),
+            (Field::Password, false) => {
+                "Which password would you like to copy to your clipboard? ".to_owned()
+            }
+            (_, true) => format!("Which password would you like to see the {} of? ", field.name()),
+            (_, false) => {
+                format!("Which password would you like to copy the {} of? ", field.name())
+            }
+        };
+        println_stderr!("");
+        list::search_and_choose_filtered_password(
+            store, &query, |p| list::has_all_tags(p, &tags), list::WITH_NUMBERS, &prompt,
+        )?
+    };
+
+    if matches.opt_present("info") {
+        return print_info(password, json);
+    }
+
+    // Copying an empty string would look like it worked.
+    let value = field.value(password).ok_or_else(|| {
+        println_err!("Woops, {} has no {}.", password.name, field.name());
+        ExitCode::Error
+    })?;
+
+    if json {
+        return print_json(password, field, &value);
+    }
+    if to_stdout {
+        if matches.opt_present("no-newline") {
+            print_stdout!("{}", value.expose());
         } else {
-            "to copy to your clipboard"
-        },
-    );
-    println_stderr!("");
-    let password = list::search_and_choose_password(
-        store, query, list::WITH_NUMBERS, &prompt,
-    ).ok_or(1)?;
+            print_stdout!("{}\n", value.expose());
+        }
+        return Ok(());
+    }
+
+    if matches.opt_present("type") {
+        return type_value(matches, password, field, &value);
+    }
+
+    match field {
+        Field::Password => clip::confirm_password_retrieved(show, password),
+        _ => clip::confirm_field_retrieved(show, password, field.name(), &value),
+    }
 
-    clip::confirm_password_retrieved(show, &password);
+    if let Some(ref notes) = password.notes {
+        if matches.opt_present("notes") {
+            println_stderr!("");
+            println_requested!("Here is the note for {}:", password.name);
+            note::print_notes(notes);
+        } else if field != Field::Notes {
+            println_stderr!("This password has a note. See it with `rooster get '{}' --notes`.",
+                            password.name);
+        }
+    }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::{callback_exec, format_info, Field, FIELDS};
+    use commands::harness::{run, store};
+    use exit_code::ExitCode;
+    use password::v2::Password;
+    use safe_string::SafeString;
+    use timestamp::Zone;
+
+    #[test]
+    fn unset_fields_have_no_value() {
+        let mut password = Password::new("YouTube".to_owned(),
+                                         "me".to_owned(),
+                                         SafeString::new("secret".to_owned()));
+        assert_eq!(Field::Url.value(&password), None);
+        assert_eq!(Field::Notes.value(&password), None);
+
+        password.url = Some("https://youtube.com".to_owned());
+        assert_eq!(Field::Url.value(&password).unwrap().expose(), "https://youtube.com");
+        assert_eq!(Field::Username.value(&password).unwrap().expose(), "me");
+        assert_eq!(Field::Password.value(&password).unwrap().expose(), "secret");
+    }
+
+    #[test]
+    fn fields_are_found_by_name() {
+        for field in FIELDS {
+            assert_eq!(Field::from_name(field.name()), Some(*field));
+        }
+        assert_eq!(Field::from_name("email"), None);
+    }
+
+    #[test]
+    fn passwords_are_shown() {
+        let mut store = store(&[("YouTube", "me@example.com", "hunter2")]);
+        let (result, output) = run(callback_exec, &["get", "youtube", "--show"], &[], &mut store);
+        assert_eq!(result, Ok(()));
+        assert!(output.stderr.contains("Here is your password for YouTube: hunter2"));
+        assert_eq!(output.stdout, "");
+    }
+
+    #[test]
+    fn only_the_value_goes_to_stdout() {
+        let mut store = store(&[("YouTube", "me@example.com", "hunter2")]);
+        let (result, output) = run(callback_exec, &["get", "youtube", "--stdout"], &[], &mut store);
+        assert_eq!(result, Ok(()));
+        assert_eq!(output.stdout, "hunter2\n");
+
+        let (_, output) = run(callback_exec,
+                              &["get", "youtube", "--stdout", "-u"],
+                              &[],
+                              &mut store);
+        assert_eq!(output.stdout, "me@example.com\n");
+    }
+
+    #[test]
+    fn the_chosen_password_is_shown() {
+        let mut store = store(&[("Slack (work)", "me@work.com", "first"),
+                                ("Slack (home)", "me@example.com", "second")]);
+        let (result, output) = run(callback_exec, &["get", "slack", "--show"], &["2"], &mut store);
+        assert_eq!(result, Ok(()));
+        assert!(output.stderr.contains("Type in number from 1 to 2"));
+        assert!(output.stderr.contains("Here is your password for Slack (work): first"));
+    }
+
+    #[test]
+    fn unknown_passwords_are_not_found() {
+        let mut store = store(&[("YouTube", "me@example.com", "hunter2")]);
+        let (result, output) = run(callback_exec, &["get", "github", "--show"], &[], &mut store);
+        assert_eq!(result, Err(ExitCode::NotFound));
+        assert!(!output.stderr.contains("hunter2"));
+    }
+
+    #[test]
+    fn the_query_is_required() {
+        let mut store = store(&[]);
+        let (result, _) = run(callback_exec, &["get"], &[], &mut store);
+        assert_eq!(result, Err(ExitCode::Usage));
+    }
+
+    #[test]
+    fn info_renderings() {
+        let now = 1614859200;
+        let mut password = Password {
+            created_at: now - 400 * 86400,
+            updated_at: now - 2 * 86400,
+            ..Password::new("YouTube".to_owned(),
+                            "me@example.com".to_owned(),
+                            SafeString::new("hunter2".to_owned()))
+        };
+        assert_eq!(format_info(&password, now, Zone::Offset(0)),
+                   vec!["Name:     YouTube",
+                        "Username: me@example.com",
+                        "Created:  2020-01-29 (1 year ago)",
+                        "Updated:  2021-03-02 (2 days ago)"]);
+
+        password.url = Some("https://youtube.com".to_owned());
+        password.tags = vec!["fun".to_owned(), "video".to_owned()];
+        password.notes = Some(SafeString::new("recovery codes".to_owned()));
+        assert_eq!(format_info(&password, now, Zone::Offset(0))[4..].to_vec(),
+                   vec!["URL:      https://youtube.com", "Tags:     fun, video", "Notes:    yes"]);
+    }
+
+    #[test]
+    fn info_never_shows_secrets() {
+        let mut store = store(&[]);
+        store.add_password(Password {
+                notes: Some(SafeString::new("recovery codes".to_owned())),
+                ..Password::new("YouTube".to_owned(),
+                                "me@example.com".to_owned(),
+                                SafeString::new("hunter2".to_owned()))
+            })
+            .unwrap();
+
+        let (result, output) = run(callback_exec, &["get", "youtube", "--info"], &[], &mut store);
+        assert_eq!(result, Ok(()));
+        assert!(output.stdout.contains("Username: me@example.com"));
+        assert!(output.stdout.contains("Notes:    yes"));
+
+        let (result, output) = run(callback_exec,
+                                   &["get", "youtube", "--info", "--json"],
+                                   &[],
+                                   &mut store);
+        assert_eq!(result, Ok(()));
+        assert!(output.stdout.contains("\"username\":\"me@example.com\""));
+        assert!(output.stdout.contains("\"has_notes\":true"));
+
+        for output in [&output.stdout, &output.stderr].iter() {
+            assert!(!output.contains("hunter2"));
+            assert!(!output.contains("recovery codes"));
+        }
+    }
+
+    #[test]
+    fn info_only_goes_with_a_query() {
+        let mut store = store(&[("YouTube", "me@example.com", "hunter2")]);
+        let (result, _) = run(callback_exec,
+                              &["get", "youtube", "--info", "--show"],
+                              &[],
+                              &mut store);
+        assert_eq!(result, Err(ExitCode::Usage));
+    }
+}
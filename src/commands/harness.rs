@@ -0,0 +1,81 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! Runs commands in tests, the way `main` would, but with a store that lives in memory, answers
+//! that are given in advance and output that is kept to be checked.
+
+use answer;
+use options;
+use console::{self, Captured};
+use exit_code::ExitCode;
+use getopts;
+use password::v2::{Kdf, Password, PasswordStore, SecondFactors};
+use password::v3::Argon2Params;
+use safe_string::SafeString;
+
+/// The cheapest parameters Argon2id accepts, so that making a store takes no time.
+const CHEAP_KDF: Kdf = Kdf::Argon2id(Argon2Params {
+    memory_kib: 8,
+    iterations: 1,
+    parallelism: 1,
+});
+
+/// Parses a command line, without the name of the binary: `&["get", "youtube", "--show"]`.
+pub fn matches(args: &[&str]) -> getopts::Matches {
+    options::build().parse(args).expect("the options should be valid")
+}
+
+/// A store holding a password for each `(name, username, password)`.
+pub fn store(fixtures: &[(&str, &str, &str)]) -> PasswordStore {
+    let master = SafeString::new("correct horse battery staple".to_owned());
+    let mut store = PasswordStore::new_with_kdf(master, &SecondFactors::default(), CHEAP_KDF)
+        .unwrap();
+    for &(name, username, password) in fixtures.iter() {
+        store.add_password(Password::new(name.to_owned(),
+                                         username.to_owned(),
+                                         SafeString::new(password.to_owned())))
+            .unwrap();
+    }
+    store
+}
+
+/// Runs the command with these arguments on the store, answering its questions with `answers`.
+pub fn run<F>(callback_exec: F,
+              args: &[&str],
+              answers: &[&str],
+              store: &mut PasswordStore)
+              -> (Result<(), ExitCode>, Captured)
+    where F: FnOnce(&getopts::Matches, &mut PasswordStore) -> Result<(), ExitCode>
+{
+    let matches = matches(args);
+    answer::script(Some(answers));
+    let outcome = console::capture(|| callback_exec(&matches, store));
+    answer::script(None);
+    outcome
+}
+
+/// Same as `run`, for the commands that don't open the password file.
+pub fn run_without_store<F>(callback_exec: F,
+                            args: &[&str],
+                            answers: &[&str])
+                            -> (Result<(), ExitCode>, Captured)
+    where F: FnOnce(&getopts::Matches) -> Result<(), ExitCode>
+{
+    let matches = matches(args);
+    answer::script(Some(answers));
+    let outcome = console::capture(|| callback_exec(&matches));
+    answer::script(None);
+    outcome
+}
@@ -0,0 +1,163 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use exit_code::ExitCode;
+use getopts;
+use password;
+use clip::{copy_to_clipboard, explain_copy, paste_keys};
+use list;
+use timestamp;
+use help::Help;
+use std::io::Write;
+
+pub fn callback_help() -> Help {
+    Help::new()
+        .usage(&["rooster history -h",
+                 "rooster history <query>",
+                 "rooster history <query> --index <n> [--show]",
+                 "rooster history <query> --purge"])
+        .text(&["Rooster keeps the previous values of each password, most recent first, in case a",
+                "new password didn't make it to the app. Without options, lists when each value",
+                "was replaced. --index copies the value with that number to the clipboard, or",
+                "shows it with --show. --purge forgets all the previous values of the password."])
+        .examples(&["rooster history youtube",
+                    "rooster history youtube --index 1",
+                    "rooster history youtube --index 2 --show",
+                    "rooster history youtube --purge"])
+}
+
+fn index_from_matches(matches: &getopts::Matches) -> Result<Option<usize>, ExitCode> {
+    match matches.opt_str("index") {
+        Some(index) => {
+            match index.parse::<usize>() {
+                Ok(index) if index > 0 => Ok(Some(index)),
+                _ => {
+                    println_err!("Woops, the index must be a number from 1, like 1 or 2.");
+                    Err(ExitCode::Usage)
+                }
+            }
+        }
+        None => Ok(None),
+    }
+}
+
+pub fn check_args(matches: &getopts::Matches) -> Result<(), ExitCode> {
+    if matches.free.len() < 2 {
+        println_err!("Woops, seems like the app name is missing here. For help, try:");
+        println_err!("    rooster history -h");
+        return Err(ExitCode::Usage);
+    }
+
+    if matches.opt_present("purge") &&
+       (matches.opt_present("index") || matches.opt_present("show")) {
+        println_err!("Woops, --purge can't be used with --index or --show. For help, try:");
+        println_err!("    rooster history -h");
+        return Err(ExitCode::Usage);
+    }
+
+    if matches.opt_present("show") && !matches.opt_present("index") {
+        println_err!("Woops, which previous value should I show? Use --index. For help, try:");
+        println_err!("    rooster history -h");
+        return Err(ExitCode::Usage);
+    }
+
+    index_from_matches(matches)?;
+
+    Ok(())
+}
+
+pub fn callback_exec(matches: &getopts::Matches,
+                     store: &mut password::v2::PasswordStore)
+                     -> Result<(), ExitCode> {
+    check_args(matches)?;
+
+    let query = &matches.free[1];
+
+    println_stderr!("");
+    let password = list::search_and_choose_password(
+        store, query, list::WITH_NUMBERS,
+        "Which password's history are you interested in?",
+    )?.clone();
+
+    if matches.opt_present("purge") {
+        store.change_password(&password.name,
+                              &|old_password: password::v2::Password| {
+            password::v2::Password {
+                history: Vec::new(),
+                ..old_password
+            }
+        }).map_err(|err| {
+            println_err!("Woops, I couldn't purge the history (reason: {:?}).", err);
+            ExitCode::from_password_error(&err)
+        })?;
+        println_ok!("Done! I've forgotten the {} previous value(s) of {}.",
+                    password.history.len(),
+                    password.name);
+        return Ok(());
+    }
+
+    if password.history.is_empty() {
+        println_stderr!("{} has no previous values.", password.name);
+        return Ok(());
+    }
+
+    let index = match index_from_matches(matches)? {
+        Some(index) => index,
+        None => {
+            println_title!("Previous values of {}", password.name);
+            for (i, past) in password.history.iter().enumerate() {
+                println_stdout!("{} replaced on {}",
+                                i + 1,
+                                timestamp::to_local_string(past.changed_at));
+            }
+            return Ok(());
+        }
+    };
+
+    let past = match password.history.get(index - 1) {
+        Some(past) => past,
+        None => {
+            println_err!("Woops, {} only has {} previous value(s).",
+                         password.name,
+                         password.history.len());
+            return Err(ExitCode::Error);
+        }
+    };
+
+    if matches.opt_present("show") {
+        println_requested!("Alright! Here is the previous value #{} of {}: {}",
+                           index,
+                           password.name,
+                           past.password.expose());
+    } else {
+        let show_command = format!("rooster history '{}' --index {} --show", password.name, index);
+        match copy_to_clipboard(&past.password) {
+            Err(_) => {
+                println_err!("Hmm, I tried to copy the previous value to your clipboard, but \
+                              something went wrong. You can see it with `{}`",
+                             show_command);
+                return Err(ExitCode::Error);
+            }
+            Ok(copied) => {
+                println_ok!("Alright! You can paste the previous value #{} of {} with {}.",
+                            index,
+                            password.name,
+                            paste_keys(copied));
+                explain_copy(copied, &show_command);
+            }
+        }
+    }
+
+    Ok(())
+}
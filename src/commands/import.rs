@@ -0,0 +1,678 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use exit_code::ExitCode;
+use getopts;
+use password;
+use password::v2::Password;
+use csv;
+use json_stream::ArrayElements;
+use safe_string::SafeString;
+use serde_json;
+use help::Help;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{Read, Write};
+
+pub fn callback_help() -> Help {
+    Help::new()
+        .usage(&["rooster import -h",
+                 "rooster import <file.csv> [--map-fields <mapping>] [--dry-run]",
+                 "rooster import <file.json> [--dry-run]"])
+        .text(&["Imports passwords from a CSV file. By default, the first row must name the",
+                "columns: name, username, password and optionally url and notes."])
+        .text(&["Files ending with .json are read as the output of `rooster export`. They are read",
+                "one password at a time, so large files don't need much memory. If any password",
+                "in the file is invalid, nothing is imported."])
+        .text(&["For other layouts, --map-fields tells which column holds which field, as a comma",
+                "separated list of:"])
+        .then_table(19,
+                    &[("<field>=<column>",
+                       "<field> is one of name, username, password, url, notes,\n\
+                        <column> is a column number, starting from 1, or the\n\
+                        name of a column when `header` is given"),
+                      ("header",
+                       "the first row names the columns, fields that aren't\n\
+                        mapped are looked up by their own name"),
+                      ("skip-header", "the first row is ignored")])
+        .text(&["Passwords whose name is already taken are skipped. With --dry-run, nothing is",
+                "imported, Rooster only shows what it would do."])
+        .examples(&["rooster import passwords.csv",
+                    "rooster import in.csv --map-fields 'name=2,username=5,password=6,skip-header'",
+                    "rooster import in.csv --map-fields 'header,name=Title,username=Login'",
+                    "rooster import in.csv --dry-run",
+                    "rooster import export.json"])
+}
+
+/// How often progress is shown while importing JSON.
+const PROGRESS_EVERY: usize = 5000;
+
+fn is_json_path(path: &str) -> bool {
+    path.to_lowercase().ends_with(".json")
+}
+
+/// 19874 -> "19,874"
+fn with_thousands_separators(n: usize) -> String {
+    let digits = n.to_string();
+    let mut s = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            s.push(',');
+        }
+        s.push(c);
+    }
+    s
+}
+
+fn open_file(path: &str) -> Result<File, ExitCode> {
+    File::open(path).map_err(|err| {
+        println_err!("Woops, I couldn't read {} (reason: {:?}).", path, err);
+        ExitCode::Io
+    })
+}
+
+/// Imports the output of `rooster export`. The file is read twice, first to count and check
+/// the entries, then to import them, and never held in memory as a whole. Returning an error
+/// half way is fine, since the store is only saved when the command succeeds.
+fn import_json(store: &mut password::v2::PasswordStore,
+               path: &str,
+               dry_run: bool)
+               -> Result<(), ExitCode> {
+    let mut total = 0;
+    for element in ArrayElements::new(open_file(path)?) {
+        element.map_err(|err| {
+            println_err!("Woops, I can't import {}: {}.", path, err);
+            ExitCode::Error
+        })?;
+        total += 1;
+    }
+
+    let mut imported: Vec<String> = Vec::new();
+    let mut skipped: Vec<String> = Vec::new();
+    let mut names: HashSet<String> = HashSet::new();
+    for (i, element) in ArrayElements::new(open_file(path)?).enumerate() {
+        if i > 0 && i % PROGRESS_EVERY == 0 {
+            println_stderr!("{} {}/{}",
+                            if dry_run { "Checked" } else { "Imported" },
+                            with_thousands_separators(i),
+                            with_thousands_separators(total));
+        }
+
+        let element = element.map_err(|err| {
+            println_err!("Woops, I can't import {}: {}.", path, err);
+            ExitCode::Error
+        })?;
+        let password: Password = serde_json::from_str(element.expose()).map_err(|err| {
+            println_err!("Woops, entry {} of {} is not a valid password ({}), so I didn't import \
+                          anything.",
+                         i + 1,
+                         path,
+                         err);
+            ExitCode::Error
+        })?;
+        if password.name.is_empty() {
+            println_err!("Woops, entry {} of {} has no name, so I didn't import anything.",
+                         i + 1,
+                         path);
+            return Err(ExitCode::Error);
+        }
+        if password.password.is_empty() {
+            println_err!("Woops, entry {} of {} has no password, so I didn't import anything.",
+                         i + 1,
+                         path);
+            return Err(ExitCode::Error);
+        }
+
+        // Names imported by a dry run aren't in the store, so they're looked up separately.
+        let lowercase_name = password.name.to_lowercase();
+        if store.has_password(password.name.as_str()) || names.contains(&lowercase_name) {
+            skipped.push(format!("{} (entry {})", password.name, i + 1));
+            continue;
+        }
+
+        imported.push(password.name.clone());
+        if dry_run {
+            names.insert(lowercase_name);
+        } else {
+            store.add_password(password).map_err(|err| {
+                println_err!("Woops, I couldn't import entry {} (reason: {:?}).", i + 1, err);
+                ExitCode::from_password_error(&err)
+            })?;
+        }
+    }
+
+    print_summary(&imported, &skipped, dry_run);
+    Ok(())
+}
+
+fn print_summary(imported: &[String], skipped: &[String], dry_run: bool) {
+    if !skipped.is_empty() {
+        if dry_run {
+            println_stderr!("These names are already taken, their passwords would be skipped:");
+        } else {
+            println_stderr!("These names were already taken, I've skipped their passwords:");
+        }
+        for name in skipped.iter() {
+            println_stderr!("    {}", name);
+        }
+    }
+
+    if dry_run {
+        println_ok!("I would import {} password(s) and skip {}. Nothing was changed.",
+                    with_thousands_separators(imported.len()),
+                    with_thousands_separators(skipped.len()));
+    } else {
+        println_ok!("Done! I've imported {} password(s) and skipped {}.",
+                    with_thousands_separators(imported.len()),
+                    with_thousands_separators(skipped.len()));
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Field {
+    Name,
+    Username,
+    Password,
+    Url,
+    Notes,
+}
+
+const FIELDS: [Field; 5] =
+    [Field::Name, Field::Username, Field::Password, Field::Url, Field::Notes];
+
+impl Field {
+    fn name(&self) -> &'static str {
+        match *self {
+            Field::Name => "name",
+            Field::Username => "username",
+            Field::Password => "password",
+            Field::Url => "url",
+            Field::Notes => "notes",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Field> {
+        FIELDS.iter().cloned().find(|f| f.name() == name)
+    }
+
+    fn is_required(&self) -> bool {
+        match *self {
+            Field::Name | Field::Username | Field::Password => true,
+            Field::Url | Field::Notes => false,
+        }
+    }
+
+    /// Secret fields are never shown in previews.
+    fn is_secret(&self) -> bool {
+        match *self {
+            Field::Password | Field::Notes => true,
+            Field::Name | Field::Username | Field::Url => false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum HeaderRow {
+    None,
+    Named,
+    Skipped,
+}
+
+#[derive(Debug, PartialEq)]
+enum Column {
+    /// From 0.
+    Index(usize),
+    Named(String),
+}
+
+#[derive(Debug, PartialEq)]
+struct FieldMapping {
+    header: HeaderRow,
+    columns: Vec<(Field, Column)>,
+}
+
+impl FieldMapping {
+    /// Used without --map-fields: the first row names the columns.
+    fn default() -> FieldMapping {
+        FieldMapping {
+            header: HeaderRow::Named,
+            columns: Vec::new(),
+        }
+    }
+
+    /// Parses mappings like "name=2,username=5,password=6,url=1,skip-header".
+    fn parse(s: &str) -> Result<FieldMapping, String> {
+        let mut header = HeaderRow::None;
+        let mut columns: Vec<(Field, Column)> = Vec::new();
+
+        for item in s.split(',').map(|item| item.trim()).filter(|item| !item.is_empty()) {
+            let (field, column) = match item.find('=') {
+                Some(i) => (item[..i].trim(), item[i + 1..].trim()),
+                None => {
+                    let new_header = match item {
+                        "header" => HeaderRow::Named,
+                        "skip-header" => HeaderRow::Skipped,
+                        _ => {
+                            return Err(format!("\"{}\" should look like <field>=<column>, \
+                                                `header` or `skip-header`",
+                                               item))
+                        }
+                    };
+                    if header != HeaderRow::None && header != new_header {
+                        return Err("`header` and `skip-header` can't be used together"
+                            .to_owned());
+                    }
+                    header = new_header;
+                    continue;
+                }
+            };
+
+            let field = Field::from_name(field.to_lowercase().as_str()).ok_or_else(|| {
+                    format!("\"{}\" is not a field, valid fields are: {}",
+                            field,
+                            FIELDS.iter().map(|f| f.name()).collect::<Vec<&str>>().join(", "))
+                })?;
+            if columns.iter().any(|&(f, _)| f == field) {
+                return Err(format!("the {} field is mapped twice", field.name()));
+            }
+
+            let column = if !column.is_empty() && column.chars().all(|c| c.is_ascii_digit()) {
+                match column.parse::<usize>() {
+                    Ok(index) if index > 0 => Column::Index(index - 1),
+                    _ => {
+                        return Err(format!("column numbers start from 1, \"{}\" is invalid",
+                                           column))
+                    }
+                }
+            } else if column.is_empty() {
+                return Err(format!("the column of the {} field is missing", field.name()));
+            } else {
+                Column::Named(column.to_owned())
+            };
+            columns.push((field, column));
+        }
+
+        if header != HeaderRow::Named && columns.iter().any(|c| match c.1 {
+            Column::Named(_) => true,
+            Column::Index(_) => false,
+        }) {
+            return Err("columns can only be referred to by name with `header`".to_owned());
+        }
+
+        Ok(FieldMapping {
+            header: header,
+            columns: columns,
+        })
+    }
+
+    /// Finds the column of each field, from 0. Required fields may still be missing, see
+    /// `ResolvedMapping::missing_fields`.
+    fn resolve(&self, header: Option<&csv::Record>) -> Result<ResolvedMapping, String> {
+        let header_names: Vec<String> = match header {
+            Some(record) if self.header == HeaderRow::Named => {
+                record.fields.iter().map(|f| f.expose().trim().to_lowercase()).collect()
+            }
+            _ => Vec::new(),
+        };
+        let find_header = |name: &str| {
+            let name = name.trim().to_lowercase();
+            header_names.iter().position(|h| *h == name)
+        };
+
+        let mut resolved = ResolvedMapping { columns: Vec::new() };
+        for field in FIELDS.iter() {
+            let column = match self.columns.iter().find(|&&(f, _)| f == *field) {
+                Some(&(_, Column::Index(index))) => Some(index),
+                Some(&(_, Column::Named(ref name))) => {
+                    Some(find_header(name.as_str())
+                        .ok_or_else(|| format!("there is no column named \"{}\"", name))?)
+                }
+                // Fields that aren't mapped explicitly are looked up by their own name.
+                None => find_header(field.name()),
+            };
+            if let Some(column) = column {
+                resolved.columns.push((*field, column));
+            }
+        }
+
+        Ok(resolved)
+    }
+}
+
+struct ResolvedMapping {
+    columns: Vec<(Field, usize)>,
+}
+
+impl ResolvedMapping {
+    fn column_of(&self, field: Field) -> Option<usize> {
+        self.columns.iter().find(|&&(f, _)| f == field).map(|&(_, column)| column)
+    }
+
+    fn missing_fields(&self) -> Vec<&'static str> {
+        FIELDS.iter()
+            .filter(|f| f.is_required() && self.column_of(**f).is_none())
+            .map(|f| f.name())
+            .collect()
+    }
+
+    fn field_of(&self, column: usize) -> Option<Field> {
+        self.columns.iter().find(|&&(_, c)| c == column).map(|&(f, _)| f)
+    }
+
+    fn value<'a>(&self, record: &'a csv::Record, field: Field) -> Option<&'a SafeString> {
+        self.column_of(field).and_then(|column| record.fields.get(column))
+    }
+
+    fn check_record(&self, record: &csv::Record) -> Result<(), String> {
+        for &(field, column) in self.columns.iter() {
+            if column >= record.fields.len() {
+                return Err(format!("line {} has {} column(s), but the {} field is in column {}",
+                                   record.line,
+                                   record.fields.len(),
+                                   field.name(),
+                                   column + 1));
+            }
+        }
+
+        match self.value(record, Field::Name) {
+            Some(name) if !name.expose().trim().is_empty() => {}
+            _ => return Err(format!("line {} has no name", record.line)),
+        }
+        match self.value(record, Field::Password) {
+            Some(password) if !password.is_empty() => Ok(()),
+            _ => Err(format!("line {} has no password", record.line)),
+        }
+    }
+
+    fn to_password(&self, record: &csv::Record) -> password::v2::Password {
+        let text = |field| self.value(record, field).map(|v| v.expose().trim().to_owned());
+        let optional = |field| text(field).and_then(|v| if v.is_empty() { None } else { Some(v) });
+
+        let mut password = password::v2::Password::new(text(Field::Name).unwrap_or_default(),
+                                                       text(Field::Username).unwrap_or_default(),
+                                                       self.value(record, Field::Password)
+                                                           .cloned()
+                                                           .unwrap_or_else(|| {
+                                                               SafeString::new(String::new())
+                                                           }));
+        password.url = optional(Field::Url);
+        password.notes = self.value(record, Field::Notes)
+            .and_then(|v| if v.expose().trim().is_empty() { None } else { Some(v.clone()) });
+        password
+    }
+}
+
+/// Shows a cell in previews. Cells that may hold a secret are masked.
+fn masked_cell(cell: &SafeString, field: Option<Field>) -> String {
+    match field {
+        Some(field) if !field.is_secret() => cell.expose().to_owned(),
+        _ if cell.is_empty() => String::new(),
+        _ => "********".to_owned(),
+    }
+}
+
+/// Lists the columns of the file and a sample row, to help writing a mapping.
+fn print_detected_columns(header: Option<&csv::Record>,
+                          sample: Option<&csv::Record>,
+                          mapping: Option<&ResolvedMapping>) {
+    let count = header.iter().chain(sample.iter()).map(|r| r.fields.len()).max().unwrap_or(0);
+
+    println_stderr!("");
+    println_stderr!("I found these columns (values that may be secret are masked):");
+    for column in 0..count {
+        let name = header.and_then(|h| h.fields.get(column))
+            .map(|name| format!(" \"{}\"", name.expose()))
+            .unwrap_or_default();
+        let field = mapping.and_then(|m| m.field_of(column));
+        let value = sample.and_then(|s| s.fields.get(column))
+            .map(|cell| masked_cell(cell, field))
+            .unwrap_or_default();
+        let mapped = field.map(|f| format!(" -> {}", f.name())).unwrap_or_default();
+        println_stderr!("    {}{}: {}{}", column + 1, name, value, mapped);
+    }
+}
+
+pub fn check_args(matches: &getopts::Matches) -> Result<(), ExitCode> {
+    if matches.free.len() < 2 {
+        println_err!("Woops, seems like the file to import is missing here. For help, try:");
+        println_err!("    rooster import -h");
+        return Err(ExitCode::Usage);
+    }
+
+    if is_json_path(&matches.free[1]) && matches.opt_present("map-fields") {
+        println_err!("Woops, --map-fields only works with CSV files. For help, try:");
+        println_err!("    rooster import -h");
+        return Err(ExitCode::Usage);
+    }
+
+    if let Some(mapping) = matches.opt_str("map-fields") {
+        if let Err(err) = FieldMapping::parse(mapping.as_str()) {
+            println_err!("Woops, {}. For help, try:", err);
+            println_err!("    rooster import -h");
+            return Err(ExitCode::Usage);
+        }
+    }
+
+    Ok(())
+}
+
+pub fn callback_exec(matches: &getopts::Matches,
+                     store: &mut password::v2::PasswordStore)
+                     -> Result<(), ExitCode> {
+    check_args(matches)?;
+
+    let path = &matches.free[1];
+    let dry_run = matches.opt_present("dry-run");
+    if is_json_path(path) {
+        return import_json(store, path, dry_run);
+    }
+
+    let mapping = match matches.opt_str("map-fields") {
+        Some(mapping) => FieldMapping::parse(mapping.as_str()).map_err(|_| ExitCode::Error)?,
+        None => FieldMapping::default(),
+    };
+
+    let mut contents = SafeString::new(String::new());
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut contents.inner_mut()))
+        .map_err(|err| {
+            println_err!("Woops, I couldn't read {} (reason: {:?}).", path, err);
+            ExitCode::Io
+        })?;
+
+    let records = csv::parse(contents.expose()).map_err(|err| {
+        println_err!("Woops, {} is not a valid CSV file: {}.", path, err);
+        ExitCode::Error
+    })?;
+
+    let (header, rows) = match mapping.header {
+        HeaderRow::None => (None, &records[..]),
+        HeaderRow::Named | HeaderRow::Skipped if records.is_empty() => (None, &records[..]),
+        HeaderRow::Named | HeaderRow::Skipped => (Some(&records[0]), &records[1..]),
+    };
+
+    let resolved = match mapping.resolve(header) {
+        Ok(resolved) => resolved,
+        Err(err) => {
+            println_err!("Woops, {}. For help, try:", err);
+            println_err!("    rooster import -h");
+            print_detected_columns(header, rows.first(), None);
+            return Err(ExitCode::Usage);
+        }
+    };
+
+    let missing = resolved.missing_fields();
+    if !missing.is_empty() {
+        println_err!("Woops, I don't know which column holds the {} field(s). Use --map-fields \
+                      to tell me which column is which.",
+                     missing.join(", "));
+        print_detected_columns(header, rows.first(), Some(&resolved));
+        return Err(ExitCode::Error);
+    }
+
+    // Check every row before importing anything, so that a bad file doesn't get half imported.
+    let errors: Vec<String> = rows.iter().filter_map(|r| resolved.check_record(r).err()).collect();
+    if !errors.is_empty() {
+        println_err!("Woops, I can't import {}:", path);
+        for err in errors.iter() {
+            println_stderr_always!("    {}", err);
+        }
+        print_detected_columns(header, rows.first(), Some(&resolved));
+        return Err(ExitCode::Error);
+    }
+
+    if dry_run {
+        print_detected_columns(header, rows.first(), Some(&resolved));
+        println_stderr!("");
+    }
+
+    let mut imported: Vec<String> = Vec::new();
+    let mut skipped: Vec<String> = Vec::new();
+    for row in rows.iter() {
+        let password = resolved.to_password(row);
+        let taken = store.has_password(password.name.as_str()) ||
+                    imported.iter().any(|n| n.to_lowercase() == password.name.to_lowercase());
+        if taken {
+            skipped.push(format!("{} (line {})", password.name, row.line));
+            continue;
+        }
+
+        imported.push(password.name.clone());
+        if !dry_run {
+            store.add_password(password).map_err(|err| {
+                println_err!("Woops, I couldn't import line {} (reason: {:?}).", row.line, err);
+                ExitCode::from_password_error(&err)
+            })?;
+        }
+    }
+
+    print_summary(&imported, &skipped, dry_run);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Column, Field, FieldMapping, HeaderRow, masked_cell, with_thousands_separators};
+    use csv;
+    use safe_string::SafeString;
+
+    #[test]
+    fn parse_mapping() {
+        assert_eq!(FieldMapping::parse("name=2, username=5,password=6,url=1,skip-header"),
+                   Ok(FieldMapping {
+                       header: HeaderRow::Skipped,
+                       columns: vec![(Field::Name, Column::Index(1)),
+                                     (Field::Username, Column::Index(4)),
+                                     (Field::Password, Column::Index(5)),
+                                     (Field::Url, Column::Index(0))],
+                   }));
+        assert_eq!(FieldMapping::parse("header,Username=Login"),
+                   Ok(FieldMapping {
+                       header: HeaderRow::Named,
+                       columns: vec![(Field::Username, Column::Named("Login".to_owned()))],
+                   }));
+    }
+
+    #[test]
+    fn parse_invalid_mappings() {
+        assert!(FieldMapping::parse("name=0").is_err());
+        assert!(FieldMapping::parse("name=1,name=2").is_err());
+        assert!(FieldMapping::parse("title=1").is_err());
+        assert!(FieldMapping::parse("name=").is_err());
+        assert!(FieldMapping::parse("name=Title").is_err());
+        assert!(FieldMapping::parse("header,skip-header").is_err());
+        assert!(FieldMapping::parse("headers").is_err());
+    }
+
+    #[test]
+    fn resolve_header_names() {
+        let records = csv::parse("URL,Title,Login,Secret,Extra\nhttps://a.com,A,me,pw,x\n")
+            .unwrap();
+
+        let mapping = FieldMapping::parse("header,name=title,username=LOGIN,password=Secret")
+            .unwrap();
+        let resolved = mapping.resolve(Some(&records[0])).unwrap();
+        assert!(resolved.missing_fields().is_empty());
+        assert_eq!(resolved.column_of(Field::Name), Some(1));
+        assert_eq!(resolved.column_of(Field::Url), Some(0));
+        assert_eq!(resolved.column_of(Field::Notes), None);
+
+        let password = resolved.to_password(&records[1]);
+        assert_eq!(password.name, "A");
+        assert_eq!(password.username, "me");
+        assert_eq!(password.password.expose(), "pw");
+        assert_eq!(password.url, Some("https://a.com".to_owned()));
+
+        let resolved = FieldMapping::default().resolve(Some(&records[0])).unwrap();
+        assert_eq!(resolved.missing_fields(), vec!["name", "username", "password"]);
+        let mapping = FieldMapping::parse("header,name=Nope").unwrap();
+        assert!(mapping.resolve(Some(&records[0])).is_err());
+    }
+
+    #[test]
+    fn quoted_fields_and_embedded_newlines() {
+        let records = csv::parse("name,username,password,notes\n\
+                                  \"Bank, Inc.\",me,\"p\"\"w\",\"q: pet?\na: cat\"\n")
+            .unwrap();
+        let resolved = FieldMapping::default().resolve(Some(&records[0])).unwrap();
+
+        let password = resolved.to_password(&records[1]);
+        assert_eq!(password.name, "Bank, Inc.");
+        assert_eq!(password.password.expose(), "p\"w");
+        assert_eq!(password.notes.unwrap().expose(), "q: pet?\na: cat");
+    }
+
+    #[test]
+    fn mismatched_column_counts() {
+        let records = csv::parse("a,me,pw,extra\nb,me\n,me,pw\n").unwrap();
+        let resolved = FieldMapping::parse("name=1,username=2,password=3").unwrap()
+            .resolve(None)
+            .unwrap();
+
+        assert!(resolved.check_record(&records[0]).is_ok());
+        assert_eq!(resolved.check_record(&records[1]),
+                   Err("line 2 has 2 column(s), but the password field is in column 3"
+                       .to_owned()));
+        assert_eq!(resolved.check_record(&records[2]), Err("line 3 has no name".to_owned()));
+    }
+
+    #[test]
+    fn rows_without_a_password_are_rejected() {
+        let records = csv::parse("name,username,password
+a,me,
+b,me,\" \"\n").unwrap();
+        let resolved = FieldMapping::default().resolve(Some(&records[0])).unwrap();
+
+        assert_eq!(resolved.check_record(&records[1]),
+                   Err("line 2 has no password".to_owned()));
+        assert!(resolved.check_record(&records[2]).is_ok());
+    }
+
+    #[test]
+    fn secrets_are_masked() {
+        let cell = SafeString::new("hunter2".to_owned());
+        assert_eq!(masked_cell(&cell, Some(Field::Username)), "hunter2");
+        assert_eq!(masked_cell(&cell, Some(Field::Password)), "********");
+        assert_eq!(masked_cell(&cell, None), "********");
+        assert_eq!(masked_cell(&SafeString::new(String::new()), None), "");
+    }
+
+    #[test]
+    fn thousands_separators() {
+        assert_eq!(with_thousands_separators(0), "0");
+        assert_eq!(with_thousands_separators(999), "999");
+        assert_eq!(with_thousands_separators(5000), "5,000");
+        assert_eq!(with_thousands_separators(1234567), "1,234,567");
+    }
+}
@@ -0,0 +1,255 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use exit_code::ExitCode;
+use answer;
+use getopts;
+use password;
+use safe_string::SafeString;
+use super::set_master_password::{confirm_strength, read_new_master_password};
+use {get_password_file_path, given_master_password, lock_password_file_for_writing,
+     new_yubikey_response, open_password_file, save_password_store, second_factors,
+     set_env_var_command, ROOSTER_FILE_ENV_VAR};
+use help::Help;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+pub fn callback_help() -> Help {
+    Help::new()
+        .usage(&["rooster init -h",
+                 "rooster init [--force] [--key-file <path>] [--yubikey]"])
+        .text(&["Creates a new, empty password file where Rooster looks for it: the file given",
+                &format!("with --file or --profile, ${}, or the default one.",
+                         ROOSTER_FILE_ENV_VAR)])
+        .text(&["The master password is asked twice, and its strength is shown. It can also come",
+                "from --master-password-file or $ROOSTER_MASTER_PASSWORD, to create the file",
+                "from a script."])
+        .text(&["If there is already a password file there, nothing is done, unless --force is",
+                "given. The old file can then be brought back with `rooster restore --previous`.",
+                "If the directory of the password file doesn't exist, you are asked whether to",
+                "create it."])
+        .text(&["With --key-file or --yubikey, the password file also needs the key file or the",
+                "YubiKey to be opened, see `rooster set-master-password -h`."])
+        .examples(&["rooster init",
+                    "rooster init --file ~/Dropbox/passwords.rooster"])
+}
+
+pub fn check_args(matches: &getopts::Matches) -> Result<(), ExitCode> {
+    if matches.free.len() > 1 {
+        println_err!("Woops, init doesn't take any arguments. For help, try:");
+        println_err!("    rooster init -h");
+        return Err(ExitCode::Usage);
+    }
+    Ok(())
+}
+
+/// Whether the directory of the password file is there, or could be created.
+fn prepare_directory(path: &Path) -> Result<bool, ExitCode> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() && !dir.exists() => dir,
+        _ => return Ok(true),
+    };
+
+    print_stderr!("The directory {} doesn't exist. Should I create it (y/n)? ",
+                  dir.display());
+    let mut line = String::new();
+    answer::read_line(&mut line).map_err(|err| {
+        println_err!("I couldn't read that (reason: {:?}).", err);
+        ExitCode::Error
+    })?;
+    Ok(line.starts_with('y'))
+}
+
+/// The master password given with $ROOSTER_MASTER_PASSWORD or in a file, or else a new one
+/// typed twice.
+fn new_master_password() -> Result<Option<SafeString>, ExitCode> {
+    let master_password = match given_master_password() {
+        Some((master_password, _)) => master_password,
+        None => {
+            let master_password = read_new_master_password()?;
+            if !confirm_strength(&master_password)? {
+                return Ok(None);
+            }
+            master_password
+        }
+    };
+    Ok(Some(master_password))
+}
+
+fn print_next_steps(matches: &getopts::Matches, path: &str) {
+    println_stderr!("");
+    println_stderr!("Add your first password with:");
+    println_stderr!("    rooster add <app_name> <username>");
+    if matches.opt_present("file") {
+        println_stderr!("");
+        println_stderr!("To use this password file without --file, add this to your shell config \
+                         (.bashrc, .zshrc, etc):");
+        println_stderr!("    {}", set_env_var_command(ROOSTER_FILE_ENV_VAR, path));
+    }
+}
+
+pub fn callback_exec(matches: &getopts::Matches) -> Result<(), ExitCode> {
+    check_args(matches)?;
+
+    let path = get_password_file_path(matches).map_err(|_| {
+        println_err!("Woops, I could not determine where your password file is. Set ${} to \
+                      where it should be, or give it with --file.",
+                     ROOSTER_FILE_ENV_VAR);
+        ExitCode::Error
+    })?;
+    let replaced = Path::new(&path).exists();
+    if replaced && !matches.opt_present("force") {
+        println_err!("Woops, there is already a password file at {}. Use --force to replace it \
+                      with an empty one.",
+                     path);
+        return Err(ExitCode::AlreadyExists);
+    }
+    let _locked = lock_password_file_for_writing(matches, &path)?;
+    if !prepare_directory(Path::new(&path))? {
+        println_stderr!("OK, I didn't create anything.");
+        return Err(ExitCode::Error);
+    }
+
+    let master_password = match new_master_password()? {
+        Some(master_password) => master_password,
+        None => {
+            println_err!("Alright, I didn't create anything.");
+            return Err(ExitCode::Error);
+        }
+    };
+    let mut factors = second_factors(None)?;
+    if matches.opt_present("yubikey") {
+        factors.yubikey = Some(new_yubikey_response()?);
+    }
+    let store = password::v2::PasswordStore::new_with_factors(master_password, &factors)
+        .map_err(|err| {
+            println_err!("Woops, I couldn't create the password store (reason: {:?}).", err);
+            ExitCode::Error
+        })?;
+
+    if let Some(dir) = Path::new(&path).parent() {
+        fs::create_dir_all(dir).map_err(|err| {
+            println_err!("Woops, I couldn't create {} (reason: {:?}).", dir.display(), err);
+            ExitCode::Io
+        })?;
+    }
+    // Creates the file with its permissions, the store then replaces it in one go.
+    open_password_file(&path, true).map_err(|err| {
+        println_err!("Woops, I couldn't create the password file at {} (reason: {:?}).",
+                     path,
+                     err);
+        ExitCode::Io
+    })?;
+    save_password_store(&store, &path)?;
+
+    println_ok!("Done! I've created your password file at {}.", path);
+    print_next_steps(matches, &path);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::callback_exec;
+    use commands::harness::run_without_store;
+    use exit_code::ExitCode;
+    use password::v2::PasswordStore;
+    use safe_string::SafeString;
+    use safe_vec::SafeVec;
+    use std::env;
+    use std::fs;
+    use std::path::Path;
+    use std::process;
+
+    const MASTER: &'static str = "staple battery horse correct quietly";
+
+    /// Where a password file can be created, in a directory of its own.
+    fn password_file_path(name: &str) -> String {
+        let dir = env::temp_dir().join(format!("rooster-init-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir(&dir).unwrap();
+        dir.join("passwords.rooster").to_string_lossy().into_owned()
+    }
+
+    fn remove_dir(path: &str) {
+        fs::remove_dir_all(Path::new(path).parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn an_empty_password_file_is_created() {
+        let path = password_file_path("created");
+        let (result, output) = run_without_store(callback_exec,
+                                                 &["init", "--file", &path],
+                                                 &[MASTER, MASTER]);
+        assert_eq!(result, Ok(()));
+        assert!(output.stderr.contains("I've created your password file"));
+
+        let input = SafeVec::new(fs::read(&path).unwrap());
+        let store = PasswordStore::from_input(SafeString::new(MASTER.to_owned()), input).unwrap();
+        assert!(store.get_all_passwords().is_empty());
+        if cfg!(unix) {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+        remove_dir(&path);
+    }
+
+    #[test]
+    fn existing_files_are_only_replaced_with_force() {
+        let path = password_file_path("existing");
+        fs::write(&path, b"encrypted").unwrap();
+        let (result, _) = run_without_store(callback_exec,
+                                            &["init", "--file", &path],
+                                            &[MASTER, MASTER]);
+        assert_eq!(result, Err(ExitCode::AlreadyExists));
+        assert_eq!(fs::read(&path).unwrap(), b"encrypted");
+
+        let (result, _) = run_without_store(callback_exec,
+                                            &["init", "--file", &path, "--force"],
+                                            &[MASTER, MASTER]);
+        assert_eq!(result, Ok(()));
+        assert_ne!(fs::read(&path).unwrap(), b"encrypted");
+        assert_eq!(fs::read(format!("{}.prev", path)).unwrap(), b"encrypted");
+        remove_dir(&path);
+    }
+
+    #[test]
+    fn missing_directories_are_created_if_wanted() {
+        let dir = password_file_path("directories");
+        let path = Path::new(&dir).with_file_name("new").join("passwords.rooster");
+        let path = path.to_string_lossy().into_owned();
+
+        let (result, _) = run_without_store(callback_exec, &["init", "--file", &path], &["n"]);
+        assert_eq!(result, Err(ExitCode::Error));
+        assert!(!Path::new(&path).parent().unwrap().exists());
+
+        let (result, _) = run_without_store(callback_exec,
+                                            &["init", "--file", &path],
+                                            &["y", MASTER, MASTER]);
+        assert_eq!(result, Ok(()));
+        assert!(Path::new(&path).exists());
+        remove_dir(&dir);
+    }
+
+    #[test]
+    fn nothing_is_created_if_the_passwords_differ() {
+        let path = password_file_path("mismatch");
+        let (result, _) = run_without_store(callback_exec,
+                                            &["init", "--file", &path],
+                                            &[MASTER, "staple battery horse correct quietlY"]);
+        assert_eq!(result, Err(ExitCode::Error));
+        assert!(!Path::new(&path).exists());
+        remove_dir(&path);
+    }
+}
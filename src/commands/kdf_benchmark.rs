@@ -0,0 +1,175 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+use exit_code::ExitCode;
+use getopts;
+use password;
+use password::v2::Kdf;
+use commands::set_kdf;
+use help::Help;
+use std::io::Write;
+use std::time::Duration;
+
+/// How long opening the password file should take, unless --target-ms says otherwise.
+const DEFAULT_TARGET_MS: u64 = 1000;
+
+pub fn callback_help() -> Help {
+    Help::new()
+        .usage(&["rooster kdf-benchmark -h",
+                 "rooster kdf-benchmark [--target-ms <ms>] [--scrypt] [--apply]"])
+        .examples(&["rooster kdf-benchmark",
+                    "rooster kdf-benchmark --target-ms 500 --apply"])
+        .text(&["Derives keys with more and more memory on this machine, and shows how long each",
+                &format!("takes, until it takes longer than opening your password file should: {} \
+                          ms, or",
+                         DEFAULT_TARGET_MS),
+                "<ms>. The parameters that come closest are the ones to give to `rooster set-kdf`.",
+                "The longer it takes, the longer it also takes to guess your master password."])
+        .text(&["Argon2id is measured, like for new password files, or scrypt with --scrypt.",
+                "This doesn't need a password file, except with --apply, which derives the key of",
+                "your password file with the parameters that come closest, like set-kdf does."])
+}
+
+fn target_from_matches(matches: &getopts::Matches) -> Result<Duration, ExitCode> {
+    match matches.opt_str("target-ms") {
+        Some(value) => {
+            match value.parse::<u64>() {
+                Ok(ms) if ms > 0 => Ok(Duration::from_millis(ms)),
+                _ => {
+                    println_err!("Woops, --target-ms must be a number of milliseconds, like \
+                                  1000, not \"{}\".",
+                                 value);
+                    Err(ExitCode::Usage)
+                }
+            }
+        }
+        None => Ok(Duration::from_millis(DEFAULT_TARGET_MS)),
+    }
+}
+
+/// The parameters to measure, from the cheapest to the most costly. Each needs twice the memory
+/// of the previous one, and takes about twice as long.
+fn levels(scrypt: bool) -> Vec<Kdf> {
+    if scrypt {
+        (12..password::v2::MAX_SCRYPT_LOG2_N + 1)
+            .map(|log2_n| Kdf::Scrypt { log2_n: log2_n, r: 8, p: 1 })
+            .collect()
+    } else {
+        (3..13)
+            .map(|shift| {
+                Kdf::Argon2id(password::v3::Argon2Params {
+                    memory_kib: 1024 << shift,
+                    ..password::v3::DEFAULT_PARAMS
+                })
+            })
+            .collect()
+    }
+}
+
+/// The measured parameters that take the closest to `target`.
+fn closest(measured: &[(Kdf, Duration)], target: Duration) -> Option<Kdf> {
+    let distance = |took: Duration| if took > target { took - target } else { target - took };
+    measured.iter().min_by_key(|&&(_, took)| distance(took)).map(|&(kdf, _)| kdf)
+}
+
+fn millis(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + (duration.subsec_nanos() / 1_000_000) as u64
+}
+
+pub fn check_args(matches: &getopts::Matches) -> Result<(), ExitCode> {
+    target_from_matches(matches)?;
+    Ok(())
+}
+
+/// The password file is only needed to apply the parameters.
+pub fn needs_store(matches: &getopts::Matches) -> bool {
+    matches.opt_present("apply")
+}
+
+/// Measures how long deriving a key takes with more and more memory, and gives the parameters
+/// that come closest to the target.
+fn benchmark(matches: &getopts::Matches) -> Result<Kdf, ExitCode> {
+    let target = target_from_matches(matches)?;
+
+    println_stderr!("Measuring how long deriving a key takes on this machine...");
+    let mut measured = Vec::new();
+    for kdf in levels(matches.opt_present("scrypt")) {
+        let took = kdf.time_derivation();
+        println_stdout!("{:>6} MiB {:>7} ms", kdf.memory() >> 20, millis(took));
+        measured.push((kdf, took));
+        // The next ones can only be further from the target.
+        if took >= target {
+            break;
+        }
+    }
+
+    let recommended = match closest(&measured, target) {
+        Some(kdf) => kdf,
+        None => return Err(ExitCode::Error),
+    };
+    println_stdout!("");
+    println_stdout!("Closest to {} ms: {}.",
+                    millis(target),
+                    set_kdf::describe(&recommended));
+    Ok(recommended)
+}
+
+/// Runs the benchmark without the password file, unless the parameters are to be applied.
+pub fn callback_without_store(matches: &getopts::Matches) -> Result<(), ExitCode> {
+    check_args(matches)?;
+    if needs_store(matches) {
+        return Ok(());
+    }
+    benchmark(matches)?;
+    println_stdout!("Use it with `rooster set-kdf`, or run this again with --apply.");
+    Ok(())
+}
+
+pub fn callback_exec(matches: &getopts::Matches,
+                     store: &mut password::v2::PasswordStore)
+                     -> Result<(), ExitCode> {
+    let recommended = benchmark(matches)?;
+    set_kdf::apply(recommended, store)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{closest, levels};
+    use password::v2::Kdf;
+    use std::time::Duration;
+
+    #[test]
+    fn levels_double_the_memory() {
+        for &scrypt in &[false, true] {
+            let levels = levels(scrypt);
+            for pair in levels.windows(2) {
+                assert_eq!(pair[1].memory(), 2 * pair[0].memory());
+            }
+        }
+        assert_eq!(levels(true)[0], Kdf::Scrypt { log2_n: 12, r: 8, p: 1 });
+        assert_eq!(levels(false).last().unwrap().memory(), 4 << 30);
+    }
+
+    #[test]
+    fn the_closest_level_may_be_faster_or_slower() {
+        let ms = Duration::from_millis;
+        let scrypt = levels(true);
+        let measured = vec![(scrypt[0], ms(300)), (scrypt[1], ms(600)), (scrypt[2], ms(1200))];
+        assert_eq!(closest(&measured, ms(1000)), Some(scrypt[2]));
+        assert_eq!(closest(&measured, ms(800)), Some(scrypt[1]));
+        assert_eq!(closest(&measured, ms(10)), Some(scrypt[0]));
+        assert_eq!(closest(&[], ms(10)), None);
+    }
+}
@@ -12,31 +12,452 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use exit_code::ExitCode;
+use answer;
 use getopts;
+use ffi;
 use password;
 use list;
-use std::io::Write;
+use output_format;
+use serde_json;
+use timestamp;
+use help::Help;
+use std::fs::File;
+use std::io::{Read, Write};
 
-pub fn callback_help() {
-    println!("Usage:");
-    println!("    rooster list -h");
-    println!("    rooster list");
-    println!("");
-    println!("Example:");
-    println!("    rooster list");
+pub fn callback_help() -> Help {
+    Help::new()
+        .usage(&["rooster list -h",
+                 "rooster list",
+                 "rooster list [<query>...] [--exact] [--tag <tag>]... [--json]",
+                 "rooster list [--sort name|created|updated] [--reverse] [--verbose]",
+                 "rooster list [<query>] --porcelain",
+                 "rooster list [<query>] --names-only",
+                 "rooster list <regex> --regex",
+                 "rooster list --inactive-usernames <file> [--archive-matches]"])
+        .text(&["With --inactive-usernames, only the passwords whose username appears in <file>",
+                "are listed. The file contains one username or domain per line, or a single",
+                "column CSV. Usernames are compared exactly, without regard to casing. A domain",
+                "(no @ sign) also matches every username ending with @<domain>. With",
+                "--archive-matches, the matching passwords are archived."])
+        .text(&["Favorites (see `rooster favorite -h`) are listed first, in their own section.",
+                "Archived passwords (see `rooster archive -h`) are only listed with --archived."])
+        .text(&["With a query, only the passwords whose name matches it are listed, with the",
+                "same fuzzy search as the other commands. With several words, the passwords have",
+                "to match all of them, like `rooster list aws prod`. With --exact, the name has to",
+                "contain the query as is (without regard to casing). With --regex, the query is a",
+                "regular expression instead, matched against names and usernames without regard",
+                "to casing."])
+        .text(&["--updated-since, --updated-before and --created-since only keep the passwords",
+                "changed or created since or before a date, like 2020-01-01 (in UTC), or a",
+                "duration ago, like 90d, 12w, 6m or 1y."])
+        .text(&["With --verbose, the creation and update dates of each password are shown too."])
+        .text(&["With --porcelain, stdout only holds one line per password, with its name and",
+                "username separated by a tab. Tabs and line breaks within them are replaced with",
+                "spaces, and there are no colors or sections. This format won't change, so that",
+                "scripts can rely on it."])
+        .text(&["With --names-only, stdout only holds the name of each password, one per line,",
+                "with tabs and line breaks replaced the same way. Shell completions use it."])
+        .text(&["Passwords are sorted by name, or with --sort, by creation or update time, oldest",
+                "first, and then by name. --reverse sorts the other way around."])
+        .text(&["With --json (or --format json), stdout only holds a JSON array with the name,",
+                "username, creation and update times (in seconds since the epoch), URL, tags, and",
+                "whether each password is a favorite or archived. Passwords and notes are never",
+                "included."])
+        .examples(&["rooster list",
+                    "rooster list --tag work",
+                    "rooster list gml",
+                    "rooster list mail --exact --json",
+                    "rooster list --updated-before 2020-01-01 --sort updated",
+                    "rooster list --sort updated",
+                    "rooster list --json | jq -r '.[].name'",
+                    "rooster --archived list",
+                    "rooster list --inactive-usernames deprovisioned.csv"])
 }
 
-pub fn callback_exec(_matches: &getopts::Matches,
+/// What `rooster list --json` shows of a password, which leaves out every secret. `rooster get
+/// --info --json` shows it too.
+#[derive(Serialize)]
+pub struct JsonEntry<'a> {
+    name: &'a str,
+    username: &'a str,
+    created_at: ffi::time_t,
+    updated_at: ffi::time_t,
+    url: Option<&'a str>,
+    tags: &'a [String],
+    favorite: bool,
+    archived: bool,
+}
+
+impl<'a> JsonEntry<'a> {
+    pub fn of(password: &'a password::v2::Password) -> JsonEntry<'a> {
+        JsonEntry {
+            name: &password.name,
+            username: &password.username,
+            created_at: password.created_at,
+            updated_at: password.updated_at,
+            url: password.url.as_ref().map(|url| url.as_str()),
+            tags: &password.tags,
+            favorite: password.favorite,
+            archived: password.archived,
+        }
+    }
+}
+
+fn print_json(passwords: &[&password::v2::Password]) -> Result<(), ExitCode> {
+    let entries: Vec<JsonEntry> = passwords.iter().map(|p| JsonEntry::of(p)).collect();
+    let json = serde_json::to_string_pretty(&entries).map_err(|err| {
+        println_err!("I couldn't write the list (reason: {:?}).", err);
+        ExitCode::Error
+    })?;
+    print_stdout!("{}\n", json);
+    Ok(())
+}
+
+/// A date option like --updated-since, `Err` if it can't be parsed (after telling the user).
+fn time_from_matches(matches: &getopts::Matches,
+                     option: &str)
+                     -> Result<Option<ffi::time_t>, ExitCode> {
+    match matches.opt_str(option) {
+        Some(value) => {
+            timestamp::parse_date_or_duration(&value, ffi::time()).map(Some).map_err(|err| {
+                println_err!("Woops, --{} is wrong: {}.", option, err);
+                ExitCode::Usage
+            })
+        }
+        None => Ok(None),
+    }
+}
+
+pub fn callback_exec(matches: &getopts::Matches,
                      store: &mut password::v2::PasswordStore)
-                     -> Result<(), i32> {
-    let passwords = store.get_all_passwords();
+                     -> Result<(), ExitCode> {
+    let json = output_format::json_requested(matches);
+    if json && matches.opt_present("inactive-usernames") {
+        println_err!("Woops, --json can't be used with --inactive-usernames. For help, try:");
+        println_err!("    rooster list -h");
+        return Err(ExitCode::Usage);
+    }
+    // --names-only goes with --porcelain, which is how completions call it.
+    let formats = if matches.opt_present("names-only") {
+        ["json", "names-only", "verbose"]
+    } else {
+        ["json", "porcelain", "verbose"]
+    };
+    let chosen: Vec<&&str> = formats.iter()
+        .filter(|&&format| if format == "json" { json } else { matches.opt_present(format) })
+        .collect();
+    if chosen.len() > 1 {
+        println_err!("Woops, --{} and --{} can't be used together. For help, try:",
+                     chosen[0],
+                     chosen[1]);
+        println_err!("    rooster list -h");
+        return Err(ExitCode::Usage);
+    }
+
+    if let Some(path) = matches.opt_str("inactive-usernames") {
+        return list_inactive_usernames(matches, store, path.as_str());
+    }
+
+    let tags = list::tags_from_matches(matches)?;
+    let query = list::query_from_matches(matches);
+    let matching_names: Option<Vec<String>> = query.as_ref().map(|query| {
+        if matches.opt_present("exact") {
+            let query = query.to_lowercase();
+            store.get_all_passwords()
+                .into_iter()
+                .filter(|p| p.name.to_lowercase().contains(&query))
+                .map(|p| p.name.clone())
+                .collect()
+        } else {
+            store.search_passwords(query).into_iter().map(|p| p.name.clone()).collect()
+        }
+    });
+    let updated_since = time_from_matches(matches, "updated-since")?;
+    let updated_before = time_from_matches(matches, "updated-before")?;
+    let created_since = time_from_matches(matches, "created-since")?;
+    let include_archived = store.includes_archived();
+    let mut passwords: Vec<&password::v2::Password> = store.get_all_passwords()
+        .into_iter()
+        .filter(|p| include_archived || !p.archived)
+        .filter(|p| list::has_all_tags(p, &tags))
+        .filter(|p| matching_names.as_ref().map_or(true, |names| names.contains(&p.name)))
+        .filter(|p| updated_since.map_or(true, |since| p.updated_at >= since))
+        .filter(|p| updated_before.map_or(true, |before| p.updated_at < before))
+        .filter(|p| created_since.map_or(true, |since| p.created_at >= since))
+        .collect();
+    list::sort_passwords(&mut passwords, list::sort_order(), store.collation());
+
+    if let Some(query) = query {
+        if passwords.is_empty() {
+            println_err!("No entries match \"{}\".", query);
+            return Err(ExitCode::NotFound);
+        }
+    }
+
+    if json {
+        return print_json(&passwords);
+    }
+
+    if matches.opt_present("names-only") {
+        for password in passwords.iter() {
+            println_stdout!("{}", list::porcelain_field(&password.name));
+        }
+        return Ok(());
+    }
 
-    if passwords.len() == 0 {
-        println!("No passwords on record yet. Add one with 'rooster add <app> <username>'.");
+    if list::porcelain() {
+        list::print_list_of_passwords(&passwords,
+                                      list::WITHOUT_NUMBERS,
+                                      list::OutputStream::Stdout);
+        return Ok(());
+    }
+
+    if passwords.len() == 0 && !tags.is_empty() {
+        println_stderr!("No passwords are tagged {}.", tags.join(" and "));
+    } else if passwords.len() == 0 && store.get_all_passwords().len() > 0 {
+        println_stderr!("All your passwords are archived. To list them, use --archived.");
+    } else if passwords.len() == 0 {
+        println_stdout!("No passwords on record yet. Add one with 'rooster add <app> <username>'.");
     } else {
         println_stderr!("");
-        list::print_list_of_passwords(&passwords, list::WITHOUT_NUMBERS, list::OutputStream::Stdout);
+        print_favorites_first(&passwords, matches.opt_present("verbose"));
+    }
+
+    Ok(())
+}
+
+/// Prints the favorites in their own section at the top. Without favorites, this is a plain
+/// list.
+fn print_favorites_first(passwords: &Vec<&password::v2::Password>, verbose: bool) {
+    let print = |passwords: &Vec<&password::v2::Password>| if verbose {
+        list::print_verbose_list_of_passwords(passwords)
+    } else {
+        list::print_list_of_passwords(passwords, list::WITHOUT_NUMBERS, list::OutputStream::Stdout)
+    };
+
+    let (favorites, others): (Vec<&password::v2::Password>, Vec<&password::v2::Password>) =
+        passwords.iter().partition(|p| p.favorite);
+
+    if favorites.is_empty() {
+        print(&others);
+        return;
+    }
+
+    println_title!("Favorites");
+    print(&favorites);
+
+    if !others.is_empty() {
+        println_stderr!("");
+        println_title!("Other passwords");
+        print(&others);
+    }
+}
+
+fn list_inactive_usernames(matches: &getopts::Matches,
+                           store: &mut password::v2::PasswordStore,
+                           path: &str)
+                           -> Result<(), ExitCode> {
+    let mut contents = String::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut contents))
+        .map_err(|err| {
+            println_err!("Woops, I couldn't read {} (reason: {:?}).", path, err);
+            ExitCode::Io
+        })?;
+
+    let inactive = parse_username_list(contents.as_str());
+    if inactive.is_empty() {
+        println_err!("Woops, I couldn't find any username in {}.", path);
+        return Err(ExitCode::Error);
+    }
+
+    let passwords: Vec<&password::v2::Password> = store.get_all_passwords()
+        .into_iter()
+        .filter(|p| inactive.iter().any(|item| username_matches(item, p.username.as_str())))
+        .collect();
+
+    if passwords.is_empty() {
+        println_ok!("None of your passwords match the {} usernames in {}.",
+                    inactive.len(),
+                    path);
+        return Ok(());
+    }
+
+    println_stderr!("");
+    println_stderr!("These passwords belong to inactive accounts. You should archive them:");
+    println_stderr!("");
+    list::print_list_of_passwords(&passwords, list::WITHOUT_NUMBERS, list::OutputStream::Stdout);
+
+    if matches.opt_present("archive-matches") {
+        let names: Vec<String> = passwords.iter()
+            .filter(|p| !p.archived)
+            .map(|p| p.name.clone())
+            .collect();
+        return archive_passwords(store, names);
+    }
+
+    Ok(())
+}
+
+fn archive_passwords(store: &mut password::v2::PasswordStore,
+                     names: Vec<String>)
+                     -> Result<(), ExitCode> {
+    println_stderr!("");
+    if names.is_empty() {
+        println_ok!("All of these passwords are already archived.");
+        return Ok(());
     }
 
+    print_stderr!("Archive these {} password(s) (y/n)? ", names.len());
+    let mut line = String::new();
+    answer::read_line(&mut line).map_err(|err| {
+        println_err!("I couldn't read that (reason: {:?}).", err);
+        ExitCode::Error
+    })?;
+    if !line.starts_with('y') {
+        println_stderr!("OK, I didn't change anything.");
+        return Ok(());
+    }
+
+    // All the passwords are changed in memory, the file is only written once at the end.
+    for name in names.iter() {
+        store.change_password(name,
+                              &|old_password: password::v2::Password| {
+            password::v2::Password {
+                archived: true,
+                ..old_password
+            }
+        }).map_err(|err| {
+            println_err!("Woops, I couldn't archive {} (reason: {:?}).", name, err);
+            ExitCode::from_password_error(&err)
+        })?;
+    }
+
+    println_ok!("Done! I've archived {} password(s).", names.len());
     Ok(())
 }
+
+/// Reads usernames from either a plain list (one per line) or a single column CSV.
+///
+/// The UTF-8 byte order mark, blank lines, surrounding whitespace, CSV quotes and a CSV header
+/// are removed. Duplicates (without regard to casing) are only kept once.
+fn parse_username_list(contents: &str) -> Vec<String> {
+    let contents = contents.trim_start_matches('\u{feff}');
+
+    let mut usernames: Vec<String> = Vec::new();
+    for (i, line) in contents.lines().enumerate() {
+        let username = first_csv_field(line).trim().to_owned();
+        if username.is_empty() {
+            continue;
+        }
+
+        if i == 0 && is_csv_header(username.as_str()) {
+            continue;
+        }
+
+        if !usernames.iter().any(|u| u.to_lowercase() == username.to_lowercase()) {
+            usernames.push(username);
+        }
+    }
+    usernames
+}
+
+/// Returns the first field of a CSV line, or the whole line for plain lists.
+fn first_csv_field(line: &str) -> String {
+    let line = line.trim();
+
+    if !line.starts_with('"') {
+        return match line.find(',') {
+            Some(i) => line[..i].to_owned(),
+            None => line.to_owned(),
+        };
+    }
+
+    // Quoted field, where "" stands for a single quote.
+    let mut field = String::new();
+    let mut chars = line[1..].chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '"' {
+            if chars.peek() == Some(&'"') {
+                chars.next();
+            } else {
+                break;
+            }
+        }
+        field.push(c);
+    }
+    field
+}
+
+fn is_csv_header(field: &str) -> bool {
+    match field.to_lowercase().as_str() {
+        "username" | "usernames" | "user" | "login" | "email" | "e-mail" | "account" |
+        "domain" => true,
+        _ => false,
+    }
+}
+
+/// Usernames match exactly without regard to casing, and domains match any username at that
+/// domain.
+fn username_matches(item: &str, username: &str) -> bool {
+    let item = item.to_lowercase();
+    let username = username.to_lowercase();
+
+    if item == username {
+        return true;
+    }
+
+    if !item.contains('@') {
+        if let Some(at) = username.rfind('@') {
+            return username[at + 1..] == item;
+        }
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_username_list, username_matches};
+
+    #[test]
+    fn parse_plain_list_with_whitespace_and_duplicates() {
+        let contents = "  alice@example.com \n\nbob@example.com\r\nALICE@example.com\n\t\n";
+        assert_eq!(parse_username_list(contents),
+                   vec!["alice@example.com", "bob@example.com"]);
+    }
+
+    #[test]
+    fn parse_csv_with_bom_and_header() {
+        let contents = "\u{feff}\"email\"\n\"alice@example.com\"\n\"bob@example.com\"\n\
+                        \"alice@example.com\"\n";
+        assert_eq!(parse_username_list(contents),
+                   vec!["alice@example.com", "bob@example.com"]);
+    }
+
+    #[test]
+    fn parse_csv_with_quotes_and_extra_columns() {
+        let contents = "username,deprovisioned_at\n\"o\"\"neil\",2020-01-01\ncarol,2020-02-01\n";
+        assert_eq!(parse_username_list(contents), vec!["o\"neil", "carol"]);
+    }
+
+    #[test]
+    fn parse_only_bom() {
+        assert!(parse_username_list("\u{feff}").is_empty());
+    }
+
+    #[test]
+    fn usernames_match_without_casing() {
+        assert!(username_matches("Alice@Example.com", "alice@example.COM"));
+        assert!(!username_matches("alice@example.com", "alice@example.org"));
+        assert!(!username_matches("alice", "alice2"));
+    }
+
+    #[test]
+    fn domains_match_usernames_at_that_domain() {
+        assert!(username_matches("example.com", "alice@EXAMPLE.com"));
+        assert!(!username_matches("example.com", "alice@sub.example.com"));
+        assert!(username_matches("alice", "alice"));
+    }
+}
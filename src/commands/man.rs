@@ -0,0 +1,228 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use exit_code::ExitCode;
+use askpass;
+use commands::restore;
+use getopts;
+use git;
+use password;
+use usage;
+use yubikey;
+use {COMMANDS, ROOSTER_FILE_DEFAULT, ROOSTER_FILE_ENV_VAR, ROOSTER_HISTORY_DEPTH_ENV_VAR,
+     ROOSTER_MASTER_PASSWORD_ENV_VAR, ROOSTER_MASTER_PASSWORD_FILE_ENV_VAR,
+     ROOSTER_NON_INTERACTIVE_ENV_VAR, ROOSTER_NOTIFY_AFTER_ENV_VAR, ROOSTER_SELECTION_ENV_VAR};
+use help::{Block, Help, Part};
+use std::io::Write;
+
+pub fn callback_help() -> Help {
+    Help::new()
+        .usage(&["rooster man -h",
+                 "rooster man"])
+        .text(&["Prints the man page of Rooster, in roff, made from the same help texts as -h."])
+        .examples(&["rooster man > rooster.1"])
+}
+
+/// The environment variables, which the help texts only mention in passing.
+fn environment() -> Vec<(&'static str, &'static str)> {
+    vec![(ROOSTER_FILE_ENV_VAR, "The password file, unless --file is given."),
+         (ROOSTER_HISTORY_DEPTH_ENV_VAR, "How many previous values to keep for each password."),
+         (ROOSTER_NOTIFY_AFTER_ENV_VAR,
+          "How many seconds a command takes before --notify-done rings the bell."),
+         (ROOSTER_SELECTION_ENV_VAR, "Set to \"primary\" to copy to the PRIMARY selection."),
+         (ROOSTER_MASTER_PASSWORD_ENV_VAR,
+          "The master password, removed from the environment right away."),
+         (ROOSTER_MASTER_PASSWORD_FILE_ENV_VAR,
+          "A file whose first line is the master password."),
+         (ROOSTER_NON_INTERACTIVE_ENV_VAR,
+          "Fail instead of asking anything, like --non-interactive."),
+         (restore::ROOSTER_NO_BACKUP_ENV_VAR,
+          "Don't keep the previous version of the password file, like --no-backup."),
+         (git::ROOSTER_GIT_ENV_VAR,
+          "Commit the password file after every change, if it is in a git repository."),
+         (askpass::ROOSTER_ASKPASS_ENV_VAR,
+          "A program that asks for the master password without a terminal."),
+         (askpass::SSH_ASKPASS_ENV_VAR, "Used when $ROOSTER_ASKPASS isn't set."),
+         (yubikey::YUBIKEY_SLOT_ENV_VAR, "The slot of the YubiKey to ask, 1 or 2.")]
+}
+
+/// Escapes text for roff: backslashes, dashes and dots or quotes at the start of a line.
+fn escape(text: &str) -> String {
+    let escaped = text.replace('\\', "\\e").replace('-', "\\-");
+    if escaped.starts_with('.') || escaped.starts_with('\'') {
+        format!("\\&{}", escaped)
+    } else {
+        escaped
+    }
+}
+
+/// Prose becomes filled text, commands are kept as they are, and tables become tagged
+/// paragraphs, after which the indentation has to be reset.
+fn render_parts(parts: &[Part], out: &mut String) {
+    let mut after_table = false;
+    for part in parts.iter() {
+        if after_table {
+            out.push_str(".PP\n");
+        }
+        after_table = false;
+        match *part {
+            Part::Text(ref lines) => {
+                for line in lines.iter() {
+                    out.push_str(&escape(line));
+                    out.push('\n');
+                }
+            }
+            Part::Commands(ref lines) => {
+                out.push_str(".nf\n");
+                for line in lines.iter() {
+                    out.push_str(&escape(line));
+                    out.push('\n');
+                }
+                out.push_str(".fi\n");
+            }
+            Part::Table(_, ref rows) => {
+                for &(ref name, ref description) in rows.iter() {
+                    out.push_str(&format!(".TP\n\\fB{}\\fR\n{}\n",
+                                          escape(name),
+                                          escape(&description.replace('\n', " "))));
+                }
+                after_table = true;
+            }
+        }
+    }
+}
+
+fn render_command(name: &str, help: &Help, out: &mut String) {
+    out.push_str(&format!(".SS \"rooster {}\"\n", escape(name)));
+    for block in help.blocks().iter() {
+        match block.title {
+            Some(title) if title != "Usage" => out.push_str(&format!(".PP\n\\fI{}:\\fR\n", title)),
+            _ => out.push_str(".PP\n"),
+        }
+        render_parts(&block.parts, out);
+    }
+}
+
+fn man_page(global_help: &Help, command_helps: &[(&str, Help)]) -> String {
+    let mut out = String::new();
+    out.push_str(&format!(".TH ROOSTER 1 \"\" \"Rooster {}\" \"User Commands\"\n",
+                          env!("CARGO_PKG_VERSION")));
+    out.push_str(".SH NAME\n");
+    let description = env!("CARGO_PKG_DESCRIPTION").to_lowercase();
+    out.push_str(&format!("rooster \\- {}\n", escape(&description)));
+
+    let blocks = global_help.blocks();
+    for block in blocks.iter().filter(|block| block.title == Some("Usage")) {
+        out.push_str(".SH SYNOPSIS\n");
+        render_parts(&block.parts, &mut out);
+    }
+    out.push_str(".SH DESCRIPTION\n");
+    for block in blocks.iter().filter(|block| block.title.is_none()) {
+        out.push_str(".PP\n");
+        render_parts(&block.parts, &mut out);
+    }
+    for block in blocks.iter().filter(|block| block.title == Some("Options")) {
+        out.push_str(".SH OPTIONS\n");
+        render_parts(&block.parts, &mut out);
+    }
+    let is_commands = |block: &&Block| block.title.map_or(false, |t| t.starts_with("Commands"));
+    for block in blocks.iter().filter(is_commands) {
+        out.push_str(".SH COMMANDS\n");
+        render_parts(&block.parts, &mut out);
+        for &(name, ref help) in command_helps.iter() {
+            render_command(name, help, &mut out);
+        }
+    }
+
+    out.push_str(".SH ENVIRONMENT\n");
+    for (name, description) in environment() {
+        out.push_str(&format!(".TP\n\\fB{}\\fR\n{}\n", escape(name), escape(description)));
+    }
+
+    out.push_str(".SH FILES\n");
+    out.push_str(&format!(".TP\n\\fI~/{}\\fR\n", escape(ROOSTER_FILE_DEFAULT)));
+    out.push_str(&format!("The password file, encrypted with the master password. Its format is \
+                           version {}, or version {} when it also needs a key file or a YubiKey.\n",
+                          password::v2::VERSION,
+                          password::v2::VERSION_WITH_SECOND_FACTORS));
+
+    for block in blocks.iter().filter(|block| block.title == Some("Exit codes")) {
+        out.push_str(".SH \"EXIT STATUS\"\n");
+        render_parts(&block.parts, &mut out);
+    }
+    out
+}
+
+pub fn callback_exec(matches: &getopts::Matches) -> Result<(), ExitCode> {
+    if matches.free.len() > 1 {
+        println_err!("Woops, man doesn't take any arguments. For help, try:");
+        println_err!("    rooster man -h");
+        return Err(ExitCode::Usage);
+    }
+
+    let global_help = usage(&format!("~/{}", ROOSTER_FILE_DEFAULT), None);
+    let command_helps: Vec<(&str, Help)> = COMMANDS.iter()
+        .filter(|command| command.name != "man")
+        .map(|command| (command.name, (command.callback_help)()))
+        .collect();
+
+    print_stdout!("{}", man_page(&global_help, &command_helps));
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{escape, man_page};
+    use help::Help;
+
+    #[test]
+    fn escapes_roff() {
+        assert_eq!(escape("--show"), "\\-\\-show");
+        assert_eq!(escape(".hidden"), "\\&.hidden");
+        assert_eq!(escape("a\\b"), "a\\eb");
+    }
+
+    #[test]
+    fn renders_every_section() {
+        let global_help = Help::new()
+            .text(&["Welcome."])
+            .usage(&["rooster -h"])
+            .table("Options",
+                   18,
+                   &[("-h, --help", "Display a help message"),
+                     ("--similar-to <query>", "Generate a password\nlike another")])
+            .table("Commands", 27, &[("get", "Retrieve a password")])
+            .table("Exit codes", 4, &[("0", "Success")]);
+        let get_help = Help::new()
+            .usage(&["rooster get <query>"])
+            .text(&["Matches names,", "for instance:"])
+            .then_table(7, &[("ytb", "YouTube")])
+            .then_text(&["and usernames."])
+            .examples(&["rooster get youtube"]);
+        let page = man_page(&global_help, &[("get", get_help)]);
+        for expected in &[".SH SYNOPSIS\n.nf\nrooster \\-h\n.fi\n",
+                          ".SH DESCRIPTION\n.PP\nWelcome.\n",
+                          ".TP\n\\fB\\-h, \\-\\-help\\fR\nDisplay a help message\n",
+                          ".TP\n\\fB\\-\\-similar\\-to <query>\\fR\nGenerate a password like \
+                           another\n",
+                          ".TP\n\\fBget\\fR\nRetrieve a password\n",
+                          ".SS \"rooster get\"\n",
+                          "for instance:\n.TP\n\\fBytb\\fR\nYouTube\n.PP\nand usernames.\n",
+                          ".PP\n\\fIExample:\\fR\n.nf\nrooster get youtube\n.fi\n",
+                          ".SH ENVIRONMENT\n",
+                          ".SH \"EXIT STATUS\"\n.TP\n\\fB0\\fR\nSuccess\n"] {
+            assert!(page.contains(expected), "{}\n{}", expected, page);
+        }
+    }
+}
@@ -0,0 +1,171 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+use exit_code::ExitCode;
+use answer;
+use getopts;
+use password;
+use password::v2::Password;
+use list;
+use help::Help;
+use std::io::Write;
+
+pub fn callback_help() -> Help {
+    Help::new()
+        .usage(&["rooster merge -h",
+                 "rooster merge <source> <target>"])
+        .text(&["Merges two passwords for the same account into <target>, then deletes <source>.",
+                "The username, URL and note of <source> are copied where <target> has none, tags",
+                "are combined, and the password of <source> is kept in the history of <target>.",
+                "If both have a different username, you are asked which one to keep."])
+        .examples(&["rooster merge old-youtube youtube"])
+}
+
+pub fn check_args(matches: &getopts::Matches) -> Result<(), ExitCode> {
+    if matches.free.len() != 3 {
+        println_err!("Woops, I need the names of the two passwords to merge. For help, try:");
+        println_err!("    rooster merge -h");
+        return Err(ExitCode::Usage);
+    }
+
+    Ok(())
+}
+
+/// Whether both passwords have a username, and they're not the same.
+fn usernames_conflict(source: &Password, target: &Password) -> bool {
+    !source.username.is_empty() && !target.username.is_empty() &&
+    source.username.to_lowercase() != target.username.to_lowercase()
+}
+
+fn ask_username(source: &Password, target: &Password) -> Result<String, ExitCode> {
+    println_stderr_always!("These passwords have different usernames:");
+    println_stderr_always!("    1 {} ({})", source.username, source.name);
+    println_stderr_always!("    2 {} ({})", target.username, target.name);
+    loop {
+        print_stderr!("Which username would you like to keep (1 or 2)? ");
+        let mut line = String::new();
+        match answer::read_line(&mut line) {
+            Ok(0) => {
+                println_err!("I couldn't read your answer, so I didn't change anything.");
+                return Err(ExitCode::Error);
+            }
+            Ok(_) => {}
+            Err(err) => {
+                println_err!("I couldn't read your answer (reason: {:?}), so I didn't change \
+                              anything.",
+                             err);
+                return Err(ExitCode::Error);
+            }
+        }
+
+        match line.trim() {
+            "1" => return Ok(source.username.clone()),
+            "2" => return Ok(target.username.clone()),
+            _ => println_err!("I need 1 or 2."),
+        }
+    }
+}
+
+/// `target` with the fields of `source` folded into it, and the given username.
+fn merge_passwords(source: &Password,
+                   target: &Password,
+                   username: String,
+                   history_depth: usize)
+                   -> Password {
+    let mut merged = target.clone();
+    merged.merge_from(source.clone(), history_depth);
+    merged.username = username;
+    merged
+}
+
+pub fn callback_exec(matches: &getopts::Matches,
+                     store: &mut password::v2::PasswordStore)
+                     -> Result<(), ExitCode> {
+    check_args(matches)?;
+
+    println_stderr!("");
+    let source = list::search_and_choose_password(
+        store, &matches.free[1], list::WITH_NUMBERS,
+        "Which password would you like to merge from?",
+    )?.clone();
+    let target = list::search_and_choose_password(
+        store, &matches.free[2], list::WITH_NUMBERS,
+        "Which password would you like to merge into?",
+    )?.clone();
+
+    if source.name == target.name {
+        println_err!("Woops, \"{}\" can't be merged with itself.", source.name);
+        return Err(ExitCode::Error);
+    }
+
+    let username = if usernames_conflict(&source, &target) {
+        ask_username(&source, &target)?
+    } else if target.username.is_empty() {
+        source.username.clone()
+    } else {
+        target.username.clone()
+    };
+    let merged = merge_passwords(&source, &target, username, store.history_depth());
+
+    // Both changes are applied in memory at once, the file is only written afterwards.
+    let target_index = store.index_of(&target.name).ok_or(ExitCode::Error)?;
+    let source_index = store.index_of(&source.name).ok_or(ExitCode::Error)?;
+    store.replace_and_delete(vec![(target_index, merged)], vec![source_index]);
+
+    println_ok!("Done! I've merged \"{}\" into \"{}\".", source.name, target.name);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{merge_passwords, usernames_conflict};
+    use password::v2::Password;
+    use safe_string::SafeString;
+
+    fn password(name: &str, username: &str, password: &str, created_at: u32) -> Password {
+        Password {
+            created_at: created_at,
+            updated_at: created_at + 100,
+            ..Password::new(name.to_owned(),
+                            username.to_owned(),
+                            SafeString::new(password.to_owned()))
+        }
+    }
+
+    #[test]
+    fn merge_keeps_the_target_password() {
+        let mut source = password("old-youtube", "me", "old", 10);
+        source.notes = Some(SafeString::new("recovery codes".to_owned()));
+        let target = password("YouTube", "", "new", 50);
+
+        let merged = merge_passwords(&source, &target, "me".to_owned(), 5);
+        assert_eq!(merged.name, "YouTube");
+        assert_eq!(merged.username, "me");
+        assert_eq!(merged.password.expose(), "new");
+        assert_eq!(merged.history.len(), 1);
+        assert_eq!(merged.history[0].password.expose(), "old");
+        assert_eq!(merged.notes.unwrap().expose(), "recovery codes");
+        assert_eq!(merged.created_at, 10);
+        assert_eq!(merged.updated_at, 150);
+    }
+
+    #[test]
+    fn only_different_usernames_conflict() {
+        let a = password("a", "me@example.com", "x", 0);
+        assert!(!usernames_conflict(&a, &password("b", "ME@example.com", "x", 0)));
+        assert!(!usernames_conflict(&a, &password("b", "", "x", 0)));
+        assert!(usernames_conflict(&a, &password("b", "you@example.com", "x", 0)));
+    }
+}
@@ -14,8 +14,10 @@
 
 pub mod get;
 pub mod add;
+pub mod add_or_update;
 pub mod delete;
 pub mod generate;
+pub mod gen;
 pub mod list;
 pub mod regenerate;
 pub mod export;
@@ -24,3 +26,29 @@ pub mod rename;
 pub mod transfer;
 pub mod change;
 pub mod uninstall;
+pub mod edit;
+pub mod note;
+pub mod doctor;
+pub mod tag;
+pub mod favorite;
+pub mod history;
+pub mod import;
+pub mod undo;
+pub mod dedupe;
+pub mod merge;
+pub mod clear_all;
+pub mod clear;
+pub mod archive;
+pub mod unarchive;
+pub mod config;
+pub mod completions;
+pub mod man;
+pub mod batch;
+pub mod profile;
+pub mod restore;
+pub mod set_kdf;
+pub mod kdf_benchmark;
+pub mod rekey;
+pub mod init;
+#[cfg(test)]
+pub mod harness;
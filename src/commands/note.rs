@@ -0,0 +1,139 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use exit_code::ExitCode;
+use getopts;
+use password;
+use ffi;
+use list;
+use safe_string::SafeString;
+use help::Help;
+use std::io::{stdin, Read, Write};
+
+pub fn callback_help() -> Help {
+    Help::new()
+        .usage(&["rooster note -h",
+                 "rooster note <query>",
+                 "rooster note <query> --set",
+                 "rooster note <query> --clear"])
+        .text(&["Notes are free-form text attached to a password, like security questions or",
+                "recovery codes. They may span several lines. With --set, the note is read from",
+                "standard input until the end of input (Ctrl+D)."])
+        .examples(&["rooster note youtube",
+                    "rooster note youtube --set",
+                    "rooster note youtube --set < recovery-codes.txt",
+                    "rooster note youtube --clear"])
+}
+
+pub fn check_args(matches: &getopts::Matches) -> Result<(), ExitCode> {
+    if matches.free.len() < 2 {
+        println_err!("Woops, seems like the app name is missing here. For help, try:");
+        println_err!("    rooster note -h");
+        return Err(ExitCode::Usage);
+    }
+
+    if matches.opt_present("set") && matches.opt_present("clear") {
+        println_err!("Woops, I can't set and clear the note at the same time. For help, try:");
+        println_err!("    rooster note -h");
+        return Err(ExitCode::Usage);
+    }
+
+    Ok(())
+}
+
+/// Prints notes to stdout, keeping their line breaks.
+pub fn print_notes(notes: &SafeString) {
+    println_stdout!("{}", notes.expose());
+}
+
+/// Whether the note is read from stdin, which `--set` does.
+pub fn reads_stdin(matches: &getopts::Matches) -> bool {
+    matches.opt_present("set")
+}
+
+fn read_notes_from_stdin() -> Result<SafeString, ExitCode> {
+    println_stderr_always!("Type your note, then press Ctrl+D on an empty line when you're done:");
+
+    let mut notes = SafeString::new(String::new());
+    stdin().read_to_string(&mut notes.inner_mut()).map_err(|err| {
+        println_err!("I couldn't read the note (reason: {:?}).", err);
+        ExitCode::Error
+    })?;
+
+    let len = notes.expose().trim_end_matches(|c| c == '\n' || c == '\r').len();
+    notes.inner_mut().truncate(len);
+    Ok(notes)
+}
+
+fn set_notes(store: &mut password::v2::PasswordStore,
+             app_name: &str,
+             notes: Option<SafeString>)
+             -> Result<(), ExitCode> {
+    store.change_password(app_name,
+                          &|old_password: password::v2::Password| {
+        password::v2::Password {
+            notes: notes.clone(),
+            updated_at: ffi::time(),
+            ..old_password
+        }
+    }).map_err(|err| {
+        println_err!("Woops, I couldn't save the note (reason: {:?}).", err);
+        ExitCode::from_password_error(&err)
+    })
+}
+
+pub fn callback_exec(matches: &getopts::Matches,
+                     store: &mut password::v2::PasswordStore)
+                     -> Result<(), ExitCode> {
+    check_args(matches)?;
+
+    let query = &matches.free[1];
+
+    println_stderr!("");
+    let password = list::search_and_choose_password(
+        store, query, list::WITH_NUMBERS,
+        "Which password's note are you interested in?",
+    )?.clone();
+
+    if matches.opt_present("clear") {
+        set_notes(store, &password.name, None)?;
+        println_ok!("Done! I've removed the note for \"{}\".", password.name);
+        return Ok(());
+    }
+
+    if matches.opt_present("set") {
+        let notes = read_notes_from_stdin()?;
+        if notes.expose().trim().is_empty() {
+            println_err!("The note is empty. To remove a note, use --clear.");
+            return Err(ExitCode::Error);
+        }
+        set_notes(store, &password.name, Some(notes))?;
+        println_ok!("Done! I've saved the note for \"{}\".", password.name);
+        return Ok(());
+    }
+
+    match password.notes {
+        Some(ref notes) => {
+            println_requested!("Here is the note for {}:", password.name);
+            print_notes(notes);
+        }
+        None => {
+            println_stderr!("There is no note for {}. Add one with `rooster note '{}' --set`.",
+                            password.name,
+                            password.name);
+        }
+    }
+
+    Ok(())
+}
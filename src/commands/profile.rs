@@ -0,0 +1,119 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+use exit_code::ExitCode;
+use getopts;
+use profiles;
+use help::Help;
+use std::env;
+use std::io::Write;
+
+pub fn callback_help() -> Help {
+    let profiles_path = profiles::profiles_path()
+        .map_or("~/.config/rooster/profiles.toml".to_owned(),
+                |path| path.display().to_string());
+    Help::new()
+        .usage(&["rooster profile -h",
+                 "rooster profile [list]",
+                 "rooster profile add <name> <path>",
+                 "rooster profile remove <name>"])
+        .text(&["Profiles give names to password files, to switch between them with --profile",
+                &format!("instead of $ROOSTER_FILE. They are kept in {}.", profiles_path)])
+        .text(&["The password file of a profile doesn't need to exist yet: the first command run",
+                "with --profile offers to create it."])
+        .examples(&["rooster profile add work ~/work/.passwords.rooster",
+                    "rooster --profile work get vpn",
+                    "rooster profile remove work"])
+}
+
+/// Relative paths are taken from the current directory, since profiles are used from anywhere.
+fn absolute_path(path: &str) -> Result<String, ExitCode> {
+    let path = match (path.starts_with("~/"), env::home_dir()) {
+        (true, Some(home)) => home.join(&path[2..]),
+        _ => env::current_dir().map_err(|_| ExitCode::Error)?.join(path),
+    };
+    path.into_os_string().into_string().map_err(|_| {
+        println_err!("Woops, the path of a profile must be valid UTF-8.");
+        ExitCode::Usage
+    })
+}
+
+fn load() -> Result<profiles::Profiles, ExitCode> {
+    profiles::load().map_err(|err| {
+        println_err!("Woops, {}.", err);
+        ExitCode::Io
+    })
+}
+
+fn save(profiles: &profiles::Profiles) -> Result<(), ExitCode> {
+    profiles::save(profiles).map_err(|err| {
+        println_err!("Woops, {}.", err);
+        ExitCode::Io
+    })
+}
+
+fn usage_error() -> Result<(), ExitCode> {
+    println_err!("Woops, that's not how profiles are managed. For help, try:");
+    println_err!("    rooster profile -h");
+    Err(ExitCode::Usage)
+}
+
+pub fn callback_exec(matches: &getopts::Matches) -> Result<(), ExitCode> {
+    let args: Vec<&str> = matches.free[1..].iter().map(|arg| arg.as_str()).collect();
+    match args.as_slice() {
+        [] | ["list"] => {
+            let profiles = load()?;
+            if profiles.entries().is_empty() {
+                println_stderr!("There are no profiles yet. Add one with `rooster profile add \
+                                 <name> <path>`.");
+            }
+            for &(ref name, ref path) in profiles.entries().iter() {
+                println_stdout!("{}\t{}", name, path);
+            }
+            Ok(())
+        }
+        ["add", name, path] => {
+            profiles::check_name(name).map_err(|err| {
+                println_err!("Woops, {}.", err);
+                ExitCode::Usage
+            })?;
+            let path = absolute_path(path)?;
+            let mut profiles = load()?;
+            let replaced = profiles.get(name).is_some();
+            profiles.set(name, &path);
+            save(&profiles)?;
+            if replaced {
+                println_ok!("Done! The {} profile now uses {}.", name, path);
+            } else {
+                println_ok!("Done! Use the {} profile with `rooster --profile {} ...`.",
+                            name,
+                            name);
+            }
+            Ok(())
+        }
+        ["remove", name] => {
+            let mut profiles = load()?;
+            if !profiles.remove(name) {
+                println_err!("Woops, there is no profile called \"{}\".", name);
+                return Err(ExitCode::Error);
+            }
+            save(&profiles)?;
+            println_ok!("Done! I've removed the {} profile. Its password file is still there.",
+                        name);
+            Ok(())
+        }
+        _ => usage_error(),
+    }
+}
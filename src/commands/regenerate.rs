@@ -12,78 +12,259 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use exit_code::ExitCode;
+use answer;
 use getopts;
 use ffi;
 use list;
 use password;
-use generate::{PasswordSpec, generate_hard_password};
+use super::generate::{check_generation_args, generator_from_matches,
+                      new_password_from_matches};
 use clip;
+use safe_string::SafeString;
+use timestamp;
+use help::Help;
 use std::io::Write;
 
-pub fn callback_help() {
-    println!("Usage:");
-    println!("    rooster regenerate -h");
-    println!("    rooster regenerate <query>");
-    println!("");
-    println!("Examples:");
-    println!("    rooster regenerate youtube");
-    println!("    rooster regenerate ytb");
+pub fn callback_help() -> Help {
+    Help::new()
+        .usage(&["rooster regenerate -h",
+                 "rooster regenerate <query>",
+                 "rooster regenerate <query> [--charset <chars>] [--exclude-chars <chars>] \
+                  [--exclude-similar]",
+                 "rooster regenerate <query> --similar-to <other_query>",
+                 "rooster regenerate <query> --words <n> [--separator <sep>] [--capitalize] \
+                  [--add-digit]",
+                 "rooster regenerate <query> --pronounceable [-l <length>] [--add-digit]",
+                 "rooster regenerate <query> --pin [-l <length>]",
+                 "rooster regenerate --all [--confirm-each]",
+                 "rooster regenerate [<query>] --older-than <duration> [--confirm-each]"])
+        .examples(&["rooster regenerate youtube",
+                    "rooster regenerate ytb",
+                    "rooster regenerate github --similar-to github",
+                    "rooster regenerate bank --exclude-chars \"\\\"'\\\\&\""])
+        .text(&["The options to choose the characters are the same as for `rooster generate`,",
+                "see `rooster generate -h`."])
+        .text(&["With --all, every password is regenerated. With --older-than, only those that",
+                "haven't changed for that long are, like 90d, 12w, 6m or 1y, and a query narrows",
+                "them down to the apps that match it. The new passwords are saved all at once, and",
+                "you get a list of the apps to update. --confirm-each asks about every password."])
+        .commands(&["rooster regenerate --older-than 1y",
+                    "rooster regenerate work --older-than 6m --words 5 --confirm-each"])
 }
 
-pub fn check_args(matches: &getopts::Matches) -> Result<(), i32> {
+/// Whether to regenerate several passwords at once, rather than one picked by a query.
+fn is_bulk(matches: &getopts::Matches) -> bool {
+    matches.opt_present("all") || matches.opt_present("older-than")
+}
+
+pub fn check_args(matches: &getopts::Matches) -> Result<(), ExitCode> {
+    if is_bulk(matches) {
+        return check_bulk_args(matches);
+    }
+
+    if matches.opt_present("confirm-each") {
+        println_err!("Woops, --confirm-each only goes with --all or --older-than. For help, try:");
+        println_err!("    rooster regenerate -h");
+        return Err(ExitCode::Usage);
+    }
+
     if matches.free.len() < 2 {
         println_err!("Woops, seems like the app name is missing here. For help, try:");
         println_err!("    rooster regenerate -h");
-        return Err(1);
+        return Err(ExitCode::Usage);
+    }
+
+    check_generation_args(matches, "regenerate")?;
+
+    Ok(())
+}
+
+fn check_bulk_args(matches: &getopts::Matches) -> Result<(), ExitCode> {
+    let fail = |message: &str| -> Result<(), ExitCode> {
+        println_err!("Woops, {}. For help, try:", message);
+        println_err!("    rooster regenerate -h");
+        Err(ExitCode::Usage)
+    };
+
+    if matches.opt_present("all") && matches.opt_present("older-than") {
+        return fail("--all and --older-than can't be used together");
+    }
+    if matches.opt_present("all") && matches.free.len() > 1 {
+        return fail("--all regenerates every password, so it can't be used with a query");
+    }
+    if matches.free.len() > 2 {
+        return fail("only one query can be used with --older-than");
+    }
+    if matches.opt_present("similar-to") {
+        return fail("--similar-to only regenerates one password at a time");
+    }
+    if let Some(duration) = matches.opt_str("older-than") {
+        if let Err(err) = timestamp::parse_duration(&duration) {
+            return fail(&err);
+        }
+    }
+
+    check_generation_args(matches, "regenerate")
+}
+
+/// The passwords to regenerate in bulk, sorted like `rooster list` sorts them.
+fn select_passwords(matches: &getopts::Matches,
+                    store: &password::v2::PasswordStore)
+                    -> Vec<password::v2::Password> {
+    let passwords = match matches.free.get(1) {
+        Some(query) => store.search_passwords(query),
+        None => {
+            store.get_all_passwords()
+                .into_iter()
+                .filter(|p| !p.archived || store.includes_archived())
+                .collect()
+        }
+    };
+
+    // Checked by `check_bulk_args`.
+    let max_age = matches.opt_str("older-than")
+        .map(|duration| timestamp::parse_duration(&duration).unwrap());
+    let now = ffi::time() as i64;
+
+    passwords.into_iter()
+        .filter(|p| max_age.map_or(true, |max_age| now - (p.updated_at as i64) > max_age))
+        .cloned()
+        .collect()
+}
+
+/// Reads a yes or no answer, `Err` if there is nothing to read.
+fn ask_yes_no(question: &str) -> Result<bool, ExitCode> {
+    loop {
+        print_stderr!("{} (y/n)? ", question);
+        let mut line = String::new();
+        match answer::read_line(&mut line) {
+            Ok(0) => {
+                println_err!("I couldn't read your answer, so I didn't change anything.");
+                return Err(ExitCode::Error);
+            }
+            Ok(_) if line.starts_with('y') => return Ok(true),
+            Ok(_) if line.starts_with('n') => return Ok(false),
+            Ok(_) => println_stderr!("I didn't get that."),
+            Err(err) => {
+                println_err!("I couldn't read your answer (reason: {:?}), so I didn't change \
+                              anything.",
+                             err);
+                return Err(ExitCode::Error);
+            }
+        }
+    }
+}
+
+fn regenerate_several(matches: &getopts::Matches,
+                      store: &mut password::v2::PasswordStore)
+                      -> Result<(), ExitCode> {
+    let mut passwords = select_passwords(matches, store);
+    if passwords.is_empty() {
+        println_stderr!("There are no passwords to regenerate.");
+        return Ok(());
+    }
+
+    // Refuse weak passwords before asking about each of them.
+    let generator = generator_from_matches(matches)?;
+
+    println_stderr!("");
+    if matches.opt_present("confirm-each") {
+        let mut confirmed = Vec::new();
+        for password in passwords.into_iter() {
+            let question = format!("Regenerate {} ({}), last changed on {}",
+                                   password.name,
+                                   password.username,
+                                   timestamp::to_local_string(password.updated_at));
+            if ask_yes_no(&question)? {
+                confirmed.push(password);
+            }
+        }
+        passwords = confirmed;
+        if passwords.is_empty() {
+            println_stderr!("Alright, I didn't change anything.");
+            return Ok(());
+        }
+    } else {
+        println_stderr_always!("I'm about to regenerate these {} password(s):", passwords.len());
+        for password in passwords.iter() {
+            println_stderr_always!("    {} ({}), last changed on {}",
+                                   password.name,
+                                   password.username,
+                                   timestamp::to_local_string(password.updated_at));
+        }
+        if !ask_yes_no("Regenerate them")? {
+            println_stderr!("Alright, I didn't change anything.");
+            return Ok(());
+        }
     }
 
+    // Everything is generated before anything changes, and the file is only written once the
+    // callback returns, so a failure can't leave some passwords rotated and others not.
+    let mut new_passwords: Vec<SafeString> = Vec::with_capacity(passwords.len());
+    for _ in passwords.iter() {
+        new_passwords.push(generator.generate()?);
+    }
+
+    let now = ffi::time();
+    for (password, new_password) in passwords.iter().zip(new_passwords.into_iter()) {
+        let change_result = store.change_password(&password.name,
+                                                  &|old_password: password::v2::Password| {
+            password::v2::Password {
+                password: new_password.clone(),
+                updated_at: now,
+                ..old_password
+            }
+        });
+        if let Err(err) = change_result {
+            println_err!("Woops, I couldn't save the new password for {} (reason: {:?}), so I \
+                          didn't change anything.",
+                         password.name,
+                         err);
+            return Err(ExitCode::from_password_error(&err));
+        }
+    }
+
+    println_ok!("Done! I've regenerated {} password(s). Now log in to these apps and set their \
+                 new password, which you can get with `rooster get <app>`:",
+                passwords.len());
+    for password in passwords.iter() {
+        println_stderr!("    [ ] {} ({})", password.name, password.username);
+    }
     Ok(())
 }
 
 pub fn callback_exec(matches: &getopts::Matches,
                      store: &mut password::v2::PasswordStore)
-                     -> Result<(), i32> {
+                     -> Result<(), ExitCode> {
     check_args(matches)?;
 
+    if is_bulk(matches) {
+        return regenerate_several(matches, store);
+    }
+
     let query = &matches.free[1];
 
     println_stderr!("");
     let password = list::search_and_choose_password(
         store, query, list::WITH_NUMBERS,
         "Which password would you like to regenerate?",
-    ).ok_or(1)?.clone();
+    )?.clone();
 
-    let password_spec = PasswordSpec::from_matches(matches);
-
-    let password_as_string = match password_spec {
-        None => {
-            return Err(1);
-        }
-        Some(spec) => {
-            match generate_hard_password(spec.alnum, spec.len) {
-                Ok(password_as_string) => password_as_string,
-                Err(io_err) => {
-                    println_stderr!("Woops, I could not generate the password (reason: {:?}).",
-                                    io_err);
-                    return Err(1);
-                }
-            }
-        }
-    };
+    let password_as_string = new_password_from_matches(matches, store)?;
 
     let change_result = store.change_password(&password.name,
                                               &|old_password: password::v2::Password| {
         password::v2::Password {
-            name: old_password.name.clone(),
-            username: old_password.username.clone(),
             password: password_as_string.clone(),
-            created_at: old_password.created_at,
             updated_at: ffi::time(),
+            ..old_password
         }
     });
 
     match change_result {
         Ok(_) => {
+            let password = store.get_password(&password.name).unwrap_or(password);
             let show = matches.opt_present("show");
             clip::confirm_password_retrieved(show, &password);
             Ok(())
@@ -91,7 +272,59 @@ pub fn callback_exec(matches: &getopts::Matches,
         Err(err) => {
             println_err!("Woops, I couldn't save the new password (reason: {:?}).",
                          err);
-            Err(1)
+            Err(ExitCode::from_password_error(&err))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::callback_exec;
+    use commands::harness::{run, store};
+    use exit_code::ExitCode;
+
+    #[test]
+    fn every_password_is_regenerated_with_all() {
+        let mut store = store(&[("GitHub", "me", "hunter2"), ("YouTube", "me", "hunter3")]);
+        let (result, output) = run(callback_exec, &["regenerate", "--all"], &["y"], &mut store);
+        assert_eq!(result, Ok(()));
+        for &(name, old) in &[("GitHub", "hunter2"), ("YouTube", "hunter3")] {
+            let password = store.get_password(name).unwrap();
+            assert_ne!(password.password.expose(), old);
+            assert_eq!(password.history[0].password.expose(), old);
+            assert!(output.stderr.contains(&format!("[ ] {} (me)", name)), "{}", output.stderr);
         }
     }
+
+    #[test]
+    fn only_confirmed_passwords_are_regenerated() {
+        let mut store = store(&[("GitHub", "me", "hunter2"), ("YouTube", "me", "hunter3")]);
+        let (result, _) = run(callback_exec,
+                              &["regenerate", "--all", "--confirm-each"],
+                              &["n", "y"],
+                              &mut store);
+        assert_eq!(result, Ok(()));
+        assert_eq!(store.get_password("GitHub").unwrap().password.expose(), "hunter2");
+        assert_ne!(store.get_password("YouTube").unwrap().password.expose(), "hunter3");
+    }
+
+    #[test]
+    fn recent_passwords_are_kept_with_older_than() {
+        let mut store = store(&[("GitHub", "me", "hunter2")]);
+        let (result, output) = run(callback_exec,
+                                   &["regenerate", "--older-than", "90d"],
+                                   &[],
+                                   &mut store);
+        assert_eq!(result, Ok(()));
+        assert!(output.stderr.contains("no passwords to regenerate"), "{}", output.stderr);
+        assert_eq!(store.get_password("GitHub").unwrap().password.expose(), "hunter2");
+    }
+
+    #[test]
+    fn all_takes_no_query() {
+        let mut store = store(&[("GitHub", "me", "hunter2")]);
+        let (result, _) = run(callback_exec, &["regenerate", "GitHub", "--all"], &[], &mut store);
+        assert_eq!(result, Err(ExitCode::Usage));
+        assert_eq!(store.get_password("GitHub").unwrap().password.expose(), "hunter2");
+    }
 }
@@ -0,0 +1,82 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+use exit_code::ExitCode;
+use getopts;
+use password;
+use commands::set_master_password::current_factors;
+use crypto::digest::Digest;
+use crypto::sha2::Sha256;
+use {ask_master_password, get_password_file_path};
+use help::Help;
+use std::fs;
+use std::io::{Result as IoResult, Write};
+
+pub fn callback_help() -> Help {
+    Help::new()
+        .usage(&["rooster rekey -h",
+                 "rooster rekey"])
+        .text(&["Derives a new key for your password file from a new random salt, and encrypts",
+                "all your passwords again with it. Your master password, which is asked again,",
+                "and your key file or YubiKey stay the same."])
+        .text(&["The SHA-256 fingerprints of the file before and after are shown, so you can check",
+                "that it really changed, like after copying it to a machine you don't fully \
+                 trust."])
+}
+
+/// The SHA-256 hash of the whole password file, to tell its versions apart.
+pub fn file_fingerprint(path: &str) -> IoResult<String> {
+    let mut hasher = Sha256::new();
+    hasher.input(&fs::read(path)?);
+    Ok(hasher.result_str())
+}
+
+/// Shows the fingerprint of the password file once it was saved with the new key.
+pub fn print_new_fingerprint(path: &str) {
+    match file_fingerprint(path) {
+        Ok(fingerprint) => println_stdout!("New fingerprint: {}", fingerprint),
+        Err(err) => println_err!("Woops, I couldn't read the password file (reason: {}).", err),
+    }
+}
+
+pub fn callback_exec(matches: &getopts::Matches,
+                     store: &mut password::v2::PasswordStore)
+                     -> Result<(), ExitCode> {
+    let path = get_password_file_path(matches)?;
+    let fingerprint = file_fingerprint(&path).map_err(|err| {
+        println_err!("Woops, I couldn't read the password file (reason: {}).", err);
+        ExitCode::Io
+    })?;
+
+    // The master password isn't kept after opening the file, and the new key needs it.
+    let master_password = ask_master_password().map_err(|err| {
+        println_err!("Woops, I could not read your master password (reason: {}).", err);
+        ExitCode::Error
+    })?;
+    let factors = current_factors(store);
+    store.rekey(master_password.expose(), &factors).map_err(|err| {
+        match err {
+            password::PasswordError::WrongMasterPasswordError => {
+                println_err!("Woops, that's not the master password your file was opened with.")
+            }
+            ref err => println_err!("Woops, I couldn't derive a new key (reason: {:?}).", err),
+        }
+        ExitCode::from_password_error(&err)
+    })?;
+
+    println_ok!("Done! Your password file has a new salt and a new key.");
+    println_stdout!("Old fingerprint: {}", fingerprint);
+    Ok(())
+}
@@ -12,37 +12,123 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use exit_code::ExitCode;
+use answer;
 use getopts;
 use password;
-use ffi;
+use password::v2::RenameCollision;
 use list;
+use timestamp;
+use help::Help;
 use std::io::Write;
 
-pub fn callback_help() {
-    println!("Usage:");
-    println!("    rooster rename -h");
-    println!("    rooster rename <query> <new_app_name>");
-    println!("");
-    println!("Examples:");
-    println!("    rooster rename youtube Dailymotion");
-    println!("    rooster rename ytb Dailymotion");
+pub fn callback_help() -> Help {
+    Help::new()
+        .usage(&["rooster rename -h",
+                 "rooster rename <query> <new_app_name> [--force]",
+                 "rooster rename --swap <query> <query>"])
+        .text(&["With --swap, the two passwords exchange their names. Each query must match a",
+                "single password."])
+        .text(&["If another password already has the new name, you can choose to keep it and",
+                "stop, to overwrite it (its value goes to the history of the renamed password), or",
+                "to merge the two. --force overwrites it without asking."])
+        .examples(&["rooster rename youtube Dailymotion",
+                    "rooster rename ytb Dailymotion",
+                    "rooster rename --swap 'Slack (work)' 'Slack (personal)'"])
 }
 
-pub fn check_args(matches: &getopts::Matches) -> Result<(), i32> {
+pub fn check_args(matches: &getopts::Matches) -> Result<(), ExitCode> {
     if matches.free.len() < 3 {
         println_err!("Woops, seems like the app name is missing here. For help, try:");
         println_err!("    rooster rename -h");
-        return Err(1);
+        return Err(ExitCode::Usage);
     }
 
     Ok(())
 }
 
+fn swap(matches: &getopts::Matches,
+        store: &mut password::v2::PasswordStore)
+        -> Result<(), ExitCode> {
+    // Swapping the wrong passwords is easy to miss, so there is no choosing between several.
+    let a = list::resolve_unique_password(store, &matches.free[1], |_| true)?.name.clone();
+    let b = list::resolve_unique_password(store, &matches.free[2], |_| true)?.name.clone();
+    if a == b {
+        println_err!("Woops, both queries match \"{}\". I need two different passwords.", a);
+        return Err(ExitCode::Error);
+    }
+
+    println_stderr_always!("These passwords will exchange their names:");
+    for name in [&a, &b].iter() {
+        let password = store.get_password(name).ok_or(ExitCode::Error)?;
+        println_stderr_always!("    {}: {}, updated on {}",
+                               password.name,
+                               password.username,
+                               timestamp::to_local_string(password.updated_at));
+    }
+    print_stderr!("Swap them (y/n)? ");
+
+    let mut line = String::new();
+    answer::read_line(&mut line).map_err(|err| {
+        println_err!("I couldn't read that (reason: {:?}).", err);
+        ExitCode::Error
+    })?;
+    if !line.starts_with('y') {
+        println_stderr!("OK, I didn't change anything.");
+        return Ok(());
+    }
+
+    store.swap_names(&a, &b).map_err(|err| {
+        println_err!("Woops, I couldn't swap the names (reason: {:?}).", err);
+        ExitCode::from_password_error(&err)
+    })?;
+    println_ok!("Done! {} and {} have swapped names.", a, b);
+    Ok(())
+}
+
+fn parse_collision_choice(answer: &str) -> Option<RenameCollision> {
+    match answer.trim() {
+        "a" | "abort" | "" => Some(RenameCollision::Fail),
+        "o" | "overwrite" => Some(RenameCollision::Overwrite),
+        "m" | "merge" => Some(RenameCollision::Merge),
+        _ => None,
+    }
+}
+
+/// What to do with the password that already has the new name.
+fn ask_collision_choice(existing: &password::v2::Password) -> Result<RenameCollision, ExitCode> {
+    println_stderr!("There is already a password named {}: {}, updated on {}.",
+                    existing.name,
+                    existing.username,
+                    timestamp::to_local_string(existing.updated_at));
+    loop {
+        print_stderr!("Type a to abort, o to overwrite it, or m to merge the two: ");
+        let mut line = String::new();
+        match answer::read_line(&mut line) {
+            Ok(0) => return Ok(RenameCollision::Fail),
+            Ok(_) => {}
+            Err(err) => {
+                println_err!("I couldn't read your answer (reason: {:?}).", err);
+                return Err(ExitCode::Error);
+            }
+        }
+
+        match parse_collision_choice(line.as_str()) {
+            Some(choice) => return Ok(choice),
+            None => println_err!("I need a, o or m."),
+        }
+    }
+}
+
 pub fn callback_exec(matches: &getopts::Matches,
                      store: &mut password::v2::PasswordStore)
-                     -> Result<(), i32> {
+                     -> Result<(), ExitCode> {
     check_args(matches)?;
 
+    if matches.opt_present("swap") {
+        return swap(matches, store);
+    }
+
     let query = &matches.free[1];
     let new_name = &matches.free[2];
 
@@ -50,29 +136,103 @@ pub fn callback_exec(matches: &getopts::Matches,
     let password = list::search_and_choose_password(
         store, query, list::WITH_NUMBERS,
         "Which password would you like to rename?",
-    ).ok_or(1)?.clone();
+    )?.clone();
     println_stderr!("");
 
-    let change_result = store.change_password(&password.name,
-                                              &|old_password: password::v2::Password| {
-        password::v2::Password {
-            name: new_name.clone(),
-            username: old_password.username.clone(),
-            password: old_password.password.clone(),
-            created_at: old_password.created_at,
-            updated_at: ffi::time(),
-        }
-    });
+    let existing = store.index_of(new_name)
+        .filter(|&index| store.index_of(&password.name) != Some(index))
+        .map(|index| store.password_at(index).clone());
+    let on_collision = match existing {
+        Some(_) if matches.opt_present("force") => RenameCollision::Overwrite,
+        Some(ref existing) => ask_collision_choice(existing)?,
+        None => RenameCollision::Fail,
+    };
+    if existing.is_some() && on_collision == RenameCollision::Fail {
+        println_stderr!("OK, I didn't change anything.");
+        return Ok(());
+    }
 
-    match change_result {
+    match store.rename_password(&password.name, new_name, on_collision) {
         Ok(_) => {
-            println_ok!("Done! I've renamed {} to {}", password.name, new_name);
+            match (on_collision, existing.is_some()) {
+                (RenameCollision::Overwrite, true) => {
+                    println_ok!("Done! I've renamed {} to {}, replacing the password that had \
+                                 that name.",
+                                password.name,
+                                new_name)
+                }
+                (RenameCollision::Merge, true) => {
+                    println_ok!("Done! I've renamed {} to {} and merged it with the password \
+                                 that had that name.",
+                                password.name,
+                                new_name)
+                }
+                _ => println_ok!("Done! I've renamed {} to {}", password.name, new_name),
+            }
             Ok(())
         }
         Err(err) => {
             println_err!("Woops, I couldn't save the new app name (reason: {:?}).",
                          err);
-            Err(1)
+            Err(ExitCode::from_password_error(&err))
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::callback_exec;
+    use commands::harness::{run, store};
+    use exit_code::ExitCode;
+
+    #[test]
+    fn passwords_are_renamed() {
+        let mut store = store(&[("YouTube", "me@example.com", "hunter2")]);
+        let (result, output) = run(callback_exec,
+                                   &["rename", "youtube", "Dailymotion"],
+                                   &[],
+                                   &mut store);
+        assert_eq!(result, Ok(()));
+        assert!(output.stderr.contains("I've renamed YouTube to Dailymotion"));
+        assert!(!store.has_password("YouTube"));
+        assert_eq!(store.get_password("Dailymotion").unwrap().password.expose(), "hunter2");
+    }
+
+    #[test]
+    fn taken_names_are_kept_unless_chosen_otherwise() {
+        let mut store = store(&[("YouTube", "me@example.com", "hunter2"),
+                                ("Dailymotion", "me", "zxcvbn")]);
+        let (result, output) = run(callback_exec,
+                                   &["rename", "youtube", "Dailymotion"],
+                                   &["a"],
+                                   &mut store);
+        assert_eq!(result, Ok(()));
+        assert!(output.stderr.contains("There is already a password named Dailymotion"));
+        assert!(store.has_password("YouTube"));
+        assert_eq!(store.get_password("Dailymotion").unwrap().password.expose(), "zxcvbn");
+
+        let (result, _) = run(callback_exec,
+                              &["rename", "youtube", "Dailymotion", "--force"],
+                              &[],
+                              &mut store);
+        assert_eq!(result, Ok(()));
+        assert!(!store.has_password("YouTube"));
+        assert_eq!(store.get_password("Dailymotion").unwrap().password.expose(), "hunter2");
+    }
+
+    #[test]
+    fn unknown_passwords_are_not_renamed() {
+        let mut store = store(&[("YouTube", "me@example.com", "hunter2")]);
+        let (result, _) = run(callback_exec, &["rename", "gitlab", "GitHub"], &[], &mut store);
+        assert_eq!(result, Err(ExitCode::NotFound));
+        assert!(!store.has_password("GitHub"));
+    }
+
+    #[test]
+    fn the_new_name_is_required() {
+        let mut store = store(&[("YouTube", "me@example.com", "hunter2")]);
+        let (result, output) = run(callback_exec, &["rename", "youtube"], &[], &mut store);
+        assert_eq!(result, Err(ExitCode::Usage));
+        assert!(output.stderr.contains("rooster rename -h"));
+    }
+}
@@ -0,0 +1,162 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+use answer;
+use atomic_file;
+use exit_code::ExitCode;
+use getopts;
+use password;
+use safe_vec::SafeVec;
+use {ask_master_password, get_password_file_path, lock_password_file_for_writing,
+     save_password_file, second_factors};
+use help::Help;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+pub const ROOSTER_NO_BACKUP_ENV_VAR: &'static str = "ROOSTER_NO_BACKUP";
+
+static NO_BACKUP: AtomicBool = AtomicBool::new(false);
+
+pub fn callback_help() -> Help {
+    Help::new()
+        .usage(&["rooster restore -h",
+                 "rooster restore --previous"])
+        .text(&["Every time your password file is saved, the version it replaces is kept next to",
+                "it, like ~/.passwords.rooster.prev. This puts that version back, and keeps the",
+                "current one in its place, so running it again goes back to where you were."])
+        .text(&["Unlike `rooster undo`, this works after any save, even if nothing changed. To not",
+                "keep the previous version, for instance because you have your own backups, use",
+                &format!("--no-backup or set ${}.", ROOSTER_NO_BACKUP_ENV_VAR)])
+        .examples(&["rooster restore --previous"])
+}
+
+/// The previous version is kept next to the password file, like ~/.passwords.rooster.prev.
+pub fn previous_file_path(password_file_path: &str) -> String {
+    format!("{}.prev", password_file_path)
+}
+
+/// Set with --no-backup or $ROOSTER_NO_BACKUP.
+pub fn set_no_backup(no_backup: bool) {
+    NO_BACKUP.store(no_backup, Ordering::SeqCst);
+}
+
+/// Copies the password file as it is on disk to the previous version, before it is replaced.
+pub fn save_previous_version(password_file_path: &str) -> io::Result<()> {
+    if NO_BACKUP.load(Ordering::SeqCst) {
+        return Ok(());
+    }
+    let current = SafeVec::new(fs::read(password_file_path)?);
+    // A brand new password file has no previous version yet.
+    if current.is_empty() {
+        return Ok(());
+    }
+    let previous_path = previous_file_path(password_file_path);
+    atomic_file::write(Path::new(&previous_path),
+                       |file: &mut File| file.write_all(current.expose()))
+}
+
+pub fn check_args(matches: &getopts::Matches) -> Result<(), ExitCode> {
+    if matches.free.len() > 1 || !matches.opt_present("previous") {
+        println_err!("Woops, restore only brings back the previous version, with --previous. \
+                      For help, try:");
+        println_err!("    rooster restore -h");
+        return Err(ExitCode::Usage);
+    }
+    Ok(())
+}
+
+pub fn callback_exec(matches: &getopts::Matches) -> Result<(), ExitCode> {
+    check_args(matches)?;
+
+    let password_file_path = get_password_file_path(matches).map_err(|_| {
+        println_err!("Woops, I could not determine where your password file is.");
+        ExitCode::Error
+    })?;
+    let previous_path = previous_file_path(password_file_path.as_str());
+    let _locked = lock_password_file_for_writing(matches, &password_file_path)?;
+
+    let previous = match fs::read(&previous_path) {
+        Ok(previous) => SafeVec::new(previous),
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => {
+            println_err!("Woops, there is no previous version of your password file at {}.",
+                         previous_path);
+            return Err(ExitCode::NotFound);
+        }
+        Err(err) => {
+            println_err!("Woops, I couldn't read {} (reason: {:?}).", previous_path, err);
+            return Err(ExitCode::Io);
+        }
+    };
+    let current = fs::read(&password_file_path).map(SafeVec::new).map_err(|err| {
+        println_err!("Woops, I couldn't read {} (reason: {:?}).", password_file_path, err);
+        ExitCode::Io
+    })?;
+
+    // Make sure the previous version can be opened before going back to it.
+    let master_password = ask_master_password().map_err(|err| {
+        println_err!("Woops, I could not read your master password (reason: {}).", err);
+        ExitCode::Error
+    })?;
+    let header = password::v2::Header::read(&mut io::Cursor::new(previous.expose())).ok();
+    let factors = second_factors(header.as_ref())?;
+    let store = password::v2::PasswordStore::from_input_with_factors(master_password,
+                                                                     previous.clone(),
+                                                                     &factors)
+        .map_err(|err| {
+            println_err!("Woops, I can't open the previous version with this master password \
+                          (reason: {:?}). Nothing was changed.",
+                         err);
+            match err {
+                // A wrong key sometimes decrypts to garbage instead of failing.
+                password::PasswordError::InvalidJsonError => ExitCode::WrongMasterPassword,
+                _ => ExitCode::from_password_error(&err),
+            }
+        })?;
+
+    println_stderr!("");
+    print_stderr!("Go back to the previous version of your password file, with {} password(s)? \
+                   The current version will become the previous one (y/n) ",
+                  store.get_all_passwords().len());
+    let mut line = String::new();
+    answer::read_line(&mut line).map_err(|err| {
+        println_err!("I couldn't read that (reason: {:?}).", err);
+        ExitCode::Error
+    })?;
+    if !line.starts_with('y') {
+        println_stderr!("OK, I didn't change anything.");
+        return Ok(());
+    }
+
+    // This also keeps the current version as the previous one.
+    save_password_file(&password_file_path, false, |path| {
+        atomic_file::write(path, |file: &mut File| file.write_all(previous.expose()))
+    })?;
+    // Going back and forth needs it, even if other commands don't keep it.
+    if NO_BACKUP.load(Ordering::SeqCst) {
+        if let Err(err) = atomic_file::write(Path::new(&previous_path),
+                                             |file: &mut File| file.write_all(current.expose())) {
+            println_err!("Your password file is back to its previous version, but I couldn't \
+                          keep the one it replaced in {} (reason: {:?}).",
+                         previous_path,
+                         err);
+            return Err(ExitCode::Io);
+        }
+    }
+
+    println_ok!("Done! Your password file is back to its previous version.");
+    Ok(())
+}
@@ -0,0 +1,189 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+use exit_code::ExitCode;
+use getopts;
+use password;
+use password::v2::Kdf;
+use ask_master_password;
+use help::Help;
+use std::io::Write;
+
+pub fn callback_help() -> Help {
+    Help::new()
+        .usage(&["rooster set-kdf -h",
+                 "rooster set-kdf --log-n <n> [--scrypt-r <r>] [--scrypt-p <p>]",
+                 "rooster set-kdf --argon2id [--memory-mib <m>]"])
+        .examples(&["rooster set-kdf --log-n 17",
+                    "rooster set-kdf --log-n 15 --scrypt-r 8 --scrypt-p 2",
+                    "rooster set-kdf --argon2id --memory-mib 256"])
+        .text(&["Changes how the key of your password file is derived from your master password,",
+                "which is what makes guessing the master password slow. Your master password is",
+                "asked again, and stays the same."])
+        .text(&["With --log-n, the key is derived with scrypt, with N = 2^<n>, and r = <r> and",
+                "p = <p> (default: 8 and 1). Opening the file then needs 128 * r * N bytes of",
+                "memory, like 128 MiB for --log-n 17, and takes about twice as long for each step",
+                "of --log-n, and p times as long. Files created before Argon2id use --log-n 12.",
+                &format!("<n> can be at most {}, <r> at most {} and <p> at most {}.",
+                         password::v2::MAX_SCRYPT_LOG2_N,
+                         password::v2::MAX_SCRYPT_R,
+                         password::v2::MAX_SCRYPT_P),
+                "Older versions of Rooster can open scrypt files, but a damaged one is then",
+                "often taken for a wrong master password, which only Argon2id files tell apart."])
+        .text(&["With --argon2id, the key is derived with Argon2id, like for new password files,",
+                "which older versions of Rooster can't open. It uses <m> MiB of memory (default:",
+                &format!("{}, at most {}).",
+                         password::v3::DEFAULT_PARAMS.memory_kib / 1024,
+                         password::v3::MAX_MEMORY_KIB / 1024)])
+        .text(&["To find the parameters that take about a second to open your file on this",
+                "machine, see `rooster kdf-benchmark -h`."])
+}
+
+/// Reads a number option, or gives the default without it.
+fn number_from_matches(matches: &getopts::Matches,
+                       name: &str,
+                       default: u32)
+                       -> Result<u32, ExitCode> {
+    match matches.opt_str(name) {
+        Some(value) => {
+            value.parse::<u32>().map_err(|_| {
+                println_err!("Woops, --{} must be a number, like {}, not \"{}\".",
+                             name,
+                             default,
+                             value);
+                ExitCode::Usage
+            })
+        }
+        None => Ok(default),
+    }
+}
+
+/// The memory scrypt needs, in MiB, even for params too large for `scrypt_memory`.
+fn scrypt_memory_mib(log2_n: u32, r: u32) -> f64 {
+    128.0 * r as f64 * 2f64.powi(log2_n as i32) / (1024.0 * 1024.0)
+}
+
+fn kdf_from_matches(matches: &getopts::Matches) -> Result<Kdf, ExitCode> {
+    let scrypt = ["log-n", "scrypt-r", "scrypt-p"].iter().any(|name| matches.opt_present(name));
+    if matches.opt_present("argon2id") {
+        if scrypt {
+            println_err!("Woops, --argon2id can't be used with --log-n, --scrypt-r or --scrypt-p.");
+            return Err(ExitCode::Usage);
+        }
+        let default_mib = password::v3::DEFAULT_PARAMS.memory_kib / 1024;
+        let memory_mib = number_from_matches(matches, "memory-mib", default_mib)?;
+        let params = password::v3::Argon2Params {
+            memory_kib: memory_mib.saturating_mul(1024),
+            ..password::v3::DEFAULT_PARAMS
+        };
+        if memory_mib == 0 || !params.is_valid() {
+            println_err!("Woops, --memory-mib must be from 1 to {}.",
+                         password::v3::MAX_MEMORY_KIB / 1024);
+            return Err(ExitCode::Usage);
+        }
+        return Ok(Kdf::Argon2id(params));
+    }
+    if matches.opt_present("memory-mib") {
+        println_err!("Woops, --memory-mib only goes with --argon2id.");
+        return Err(ExitCode::Usage);
+    }
+    if !matches.opt_present("log-n") {
+        println_err!("Woops, use --log-n for scrypt, or --argon2id. For help, try:");
+        println_err!("    rooster set-kdf -h");
+        return Err(ExitCode::Usage);
+    }
+
+    let log2_n = number_from_matches(matches, "log-n", 17)?;
+    let r = number_from_matches(matches, "scrypt-r", 8)?;
+    let p = number_from_matches(matches, "scrypt-p", 1)?;
+    if log2_n > password::v2::MAX_SCRYPT_LOG2_N as u32 || r > password::v2::MAX_SCRYPT_R {
+        println_err!("Woops, with --log-n {} and --scrypt-r {}, opening your password file would \
+                      need {:.0} MiB of memory. Use --log-n {} or less, and --scrypt-r {} or \
+                      less.",
+                     log2_n,
+                     r,
+                     scrypt_memory_mib(log2_n, r),
+                     password::v2::MAX_SCRYPT_LOG2_N,
+                     password::v2::MAX_SCRYPT_R);
+        return Err(ExitCode::Usage);
+    }
+    if p > password::v2::MAX_SCRYPT_P {
+        println_err!("Woops, with --scrypt-p {}, opening your password file would take {} times as \
+                      long as with --scrypt-p 1. Use --scrypt-p {} or less.",
+                     p,
+                     p,
+                     password::v2::MAX_SCRYPT_P);
+        return Err(ExitCode::Usage);
+    }
+    let log2_n = log2_n as u8;
+    if !password::v2::are_valid_scrypt_params(log2_n, r, p) {
+        println_err!("Woops, scrypt can't use --log-n {}, --scrypt-r {} and --scrypt-p {}. They \
+                      must be at least 1, and --log-n less than 16 times --scrypt-r.",
+                     log2_n,
+                     r,
+                     p);
+        return Err(ExitCode::Usage);
+    }
+    Ok(Kdf::Scrypt {
+        log2_n: log2_n,
+        r: r,
+        p: p,
+    })
+}
+
+pub fn check_args(matches: &getopts::Matches) -> Result<(), ExitCode> {
+    kdf_from_matches(matches).map(|_| ())
+}
+
+pub fn callback_exec(matches: &getopts::Matches,
+                     store: &mut password::v2::PasswordStore)
+                     -> Result<(), ExitCode> {
+    apply(kdf_from_matches(matches)?, store)
+}
+
+/// Derives the key of the store with `kdf` from now on, after asking the master password again.
+pub fn apply(kdf: Kdf, store: &mut password::v2::PasswordStore) -> Result<(), ExitCode> {
+    // The master password isn't kept after opening the file, and the new key needs it.
+    let master_password = ask_master_password().map_err(|err| {
+        println_err!("Woops, I could not read your master password (reason: {}).", err);
+        ExitCode::Error
+    })?;
+    store.change_key_derivation(master_password.expose(), kdf).map_err(|err| {
+        println_err!("Woops, that's not the master password your file was opened with.");
+        ExitCode::from_password_error(&err)
+    })?;
+
+    println_ok!("Done! Your key is now derived with {}.", describe(&kdf));
+    Ok(())
+}
+
+/// The key derivation and its parameters, as they are given to set-kdf.
+pub fn describe(kdf: &Kdf) -> String {
+    match *kdf {
+        Kdf::Scrypt { log2_n, r, p } => {
+            format!("scrypt (--log-n {} --scrypt-r {} --scrypt-p {}), which needs {} MiB of \
+                     memory",
+                    log2_n,
+                    r,
+                    p,
+                    kdf.memory() >> 20)
+        }
+        Kdf::Argon2id(ref params) => {
+            format!("Argon2id (--argon2id --memory-mib {}), which needs {} MiB of memory",
+                    params.memory_kib / 1024,
+                    kdf.memory() >> 20)
+        }
+    }
+}
@@ -12,53 +12,174 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use exit_code::ExitCode;
+use crypto::util::fixed_time_eq;
+use answer;
 use getopts;
+use key_file;
+use new_yubikey_response;
+use yubikey;
 use password;
-use rpassword::prompt_password_stderr;
 use safe_string::SafeString;
+use strength::{Strength, MIN_LENGTH};
+use help::Help;
 use std::io::Write;
-use std::ops::Deref;
 
-pub fn callback_help() {
-    println!("Usage:");
-    println!("    rooster set-master-password -h");
-    println!("    rooster set-master-password");
-    println!("");
-    println!("Example:");
-    println!("    rooster set-master-password");
+pub fn callback_help() -> Help {
+    Help::new()
+        .usage(&["rooster set-master-password -h",
+                 "rooster set-master-password [--new-key-file <path> | --no-key-file]",
+                 "                            [--yubikey | --no-yubikey]"])
+        .examples(&["rooster set-master-password",
+                    "rooster set-master-password --new-key-file /media/usb/rooster.key",
+                    "rooster set-master-password --yubikey"])
+        .text(&["The new master password is asked twice, and its strength is shown. If it is",
+                &format!("shorter than {} characters or one of the most common passwords, you \
+                          have to",
+                         MIN_LENGTH),
+                "confirm that you want it anyway."])
+        .text(&["With --new-key-file, the password file can then only be opened with both the",
+                "master password and the key file, given with --key-file. Any file will do, but it",
+                "must never change. Without --new-key-file or --no-key-file, the current key file",
+                "is kept."])
+        .text(&["With --yubikey, the password file also needs the HMAC-SHA1 challenge-response \
+                 slot",
+                &format!("of a YubiKey (slot 2, or the one in ${}), which has to be touched every \
+                          time.",
+                         yubikey::YUBIKEY_SLOT_ENV_VAR),
+                "This needs Rooster to be built with the yubikey feature, and ykchalresp."])
 }
 
-pub fn callback_exec(_matches: &getopts::Matches,
-                     store: &mut password::v2::PasswordStore)
-                     -> Result<(), i32> {
-    match prompt_password_stderr("Type your new master password: ") {
-        Ok(master_password) => {
-            let master_password = SafeString::new(master_password);
+/// Asks for a new master password twice, for this command and `rooster init`.
+pub fn read_new_master_password() -> Result<SafeString, ExitCode> {
+    let mut master_password = match answer::prompt_password("Type your new master password: ",
+                                                            "a new master password") {
+        Ok(master_password) => master_password,
+        Err(err) => {
+            println_err!("I could not read your new master password (reason: {:?}).", err);
+            return Err(ExitCode::Error);
+        }
+    };
+
+    let mut master_password_confirmation =
+        match answer::prompt_password("Type your new master password once more: ",
+                                      "a new master password") {
+        Ok(master_password_confirmation) => master_password_confirmation,
+        Err(err) => {
+            println_err!("I could not read your new master password (reason: {:?}).", err);
+            return Err(ExitCode::Error);
+        }
+    };
 
-            let master_password_confirmation = match prompt_password_stderr("Type your new \
-                                                                             master password \
-                                                                             once more: ") {
-                Ok(master_password_confirmation) => SafeString::new(master_password_confirmation),
-                Err(err) => {
-                    println_err!("I could not read your new master password (reason: {:?}).",
-                                 err);
-                    return Err(1);
-                }
-            };
+    let matching = fixed_time_eq(master_password.expose().as_bytes(),
+                                 master_password_confirmation.expose().as_bytes());
+    master_password_confirmation.wipe();
 
-            if master_password != master_password_confirmation {
-                println_err!("The master password confirmation did not match. Aborting.");
-                return Err(1);
-            }
+    if !matching {
+        master_password.wipe();
+        println_err!("The master password confirmation did not match. Aborting.");
+        return Err(ExitCode::Error);
+    }
+
+    Ok(master_password)
+}
+
+/// Shows how strong the new master password is, and tells whether it should be used: a weak
+/// one has to be confirmed.
+pub fn confirm_strength(master_password: &SafeString) -> Result<bool, ExitCode> {
+    let strength = Strength::of(master_password.expose());
+    println_stderr!("Strength of the new master password: {}.", strength.describe());
+    if !strength.is_weak() {
+        return Ok(true);
+    }
+
+    println_err!("");
+    println_err!("Careful, this master password is weak: {}. Anyone who gets a copy of your \
+                  password file could guess it and read all your passwords.",
+                 strength.weaknesses().join(" and "));
+    print_stderr!("Do you really want to use it (y/n)? ");
+
+    let mut line = String::new();
+    answer::read_line(&mut line).map_err(|err| {
+        println_err!("I couldn't read that (reason: {:?}).", err);
+        ExitCode::Error
+    })?;
+    Ok(line.starts_with('y'))
+}
 
-            store.change_master_password(master_password.deref());
+/// The second factors the store was opened with, which a new key needs again.
+pub fn current_factors(store: &password::v2::PasswordStore) -> password::v2::SecondFactors {
+    password::v2::SecondFactors {
+        key_file: key_file::get(),
+        yubikey: store.yubikey().cloned(),
+    }
+}
+
+pub fn callback_exec(matches: &getopts::Matches,
+                     store: &mut password::v2::PasswordStore)
+                     -> Result<(), ExitCode> {
+    if matches.opt_present("new-key-file") && matches.opt_present("no-key-file") {
+        println_err!("Woops, --new-key-file and --no-key-file can't be used together.");
+        return Err(ExitCode::Usage);
+    }
+    if matches.opt_present("yubikey") && matches.opt_present("no-yubikey") {
+        println_err!("Woops, --yubikey and --no-yubikey can't be used together.");
+        return Err(ExitCode::Usage);
+    }
+    // Read before the new master password is asked for, in case the file can't be read.
+    let new_key_file = match matches.opt_str("new-key-file") {
+        Some(path) => {
+            Some(key_file::read(&path).map_err(|reason| {
+                println_err!("Woops, {}.", reason);
+                ExitCode::Error
+            })?)
         }
-        Err(err) => {
-            println_err!("I could not read your new master password (reason: {:?}).",
-                         err);
-            return Err(1);
+        None => None,
+    };
+
+    let changes_factors = ["new-key-file", "no-key-file", "yubikey", "no-yubikey"]
+        .iter()
+        .any(|name| matches.opt_present(name));
+
+    // The new master password only lives for as long as it takes to derive the new key. The
+    // store wipes the old key itself.
+    {
+        let mut master_password = read_new_master_password()?;
+        if !confirm_strength(&master_password)? {
+            println_err!("Alright, I kept your old master password.");
+            return Err(ExitCode::Error);
+        }
+        if changes_factors {
+            let mut factors = current_factors(store);
+            if matches.opt_present("no-key-file") {
+                factors.key_file = None;
+            } else if new_key_file.is_some() {
+                factors.key_file = new_key_file.clone();
+            }
+            if matches.opt_present("yubikey") {
+                factors.yubikey = Some(new_yubikey_response()?);
+            } else if matches.opt_present("no-yubikey") {
+                factors.yubikey = None;
+            }
+            store.change_master_password_and_factors(master_password.expose(), &factors);
+        } else {
+            store.change_master_password(master_password.expose());
         }
+        master_password.wipe();
     }
+
     println_ok!("Your master password has been changed.");
+    if new_key_file.is_some() {
+        println_stderr!("From now on, open your password file with --key-file too. Without the \
+                         key file, your passwords are lost.");
+    } else if store.has_key_file() {
+        println_stderr!("Your password file still needs its key file.");
+    }
+    if matches.opt_present("yubikey") {
+        println_stderr!("From now on, your password file needs your YubiKey too. Without it, \
+                         your passwords are lost.");
+    } else if store.yubikey().is_some() {
+        println_stderr!("Your password file still needs your YubiKey.");
+    }
     Ok(())
 }
@@ -0,0 +1,121 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use exit_code::ExitCode;
+use getopts;
+use password;
+use password::v2::normalize_tag;
+use ffi;
+use list;
+use help::Help;
+use std::io::Write;
+
+pub fn callback_help() -> Help {
+    Help::new()
+        .usage(&["rooster tag -h",
+                 "rooster tag <query>",
+                 "rooster tag <query> [+<tag>|-<tag>]..."])
+        .text(&["Without tags, shows the tags of the password. +<tag> adds a tag, -<tag> removes",
+                "it. Tags are lowercase."])
+        .examples(&["rooster tag youtube",
+                    "rooster tag youtube +personal +video",
+                    "rooster tag youtube +work -personal"])
+}
+
+enum TagChange {
+    Add(String),
+    Remove(String),
+}
+
+fn parse_tag_changes(args: &[String]) -> Result<Vec<TagChange>, String> {
+    let mut changes = Vec::new();
+    for arg in args.iter() {
+        if arg.starts_with('+') {
+            changes.push(TagChange::Add(normalize_tag(&arg[1..])?));
+        } else if arg.starts_with('-') {
+            changes.push(TagChange::Remove(normalize_tag(&arg[1..])?));
+        } else {
+            return Err(format!("\"{}\" should start with + to add a tag or - to remove it", arg));
+        }
+    }
+    Ok(changes)
+}
+
+pub fn check_args(matches: &getopts::Matches) -> Result<(), ExitCode> {
+    if matches.free.len() < 2 {
+        println_err!("Woops, seems like the app name is missing here. For help, try:");
+        println_err!("    rooster tag -h");
+        return Err(ExitCode::Usage);
+    }
+
+    if let Err(err) = parse_tag_changes(&matches.free[2..]) {
+        println_err!("Woops, {}. For help, try:", err);
+        println_err!("    rooster tag -h");
+        return Err(ExitCode::Usage);
+    }
+
+    Ok(())
+}
+
+pub fn callback_exec(matches: &getopts::Matches,
+                     store: &mut password::v2::PasswordStore)
+                     -> Result<(), ExitCode> {
+    check_args(matches)?;
+
+    let query = &matches.free[1];
+    let changes = parse_tag_changes(&matches.free[2..]).map_err(|_| ExitCode::Error)?;
+
+    println_stderr!("");
+    let password = list::search_and_choose_password(
+        store, query, list::WITH_NUMBERS,
+        "Which password would you like to tag?",
+    )?.clone();
+
+    if changes.is_empty() {
+        if password.tags.is_empty() {
+            println_stderr!("{} has no tags.", password.name);
+        } else {
+            println_stdout!("{}", password.tags.join(" "));
+        }
+        return Ok(());
+    }
+
+    let mut tags = password.clone();
+    for change in changes.into_iter() {
+        match change {
+            TagChange::Add(tag) => tags.add_tag(tag),
+            TagChange::Remove(tag) => tags.remove_tag(tag.as_str()),
+        }
+    }
+    let tags = tags.tags;
+
+    store.change_password(&password.name,
+                          &|old_password: password::v2::Password| {
+        password::v2::Password {
+            tags: tags.clone(),
+            updated_at: ffi::time(),
+            ..old_password
+        }
+    }).map_err(|err| {
+        println_err!("Woops, I couldn't save the tags (reason: {:?}).", err);
+        ExitCode::from_password_error(&err)
+    })?;
+
+    if tags.is_empty() {
+        println_ok!("Done! {} has no tags anymore.", password.name);
+    } else {
+        println_ok!("Done! {} is now tagged: {}.", password.name, tags.join(", "));
+    }
+    Ok(())
+}
@@ -12,27 +12,27 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use exit_code::ExitCode;
 use getopts;
 use password;
 use ffi;
 use list;
+use help::Help;
 use std::io::Write;
 
-pub fn callback_help() {
-    println!("Usage:");
-    println!("    rooster transfer -h");
-    println!("    rooster transfer <app> <new_username>");
-    println!("");
-    println!("Examples:");
-    println!("    rooster transfer dailymotion new@example.com");
-    println!("    rooster transfer dm new@example.com");
+pub fn callback_help() -> Help {
+    Help::new()
+        .usage(&["rooster transfer -h",
+                 "rooster transfer <app> <new_username>"])
+        .examples(&["rooster transfer dailymotion new@example.com",
+                    "rooster transfer dm new@example.com"])
 }
 
-pub fn check_args(matches: &getopts::Matches) -> Result<(), i32> {
+pub fn check_args(matches: &getopts::Matches) -> Result<(), ExitCode> {
     if matches.free.len() < 3 {
         println_err!("Woops, seems like the app name or username is missing here. For help, try:");
         println_err!("    rooster transfer -h");
-        return Err(1);
+        return Err(ExitCode::Usage);
     }
 
     Ok(())
@@ -40,7 +40,7 @@ pub fn check_args(matches: &getopts::Matches) -> Result<(), i32> {
 
 pub fn callback_exec(matches: &getopts::Matches,
                      store: &mut password::v2::PasswordStore)
-                     -> Result<(), i32> {
+                     -> Result<(), ExitCode> {
     check_args(matches)?;
 
     let query = &matches.free[1];
@@ -50,31 +50,32 @@ pub fn callback_exec(matches: &getopts::Matches,
     let password = list::search_and_choose_password(
         store, query, list::WITH_NUMBERS,
         "Which password would you like to transfer?",
-    ).ok_or(1)?.clone();
+    )?.clone();
     println_stderr!("");
 
-    let old_username = password.username;
+    let old_username = password.username.clone();
 
     let change_result = store.change_password(&password.name,
                                               &|old_password: password::v2::Password| {
         password::v2::Password {
-            name: old_password.name.clone(),
             username: new_username.clone(),
-            password: old_password.password.clone(),
-            created_at: old_password.created_at,
             updated_at: ffi::time(),
+            ..old_password
         }
     });
 
     match change_result {
         Ok(_) => {
-            println_ok!("Done! I've transferd {} to {}", old_username, new_username);
+            println_ok!("Done! I've transferred {} from {} to {}",
+                        password.name,
+                        old_username,
+                        new_username);
             Ok(())
         }
         Err(err) => {
-            println_err!("Woops, I couldn't save the new app name (reason: {:?}).",
+            println_err!("Woops, I couldn't save the new username (reason: {:?}).",
                          err);
-            Err(1)
+            Err(ExitCode::from_password_error(&err))
         }
     }
 }
@@ -0,0 +1,39 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+use exit_code::ExitCode;
+use getopts;
+use password;
+use super::archive::{check_args, set_archived};
+use help::Help;
+
+pub fn callback_help() -> Help {
+    Help::new()
+        .usage(&["rooster unarchive -h",
+                 "rooster unarchive <query>"])
+        .text(&["Brings back a password archived with `rooster archive`. Only archived passwords",
+                "are searched."])
+        .examples(&["rooster unarchive youtube"])
+}
+
+pub fn callback_exec(matches: &getopts::Matches,
+                     store: &mut password::v2::PasswordStore)
+                     -> Result<(), ExitCode> {
+    check_args(matches, "unarchive")?;
+
+    // Archived passwords are hidden from searches by default.
+    store.set_include_archived(true);
+    set_archived(matches, store, false)
+}
@@ -0,0 +1,135 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use exit_code::ExitCode;
+use answer;
+use atomic_file;
+use getopts;
+use password;
+use safe_vec::SafeVec;
+use {ask_master_password, get_password_file_path, second_factors};
+use help::Help;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+pub fn callback_help() -> Help {
+    Help::new()
+        .usage(&["rooster undo -h",
+                 "rooster undo"])
+        .text(&["Goes back to how your passwords were before the last command that changed them.",
+                "Only the last change can be undone, and only once."])
+        .examples(&["rooster undo"])
+}
+
+/// The previous state is kept next to the password file, like ~/.passwords.rooster.undo.
+pub fn undo_file_path(password_file_path: &str) -> String {
+    format!("{}.undo", password_file_path)
+}
+
+/// Creates or truncates a file only the owner can read, for copies of the password file.
+#[cfg(unix)]
+pub fn create_private_file(path: &str) -> io::Result<File> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    OpenOptions::new().write(true).create(true).truncate(true).mode(0o600).open(path)
+}
+
+#[cfg(not(unix))]
+pub fn create_private_file(path: &str) -> io::Result<File> {
+    OpenOptions::new().write(true).create(true).truncate(true).open(path)
+}
+
+/// Copies the password file as it is on disk, still encrypted, to the undo file. Must be called
+/// before a changed store is synced to the password file.
+pub fn save_undo_state(password_file_path: &str) -> io::Result<()> {
+    let previous = SafeVec::new(fs::read(password_file_path)?);
+
+    let undo_path = undo_file_path(password_file_path);
+
+    // A brand new password file has nothing to go back to.
+    if previous.is_empty() {
+        return match fs::remove_file(&undo_path) {
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        };
+    }
+
+    // Like the previous version, so that a crash halfway through doesn't leave half an undo
+    // file. The new file only the owner can read replaces older ones, whatever their mode.
+    atomic_file::write(Path::new(&undo_path),
+                       |file: &mut File| file.write_all(previous.expose()))
+}
+
+pub fn callback_exec(matches: &getopts::Matches) -> Result<(), ExitCode> {
+    let password_file_path = get_password_file_path(matches).map_err(|_| {
+        println_err!("Woops, I could not determine where your password file is.");
+        ExitCode::Error
+    })?;
+    let undo_path = undo_file_path(password_file_path.as_str());
+
+    let mut previous = SafeVec::new(Vec::new());
+    match File::open(&undo_path).and_then(|mut file| file.read_to_end(&mut previous.inner_mut())) {
+        Ok(_) => {}
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => {
+            println_stderr!("There is nothing to undo.");
+            return Ok(());
+        }
+        Err(err) => {
+            println_err!("Woops, I couldn't read {} (reason: {:?}).", undo_path, err);
+            return Err(ExitCode::Io);
+        }
+    }
+
+    // Make sure the previous state can be opened before going back to it. If the last change was
+    // a new master password, this is the old one.
+    let master_password = ask_master_password().map_err(|err| {
+        println_err!("Woops, I could not read your master password (reason: {}).", err);
+        ExitCode::Error
+    })?;
+    let header = password::v2::Header::read(&mut io::Cursor::new(previous.expose())).ok();
+    let factors = second_factors(header.as_ref())?;
+    let store = password::v2::PasswordStore::from_input_with_factors(master_password,
+                                                                     previous,
+                                                                     &factors)
+        .map_err(|err| {
+            println_err!("Woops, I can't open the previous state with this master password \
+                          (reason: {:?}). Nothing was changed.",
+                         err);
+            ExitCode::Error
+        })?;
+
+    println_stderr!("");
+    print_stderr!("Go back to the previous state of your password file, with {} password(s)? \
+                   The current state will be lost (y/n) ",
+                  store.get_all_passwords().len());
+    let mut line = String::new();
+    answer::read_line(&mut line).map_err(|err| {
+        println_err!("I couldn't read that (reason: {:?}).", err);
+        ExitCode::Error
+    })?;
+    if !line.starts_with('y') {
+        println_stderr!("OK, I didn't change anything.");
+        return Ok(());
+    }
+
+    // Renaming replaces the password file in one go and removes the undo file at the same time.
+    fs::rename(&undo_path, &password_file_path).map_err(|err| {
+        println_err!("Woops, I couldn't restore {} (reason: {:?}).", undo_path, err);
+        ExitCode::Io
+    })?;
+
+    println_ok!("Done! Your password file is back to how it was before the last change.");
+    Ok(())
+}
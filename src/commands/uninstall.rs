@@ -12,36 +12,333 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use exit_code::ExitCode;
+use answer;
 use getopts;
-use get_password_file_path;
+use {check_password_file_unchanged, get_password_file_path, lock_password_file_for_writing};
 use quale::which;
+use super::clear_all::CONFIRMATION;
+use super::restore::previous_file_path;
+use super::undo::{create_private_file, undo_file_path};
+use help::Help;
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 
-pub fn callback_help() {
-    println!("Usage:");
-    println!("    rooster uninstall -h");
-    println!("    rooster uninstall");
-    println!("");
-    println!("Example:");
-    println!("    rooster uninstall");
+/// The shell startup files where $ROOSTER_FILE is usually set, relative to the home directory.
+const SHELL_RC_FILES: &'static [&'static str] = &[".bashrc",
+                                                  ".bash_profile",
+                                                  ".profile",
+                                                  ".zshrc",
+                                                  ".zshenv",
+                                                  ".zprofile"];
+
+pub fn callback_help() -> Help {
+    Help::new()
+        .usage(&["rooster uninstall -h",
+                 "rooster uninstall [--export-first <path>] [--dry-run]"])
+        .text(&["Deletes your password file, along with the copies Rooster keeps next to it for",
+                &format!("`rooster undo` and `rooster restore`. You have to type {} to confirm.",
+                         CONFIRMATION),
+                "The files are overwritten with zeros before they are deleted. On SSDs and",
+                "copy-on-write file systems, the old content may still be on the disk."])
+        .text(&["Before that, you are offered to save an encrypted copy of the password file, \
+                 which",
+                "you can open with `ROOSTER_FILE=<path> rooster list`. --export-first saves it to",
+                "<path> without asking."])
+        .text(&["The lines of your shell startup files that set $ROOSTER_FILE are shown, so that",
+                "you can remove them, along with the commands that remove Rooster itself."])
+        .text(&["With --dry-run, nothing is deleted, you only see what would be."])
+        .examples(&["rooster uninstall --dry-run",
+                    "rooster uninstall --export-first ~/passwords-backup.rooster"])
+}
+
+pub fn check_args(matches: &getopts::Matches) -> Result<(), ExitCode> {
+    if matches.free.len() > 1 {
+        println_err!("Woops, uninstall doesn't take any arguments. For help, try:");
+        println_err!("    rooster uninstall -h");
+        return Err(ExitCode::Usage);
+    }
+
+    if let Some(path) = matches.opt_str("export-first") {
+        if Path::new(&path).exists() {
+            println_err!("Woops, {} already exists. I won't overwrite it.", path);
+            return Err(ExitCode::Error);
+        }
+    }
+
+    Ok(())
+}
+
+/// The lines that set $ROOSTER_FILE, with their number.
+fn rooster_file_exports(content: &str) -> Vec<(usize, &str)> {
+    content.lines()
+        .enumerate()
+        .filter(|&(_, line)| line.trim_start().starts_with("export ROOSTER_FILE="))
+        .map(|(index, line)| (index + 1, line.trim()))
+        .collect()
+}
+
+fn print_shell_exports() {
+    let home = match env::home_dir() {
+        Some(home) => home,
+        None => return,
+    };
+
+    let mut found = Vec::new();
+    for name in SHELL_RC_FILES.iter() {
+        let path = home.join(name);
+        if let Ok(content) = fs::read_to_string(&path) {
+            for (number, line) in rooster_file_exports(&content) {
+                found.push(format!("    {}:{}: {}", path.display(), number, line));
+            }
+        }
+    }
+
+    if !found.is_empty() {
+        println_stdout!();
+        println_stdout!("$ROOSTER_FILE is set in your shell startup files, you may remove these \
+                         lines:");
+        println_stdout!();
+        for line in found.iter() {
+            println_stdout!("{}", line);
+        }
+    }
+}
+
+fn print_binaries() {
+    let binaries: Vec<PathBuf> = ["rooster", "rooster-clipboard"]
+        .iter()
+        .filter_map(|name| which(name))
+        .collect();
+    if binaries.is_empty() {
+        return;
+    }
+
+    println_stdout!();
+    println_stdout!("To remove Rooster itself from your system, run the following commands:");
+    println_stdout!();
+    for binary in binaries.iter() {
+        println_stdout!("    sudo rm {}", binary.to_string_lossy());
+    }
+}
+
+/// The password file and the copies of it that exist.
+fn files_to_delete(password_file_path: &str) -> Vec<String> {
+    vec![password_file_path.to_owned(),
+         undo_file_path(password_file_path),
+         previous_file_path(password_file_path)]
+        .into_iter()
+        .filter(|path| Path::new(path).is_file())
+        .collect()
+}
+
+/// Copies the password file as it is on disk, still encrypted, to a new file.
+fn export_to(password_file_path: &str, path: &str) -> Result<(), ExitCode> {
+    if Path::new(path).exists() {
+        println_err!("Woops, {} already exists. I won't overwrite it.", path);
+        return Err(ExitCode::Error);
+    }
+    fs::read(password_file_path)
+        .and_then(|content| {
+            let mut file = create_private_file(path)?;
+            file.write_all(&content)?;
+            file.sync_all()
+        })
+        .map_err(|err| {
+            println_err!("Woops, I couldn't save a copy of your password file to {} (reason: \
+                          {:?}).",
+                         path,
+                         err);
+            ExitCode::Io
+        })?;
+    println_ok!("I've saved a copy of your password file to {}.", path);
+    Ok(())
+}
+
+fn read_answer() -> Result<String, ExitCode> {
+    let mut line = String::new();
+    answer::read_line(&mut line).map_err(|err| {
+        println_err!("I couldn't read that (reason: {:?}).", err);
+        ExitCode::Error
+    })?;
+    Ok(line.trim_end_matches(|c| c == '\n' || c == '\r').to_owned())
+}
+
+/// Saves an encrypted copy where --export-first says, or where the user wants one if any.
+fn offer_export(matches: &getopts::Matches, password_file_path: &str) -> Result<(), ExitCode> {
+    if let Some(path) = matches.opt_str("export-first") {
+        return export_to(password_file_path, &path);
+    }
+
+    println_stderr!("");
+    println_stderr_always!("You can save an encrypted copy of your password file first, in case");
+    println_stderr_always!("you need it again.");
+    print_stderr!("Where should I save it? Leave empty to skip: ");
+    let path = read_answer()?;
+    if path.trim().is_empty() {
+        return Ok(());
+    }
+    export_to(password_file_path, path.trim())
 }
 
-pub fn callback_exec(_matches: &getopts::Matches) -> Result<(), i32> {
-    println!("To uninstall Rooster from your system, run the following commands:");
-    println!();
-    println!("    sudo rm {}",
-             which("rooster").unwrap().to_string_lossy());
-    println!("    sudo rm {}",
-             which("rooster-clipboard").unwrap().to_string_lossy());
+fn confirm(files: &[String]) -> Result<bool, ExitCode> {
+    println_stderr!("");
+    println_stderr_always!("I'm about to delete these files, with all your passwords:");
+    for file in files.iter() {
+        println_stderr_always!("    {}", file);
+    }
+    print_stderr!("Type {} to confirm: ", CONFIRMATION);
+    Ok(read_answer()? == CONFIRMATION)
+}
 
-    match get_password_file_path().ok() {
-        Some(file) => {
-            println!();
-            println!("If you want to remove your password file as well, it is located at:");
-            println!();
-            println!("    {}", file);
+/// Overwrites the content of the file with zeros, then deletes it.
+fn overwrite_and_remove(path: &str) -> io::Result<()> {
+    let len = fs::metadata(path)?.len();
+    {
+        let mut file = OpenOptions::new().write(true).open(path)?;
+        let zeros = [0u8; 4096];
+        let mut left = len;
+        while left > 0 {
+            let chunk = ::std::cmp::min(left, zeros.len() as u64) as usize;
+            file.write_all(&zeros[..chunk])?;
+            left -= chunk as u64;
         }
-        None => {}
+        file.sync_all()?;
     }
+    fs::remove_file(path)
+}
+
+pub fn callback_exec(matches: &getopts::Matches) -> Result<(), ExitCode> {
+    check_args(matches)?;
+
+    let password_file_path = get_password_file_path(matches).map_err(|_| {
+        println_err!("Woops, I could not determine where your password file is.");
+        ExitCode::Error
+    })?;
+    let files = files_to_delete(&password_file_path);
+    let dry_run = matches.opt_present("dry-run");
+
+    if files.is_empty() {
+        println_stderr!("There is no password file at {}, so there is nothing to delete.",
+                        password_file_path);
+    } else if dry_run {
+        println_stdout!("These files would be deleted:");
+        println_stdout!();
+        for file in files.iter() {
+            println_stdout!("    {}", file);
+        }
+    } else {
+        let _locked = lock_password_file_for_writing(matches, &password_file_path)?;
+        if files.contains(&password_file_path) {
+            offer_export(matches, &password_file_path)?;
+        }
+        if !confirm(&files)? {
+            println_stderr!("OK, I didn't delete anything.");
+            return Ok(());
+        }
+        check_password_file_unchanged(&password_file_path)?;
+        for file in files.iter() {
+            overwrite_and_remove(file).map_err(|err| {
+                println_err!("Woops, I couldn't delete {} (reason: {:?}).", file, err);
+                ExitCode::Io
+            })?;
+        }
+        println_ok!("Done! I've deleted your password file.");
+    }
+
+    print_shell_exports();
+    print_binaries();
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::{callback_exec, rooster_file_exports};
+    use commands::harness::run_without_store;
+    use exit_code::ExitCode;
+    use std::env;
+    use std::fs;
+    use std::path::Path;
+    use std::process;
+
+    /// A password file and its undo file, in a directory of their own.
+    fn password_file(name: &str) -> String {
+        let dir = env::temp_dir().join(format!("rooster-uninstall-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir(&dir).unwrap();
+        let path = dir.join("passwords.rooster").to_string_lossy().into_owned();
+        fs::write(&path, b"encrypted").unwrap();
+        fs::write(format!("{}.undo", path), b"encrypted before").unwrap();
+        path
+    }
+
+    fn remove_dir(path: &str) {
+        fs::remove_dir_all(Path::new(path).parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn exports_are_found_in_shell_files() {
+        let content = "alias ll='ls -l'\n  export ROOSTER_FILE=~/passwords.rooster\n\
+                       # export ROOSTER_FILE=/tmp/old\nexport ROOSTER_FILEX=1\n";
+        assert_eq!(rooster_file_exports(content),
+                   vec![(2, "export ROOSTER_FILE=~/passwords.rooster")]);
+    }
+
+    #[test]
+    fn files_are_deleted_once_confirmed() {
+        let path = password_file("confirmed");
+        let (result, output) = run_without_store(callback_exec,
+                                                 &["uninstall", "--file", &path],
+                                                 &["", "DELETE"]);
+        assert_eq!(result, Ok(()));
+        assert!(output.stderr.contains(&format!("{}.undo", path)));
+        assert!(!Path::new(&path).exists());
+        assert!(!Path::new(&format!("{}.undo", path)).exists());
+        remove_dir(&path);
+    }
+
+    #[test]
+    fn nothing_is_deleted_without_confirmation() {
+        let path = password_file("unconfirmed");
+        for answers in [&["", "y"][..], &["", "delete"][..], &[][..]].iter() {
+            let (result, _) = run_without_store(callback_exec,
+                                                &["uninstall", "--file", &path],
+                                                answers);
+            assert_eq!(result, Ok(()));
+            assert_eq!(fs::read(&path).unwrap(), b"encrypted");
+        }
+
+        let (result, output) = run_without_store(callback_exec,
+                                                 &["uninstall", "--file", &path, "--dry-run"],
+                                                 &["DELETE"]);
+        assert_eq!(result, Ok(()));
+        assert!(output.stdout.contains(&path));
+        assert_eq!(fs::read(&path).unwrap(), b"encrypted");
+        remove_dir(&path);
+    }
+
+    #[test]
+    fn a_copy_can_be_saved_first() {
+        let path = password_file("export");
+        let copy = format!("{}.copy", path);
+        let (result, _) = run_without_store(callback_exec,
+                                            &["uninstall", "--file", &path],
+                                            &[&copy, "DELETE"]);
+        assert_eq!(result, Ok(()));
+        assert!(!Path::new(&path).exists());
+        assert_eq!(fs::read(&copy).unwrap(), b"encrypted");
+
+        // An existing file is never overwritten by the copy.
+        fs::write(&path, b"encrypted").unwrap();
+        let (result, _) = run_without_store(callback_exec,
+                                            &["uninstall", "--file", &path, "--export-first",
+                                              &copy],
+                                            &["DELETE"]);
+        assert_eq!(result, Err(ExitCode::Error));
+        assert!(Path::new(&path).exists());
+        remove_dir(&path);
+    }
+}
@@ -0,0 +1,99 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The standard output and error of Rooster, which the printing macros write to. Tests can
+//! capture them, to check what a command says.
+
+use std::io::{self, Write};
+#[cfg(test)]
+use std::cell::RefCell;
+
+#[cfg(test)]
+thread_local! {
+    static CAPTURED: RefCell<Option<Captured>> = RefCell::new(None);
+}
+
+/// What was printed while capturing.
+#[cfg(test)]
+#[derive(Default)]
+pub struct Captured {
+    pub stdout: String,
+    pub stderr: String,
+}
+
+pub struct Output {
+    stderr: bool,
+}
+
+pub fn stdout() -> Output {
+    Output { stderr: false }
+}
+
+pub fn stderr() -> Output {
+    Output { stderr: true }
+}
+
+/// Runs `f` with everything the macros print on this thread kept, instead of printed.
+#[cfg(test)]
+pub fn capture<R, F: FnOnce() -> R>(f: F) -> (R, Captured) {
+    CAPTURED.with(|captured| *captured.borrow_mut() = Some(Captured::default()));
+    let result = f();
+    let captured = CAPTURED.with(|captured| captured.borrow_mut().take());
+    (result, captured.unwrap_or_default())
+}
+
+impl Output {
+    /// Keeps the output if it's being captured, and tells whether it was.
+    #[cfg(test)]
+    fn keep(&self, buf: &[u8]) -> bool {
+        CAPTURED.with(|captured| match *captured.borrow_mut() {
+            Some(ref mut captured) => {
+                let text = String::from_utf8_lossy(buf);
+                if self.stderr {
+                    captured.stderr.push_str(&text);
+                } else {
+                    captured.stdout.push_str(&text);
+                }
+                true
+            }
+            None => false,
+        })
+    }
+
+    #[cfg(not(test))]
+    fn keep(&self, _buf: &[u8]) -> bool {
+        false
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.keep(buf) {
+            return Ok(buf.len());
+        }
+        if self.stderr {
+            io::stderr().write(buf)
+        } else {
+            io::stdout().write(buf)
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.stderr {
+            io::stderr().flush()
+        } else {
+            io::stdout().flush()
+        }
+    }
+}
@@ -0,0 +1,169 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A small CSV reader (RFC 4180), for importing passwords exported by other tools.
+//!
+//! Every field is kept in a `SafeString`, since any column may hold a password.
+
+use safe_string::SafeString;
+
+pub struct Record {
+    /// Line of the file where the record starts, from 1.
+    pub line: usize,
+    pub fields: Vec<SafeString>,
+}
+
+/// Splits CSV contents into records. Quoted fields may contain commas, line breaks and quotes
+/// written as "". Blank lines are skipped. Records may have different numbers of fields.
+pub fn parse(contents: &str) -> Result<Vec<Record>, String> {
+    let contents = contents.trim_start_matches('\u{feff}');
+
+    let mut records = Vec::new();
+    let mut fields = Vec::new();
+    let mut field = SafeString::new(String::new());
+    let mut line = 1;
+    let mut record_line = 1;
+    let mut in_quotes = false;
+    let mut quoted_line = 0;
+    let mut at_field_start = true;
+
+    let mut chars = contents.chars().peekable();
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.inner_mut().push('"');
+                }
+                '"' => {
+                    in_quotes = false;
+                    match chars.peek() {
+                        None | Some(&',') | Some(&'\n') | Some(&'\r') => {}
+                        Some(_) => {
+                            return Err(format!("unexpected character after a closing quote on \
+                                                line {}",
+                                               line));
+                        }
+                    }
+                }
+                '\n' => {
+                    line += 1;
+                    field.inner_mut().push(c);
+                }
+                _ => field.inner_mut().push(c),
+            }
+            continue;
+        }
+
+        match c {
+            '"' if at_field_start => {
+                in_quotes = true;
+                quoted_line = line;
+                at_field_start = false;
+            }
+            ',' => {
+                fields.push(field);
+                field = SafeString::new(String::new());
+                at_field_start = true;
+            }
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' | '\r' => {
+                end_record(&mut records, &mut fields, field, record_line);
+                field = SafeString::new(String::new());
+                at_field_start = true;
+                line += 1;
+                record_line = line;
+            }
+            _ => {
+                field.inner_mut().push(c);
+                at_field_start = false;
+            }
+        }
+    }
+
+    if in_quotes {
+        return Err(format!("the quote opened on line {} is never closed", quoted_line));
+    }
+    end_record(&mut records, &mut fields, field, record_line);
+
+    Ok(records)
+}
+
+fn end_record(records: &mut Vec<Record>,
+              fields: &mut Vec<SafeString>,
+              last_field: SafeString,
+              line: usize) {
+    fields.push(last_field);
+    let fields = ::std::mem::replace(fields, Vec::new());
+
+    // Blank lines don't count as records with a single empty field.
+    if fields.len() == 1 && fields[0].is_empty() {
+        return;
+    }
+    records.push(Record {
+        line: line,
+        fields: fields,
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse;
+
+    fn fields(contents: &str) -> Vec<Vec<String>> {
+        parse(contents)
+            .unwrap()
+            .iter()
+            .map(|r| r.fields.iter().map(|f| f.expose().to_owned()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn plain_fields() {
+        assert_eq!(fields("a,b,c\r\n1,2,3\n\n4,,6"),
+                   vec![vec!["a", "b", "c"], vec!["1", "2", "3"], vec!["4", "", "6"]]);
+    }
+
+    #[test]
+    fn quoted_fields() {
+        assert_eq!(fields("\"a,b\",\"say \"\"hi\"\"\",\"\"\n"),
+                   vec![vec!["a,b", "say \"hi\"", ""]]);
+    }
+
+    #[test]
+    fn embedded_newlines() {
+        let records = parse("name,notes\nGitHub,\"line 1\nline 2\"\nSlack,x\n").unwrap();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[1].fields[1].expose(), "line 1\nline 2");
+        assert_eq!(records[1].line, 2);
+        assert_eq!(records[2].line, 4);
+    }
+
+    #[test]
+    fn mismatched_column_counts() {
+        assert_eq!(fields("a,b,c\n1\n1,2,3,4"),
+                   vec![vec!["a", "b", "c"], vec!["1"], vec!["1", "2", "3", "4"]]);
+    }
+
+    #[test]
+    fn invalid_quotes() {
+        assert!(parse("\"abc\nd").is_err());
+        assert!(parse("\"abc\"d,e").is_err());
+    }
+
+    #[test]
+    fn byte_order_mark() {
+        assert_eq!(fields("\u{feff}a,b"), vec![vec!["a", "b"]]);
+    }
+}
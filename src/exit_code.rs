@@ -0,0 +1,163 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! The exit codes of Rooster, so that scripts can tell failures apart.
+
+use output_format;
+use password::PasswordError;
+use std::process;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExitCode {
+    Success = 0,
+    /// Any failure without a code of its own.
+    Error = 1,
+    /// Wrong arguments or options.
+    Usage = 2,
+    /// Rooster would have asked something, with --non-interactive.
+    WouldAsk = 3,
+    /// The master password, the key file or the YubiKey is wrong.
+    WrongMasterPassword = 4,
+    /// The password file can't be read, or was made by a newer Rooster.
+    CorruptedFile = 5,
+    /// No password matches the query.
+    NotFound = 6,
+    /// A password with this name already exists.
+    AlreadyExists = 7,
+    /// A file couldn't be read or written.
+    Io = 8,
+    /// There is no password file, and it wasn't created.
+    NoPasswordFile = 9,
+    /// The password file was changed by another program, so it wasn't saved.
+    FileChanged = 10,
+    /// Another Rooster is using the password file.
+    Locked = 11,
+}
+
+/// Every exit code, in order, for the help.
+pub const ALL: &'static [ExitCode] = &[ExitCode::Success,
+                                       ExitCode::Error,
+                                       ExitCode::Usage,
+                                       ExitCode::WouldAsk,
+                                       ExitCode::WrongMasterPassword,
+                                       ExitCode::CorruptedFile,
+                                       ExitCode::NotFound,
+                                       ExitCode::AlreadyExists,
+                                       ExitCode::Io,
+                                       ExitCode::NoPasswordFile,
+                                       ExitCode::FileChanged,
+                                       ExitCode::Locked];
+
+impl ExitCode {
+    pub fn code(self) -> i32 {
+        self as i32
+    }
+
+    pub fn exit(self) -> ! {
+        output_format::report(self);
+        process::exit(self.code())
+    }
+
+    /// The stable name of the failure, for --error-format json.
+    pub fn name(self) -> &'static str {
+        match self {
+            ExitCode::Success => "success",
+            ExitCode::Error => "error",
+            ExitCode::Usage => "usage",
+            ExitCode::WouldAsk => "non_interactive",
+            ExitCode::WrongMasterPassword => "wrong_master_password",
+            ExitCode::CorruptedFile => "corrupted_file",
+            ExitCode::NotFound => "no_such_app",
+            ExitCode::AlreadyExists => "app_exists",
+            ExitCode::Io => "io",
+            ExitCode::NoPasswordFile => "no_password_file",
+            ExitCode::FileChanged => "file_changed",
+            ExitCode::Locked => "locked",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            ExitCode::Success => "Success",
+            ExitCode::Error => "Any other error",
+            ExitCode::Usage => "Wrong arguments or options",
+            ExitCode::WouldAsk => "Rooster would have asked something, with --non-interactive",
+            ExitCode::WrongMasterPassword => "Wrong master password, key file or YubiKey",
+            ExitCode::CorruptedFile => "The password file is corrupted, or too recent",
+            ExitCode::NotFound => "No password matches the query",
+            ExitCode::AlreadyExists => "A password with this name already exists",
+            ExitCode::Io => "A file couldn't be read or written",
+            ExitCode::NoPasswordFile => "There is no password file",
+            ExitCode::FileChanged => "The password file was changed by another program",
+            ExitCode::Locked => "Another Rooster is using the password file, see --wait",
+        }
+    }
+
+    /// The one place where password errors get their exit code.
+    pub fn from_password_error(err: &PasswordError) -> ExitCode {
+        match *err {
+            PasswordError::WrongMasterPasswordError |
+            PasswordError::DecryptionError |
+            PasswordError::KeyFileRequiredError |
+            PasswordError::WrongKeyFileError |
+            PasswordError::KeyFileNotUsedError |
+            PasswordError::YubiKeyRequiredError => ExitCode::WrongMasterPassword,
+            PasswordError::CorruptionError |
+            PasswordError::InvalidJsonError |
+            PasswordError::WrongVersionError => ExitCode::CorruptedFile,
+            PasswordError::NoSuchAppError => ExitCode::NotFound,
+            PasswordError::AppExistsError => ExitCode::AlreadyExists,
+            PasswordError::Io(_) => ExitCode::Io,
+            PasswordError::EncryptionError => ExitCode::Error,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ExitCode, ALL};
+    use password::PasswordError;
+    use std::io::{Error as IoError, ErrorKind as IoErrorKind};
+
+    #[test]
+    fn codes_are_distinct_and_in_order() {
+        for (i, code) in ALL.iter().enumerate() {
+            assert_eq!(code.code(), i as i32);
+        }
+    }
+
+    #[test]
+    fn names_are_distinct() {
+        for (i, code) in ALL.iter().enumerate() {
+            assert!(ALL[i + 1..].iter().all(|other| other.name() != code.name()));
+        }
+    }
+
+    #[test]
+    fn password_errors_have_their_code() {
+        let code = |err: PasswordError| ExitCode::from_password_error(&err);
+        assert_eq!(code(PasswordError::WrongMasterPasswordError), ExitCode::WrongMasterPassword);
+        assert_eq!(code(PasswordError::DecryptionError), ExitCode::WrongMasterPassword);
+        assert_eq!(code(PasswordError::WrongKeyFileError), ExitCode::WrongMasterPassword);
+        assert_eq!(code(PasswordError::YubiKeyRequiredError), ExitCode::WrongMasterPassword);
+        assert_eq!(code(PasswordError::CorruptionError), ExitCode::CorruptedFile);
+        assert_eq!(code(PasswordError::WrongVersionError), ExitCode::CorruptedFile);
+        assert_eq!(code(PasswordError::NoSuchAppError), ExitCode::NotFound);
+        assert_eq!(code(PasswordError::AppExistsError), ExitCode::AlreadyExists);
+        assert_eq!(code(PasswordError::Io(IoError::new(IoErrorKind::Other, "disk"))),
+                   ExitCode::Io);
+        assert_eq!(code(PasswordError::EncryptionError), ExitCode::Error);
+    }
+}
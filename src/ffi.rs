@@ -21,6 +21,8 @@ pub type time_t = libc::c_uint;
 mod internal {
     extern "C" {
         pub fn time(t: *mut super::time_t) -> super::time_t;
+        #[cfg(unix)]
+        pub fn tzset();
     }
 }
 
@@ -33,3 +35,29 @@ pub fn time() -> time_t {
 
     retrieved_time
 }
+
+/// Breaks a timestamp down in the local timezone, as configured by $TZ.
+///
+/// This is the only place where Rooster deals with local time. Timestamps are always stored
+/// in seconds since the epoch, in UTC.
+#[cfg(unix)]
+pub fn localtime(t: time_t) -> Option<libc::tm> {
+    use std::mem;
+
+    let t = t as libc::time_t;
+    unsafe {
+        // localtime_r doesn't have to read $TZ, unlike localtime.
+        internal::tzset();
+        let mut tm: libc::tm = mem::zeroed();
+        if libc::localtime_r(&t, &mut tm).is_null() {
+            None
+        } else {
+            Some(tm)
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub fn localtime(_t: time_t) -> Option<libc::tm> {
+    None
+}
@@ -0,0 +1,98 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! Notices when the password file was changed by someone else, like another Rooster or Dropbox,
+//! between reading and writing it, so that their changes aren't overwritten without asking.
+
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::Hasher;
+use std::io::ErrorKind as IoErrorKind;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+static READ: Mutex<Option<u64>> = Mutex::new(None);
+static FORCE_WRITE: AtomicBool = AtomicBool::new(false);
+
+fn fingerprint(bytes: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    hasher.write(bytes);
+    hasher.finish()
+}
+
+/// Set with --force-write.
+pub fn set_force_write(force_write: bool) {
+    FORCE_WRITE.store(force_write, Ordering::SeqCst);
+}
+
+pub fn is_force_write() -> bool {
+    FORCE_WRITE.load(Ordering::SeqCst)
+}
+
+/// Remembers what the password file held when it was read. A new file holds nothing.
+pub fn remember(bytes: &[u8]) {
+    *READ.lock().unwrap() = Some(fingerprint(bytes));
+}
+
+/// Forgets what was read, when there is no password file yet.
+pub fn forget() {
+    *READ.lock().unwrap() = None;
+}
+
+/// Remembers what was just written to the password file.
+pub fn remember_file(path: &str) {
+    if let Ok(bytes) = fs::read(path) {
+        remember(&bytes);
+    }
+}
+
+/// Whether the password file at `path` no longer holds what was read. Reading it by its path
+/// also notices files that were replaced rather than written to, like Dropbox does.
+pub fn changed_since_read(path: &str) -> bool {
+    let read = match *READ.lock().unwrap() {
+        Some(read) => read,
+        None => return false,
+    };
+    match fs::read(path) {
+        Ok(bytes) => fingerprint(&bytes) != read,
+        Err(ref err) if err.kind() == IoErrorKind::NotFound => true,
+        // Writing will tell what is wrong.
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{changed_since_read, remember, remember_file};
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn changes_by_others_are_noticed() {
+        let path = env::temp_dir().join(format!("rooster-file-state-{}", ::std::process::id()));
+        let path = path.to_str().unwrap();
+        fs::write(path, b"before").unwrap();
+        remember(b"before");
+        assert!(!changed_since_read(path));
+
+        fs::write(path, b"after").unwrap();
+        assert!(changed_since_read(path));
+        remember_file(path);
+        assert!(!changed_since_read(path));
+
+        fs::remove_file(path).unwrap();
+        assert!(changed_since_read(path));
+    }
+}
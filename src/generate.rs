@@ -12,85 +12,433 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use getopts;
 use rand::{Rng, OsRng};
-use std::io::{Write, Result as IoResult};
+use std::io::Result as IoResult;
 use safe_string::SafeString;
 
-fn generate_password(alnum: bool, len: usize) -> IoResult<SafeString> {
-    let mut password_as_string = String::new();
-    let mut rng = OsRng::new()?;
-    for _ in 0..len {
-        if alnum {
-            match rng.gen_range(0, 3) {
-                // Numbers 0-9
-                0 => password_as_string.push(rng.gen_range(48, 58) as u8 as char),
-                // Uppercase A-Z
-                1 => password_as_string.push(rng.gen_range(65, 91) as u8 as char),
-                // Lowercase a-z
-                2 => password_as_string.push(rng.gen_range(97, 123) as u8 as char),
-                _ => unreachable!(),
-            }
+/// Characters that are easily mistaken for one another, left out with --exclude-similar.
+pub const SIMILAR_CHARS: &'static str = "0O1lI|";
+
+/// The kinds of characters: lowercase and uppercase letters, digits and the rest.
+fn kinds<I: Iterator<Item = char>>(chars: I) -> [bool; 4] {
+    let mut kinds = [false; 4];
+    for c in chars {
+        let kind = if c.is_ascii_lowercase() {
+            0
+        } else if c.is_ascii_uppercase() {
+            1
+        } else if c.is_ascii_digit() {
+            2
         } else {
-            password_as_string.push(rng.gen_range(33, 127) as u8 as char);
-        }
+            3
+        };
+        kinds[kind] = true;
     }
-    Ok(SafeString::new(password_as_string))
+    kinds
 }
 
-/// Returns true if the password contains at least one digit, one uppercase letter and one
-/// lowercase letter.
-fn password_is_hard(password: &str, alnum: bool) -> bool {
-    let is_punctuation = |c| -> bool { "!\"#$%&'()*+,-./:;<=>?@[\\]^_`{|}~".find(c).is_some() };
-
-    password.find(char::is_numeric).is_some() && password.find(char::is_lowercase).is_some() &&
-    password.find(char::is_uppercase).is_some() &&
-    (alnum || password.find(is_punctuation).is_some())
+/// Returns true if the password has every kind of character of the alphabet: digits, uppercase
+/// letters, lowercase letters and symbols.
+fn password_is_hard(password: &str, alphabet: &[char]) -> bool {
+    kinds(password.chars()) == kinds(alphabet.iter().cloned())
 }
 
-pub fn generate_hard_password(alnum: bool, len: usize) -> IoResult<SafeString> {
+/// Draws each character uniformly from the alphabet, then starts over until the password is hard,
+/// which keeps the result uniform over hard passwords.
+pub fn generate_hard_password(spec: &PasswordSpec) -> IoResult<SafeString> {
+    let mut rng = OsRng::new()?;
     loop {
-        let password = generate_password(alnum, len)?;
-        if password_is_hard(password.as_ref(), alnum) {
+        let mut password = SafeString::new(String::with_capacity(spec.len));
+        for _ in 0..spec.len {
+            password.inner_mut().push(spec.alphabet[rng.gen_range(0, spec.alphabet.len())]);
+        }
+        if password_is_hard(password.expose(), &spec.alphabet) {
             return Ok(password);
         }
     }
 }
 
 pub struct PasswordSpec {
-    pub alnum: bool,
     pub len: usize,
+    /// The characters to draw from, sorted and without duplicates.
+    pub alphabet: Vec<char>,
 }
 
 impl PasswordSpec {
-    pub fn from_matches(matches: &getopts::Matches) -> Option<PasswordSpec> {
-        let alnum = matches.opt_present("alnum");
-        let mut password_len = 32;
-        if let Some(len) = matches.opt_str("length") {
-            password_len = match len.parse::<usize>() {
-                Ok(parsed_len) => {
-                    // We want passwords to contain at least one uppercase letter, one lowercase
-                    // letter and one digit. So we need at least 4 characters for each password.
-                    // This checks makes sure we don't run into an infinite loop trying to generate
-                    // a password of length <4 with 4 different kinds of characters (uppercase,
-                    // lowercase, numeric, punctuation).
-                    if parsed_len < 4 {
-                        println_err!("Woops! The length of the password must be at least 4. This");
-                        println_err!("allows us to make sure your password is secure.");
-                        return None;
+    pub fn entropy_bits(&self) -> f64 {
+        self.len as f64 * (self.alphabet.len() as f64).log2()
+    }
+}
+
+/// PINs are 6 digits long unless --length says otherwise.
+pub const DEFAULT_PIN_LENGTH: usize = 6;
+
+/// Turns a random byte into a digit, or `None` if it must be thrown away. 250 is the largest
+/// multiple of 10 that fits in a byte, and keeping bytes above it would favor 0 to 5.
+fn digit_from_byte(byte: u8) -> Option<char> {
+    if byte < 250 {
+        Some((b'0' + byte % 10) as char)
+    } else {
+        None
+    }
+}
+
+/// Generates a numeric PIN with uniformly random digits.
+pub fn generate_pin(len: usize) -> IoResult<SafeString> {
+    let mut rng = OsRng::new()?;
+
+    let mut pin = SafeString::new(String::with_capacity(len));
+    while pin.expose().len() < len {
+        if let Some(digit) = digit_from_byte(rng.gen()) {
+            pin.inner_mut().push(digit);
+        }
+    }
+    Ok(pin)
+}
+
+/// Words for passphrases, one per line. Lines starting with # are comments.
+const WORDLIST: &'static str = include_str!("wordlist.txt");
+
+pub fn wordlist() -> Vec<&'static str> {
+    WORDLIST.lines().filter(|line| !line.starts_with('#')).collect()
+}
+
+pub fn wordlist_len() -> usize {
+    wordlist().len()
+}
+
+/// A passphrase made of random words, like "Velvet-Orbit-Crane-Lunar7", for the passwords that
+/// must be typed by hand.
+pub struct PassphraseSpec {
+    pub words: usize,
+    pub separator: String,
+    pub capitalize: bool,
+    /// Appends a random digit, for sites that require one.
+    pub add_digit: bool,
+}
+
+impl PassphraseSpec {
+    /// How hard the passphrase is to guess for someone who knows how it was made.
+    pub fn entropy_bits(&self) -> f64 {
+        let mut bits = self.words as f64 * (wordlist_len() as f64).log2();
+        if self.add_digit {
+            bits += 10f64.log2();
+        }
+        bits
+    }
+}
+
+pub fn generate_passphrase(spec: &PassphraseSpec) -> IoResult<SafeString> {
+    let words = wordlist();
+    let mut rng = OsRng::new()?;
+
+    let mut passphrase = SafeString::new(String::new());
+    for i in 0..spec.words {
+        if i > 0 {
+            passphrase.inner_mut().push_str(&spec.separator);
+        }
+        let word = words[rng.gen_range(0, words.len())];
+        if spec.capitalize {
+            let mut chars = word.chars();
+            if let Some(first) = chars.next() {
+                passphrase.inner_mut().extend(first.to_uppercase());
+            }
+            passphrase.inner_mut().push_str(chars.as_str());
+        } else {
+            passphrase.inner_mut().push_str(word);
+        }
+    }
+    if spec.add_digit {
+        passphrase.inner_mut().push(rng.gen_range(b'0', b'9' + 1) as char);
+    }
+    Ok(passphrase)
+}
+
+/// Letters that can't be mistaken for one another when read aloud or typed on a remote.
+const CONSONANTS: &'static [u8] = b"bdfghjklmnprstvz";
+const VOWELS: &'static [u8] = b"aeiou";
+
+/// A password like "davokirulema", which alternates consonants and vowels so that it can be
+/// spoken, optionally ending with a digit.
+pub struct PronounceableSpec {
+    /// The total number of characters, digit included.
+    pub len: usize,
+    pub add_digit: bool,
+}
+
+impl PronounceableSpec {
+    fn letters(&self) -> usize {
+        if self.add_digit { self.len - 1 } else { self.len }
+    }
+
+    pub fn entropy_bits(&self) -> f64 {
+        let consonants = (self.letters() + 1) / 2;
+        let vowels = self.letters() / 2;
+        let mut bits = consonants as f64 * (CONSONANTS.len() as f64).log2() +
+                       vowels as f64 * (VOWELS.len() as f64).log2();
+        if self.add_digit {
+            bits += 10f64.log2();
+        }
+        bits
+    }
+
+    /// The entropy of a random password of the same length, to compare with.
+    pub fn random_entropy_bits(&self) -> f64 {
+        self.len as f64 * 94f64.log2()
+    }
+}
+
+pub fn generate_pronounceable_password(spec: &PronounceableSpec) -> IoResult<SafeString> {
+    let mut rng = OsRng::new()?;
+
+    let mut password = SafeString::new(String::with_capacity(spec.len));
+    for i in 0..spec.letters() {
+        let letters = if i % 2 == 0 { CONSONANTS } else { VOWELS };
+        password.inner_mut().push(letters[rng.gen_range(0, letters.len())] as char);
+    }
+    if spec.add_digit {
+        password.inner_mut().push(rng.gen_range(b'0', b'9' + 1) as char);
+    }
+    Ok(password)
+}
+
+/// The length and kinds of characters of a password, without the password itself.
+#[derive(Debug, PartialEq)]
+pub struct PasswordShape {
+    pub len: usize,
+    pub lowercase: bool,
+    pub uppercase: bool,
+    pub digits: bool,
+    pub symbols: bool,
+}
+
+impl PasswordShape {
+    /// Looks at the password one character at a time, so it is never copied.
+    pub fn of(password: &SafeString) -> PasswordShape {
+        let mut shape = PasswordShape {
+            len: 0,
+            lowercase: false,
+            uppercase: false,
+            digits: false,
+            symbols: false,
+        };
+        for c in password.expose().chars() {
+            shape.len += 1;
+            if c.is_ascii_lowercase() {
+                shape.lowercase = true;
+            } else if c.is_ascii_uppercase() {
+                shape.uppercase = true;
+            } else if c.is_ascii_digit() {
+                shape.digits = true;
+            } else {
+                // Non-ASCII characters can't be generated, punctuation is the closest thing.
+                shape.symbols = true;
+            }
+        }
+        shape
+    }
+
+    pub fn describe(&self) -> String {
+        let mut classes = Vec::new();
+        if self.lowercase {
+            classes.push("lowercase letters");
+        }
+        if self.uppercase {
+            classes.push("uppercase letters");
+        }
+        if self.digits {
+            classes.push("digits");
+        }
+        if self.symbols {
+            classes.push("symbols");
+        }
+
+        let classes = match classes.len() {
+            0 => "nothing".to_owned(),
+            1 => classes[0].to_owned(),
+            n => format!("{} and {}", classes[..n - 1].join(", "), classes[n - 1]),
+        };
+        format!("{} characters with {}", self.len, classes)
+    }
+
+    pub fn alphabet_len(&self) -> usize {
+        self.alphabet().len()
+    }
+
+    /// Roughly, since passwords that miss a kind of character are never generated.
+    pub fn entropy_bits(&self) -> f64 {
+        self.len as f64 * (self.alphabet_len() as f64).log2()
+    }
+
+    fn alphabet(&self) -> Vec<u8> {
+        (33u8..127)
+            .filter(|&b| {
+                let c = b as char;
+                (self.lowercase && c.is_ascii_lowercase()) ||
+                (self.uppercase && c.is_ascii_uppercase()) ||
+                (self.digits && c.is_ascii_digit()) ||
+                (self.symbols && c.is_ascii_punctuation())
+            })
+            .collect()
+    }
+}
+
+/// Generates a password with the same length and kinds of characters as the shape, with at
+/// least one character of each kind.
+pub fn generate_password_with_shape(shape: &PasswordShape) -> IoResult<SafeString> {
+    let alphabet = shape.alphabet();
+    assert!(!alphabet.is_empty() || shape.len == 0);
+
+    let mut rng = OsRng::new()?;
+    loop {
+        let mut password = SafeString::new(String::with_capacity(shape.len));
+        for _ in 0..shape.len {
+            password.inner_mut().push(alphabet[rng.gen_range(0, alphabet.len())] as char);
+        }
+        if PasswordShape::of(&password) == *shape {
+            return Ok(password);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{PassphraseSpec, PasswordShape, PronounceableSpec, CONSONANTS, VOWELS,
+                digit_from_byte, generate_passphrase, generate_password_with_shape,
+                generate_pin, generate_pronounceable_password, wordlist};
+    use safe_string::SafeString;
+
+    fn shape_of(s: &str) -> PasswordShape {
+        PasswordShape::of(&SafeString::new(s.to_owned()))
+    }
+
+    #[test]
+    fn shape_inference() {
+        let shapes = [("hunter2", 7, true, false, true, false),
+                      ("1234", 4, false, false, true, false),
+                      ("CorrectHorse", 12, true, true, false, false),
+                      ("k8#Qz-é", 7, true, true, true, true),
+                      ("", 0, false, false, false, false)];
+        for &(password, len, lowercase, uppercase, digits, symbols) in shapes.iter() {
+            assert_eq!(shape_of(password),
+                       PasswordShape {
+                           len: len,
+                           lowercase: lowercase,
+                           uppercase: uppercase,
+                           digits: digits,
+                           symbols: symbols,
+                       });
+        }
+    }
+
+    #[test]
+    fn shape_description() {
+        assert_eq!(shape_of("1234").describe(), "4 characters with digits");
+        assert_eq!(shape_of("ab12").describe(),
+                   "4 characters with lowercase letters and digits");
+        assert_eq!(shape_of("aB1!").describe(),
+                   "4 characters with lowercase letters, uppercase letters, digits and symbols");
+    }
+
+    #[test]
+    fn generated_passwords_have_the_same_shape() {
+        for password in ["hunter2", "1234", "CorrectHorse", "k8#Qz-é", "x", "!!"].iter() {
+            let shape = shape_of(password);
+            for _ in 0..20 {
+                assert_eq!(PasswordShape::of(&generate_password_with_shape(&shape).unwrap()),
+                           shape);
+            }
+        }
+    }
+
+    #[test]
+    fn wordlist_has_unique_lowercase_words() {
+        let words = wordlist();
+        // Five dice, 6^5, minus the four words with a dash.
+        assert_eq!(words.len(), 7772);
+        let mut unique = words.clone();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(unique.len(), words.len());
+        // No dash, so that passphrases split back into their words with the default separator.
+        assert!(words.iter().all(|w| !w.is_empty() && w.chars().all(|c| c.is_ascii_lowercase())));
+    }
+
+    #[test]
+    fn passphrases_follow_the_spec() {
+        let spec = PassphraseSpec {
+            words: 6,
+            separator: " ".to_owned(),
+            capitalize: false,
+            add_digit: false,
+        };
+        let passphrase = generate_passphrase(&spec).unwrap();
+        let words: Vec<&str> = passphrase.expose().split(' ').collect();
+        assert_eq!(words.len(), 6);
+        assert!(words.iter().all(|w| wordlist().contains(w)));
+        assert_eq!(format!("{:.1}", spec.entropy_bits()), "77.5");
+
+        let spec = PassphraseSpec {
+            words: 4,
+            separator: "_".to_owned(),
+            capitalize: true,
+            add_digit: true,
+        };
+        let passphrase = generate_passphrase(&spec).unwrap();
+        let (words, digit) = passphrase.expose().split_at(passphrase.expose().len() - 1);
+        assert!(digit.chars().all(|c| c.is_ascii_digit()));
+        for word in words.split('_') {
+            assert!(word.chars().next().unwrap().is_ascii_uppercase());
+            assert!(wordlist().contains(&word.to_lowercase().as_str()));
+        }
+    }
+
+    #[test]
+    fn pronounceable_passwords_alternate_consonants_and_vowels() {
+        for len in 4..40 {
+            for &add_digit in [false, true].iter() {
+                let spec = PronounceableSpec { len: len, add_digit: add_digit };
+                let password = generate_pronounceable_password(&spec).unwrap();
+                let bytes = password.expose().as_bytes();
+                assert_eq!(bytes.len(), len);
+
+                for (i, b) in bytes.iter().enumerate() {
+                    if add_digit && i == len - 1 {
+                        assert!(b.is_ascii_digit(), "{}", password.expose());
+                    } else if i % 2 == 0 {
+                        assert!(CONSONANTS.contains(b), "{}", password.expose());
+                    } else {
+                        assert!(VOWELS.contains(b), "{}", password.expose());
                     }
-                    parsed_len
-                }
-                Err(_) => {
-                    println_err!("Woops! The length option must be a valid number, for instance \
-                                  8 or 16.");
-                    return None;
                 }
             }
         }
-        Some(PasswordSpec {
-                 alnum: alnum,
-                 len: password_len,
-             })
+    }
+
+    #[test]
+    fn pronounceable_passwords_are_weaker_than_random_ones() {
+        let spec = PronounceableSpec { len: 16, add_digit: false };
+        assert_eq!(spec.entropy_bits(), 8.0 * 4.0 + 8.0 * 5f64.log2());
+        assert!(spec.entropy_bits() < spec.random_entropy_bits());
+    }
+
+    #[test]
+    fn pins_only_have_digits() {
+        for len in 1..20 {
+            let pin = generate_pin(len).unwrap();
+            assert_eq!(pin.expose().len(), len);
+            assert!(pin.expose().chars().all(|c| c.is_ascii_digit()));
+        }
+    }
+
+    #[test]
+    fn every_digit_is_as_likely() {
+        let mut counts = [0; 10];
+        for byte in 0..256 {
+            if let Some(digit) = digit_from_byte(byte as u8) {
+                counts[digit.to_digit(10).unwrap() as usize] += 1;
+            }
+        }
+        assert_eq!(counts, [25; 10]);
     }
 }
@@ -0,0 +1,120 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! With $ROOSTER_GIT set, a password file that lives in a git work tree is committed after
+//! every change, and with --git-sync, pulled before and pushed after. The file is always
+//! written first, so git failing never loses a change. Commit messages only hold the command
+//! name, since the history of the repository may be seen by others.
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+pub const ROOSTER_GIT_ENV_VAR: &'static str = "ROOSTER_GIT";
+
+/// Runs git in `dir`, and returns what it printed, or why it failed.
+fn git(dir: &Path, args: &[&str]) -> Result<String, String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|err| format!("I couldn't run git (reason: {})", err))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_owned();
+        return Err(format!("`git {}` failed: {}", args.join(" "), stderr));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// The directory to run git in, if the password file is inside a work tree.
+pub fn work_tree_of(file: &Path) -> Option<PathBuf> {
+    let dir = match file.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    match git(&dir, &["rev-parse", "--is-inside-work-tree"]) {
+        Ok(ref inside) if inside.trim() == "true" => Some(dir),
+        _ => None,
+    }
+}
+
+/// The files git couldn't merge, from `git status --porcelain`.
+pub fn conflicted_files(status: &str) -> Vec<&str> {
+    status.lines()
+        .filter(|line| {
+            line.len() > 3 &&
+            ["DD", "AU", "UD", "UA", "DU", "AA", "UU"].contains(&&line[..2])
+        })
+        .map(|line| &line[3..])
+        .collect()
+}
+
+/// Why pulling failed.
+#[derive(Debug, PartialEq)]
+pub enum PullError {
+    /// The remote changes conflict with the local ones, which were left as they were.
+    Conflict(String),
+    /// Anything else, like being offline. The local password file can still be used.
+    Failed(String),
+}
+
+/// Brings the changes made on other machines. A rebase that conflicts is undone, so that the
+/// work tree is left as it was, and the conflict is told instead.
+pub fn pull(dir: &Path) -> Result<(), PullError> {
+    let err = match git(dir, &["pull", "--rebase", "--quiet"]) {
+        Ok(_) => return Ok(()),
+        Err(err) => err,
+    };
+    let status = git(dir, &["status", "--porcelain"]).unwrap_or_default();
+    let conflicts = conflicted_files(&status);
+    if conflicts.is_empty() {
+        return Err(PullError::Failed(err));
+    }
+    let _ = git(dir, &["rebase", "--abort"]);
+    Err(PullError::Conflict(format!("the changes from the remote conflict with yours in {}. \
+                                     Merge them with git in {}, then try again",
+                                    conflicts.join(", "),
+                                    dir.display())))
+}
+
+/// Commits the password file alone, whatever else is staged. Returns false when there was
+/// nothing to commit.
+pub fn commit(dir: &Path, file: &Path, command: &str) -> Result<bool, String> {
+    let file = file.file_name().map(Path::new).unwrap_or(file);
+    let file = file.to_string_lossy();
+    git(dir, &["add", "--", &file])?;
+    if git(dir, &["diff", "--cached", "--quiet", "--", &file]).is_ok() {
+        return Ok(false);
+    }
+    let message = format!("rooster: {}", command);
+    git(dir, &["commit", "--quiet", "-m", &message, "--", &file])?;
+    Ok(true)
+}
+
+pub fn push(dir: &Path) -> Result<(), String> {
+    git(dir, &["push", "--quiet"]).map(|_| ())
+}
+
+#[cfg(test)]
+mod test {
+    use super::conflicted_files;
+
+    #[test]
+    fn conflicts_are_read_from_the_status() {
+        let status = "UU passwords.rooster\n M notes.txt\nAA other.rooster\n?? new\n";
+        assert_eq!(conflicted_files(status), vec!["passwords.rooster", "other.rooster"]);
+        assert!(conflicted_files("").is_empty());
+    }
+}
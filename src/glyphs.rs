@@ -0,0 +1,163 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Decorative characters, like the star in front of favorites, don't exist in every terminal.
+//! They all go through `Glyph`, which knows a Unicode and an ASCII version of each of them.
+
+use sys;
+use std::env;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Charset {
+    Ascii,
+    Unicode,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Glyph {
+    /// In front of favorite passwords.
+    Star,
+    /// In front of the highlighted password, when choosing one with the arrow keys.
+    Pointer,
+}
+
+impl Glyph {
+    /// The Unicode and ASCII versions of every glyph. The ASCII version must have the same width,
+    /// so that columns stay aligned.
+    fn variants(&self) -> (&'static str, &'static str) {
+        match *self {
+            Glyph::Star => ("\u{2605}", "*"),
+            Glyph::Pointer => ("\u{276f}", ">"),
+        }
+    }
+
+    /// Usually called with `charset()`, the charset of the terminal.
+    pub fn to_str(&self, charset: Charset) -> &'static str {
+        let (unicode, ascii) = self.variants();
+        match charset {
+            Charset::Unicode => unicode,
+            Charset::Ascii => ascii,
+        }
+    }
+}
+
+static USE_ASCII: AtomicBool = AtomicBool::new(false);
+
+pub fn set_charset(charset: Charset) {
+    USE_ASCII.store(charset == Charset::Ascii, Ordering::SeqCst);
+}
+
+pub fn charset() -> Charset {
+    if USE_ASCII.load(Ordering::SeqCst) {
+        Charset::Ascii
+    } else {
+        Charset::Unicode
+    }
+}
+
+/// Guesses whether the terminal can show UTF-8, from the locale.
+///
+/// The C library is asked first, since it knows about aliases and defaults. If the locale isn't
+/// installed, the locale variables are parsed instead.
+pub fn detect_charset() -> Charset {
+    if let Some(codeset) = sys::locale_codeset() {
+        return charset_of_codeset(codeset.as_str());
+    }
+
+    let lc_all = env::var("LC_ALL").ok();
+    let lc_ctype = env::var("LC_CTYPE").ok();
+    let lang = env::var("LANG").ok();
+    match locale_from_env(lc_all.as_ref().map(|s| s.as_str()),
+                          lc_ctype.as_ref().map(|s| s.as_str()),
+                          lang.as_ref().map(|s| s.as_str())) {
+        Some(locale) => charset_of_locale(locale),
+        None => Charset::Ascii,
+    }
+}
+
+/// The first non-empty variable wins, in the order POSIX gives them.
+fn locale_from_env<'a>(lc_all: Option<&'a str>,
+                       lc_ctype: Option<&'a str>,
+                       lang: Option<&'a str>)
+                       -> Option<&'a str> {
+    [lc_all, lc_ctype, lang].iter().filter_map(|v| *v).find(|v| !v.is_empty())
+}
+
+/// Locales look like "language[_territory][.codeset][@modifier]", eg. "en_US.UTF-8@euro".
+/// Without a codeset, the C library doesn't use UTF-8.
+fn charset_of_locale(locale: &str) -> Charset {
+    let locale = match locale.find('@') {
+        Some(i) => &locale[..i],
+        None => locale,
+    };
+    match locale.find('.') {
+        Some(i) => charset_of_codeset(&locale[i + 1..]),
+        None => Charset::Ascii,
+    }
+}
+
+/// "UTF-8", "utf8" and "UTF_8" are all spelled in the wild.
+fn charset_of_codeset(codeset: &str) -> Charset {
+    let codeset: String = codeset.chars()
+        .filter(|&c| c != '-' && c != '_')
+        .collect();
+    if codeset.to_lowercase() == "utf8" {
+        Charset::Unicode
+    } else {
+        Charset::Ascii
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Charset, Glyph, charset_of_codeset, charset_of_locale, locale_from_env};
+
+    #[test]
+    fn locale_variables_precedence() {
+        assert_eq!(locale_from_env(Some("C"), Some("en_US.UTF-8"), None), Some("C"));
+        assert_eq!(locale_from_env(Some(""), Some("en_US.UTF-8"), Some("C")),
+                   Some("en_US.UTF-8"));
+        assert_eq!(locale_from_env(None, None, Some("fr_FR")), Some("fr_FR"));
+        assert_eq!(locale_from_env(None, Some(""), None), None);
+    }
+
+    #[test]
+    fn charsets_of_locales() {
+        assert_eq!(charset_of_locale("en_US.UTF-8"), Charset::Unicode);
+        assert_eq!(charset_of_locale("de_DE.utf8@euro"), Charset::Unicode);
+        assert_eq!(charset_of_locale("C.UTF-8"), Charset::Unicode);
+        assert_eq!(charset_of_locale("C"), Charset::Ascii);
+        assert_eq!(charset_of_locale("POSIX"), Charset::Ascii);
+        assert_eq!(charset_of_locale("en_US"), Charset::Ascii);
+        assert_eq!(charset_of_locale("ru_RU.KOI8-R"), Charset::Ascii);
+    }
+
+    #[test]
+    fn charsets_of_codesets() {
+        assert_eq!(charset_of_codeset("UTF-8"), Charset::Unicode);
+        assert_eq!(charset_of_codeset("utf_8"), Charset::Unicode);
+        assert_eq!(charset_of_codeset("ANSI_X3.4-1968"), Charset::Ascii);
+    }
+
+    #[test]
+    fn ascii_glyphs_are_ascii() {
+        for glyph in [Glyph::Star, Glyph::Pointer].iter() {
+            let ascii = glyph.to_str(Charset::Ascii);
+            let unicode = glyph.to_str(Charset::Unicode);
+            assert!(ascii.is_ascii());
+            assert_eq!(ascii.chars().count(), unicode.chars().count());
+        }
+    }
+}
@@ -0,0 +1,189 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The help texts, kept as data so that `rooster -h` and `rooster man` show the same thing.
+
+use std::fmt;
+
+/// A help text: blocks separated by blank lines.
+pub struct Help {
+    blocks: Vec<Block>,
+}
+
+/// A paragraph of the help, under a title like "Usage" or "Examples" or without one.
+pub struct Block {
+    pub title: Option<&'static str>,
+    pub parts: Vec<Part>,
+}
+
+pub enum Part {
+    /// Prose, already broken into lines for the terminal.
+    Text(Vec<String>),
+    /// Indented lines kept as they are, like commands to type.
+    Commands(Vec<String>),
+    /// Names and their descriptions, like options. Descriptions start `width` columns after the
+    /// indentation and may span several lines, separated with '\n'.
+    Table(usize, Vec<(String, String)>),
+}
+
+fn owned(lines: &[&str]) -> Vec<String> {
+    lines.iter().map(|line| (*line).to_owned()).collect()
+}
+
+impl Help {
+    pub fn new() -> Help {
+        Help { blocks: Vec::new() }
+    }
+
+    pub fn blocks(&self) -> &[Block] {
+        &self.blocks
+    }
+
+    fn block(mut self, title: Option<&'static str>, part: Part) -> Help {
+        self.blocks.push(Block {
+            title: title,
+            parts: vec![part],
+        });
+        self
+    }
+
+    /// Adds a part to the last block, without a blank line before it.
+    fn then(mut self, part: Part) -> Help {
+        self.blocks.last_mut().expect("nothing to follow").parts.push(part);
+        self
+    }
+
+    /// The ways to run the command, without their indentation.
+    pub fn usage(self, lines: &[&str]) -> Help {
+        self.block(Some("Usage"), Part::Commands(owned(lines)))
+    }
+
+    pub fn examples(self, lines: &[&str]) -> Help {
+        let title = if lines.len() == 1 { "Example" } else { "Examples" };
+        self.block(Some(title), Part::Commands(owned(lines)))
+    }
+
+    pub fn text(self, lines: &[&str]) -> Help {
+        self.block(None, Part::Text(owned(lines)))
+    }
+
+    pub fn commands(self, lines: &[&str]) -> Help {
+        self.block(None, Part::Commands(owned(lines)))
+    }
+
+    pub fn table(self, title: &'static str, width: usize, rows: &[(&str, &str)]) -> Help {
+        self.block(Some(title), table(width, rows))
+    }
+
+    pub fn then_text(self, lines: &[&str]) -> Help {
+        self.then(Part::Text(owned(lines)))
+    }
+
+    pub fn then_commands(self, lines: &[&str]) -> Help {
+        self.then(Part::Commands(owned(lines)))
+    }
+
+    pub fn then_table(self, width: usize, rows: &[(&str, &str)]) -> Help {
+        self.then(table(width, rows))
+    }
+}
+
+fn table(width: usize, rows: &[(&str, &str)]) -> Part {
+    let rows = rows.iter().map(|&(name, description)| (name.to_owned(), description.to_owned()));
+    Part::Table(width, rows.collect())
+}
+
+/// Long options without a short one are indented further, so that they line up with the long
+/// names of "-s, --show".
+fn indented_name(name: &str) -> String {
+    if name.starts_with("--") {
+        format!("    {}", name)
+    } else {
+        name.to_owned()
+    }
+}
+
+/// The terminal rendering, for -h.
+impl fmt::Display for Help {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, block) in self.blocks.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            if let Some(title) = block.title {
+                writeln!(f, "{}:", title)?;
+            }
+            for part in block.parts.iter() {
+                match *part {
+                    Part::Text(ref lines) => {
+                        for line in lines.iter() {
+                            writeln!(f, "{}", line)?;
+                        }
+                    }
+                    Part::Commands(ref lines) => {
+                        for line in lines.iter() {
+                            writeln!(f, "    {}", line)?;
+                        }
+                    }
+                    Part::Table(width, ref rows) => {
+                        for &(ref name, ref description) in rows.iter() {
+                            let name = indented_name(name);
+                            let mut lines = description.lines();
+                            if name.len() < width {
+                                let first = lines.next().unwrap_or("");
+                                writeln!(f, "    {:<width$}{}", name, first, width = width)?;
+                            } else {
+                                writeln!(f, "    {}", name)?;
+                            }
+                            for line in lines {
+                                writeln!(f, "    {:width$}{}", "", line, width = width)?;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Help;
+
+    #[test]
+    fn renders_blocks_separated_by_blank_lines() {
+        let help = Help::new()
+            .usage(&["rooster get -h", "rooster get <query>"])
+            .text(&["Finds a password, for instance:"])
+            .then_commands(&["rooster get ytb"])
+            .examples(&["rooster get youtube"]);
+        assert_eq!(help.to_string(),
+                   "Usage:\n    rooster get -h\n    rooster get <query>\n\nFinds a password, for \
+                    instance:\n    rooster get ytb\n\nExample:\n    rooster get youtube\n");
+    }
+
+    #[test]
+    fn lines_up_table_descriptions() {
+        let help = Help::new().table("Options",
+                                     18,
+                                     &[("-h, --help", "Display a help message"),
+                                       ("--json", "Print JSON"),
+                                       ("--similar-to <query>", "Generate a password\nlike it")]);
+        assert_eq!(help.to_string(),
+                   "Options:\n    -h, --help        Display a help message\n        --json        \
+                    Print JSON\n        --similar-to <query>\n                      Generate a \
+                    password\n                      like it\n");
+    }
+}
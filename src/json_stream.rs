@@ -0,0 +1,299 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! Reads the elements of a top-level JSON array one at a time, like the output of
+//! `rooster export`, so that importing a large file doesn't need the whole file in memory.
+//!
+//! Elements are only split here, `serde_json` parses each of them afterwards.
+
+use safe_string::SafeString;
+use safe_vec::SafeVec;
+use std::io::{BufReader, Bytes, Read};
+
+enum State {
+    /// Before the opening bracket.
+    Start,
+    /// Right after the opening bracket, where the array may end.
+    First,
+    /// After a comma, where another element must come.
+    Next,
+    /// After the closing bracket, or after an error.
+    Done,
+}
+
+pub struct ArrayElements<R: Read> {
+    bytes: Bytes<BufReader<R>>,
+    state: State,
+    /// The element being read, wiped between elements since it may hold passwords.
+    element: SafeVec,
+    /// Number of elements read so far.
+    count: usize,
+}
+
+impl<R: Read> ArrayElements<R> {
+    pub fn new(reader: R) -> ArrayElements<R> {
+        ArrayElements {
+            bytes: BufReader::new(reader).bytes(),
+            state: State::Start,
+            element: SafeVec::new(Vec::new()),
+            count: 0,
+        }
+    }
+
+    fn next_byte(&mut self) -> Result<Option<u8>, String> {
+        match self.bytes.next() {
+            Some(Ok(b)) => Ok(Some(b)),
+            Some(Err(err)) => Err(format!("I couldn't read the input (reason: {:?})", err)),
+            None => Ok(None),
+        }
+    }
+
+    /// The next byte that isn't whitespace, or an error at the end of the input.
+    fn next_token(&mut self) -> Result<u8, String> {
+        loop {
+            match self.next_byte()? {
+                Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r') => {}
+                Some(b) => return Ok(b),
+                None => return Err("the input ends before the end of the array".to_owned()),
+            }
+        }
+    }
+
+    fn read_start(&mut self) -> Result<(), String> {
+        let mut b = self.next_token()?;
+        // UTF-8 byte order mark.
+        if b == 0xef {
+            if self.next_byte()? != Some(0xbb) || self.next_byte()? != Some(0xbf) {
+                return Err("the input is not valid UTF-8".to_owned());
+            }
+            b = self.next_token()?;
+        }
+        if b != b'[' {
+            return Err("the input is not a JSON array".to_owned());
+        }
+        Ok(())
+    }
+
+    fn read_end(&mut self) -> Result<(), String> {
+        loop {
+            match self.next_byte()? {
+                Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r') => {}
+                Some(_) => return Err("there is something after the end of the array".to_owned()),
+                None => return Ok(()),
+            }
+        }
+    }
+
+    /// Reads an element starting with `first`, up to the comma or bracket that follows it.
+    /// Returns whether the array goes on.
+    fn read_element(&mut self, first: u8) -> Result<bool, String> {
+        self.element.wipe();
+        self.element.inner_mut().clear();
+
+        let mut depth = 0usize;
+        let mut in_string = false;
+        let mut escaped = false;
+        let mut b = first;
+        loop {
+            if in_string {
+                if escaped {
+                    escaped = false;
+                } else if b == b'\\' {
+                    escaped = true;
+                } else if b == b'"' {
+                    in_string = false;
+                }
+            } else {
+                match b {
+                    b'"' => in_string = true,
+                    b'{' | b'[' => depth += 1,
+                    b'}' | b']' if depth > 0 => depth -= 1,
+                    b',' if depth == 0 => return Ok(true),
+                    b']' if depth == 0 => return Ok(false),
+                    b'}' => return Err(format!("entry {} has an unexpected }}", self.count + 1)),
+                    _ => {}
+                }
+            }
+            self.element.inner_mut().push(b);
+
+            b = match self.next_byte()? {
+                Some(b) => b,
+                None => {
+                    return Err(format!("the input ends in the middle of entry {}",
+                                       self.count + 1))
+                }
+            };
+        }
+    }
+
+    fn element_as_string(&mut self) -> Result<SafeString, String> {
+        let len = self.element
+            .expose()
+            .iter()
+            .rposition(|b| !b" \t\n\r".contains(b))
+            .map_or(0, |i| i + 1);
+        if len == 0 {
+            return Err(format!("entry {} is empty", self.count + 1));
+        }
+        match ::std::str::from_utf8(&self.element.expose()[..len]) {
+            Ok(s) => Ok(SafeString::new(s.to_owned())),
+            Err(_) => Err(format!("entry {} is not valid UTF-8", self.count + 1)),
+        }
+    }
+
+    fn read_next(&mut self) -> Result<Option<SafeString>, String> {
+        let first = match self.state {
+            State::Done => return Ok(None),
+            State::Start => {
+                self.read_start()?;
+                self.state = State::First;
+                return self.read_next();
+            }
+            State::First => {
+                match self.next_token()? {
+                    b']' => {
+                        self.state = State::Done;
+                        self.read_end()?;
+                        return Ok(None);
+                    }
+                    b => b,
+                }
+            }
+            State::Next => {
+                match self.next_token()? {
+                    b']' => return Err("there is a comma before the end of the array".to_owned()),
+                    b => b,
+                }
+            }
+        };
+
+        let goes_on = self.read_element(first)?;
+        let element = self.element_as_string()?;
+        self.element.wipe();
+        self.count += 1;
+
+        if goes_on {
+            self.state = State::Next;
+        } else {
+            self.state = State::Done;
+            self.read_end()?;
+        }
+        Ok(Some(element))
+    }
+}
+
+impl<R: Read> Iterator for ArrayElements<R> {
+    type Item = Result<SafeString, String>;
+
+    /// Stops after the first error.
+    fn next(&mut self) -> Option<Result<SafeString, String>> {
+        match self.read_next() {
+            Ok(Some(element)) => Some(Ok(element)),
+            Ok(None) => None,
+            Err(err) => {
+                self.state = State::Done;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ArrayElements;
+    use std::io::Read;
+
+    fn elements(input: &str) -> Result<Vec<String>, String> {
+        ArrayElements::new(input.as_bytes())
+            .map(|e| e.map(|e| e.expose().to_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn split_elements() {
+        assert_eq!(elements(" [ ] "), Ok(vec![]));
+        assert_eq!(elements("\u{feff}[{\"a\": 1}]"), Ok(vec!["{\"a\": 1}".to_owned()]));
+        assert_eq!(elements("[{\"a\": \"],}\\\"{\"}, {\"b\": [1, {\"c\": 2}]}\n]\n"),
+                   Ok(vec!["{\"a\": \"],}\\\"{\"}".to_owned(),
+                           "{\"b\": [1, {\"c\": 2}]}".to_owned()]));
+        assert_eq!(elements("[1, \"é\" ,null]"),
+                   Ok(vec!["1".to_owned(), "\"é\"".to_owned(), "null".to_owned()]));
+    }
+
+    #[test]
+    fn invalid_arrays() {
+        assert!(elements("").is_err());
+        assert!(elements("{}").is_err());
+        assert!(elements("[{}").is_err());
+        assert!(elements("[{},]").is_err());
+        assert!(elements("[,{}]").is_err());
+        assert!(elements("[{}}]").is_err());
+        assert!(elements("[{\"a\": \"]}").is_err());
+        assert!(elements("[{}] x").is_err());
+    }
+
+    /// Generates `[{"name":"app 0",...},...]` without ever holding the whole input.
+    struct GeneratedArray {
+        entries: usize,
+        next: usize,
+        pending: Vec<u8>,
+    }
+
+    impl Read for GeneratedArray {
+        fn read(&mut self, buf: &mut [u8]) -> ::std::io::Result<usize> {
+            if self.pending.is_empty() {
+                if self.next > self.entries {
+                    return Ok(0);
+                }
+                self.pending = match self.next {
+                    0 => b"[".to_vec(),
+                    n if n == self.entries => b"]".to_vec(),
+                    n => {
+                        format!("{}{{\"name\":\"app {}\",\"username\":\"me\",\
+                                 \"password\":\"secret {}\",\"created_at\":1,\"updated_at\":2}}",
+                                if n > 1 { ",\n" } else { "" },
+                                n,
+                                n)
+                            .into_bytes()
+                    }
+                };
+                self.next += 1;
+            }
+            let len = buf.len().min(self.pending.len());
+            buf[..len].copy_from_slice(&self.pending[..len]);
+            self.pending.drain(..len);
+            Ok(len)
+        }
+    }
+
+    #[test]
+    fn large_inputs_are_read_one_element_at_a_time() {
+        let input = GeneratedArray {
+            entries: 50001,
+            next: 0,
+            pending: Vec::new(),
+        };
+        let mut elements = ArrayElements::new(input);
+        let mut count = 0;
+        while let Some(element) = elements.next() {
+            let element = element.unwrap();
+            assert!(element.expose().starts_with("{\"name\":\"app "));
+            count += 1;
+        }
+        assert_eq!(count, 50000);
+        // Only ever one element is buffered, not the input.
+        assert!(elements.element.inner_mut().capacity() < 1024);
+    }
+}
@@ -0,0 +1,46 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! The key file given with --key-file, which password files may need on top of the master
+//! password. Any file will do, as long as it doesn't change: its contents are mixed into the
+//! derivation of the encryption key.
+
+use safe_vec::SafeVec;
+use std::fs::File;
+use std::io::Read;
+use std::sync::Mutex;
+
+static KEY_FILE: Mutex<Option<SafeVec>> = Mutex::new(None);
+
+/// Reads a whole key file.
+pub fn read(path: &str) -> Result<SafeVec, String> {
+    let mut contents = SafeVec::new(Vec::new());
+    File::open(path)
+        .and_then(|mut file| file.read_to_end(&mut contents.inner_mut()))
+        .map_err(|err| format!("I couldn't read the key file {} (reason: {})", path, err))?;
+    if contents.is_empty() {
+        return Err(format!("the key file {} is empty", path));
+    }
+    Ok(contents)
+}
+
+/// Remembers the key file given with --key-file, for as long as Rooster runs.
+pub fn set(contents: SafeVec) {
+    *KEY_FILE.lock().unwrap() = Some(contents);
+}
+
+pub fn get() -> Option<SafeVec> {
+    KEY_FILE.lock().unwrap().clone()
+}
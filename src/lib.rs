@@ -0,0 +1,73 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The password files of Rooster, for programs that want to read or change them without going
+//! through the `rooster` command.
+//!
+//! A password file is opened with the master password into a `PasswordStore`, which holds the
+//! passwords decrypted in memory, and saved with `sync` or `write_to`. Secrets are kept in
+//! `SafeString` and `SafeVec`, which are wiped when dropped. Frontends that can't block while
+//! the key is derived from the master password can open and save stores with `task`, on a
+//! worker thread.
+//!
+//! ```
+//! use rooster::password::v2::{Password, PasswordStore};
+//! use rooster::safe_string::SafeString;
+//! use rooster::safe_vec::SafeVec;
+//!
+//! let master = || SafeString::new("correct horse battery staple".to_owned());
+//!
+//! let mut store = PasswordStore::new(master()).unwrap();
+//! store.add_password(Password::new("YouTube".to_owned(),
+//!                                  "me@example.com".to_owned(),
+//!                                  SafeString::new("hunter2".to_owned())))
+//!     .unwrap();
+//!
+//! let mut file = SafeVec::new(Vec::new());
+//! store.write_to(&mut *file.inner_mut()).unwrap();
+//!
+//! let store = PasswordStore::from_input(master(), file).unwrap();
+//! assert_eq!(store.get_password("YouTube").unwrap().password.expose(), "hunter2");
+//! ```
+//!
+//! Programs that link this library should also use `memory::ScrubbingAllocator` as their
+//! global allocator, so that secrets aren't left behind when their buffers grow.
+
+extern crate libc;
+extern crate crypto;
+extern crate rand;
+extern crate byteorder;
+extern crate serde;
+extern crate serde_json;
+extern crate regex;
+extern crate argon2;
+
+#[macro_use]
+extern crate serde_derive;
+
+#[cfg(test)]
+#[global_allocator]
+static ALLOCATOR: memory::ScrubbingAllocator = memory::ScrubbingAllocator;
+
+pub mod aes;
+pub mod atomic_file;
+pub mod collation;
+pub mod ffi;
+pub mod generate;
+pub mod memory;
+pub mod password;
+pub mod safe_string;
+pub mod safe_vec;
+pub mod task;
+pub mod timestamp;
@@ -12,9 +12,19 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use password::v2::{Password, PasswordStore};
+use exit_code::ExitCode;
+use answer;
+use getopts;
+use output_format;
+use collation::Collation;
+use color::Color;
+use ffi;
+use glyphs::{self, Charset, Glyph};
+use timestamp::{self, Zone};
+use password::v2::{normalize_tag, Matching, Password, PasswordStore};
+use selector;
 use std::io::Write;
-use std::io::stdin;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering as AtomicOrdering};
 
 /// Used to indicate lists should have a number, ie: 23 Google my.account@gmail.com
 pub const WITH_NUMBERS: bool = true;
@@ -27,7 +37,33 @@ pub enum OutputStream {
     Stderr,
 }
 
+static PORCELAIN: AtomicBool = AtomicBool::new(false);
+
+/// With --porcelain, lists are printed in a format that scripts can rely on, see
+/// `format_porcelain_list_of_passwords`.
+pub fn set_porcelain(porcelain: bool) {
+    PORCELAIN.store(porcelain, AtomicOrdering::SeqCst);
+}
+
+pub fn porcelain() -> bool {
+    PORCELAIN.load(AtomicOrdering::SeqCst)
+}
+
 pub fn print_list_of_passwords(passwords: &Vec<&Password>, with_numbers: bool, output_stream: OutputStream) {
+    let lines = if porcelain() {
+        format_porcelain_list_of_passwords(passwords, with_numbers)
+    } else {
+        format_list_of_passwords(passwords, with_numbers, glyphs::charset())
+    };
+    for s in lines {
+        match output_stream {
+            OutputStream::Stdout => println_stdout!("{}", s),
+            OutputStream::Stderr => println_stderr_always!("{}", s)
+        }
+    }
+}
+
+fn format_list_of_passwords(passwords: &Vec<&Password>, with_numbers: bool, charset: Charset) -> Vec<String> {
     // Find the app name column length
     let longest_app_name = passwords.iter().fold(0, |acc, p| if p.name.len() > acc {
         p.name.len()
@@ -38,12 +74,28 @@ pub fn print_list_of_passwords(passwords: &Vec<&Password>, with_numbers: bool, o
     // Find the number column length
     let i_width = ((passwords.len() as f64).log10() + 1 as f64).floor() as usize;
 
+    // Favorites get a star, the other passwords are padded to keep the columns aligned
+    let any_favorite = passwords.iter().any(|p| p.favorite);
+
+    let mut lines = Vec::with_capacity(passwords.len());
     for (i, p) in passwords.iter().enumerate() {
+        let star = match (any_favorite, p.favorite) {
+            (false, _) => String::new(),
+            (true, true) => {
+                format!("{}{}{} ",
+                        Color::Yellow.to_color_code(),
+                        Glyph::Star.to_str(charset),
+                        Color::Reset.to_color_code())
+            }
+            (true, false) => "  ".to_owned(),
+        };
+
         let s = match with_numbers {
             WITH_NUMBERS => {
                 format!(
-                    "{:i_width$} {:app_name_width$} {:30}",
+                    "{:i_width$} {}{:app_name_width$} {:30}",
                     i + 1,
+                    star,
                     p.name,
                     p.username,
                     i_width = i_width,
@@ -52,7 +104,8 @@ pub fn print_list_of_passwords(passwords: &Vec<&Password>, with_numbers: bool, o
             }
             WITHOUT_NUMBERS => {
                 format!(
-                    "{:app_name_width$} {:30}",
+                    "{}{:app_name_width$} {:30}",
+                    star,
                     p.name,
                     p.username,
                     app_name_width = longest_app_name
@@ -60,14 +113,75 @@ pub fn print_list_of_passwords(passwords: &Vec<&Password>, with_numbers: bool, o
             }
         };
 
-        match output_stream {
-            OutputStream::Stdout => println!("{}", s),
-            OutputStream::Stderr => println_stderr!("{}", s)
-        }
+        lines.push(s);
     }
+    lines
 }
 
-fn request_password_index_from_stdin(passwords: &Vec<&Password>, prompt: &str) -> usize {
+/// One line per password, with the name and the username separated by a tab, and the number
+/// first if asked. There is no padding, color or decoration, and tabs and line breaks within
+/// fields are replaced with spaces. This format is stable.
+/// A name or username on a single line, without the tabs that separate porcelain fields.
+pub fn porcelain_field(value: &str) -> String {
+    value.replace(|c| c == '\t' || c == '\n' || c == '\r', " ")
+}
+
+fn format_porcelain_list_of_passwords(passwords: &Vec<&Password>,
+                                      with_numbers: bool)
+                                      -> Vec<String> {
+    let field = porcelain_field;
+    passwords.iter()
+        .enumerate()
+        .map(|(i, p)| match with_numbers {
+            WITH_NUMBERS => format!("{}\t{}\t{}", i + 1, field(&p.name), field(&p.username)),
+            WITHOUT_NUMBERS => format!("{}\t{}", field(&p.name), field(&p.username)),
+        })
+        .collect()
+}
+
+/// Like `print_list_of_passwords`, with the creation and update dates of each password.
+pub fn print_verbose_list_of_passwords(passwords: &Vec<&Password>) {
+    let now = ffi::time();
+    for s in format_verbose_list_of_passwords(passwords, now, Zone::Local, glyphs::charset()) {
+        println_stdout!("{}", s);
+    }
+}
+
+fn format_verbose_list_of_passwords(passwords: &Vec<&Password>,
+                                    now: ffi::time_t,
+                                    zone: Zone,
+                                    charset: Charset)
+                                    -> Vec<String> {
+    let name_width = passwords.iter().map(|p| p.name.chars().count()).max().unwrap_or(0);
+    let username_width = passwords.iter().map(|p| p.username.chars().count()).max().unwrap_or(0);
+    let any_favorite = passwords.iter().any(|p| p.favorite);
+
+    passwords.iter()
+        .map(|p| {
+            let star = match (any_favorite, p.favorite) {
+                (false, _) => String::new(),
+                (true, true) => {
+                    format!("{}{}{} ",
+                            Color::Yellow.to_color_code(),
+                            Glyph::Star.to_str(charset),
+                            Color::Reset.to_color_code())
+                }
+                (true, false) => "  ".to_owned(),
+            };
+            format!("{}{:name_width$}  {:username_width$}  created {}  updated {}",
+                    star,
+                    p.name,
+                    p.username,
+                    timestamp::to_date_in(p.created_at, zone),
+                    timestamp::to_date_and_age(p.updated_at, now, zone),
+                    name_width = name_width,
+                    username_width = username_width)
+        })
+        .collect()
+}
+
+/// `None` if there is no answer to read.
+fn request_password_index_from_stdin(passwords: &Vec<&Password>, prompt: &str) -> Option<usize> {
     assert!(!passwords.is_empty());
 
     // Read the index from the command line and convert to a number
@@ -75,14 +189,15 @@ fn request_password_index_from_stdin(passwords: &Vec<&Password>, prompt: &str) -
     loop {
 
         if passwords.len() > 1 {
-            println_stderr!("{}", prompt);
-            println_stderr!("Type in number from 1 to {}", passwords.len());
+            println_stderr_always!("{}", prompt);
+            println_stderr_always!("Type in number from 1 to {}", passwords.len());
         } else if passwords.len() == 1 {
-            println_stderr!("If this is the password you mean, type number 1.");
+            println_stderr_always!("If this is the password you mean, type number 1.");
         }
 
         line.clear();
-        match stdin().read_line(&mut line) {
+        match answer::read_line(&mut line) {
+            Ok(0) => return None,
             Ok(_) => {
                 match line.trim().parse::<usize>() {
                     Ok(index) => {
@@ -91,7 +206,7 @@ fn request_password_index_from_stdin(passwords: &Vec<&Password>, prompt: &str) -
                             continue;
                         }
 
-                        return index - 1;
+                        return Some(index - 1);
                     }
                     Err(err) => {
                         println_err!("This isn't a valid number (reason: {}). Let's try again (1 to {}): ", err, passwords.len());
@@ -100,17 +215,24 @@ fn request_password_index_from_stdin(passwords: &Vec<&Password>, prompt: &str) -
                 };
             }
             Err(err) => {
-                println_err!("I couldn't read that (reason: {}). Let's try again (1 to {}): ", err, passwords.len());
+                println_err!("I couldn't read that (reason: {}).", err);
+                return None;
             }
         }
     }
 }
 
+/// Lets the user pick a password with the arrow keys, or by typing its number when there is no
+/// terminal for that. `None` if they cancelled.
 pub fn choose_password_in_list(
     passwords: &Vec<&Password>,
     with_numbers: bool,
     prompt: &str,
-) -> usize {
+) -> Option<usize> {
+    answer::require_interactive("a choice among the matching passwords");
+    if let Some(choice) = selector::choose(passwords, prompt) {
+        return choice;
+    }
     print_list_of_passwords(passwords, with_numbers, OutputStream::Stderr);
     println_stderr!("");
     request_password_index_from_stdin(passwords, prompt)
@@ -121,19 +243,410 @@ pub fn search_and_choose_password<'a>(
     query: &str,
     with_numbers: bool,
     prompt: &str,
-) -> Option<&'a Password> {
-    let passwords = store.search_passwords(query);
-    if passwords.len() == 0 {
-        println_stderr!("I can't find any passwords for \"{}\"", query);
-        return None;
+) -> Result<&'a Password, ExitCode> {
+    search_and_choose_filtered_password(store, query, |_| true, with_numbers, prompt)
+}
+
+/// What a query refers to.
+pub enum Resolution<'a> {
+    /// The query is the name of this password.
+    Found(&'a Password),
+    /// The query may refer to any of these passwords (fuzzy matching or --regex only).
+    Choose(Vec<&'a Password>),
+    /// Nothing matches, these passwords are close though.
+    NotFound(Vec<&'a Password>),
+}
+
+/// How many close passwords are suggested when nothing matches.
+const MAX_SUGGESTIONS: usize = 5;
+
+/// Every command that looks up a password by name goes through here, so that they all follow
+/// the matching mode of the store.
+pub fn resolve_password<'a, F>(store: &'a PasswordStore, query: &str, filter: F) -> Resolution<'a>
+    where F: Fn(&Password) -> bool
+{
+    let mut passwords: Vec<&Password> = store.search_passwords(query)
+        .into_iter()
+        .filter(|p| filter(p))
+        .collect();
+    if sort_order_is_set() {
+        sort_passwords(&mut passwords, sort_order(), store.collation());
     }
+    sort_favorites_first(&mut passwords);
 
     if let Some(&password) = passwords.iter().find(|p| {
         p.name.to_lowercase() == query.to_lowercase()
     }) {
-        return Some(&password)
+        return Resolution::Found(password);
+    }
+
+    match store.matching() {
+        _ if store.searches_with_regex() && !passwords.is_empty() => Resolution::Choose(passwords),
+        Matching::Fuzzy if !passwords.is_empty() => Resolution::Choose(passwords),
+        Matching::Fuzzy | Matching::Exact => {
+            passwords.truncate(MAX_SUGGESTIONS);
+            Resolution::NotFound(passwords)
+        }
+    }
+}
+
+/// What to say when nothing matches the query.
+fn not_found_message(store: &PasswordStore, query: &str) -> String {
+    if store.searches_with_regex() {
+        return format!("I can't find any passwords matching \"{}\"", query);
+    }
+    match store.matching() {
+        Matching::Exact => format!("No exact match for \"{}\"", query),
+        Matching::Fuzzy => format!("I can't find any passwords for \"{}\"", query),
+    }
+}
+
+/// The only password the query refers to, for when there is no one to choose between several
+/// (in scripts, or when picking the wrong one would go unnoticed).
+pub fn resolve_unique_password<'a, F>(store: &'a PasswordStore,
+                                      query: &str,
+                                      filter: F)
+                                      -> Result<&'a Password, ExitCode>
+    where F: Fn(&Password) -> bool
+{
+    match resolve_password(store, query, filter) {
+        Resolution::Found(password) => Ok(password),
+        Resolution::Choose(ref passwords) if passwords.len() == 1 => Ok(passwords[0]),
+        Resolution::Choose(passwords) => {
+            let names: Vec<&str> = passwords.iter().map(|p| p.name.as_str()).collect();
+            println_err!("Woops, \"{}\" matches several passwords: {}. Please use the full name.",
+                         query,
+                         names.join(", "));
+            Err(ExitCode::Error)
+        }
+        Resolution::NotFound(_) => {
+            output_format::set_app(query);
+            println_err!("Woops! {}.", not_found_message(store, query));
+            Err(ExitCode::NotFound)
+        }
+    }
+}
+
+/// Same as `search_and_choose_password`, but only considers the passwords accepted by `filter`.
+pub fn search_and_choose_filtered_password<'a, F>(
+    store: &'a PasswordStore,
+    query: &str,
+    filter: F,
+    with_numbers: bool,
+    prompt: &str,
+) -> Result<&'a Password, ExitCode>
+    where F: Fn(&Password) -> bool
+{
+    let resolution = resolve_password(store, query, filter);
+
+    if let Resolution::NotFound(_) = resolution {
+        output_format::set_app(query);
+        let archived = store.get_password(query).map_or(false, |p| p.archived);
+        if archived && !store.includes_archived() {
+            println_err!("Woops, \"{}\" is archived, use --archived to include archived \
+                          passwords.",
+                         query);
+            return Err(ExitCode::NotFound);
+        }
+    }
+
+    match resolution {
+        Resolution::Found(password) => Ok(password),
+        Resolution::Choose(passwords) => {
+            match choose_password_in_list(&passwords, with_numbers, prompt) {
+                Some(index) => Ok(passwords[index]),
+                None => {
+                    println_stderr!("Alright, I didn't pick any password.");
+                    Err(ExitCode::Error)
+                }
+            }
+        }
+        Resolution::NotFound(ref suggestions) if suggestions.is_empty() => {
+            println_stderr_always!("{}", not_found_message(store, query));
+            Err(ExitCode::NotFound)
+        }
+        Resolution::NotFound(suggestions) => {
+            let names: Vec<&str> = suggestions.iter().map(|p| p.name.as_str()).collect();
+            println_stderr_always!("{}. Did you mean {}?",
+                                   not_found_message(store, query),
+                                   names.join(", "));
+            println_stderr_always!("Exact matching is on, use --fuzzy to pick from close names.");
+            Err(ExitCode::NotFound)
+        }
+    }
+}
+
+/// What lists of passwords are sorted by, see --sort.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SortKey {
+    Name,
+    Created,
+    Updated,
+}
+
+impl SortKey {
+    const ALL: &'static [SortKey] = &[SortKey::Name, SortKey::Created, SortKey::Updated];
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            SortKey::Name => "name",
+            SortKey::Created => "created",
+            SortKey::Updated => "updated",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<SortKey> {
+        SortKey::ALL.iter().cloned().find(|key| key.name() == name)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SortOrder {
+    pub key: SortKey,
+    pub reverse: bool,
+}
+
+static SORT_KEY: AtomicUsize = AtomicUsize::new(0);
+static SORT_REVERSE: AtomicBool = AtomicBool::new(false);
+static SORT_SET: AtomicBool = AtomicBool::new(false);
+
+/// Set once from --sort and --reverse, for every list shown by this process.
+pub fn set_sort_order(order: SortOrder) {
+    let index = SortKey::ALL.iter().position(|&key| key == order.key).unwrap();
+    SORT_KEY.store(index, AtomicOrdering::SeqCst);
+    SORT_REVERSE.store(order.reverse, AtomicOrdering::SeqCst);
+    SORT_SET.store(true, AtomicOrdering::SeqCst);
+}
+
+/// The order asked for with --sort and --reverse, by name by default.
+pub fn sort_order() -> SortOrder {
+    SortOrder {
+        key: SortKey::ALL[SORT_KEY.load(AtomicOrdering::SeqCst)],
+        reverse: SORT_REVERSE.load(AtomicOrdering::SeqCst),
     }
+}
+
+/// Search results keep the order of the search unless --sort or --reverse is given.
+fn sort_order_is_set() -> bool {
+    SORT_SET.load(AtomicOrdering::SeqCst)
+}
+
+/// Sorts passwords by the key of the order, then by name for ties, so that the result doesn't
+/// depend on the order of the file. Only the key is reversed.
+pub fn sort_passwords(passwords: &mut Vec<&Password>, order: SortOrder, collation: Collation) {
+    passwords.sort_by(|a, b| {
+        let by_name = collation.compare(&a.name, &b.name);
+        let by_key = match order.key {
+            SortKey::Name => by_name,
+            SortKey::Created => a.created_at.cmp(&b.created_at),
+            SortKey::Updated => a.updated_at.cmp(&b.updated_at),
+        };
+        let by_key = if order.reverse { by_key.reverse() } else { by_key };
+        by_key.then(by_name)
+    });
+}
+
+/// Moves favorites before the other passwords, keeping the order within each group.
+pub fn sort_favorites_first(passwords: &mut Vec<&Password>) {
+    passwords.sort_by_key(|p| !p.favorite);
+}
+
+/// The words after the command name, which all have to match, like `rooster get aws prod`.
+pub fn query_from_matches(matches: &getopts::Matches) -> Option<String> {
+    if matches.free.len() < 2 {
+        return None;
+    }
+    Some(matches.free[1..].join(" "))
+}
+
+/// Reads the tags given with --tag, normalized and without duplicates.
+pub fn tags_from_matches(matches: &getopts::Matches) -> Result<Vec<String>, ExitCode> {
+    let mut tags: Vec<String> = Vec::new();
+    for tag in matches.opt_strs("tag") {
+        let tag = normalize_tag(tag.as_str()).map_err(|err| {
+            println_err!("Woops, {}.", err);
+            ExitCode::Error
+        })?;
+        if !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    Ok(tags)
+}
+
+/// True if the password has all the tags.
+pub fn has_all_tags(password: &Password, tags: &[String]) -> bool {
+    tags.iter().all(|tag| password.has_tag(tag))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{format_list_of_passwords, format_porcelain_list_of_passwords,
+                format_verbose_list_of_passwords, resolve_password, sort_favorites_first,
+                sort_passwords, Resolution, SortKey, SortOrder, WITH_NUMBERS, WITHOUT_NUMBERS};
+    use collation::Collation;
+    use glyphs::Charset;
+    use password::v2::{Matching, Password, PasswordStore};
+    use safe_string::SafeString;
+    use timestamp::Zone;
+
+    fn password(name: &str, favorite: bool) -> Password {
+        Password {
+            favorite: favorite,
+            ..Password::new(name.to_owned(), "me".to_owned(), SafeString::new("x".to_owned()))
+        }
+    }
+
+    #[test]
+    fn favorites_are_sorted_first() {
+        let (a, b, c, d) = (password("a", false),
+                            password("b", true),
+                            password("c", false),
+                            password("d", true));
+        let mut passwords = vec![&a, &b, &c, &d];
+        sort_favorites_first(&mut passwords);
 
-    let index = choose_password_in_list(&passwords, with_numbers, prompt);
-    Some(passwords[index])
+        let names: Vec<&str> = passwords.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["b", "d", "a", "c"]);
+    }
+
+    #[test]
+    fn sorting_by_timestamps_falls_back_to_names() {
+        let with_times = |name: &str, created_at, updated_at| {
+            Password {
+                created_at: created_at,
+                updated_at: updated_at,
+                ..password(name, false)
+            }
+        };
+        let (a, b, c, d) = (with_times("bravo", 10, 40),
+                            with_times("Alpha", 20, 30),
+                            with_times("charlie", 10, 30),
+                            with_times("delta", 30, 20));
+        let sorted = |key, reverse| {
+            let mut passwords = vec![&a, &b, &c, &d];
+            sort_passwords(&mut passwords,
+                           SortOrder {
+                               key: key,
+                               reverse: reverse,
+                           },
+                           Collation::Codepoint);
+            passwords.iter().map(|p| p.name.as_str()).collect::<Vec<&str>>()
+        };
+
+        assert_eq!(sorted(SortKey::Name, false), vec!["Alpha", "bravo", "charlie", "delta"]);
+        assert_eq!(sorted(SortKey::Name, true), vec!["delta", "charlie", "bravo", "Alpha"]);
+        assert_eq!(sorted(SortKey::Created, false), vec!["bravo", "charlie", "Alpha", "delta"]);
+        assert_eq!(sorted(SortKey::Created, true), vec!["delta", "Alpha", "bravo", "charlie"]);
+        assert_eq!(sorted(SortKey::Updated, false), vec!["delta", "Alpha", "charlie", "bravo"]);
+        assert_eq!(SortKey::from_name("updated"), Some(SortKey::Updated));
+        assert_eq!(SortKey::from_name("size"), None);
+    }
+
+    #[test]
+    fn porcelain_list_renderings() {
+        let (youtube, github) = (password("You\tTube", false), password("GitHub", true));
+        let passwords = vec![&github, &youtube];
+
+        assert_eq!(format_porcelain_list_of_passwords(&passwords, WITHOUT_NUMBERS),
+                   vec!["GitHub\tme", "You Tube\tme"]);
+        assert_eq!(format_porcelain_list_of_passwords(&passwords, WITH_NUMBERS),
+                   vec!["1\tGitHub\tme", "2\tYou Tube\tme"]);
+    }
+
+    #[test]
+    fn verbose_list_renderings() {
+        let now = 1614859200;
+        let (github, forum) = (Password {
+                                   created_at: now - 400 * 86400,
+                                   updated_at: now - 95 * 86400,
+                                   ..password("GitHub", false)
+                               },
+                               Password {
+                                   username: "someone@example.com".to_owned(),
+                                   created_at: now - 2 * 86400,
+                                   updated_at: now - 2 * 86400,
+                                   ..password("Fórum", false)
+                               });
+        let passwords = vec![&forum, &github];
+
+        let utc = Zone::Offset(0);
+        let lines = format_verbose_list_of_passwords(&passwords, now, utc, Charset::Ascii);
+        assert_eq!(lines,
+                   vec!["Fórum   someone@example.com  created 2021-03-02  updated 2021-03-02 \
+                         (2 days ago)",
+                        "GitHub  me                   created 2020-01-29  updated 2020-11-29 \
+                         (3 months ago)"]);
+    }
+
+    #[test]
+    fn list_renderings() {
+        let (youtube, github) = (password("YouTube", false), password("GitHub", true));
+        let passwords = vec![&github, &youtube];
+
+        assert_eq!(format_list_of_passwords(&passwords, WITHOUT_NUMBERS, Charset::Unicode),
+                   vec!["\x1b[33m\u{2605}\x1b[39m GitHub  me                            ",
+                        "  YouTube me                            "]);
+        assert_eq!(format_list_of_passwords(&passwords, WITHOUT_NUMBERS, Charset::Ascii),
+                   vec!["\x1b[33m*\x1b[39m GitHub  me                            ",
+                        "  YouTube me                            "]);
+    }
+
+    #[test]
+    fn chooser_renderings() {
+        let (youtube, github) = (password("YouTube", false), password("GitHub", true));
+        let passwords = vec![&github, &youtube];
+
+        assert_eq!(format_list_of_passwords(&passwords, WITH_NUMBERS, Charset::Unicode),
+                   vec!["1 \x1b[33m\u{2605}\x1b[39m GitHub  me                            ",
+                        "2   YouTube me                            "]);
+        assert_eq!(format_list_of_passwords(&passwords, WITH_NUMBERS, Charset::Ascii),
+                   vec!["1 \x1b[33m*\x1b[39m GitHub  me                            ",
+                        "2   YouTube me                            "]);
+    }
+
+    #[test]
+    fn renderings_without_favorites_have_no_star_column() {
+        let youtube = password("YouTube", false);
+        for &charset in [Charset::Unicode, Charset::Ascii].iter() {
+            assert_eq!(format_list_of_passwords(&vec![&youtube], WITHOUT_NUMBERS, charset),
+                       vec!["YouTube me                            "]);
+        }
+    }
+
+    fn names(passwords: &[&Password]) -> Vec<String> {
+        passwords.iter().map(|p| p.name.clone()).collect()
+    }
+
+    #[test]
+    fn near_misses_depend_on_matching() {
+        let mut store = PasswordStore::new(SafeString::new("master".to_owned())).unwrap();
+        store.add_password(password("YouTube", false)).unwrap();
+        store.add_password(password("Yahoo", false)).unwrap();
+
+        match resolve_password(&store, "ytb", |_| true) {
+            Resolution::Choose(passwords) => assert_eq!(names(&passwords), vec!["YouTube"]),
+            _ => panic!("fuzzy matching should offer YouTube"),
+        }
+        match resolve_password(&store, "youtube", |_| true) {
+            Resolution::Found(password) => assert_eq!(password.name, "YouTube"),
+            _ => panic!("exact names should be found right away"),
+        }
+
+        store.override_matching(Matching::Exact);
+        match resolve_password(&store, "ytb", |_| true) {
+            Resolution::NotFound(suggestions) => {
+                assert_eq!(names(&suggestions), vec!["YouTube"])
+            }
+            _ => panic!("exact matching should only suggest YouTube"),
+        }
+        match resolve_password(&store, "YOUTUBE", |_| true) {
+            Resolution::Found(password) => assert_eq!(password.name, "YouTube"),
+            _ => panic!("exact matching ignores casing"),
+        }
+        match resolve_password(&store, "github", |_| true) {
+            Resolution::NotFound(suggestions) => assert!(suggestions.is_empty()),
+            _ => panic!("nothing is close to github"),
+        }
+    }
 }
@@ -2,12 +2,25 @@
 #[allow(unused_imports)]
 use std::io::Write;
 
+// Printed even with --quiet: errors, what the user asked for, and what they need to see to
+// answer a question.
+#[macro_export]
+macro_rules! println_stderr_always(
+    ($($arg:tt)*) => (
+        if !::output_format::json_errors() {
+            match writeln!(&mut ::console::stderr(), $($arg)*) {
+                Ok(_) => {},
+                Err(x) => panic!("Unable to write to stderr: {}", x),
+            }
+        }
+    )
+);
+
 #[macro_export]
 macro_rules! println_stderr(
     ($($arg:tt)*) => (
-        match writeln!(&mut ::std::io::stderr(), $($arg)*) {
-            Ok(_) => {},
-            Err(x) => panic!("Unable to write to stderr: {}", x),
+        if !::quiet::is_enabled() {
+            println_stderr_always!($($arg)*)
         }
     )
 );
@@ -15,7 +28,18 @@ macro_rules! println_stderr(
 #[macro_export]
 macro_rules! println_err(
     ($($args:tt)*) => (
-        println_stderr!("{}", format!("{}{}{}", ::color::Color::Red.to_color_code(), format!($($args)*), ::color::Color::Reset.to_color_code()))
+        if ::output_format::json_errors() {
+            ::output_format::record(format!($($args)*))
+        } else {
+            println_stderr_always!("{}", format!("{}{}{}", ::color::Color::Red.to_color_code(), format!($($args)*), ::color::Color::Reset.to_color_code()))
+        }
+    )
+);
+
+#[macro_export]
+macro_rules! println_requested(
+    ($($args:tt)*) => (
+        println_stderr_always!("{}", format!("{}{}{}", ::color::Color::Green.to_color_code(), format!($($args)*), ::color::Color::Reset.to_color_code()))
     )
 );
 
@@ -36,9 +60,9 @@ macro_rules! println_title(
 #[macro_export]
 macro_rules! print_stderr(
     ($($arg:tt)*) => (
-        match write!(::std::io::stderr(), $($arg)*) {
+        match write!(::console::stderr(), $($arg)*) {
             Ok(_) => {
-                match ::std::io::stderr().flush() {
+                match ::console::stderr().flush() {
                     Ok(_) => {},
                     Err(x) => panic!("Unable to write to stderr: {}", x)
                 }
@@ -51,9 +75,9 @@ macro_rules! print_stderr(
 #[macro_export]
 macro_rules! print_stdout(
     ($($arg:tt)*) => (
-        match write!(::std::io::stdout(), $($arg)*) {
+        match write!(::console::stdout(), $($arg)*) {
             Ok(_) => {
-                match ::std::io::stdout().flush() {
+                match ::console::stdout().flush() {
                     Ok(_) => {},
                     Err(x) => panic!("Unable to write to stdout: {}", x)
                 }
@@ -62,3 +86,13 @@ macro_rules! print_stdout(
         }
     )
 );
+
+#[macro_export]
+macro_rules! println_stdout(
+    () => (
+        print_stdout!("\n")
+    );
+    ($($args:tt)*) => (
+        print_stdout!("{}\n", format!($($args)*))
+    )
+);
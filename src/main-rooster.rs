@@ -14,59 +14,106 @@
 
 // #![allow(useless_format, too_many_arguments)]
 
-extern crate libc;
 extern crate getopts;
+extern crate libc;
 extern crate crypto;
 extern crate rpassword;
 extern crate rand;
-extern crate byteorder;
 extern crate quale;
 extern crate serde;
 extern crate serde_json;
 extern crate clipboard;
 extern crate shell_escape;
+extern crate regex;
+extern crate rooster;
 
 #[macro_use]
 extern crate serde_derive;
 
 use std::fs::File;
 use std::env;
-use std::env::VarError;
-use std::path::MAIN_SEPARATOR as PATH_SEP;
+use std::ffi::OsString;
 use std::io::Result as IoResult;
 use std::io::Error as IoError;
 use std::io::ErrorKind as IoErrorKind;
 use std::io::Write;
 use std::io::Read;
 use std::path::{Path, PathBuf};
-use getopts::Options;
-use rpassword::prompt_password_stderr;
 use safe_string::SafeString;
 use safe_vec::SafeVec;
+use exit_code::ExitCode;
+use help::Help;
 use std::ops::Deref;
+use std::time::{Duration, Instant};
+use std::sync::Mutex;
+
+use rooster::{atomic_file, collation, ffi, generate, memory, password, safe_string, safe_vec,
+              timestamp};
+
+#[global_allocator]
+static ALLOCATOR: memory::ScrubbingAllocator = memory::ScrubbingAllocator;
+
+/// The master password from $ROOSTER_MASTER_PASSWORD or --master-password-file, with where it
+/// comes from. $ROOSTER_MASTER_PASSWORD is removed from the environment as soon as Rooster starts.
+static GIVEN_MASTER_PASSWORD: Mutex<Option<(SafeString, String)>> = Mutex::new(None);
 
 mod macros;
-mod aes;
+mod console;
 mod commands;
-mod ffi;
-mod password;
 mod color;
-mod safe_string;
-mod safe_vec;
-mod generate;
 mod clip;
 mod list;
+mod glyphs;
+mod csv;
+mod json_stream;
+mod notify;
+mod sys;
+mod strength;
+mod selector;
+mod askpass;
+mod answer;
+mod key_file;
+mod yubikey;
+mod quiet;
+mod profiles;
+mod output_format;
+mod exit_code;
+mod git;
+mod file_state;
+mod upgrade;
+mod help;
+mod options;
 
 const ROOSTER_FILE_ENV_VAR: &'static str = "ROOSTER_FILE";
 const ROOSTER_FILE_DEFAULT: &'static str = ".passwords.rooster";
+/// In %APPDATA%\rooster, where files don't need to be hidden.
+const ROOSTER_FILE_WINDOWS: &'static str = "passwords.rooster";
+const ROOSTER_HISTORY_DEPTH_ENV_VAR: &'static str = "ROOSTER_HISTORY_DEPTH";
+const ROOSTER_NOTIFY_AFTER_ENV_VAR: &'static str = "ROOSTER_NOTIFY_AFTER";
+const ROOSTER_SELECTION_ENV_VAR: &'static str = "ROOSTER_SELECTION";
+const ROOSTER_MASTER_PASSWORD_ENV_VAR: &'static str = "ROOSTER_MASTER_PASSWORD";
+const ROOSTER_MASTER_PASSWORD_FILE_ENV_VAR: &'static str = "ROOSTER_MASTER_PASSWORD_FILE";
+const ROOSTER_NON_INTERACTIVE_ENV_VAR: &'static str = "ROOSTER_NON_INTERACTIVE";
 const DONT_CREATE_PASSWORD_FILE: &'static str = "DONT_CREATE_PASSWORD_FILE";
 const FAIL_READING_NEW_PASSWORD: &'static str = "FAIL_READING_NEW_PASSWORD";
 
 struct Command {
     name: &'static str,
-    callback_exec: Option<fn(&getopts::Matches, &mut password::v2::PasswordStore) -> Result<(), i32>>,
-    callback_help: fn(),
-    callback_without_store: Option<fn(&getopts::Matches) -> Result<(), i32>>,
+    callback_exec: Option<fn(&getopts::Matches, &mut password::v2::PasswordStore) -> Result<(), ExitCode>>,
+    callback_help: fn() -> Help,
+    callback_without_store: Option<fn(&getopts::Matches) -> Result<(), ExitCode>>,
+    /// Whether the password file has to be opened for these arguments. Without it, it has to be
+    /// whenever there is a `callback_exec`. When it doesn't, `callback_without_store` runs the
+    /// command on its own.
+    needs_store: Option<fn(&getopts::Matches) -> bool>,
+    /// Whether the command reads its input from stdin for these arguments, so that nothing else
+    /// may ask questions there.
+    reads_stdin: Option<fn(&getopts::Matches) -> bool>,
+    /// Whether what the command reads from stdin starts on its first line, so that the master
+    /// password can't be read there too.
+    takes_all_of_stdin: bool,
+    /// Runs once the command succeeded and the password file was saved, with its path.
+    after_save: Option<fn(&str)>,
 }
 
 static COMMANDS: &'static [Command] = &[Command {
@@ -74,91 +121,512 @@ static COMMANDS: &'static [Command] = &[Command {
      callback_exec: Some(commands::get::callback_exec),
      callback_help: commands::get::callback_help,
      callback_without_store: Some(commands::get::check_args),
+     needs_store: None,
+     reads_stdin: None,
+     takes_all_of_stdin: false,
+     after_save: None,
  },
  Command {
      name: "add",
      callback_exec: Some(commands::add::callback_exec),
      callback_help: commands::add::callback_help,
      callback_without_store: Some(commands::add::check_args),
+     needs_store: None,
+     reads_stdin: Some(commands::add::reads_stdin),
+     takes_all_of_stdin: true,
+     after_save: None,
+ },
+ Command {
+     name: "add-or-update",
+     callback_exec: Some(commands::add_or_update::callback_exec),
+     callback_help: commands::add_or_update::callback_help,
+     callback_without_store: Some(commands::add_or_update::check_args),
+     needs_store: None,
+     reads_stdin: Some(commands::add_or_update::reads_stdin),
+     takes_all_of_stdin: false,
+     after_save: None,
  },
  Command {
      name: "delete",
      callback_exec: Some(commands::delete::callback_exec),
      callback_help: commands::delete::callback_help,
      callback_without_store: Some(commands::delete::check_args),
+     needs_store: None,
+     reads_stdin: None,
+     takes_all_of_stdin: false,
+     after_save: None,
  },
  Command {
      name: "generate",
      callback_exec: Some(commands::generate::callback_exec),
      callback_help: commands::generate::callback_help,
      callback_without_store: Some(commands::generate::check_args),
+     needs_store: None,
+     reads_stdin: None,
+     takes_all_of_stdin: false,
+     after_save: None,
+ },
+ Command {
+     name: "gen",
+     callback_exec: None,
+     callback_help: commands::gen::callback_help,
+     callback_without_store: Some(commands::gen::callback_exec),
+     needs_store: None,
+     reads_stdin: None,
+     takes_all_of_stdin: false,
+     after_save: None,
  },
  Command {
      name: "regenerate",
      callback_exec: Some(commands::regenerate::callback_exec),
      callback_help: commands::regenerate::callback_help,
      callback_without_store: Some(commands::regenerate::check_args),
+     needs_store: None,
+     reads_stdin: None,
+     takes_all_of_stdin: false,
+     after_save: None,
  },
  Command {
      name: "list",
      callback_exec: Some(commands::list::callback_exec),
      callback_help: commands::list::callback_help,
      callback_without_store: None,
+     needs_store: None,
+     reads_stdin: None,
+     takes_all_of_stdin: false,
+     after_save: None,
+ },
+ Command {
+     name: "import",
+     callback_exec: Some(commands::import::callback_exec),
+     callback_help: commands::import::callback_help,
+     callback_without_store: Some(commands::import::check_args),
+     needs_store: None,
+     reads_stdin: None,
+     takes_all_of_stdin: false,
+     after_save: None,
  },
  Command {
      name: "export",
      callback_exec: Some(commands::export::callback_exec),
      callback_help: commands::export::callback_help,
      callback_without_store: None,
+     needs_store: None,
+     reads_stdin: None,
+     takes_all_of_stdin: false,
+     after_save: None,
+ },
+ Command {
+     name: "clear-all",
+     callback_exec: Some(commands::clear_all::callback_exec),
+     callback_help: commands::clear_all::callback_help,
+     callback_without_store: None,
+     needs_store: None,
+     reads_stdin: None,
+     takes_all_of_stdin: false,
+     after_save: None,
  },
  Command {
      name: "set-master-password",
      callback_exec: Some(commands::set_master_password::callback_exec),
      callback_help: commands::set_master_password::callback_help,
      callback_without_store: None,
+     needs_store: None,
+     reads_stdin: None,
+     takes_all_of_stdin: false,
+     after_save: None,
+ },
+ Command {
+     name: "set-kdf",
+     callback_exec: Some(commands::set_kdf::callback_exec),
+     callback_help: commands::set_kdf::callback_help,
+     callback_without_store: Some(commands::set_kdf::check_args),
+     needs_store: None,
+     reads_stdin: None,
+     takes_all_of_stdin: false,
+     after_save: None,
+ },
+ Command {
+     name: "kdf-benchmark",
+     callback_exec: Some(commands::kdf_benchmark::callback_exec),
+     callback_help: commands::kdf_benchmark::callback_help,
+     callback_without_store: Some(commands::kdf_benchmark::callback_without_store),
+     needs_store: Some(commands::kdf_benchmark::needs_store),
+     reads_stdin: None,
+     takes_all_of_stdin: false,
+     after_save: None,
+ },
+ Command {
+     name: "rekey",
+     callback_exec: Some(commands::rekey::callback_exec),
+     callback_help: commands::rekey::callback_help,
+     callback_without_store: None,
+     needs_store: None,
+     reads_stdin: None,
+     takes_all_of_stdin: false,
+     after_save: Some(commands::rekey::print_new_fingerprint),
  },
  Command {
      name: "rename",
      callback_exec: Some(commands::rename::callback_exec),
      callback_help: commands::rename::callback_help,
      callback_without_store: Some(commands::rename::check_args),
+     needs_store: None,
+     reads_stdin: None,
+     takes_all_of_stdin: false,
+     after_save: None,
  },
  Command {
      name: "transfer",
      callback_exec: Some(commands::transfer::callback_exec),
      callback_help: commands::transfer::callback_help,
      callback_without_store: Some(commands::transfer::check_args),
+     needs_store: None,
+     reads_stdin: None,
+     takes_all_of_stdin: false,
+     after_save: None,
  },
  Command {
      name: "change",
      callback_exec: Some(commands::change::callback_exec),
      callback_help: commands::change::callback_help,
      callback_without_store: Some(commands::change::check_args),
+     needs_store: None,
+     reads_stdin: None,
+     takes_all_of_stdin: false,
+     after_save: None,
+ },
+ Command {
+     name: "edit",
+     callback_exec: Some(commands::edit::callback_exec),
+     callback_help: commands::edit::callback_help,
+     callback_without_store: Some(commands::edit::check_args),
+     needs_store: None,
+     reads_stdin: None,
+     takes_all_of_stdin: false,
+     after_save: None,
+ },
+ Command {
+     name: "note",
+     callback_exec: Some(commands::note::callback_exec),
+     callback_help: commands::note::callback_help,
+     callback_without_store: Some(commands::note::check_args),
+     needs_store: None,
+     reads_stdin: Some(commands::note::reads_stdin),
+     takes_all_of_stdin: false,
+     after_save: None,
+ },
+ Command {
+     name: "tag",
+     callback_exec: Some(commands::tag::callback_exec),
+     callback_help: commands::tag::callback_help,
+     callback_without_store: Some(commands::tag::check_args),
+     needs_store: None,
+     reads_stdin: None,
+     takes_all_of_stdin: false,
+     after_save: None,
+ },
+ Command {
+     name: "favorite",
+     callback_exec: Some(commands::favorite::callback_exec),
+     callback_help: commands::favorite::callback_help,
+     callback_without_store: Some(commands::favorite::check_args),
+     needs_store: None,
+     reads_stdin: None,
+     takes_all_of_stdin: false,
+     after_save: None,
+ },
+ Command {
+     name: "archive",
+     callback_exec: Some(commands::archive::callback_exec),
+     callback_help: commands::archive::callback_help,
+     callback_without_store: None,
+     needs_store: None,
+     reads_stdin: None,
+     takes_all_of_stdin: false,
+     after_save: None,
+ },
+ Command {
+     name: "unarchive",
+     callback_exec: Some(commands::unarchive::callback_exec),
+     callback_help: commands::unarchive::callback_help,
+     callback_without_store: None,
+     needs_store: None,
+     reads_stdin: None,
+     takes_all_of_stdin: false,
+     after_save: None,
+ },
+ Command {
+     name: "history",
+     callback_exec: Some(commands::history::callback_exec),
+     callback_help: commands::history::callback_help,
+     callback_without_store: Some(commands::history::check_args),
+     needs_store: None,
+     reads_stdin: None,
+     takes_all_of_stdin: false,
+     after_save: None,
+ },
+ Command {
+     name: "dedupe",
+     callback_exec: Some(commands::dedupe::callback_exec),
+     callback_help: commands::dedupe::callback_help,
+     callback_without_store: None,
+     needs_store: None,
+     reads_stdin: None,
+     takes_all_of_stdin: false,
+     after_save: None,
+ },
+ Command {
+     name: "merge",
+     callback_exec: Some(commands::merge::callback_exec),
+     callback_help: commands::merge::callback_help,
+     callback_without_store: None,
+     needs_store: None,
+     reads_stdin: None,
+     takes_all_of_stdin: false,
+     after_save: None,
+ },
+ Command {
+     name: "doctor",
+     callback_exec: Some(commands::doctor::callback_exec),
+     callback_help: commands::doctor::callback_help,
+     callback_without_store: None,
+     needs_store: None,
+     reads_stdin: None,
+     takes_all_of_stdin: false,
+     after_save: None,
+ },
+ Command {
+     name: "config",
+     callback_exec: Some(commands::config::callback_exec),
+     callback_help: commands::config::callback_help,
+     callback_without_store: None,
+     needs_store: None,
+     reads_stdin: None,
+     takes_all_of_stdin: false,
+     after_save: None,
+ },
+ Command {
+     name: "profile",
+     callback_exec: None,
+     callback_help: commands::profile::callback_help,
+     callback_without_store: Some(commands::profile::callback_exec),
+     needs_store: None,
+     reads_stdin: None,
+     takes_all_of_stdin: false,
+     after_save: None,
+ },
+ Command {
+     name: "batch",
+     callback_exec: Some(commands::batch::callback_exec),
+     callback_help: commands::batch::callback_help,
+     callback_without_store: Some(commands::batch::check_args),
+     needs_store: None,
+     reads_stdin: Some(commands::batch::reads_stdin),
+     takes_all_of_stdin: false,
+     after_save: Some(commands::batch::after_save),
+ },
+ Command {
+     name: "init",
+     callback_exec: None,
+     callback_help: commands::init::callback_help,
+     callback_without_store: Some(commands::init::callback_exec),
+     needs_store: None,
+     reads_stdin: None,
+     takes_all_of_stdin: false,
+     after_save: None,
+ },
+ Command {
+     name: "restore",
+     callback_exec: None,
+     callback_help: commands::restore::callback_help,
+     callback_without_store: Some(commands::restore::callback_exec),
+     needs_store: None,
+     reads_stdin: None,
+     takes_all_of_stdin: false,
+     after_save: None,
+ },
+ Command {
+     name: "undo",
+     callback_exec: None,
+     callback_help: commands::undo::callback_help,
+     callback_without_store: Some(commands::undo::callback_exec),
+     needs_store: None,
+     reads_stdin: None,
+     takes_all_of_stdin: false,
+     after_save: None,
+ },
+ Command {
+     name: "clear",
+     callback_exec: None,
+     callback_help: commands::clear::callback_help,
+     callback_without_store: Some(commands::clear::callback_exec),
+     needs_store: None,
+     reads_stdin: None,
+     takes_all_of_stdin: false,
+     after_save: None,
  },
  Command {
      name: "uninstall",
      callback_exec: None,
      callback_help: commands::uninstall::callback_help,
      callback_without_store: Some(commands::uninstall::callback_exec),
+     needs_store: None,
+     reads_stdin: None,
+     takes_all_of_stdin: false,
+     after_save: None,
+ },
+ Command {
+     name: "completions",
+     callback_exec: None,
+     callback_help: commands::completions::callback_help,
+     callback_without_store: Some(commands::completions::callback_exec),
+     needs_store: None,
+     reads_stdin: None,
+     takes_all_of_stdin: false,
+     after_save: None,
+ },
+ // Not listed in the help, it's for packagers.
+ Command {
+     name: "man",
+     callback_exec: None,
+     callback_help: commands::man::callback_help,
+     callback_without_store: Some(commands::man::callback_exec),
+     needs_store: None,
+     reads_stdin: None,
+     takes_all_of_stdin: false,
+     after_save: None,
  }];
 
+/// Other names for commands, from coreutils and other password managers. Real command names
+/// always win, so `gen` stays its own command rather than an alias of `generate`.
+static ALIASES: &'static [(&'static str, &'static str)] = &[("ls", "list"),
+                                                             ("rm", "delete"),
+                                                             ("del", "delete"),
+                                                             ("new", "add"),
+                                                             ("mv", "rename"),
+                                                             ("show", "get")];
+
+/// Whether the command opens the password file, with these arguments.
+fn needs_store(command: &Command, matches: &getopts::Matches) -> bool {
+    command.callback_exec.is_some() && command.needs_store.map_or(true, |needs| needs(matches))
+}
+
 fn command_from_name(name: &str) -> Option<&'static Command> {
     for c in COMMANDS.iter() {
         if c.name == name {
             return Some(c);
         }
     }
+    for &(alias, command_name) in ALIASES.iter() {
+        if alias == name {
+            return command_from_name(command_name);
+        }
+    }
     None
 }
 
+/// How many characters to insert, delete or replace to go from `a` to `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..b.len() + 1).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, &cb) in b.iter().enumerate() {
+            let replace = previous[j] + if ca == cb { 0 } else { 1 };
+            current.push(replace.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// The command or alias closest to a mistyped name, if it's close enough to be a typo.
+fn closest_command_name(name: &str) -> Option<&'static str> {
+    COMMANDS.iter()
+        .map(|c| c.name)
+        .chain(ALIASES.iter().map(|&(alias, _)| alias))
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|&(distance, candidate)| distance <= 2 && distance < candidate.len())
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, candidate)| candidate)
+}
+
 fn open_password_file(filename: &str, create: bool) -> IoResult<File> {
     let mut options = std::fs::OpenOptions::new();
     options.read(true);
     options.write(true);
     options.create(create);
+    // Only used when the file is created, whatever the umask.
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+
+        options.mode(0o600);
+    }
     options.open(&Path::new(filename))
 }
 
+/// Warns when other users can read the password file, and makes it private with `fix` or if the
+/// user agrees. The user is only asked when `may_ask` is set, since the answer would otherwise be
+/// taken from the command's own input. Also warns when anyone can replace the file.
+#[cfg(unix)]
+fn check_password_file_permissions(path: &str, fix: bool, may_ask: bool) {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = match std::fs::metadata(path) {
+        Ok(metadata) => metadata.permissions().mode(),
+        Err(_) => return,
+    };
+    if mode & 0o077 != 0 {
+        println_err!("Warning: other users can read your password file {} (mode {:04o}). It is \
+                      encrypted, but they could try to guess your master password.",
+                     path,
+                     mode & 0o7777);
+        // Asking is not possible with --non-interactive, and the warning is enough.
+        let fix = fix ||
+                  (may_ask && !answer::is_non_interactive() && {
+            print_stderr!("Would you like to make it private (y/n)? ");
+            let mut line = String::new();
+            answer::read_line(&mut line).is_ok() && line.starts_with('y')
+        });
+        if !fix {
+            println_stderr!("You can make it private with `chmod 600 {}`, or with \
+                             --fix-permissions.",
+                            path);
+        } else {
+            match std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode & !0o077)) {
+                Ok(()) => println_ok!("Done! Only you can read your password file now."),
+                Err(err) => {
+                    println_err!("Woops, I couldn't make it private (reason: {}).", err);
+                }
+            }
+        }
+    }
+
+    let dir = match Path::new(path).parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let dir_mode = std::fs::metadata(dir).map(|metadata| metadata.permissions().mode());
+    if dir_mode.unwrap_or(0) & 0o002 != 0 {
+        println_err!("Warning: any user can change {}, so they could replace your password file.",
+                     dir.display());
+    }
+}
+
+#[cfg(not(unix))]
+fn check_password_file_permissions(_path: &str, _fix: bool, _may_ask: bool) {}
+
+/// How to set an environment variable for good, to tell the user.
+fn set_env_var_command(name: &str, value: &str) -> String {
+    if cfg!(windows) {
+        format!("setx {} \"{}\"", name, value)
+    } else {
+        format!("export {}={}", name, value)
+    }
+}
+
 // Look for Dropbox folder.
 //
 // If you want support for other cloud services, please open an issue
@@ -200,6 +668,7 @@ fn get_password_file(filename: &str,
         Err(err) => {
             match err.kind() {
                 IoErrorKind::NotFound => {
+                    answer::require_interactive("whether to create a password file");
                     let mut show_default_no_file_msg = true;
 
                     if let Some(dropbox_folder) = get_dropbox_folder() {
@@ -218,15 +687,17 @@ fn get_password_file(filename: &str,
                             println_stderr!("");
                             print_stderr!("Is that your correct password file (y/n)? ");
                             let mut line = String::new();
-                            std::io::stdin().read_line(&mut line)?;
+                            answer::read_line(&mut line)?;
                             if line.starts_with('y') {
                                 println_stderr!("");
                                 println_title!("|------------- Configuration ------------|");
                                 println_stderr!("");
                                 println_stderr!("You might want to add this to your shell config \
                                                  (.bashrc, .zshrc, etc):");
-                                println_stderr!("    export ROOSTER_FILE={}",
-                                                file_in_dropbox.to_string_lossy());
+                                println_stderr!("    {}",
+                                                set_env_var_command(ROOSTER_FILE_ENV_VAR,
+                                                                    &file_in_dropbox
+                                                                        .to_string_lossy()));
                                 println_stderr!("");
                                 println_stderr!("This way, I won't ask you if this is the right \
                                                  file every time.");
@@ -258,7 +729,7 @@ fn get_password_file(filename: &str,
                         }
 
                         let mut line = String::new();
-                        std::io::stdin().read_line(&mut line)?;
+                        answer::read_line(&mut line)?;
                         if line.starts_with('y') {
                             println_stderr!("");
                             println_stderr!("Alright, will do! But first, there is some stuff we \
@@ -274,10 +745,10 @@ fn get_password_file(filename: &str,
                                              protected.");
                             println_stderr!("");
 
-                            let master_password = prompt_password_stderr("What would you like it \
-                                                                          to be? ");
+                            let master_password =
+                                answer::prompt_password("What would you like it to be? ",
+                                                        "a new master password");
                             let master_password = master_password
-                                .map(SafeString::new)
                                 .map_err(|_| {
                                              IoError::new(IoErrorKind::Other,
                                                           FAIL_READING_NEW_PASSWORD)
@@ -297,29 +768,37 @@ fn get_password_file(filename: &str,
                                 print_stderr!("Would you like to add your password file to \
                                                Dropbox (y/n)? ");
                                 let mut line = String::new();
-                                std::io::stdin().read_line(&mut line)?;
+                                answer::read_line(&mut line)?;
                                 if line.starts_with('y') {
-                                    filename = format!("{}/{}",
-                                                       folder.to_string_lossy(),
-                                                       ROOSTER_FILE_DEFAULT);
+                                    filename = folder.join(ROOSTER_FILE_DEFAULT)
+                                        .to_string_lossy()
+                                        .into_owned();
 
                                     println_stderr!("");
                                     println_title!("|------------- Configuration ------------|");
                                     println_stderr!("");
                                     println_stderr!("You'll need to add this to your shell \
                                                      config (.bashrc, .zshrc, etc):");
-                                    println_stderr!("    export ROOSTER_FILE={}", filename);
+                                    println_stderr!("    {}",
+                                                    set_env_var_command(ROOSTER_FILE_ENV_VAR,
+                                                                        &filename));
 
                                     if let Some(previous) = env::var(ROOSTER_FILE_ENV_VAR).ok() {
                                         println_stderr!("");
                                         println_stderr!("You'll also need to delete your \
                                                          previous Rooster file configuration. It \
                                                          probably looks something like this:");
-                                        println_stderr!("    export ROOSTER_FILE={}", previous);
+                                        println_stderr!("    {}",
+                                                        set_env_var_command(ROOSTER_FILE_ENV_VAR,
+                                                                            &previous));
                                     }
                                 }
                             }
 
+                            // Like %APPDATA%\rooster, which is only there after the first run.
+                            if let Some(dir) = Path::new(&filename).parent() {
+                                std::fs::create_dir_all(dir)?;
+                            }
                             let password_file = open_password_file(filename.as_str(), true)?;
 
                             println_stderr!("");
@@ -341,104 +820,210 @@ fn get_password_file(filename: &str,
     }
 }
 
+/// Explains why the second factors of the password file don't work.
+fn explain_second_factor_error(err: &password::PasswordError) {
+    match *err {
+        password::PasswordError::KeyFileRequiredError => {
+            println_err!("Woops, your password file needs its key file. Give it with \
+                          --key-file <path>.");
+        }
+        password::PasswordError::WrongKeyFileError => {
+            println_err!("Woops, this isn't the key file of your password file, or it was \
+                          modified.");
+        }
+        password::PasswordError::KeyFileNotUsedError => {
+            println_err!("Woops, your password file doesn't use a key file. Leave out \
+                          --key-file, or add one with `rooster set-master-password \
+                          --new-key-file <path>`.");
+        }
+        password::PasswordError::YubiKeyRequiredError if !yubikey::is_supported() => {
+            println_err!("Woops, your password file needs a YubiKey, but this Rooster was built \
+                          without YubiKey support. Rebuild it with `cargo install rooster \
+                          --features yubikey`.");
+        }
+        _ => println_err!("Woops, your password file needs a YubiKey."),
+    }
+}
+
+/// Checks that the second factors the password file needs can be given, before the master
+/// password is asked for.
+fn check_second_factors(header: &password::v2::Header) -> Result<(), password::PasswordError> {
+    match (header.needs_key_file(), key_file::get().is_some()) {
+        (true, false) => return Err(password::PasswordError::KeyFileRequiredError),
+        (false, true) => return Err(password::PasswordError::KeyFileNotUsedError),
+        _ => {}
+    }
+    if header.yubikey_challenge().is_some() && !yubikey::is_supported() {
+        return Err(password::PasswordError::YubiKeyRequiredError);
+    }
+    Ok(())
+}
+
+/// What opens the password file besides the master password: the key file given with
+/// --key-file, and the response of the YubiKey to the challenge of the file.
+pub fn second_factors(header: Option<&password::v2::Header>)
+                      -> Result<password::v2::SecondFactors, ExitCode> {
+    let yubikey = match header.and_then(|header| header.yubikey_challenge()) {
+        Some(challenge) => {
+            let response = yubikey::challenge_response(&challenge).map_err(|reason| {
+                println_err!("Woops, I couldn't get the response of your YubiKey (reason: {}).",
+                             reason);
+                ExitCode::Error
+            })?;
+            Some(password::v2::YubiKeyResponse {
+                challenge: challenge,
+                response: response,
+            })
+        }
+        None => None,
+    };
+    Ok(password::v2::SecondFactors {
+        key_file: key_file::get(),
+        yubikey: yubikey,
+    })
+}
+
+/// Sets up a YubiKey for a new password file or a new master password, with a new challenge.
+pub fn new_yubikey_response() -> Result<password::v2::YubiKeyResponse, ExitCode> {
+    let challenge = password::v2::generate_yubikey_challenge().map_err(|err| {
+        println_err!("Woops, I couldn't create a challenge for your YubiKey (reason: {}).", err);
+        ExitCode::Error
+    })?;
+    let response = yubikey::challenge_response(&challenge).map_err(|reason| {
+        println_err!("Woops, I couldn't get the response of your YubiKey (reason: {}).", reason);
+        ExitCode::Error
+    })?;
+    Ok(password::v2::YubiKeyResponse {
+        challenge: challenge,
+        response: response,
+    })
+}
+
 fn get_password_store(file: &mut File,
-                      new_master_password: Option<SafeString>)
-                      -> Result<password::v2::PasswordStore, i32> {
+                      new_master_password: Option<SafeString>,
+                      new_yubikey: bool,
+                      offer_upgrade: bool)
+                      -> Result<password::v2::PasswordStore, ExitCode> {
     // If there was no password file, return early with an empty store
     match new_master_password {
-        Some(p) => return password::v2::PasswordStore::new(p.clone()).map_err(|_| 1),
+        Some(p) => {
+            let mut factors = second_factors(None)?;
+            if new_yubikey {
+                factors.yubikey = Some(new_yubikey_response()?);
+            }
+            file_state::remember(&[]);
+            return password::v2::PasswordStore::new_with_factors(p.clone(), &factors)
+                .map_err(|_| ExitCode::Error);
+        }
         None => {}
     }
 
     // Read the Rooster file contents.
     let mut input: SafeVec = SafeVec::new(Vec::new());
-    file.read_to_end(input.inner_mut()).map_err(|_| 1)?;
+    file.read_to_end(&mut input.inner_mut()).map_err(|_| ExitCode::Io)?;
+    file_state::remember(input.expose());
+
+    // Say what is missing before asking for the master password. Older files have no header
+    // to read: they are upgraded below.
+    let header = password::v2::Header::read(&mut std::io::Cursor::new(input.expose())).ok();
+    if let Some(ref header) = header {
+        if let Err(err) = check_second_factors(header) {
+            explain_second_factor_error(&err);
+            return Err(ExitCode::from_password_error(&err));
+        }
+    }
+    // The YubiKey is only asked once, after the first master password.
+    let mut factors = None;
 
-    // We'll ask the master password 3 times before considering that the Rooster file
-    // is corrupted and telling the user about it.
+    // We'll ask the master password 3 times, but only when it is wrong: a corrupted file stays
+    // corrupted. Asking again makes no sense when it comes from $ROOSTER_MASTER_PASSWORD or from
+    // a file, nor from stdin when it isn't a terminal, where the next line isn't another try.
     let mut number_allowed_fails = 3 - 1;
     loop {
-        let master_password = match ask_master_password() {
-            Ok(p) => p,
+        let (master_password, given) = match read_master_password() {
+            Ok(read) => read,
             Err(err) => {
                 println_err!("Woops, I could not read your master password (reason: {}).",
                              err);
-                std::process::exit(1);
+                ExitCode::Error.exit();
             }
         };
 
+        if factors.is_none() {
+            factors = Some(second_factors(header.as_ref())?);
+        }
+
         // Try to open the file as is.
-        match password::v2::PasswordStore::from_input(master_password.clone(), input.clone()) {
-            Ok(store) => {
+        match password::v2::PasswordStore::from_input_with_factors(master_password.clone(),
+                                                                   input.clone(),
+                                                                   factors.as_ref().unwrap()) {
+            Ok(mut store) => {
+                if offer_upgrade {
+                    upgrade::offer_upgrade_v2_v3(&master_password, &mut store);
+                }
                 return Ok(store);
             }
-            Err(password::PasswordError::CorruptionError) => {
-                println_err!("Your Rooster file is corrupted.");
-                return Err(1);
+            Err(ref err) if err.is_second_factor_error() => {
+                explain_second_factor_error(err);
+                return Err(ExitCode::from_password_error(err));
             }
-            Err(err) => {
+            Err(ref err) if err.is_wrong_master_password() => {
+                if let Some(given) = given {
+                    println_err!("Woops, {} isn't the right master password.", given);
+                    return Err(ExitCode::WrongMasterPassword);
+                }
                 // Try again.
                 if number_allowed_fails > 0 {
                     number_allowed_fails = number_allowed_fails - 1;
                     println_err!("Woops, that's not the right password. Let's try again.");
                     continue;
                 }
-
-                match err {
-                    password::PasswordError::WrongVersionError => {
-                        // If we can't open the file, we may need to upgrade its format first.
-                        match password::upgrade(master_password.clone(), input.clone()) {
-                            Ok(store) => {
-                                return Ok(store);
-                            }
-                            Err(err) => {
-                                // Try again.
-                                if number_allowed_fails > 0 {
-                                    number_allowed_fails = number_allowed_fails - 1;
-                                    println_err!("Woops, that's not the right password. Let's \
-                                                  try again.");
-                                    continue;
-                                }
-                                match err {
-                                    password::PasswordError::WrongVersionError => {
-                                        println_err!("I could not open the Rooster file because \
-                                                      your version of Rooster is outdated.");
-                                        println_err!("Try upgrading Rooster to the latest \
-                                                      version.");
-
-                                        return Err(1);
-                                    }
-                                    password::PasswordError::Io(err) => {
-                                        println_err!("I couldn't open your Rooster file (reason: \
-                                                      {:?})",
-                                                     err);
-
-                                        return Err(1);
-                                    }
-                                    _ => {
-                                        println_err!("Decryption of your Rooster file keeps \
-                                                      failing. This is a sign that your Rooster \
-                                                      file is probably corrupted.");
-
-                                        return Err(1);
-                                    }
-                                }
-                            }
-                        }
+                println_err!("Woops, that's still not the right master password.");
+                return Err(ExitCode::WrongMasterPassword);
+            }
+            Err(password::PasswordError::WrongVersionError) => {
+                // If we can't open the file, we may need to upgrade its format first.
+                match upgrade::upgrade(master_password.clone(), input.clone()) {
+                    Ok(store) => {
+                        return Ok(store);
                     }
-                    password::PasswordError::Io(err) => {
-                        println_err!("I couldn't open your Rooster file (reason: {:?})", err);
-
-                        return Err(1);
+                    Err(ref err) if err.is_wrong_master_password() && given.is_none() &&
+                                    number_allowed_fails > 0 => {
+                        // Try again.
+                        number_allowed_fails = number_allowed_fails - 1;
+                        println_err!("Woops, that's not the right password. Let's try again.");
+                        continue;
                     }
-                    _ => {
-                        println_err!("Decryption of your Rooster file keeps failing. This is a \
-                                      sign that your Rooster file is probably corrupted.");
+                    Err(password::PasswordError::WrongVersionError) => {
+                        println_err!("I could not open the Rooster file because your version \
+                                      of Rooster is outdated.");
+                        println_err!("Try upgrading Rooster to the latest version.");
 
-                        return Err(1);
+                        return Err(ExitCode::CorruptedFile);
                     }
+                    Err(err) => return Err(explain_open_error(err)),
                 }
             }
+            Err(err) => return Err(explain_open_error(err)),
+        }
+    }
+}
+
+/// Says why the password file can't be opened, for errors that asking the master password
+/// again wouldn't fix.
+fn explain_open_error(err: password::PasswordError) -> ExitCode {
+    match err {
+        password::PasswordError::Io(err) => {
+            println_err!("I couldn't open your Rooster file (reason: {:?})", err);
+            ExitCode::Io
+        }
+        ref err if err.is_wrong_master_password() => {
+            println_err!("Woops, that's still not the right master password.");
+            ExitCode::WrongMasterPassword
+        }
+        _ => {
+            println_err!("Your Rooster file is corrupted.");
+            ExitCode::CorruptedFile
         }
     }
 }
@@ -446,150 +1031,784 @@ fn get_password_store(file: &mut File,
 fn execute_command_from_filename(matches: &getopts::Matches,
                                  command: &Command,
                                  file: &mut File,
-                                 store: &mut password::v2::PasswordStore)
-                                 -> Result<(), i32> {
-    // Execute the command and save the new password list
-    match command.callback_exec {
-        Some(cb) => {
-            (cb)(matches, store)?;
+                                 store: &mut password::v2::PasswordStore,
+                                 password_file_path: &str)
+                                 -> Result<(), ExitCode> {
+    let wait = matches.opt_present("wait");
+    loop {
+        // Execute the command and save the new password list
+        match command.callback_exec {
+            Some(cb) => {
+                (cb)(matches, store)?;
+            }
+            None => {}
         }
-        None => {}
+
+        // Only reading passwords leaves the file as it is. A new file still has to be written
+        // once.
+        let is_new_file = file.metadata().map(|m| m.len() == 0).unwrap_or(true);
+        if !store.is_modified() && !is_new_file {
+            return Ok(());
+        }
+
+        // Reading commands share the password file until they change it.
+        if lock_for(command) == sys::Lock::Shared {
+            lock_password_file(file, password_file_path, sys::Lock::Exclusive, wait)?;
+        }
+
+        // A batch may have saved some of its lines already, so it can't simply run again.
+        match save_password_store(store, password_file_path) {
+            Err(ExitCode::FileChanged) if command.name != "batch" => {}
+            result => return result,
+        }
+        if !ask_run_again()? {
+            return Err(ExitCode::FileChanged);
+        }
+        *file = open_password_file(password_file_path, false).map_err(|err| {
+            println_err!("Woops, I couldn't open the password file again (reason: {:?}).", err);
+            ExitCode::Io
+        })?;
+        lock_password_file(file, password_file_path, sys::Lock::Exclusive, wait)?;
+        *store = get_password_store(file, None, false, false)?;
+    }
+}
+
+/// Commands that only change the password file when asked to, like `list --archive`, and can
+/// share it with each other until then.
+const READING_COMMANDS: &'static [&'static str] = &["get", "list", "export", "history", "doctor"];
+
+fn lock_for(command: &Command) -> sys::Lock {
+    if READING_COMMANDS.contains(&command.name) {
+        sys::Lock::Shared
+    } else {
+        sys::Lock::Exclusive
+    }
+}
+
+/// Keeps other Roosters from changing the password file while this one uses it. With `wait`,
+/// waits for them to be done instead of failing. Saving replaces the file, so the one at `path`
+/// is opened again if the Rooster that was waited for saved it meanwhile.
+fn lock_password_file(file: &mut File,
+                      path: &str,
+                      lock: sys::Lock,
+                      wait: bool)
+                      -> Result<(), ExitCode> {
+    loop {
+        try_lock_password_file(file, lock, wait)?;
+        if sys::is_same_file(file, Path::new(path)) {
+            return Ok(());
+        }
+        *file = open_password_file(path, false).map_err(|err| {
+            println_err!("Woops, I couldn't open the password file again (reason: {:?}).", err);
+            ExitCode::Io
+        })?;
     }
+}
+
+fn try_lock_password_file(file: &File, lock: sys::Lock, wait: bool) -> Result<(), ExitCode> {
+    match sys::lock_file(file, lock, false) {
+        Ok(true) => return Ok(()),
+        Ok(false) => {}
+        Err(err) => {
+            println_stderr!("Warning: I couldn't lock the password file (reason: {}), so other \
+                             programs may change it meanwhile.",
+                            err);
+            return Ok(());
+        }
+    }
+
+    // Only an exclusive lock is in the way of a shared one.
+    let other = if lock == sys::Lock::Exclusive &&
+                   sys::lock_file(file, sys::Lock::Shared, false).unwrap_or(false) {
+        "reading"
+    } else {
+        "changing"
+    };
+    if !wait {
+        println_err!("Woops, another Rooster is {} the password file. Try again when it is done, \
+                      or use --wait.",
+                     other);
+        return Err(ExitCode::Locked);
+    }
+    println_stderr!("Another Rooster is {} the password file, I'm waiting for it to be done...",
+                    other);
+    sys::lock_file(file, lock, true).map(|_| ()).map_err(|err| {
+        println_err!("Woops, I couldn't lock the password file (reason: {}).", err);
+        ExitCode::Io
+    })
+}
+
+/// Whether to read the password file that was changed by someone else, and run the command on
+/// it. Asking is not possible with --non-interactive, which means no.
+fn ask_run_again() -> Result<bool, ExitCode> {
+    if answer::is_non_interactive() {
+        return Ok(false);
+    }
+    loop {
+        print_stderr!("Would you like to read it again and run the command again (y/n)? ");
+        let mut line = String::new();
+        match answer::read_line(&mut line) {
+            Ok(_) if line.starts_with('y') => return Ok(true),
+            Ok(_) if line.starts_with('n') => return Ok(false),
+            Ok(0) | Err(_) => return Ok(false),
+            Ok(_) => println_stderr!("I didn't get that."),
+        }
+    }
+}
 
-    match store.sync(file) {
-        Ok(()) => { Ok(()) }
+/// Locks the password file at `path` for commands that replace or delete it without opening it,
+/// like `rooster restore`, and remembers what it holds to notice changes by others before
+/// writing. The lock is kept until the returned file is closed. There is nothing to lock when the
+/// file doesn't exist yet.
+fn lock_password_file_for_writing(matches: &getopts::Matches,
+                                  path: &str)
+                                  -> Result<Option<File>, ExitCode> {
+    let mut file = match open_password_file(path, false) {
+        Ok(file) => file,
+        Err(ref err) if err.kind() == IoErrorKind::NotFound => {
+            file_state::forget();
+            return Ok(None);
+        }
+        Err(err) => {
+            println_err!("Woops, I couldn't open the password file (reason: {:?}).", err);
+            return Err(ExitCode::Io);
+        }
+    };
+    lock_password_file(&mut file, path, sys::Lock::Exclusive, matches.opt_present("wait"))?;
+    let mut input = SafeVec::new(Vec::new());
+    file.read_to_end(&mut input.inner_mut()).map_err(|err| {
+        println_err!("Woops, I couldn't read the password file (reason: {:?}).", err);
+        ExitCode::Io
+    })?;
+    file_state::remember(input.expose());
+    Ok(Some(file))
+}
+
+/// Fails unless the password file still holds what was read, or --force-write was given.
+fn check_password_file_unchanged(password_file_path: &str) -> Result<(), ExitCode> {
+    if !file_state::is_force_write() && file_state::changed_since_read(password_file_path) {
+        println_err!("Woops, the password file was changed by another program since I read it, \
+                      so I didn't save your changes. Use --force-write to overwrite it anyway.");
+        return Err(ExitCode::FileChanged);
+    }
+    Ok(())
+}
+
+/// Writes the store to the password file, after keeping what the file held for `rooster undo`
+/// and `rooster restore --previous`.
+fn save_password_store(store: &password::v2::PasswordStore,
+                       password_file_path: &str)
+                       -> Result<(), ExitCode> {
+    save_password_file(password_file_path,
+                       store.is_modified(),
+                       |path| store.sync(path))
+}
+
+/// Replaces the password file with `write`, the same way for every command: only if nobody else
+/// changed it, and after keeping what it held for `rooster restore --previous`, and for
+/// `rooster undo` with `keep_undo`.
+fn save_password_file<F, E>(password_file_path: &str,
+                            keep_undo: bool,
+                            write: F)
+                            -> Result<(), ExitCode>
+    where F: FnOnce(&Path) -> Result<(), E>,
+          E: std::fmt::Debug
+{
+    check_password_file_unchanged(password_file_path)?;
+
+    if let Err(err) = commands::restore::save_previous_version(password_file_path) {
+        println_err!("Woops, I couldn't keep the previous version of the password file, so I \
+                      didn't save your changes (reason: {:?}). Use --no-backup to save without \
+                      it.",
+                     err);
+        return Err(ExitCode::Io);
+    }
+
+    // Keep the current file around, so that `rooster undo` can go back to it.
+    if keep_undo {
+        if let Err(err) = commands::undo::save_undo_state(password_file_path) {
+            println_err!("Woops, I couldn't save the undo file, so I didn't save your changes \
+                          (reason: {:?}).",
+                         err);
+            return Err(ExitCode::Io);
+        }
+    }
+
+    match write(Path::new(password_file_path)) {
+        Ok(()) => {
+            file_state::remember_file(password_file_path);
+            Ok(())
+        }
         Err(err) => {
             println_err!("I could not save the password file (reason: {:?}).", err);
-            Err(1)
+            Err(ExitCode::Io)
         }
     }
 }
 
-fn get_password_file_path() -> Result<String, i32> {
-    let rooster_file = env::var(ROOSTER_FILE_ENV_VAR);
-    let home_dir = env::home_dir();
+/// The password file from --file, relative to the current directory, then --profile, then
+/// $ROOSTER_FILE, then the default one in the home directory.
+fn get_password_file_path(matches: &getopts::Matches) -> Result<String, ExitCode> {
+    if let Some(filename) = matches.opt_str("file") {
+        let path = env::current_dir().map_err(|_| ExitCode::Error)?.join(filename);
+        return path.into_os_string().into_string().map_err(|_| ExitCode::Error);
+    }
 
-    match rooster_file {
-        Ok(filename) => Ok(filename),
-        Err(VarError::NotPresent) => {
-            let mut filename = match home_dir {
-                Some(home) => {
-                    home.as_os_str()
-                        .to_os_string()
-                        .into_string()
-                        .map_err(|_| 1)?
-                }
-                None => {
-                    return Err(1);
-                }
-            };
-            filename.push(PATH_SEP);
-            filename.push_str(ROOSTER_FILE_DEFAULT);
-            Ok(filename)
+    if let Some(name) = matches.opt_str("profile") {
+        let profiles = profiles::load().map_err(|err| {
+            println_err!("Woops, {}.", err);
+            ExitCode::Io
+        })?;
+        return match profiles.get(&name) {
+            Some(path) => Ok(path.to_owned()),
+            None => {
+                println_err!("Woops, there is no profile called \"{}\". Add it with `rooster \
+                              profile add {} <path>`.",
+                             name,
+                             name);
+                Err(ExitCode::Usage)
+            }
+        };
+    }
+
+    let path = password_file_path_from_env(|name| env::var_os(name), cfg!(windows), env::home_dir())
+        .ok_or(ExitCode::Error)?;
+    path.into_os_string().into_string().map_err(|_| ExitCode::Error)
+}
+
+/// $ROOSTER_FILE, or else the default password file: in %APPDATA%\rooster on Windows, in the
+/// home directory elsewhere. The environment is read through `var`, so that tests can give
+/// their own.
+fn password_file_path_from_env<F>(var: F, windows: bool, home: Option<PathBuf>) -> Option<PathBuf>
+    where F: Fn(&str) -> Option<OsString>
+{
+    let var = |name: &str| var(name).filter(|value| !value.is_empty());
+    if let Some(path) = var(ROOSTER_FILE_ENV_VAR) {
+        return Some(PathBuf::from(path));
+    }
+    if !windows {
+        return home.map(|home| home.join(ROOSTER_FILE_DEFAULT));
+    }
+    match var("APPDATA") {
+        Some(app_data) => Some(PathBuf::from(app_data).join("rooster").join(ROOSTER_FILE_WINDOWS)),
+        None => var("USERPROFILE").map(|home| PathBuf::from(home).join(ROOSTER_FILE_DEFAULT)),
+    }
+}
+
+/// Pulls with --git-sync before the password file is opened. Stops on conflicts, but the local
+/// password file is still good when the remote can't be reached.
+fn pull_from_git(dir: Option<&Path>) {
+    let dir = match dir {
+        Some(dir) => dir,
+        None => {
+            println_err!("Woops, --git-sync only works when your password file is in a git \
+                          repository.");
+            ExitCode::Usage.exit();
+        }
+    };
+    match git::pull(dir) {
+        Ok(()) => {}
+        Err(git::PullError::Conflict(reason)) => {
+            println_err!("Woops, {}.", reason);
+            ExitCode::Error.exit();
+        }
+        Err(git::PullError::Failed(reason)) => {
+            println_err!("I couldn't pull your password file, so I'm using the one here \
+                          (reason: {}).",
+                         reason);
+        }
+    }
+}
+
+/// Commits the saved password file, and pushes it with --git-sync. The file is already saved,
+/// so failures are only told.
+fn commit_to_git(dir: &Path, password_file: &Path, command: &str, push: bool) {
+    match git::commit(dir, password_file, command) {
+        Ok(true) => {}
+        Ok(false) => return,
+        Err(reason) => {
+            println_err!("Your passwords are saved, but I couldn't commit them with git \
+                          (reason: {}).",
+                         reason);
+            return;
+        }
+    }
+    if push {
+        if let Err(reason) = git::push(dir) {
+            println_err!("Your passwords are saved and committed, but I couldn't push them \
+                          (reason: {}).",
+                         reason);
+        }
+    }
+}
+
+/// How many previous values of each password to keep, if set in $ROOSTER_HISTORY_DEPTH.
+fn get_history_depth() -> Result<Option<usize>, String> {
+    match env::var(ROOSTER_HISTORY_DEPTH_ENV_VAR) {
+        Ok(depth) => {
+            depth.trim().parse::<usize>().map(Some).map_err(|_| {
+                format!("${} must be a number, like 5, not \"{}\"",
+                        ROOSTER_HISTORY_DEPTH_ENV_VAR,
+                        depth)
+            })
+        }
+        Err(_) => Ok(None),
+    }
+}
+
+/// How long a command runs before --notify-done notifies, if set in $ROOSTER_NOTIFY_AFTER.
+fn get_notify_threshold() -> Result<Duration, String> {
+    match env::var(ROOSTER_NOTIFY_AFTER_ENV_VAR) {
+        Ok(seconds) => {
+            seconds.trim().parse::<u64>().map(Duration::from_secs).map_err(|_| {
+                format!("${} must be a number of seconds, like 5, not \"{}\"",
+                        ROOSTER_NOTIFY_AFTER_ENV_VAR,
+                        seconds)
+            })
+        }
+        Err(_) => Ok(notify::DEFAULT_THRESHOLD),
+    }
+}
+
+/// Where to copy passwords, from --primary or $ROOSTER_SELECTION.
+fn get_selection(matches: &getopts::Matches) -> Result<clip::Selection, String> {
+    let selection = if matches.opt_present("primary") {
+        clip::Selection::Primary
+    } else {
+        match env::var(ROOSTER_SELECTION_ENV_VAR) {
+            Ok(name) => {
+                clip::Selection::from_name(name.trim()).ok_or_else(|| {
+                    format!("${} can be \"clipboard\" or \"primary\", not \"{}\"",
+                            ROOSTER_SELECTION_ENV_VAR,
+                            name)
+                })?
+            }
+            Err(_) => clip::Selection::Clipboard,
+        }
+    };
+
+    // Copying to the clipboard instead would leave the user pasting something else.
+    if selection == clip::Selection::Primary && !clip::has_primary_selection() {
+        return Err("the PRIMARY selection only exists on X11 and Wayland, this system only \
+                    has the clipboard"
+            .to_owned());
+    }
+    Ok(selection)
+}
+
+/// Takes $ROOSTER_MASTER_PASSWORD out of the environment, so that no program started later
+/// (clipboard tools, editors) gets it.
+fn take_master_password_from_env() {
+    if let Some(value) = env::var_os(ROOSTER_MASTER_PASSWORD_ENV_VAR) {
+        let password = value.into_string().map(SafeString::new);
+        sys::wipe_env_var(ROOSTER_MASTER_PASSWORD_ENV_VAR);
+        if let Ok(password) = password {
+            give_master_password(password, format!("${}", ROOSTER_MASTER_PASSWORD_ENV_VAR));
+        }
+    }
+}
+
+fn give_master_password(password: SafeString, source: String) {
+    *GIVEN_MASTER_PASSWORD.lock().unwrap() = Some((password, source));
+}
+
+fn given_master_password() -> Option<(SafeString, String)> {
+    GIVEN_MASTER_PASSWORD.lock().unwrap().clone()
+}
+
+/// Reads the first line of the file, or of stdin for "-". Unless `allow_readable` is true, the
+/// file must not be readable by other users than its owner.
+fn read_master_password_file(path: &str, allow_readable: bool) -> Result<SafeString, ExitCode> {
+    if path == "-" {
+        let stdin = std::io::stdin();
+        return askpass::read_secret_line(&mut stdin.lock()).map_err(|reason| {
+            println_err!("Woops, I could not read your master password (reason: {}).", reason);
+            ExitCode::Error
+        });
+    }
+
+    let mut file = File::open(path).map_err(|err| {
+        println_err!("Woops, I could not open {} (reason: {}).", path, err);
+        ExitCode::Error
+    })?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mode = file.metadata().map(|metadata| metadata.permissions().mode()).unwrap_or(0);
+        if mode & 0o044 != 0 && !allow_readable {
+            println_err!("Woops, other users can read {}. Make it private with `chmod 600 {}`, \
+                          or use --insecure-master-password-file.",
+                         path,
+                         path);
+            return Err(ExitCode::Error);
         }
-        Err(VarError::NotUnicode(_)) => Err(1),
     }
+    #[cfg(not(unix))]
+    let _ = allow_readable;
+
+    askpass::read_secret_line(&mut file).map_err(|reason| {
+        println_err!("Woops, I could not read your master password from {} (reason: {}).",
+                     path,
+                     reason);
+        ExitCode::Error
+    })
 }
 
+/// Uses the master password given in $ROOSTER_MASTER_PASSWORD or in a file, if any. Otherwise,
+/// asks in the terminal, or with an askpass program (or pinentry) when there is no terminal to
+/// read from.
 fn ask_master_password() -> IoResult<SafeString> {
-    prompt_password_stderr("Type your master password: ").map(SafeString::new)
-}
-
-fn usage(password_file: &str) {
-    println!("Welcome to Rooster, the simple password manager for geeks :-)");
-    println!();
-    println!("The current password file is: {}", password_file);
-    println!("You may override this path in the $ROOSTER_FILE environment variable.");
-    println!("");
-    println!("Usage:");
-    println!("    rooster -h");
-    println!("    rooster [options] <command> [<args> ...]");
-    println!("    rooster <command> -h");
-    println!();
-    println!("Options:");
-    println!("    -h, --help        Display a help message");
-    println!("    -v, --version     Display the version of Rooster you are using");
-    println!("    -a, --alnum       Only use alpha numeric (a-z, A-Z, 0-9) in generated passwords");
-    println!("    -l, --length      Set a custom length for the generated password, default is 32");
-    println!("    -s, --show        Show the password instead of copying it to the clipboard");
-    println!();
-    println!("Commands for everyday use:");
-    println!("    add                        Add a new password manually");
-    println!("    change                     Change a password manually");
-    println!("    delete                     Delete a password");
-    println!("    generate                   Generate a password");
-    println!("    regenerate                 Regenerate a previously existing password");
-    println!("    get                        Retrieve a password");
-    println!("    rename                     Rename the app for a password");
-    println!("    transfer                   Change the username for a password");
-    println!("    list                       List all apps and usernames");
-    println!("    export                     Dump all your raw password data in JSON");
-    println!("    set-master-password        Set your master password");
-    println!("    uninstall                  Show instructions to uninstall Rooster");
-    println!("");
-    println!("Some commands (change, delete, regenerate, get, rename, transfer)");
-    println!("support fuzzy search of passwords:");
-    println!("    rooster get google");
-    println!("    rooster get ggl");
-    println!("");
-    println!("If multiple passwords match your search, you will be asked to choose.")
+    read_master_password().map(|(password, _)| password)
+}
+
+/// Like `ask_master_password`, with where the master password came from if asking again would
+/// read the same one, or what comes next on stdin.
+fn read_master_password() -> IoResult<(SafeString, Option<String>)> {
+    if let Some((password, source)) = given_master_password() {
+        return Ok((password, Some(source)));
+    }
+    // When stdin is the input of the command, the master password is typed in the terminal.
+    let from_pipe = !sys::stdin_is_tty() && !answer::is_stdin_input();
+    let err = match answer::prompt_password("Type your master password: ", "the master password") {
+        Ok(password) if from_pipe => {
+            return Ok((password, Some("the first line of standard input".to_owned())))
+        }
+        Ok(password) => return Ok((password, None)),
+        Err(err) => err,
+    };
+    if !askpass::is_enabled() {
+        return Err(err);
+    }
+    match askpass::ask("Type your master password to open your password file.") {
+        Some(result) => {
+            result.map(|password| (password, None))
+                .map_err(|reason| IoError::new(IoErrorKind::Other, reason))
+        }
+        None => Err(err),
+    }
+}
+
+fn usage(password_file: &str, profile: Option<&str>) -> Help {
+    let current = match profile {
+        Some(profile) => {
+            format!("The current password file is: {} (profile {})", password_file, profile)
+        }
+        None => format!("The current password file is: {}", password_file),
+    };
+    let exit_codes: Vec<(String, &str)> = exit_code::ALL.iter()
+        .map(|code| (code.code().to_string(), code.description()))
+        .collect();
+    let exit_codes: Vec<(&str, &str)> =
+        exit_codes.iter().map(|&(ref code, description)| (code.as_str(), description)).collect();
+    let options: Vec<(String, String)> = options::OPTIONS.iter()
+        .map(|opt| (opt.help_name(), opt.help_description()))
+        .collect();
+    let options: Vec<(&str, &str)> = options.iter()
+        .map(|&(ref name, ref description)| (name.as_str(), description.as_str()))
+        .collect();
+    Help::new()
+        .text(&["Welcome to Rooster, the simple password manager for geeks :-)"])
+        .text(&[&current,
+                "You may override this path with --file, with --profile (see `rooster profile",
+                "-h`), or in the $ROOSTER_FILE environment variable.",
+                &format!("The number of previous values kept for each password (default: {}) \
+                          may be set",
+                         password::v2::DEFAULT_HISTORY_DEPTH),
+                "in the $ROOSTER_HISTORY_DEPTH environment variable.",
+                &format!("With --notify-done, commands that take {} seconds or more (or the \
+                          number of seconds",
+                         notify::DEFAULT_THRESHOLD.as_secs()),
+                "in $ROOSTER_NOTIFY_AFTER) ring the bell when they are done.",
+                "With --primary, or if $ROOSTER_SELECTION is \"primary\", passwords are copied to",
+                "the PRIMARY selection of X11 and Wayland, for pasting with a middle-click.",
+                "Without a display, like over SSH, or with --osc52, the terminal is asked to copy",
+                "passwords with an OSC 52 escape sequence, which not every terminal supports.",
+                "Inside tmux, without a display or with --tmux, passwords go to a tmux buffer.",
+                "Without a terminal, the master password is asked with the program in",
+                "$ROOSTER_ASKPASS or $SSH_ASKPASS, or with pinentry, unless --no-askpass is given.",
+                "For automation, it may be set in $ROOSTER_MASTER_PASSWORD instead, which Rooster",
+                "removes from its environment right away, or in a file given with",
+                "--master-password-file or $ROOSTER_MASTER_PASSWORD_FILE.",
+                "With --non-interactive, or if $ROOSTER_NON_INTERACTIVE is set, Rooster exits with",
+                &format!("code {} and a line starting with \"rooster: non-interactive:\" instead \
+                          of asking",
+                         ExitCode::WouldAsk.code()),
+                "anything, so give the master password with one of the ways above.",
+                "If $ROOSTER_GIT is set and the password file is in a git repository, every",
+                "change is committed, with only the command name in the message. With --git-sync,",
+                "the repository is also pulled before and pushed after."])
+        .usage(&["rooster -h", "rooster [options] <command> [<args> ...]", "rooster <command> -h"])
+        .table("Options", 18, &options)
+        .table("Commands for everyday use",
+               27,
+               &[("add (new)", "Add a new password manually"),
+                 ("add-or-update", "Add a password, or update it if it exists"),
+                 ("change", "Change a password manually"),
+                 ("edit", "Edit a password in your $EDITOR"),
+                 ("delete (rm, del)", "Delete a password"),
+                 ("generate", "Generate a password"),
+                 ("gen", "Generate a password without saving it"),
+                 ("regenerate", "Regenerate a previously existing password"),
+                 ("get (show)", "Retrieve a password"),
+                 ("note", "Show, set or clear the note of a password"),
+                 ("tag", "Show, add or remove the tags of a password"),
+                 ("favorite", "Mark or unmark a password as a favorite"),
+                 ("archive", "Hide a password you don't use anymore"),
+                 ("unarchive", "Bring back an archived password"),
+                 ("history", "Show or copy the previous values of a password"),
+                 ("rename (mv)", "Rename the app for a password"),
+                 ("transfer", "Change the username for a password"),
+                 ("list (ls)", "List all apps and usernames"),
+                 ("import", "Import passwords from a CSV or JSON file"),
+                 ("export", "Dump all your raw password data in JSON"),
+                 ("clear", "Empty the clipboard now"),
+                 ("clear-all", "Delete all your passwords"),
+                 ("init", "Create a new password file"),
+                 ("set-master-password", "Set your master password"),
+                 ("set-kdf", "Change how your key is derived (scrypt, Argon2id)"),
+                 ("kdf-benchmark", "Find how costly deriving your key should be"),
+                 ("rekey", "Derive a new key with the same master password"),
+                 ("dedupe", "Find and clean up duplicate passwords"),
+                 ("merge", "Merge two passwords for the same account"),
+                 ("doctor", "Look for problems in your password file"),
+                 ("config", "Show or change the settings of your password file"),
+                 ("batch", "Run commands from stdin with a single unlock"),
+                 ("profile", "Name your password files, to switch between them"),
+                 ("undo", "Undo the last change to your passwords"),
+                 ("restore", "Go back to the previous version of your file"),
+                 ("uninstall", "Delete your password file and uninstall Rooster"),
+                 ("completions", "Print a completion script for your shell")])
+        .text(&["Some commands (change, delete, edit, regenerate, get, rename, transfer)",
+                "support fuzzy search of passwords:"])
+        .then_commands(&["rooster get google", "rooster get ggl"])
+        .text(&["If multiple passwords match your search, you will be asked to choose, with the",
+                "arrow keys or by typing more of the name. To only accept exact names, use",
+                "--exact, or see `rooster config -h`."])
+        .table("Exit codes", 4, &exit_codes)
+}
+
+/// `rooster tag <app> -work` removes a tag, but getopts would read "-work" as options. This
+/// puts a "--" before the first such argument, so the tags end up in the free arguments.
+fn escape_tag_removals(args: Vec<String>) -> Vec<String> {
+    let command_position = match args.iter().skip(1).position(|arg| !arg.starts_with('-')) {
+        Some(position) => position + 1,
+        None => return args,
+    };
+    if args[command_position] != "tag" {
+        return args;
+    }
+
+    let mut escaped = Vec::with_capacity(args.len() + 1);
+    let mut escaping = false;
+    for (i, arg) in args.into_iter().enumerate() {
+        if arg == "--" {
+            escaping = true;
+        }
+        if !escaping && i > command_position + 1 && arg.len() > 2 && arg.starts_with('-') &&
+           !arg.starts_with("--") {
+            escaped.push("--".to_owned());
+            escaping = true;
+        }
+        escaped.push(arg);
+    }
+    escaped
+}
+
+fn warn_lock_failure(err: &IoError) {
+    println_stderr!("Warning: I couldn't lock your secrets in memory (reason: {}), so they may be \
+                     written to swap. Raising the limit of locked memory (ulimit -l) fixes this.",
+                    err);
 }
 
 fn main() {
-    let args: Vec<String> = std::env::args().collect();
-
-    let mut opts = Options::new();
-    opts.optflag("h", "help", "Display a help message");
-    opts.optflag("v",
-                 "version",
-                 "Display the version of Rooster you are using");
-    opts.optflag("a",
-                 "alnum",
-                 "Only use alpha numeric (a-z, A-Z, 0-9) in generated passwords");
-    opts.optopt("l",
-                "length",
-                "Set a custom length for the generated password",
-                "32");
-    opts.optflag("s",
-                 "show",
-                 "Show the password instead of copying it to the clipboard");
+    memory::on_lock_failure(warn_lock_failure);
+    take_master_password_from_env();
+    let args: Vec<String> = escape_tag_removals(std::env::args().collect());
+
+    output_format::set_json_errors(output_format::json_errors_in_args(&args));
 
+    let opts = options::build();
     let matches = match opts.parse(&args[1..]) {
         Ok(m) => m,
         Err(err) => {
             println_err!("{}", err);
-            std::process::exit(1);
+            ExitCode::Usage.exit();
+        }
+    };
+
+    quiet::set_enabled(matches.opt_present("quiet"));
+    let formats = [("error-format", output_format::ERROR_FORMATS),
+                   ("format", output_format::FORMATS)];
+    for &(option, names) in formats.iter() {
+        if let Some(name) = matches.opt_str(option) {
+            if !names.contains(&name.as_str()) {
+                println_err!("Woops, --{} can be \"{}\", not \"{}\".",
+                             option,
+                             names.join("\" or \""),
+                             name);
+                ExitCode::Usage.exit();
+            }
+        }
+    }
+
+    // Decorative characters are replaced with ASCII when the terminal can't show them.
+    if matches.opt_present("ascii") && matches.opt_present("unicode") {
+        println_err!("Woops, --ascii and --unicode can't be used together.");
+        ExitCode::Usage.exit();
+    }
+    glyphs::set_charset(if matches.opt_present("ascii") {
+        glyphs::Charset::Ascii
+    } else if matches.opt_present("unicode") {
+        glyphs::Charset::Unicode
+    } else {
+        glyphs::detect_charset()
+    });
+
+    for exact_option in &["no-fuzzy", "exact"] {
+        if matches.opt_present("fuzzy") && matches.opt_present(exact_option) {
+            println_err!("Woops, --fuzzy and --{} can't be used together.", exact_option);
+            ExitCode::Usage.exit();
+        }
+    }
+
+    let collation = match matches.opt_str("collate") {
+        Some(name) => {
+            match collation::Collation::from_name(&name) {
+                Some(collation) => Some(collation),
+                None => {
+                    println_err!("Woops, --collate can be \"codepoint\" or \"locale\", not \"{}\".",
+                                 name);
+                    ExitCode::Usage.exit();
+                }
+            }
+        }
+        None => None,
+    };
+
+    askpass::set_enabled(!matches.opt_present("no-askpass"));
+    let non_interactive = match env::var(ROOSTER_NON_INTERACTIVE_ENV_VAR) {
+        Ok(value) => !value.is_empty() && value != "0",
+        Err(_) => false,
+    };
+    answer::set_non_interactive(non_interactive || matches.opt_present("non-interactive"));
+    file_state::set_force_write(matches.opt_present("force-write"));
+    let no_backup = match env::var(commands::restore::ROOSTER_NO_BACKUP_ENV_VAR) {
+        Ok(value) => !value.is_empty() && value != "0",
+        Err(_) => false,
+    };
+    commands::restore::set_no_backup(no_backup || matches.opt_present("no-backup"));
+    let git_enabled = match env::var(git::ROOSTER_GIT_ENV_VAR) {
+        Ok(value) => !value.is_empty() && value != "0",
+        Err(_) => false,
+    };
+
+    // --master-password-file wins over $ROOSTER_MASTER_PASSWORD, which wins over
+    // $ROOSTER_MASTER_PASSWORD_FILE.
+    if matches.opt_present("master-password-stdin") && matches.opt_present("master-password-file") {
+        println_err!("Woops, --master-password-stdin and --master-password-file can't be used \
+                      together.");
+        ExitCode::Usage.exit();
+    }
+    let master_password_file = match matches.opt_str("master-password-file") {
+        Some(path) => Some(path),
+        None if matches.opt_present("master-password-stdin") => Some("-".to_owned()),
+        None if given_master_password().is_some() => None,
+        None => env::var(ROOSTER_MASTER_PASSWORD_FILE_ENV_VAR).ok().filter(|path| !path.is_empty()),
+    };
+    if let Some(path) = master_password_file {
+        let allow_readable = matches.opt_present("insecure-master-password-file");
+        match read_master_password_file(&path, allow_readable) {
+            Ok(password) => {
+                let source = if path == "-" {
+                    // Questions are asked in the terminal instead, and passwords that commands
+                    // read from stdin come on the next lines.
+                    answer::set_stdin_taken(true);
+                    "the first line of standard input".to_owned()
+                } else {
+                    format!("the first line of {}", path)
+                };
+                give_master_password(password, source);
+            }
+            Err(code) => code.exit(),
+        }
+    }
+    if let Some(path) = matches.opt_str("key-file") {
+        match key_file::read(&path) {
+            Ok(contents) => key_file::set(contents),
+            Err(reason) => {
+                println_err!("Woops, {}.", reason);
+                ExitCode::Io.exit();
+            }
         }
+    }
+
+    let from_env = given_master_password()
+        .map_or(false, |(_, source)| source.starts_with('$'));
+    if from_env {
+        println_stderr!("Warning: the master password comes from ${}, which other users can \
+                         see in /proc on some systems.",
+                        ROOSTER_MASTER_PASSWORD_ENV_VAR);
+    }
+
+    // Output for scripts has no colors, and lists in a stable format.
+    if matches.opt_present("porcelain") {
+        color::set_enabled(false);
+        list::set_porcelain(true);
+    }
+
+    let sort_key = match matches.opt_str("sort") {
+        Some(name) => {
+            match list::SortKey::from_name(&name) {
+                Some(key) => key,
+                None => {
+                    println_err!("Woops, --sort can be \"name\", \"created\" or \"updated\", not \
+                                  \"{}\".",
+                                 name);
+                    ExitCode::Usage.exit();
+                }
+            }
+        }
+        None => list::SortKey::Name,
     };
+    if matches.opt_present("sort") || matches.opt_present("reverse") {
+        list::set_sort_order(list::SortOrder {
+            key: sort_key,
+            reverse: matches.opt_present("reverse"),
+        });
+    }
 
     // Fetch the Rooster file path now, so we can display it in help messages.
-    let password_file_path = match get_password_file_path() {
+    let profile = matches.opt_str("profile");
+    let password_file_path = match get_password_file_path(&matches) {
         Ok(path) => path,
-        Err(_) => {
+        Err(ExitCode::Error) => {
             println_err!("Woops, I could not determine where your password file is.");
             println_err!("I recommend you try setting the $ROOSTER_FILE environment");
             println_err!("variable with the absolute path to your password file.");
-            std::process::exit(1);
+            ExitCode::Error.exit();
         }
+        Err(code) => code.exit(),
     };
 
     // Global help was requested.
     if matches.opt_present("help") && matches.free.is_empty() {
-        usage(password_file_path.deref());
-        std::process::exit(0);
+        print!("{}", usage(password_file_path.deref(), profile.as_ref().map(|p| p.as_str())));
+        ExitCode::Success.exit();
     }
 
     if matches.opt_present("version") {
         println!("v{}", env!("CARGO_PKG_VERSION"));
-        std::process::exit(0);
+        ExitCode::Success.exit();
     }
 
     // No command was given, this is abnormal, so we'll show the docs.
     let command_name = match matches.free.get(0) {
         Some(command_name) => command_name,
         None => {
-            usage(password_file_path.deref());
-            std::process::exit(1);
+            print!("{}", usage(password_file_path.deref(), profile.as_ref().map(|p| p.as_str())));
+            ExitCode::Usage.exit();
         }
     };
 
@@ -599,20 +1818,84 @@ fn main() {
             println_err!("Woops, the command `{}` does not exist. Try the --help option for more \
                           info.",
                          command_name);
-            std::process::exit(1);
+            if let Some(closest) = closest_command_name(command_name) {
+                println_stderr_always!("Did you mean `{}`?", closest);
+            }
+            ExitCode::Usage.exit();
         }
     };
 
     if matches.opt_present("help") {
-        (command.callback_help)();
-        std::process::exit(0);
+        print!("{}", (command.callback_help)());
+        ExitCode::Success.exit();
+    }
+
+    // Other commands pick passwords by their name, so only get and list search with a regex.
+    let search_regex = if matches.opt_present("regex") {
+        if command.name != "get" && command.name != "list" {
+            println_err!("Woops, --regex only works with get and list.");
+            ExitCode::Usage.exit();
+        }
+        if matches.opt_present("exact") {
+            println_err!("Woops, --regex and --exact can't be used together.");
+            ExitCode::Usage.exit();
+        }
+        let pattern = match list::query_from_matches(&matches) {
+            Some(pattern) => pattern,
+            None => {
+                println_err!("Woops, --regex needs a regular expression to search with.");
+                ExitCode::Usage.exit();
+            }
+        };
+        match regex::RegexBuilder::new(&pattern).case_insensitive(true).build() {
+            Ok(regex) => Some(regex),
+            Err(err) => {
+                println_err!("Woops, \"{}\" isn't a valid regular expression:", pattern);
+                println_stderr_always!("{}", err);
+                ExitCode::Usage.exit();
+            }
+        }
+    } else {
+        None
+    };
+
+    // Commands that don't open the store copy passwords too, like gen.
+    if matches.opt_present("osc52") && matches.opt_present("tmux") {
+        println_err!("Woops, --osc52 and --tmux can't be used together.");
+        ExitCode::Usage.exit();
+    }
+    if matches.opt_present("tmux") && !clip::in_tmux() {
+        println_err!("Woops, --tmux only works inside tmux, and $TMUX isn't set.");
+        ExitCode::Usage.exit();
+    }
+    clip::set_osc52(matches.opt_present("osc52"));
+    clip::set_tmux(matches.opt_present("tmux"));
+    match get_selection(&matches) {
+        Ok(selection) => clip::set_selection(selection),
+        Err(err) => {
+            println_err!("Woops, {}.", err);
+            ExitCode::Usage.exit();
+        }
+    }
+
+    // Like the password of `rooster add`, which is the first line of stdin when it is piped in.
+    if command.takes_all_of_stdin && command.reads_stdin.map_or(false, |reads| reads(&matches)) {
+        if answer::is_stdin_taken() {
+            println_err!("Woops, {} reads the password from the first line of stdin, so the master \
+                          password can't come from there too. Give it in ${} or with \
+                          --master-password-file <path> instead.",
+                         command.name,
+                         ROOSTER_MASTER_PASSWORD_ENV_VAR);
+            ExitCode::Usage.exit();
+        }
+        answer::set_stdin_is_input(true);
     }
 
     match command.callback_without_store {
         Some(cb) => {
             match (cb)(&matches) {
                 Err(i) => {
-                    std::process::exit(i);
+                    i.exit();
                 }
                 Ok(_) => {}
             };
@@ -620,8 +1903,32 @@ fn main() {
         None => {}
     }
 
+    if needs_store(command, &matches) {
+        let history_depth = match get_history_depth() {
+            Ok(depth) => depth,
+            Err(err) => {
+                println_err!("Woops, {}.", err);
+                ExitCode::Usage.exit();
+            }
+        };
+        let notify_threshold = match get_notify_threshold() {
+            Ok(threshold) => threshold,
+            Err(err) => {
+                println_err!("Woops, {}.", err);
+                ExitCode::Usage.exit();
+            }
+        };
+
+        let git_sync = matches.opt_present("git-sync");
+        let git_dir = if git_sync || git_enabled {
+            git::work_tree_of(Path::new(password_file_path.deref()))
+        } else {
+            None
+        };
+        if git_sync {
+            pull_from_git(git_dir.as_ref().map(|dir| dir.as_path()));
+        }
 
-    if command.callback_exec.is_some() {
         let (new_master_password, mut file) = match get_password_file(password_file_path.deref(),
                                                                       false) {
             Ok(file) => file,
@@ -634,19 +1941,167 @@ fn main() {
                     println_err!("I can't find your password file at {} (reason: {})",
                                  password_file_path,
                                  err);
+                    ExitCode::Io.exit();
                 }
-                std::process::exit(1);
+                ExitCode::NoPasswordFile.exit();
             }
         };
 
-        let mut store = match get_password_store(&mut file, new_master_password) {
-            Err(i) => std::process::exit(i),
+        let wait = matches.opt_present("wait");
+        let lock = lock_for(command);
+        if let Err(code) = lock_password_file(&mut file, password_file_path.deref(), lock, wait) {
+            code.exit();
+        }
+
+        // Questions go to the terminal, and not to input meant for the command.
+        let may_ask = sys::stdin_is_tty() &&
+                      !command.reads_stdin.map_or(false, |reads| reads(&matches));
+        let is_new_file = new_master_password.is_some();
+        let mut store = match get_password_store(&mut file,
+                                                 new_master_password,
+                                                 matches.opt_present("yubikey"),
+                                                 may_ask && lock == sys::Lock::Exclusive) {
+            Err(code) => code.exit(),
             Ok(store) => store,
         };
+        // After the master password, which may come first on stdin.
+        check_password_file_permissions(password_file_path.deref(),
+                                        matches.opt_present("fix-permissions"),
+                                        may_ask);
+        if let Some(depth) = history_depth {
+            store.set_history_depth(depth);
+        }
+        if matches.opt_present("archived") {
+            store.set_include_archived(true);
+        }
+        if let Some(collation) = collation {
+            store.override_collation(collation);
+        }
+        if matches.opt_present("fuzzy") {
+            store.override_matching(password::v2::Matching::Fuzzy);
+        } else if matches.opt_present("no-fuzzy") || matches.opt_present("exact") {
+            store.override_matching(password::v2::Matching::Exact);
+        }
+        if let Some(regex) = search_regex {
+            store.search_with_regex(regex);
+        }
+
+        let passwords_before = store.get_all_passwords().len();
+        let started = Instant::now();
+        let result = execute_command_from_filename(&matches,
+                                                   command,
+                                                   &mut file,
+                                                   &mut store,
+                                                   password_file_path.deref());
+        let _ = sys::unlock_file(&file);
+
+        if result.is_ok() {
+            if let Some(after_save) = command.after_save {
+                after_save(password_file_path.deref());
+            }
+        }
+        let changed = result.is_ok() &&
+                      (store.is_modified() || is_new_file || commands::batch::saved_checkpoint());
+        if changed {
+            if let Some(ref dir) = git_dir {
+                commit_to_git(dir, Path::new(password_file_path.deref()), command.name, git_sync);
+            }
+        }
+
+        let notify_done = matches.opt_present("notify-done") || store.settings().notify_done;
+        if notify_done && notify::should_notify(started.elapsed(), notify_threshold) {
+            notify::notify(&notify::Summary::new(command.name,
+                                                 result.is_ok(),
+                                                 passwords_before,
+                                                 store.get_all_passwords().len(),
+                                                 changed));
+        }
+
+        match result {
+            Err(code) => code.exit(),
+            _ => ExitCode::Success.exit(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{closest_command_name, command_from_name, edit_distance, get_selection,
+                password_file_path_from_env, ALIASES};
+    use clip::{has_primary_selection, Selection};
+    use commands::harness::matches;
+    use std::ffi::OsString;
+    use std::path::PathBuf;
+
+    #[test]
+    fn primary_copies_to_the_primary_selection_where_there_is_one() {
+        let selection = get_selection(&matches(&["get", "YouTube", "--primary"]));
+        if has_primary_selection() {
+            assert_eq!(selection, Ok(Selection::Primary));
+        } else {
+            assert!(selection.unwrap_err().contains("only has the clipboard"));
+        }
+        assert_eq!(Selection::from_name("primary"), Some(Selection::Primary));
+        assert_eq!(Selection::from_name("clipboard"), Some(Selection::Clipboard));
+        assert_eq!(Selection::from_name("PRIMARY"), None);
+    }
 
-        match execute_command_from_filename(&matches, command, &mut file, &mut store) {
-            Err(i) => std::process::exit(i),
-            _ => std::process::exit(0),
+    #[test]
+    fn aliases_never_shadow_commands() {
+        for &(alias, command_name) in ALIASES.iter() {
+            assert_eq!(command_from_name(alias).unwrap().name, command_name);
         }
+        assert_eq!(command_from_name("gen").unwrap().name, "gen");
+    }
+
+    #[test]
+    fn edit_distance_counts_changes() {
+        assert_eq!(edit_distance("list", "list"), 0);
+        assert_eq!(edit_distance("lsit", "list"), 2);
+        assert_eq!(edit_distance("delet", "delete"), 1);
+        assert_eq!(edit_distance("", "rm"), 2);
+    }
+
+    #[test]
+    fn typos_are_matched_to_the_closest_command() {
+        assert_eq!(closest_command_name("lsit"), Some("list"));
+        assert_eq!(closest_command_name("regenrate"), Some("regenerate"));
+        assert_eq!(closest_command_name("lss"), Some("ls"));
+        assert_eq!(closest_command_name("something"), None);
+    }
+
+    #[test]
+    fn the_password_file_comes_from_the_environment() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter().find(|&&(var, _)| var == name).map(|&(_, value)| OsString::from(value))
+            }
+        };
+        let home = Some(PathBuf::from("/home/me"));
+
+        assert_eq!(password_file_path_from_env(env(&[]), false, home.clone()),
+                   Some(PathBuf::from("/home/me/.passwords.rooster")));
+        assert_eq!(password_file_path_from_env(env(&[]), false, None), None);
+        assert_eq!(password_file_path_from_env(env(&[("ROOSTER_FILE", "/tmp/p.rooster")]),
+                                               false,
+                                               home.clone()),
+                   Some(PathBuf::from("/tmp/p.rooster")));
+        assert_eq!(password_file_path_from_env(env(&[("ROOSTER_FILE", "")]), false, home.clone()),
+                   Some(PathBuf::from("/home/me/.passwords.rooster")));
+
+        let app_data = env(&[("APPDATA", "C:\\Users\\me\\AppData\\Roaming"),
+                             ("USERPROFILE", "C:\\Users\\me")]);
+        assert_eq!(password_file_path_from_env(app_data, true, home.clone()),
+                   Some(PathBuf::from("C:\\Users\\me\\AppData\\Roaming")
+                            .join("rooster")
+                            .join("passwords.rooster")));
+        assert_eq!(password_file_path_from_env(env(&[("USERPROFILE", "C:\\Users\\me")]),
+                                               true,
+                                               None),
+                   Some(PathBuf::from("C:\\Users\\me").join(".passwords.rooster")));
+        assert_eq!(password_file_path_from_env(env(&[("ROOSTER_FILE", "D:\\p.rooster")]),
+                                               true,
+                                               home),
+                   Some(PathBuf::from("D:\\p.rooster")));
     }
 }
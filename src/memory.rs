@@ -0,0 +1,350 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Keeping secrets out of swap and out of freed memory.
+//!
+//! `SafeVec` and `SafeString` lock their buffer in memory and zero it when dropped. A buffer
+//! that grows is moved by the allocator, which would leave a copy of the secret behind, so the
+//! global allocator zeroes the old buffer of every reallocation before freeing it. Libraries
+//! also make short-lived copies that we can't reach, like the buffer where serde_json unescapes
+//! strings, so the global allocator zeroes every buffer it frees.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cmp;
+use std::collections::HashMap;
+use std::io::Error as IoError;
+use std::mem;
+use std::ptr;
+use std::sync::atomic::{self, AtomicBool, Ordering};
+use std::sync::Mutex;
+
+/// The number of `LockedRegion`s on each locked page, by address. Two small secrets often share
+/// a page, which must stay locked until both are gone.
+static LOCKED_PAGES: Mutex<Option<HashMap<usize, usize>>> = Mutex::new(None);
+
+/// Locking memory is best-effort, so we only warn the first time it fails.
+static WARNED: AtomicBool = AtomicBool::new(false);
+
+/// Called the first time locking fails, see `on_lock_failure`.
+static LOCK_FAILURE_CALLBACK: Mutex<Option<fn(&IoError)>> = Mutex::new(None);
+
+/// Sets what to do the first time a buffer can't be locked, typically because the limit of
+/// locked memory (`ulimit -l`) is too small, like warning the user. Failures are ignored
+/// otherwise.
+pub fn on_lock_failure(callback: fn(&IoError)) {
+    *LOCK_FAILURE_CALLBACK.lock().unwrap() = Some(callback);
+}
+
+/// Overwrites `len` bytes at `ptr` with zeros, in a way the optimizer can't remove even though
+/// the memory is about to be freed.
+///
+/// The bytes don't have to be initialized, like the spare capacity of a `Vec`.
+pub unsafe fn zero(ptr: *mut u8, len: usize) {
+    // A word at a time, since every buffer that is freed goes through here.
+    let head = cmp::min(ptr.align_offset(mem::size_of::<usize>()), len);
+    for i in 0..head {
+        ptr::write_volatile(ptr.add(i), 0u8);
+    }
+    let words = (len - head) / mem::size_of::<usize>();
+    let aligned = ptr.add(head) as *mut usize;
+    for i in 0..words {
+        ptr::write_volatile(aligned.add(i), 0usize);
+    }
+    for i in head + words * mem::size_of::<usize>()..len {
+        ptr::write_volatile(ptr.add(i), 0u8);
+    }
+    atomic::compiler_fence(Ordering::SeqCst);
+}
+
+/// The allocator of Rooster: the system one, except that buffers are zeroed before being freed,
+/// and that reallocations always move the contents to a new buffer and zero the old one, instead
+/// of letting the system free it as is.
+pub struct ScrubbingAllocator;
+
+unsafe impl GlobalAlloc for ScrubbingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        #[cfg(test)]
+        test::inspect(ptr, layout.size());
+
+        zero(ptr, layout.size());
+        #[cfg(test)]
+        test::search_leftover(ptr, layout.size());
+
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+        let new_ptr = System.alloc(new_layout);
+        if !new_ptr.is_null() {
+            ptr::copy_nonoverlapping(ptr, new_ptr, cmp::min(layout.size(), new_size));
+            zero(ptr, layout.size());
+            self.dealloc(ptr, layout);
+        }
+        new_ptr
+    }
+}
+
+/// The pages of a buffer holding a secret, locked so that they aren't written to swap, and
+/// unlocked when dropped.
+///
+/// Locking is best-effort: if the limit of locked memory (`ulimit -l`) is too small, the
+/// callback of `on_lock_failure` is called once and the buffer stays unlocked.
+pub struct LockedRegion {
+    pages: Vec<usize>,
+}
+
+impl LockedRegion {
+    /// Locks `len` bytes at `ptr`, typically the whole capacity of a `Vec`.
+    pub fn new(ptr: *const u8, len: usize) -> LockedRegion {
+        let mut region = LockedRegion { pages: Vec::new() };
+        region.relock(ptr, len);
+        region
+    }
+
+    /// Moves the lock to a buffer that was reallocated. Does nothing if it didn't move.
+    pub fn relock(&mut self, ptr: *const u8, len: usize) {
+        let pages = pages_of(ptr as usize, len);
+        if pages == self.pages {
+            return;
+        }
+        unlock_pages(&self.pages);
+        lock_pages(&pages);
+        self.pages = pages;
+    }
+}
+
+impl Drop for LockedRegion {
+    fn drop(&mut self) {
+        unlock_pages(&self.pages);
+    }
+}
+
+fn page_size() -> usize {
+    #[cfg(unix)]
+    unsafe {
+        ::libc::sysconf(::libc::_SC_PAGESIZE) as usize
+    }
+    #[cfg(not(unix))]
+    4096
+}
+
+fn pages_of(address: usize, len: usize) -> Vec<usize> {
+    if len == 0 {
+        return Vec::new();
+    }
+    let page_size = page_size();
+    let first = address - address % page_size;
+    (first..address + len).step_by(page_size).collect()
+}
+
+fn lock_pages(pages: &[usize]) {
+    let mut error = None;
+    {
+        let mut locked = LOCKED_PAGES.lock().unwrap();
+        let locked = locked.get_or_insert_with(HashMap::new);
+        for page in pages {
+            let count = locked.entry(*page).or_insert(0);
+            *count += 1;
+            if *count == 1 {
+                if let Err(err) = mlock(*page) {
+                    error = Some(err);
+                }
+            }
+        }
+    }
+
+    if let Some(err) = error {
+        if !WARNED.swap(true, Ordering::SeqCst) {
+            let callback = *LOCK_FAILURE_CALLBACK.lock().unwrap();
+            if let Some(callback) = callback {
+                callback(&err);
+            }
+        }
+    }
+}
+
+fn unlock_pages(pages: &[usize]) {
+    if pages.is_empty() {
+        return;
+    }
+    let mut locked = LOCKED_PAGES.lock().unwrap();
+    let locked = locked.get_or_insert_with(HashMap::new);
+    for page in pages {
+        let last = match locked.get_mut(page) {
+            Some(count) => {
+                *count -= 1;
+                *count == 0
+            }
+            None => false,
+        };
+        if last {
+            locked.remove(page);
+            munlock(*page);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn mlock(page: usize) -> Result<(), IoError> {
+    if unsafe { ::libc::mlock(page as *const ::libc::c_void, page_size()) } == 0 {
+        Ok(())
+    } else {
+        Err(IoError::last_os_error())
+    }
+}
+
+#[cfg(not(unix))]
+fn mlock(_page: usize) -> Result<(), IoError> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn munlock(page: usize) {
+    unsafe {
+        ::libc::munlock(page as *const ::libc::c_void, page_size());
+    }
+}
+
+#[cfg(not(unix))]
+fn munlock(_page: usize) {}
+
+#[cfg(test)]
+pub mod test {
+    use super::{pages_of, page_size, zero, LOCKED_PAGES};
+    use std::cell::{Cell, RefCell};
+    use std::mem;
+    use std::slice;
+
+    thread_local! {
+        // The buffer whose contents are checked when it's freed, and whether they were zeros.
+        static WATCHED: Cell<usize> = const { Cell::new(0) };
+        static FREED_ZEROED: Cell<Option<bool>> = const { Cell::new(None) };
+        // The secret looked for in every buffer that is freed, and whether it was found.
+        static LEFTOVER: RefCell<Option<Vec<u8>>> = const { RefCell::new(None) };
+        static LEFTOVER_FOUND: Cell<bool> = const { Cell::new(false) };
+    }
+
+    /// Called by the allocator on every buffer, right before it's freed.
+    pub unsafe fn inspect(ptr: *mut u8, len: usize) {
+        let _ = WATCHED.try_with(|watched| {
+            if watched.get() == ptr as usize {
+                watched.set(0);
+                let zeroed = slice::from_raw_parts(ptr, len).iter().all(|b| *b == 0u8);
+                FREED_ZEROED.with(|freed| freed.set(Some(zeroed)));
+            }
+        });
+    }
+
+    /// Called by the allocator on every buffer, once it's scrubbed and right before it's freed.
+    pub unsafe fn search_leftover(ptr: *mut u8, len: usize) {
+        let _ = LEFTOVER.try_with(|leftover| {
+            if let Ok(leftover) = leftover.try_borrow() {
+                if let Some(ref secret) = *leftover {
+                    let freed = slice::from_raw_parts(ptr, len);
+                    if freed.windows(secret.len()).any(|window| window == &secret[..]) {
+                        LEFTOVER_FOUND.with(|found| found.set(true));
+                    }
+                }
+            }
+        });
+    }
+
+    /// Runs `f` and tells whether a buffer it freed still held `secret` once freed, whoever
+    /// made it.
+    pub fn leaves_behind<F: FnOnce()>(secret: &[u8], f: F) -> bool {
+        LEFTOVER_FOUND.with(|found| found.set(false));
+        LEFTOVER.with(|leftover| *leftover.borrow_mut() = Some(secret.to_vec()));
+        f();
+        let secret = LEFTOVER.with(|leftover| leftover.borrow_mut().take());
+        mem::drop(secret);
+        LEFTOVER_FOUND.with(|found| found.get())
+    }
+
+    /// Runs `free` and tells whether the buffer at `ptr` was zeroed before being freed, or None
+    /// if it wasn't freed.
+    pub fn freed_zeroed<F: FnOnce()>(ptr: *const u8, free: F) -> Option<bool> {
+        FREED_ZEROED.with(|freed| freed.set(None));
+        WATCHED.with(|watched| watched.set(ptr as usize));
+        free();
+        WATCHED.with(|watched| watched.set(0));
+        FREED_ZEROED.with(|freed| freed.get())
+    }
+
+    #[test]
+    fn the_harness_sees_secrets_left_behind() {
+        let secret = vec![0xa5u8; 64];
+        let ptr = secret.as_ptr();
+        assert_eq!(freed_zeroed(ptr, || mem::drop(secret)), Some(false));
+    }
+
+    #[test]
+    fn zero_only_clears_the_given_bytes() {
+        let mut bytes = [0xa5u8; 40];
+        unsafe { zero(bytes.as_mut_ptr().add(3), 30) };
+        assert!(bytes[..3].iter().all(|b| *b == 0xa5u8));
+        assert!(bytes[3..33].iter().all(|b| *b == 0u8));
+        assert!(bytes[33..].iter().all(|b| *b == 0xa5u8));
+    }
+
+    #[test]
+    fn freed_buffers_are_scrubbed() {
+        let copy = b"a copy nobody wipes".to_vec();
+        assert!(!leaves_behind(b"nobody wipes", || mem::drop(copy)));
+    }
+
+    #[test]
+    fn reallocation_zeroes_the_old_buffer() {
+        let mut secret = vec![0xa5u8; 64];
+        secret.shrink_to_fit();
+        let ptr = secret.as_ptr();
+        assert_eq!(freed_zeroed(ptr, || secret.extend_from_slice(&[0xa5u8; 1024])), Some(true));
+        assert!(secret.iter().all(|b| *b == 0xa5u8));
+    }
+
+    #[test]
+    fn pages_of_a_buffer() {
+        let size = page_size();
+        assert!(pages_of(size, 0).is_empty());
+        assert_eq!(pages_of(size, 1), vec![size]);
+        assert_eq!(pages_of(size + 1, size), vec![size, 2 * size]);
+        assert_eq!(pages_of(3 * size - 1, 2), vec![2 * size, 3 * size]);
+    }
+
+    #[test]
+    fn shared_pages_stay_locked_until_the_last_region_is_dropped() {
+        // A fake address that no real buffer will use.
+        let page = usize::max_value() - 2 * page_size() + 1;
+        let page = page - page % page_size();
+        let count = || {
+            LOCKED_PAGES.lock().unwrap().as_ref().and_then(|locked| locked.get(&page).cloned())
+        };
+
+        let first = super::LockedRegion { pages: vec![page] };
+        let second = super::LockedRegion { pages: vec![page] };
+        LOCKED_PAGES.lock().unwrap().get_or_insert_with(Default::default).insert(page, 2);
+        mem::drop(first);
+        assert_eq!(count(), Some(1));
+        mem::drop(second);
+        assert_eq!(count(), None);
+    }
+}
@@ -0,0 +1,114 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! Tells the user that a long command is done, in case they went to another window meanwhile.
+//! Notifications are seen by anyone looking at the screen, so they are made from a `Summary`,
+//! which can't hold anything but the command name and counts.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+/// Commands that finish quicker than this don't notify, unless $ROOSTER_NOTIFY_AFTER says so.
+pub const DEFAULT_THRESHOLD: Duration = Duration::from_secs(5);
+
+/// What a command did, as far as notifications are concerned.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Summary {
+    command: &'static str,
+    succeeded: bool,
+    passwords_before: usize,
+    passwords_after: usize,
+    saved: bool,
+}
+
+impl Summary {
+    pub fn new(command: &'static str,
+               succeeded: bool,
+               passwords_before: usize,
+               passwords_after: usize,
+               saved: bool)
+               -> Summary {
+        Summary {
+            command: command,
+            succeeded: succeeded,
+            passwords_before: passwords_before,
+            passwords_after: passwords_after,
+            saved: saved,
+        }
+    }
+
+    /// Like "rooster: import finished — 212 entries added".
+    pub fn text(&self) -> String {
+        if !self.succeeded {
+            return format!("rooster: {} failed", self.command);
+        }
+
+        let details = if self.passwords_after > self.passwords_before {
+            format!(" \u{2014} {} entries added", self.passwords_after - self.passwords_before)
+        } else if self.passwords_after < self.passwords_before {
+            format!(" \u{2014} {} entries removed", self.passwords_before - self.passwords_after)
+        } else if self.saved {
+            " \u{2014} changes saved".to_owned()
+        } else {
+            String::new()
+        };
+        format!("rooster: {} finished{}", self.command, details)
+    }
+}
+
+pub fn should_notify(elapsed: Duration, threshold: Duration) -> bool {
+    elapsed >= threshold
+}
+
+/// Rings the terminal bell, and shows a desktop notification if notify-send is installed.
+pub fn notify(summary: &Summary) {
+    print_stderr!("\x07");
+
+    // Without notify-send, the bell is all there is.
+    let _ = Command::new("notify-send")
+        .arg("Rooster")
+        .arg(summary.text())
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+}
+
+#[cfg(test)]
+mod test {
+    use super::{should_notify, Summary, DEFAULT_THRESHOLD};
+    use std::time::Duration;
+
+    #[test]
+    fn only_long_commands_notify() {
+        assert!(!should_notify(Duration::from_millis(4999), DEFAULT_THRESHOLD));
+        assert!(should_notify(Duration::from_secs(5), DEFAULT_THRESHOLD));
+        assert!(should_notify(Duration::from_secs(60), DEFAULT_THRESHOLD));
+        assert!(should_notify(Duration::from_secs(0), Duration::from_secs(0)));
+    }
+
+    #[test]
+    fn text_comes_from_the_summary() {
+        assert_eq!(Summary::new("import", true, 10, 222, true).text(),
+                   "rooster: import finished \u{2014} 212 entries added");
+        assert_eq!(Summary::new("clear-all", true, 3, 0, true).text(),
+                   "rooster: clear-all finished \u{2014} 3 entries removed");
+        assert_eq!(Summary::new("set-master-password", true, 3, 3, true).text(),
+                   "rooster: set-master-password finished \u{2014} changes saved");
+        assert_eq!(Summary::new("get", true, 3, 3, false).text(), "rooster: get finished");
+        assert_eq!(Summary::new("import", false, 3, 3, false).text(), "rooster: import failed");
+    }
+}
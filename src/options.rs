@@ -0,0 +1,509 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The command line options, kept as data so that parsing them and completing them in shells
+//! agree on which options there are, and which commands they apply to.
+
+use getopts::Options;
+
+pub enum Kind {
+    Flag,
+    /// Takes a value, shown by its hint like PATH.
+    Value(&'static str),
+    /// Takes a value, and may be given several times.
+    Multi(&'static str),
+}
+
+pub struct Opt {
+    pub short: &'static str,
+    pub long: &'static str,
+    pub kind: Kind,
+    pub description: &'static str,
+    /// The commands this option applies to. Options for the whole run apply to every command.
+    pub commands: &'static [&'static str],
+}
+
+/// Options like --file and --quiet, which the whole run reads rather than a command.
+const EVERY_COMMAND: &'static [&'static str] = &[];
+
+/// The commands that generate passwords, and read the options of `rooster generate`.
+const GENERATING: &'static [&'static str] = &["add", "change", "gen", "generate", "regenerate"];
+
+impl Opt {
+    const fn flag(short: &'static str,
+                  long: &'static str,
+                  description: &'static str,
+                  commands: &'static [&'static str])
+                  -> Opt {
+        Opt {
+            short: short,
+            long: long,
+            kind: Kind::Flag,
+            description: description,
+            commands: commands,
+        }
+    }
+
+    const fn value(short: &'static str,
+                   long: &'static str,
+                   description: &'static str,
+                   hint: &'static str,
+                   commands: &'static [&'static str])
+                   -> Opt {
+        Opt {
+            short: short,
+            long: long,
+            kind: Kind::Value(hint),
+            description: description,
+            commands: commands,
+        }
+    }
+
+    const fn multi(short: &'static str,
+                   long: &'static str,
+                   description: &'static str,
+                   hint: &'static str,
+                   commands: &'static [&'static str])
+                   -> Opt {
+        Opt {
+            short: short,
+            long: long,
+            kind: Kind::Multi(hint),
+            description: description,
+            commands: commands,
+        }
+    }
+
+    pub fn takes_value(&self) -> bool {
+        match self.kind {
+            Kind::Flag => false,
+            Kind::Value(_) | Kind::Multi(_) => true,
+        }
+    }
+
+    /// Whether the whole run reads this option, whatever the command.
+    pub fn is_global(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    /// The name shown in `rooster -h` and the man page, like "-f, --file <path>".
+    pub fn help_name(&self) -> String {
+        let mut name = String::new();
+        if !self.short.is_empty() {
+            name.push_str(&format!("-{}, ", self.short));
+        }
+        name.push_str(&format!("--{}", self.long));
+        match self.kind {
+            Kind::Flag => {}
+            Kind::Value(hint) | Kind::Multi(hint) => {
+                name.push_str(&format!(" <{}>", hint.to_lowercase()))
+            }
+        }
+        name
+    }
+
+    /// The description shown in `rooster -h`, followed by the commands the option applies to
+    /// unless it is global or for every command that generates passwords, and wrapped so that it
+    /// fits next to the names.
+    pub fn help_description(&self) -> String {
+        let mut description = self.description.to_owned();
+        if !self.is_global() && self.commands != GENERATING {
+            description.push_str(&format!(" ({})", self.commands.join(", ")));
+        }
+        wrap(&description, HELP_WIDTH)
+    }
+}
+
+/// How wide the descriptions of `rooster -h` may be, so that the table fits in 80-odd columns.
+const HELP_WIDTH: usize = 62;
+
+fn wrap(text: &str, width: usize) -> String {
+    let mut wrapped = String::new();
+    let mut line_len = 0;
+    for word in text.split(' ') {
+        if line_len > 0 && line_len + 1 + word.len() > width {
+            wrapped.push('\n');
+            line_len = 0;
+        } else if line_len > 0 {
+            wrapped.push(' ');
+            line_len += 1;
+        }
+        wrapped.push_str(word);
+        line_len += word.len();
+    }
+    wrapped
+}
+
+pub const OPTIONS: &'static [Opt] = &[
+    Opt::flag("h", "help", "Display a help message", EVERY_COMMAND),
+    Opt::flag("v", "version", "Display the version of Rooster you are using", EVERY_COMMAND),
+    Opt::value("f",
+               "file",
+               "Use this password file, whatever $ROOSTER_FILE says",
+               "PATH",
+               EVERY_COMMAND),
+    Opt::value("",
+               "profile",
+               "Use the password file of this profile, see `rooster profile`",
+               "NAME",
+               EVERY_COMMAND),
+    Opt::flag("a",
+              "alnum",
+              "Only use alpha numeric (a-z, A-Z, 0-9) in generated passwords",
+              GENERATING),
+    Opt::value("l",
+               "length",
+               "Set a custom length for the generated password, default is 32",
+               "LENGTH",
+               GENERATING),
+    Opt::value("",
+               "similar-to",
+               "Generate a password shaped like the one for <query>",
+               "QUERY",
+               GENERATING),
+    Opt::value("",
+               "charset",
+               "Only use these characters in generated passwords",
+               "CHARS",
+               GENERATING),
+    Opt::value("",
+               "exclude-chars",
+               "Never use these characters in generated passwords",
+               "CHARS",
+               GENERATING),
+    Opt::flag("",
+              "exclude-similar",
+              "Leave out characters that look alike (0O1lI|)",
+              GENERATING),
+    Opt::value("",
+               "min-entropy",
+               "Don't save a generated password with less entropy than <bits>",
+               "BITS",
+               GENERATING),
+    Opt::value("", "words", "Generate a passphrase of <n> random words instead", "N", GENERATING),
+    Opt::value("",
+               "separator",
+               "Join the words of a passphrase with <sep>, default is -",
+               "SEP",
+               GENERATING),
+    Opt::flag("",
+              "capitalize",
+              "Make the first letter of each word of a passphrase uppercase",
+              GENERATING),
+    Opt::flag("",
+              "pronounceable",
+              "Generate a password of alternating consonants and vowels",
+              GENERATING),
+    Opt::flag("",
+              "pin",
+              "Generate a PIN of digits only, 6 unless --length is given",
+              GENERATING),
+    Opt::flag("", "generate", "Generate the password instead of asking for it", &["add", "change"]),
+    Opt::flag("", "no-confirm", "Only ask for a new password once", &["add", "change"]),
+    Opt::flag("",
+              "add-digit",
+              "End a passphrase or a pronounceable password with a digit",
+              GENERATING),
+    Opt::flag("s",
+              "show",
+              "Show the password instead of copying it to the clipboard",
+              &["add", "change", "gen", "get", "history", "regenerate"]),
+    Opt::value("", "field", "Copy or show username, password, url or notes", "FIELD", &["get"]),
+    Opt::flag("", "stdout", "Only print the password on stdout, for scripts", &["get"]),
+    Opt::flag("", "no-newline", "Same as --stdout, without the trailing newline", &["get"]),
+    Opt::flag("", "notes", "Also show the note of the password", &["get"]),
+    Opt::flag("u", "username", "Copy or show the username instead of the password", &["get"]),
+    Opt::flag("", "set", "Read a new note from standard input", &["note"]),
+    Opt::flag("", "clear", "Remove the note", &["note"]),
+    Opt::multi("",
+               "tag",
+               "Tag a new password, or only show passwords with that tag",
+               "TAG",
+               &["add", "generate", "get", "list"]),
+    Opt::value("",
+               "inactive-usernames",
+               "Only list passwords whose username is in <file>",
+               "FILE",
+               &["list"]),
+    Opt::flag("",
+              "archive-matches",
+              "Archive the passwords matched by --inactive-usernames",
+              &["list"]),
+    Opt::value("",
+               "index",
+               "Pick the previous value of a password with that number",
+               "N",
+               &["history"]),
+    Opt::flag("", "purge", "Forget the previous values of a password", &["history"]),
+    Opt::value("",
+               "map-fields",
+               "Tell which CSV column holds which field",
+               "MAPPING",
+               &["import"]),
+    Opt::value("",
+               "export-first",
+               "Save an encrypted copy before deleting",
+               "PATH",
+               &["clear-all", "uninstall"]),
+    Opt::flag("", "swap", "Exchange the names of two passwords", &["rename"]),
+    Opt::flag("",
+              "force",
+              "Overwrite the password that has the new name, keep a password that is easy to \
+               guess, or replace the password file",
+              &["add", "change", "init", "rename"]),
+    Opt::flag("", "archived", "Include archived passwords", EVERY_COMMAND),
+    Opt::flag("", "fix-timestamps", "Repair broken timestamps", &["doctor"]),
+    Opt::flag("", "strict", "Stop if any query matches nothing", &["delete"]),
+    Opt::flag("", "keep-going", "Run the other lines when one fails", &["batch"]),
+    Opt::flag("", "previous", "Bring back the previous version of the password file", &["restore"]),
+    Opt::value("", "log-n", "Derive the key with scrypt, with N = 2^<n>", "N", &["set-kdf"]),
+    Opt::value("", "scrypt-r", "The r parameter of scrypt, 8 by default", "R", &["set-kdf"]),
+    Opt::value("", "scrypt-p", "The p parameter of scrypt, 1 by default", "P", &["set-kdf"]),
+    Opt::flag("", "argon2id", "Derive the key with Argon2id", &["set-kdf"]),
+    Opt::value("", "memory-mib", "The memory Argon2id uses, in MiB", "M", &["set-kdf"]),
+    Opt::value("",
+               "target-ms",
+               "How long opening the password file should take",
+               "MS",
+               &["kdf-benchmark"]),
+    Opt::flag("", "scrypt", "Measure scrypt instead of Argon2id", &["kdf-benchmark"]),
+    Opt::flag("", "apply", "Use the parameters that come closest", &["kdf-benchmark"]),
+    Opt::flag("",
+              "no-backup",
+              "Don't keep the previous version of the password file",
+              EVERY_COMMAND),
+    Opt::flag("",
+              "fix-permissions",
+              "Make the password file private if others can read it",
+              EVERY_COMMAND),
+    Opt::value("",
+               "checkpoint",
+               "Also write the password file every <n> lines",
+               "N",
+               &["batch"]),
+    Opt::flag("", "all", "Regenerate every password", &["regenerate"]),
+    Opt::value("",
+               "older-than",
+               "Regenerate the passwords that haven't changed for that long",
+               "DURATION",
+               &["regenerate"]),
+    Opt::flag("", "confirm-each", "Ask before regenerating each password", &["regenerate"]),
+    Opt::flag("",
+              "password-stdin",
+              "Read the password from stdin instead of asking for it",
+              &["add", "add-or-update"]),
+    Opt::flag("", "json", "Print the outcome in JSON", &["add-or-update", "get", "list"]),
+    Opt::flag("",
+              "notify-done",
+              "Ring the bell when a command that took long is done",
+              EVERY_COMMAND),
+    Opt::flag("",
+              "git-sync",
+              "Pull the password file with git before, and push it after",
+              EVERY_COMMAND),
+    Opt::flag("",
+              "wait",
+              "Wait for other Roosters to be done with the password file",
+              EVERY_COMMAND),
+    Opt::flag("",
+              "force-write",
+              "Save even if the password file was changed by another program meanwhile",
+              EVERY_COMMAND),
+    Opt::flag("",
+              "dry-run",
+              "Only show what would be done, without changing anything",
+              &["import", "dedupe", "uninstall"]),
+    Opt::flag("",
+              "ascii",
+              "Only use ASCII characters, even if the locale uses UTF-8",
+              EVERY_COMMAND),
+    Opt::flag("",
+              "unicode",
+              "Use Unicode characters, even if the locale doesn't use UTF-8",
+              EVERY_COMMAND),
+    Opt::value("",
+               "collate",
+               "Sort passwords by codepoint or locale, whatever the config says",
+               "COLLATION",
+               EVERY_COMMAND),
+    Opt::value("",
+               "sort",
+               "Sort passwords by name, created or updated, in lists and pickers",
+               "KEY",
+               EVERY_COMMAND),
+    Opt::flag("", "reverse", "Sort passwords the other way around", EVERY_COMMAND),
+    Opt::flag("",
+              "exact",
+              "Only find passwords by their exact name, without asking to choose (list: only \
+               list names that contain the query)",
+              EVERY_COMMAND),
+    Opt::flag("", "verbose", "Also show when passwords were created and updated", &["list"]),
+    Opt::flag("", "info", "Show the details of a password, but not the password", &["get"]),
+    Opt::flag("",
+              "porcelain",
+              "List passwords as tab-separated lines, without colors",
+              EVERY_COMMAND),
+    Opt::value("",
+               "updated-since",
+               "Only list passwords updated since a date or a duration ago, like 2020-01-01 \
+                or 90d",
+               "DATE",
+               &["list"]),
+    Opt::value("",
+               "updated-before",
+               "Only list passwords updated before a date or a duration ago, like 2020-01-01 \
+                or 90d",
+               "DATE",
+               &["list"]),
+    Opt::value("",
+               "created-since",
+               "Only list passwords created since a date or a duration ago, like 2020-01-01 \
+                or 90d",
+               "DATE",
+               &["list"]),
+    Opt::flag("", "names-only", "Only list the names of the passwords", &["list"]),
+    Opt::flag("",
+              "fuzzy",
+              "Find passwords with fuzzy search, whatever the config says",
+              EVERY_COMMAND),
+    Opt::flag("",
+              "primary",
+              "Copy to the PRIMARY selection, pasted with a middle-click",
+              EVERY_COMMAND),
+    Opt::flag("",
+              "osc52",
+              "Copy by asking the terminal, with OSC 52 (works over SSH)",
+              EVERY_COMMAND),
+    Opt::flag("", "tmux", "Copy into a tmux paste buffer", EVERY_COMMAND),
+    Opt::flag("", "type", "Type the password with xdotool instead of copying it", &["get"]),
+    Opt::value("", "type-delay", "How long to wait before typing", "SECONDS", &["get"]),
+    Opt::flag("", "press-enter", "Press Enter after typing the password", &["get"]),
+    Opt::flag("",
+              "regex",
+              "Search with a case-insensitive regular expression",
+              &["get", "list"]),
+    Opt::flag("", "no-fuzzy", "Only find passwords by their exact name", EVERY_COMMAND),
+    Opt::flag("", "no-askpass", "Only ask for the master password in the terminal", EVERY_COMMAND),
+    Opt::flag("q", "quiet", "Only print errors and what you asked for", EVERY_COMMAND),
+    Opt::value("",
+               "error-format",
+               "Tell failures in sentences or in JSON",
+               "human|json",
+               EVERY_COMMAND),
+    Opt::value("",
+               "format",
+               "Print the outcome in text or in JSON",
+               "text|json",
+               &["add-or-update", "get", "list"]),
+    Opt::flag("",
+              "non-interactive",
+              "Fail with exit code 3 instead of asking anything",
+              EVERY_COMMAND),
+    Opt::value("",
+               "master-password-file",
+               "Read the master password from the first line of a file, or of stdin for -, \
+                which only its owner may read",
+               "PATH",
+               EVERY_COMMAND),
+    Opt::flag("",
+              "master-password-stdin",
+              "Read the master password from the first line of stdin, and ask questions in the \
+               terminal",
+              EVERY_COMMAND),
+    Opt::flag("",
+              "insecure-master-password-file",
+              "Read the master password from a file other users can read",
+              EVERY_COMMAND),
+    Opt::value("",
+               "key-file",
+               "Open (or create) the password file with this key file too",
+               "PATH",
+               EVERY_COMMAND),
+    Opt::value("",
+               "new-key-file",
+               "Also require this key file from now on",
+               "PATH",
+               &["set-master-password"]),
+    Opt::flag("", "no-key-file", "Stop requiring a key file", &["set-master-password"]),
+    Opt::flag("",
+              "yubikey",
+              "Also require a YubiKey from now on",
+              &["init", "set-master-password"]),
+    Opt::flag("", "no-yubikey", "Stop requiring a YubiKey", &["set-master-password"]),
+];
+
+pub fn build() -> Options {
+    let mut opts = Options::new();
+    for opt in OPTIONS.iter() {
+        match opt.kind {
+            Kind::Flag => opts.optflag(opt.short, opt.long, opt.description),
+            Kind::Value(hint) => opts.optopt(opt.short, opt.long, opt.description, hint),
+            Kind::Multi(hint) => opts.optmulti(opt.short, opt.long, opt.description, hint),
+        };
+    }
+    opts
+}
+
+#[cfg(test)]
+mod test {
+    use super::{HELP_WIDTH, OPTIONS};
+    use exit_code::ExitCode;
+    use rooster::generate;
+    use COMMANDS;
+
+    fn description_of(long: &str) -> &'static str {
+        OPTIONS.iter().find(|opt| opt.long == long).unwrap().description
+    }
+
+    #[test]
+    fn options_apply_to_commands_that_exist() {
+        for opt in OPTIONS.iter() {
+            for name in opt.commands.iter() {
+                assert!(COMMANDS.iter().any(|command| command.name == *name),
+                        "--{} applies to {}",
+                        opt.long,
+                        name);
+            }
+        }
+    }
+
+    #[test]
+    fn help_names_show_the_value_hint() {
+        let file = OPTIONS.iter().find(|opt| opt.long == "file").unwrap();
+        assert_eq!(file.help_name(), "-f, --file <path>");
+        let swap = OPTIONS.iter().find(|opt| opt.long == "swap").unwrap();
+        assert_eq!(swap.help_name(), "--swap");
+        assert_eq!(swap.help_description(), "Exchange the names of two passwords (rename)");
+    }
+
+    #[test]
+    fn help_descriptions_fit_next_to_the_names() {
+        for opt in OPTIONS.iter() {
+            for line in opt.help_description().lines() {
+                assert!(line.len() <= HELP_WIDTH, "--{}: {}", opt.long, line);
+            }
+        }
+    }
+
+    // The descriptions are constants, so these keep them in line with the code.
+    #[test]
+    fn help_descriptions_match_the_defaults() {
+        assert!(description_of("exclude-similar")
+                    .contains(&format!("({})", generate::SIMILAR_CHARS)));
+        assert!(description_of("pin")
+                    .contains(&format!("{} unless", generate::DEFAULT_PIN_LENGTH)));
+        assert!(description_of("non-interactive")
+                    .contains(&format!("exit code {} ", ExitCode::WouldAsk.code())));
+    }
+}
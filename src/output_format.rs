@@ -0,0 +1,127 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! With --error-format json, failures are told with a single JSON object on stderr, for the
+//! programs that wrap Rooster, and --format json does the same for what commands print on
+//! success.
+
+use exit_code::ExitCode;
+use getopts;
+use serde_json;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+static JSON_ERRORS: AtomicBool = AtomicBool::new(false);
+
+/// The error messages printed so far, told in the JSON object instead.
+static MESSAGES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// The password the failure is about, if any.
+static APP: Mutex<Option<String>> = Mutex::new(None);
+
+pub const ERROR_FORMATS: &'static [&'static str] = &["human", "json"];
+pub const FORMATS: &'static [&'static str] = &["text", "json"];
+
+pub fn set_json_errors(enabled: bool) {
+    JSON_ERRORS.store(enabled, Ordering::SeqCst);
+}
+
+/// Checked by the output macros, which only record error messages when it is on.
+pub fn json_errors() -> bool {
+    JSON_ERRORS.load(Ordering::SeqCst)
+}
+
+/// Whether --error-format json is among the arguments. This is looked up before the options
+/// are parsed, so that wrong options are told in JSON too.
+pub fn json_errors_in_args(args: &[String]) -> bool {
+    args.iter().enumerate().any(|(i, arg)| {
+        arg == "--error-format=json" ||
+        (arg == "--error-format" && args.get(i + 1).map_or(false, |value| value == "json"))
+    })
+}
+
+/// Whether the outcome of the command should be printed in JSON, with --format json (or the
+/// older --json of some commands).
+pub fn json_requested(matches: &getopts::Matches) -> bool {
+    matches.opt_present("json") || matches.opt_str("format").map_or(false, |f| f == "json")
+}
+
+pub fn record(message: String) {
+    MESSAGES.lock().unwrap().push(message);
+}
+
+pub fn set_app(name: &str) {
+    *APP.lock().unwrap() = Some(name.to_owned());
+}
+
+fn error_object(code: ExitCode, messages: &[String], app: Option<&str>) -> serde_json::Value {
+    let mut object = serde_json::Map::new();
+    object.insert("error".to_owned(), code.name().into());
+    let message = if messages.is_empty() {
+        code.description().to_owned()
+    } else {
+        messages.iter().map(|m| m.trim()).collect::<Vec<&str>>().join(" ")
+    };
+    object.insert("message".to_owned(), message.into());
+    if let Some(app) = app {
+        object.insert("app".to_owned(), app.into());
+    }
+    serde_json::Value::Object(object)
+}
+
+/// Prints the JSON object for a failure, when --error-format json is on.
+pub fn report(code: ExitCode) {
+    if !json_errors() || code == ExitCode::Success {
+        return;
+    }
+    let messages = MESSAGES.lock().unwrap();
+    let app = APP.lock().unwrap();
+    let object = error_object(code, &messages, app.as_ref().map(|app| app.as_str()));
+    let _ = writeln!(&mut ::std::io::stderr(), "{}", object);
+}
+
+#[cfg(test)]
+mod test {
+    use super::{error_object, json_errors_in_args};
+    use exit_code::ExitCode;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn error_format_is_found_before_parsing() {
+        assert!(json_errors_in_args(&args(&["rooster", "--error-format", "json", "get"])));
+        assert!(json_errors_in_args(&args(&["rooster", "get", "--error-format=json"])));
+        assert!(!json_errors_in_args(&args(&["rooster", "--error-format", "human"])));
+        assert!(!json_errors_in_args(&args(&["rooster", "--error-format"])));
+    }
+
+    #[test]
+    fn error_objects_hold_the_messages() {
+        let messages = vec!["Woops, that's missing. For help, try:".to_owned(),
+                            "    rooster get -h".to_owned()];
+        let object = error_object(ExitCode::Usage, &messages, None);
+        assert_eq!(object.to_string(),
+                   "{\"error\":\"usage\",\"message\":\"Woops, that's missing. For help, try: \
+                    rooster get -h\"}");
+
+        let object = error_object(ExitCode::NotFound, &[], Some("github"));
+        assert_eq!(object["error"], "no_such_app");
+        assert_eq!(object["message"], "No password matches the query");
+        assert_eq!(object["app"], "github");
+    }
+}
@@ -14,12 +14,13 @@
 
 pub mod v1;
 pub mod v2;
+pub mod v3;
 
-use std::io::{Error as IoError, stdin, Write};
-use std::ops::Deref;
+use std::error::Error;
+use std::fmt;
+use std::io::Error as IoError;
 use std::convert::From;
 use safe_string::SafeString;
-use safe_vec::SafeVec;
 
 #[derive(Debug)]
 pub enum PasswordError {
@@ -30,7 +31,82 @@ pub enum PasswordError {
     Io(IoError),
     WrongVersionError,
     InvalidJsonError,
+    /// The signature doesn't match, or a file whose key is known to be right doesn't decrypt.
     CorruptionError,
+    /// The key check of the file doesn't match the key: the master password, or the response
+    /// of the YubiKey, is wrong.
+    WrongMasterPasswordError,
+    /// The password file needs a key file, and none was given.
+    KeyFileRequiredError,
+    /// The key file isn't the one of the password file, or it was modified.
+    WrongKeyFileError,
+    /// A key file was given, but the password file doesn't need one.
+    KeyFileNotUsedError,
+    /// The password file needs the response of a YubiKey, and none was given.
+    YubiKeyRequiredError,
+}
+
+impl PasswordError {
+    /// Whether the key file or the YubiKey is missing or wrong, rather than the master password.
+    pub fn is_second_factor_error(&self) -> bool {
+        match *self {
+            PasswordError::KeyFileRequiredError |
+            PasswordError::WrongKeyFileError |
+            PasswordError::KeyFileNotUsedError |
+            PasswordError::YubiKeyRequiredError => true,
+            _ => false,
+        }
+    }
+
+    /// Whether asking the master password again may help. Files without a key check only tell
+    /// a wrong master password by failing to decrypt, sometimes to garbage.
+    pub fn is_wrong_master_password(&self) -> bool {
+        match *self {
+            PasswordError::WrongMasterPasswordError |
+            PasswordError::DecryptionError |
+            PasswordError::InvalidJsonError => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for PasswordError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PasswordError::DecryptionError => write!(f, "the passwords could not be decrypted"),
+            PasswordError::EncryptionError => write!(f, "the passwords could not be encrypted"),
+            PasswordError::NoSuchAppError => write!(f, "there is no password for this app"),
+            PasswordError::AppExistsError => write!(f, "there is already a password for this app"),
+            PasswordError::Io(ref err) => write!(f, "{}", err),
+            PasswordError::WrongVersionError => {
+                write!(f, "the password file has a version this program can't read")
+            }
+            PasswordError::InvalidJsonError => write!(f, "the decrypted passwords aren't valid"),
+            PasswordError::CorruptionError => write!(f, "the password file is corrupted"),
+            PasswordError::WrongMasterPasswordError => write!(f, "the master password is wrong"),
+            PasswordError::KeyFileRequiredError => {
+                write!(f, "the password file can only be opened with its key file")
+            }
+            PasswordError::WrongKeyFileError => {
+                write!(f, "the key file isn't the one of the password file")
+            }
+            PasswordError::KeyFileNotUsedError => {
+                write!(f, "the password file doesn't need a key file")
+            }
+            PasswordError::YubiKeyRequiredError => {
+                write!(f, "the password file can only be opened with its YubiKey")
+            }
+        }
+    }
+}
+
+impl Error for PasswordError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            PasswordError::Io(ref err) => Some(err),
+            _ => None,
+        }
+    }
 }
 
 impl From<IoError> for PasswordError {
@@ -39,9 +115,12 @@ impl From<IoError> for PasswordError {
     }
 }
 
-fn upgrade_v1_v2(v1_passwords: &[v1::Password],
-                 v2_store: &mut v2::PasswordStore)
-                 -> Result<(), PasswordError> {
+/// Moves the passwords of a version 1 file, read with `v1::get_all_passwords`, into a new
+/// store with the same master password.
+pub fn upgrade_v1_v2(v1_passwords: &[v1::Password],
+                     master_password: SafeString)
+                     -> Result<v2::PasswordStore, PasswordError> {
+    let mut v2_store = v2::PasswordStore::new(master_password)?;
     for p in v1_passwords.iter() {
         let v2_password = v2::Password {
             name: p.name.clone(),
@@ -49,47 +128,15 @@ fn upgrade_v1_v2(v1_passwords: &[v1::Password],
             password: p.password.clone(),
             created_at: p.created_at,
             updated_at: p.updated_at,
+            url: None,
+            notes: None,
+            tags: Vec::new(),
+            favorite: false,
+            archived: false,
+            history: Vec::new(),
         };
         v2_store.add_password(v2_password)?;
     }
 
-    Ok(())
-}
-
-pub fn upgrade(master_password: SafeString,
-               input: SafeVec)
-               -> Result<v2::PasswordStore, PasswordError> {
-    // If we can't read v1 passwords, we have a hard error, because we previously tried
-    // to read the passwords as v2. Which failed. That means we can't upgrade.
-    let v1_passwords = v1::get_all_passwords(master_password.deref(), input.deref())?;
-
-    println_stderr!("Your Rooster file has version 1. You need to upgrade to version 2.");
-    println_stderr!("");
-    println_stderr!("WARNING: If in doubt, it could mean you've been hacked. Only");
-    println_stderr!("proceed if you recently upgraded your Rooster installation.");
-    println_stderr!("");
-    println_stderr!("Upgrade to version 2? [y/n]");
-    loop {
-        let mut line = String::new();
-        match stdin().read_line(&mut line) {
-            Ok(_) => {
-                if line.starts_with('y') {
-                    break;
-                } else if line.starts_with('n') {
-                    return Err(PasswordError::WrongVersionError);
-                } else {
-                    println_stderr!("I did not get that. Upgrade from v1 to v2? [y/n]");
-                }
-            }
-            Err(io_err) => {
-                return Err(PasswordError::Io(io_err));
-            }
-        }
-    }
-
-    // Upgrade from v1 to v2 if we could read v1 passwords.
-    let mut v2_store = v2::PasswordStore::new(master_password.clone())?;
-    upgrade_v1_v2(v1_passwords.deref(), &mut v2_store)?;
-
     Ok(v2_store)
 }
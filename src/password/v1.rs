@@ -22,8 +22,6 @@ use serde_json;
 use super::PasswordError;
 
 use serde_json::Error;
-use std::ops::DerefMut;
-use std::ops::Deref;
 
 /// The Rooster file format
 ///
@@ -32,7 +30,7 @@ use std::ops::Deref;
 
 /// The schema of the JSON content in the password file.
 ///
-/// ```
+/// ```text
 /// {
 ///     "passwords": [
 ///         "name": "YouTube",
@@ -80,7 +78,7 @@ fn generate_encryption_key(master_password: &str) -> SafeVec {
     let mut key = SafeVec::new(vec);
     let mut hash = crypto::sha2::Sha256::new();
     hash.input(master_password.as_bytes());
-    hash.result(key.deref_mut());
+    hash.result(&mut key.inner_mut());
 
     key
 }
@@ -101,14 +99,11 @@ pub fn get_all_passwords(master_password: &str,
         let encrypted = &encrypted[..encrypted.len() - IV_LEN];
 
         // Decrypt the data.
-        let decrypted_maybe = aes::decrypt(encrypted, key.deref(), iv);
+        let decrypted_maybe = aes::decrypt(encrypted, key.expose(), iv);
 
         match decrypted_maybe {
             Ok(decrypted) => {
-                let encoded = SafeString::new(String::from_utf8_lossy(decrypted.deref())
-                                                  .into_owned());
-
-                let s : Result<Schema, Error> =  serde_json::from_str(encoded.deref());
+                let s : Result<Schema, Error> =  serde_json::from_slice(decrypted.expose());
 
                 match s {
                     Ok(schema) => schema.passwords,
@@ -13,26 +13,35 @@
 // limitations under the License.
 
 use ffi;
+use timestamp;
 use crypto::{scrypt, hmac, sha2};
+use crypto::digest::Digest;
 use crypto::mac::{Mac, MacResult};
+use crypto::util::fixed_time_eq;
 use aes;
+use atomic_file;
 use rand::{Rng, OsRng};
 use byteorder::{ReadBytesExt, WriteBytesExt, BigEndian};
+use serde::Serializer;
 use serde_json;
 use safe_string::SafeString;
 use safe_vec::SafeVec;
+use collation::Collation;
+use regex::Regex;
 
 use super::PasswordError;
+use super::v3;
 use serde_json::Error;
-use std::io::{Seek, SeekFrom, Result as IoResult, Error as IoError, ErrorKind as IoErrorKind,
-              Read, Write, Cursor};
-use std::fs::File;
-use std::ops::DerefMut;
+use std::io::{Result as IoResult, ErrorKind as IoErrorKind, Read, Write, Cursor};
 use std::ops::Deref;
+use std::path::Path;
+use std::mem;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
 
 /// The schema of the JSON content in the password file.
 ///
-/// ```
+/// ```text
 /// {
 ///     "passwords": [
 ///         "name": "YouTube",
@@ -40,7 +49,14 @@ use std::ops::Deref;
 ///         "password": "xxxxxxxx",
 ///         "created_at": 23145436,
 ///         "updated_at": 23145546,
-///     ]
+///         "url": "https://optional.example.com",
+///         "notes": "optional, may contain\nline breaks",
+///         "tags": ["optional", "work"],
+///         "favorite": true,
+///         "archived": true,
+///         "history": [{"password": "optional", "changed_at": 23145500}],
+///     ],
+///     "settings": {"matching": "exact", "collation": "locale", "notify_done": true}
 /// }
 /// ```
 
@@ -58,16 +74,37 @@ const KEY_LEN: usize = 32;
 /// Length of the salt passed to the key derivation function.
 const SALT_LEN: usize = 32;
 
+/// Length of the value that tells whether a key file is the right one.
+const KEY_FILE_CHECK_LEN: usize = 32;
+
+/// Length of the value that tells whether the key is the right one, in version 4 files.
+const KEY_CHECK_LEN: usize = 32;
+
 /// Length of the HMAC signature
 const SIGNATURE_LEN: usize = 64;
 
-/// Scrypt parameters
-const SCRYPT_PARAM_LOG2_N: u8 = 12;
-const SCRYPT_PARAM_R: u32 = 8;
-const SCRYPT_PARAM_P: u32 = 1;
+/// The most costly scrypt params a password file may have. Beyond them, opening the file takes
+/// gigabytes of memory or minutes, which is more likely a corrupted header than a choice.
+pub const MAX_SCRYPT_LOG2_N: u8 = 24;
+pub const MAX_SCRYPT_R: u32 = 32;
+pub const MAX_SCRYPT_P: u32 = 16;
 
 /// The version of this lib
-const VERSION: u32 = 2;
+pub const VERSION: u32 = 2;
+
+/// The version of password files that need a second factor, a key file or a YubiKey. Files
+/// without one keep version 2, so that older versions of Rooster can still open them.
+pub const VERSION_WITH_SECOND_FACTORS: u32 = 3;
+
+/// Flags of version 3 files, for the second factors they need.
+const NEEDS_KEY_FILE: u8 = 1;
+const NEEDS_YUBIKEY: u8 = 2;
+
+/// Length of the challenge sent to the YubiKey, the most its HMAC-SHA1 mode takes.
+pub const YUBIKEY_CHALLENGE_LEN: usize = 64;
+
+/// How many previous values of each password are kept, unless configured otherwise
+pub const DEFAULT_HISTORY_DEPTH: usize = 5;
 
 // Create a random IV.
 fn generate_random_iv() -> IoResult<[u8; IV_LEN]> {
@@ -85,33 +122,104 @@ fn generate_random_salt() -> IoResult<[u8; SALT_LEN]> {
     Ok(bytes)
 }
 
-/// Derives a 256 bits encryption key from the password.
-fn generate_encryption_key(scrypt_params: scrypt::ScryptParams,
+/// A new random challenge for the YubiKey.
+pub fn generate_yubikey_challenge() -> IoResult<[u8; YUBIKEY_CHALLENGE_LEN]> {
+    let mut bytes: [u8; YUBIKEY_CHALLENGE_LEN] = [0; YUBIKEY_CHALLENGE_LEN];
+    let mut rng = OsRng::new()?;
+    rng.fill_bytes(&mut bytes);
+    Ok(bytes)
+}
+
+/// Derives a 256 bits encryption key from the password, and from the key file and the response
+/// of the YubiKey if there are some.
+fn generate_encryption_key(kdf: &Kdf,
                            master_password: &str,
+                           key_file_digest: Option<&SafeVec>,
+                           yubikey_response: Option<&SafeVec>,
                            salt: [u8; SALT_LEN])
                            -> SafeVec {
-    let mut vec = Vec::<u8>::with_capacity(KEY_LEN);
-    for _ in 0..KEY_LEN {
-        vec.push(0u8);
+    // The digest of the key file is appended to the password.
+    let digest_len = key_file_digest.map_or(0, |digest| digest.expose().len());
+    let mut input = SafeVec::new(Vec::with_capacity(master_password.len() + digest_len));
+    input.inner_mut().extend_from_slice(master_password.as_bytes());
+    if let Some(digest) = key_file_digest {
+        input.inner_mut().extend_from_slice(digest.expose());
     }
-    let mut output = SafeVec::new(vec);
 
-    scrypt::scrypt(master_password.as_bytes(),
-                   &salt,
-                   &scrypt_params,
-                   output.deref_mut());
+    #[cfg(test)]
+    test::DERIVATIONS.with(|count| count.set(count.get() + 1));
+
+    let mut output = match *kdf {
+        Kdf::Scrypt { log2_n, r, p } => {
+            let mut output = SafeVec::new(vec![0u8; KEY_LEN]);
+            let scrypt_params = scrypt::ScryptParams::new(log2_n, r, p);
+            scrypt::scrypt(input.expose(), &salt, &scrypt_params, &mut output.inner_mut());
+            output
+        }
+        Kdf::Argon2id(ref params) => v3::derive_key(params, input.expose(), &salt, KEY_LEN),
+    };
+
+    // The response of the YubiKey is mixed with the output of the key derivation, so that it
+    // can't be computed without the YubiKey, whatever the password.
+    if let Some(response) = yubikey_response {
+        let mut hmac = hmac::Hmac::new(sha2::Sha256::new(), response.expose());
+        hmac.input(output.expose());
+        hmac.raw_result(&mut output.inner_mut());
+    }
 
     output
 }
 
+/// Mixes the contents of a key file with the salt, so that the digest differs for every file.
+fn digest_key_file(key_file: &[u8], salt: &[u8]) -> SafeVec {
+    let mut hmac = hmac::Hmac::new(sha2::Sha512::new(), salt);
+    hmac.input(key_file);
+    let mut digest = SafeVec::new(vec![0u8; hmac.output_bytes()]);
+    hmac.raw_result(&mut digest.inner_mut());
+    digest
+}
+
+/// What is saved in the file to tell a wrong key file from a wrong master password. It doesn't
+/// help finding the digest, which is as long as a SHA-512 hash.
+fn key_file_check(key_file_digest: &SafeVec) -> [u8; KEY_FILE_CHECK_LEN] {
+    let mut hasher = sha2::Sha256::new();
+    hasher.input(key_file_digest.expose());
+    let mut check = [0u8; KEY_FILE_CHECK_LEN];
+    hasher.result(&mut check);
+    check
+}
+
+/// What is saved in version 4 files to tell a wrong master password from a corrupted file. It
+/// doesn't help guessing the master password more than the signature, which uses the same key.
+///
+/// Version 2 and 3 files, whose key is derived with scrypt, have none, so that older versions of
+/// Rooster can still open them. Passwords in them that don't decrypt are taken for a wrong
+/// master password, even when the file is what was damaged.
+fn key_check(key: &SafeVec) -> [u8; KEY_CHECK_LEN] {
+    let mut hmac = hmac::Hmac::new(sha2::Sha256::new(), key.expose());
+    hmac.input(b"rooster key check");
+    let mut check = [0u8; KEY_CHECK_LEN];
+    hmac.raw_result(&mut check);
+    check
+}
+
+/// Some files end before their signature, or even their header.
+fn truncated_as_corruption(err: PasswordError) -> PasswordError {
+    match err {
+        PasswordError::Io(ref io_err) if io_err.kind() == IoErrorKind::UnexpectedEof => {
+            PasswordError::CorruptionError
+        }
+        err => err,
+    }
+}
+
 /// Creates a HMAC struct
 fn digest(key: &[u8],
           version: u32,
-          scrypt_log2_n: u8,
-          scrypt_r: u32,
-          scrypt_p: u32,
+          kdf: &Kdf,
           iv: &[u8],
           salt: &[u8],
+          after_salt: &[u8],
           blob: &[u8])
           -> Result<hmac::Hmac<sha2::Sha512>, PasswordError> {
     let mut digest = hmac::Hmac::new(sha2::Sha512::new(), key);
@@ -119,19 +227,18 @@ fn digest(key: &[u8],
     let mut version_bytes_cursor: Cursor<Vec<u8>> = Cursor::new(Vec::new());
     version_bytes_cursor.write_u32::<BigEndian>(version)?;
 
-    let mut scrypt_bytes_cursor: Cursor<Vec<u8>> = Cursor::new(Vec::new());
-    scrypt_bytes_cursor.write_u8(scrypt_log2_n)?;
-    scrypt_bytes_cursor.write_u32::<BigEndian>(scrypt_r)?;
-    scrypt_bytes_cursor.write_u32::<BigEndian>(scrypt_p)?;
+    let mut kdf_bytes_cursor: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+    kdf.write(&mut kdf_bytes_cursor)?;
 
     let version_bytes = version_bytes_cursor.into_inner();
     digest.input(version_bytes.deref());
 
-    let scrypt_bytes = scrypt_bytes_cursor.into_inner();
-    digest.input(scrypt_bytes.deref());
+    let kdf_bytes = kdf_bytes_cursor.into_inner();
+    digest.input(kdf_bytes.deref());
 
     digest.input(iv);
     digest.input(salt);
+    digest.input(after_salt);
     digest.input(blob.deref());
 
     Ok(digest)
@@ -141,12 +248,90 @@ fn digest(key: &[u8],
 /// The format of the encrypted JSON content in the password file v1.
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Schema {
+    #[serde(serialize_with = "serialize_sorted")]
     passwords: Vec<Password>,
+    #[serde(default, skip_serializing_if = "Settings::is_default")]
+    settings: Settings,
 }
 
 impl Schema {
     fn new() -> Schema {
-        Schema { passwords: Vec::new() }
+        Schema {
+            passwords: Vec::new(),
+            settings: Settings::default(),
+        }
+    }
+}
+
+/// Saves the passwords sorted by name, without regard to casing, so that the same passwords give
+/// the same JSON whatever order they were added, imported or merged in. Fields that are maps
+/// should be `BTreeMap`s, for the same reason.
+fn serialize_sorted<S: Serializer>(passwords: &Vec<Password>,
+                                   serializer: S)
+                                   -> Result<S::Ok, S::Error> {
+    let mut sorted: Vec<&Password> = passwords.iter().collect();
+    sorted.sort_by(|a, b| {
+        a.name
+            .to_lowercase()
+            .cmp(&b.name.to_lowercase())
+            .then_with(|| a.name.cmp(&b.name))
+            .then_with(|| a.username.cmp(&b.username))
+            .then_with(|| a.created_at.cmp(&b.created_at))
+    });
+    serializer.collect_seq(sorted)
+}
+
+/// How queries like `rooster get ytb` find passwords.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Matching {
+    /// The query must be the name of the app, without regard to casing.
+    Exact,
+    /// The letters of the query must appear in the name of the app, in the same order.
+    Fuzzy,
+}
+
+impl Default for Matching {
+    fn default() -> Matching {
+        Matching::Fuzzy
+    }
+}
+
+impl Matching {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Matching::Exact => "exact",
+            Matching::Fuzzy => "fuzzy",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Matching> {
+        match name {
+            "exact" => Some(Matching::Exact),
+            "fuzzy" => Some(Matching::Fuzzy),
+            _ => None,
+        }
+    }
+}
+
+/// Preferences saved in the password file, see `rooster config`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Settings {
+    #[serde(default)]
+    pub matching: Matching,
+    #[serde(default, skip_serializing_if = "is_codepoint")]
+    pub collation: Collation,
+    /// Whether long commands ring the bell when they are done, like --notify-done.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub notify_done: bool,
+    /// Whether the user refused to derive the key with Argon2id, so that it isn't offered again.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub kdf_upgrade_declined: bool,
+}
+
+impl Settings {
+    fn is_default(&self) -> bool {
+        *self == Settings::default()
     }
 }
 
@@ -155,8 +340,57 @@ pub struct Password {
     pub name: String,
     pub username: String,
     pub password: SafeString,
+    #[serde(deserialize_with = "timestamp::deserialize")]
     pub created_at: ffi::time_t,
+    #[serde(deserialize_with = "timestamp::deserialize")]
     pub updated_at: ffi::time_t,
+    /// Address of the app's login page, for instance.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Free-form text, like security questions or recovery codes. May span several lines.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<SafeString>,
+    /// Labels like "work" or "personal", normalized with `normalize_tag`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Favorites are listed first, see `rooster favorite`.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub favorite: bool,
+    /// Archived passwords are hidden unless --archived is given, see `rooster archive`.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub archived: bool,
+    /// Previous values of the password, most recent first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<PastPassword>,
+}
+
+/// A value a password had before it was changed.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PastPassword {
+    pub password: SafeString,
+    /// When this value was replaced by a new one.
+    #[serde(deserialize_with = "timestamp::deserialize")]
+    pub changed_at: ffi::time_t,
+}
+
+fn is_codepoint(collation: &Collation) -> bool {
+    *collation == Collation::Codepoint
+}
+
+fn is_false(b: &bool) -> bool {
+    !*b
+}
+
+/// Tags are lowercase and may not contain whitespace or commas.
+pub fn normalize_tag(tag: &str) -> Result<String, String> {
+    let tag = tag.trim().to_lowercase();
+    if tag.is_empty() {
+        return Err("tags can't be empty".to_owned());
+    }
+    if tag.contains(|c: char| c.is_whitespace() || c == ',') {
+        return Err(format!("the tag \"{}\" can't contain spaces or commas", tag));
+    }
+    Ok(tag)
 }
 
 impl Password {
@@ -168,218 +402,743 @@ impl Password {
             password: password,
             created_at: timestamp,
             updated_at: timestamp,
+            url: None,
+            notes: None,
+            tags: Vec::new(),
+            favorite: false,
+            archived: false,
+            history: Vec::new(),
+        }
+    }
+
+    /// Remembers a previous value of the password, forgetting the oldest ones beyond `depth`.
+    pub fn push_history(&mut self, password: SafeString, changed_at: ffi::time_t, depth: usize) {
+        self.history.insert(0,
+                            PastPassword {
+                                password: password,
+                                changed_at: changed_at,
+                            });
+        self.history.truncate(depth);
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t == tag)
+    }
+
+    /// Adds an already normalized tag, unless the password has it already.
+    pub fn add_tag(&mut self, tag: String) {
+        if !self.has_tag(tag.as_str()) {
+            self.tags.push(tag);
+            self.tags.sort();
+        }
+    }
+
+    pub fn remove_tag(&mut self, tag: &str) {
+        self.tags.retain(|t| t != tag);
+    }
+
+    /// Folds another password for the same account into this one. Fields that are empty here
+    /// are taken from `other`, tags are combined, and the password of `other` goes in the
+    /// history unless it's the same. The earliest `created_at` and the latest `updated_at` are
+    /// kept.
+    pub fn merge_from(&mut self, other: Password, depth: usize) {
+        if self.username.is_empty() {
+            self.username = other.username.clone();
+        }
+        if self.url.is_none() {
+            self.url = other.url.clone();
+        }
+        if self.notes.is_none() {
+            self.notes = other.notes.clone();
+        }
+        for tag in other.tags.iter() {
+            self.add_tag(tag.clone());
         }
+        self.favorite = self.favorite || other.favorite;
+
+        if other.password != self.password {
+            self.history.push(PastPassword {
+                password: other.password.clone(),
+                changed_at: other.updated_at,
+            });
+        }
+        self.history.extend(other.history.iter().cloned());
+        self.history.sort_by(|a, b| b.changed_at.cmp(&a.changed_at));
+        self.history.truncate(depth);
+
+        self.created_at = self.created_at.min(other.created_at);
+        self.updated_at = self.updated_at.max(other.updated_at);
     }
 }
 
+/// The tag `rooster list --inactive-usernames --archive-matches` used before passwords had an
+/// `archived` field.
+const ARCHIVED_TAG: &'static str = "archived";
+
+/// Turns the old "archived" tag into the `archived` field. The file itself is rewritten the next
+/// time the store is saved.
+fn migrate_archived_tag(passwords: &mut [Password]) {
+    for password in passwords.iter_mut().filter(|p| p.has_tag(ARCHIVED_TAG)) {
+        password.remove_tag(ARCHIVED_TAG);
+        password.archived = true;
+    }
+}
+
+/// Position of a password in a store, as given by `PasswordStore::find_duplicates`. It is only
+/// valid until the store changes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PasswordIndex(usize);
+
+/// How well the query matches the text, without regard to casing, or `None` if it doesn't.
+///
+/// The characters of the query have to appear in the text in the same order, but it's fine if
+/// some characters are left out in the query. For instance, you can search for the app
+/// "Facebook" with just "fcbk". The whole text scores highest, then texts starting with the
+/// query, then texts containing it, earlier being better. Then come texts with gaps between the
+/// characters of the query, fewer and smaller gaps being better.
+pub fn match_score(query: &str, text: &str) -> Option<i64> {
+    let query: Vec<char> = query.chars().flat_map(|c| c.to_lowercase()).collect();
+    let text: Vec<char> = text.chars().flat_map(|c| c.to_lowercase()).collect();
+
+    if query == text {
+        return Some(4000);
+    }
+    if text.starts_with(&query) {
+        return Some(3000);
+    }
+    if let Some(position) = text.windows(query.len().max(1)).position(|w| w == &query[..]) {
+        return Some(2000 - (position as i64).min(999));
+    }
+
+    let mut first = None;
+    let mut gaps = 0;
+    let mut next = 0;
+    for &c in query.iter() {
+        let found = next + text[next..].iter().position(|&t| t == c)?;
+        if first.is_none() {
+            first = Some(found);
+        } else {
+            gaps += found - next;
+        }
+        next = found + 1;
+    }
+    let penalty = gaps as i64 * 10 + first.unwrap_or(0) as i64;
+    Some(1000 - penalty.min(999))
+}
+
+/// How well a password matches every word of a query, like "aws prod", or `None` if a word
+/// matches neither its name nor its username. Passwords whose name matches every word rank
+/// first, then by the sum of the scores of `score`, like `match_score`.
+pub fn words_score<F>(words: &[&str], password: &Password, score: F) -> Option<(bool, i64)>
+    where F: Fn(&str, &str) -> Option<i64>
+{
+    let mut total = (true, 0);
+    for word in words.iter() {
+        match score(word, &password.name) {
+            Some(score) => total.1 += score,
+            None => total = (false, total.1 + score(word, &password.username)?),
+        }
+    }
+    Some(total)
+}
+
+/// What `PasswordStore::rename_password` does when the new name is taken by another password.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RenameCollision {
+    /// Leave both passwords alone and fail with `AppExistsError`.
+    Fail,
+    /// Replace the other password. Its value goes to the history of the renamed one.
+    Overwrite,
+    /// Fold the other password into the renamed one, like `Password::merge_from`.
+    Merge,
+}
+
+fn find_root(parents: &mut Vec<usize>, i: usize) -> usize {
+    let mut root = i;
+    while parents[root] != root {
+        root = parents[root];
+    }
+    parents[i] = root;
+    root
+}
+
+/// A YubiKey challenge, and the response of the YubiKey to it.
+#[derive(Clone)]
+pub struct YubiKeyResponse {
+    pub challenge: [u8; YUBIKEY_CHALLENGE_LEN],
+    pub response: SafeVec,
+}
+
+/// What opens a password file besides the master password, for files set up to need it.
+#[derive(Clone, Default)]
+pub struct SecondFactors {
+    pub key_file: Option<SafeVec>,
+    pub yubikey: Option<YubiKeyResponse>,
+}
+
+/// How the key is derived from the master password: with scrypt in version 2 and 3 files, and
+/// with Argon2id in version 4 files.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kdf {
+    Scrypt { log2_n: u8, r: u32, p: u32 },
+    Argon2id(v3::Argon2Params),
+}
+
+/// The scrypt params of every file created before Argon2id and `rooster set-kdf`.
+pub const DEFAULT_SCRYPT: Kdf = Kdf::Scrypt {
+    log2_n: 12,
+    r: 8,
+    p: 1,
+};
+
+/// Whether scrypt accepts the params, and they are not more costly than the maximums.
+pub fn are_valid_scrypt_params(log2_n: u8, r: u32, p: u32) -> bool {
+    log2_n >= 1 && log2_n <= MAX_SCRYPT_LOG2_N && r >= 1 && r <= MAX_SCRYPT_R && p >= 1 &&
+    p <= MAX_SCRYPT_P && (log2_n as u32) < 16 * r
+}
+
+/// The memory scrypt needs to derive a key, in bytes: 128 * r * N.
+pub fn scrypt_memory(log2_n: u8, r: u32) -> u64 {
+    (128 * r as u64) << log2_n
+}
+
+impl Kdf {
+    /// The memory it takes to derive a key, in bytes.
+    pub fn memory(&self) -> u64 {
+        match *self {
+            Kdf::Scrypt { log2_n, r, .. } => scrypt_memory(log2_n, r),
+            Kdf::Argon2id(ref params) => params.memory_kib as u64 * 1024,
+        }
+    }
+
+    /// How long it takes to derive a key on this machine, as when opening a password file.
+    pub fn time_derivation(&self) -> Duration {
+        let started = Instant::now();
+        let key = generate_encryption_key(self, "benchmark", None, None, [0; SALT_LEN]);
+        drop(key);
+        started.elapsed()
+    }
+
+    /// Writes the params, as they are saved in the header and signed.
+    fn write<W: Write>(&self, writer: &mut W) -> IoResult<()> {
+        match *self {
+            Kdf::Scrypt { log2_n, r, p } => {
+                writer.write_u8(log2_n)?;
+                writer.write_u32::<BigEndian>(r)?;
+                writer.write_u32::<BigEndian>(p)
+            }
+            Kdf::Argon2id(ref params) => params.write(writer),
+        }
+    }
+}
+
+/// The cleartext start of a password file, which tells what is needed to open it.
+pub struct Header {
+    version: u32,
+    kdf: Kdf,
+    salt: [u8; SALT_LEN],
+    key_file_check: Option<[u8; KEY_FILE_CHECK_LEN]>,
+    yubikey_challenge: Option<[u8; YUBIKEY_CHALLENGE_LEN]>,
+    key_check: Option<[u8; KEY_CHECK_LEN]>,
+}
+
+impl Header {
+    /// Reads the header of a version 4 password file, or of an older version 2 or 3 one.
+    pub fn read<R: Read>(reader: &mut R) -> Result<Header, PasswordError> {
+        // Version taken from network byte order (big endian).
+        let version = reader.read_u32::<BigEndian>()?;
+
+        // Read the key derivation params.
+        let kdf = match version {
+            v3::VERSION => Kdf::Argon2id(v3::Argon2Params::read(reader)?),
+            VERSION | VERSION_WITH_SECOND_FACTORS => {
+                let log2_n = reader.read_u8()?;
+                let r = reader.read_u32::<BigEndian>()?;
+                let p = reader.read_u32::<BigEndian>()?;
+                if !are_valid_scrypt_params(log2_n, r, p) {
+                    return Err(PasswordError::CorruptionError);
+                }
+                Kdf::Scrypt {
+                    log2_n: log2_n,
+                    r: r,
+                    p: p,
+                }
+            }
+            _ => return Err(PasswordError::WrongVersionError),
+        };
+
+        // Read the old salt.
+        let mut salt: [u8; SALT_LEN] = [0u8; SALT_LEN];
+        reader.read_exact(&mut salt)?;
+
+        // Read what is needed to check the second factors.
+        let mut header = Header {
+            version: version,
+            kdf: kdf,
+            salt: salt,
+            key_file_check: None,
+            yubikey_challenge: None,
+            key_check: None,
+        };
+        if header.has_flags() {
+            let flags = reader.read_u8()?;
+            if flags & NEEDS_KEY_FILE != 0 {
+                let mut check: [u8; KEY_FILE_CHECK_LEN] = [0u8; KEY_FILE_CHECK_LEN];
+                reader.read_exact(&mut check)?;
+                header.key_file_check = Some(check);
+            }
+            if flags & NEEDS_YUBIKEY != 0 {
+                let mut challenge: [u8; YUBIKEY_CHALLENGE_LEN] = [0u8; YUBIKEY_CHALLENGE_LEN];
+                reader.read_exact(&mut challenge)?;
+                header.yubikey_challenge = Some(challenge);
+            }
+        }
+        if version == v3::VERSION {
+            let mut check: [u8; KEY_CHECK_LEN] = [0u8; KEY_CHECK_LEN];
+            reader.read_exact(&mut check)?;
+            header.key_check = Some(check);
+        }
+        Ok(header)
+    }
+
+    pub fn needs_key_file(&self) -> bool {
+        self.key_file_check.is_some()
+    }
+
+    /// The challenge to send to the YubiKey, if the file needs one.
+    pub fn yubikey_challenge(&self) -> Option<[u8; YUBIKEY_CHALLENGE_LEN]> {
+        self.yubikey_challenge
+    }
+
+    /// Whether the file says which second factors it needs, which version 2 files don't.
+    fn has_flags(&self) -> bool {
+        self.version != VERSION
+    }
+
+    /// The flags and values of the second factors, as they are saved in version 3 and 4 files.
+    fn second_factors(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        if !self.has_flags() {
+            return bytes;
+        }
+        let mut flags = 0;
+        if self.key_file_check.is_some() {
+            flags |= NEEDS_KEY_FILE;
+        }
+        if self.yubikey_challenge.is_some() {
+            flags |= NEEDS_YUBIKEY;
+        }
+        bytes.push(flags);
+        if let Some(ref check) = self.key_file_check {
+            bytes.extend_from_slice(check);
+        }
+        if let Some(ref challenge) = self.yubikey_challenge {
+            bytes.extend_from_slice(challenge);
+        }
+        bytes
+    }
+
+    /// What follows the salt: the second factors, then the key check of version 4 files.
+    fn after_salt(&self) -> Vec<u8> {
+        let mut bytes = self.second_factors();
+        if let Some(ref check) = self.key_check {
+            bytes.extend_from_slice(check);
+        }
+        bytes
+    }
+}
+
+/// An opened Rooster file.
+///
+/// Key lifetime: `key` is derived from the master password and is the only copy of it that
+/// lives as long as the store. The master password itself should be dropped by callers as soon
+/// as the store is created. When the key changes (see `change_master_password`), the old key
+/// is wiped before the method returns rather than when the store is dropped. Any new field
+/// holding key material must be a `SafeVec`/`SafeString` and follow the same rule.
+///
+/// The key is derived once, when the store is created or opened; `sync` and `write_to` reuse
+/// it, since the key derivation is slow on purpose.
 pub struct PasswordStore {
     key: SafeVec,
-    scrypt_log2_n: u8,
-    scrypt_r: u32,
-    scrypt_p: u32,
+    kdf: Kdf,
     salt: [u8; SALT_LEN],
+    /// The digest of the key file, if the password file needs one.
+    key_file_digest: Option<SafeVec>,
+    /// The challenge and the response of the YubiKey, if the password file needs one.
+    yubikey: Option<YubiKeyResponse>,
     schema: Schema,
+    history_depth: usize,
+    /// Set for a single invocation with --fuzzy or --no-fuzzy, never saved.
+    matching_override: Option<Matching>,
+    /// Set for a single invocation with --collate, never saved.
+    collation_override: Option<Collation>,
+    /// Set for a single invocation with --archived, never saved.
+    include_archived: bool,
+    /// Set for a single invocation with --regex, never saved.
+    search_regex: Option<Regex>,
+    /// Whether the passwords or the key changed since the store was opened.
+    modified: bool,
 }
 
 /// Read and writes to a Rooster file
 ///
 /// The Rooster file has the following format:
 /// - rooster version: u32, big endian
-/// - scrypt log2n:    u8
-/// - scrypt r:        u32, big endian
-/// - scrypt p:        u32, big endian
+/// - key derivation:  in version 2 and 3
+///   - scrypt log2n:    u8
+///   - scrypt r:        u32, big endian
+///   - scrypt p:        u32, big endian
+/// - key derivation:  in version 4
+///   - argon2id memory: u32, big endian, in KiB
+///   - argon2id passes: u32, big endian
+///   - argon2id lanes:  u32, big endian
 /// - salt:            256 bits
+/// - second factors:  in version 3, which needs a key file or a YubiKey or both, and version 4
+///   - flags:           u8, 1 for a key file, 2 for a YubiKey
+///   - key file check:  256 bits, with a key file
+///   - challenge:       512 bits, with a YubiKey
+/// - key check:       256 bits HMAC-SHA256 of a constant, in version 4 only
 /// - iv:              256 bits
 /// - signature:       512 bits HMAC-SHA512
 /// - encrypted blob:  variable length
 impl PasswordStore {
+    /// A new, empty store. Its key is derived from the master password with Argon2id, which
+    /// is slow on purpose.
     pub fn new(master_password: SafeString) -> IoResult<PasswordStore> {
-        let salt = generate_random_salt()?;
+        PasswordStore::new_with_factors(master_password, &SecondFactors::default())
+    }
 
-        let scrypt_params =
-            scrypt::ScryptParams::new(SCRYPT_PARAM_LOG2_N, SCRYPT_PARAM_R, SCRYPT_PARAM_P);
+    /// A new store that can only be opened with the master password and the second factors.
+    pub fn new_with_factors(master_password: SafeString,
+                            factors: &SecondFactors)
+                            -> IoResult<PasswordStore> {
+        PasswordStore::new_with_kdf(master_password, factors, Kdf::Argon2id(v3::DEFAULT_PARAMS))
+    }
 
-        let key = generate_encryption_key(scrypt_params, master_password.deref(), salt);
+    /// A new store whose key is derived with other parameters than the default ones, which must
+    /// be valid. Cheap ones make stores that are quick to open, for tests.
+    pub fn new_with_kdf(master_password: SafeString,
+                        factors: &SecondFactors,
+                        kdf: Kdf)
+                        -> IoResult<PasswordStore> {
+        let salt = generate_random_salt()?;
+
+        let key_file_digest = factors.key_file
+            .as_ref()
+            .map(|key_file| digest_key_file(key_file.expose(), &salt));
+        let key = generate_encryption_key(&kdf,
+                                          master_password.expose(),
+                                          key_file_digest.as_ref(),
+                                          factors.yubikey.as_ref().map(|yubikey| &yubikey.response),
+                                          salt);
 
         Ok(PasswordStore {
                key: key,
-               scrypt_log2_n: SCRYPT_PARAM_LOG2_N,
-               scrypt_r: SCRYPT_PARAM_R,
-               scrypt_p: SCRYPT_PARAM_P,
+               kdf: kdf,
                salt: salt,
+               key_file_digest: key_file_digest,
+               yubikey: factors.yubikey.clone(),
                schema: Schema::new(),
+               history_depth: DEFAULT_HISTORY_DEPTH,
+               matching_override: None,
+               collation_override: None,
+               include_archived: false,
+               search_regex: None,
+               modified: false,
            })
     }
 
+    /// Opens a store from the contents of a password file that needs no second factors.
+    ///
+    /// ```
+    /// use rooster::password::PasswordError;
+    /// use rooster::password::v2::PasswordStore;
+    /// use rooster::safe_string::SafeString;
+    /// use rooster::safe_vec::SafeVec;
+    ///
+    /// let master = |password: &str| SafeString::new(password.to_owned());
+    /// let mut file = SafeVec::new(Vec::new());
+    /// PasswordStore::new(master("correct horse battery staple"))
+    ///     .unwrap()
+    ///     .write_to(&mut *file.inner_mut())
+    ///     .unwrap();
+    ///
+    /// match PasswordStore::from_input(master("wrong"), file.clone()) {
+    ///     Err(PasswordError::WrongMasterPasswordError) => {}
+    ///     _ => panic!("opened with the wrong master password"),
+    /// }
+    /// assert!(PasswordStore::from_input(master("correct horse battery staple"), file).is_ok());
+    /// ```
     pub fn from_input(master_password: SafeString,
                       input: SafeVec)
                       -> Result<PasswordStore, PasswordError> {
-        let mut reader = Cursor::new(input.deref());
-
-        // Version taken from network byte order (big endian).
-        let version = reader.read_u32::<BigEndian>()?;
-        if version != VERSION {
-            return Err(PasswordError::WrongVersionError);
-        }
-
-        // Read the scrypt params.
-        let scrypt_log2_n = reader.read_u8()?;
-        let scrypt_r = reader.read_u32::<BigEndian>()?;
-        let scrypt_p = reader.read_u32::<BigEndian>()?;
+        PasswordStore::from_input_with_factors(master_password, input, &SecondFactors::default())
+    }
 
-        // Read the old salt.
-        let mut salt: [u8; SALT_LEN] = [0u8; SALT_LEN];
-        reader
-            .read(&mut salt)
-            .and_then(|num_bytes| if num_bytes == SALT_LEN {
-                          Ok(())
-                      } else {
-                          Err(IoError::new(IoErrorKind::Other, "unexpected eof"))
-                      })?;
+    /// Opens a store, with the second factors it needs if any. Giving a key file for a store
+    /// that doesn't need one is an error too, rather than a second factor that silently isn't.
+    pub fn from_input_with_factors(master_password: SafeString,
+                                   input: SafeVec,
+                                   factors: &SecondFactors)
+                                   -> Result<PasswordStore, PasswordError> {
+        let mut reader = Cursor::new(input.expose());
+        let header = Header::read(&mut reader).map_err(truncated_as_corruption)?;
+        let salt = header.salt;
+
+        // Check the second factors before the slow key derivation.
+        let key_file_digest = match (header.key_file_check, factors.key_file.as_ref()) {
+            (Some(check), Some(key_file)) => {
+                let digest = digest_key_file(key_file.expose(), &salt);
+                if !fixed_time_eq(&key_file_check(&digest), &check) {
+                    return Err(PasswordError::WrongKeyFileError);
+                }
+                Some(digest)
+            }
+            (Some(_), None) => return Err(PasswordError::KeyFileRequiredError),
+            (None, Some(_)) => return Err(PasswordError::KeyFileNotUsedError),
+            (None, None) => None,
+        };
+        let yubikey = match (header.yubikey_challenge, factors.yubikey.as_ref()) {
+            (Some(challenge), Some(yubikey)) if challenge[..] == yubikey.challenge[..] => {
+                Some(yubikey.clone())
+            }
+            (Some(_), _) => return Err(PasswordError::YubiKeyRequiredError),
+            (None, _) => None,
+        };
 
         // Read the old IV.
         let mut iv: [u8; IV_LEN] = [0u8; IV_LEN];
-        reader
-            .read(&mut iv)
-            .and_then(|num_bytes| if num_bytes == IV_LEN {
-                          Ok(())
-                      } else {
-                          Err(IoError::new(IoErrorKind::Other, "unexpected eof"))
-                      })?;
+        reader.read_exact(&mut iv).map_err(|err| truncated_as_corruption(err.into()))?;
 
         // Read the HMAC signature.
         let mut signature: [u8; SIGNATURE_LEN] = [0u8; SIGNATURE_LEN];
-        reader
-            .read(&mut signature)
-            .and_then(|num_bytes| if num_bytes == SIGNATURE_LEN {
-                          Ok(())
-                      } else {
-                          Err(IoError::new(IoErrorKind::Other, "unexpected eof"))
-                      })?;
+        reader.read_exact(&mut signature).map_err(|err| truncated_as_corruption(err.into()))?;
 
         // The encrypted password data.
         let mut blob: Vec<u8> = Vec::new();
         reader.read_to_end(&mut blob)?;
 
         // Derive a 256 bits encryption key from the password.
-        let scrypt_params = scrypt::ScryptParams::new(scrypt_log2_n, scrypt_r, scrypt_p);
-        let key = generate_encryption_key(scrypt_params, master_password.deref(), salt);
+        let key = generate_encryption_key(&header.kdf,
+                                          master_password.expose(),
+                                          key_file_digest.as_ref(),
+                                          yubikey.as_ref().map(|yubikey| &yubikey.response),
+                                          salt);
+
+        // Check the signature against what it should be.
+        let new_signature_mac = digest(key.expose(),
+                                       header.version,
+                                       &header.kdf,
+                                       &iv,
+                                       &salt,
+                                       &header.after_salt(),
+                                       blob.deref())?
+                .result();
+        let signed = new_signature_mac == MacResult::new(&signature);
+
+        // With a key check, the key is known to be right before decrypting, so a wrong
+        // signature or data that doesn't decrypt can only mean that the file is corrupted.
+        // Scrypt files have no key check, and only tell a wrong key by failing to decrypt, so
+        // encrypted passwords that were damaged are reported as a wrong master password. Only a
+        // damaged signature of passwords that decrypt, or a file that ends too early, is
+        // reported as corrupted for them.
+        let key_is_right = match header.key_check {
+            Some(ref check) => {
+                if !fixed_time_eq(&key_check(&key), check) {
+                    return Err(PasswordError::WrongMasterPasswordError);
+                }
+                if !signed {
+                    return Err(PasswordError::CorruptionError);
+                }
+                true
+            }
+            None => false,
+        };
 
         // Decrypt the data.
-        let passwords = match aes::decrypt(blob.deref(), key.as_ref(), iv.as_ref()) {
+        let mut schema = match aes::decrypt(blob.deref(), key.expose(), iv.as_ref()) {
             Ok(decrypted) => {
-                let encoded = SafeString::new(String::from_utf8_lossy(decrypted.as_ref())
-                                                  .into_owned());
-                let s : Result<Schema, Error> = serde_json::from_str(encoded.deref());
+                // Parsed right from the decrypted bytes: secrets are read straight into
+                // `SafeString`s, without a plain copy of the JSON.
+                let s : Result<Schema, Error> = serde_json::from_slice(decrypted.expose());
                 match s {
-                    Ok(json) => json.passwords,
+                    Ok(json) => json,
+                    Err(_) if key_is_right => return Err(PasswordError::CorruptionError),
                     Err(_) => {
                         return Err(PasswordError::InvalidJsonError);
                     }
                 }
             }
+            Err(_) if key_is_right => return Err(PasswordError::CorruptionError),
             Err(_) => {
                 return Err(PasswordError::DecryptionError);
             }
         };
 
-        // Check the signature against what it should be.
-        let new_signature_mac = digest(key.deref(),
-                                       version,
-                                       scrypt_log2_n,
-                                       scrypt_r,
-                                       scrypt_p,
-                                       &iv,
-                                       &salt,
-                                       blob.deref())?
-                .result();
-        let old_signature_mac = MacResult::new(&signature);
-        if new_signature_mac != old_signature_mac {
+        if !signed {
             return Err(PasswordError::CorruptionError);
         }
 
+        migrate_archived_tag(&mut schema.passwords);
+
         Ok(PasswordStore {
                key: key,
-               scrypt_log2_n: scrypt_log2_n,
-               scrypt_r: scrypt_r,
-               scrypt_p: scrypt_p,
+               kdf: header.kdf,
                salt: salt,
-               schema: Schema { passwords: passwords },
+               key_file_digest: key_file_digest,
+               yubikey: yubikey,
+               schema: schema,
+               history_depth: DEFAULT_HISTORY_DEPTH,
+               matching_override: None,
+               collation_override: None,
+               include_archived: false,
+               search_regex: None,
+               modified: false,
            })
     }
 
-    pub fn sync(&self, file: &mut File) -> Result<(), PasswordError> {
-        // This should never fail. The structs are all encodable.
-        let json_schema = match serde_json::to_string(&self.schema) {
-            Ok(json_schema) => json_schema,
-            Err(_) => {
-                return Err(PasswordError::InvalidJsonError);
-            }
-        };
-        let json_schema = SafeString::new(json_schema);
+    /// Saves the store to the password file at `path`, which is replaced in one go.
+    pub fn sync(&self, path: &Path) -> Result<(), PasswordError> {
+        atomic_file::write(path, |file| self.write_to(file))
+    }
+
+    /// Writes the encrypted store, as saved in password files. Anything can be written to, like a
+    /// `SafeVec` to send the file elsewhere.
+    ///
+    /// ```
+    /// use rooster::password::v2::PasswordStore;
+    /// use rooster::safe_string::SafeString;
+    /// use rooster::safe_vec::SafeVec;
+    ///
+    /// let store = PasswordStore::new(SafeString::new("correct horse battery staple".to_owned()))
+    ///     .unwrap();
+    /// let mut file = SafeVec::new(Vec::new());
+    /// store.write_to(&mut *file.inner_mut()).unwrap();
+    /// assert_eq!(&file.expose()[..4], &[0, 0, 0, 4]);
+    /// ```
+    pub fn write_to<W: Write>(&self, file: &mut W) -> Result<(), PasswordError> {
+        // This should never fail. The structs are all encodable. The JSON holds every password,
+        // so it's written straight into a `SafeVec`.
+        let mut json_schema = SafeVec::new(Vec::new());
+        if serde_json::to_writer(&mut *json_schema.inner_mut(), &self.schema).is_err() {
+            return Err(PasswordError::InvalidJsonError);
+        }
 
         // Encrypt the data with a new salt and a new IV.
         let iv = generate_random_iv()?;
-        let encrypted = match aes::encrypt(json_schema.deref().as_bytes(),
-                                           self.key.as_ref(),
+        let encrypted = match aes::encrypt(json_schema.expose(),
+                                           self.key.expose(),
                                            iv.as_ref()) {
             Ok(val) => val,
             Err(_) => return Err(PasswordError::EncryptionError),
         };
 
-        // Reset the file pointer.
-        file.seek(SeekFrom::Start(0)).and_then(|_| file.set_len(0))?;
-
         // Write the file version.
-        file.write_u32::<BigEndian>(VERSION)?;
+        let header = self.header();
+        file.write_u32::<BigEndian>(header.version)?;
 
-        // Write the scrypt params.
-        file.write_u8(self.scrypt_log2_n)?;
-        file.write_u32::<BigEndian>(self.scrypt_r)?;
-        file.write_u32::<BigEndian>(self.scrypt_p)?;
+        // Write the key derivation params.
+        self.kdf.write(file)?;
 
         // Write the key derivation salt.
         file.write_all(&self.salt)?;
 
+        // Write what tells which second factors are needed, and how to check them, and the
+        // key check.
+        let after_salt = header.after_salt();
+        file.write_all(&after_salt)?;
+
         // Write the encryption IV.
         file.write_all(&iv)?;
 
         // Write the file signature.
-        let signature = digest(self.key.deref(),
-                               VERSION,
-                               self.scrypt_log2_n,
-                               self.scrypt_r,
-                               self.scrypt_p,
+        let signature = digest(self.key.expose(),
+                               header.version,
+                               &self.kdf,
                                &iv,
                                &self.salt,
+                               &after_salt,
                                encrypted.as_ref())?
                 .result();
         file.write_all(signature.code())?;
 
         // Write the encrypted password data.
         file.write_all(&encrypted.as_ref())?;
-
-        file.sync_all()?;
         Ok(())
     }
 
+    fn header(&self) -> Header {
+        let needs_second_factors = self.key_file_digest.is_some() || self.yubikey.is_some();
+        let version = match self.kdf {
+            Kdf::Argon2id(_) => v3::VERSION,
+            Kdf::Scrypt { .. } if needs_second_factors => VERSION_WITH_SECOND_FACTORS,
+            Kdf::Scrypt { .. } => VERSION,
+        };
+        Header {
+            version: version,
+            kdf: self.kdf,
+            salt: self.salt,
+            key_file_check: self.key_file_digest.as_ref().map(key_file_check),
+            yubikey_challenge: self.yubikey.as_ref().map(|yubikey| yubikey.challenge),
+            key_check: match self.kdf {
+                Kdf::Argon2id(_) => Some(key_check(&self.key)),
+                Kdf::Scrypt { .. } => None,
+            },
+        }
+    }
+
     pub fn get_all_passwords(&self) -> Vec<&Password> {
         let mut passwords: Vec<&Password> = self.schema.passwords.iter().collect();
 
-        passwords.sort_by_key(|p| { return p.name.to_lowercase(); });
+        self.sort_by_name(&mut passwords);
 
         passwords
     }
 
-    /// Adds a password to the file.
+    /// Adds a password to the file. Names are unique, regardless of case.
+    ///
+    /// ```
+    /// use rooster::password::PasswordError;
+    /// # use rooster::password::v2::{Password, PasswordStore};
+    /// # use rooster::safe_string::SafeString;
+    /// # let master = SafeString::new("correct horse battery staple".to_owned());
+    /// # let mut store = PasswordStore::new(master).unwrap();
+    ///
+    /// store.add_password(Password::new("YouTube".to_owned(),
+    ///                                  "me@example.com".to_owned(),
+    ///                                  SafeString::new("hunter2".to_owned())))
+    ///     .unwrap();
+    /// match store.add_password(Password::new("youtube".to_owned(),
+    ///                                  "you@example.com".to_owned(),
+    ///                                  SafeString::new("hunter3".to_owned()))) {
+    ///     Err(PasswordError::AppExistsError) => {}
+    ///     _ => panic!("added the same app twice"),
+    /// }
+    /// ```
     pub fn add_password(&mut self, password: Password) -> Result<(), PasswordError> {
         if self.has_password(password.name.deref()) {
             return Err(PasswordError::AppExistsError);
         }
         self.schema.passwords.push(password);
+        self.modified = true;
         Ok(())
     }
 
+    /// Removes the password of an app, found like `get_password`, and returns it.
+    ///
+    /// ```
+    /// # use rooster::password::v2::{Password, PasswordStore};
+    /// # use rooster::safe_string::SafeString;
+    /// # let master = SafeString::new("correct horse battery staple".to_owned());
+    /// # let mut store = PasswordStore::new(master).unwrap();
+    /// # store.add_password(Password::new("YouTube".to_owned(),
+    ///                                  "me@example.com".to_owned(),
+    ///                                  SafeString::new("hunter2".to_owned())))
+    /// #     .unwrap();
+    /// let deleted = store.delete_password("youtube").unwrap();
+    /// assert_eq!(deleted.name, "YouTube");
+    /// assert!(store.delete_password("youtube").is_err());
+    /// ```
     pub fn delete_password(&mut self, name: &str) -> Result<Password, PasswordError> {
         let p = self.get_password(name)
             .ok_or(PasswordError::NoSuchAppError)?;
@@ -387,6 +1146,7 @@ impl PasswordStore {
         let mut i = 0;
         while i < self.schema.passwords.len() {
             if self.schema.passwords[i].name == p.name {
+                self.modified = true;
                 return Ok(self.schema.passwords.remove(i));
             }
             i += 1;
@@ -394,53 +1154,73 @@ impl PasswordStore {
         unreachable!();
     }
 
-    pub fn search_passwords(&self, name: &str) -> Vec<&Password> {
-        // Fuzzy search password app names.
-        let keys = self.schema
+    /// Fuzzy searches passwords by app name, and then by username, see `match_score`. Passwords
+    /// whose name matches come first, then those where only the username does, each sorted from
+    /// the best match to the worst, and then by name.
+    ///
+    /// ```
+    /// # use rooster::password::v2::{Password, PasswordStore};
+    /// # use rooster::safe_string::SafeString;
+    /// # let master = SafeString::new("correct horse battery staple".to_owned());
+    /// # let mut store = PasswordStore::new(master).unwrap();
+    /// # for name in ["YouTube", "GitHub", "GitLab"].iter() {
+    /// #     store.add_password(Password::new(name.to_string(),
+    /// #                                      "me@example.com".to_owned(),
+    /// #                                      SafeString::new("hunter2".to_owned())))
+    /// #         .unwrap();
+    /// # }
+    /// let found: Vec<&str> = store.search_passwords("git")
+    ///     .iter()
+    ///     .map(|p| p.name.as_str())
+    ///     .collect();
+    /// assert_eq!(found, vec!["GitHub", "GitLab"]);
+    /// ```
+    pub fn search_passwords(&self, query: &str) -> Vec<&Password> {
+        let score = |word: &str, text: &str| -> Option<i64> {
+            match self.search_regex {
+                // Regular expressions match or don't, so they are only sorted by name.
+                Some(ref regex) => if regex.is_match(text) { Some(0) } else { None },
+                None => match_score(word, text),
+            }
+        };
+        let words: Vec<&str> = match self.search_regex {
+            Some(_) => vec![query],
+            None => query.split_whitespace().collect(),
+        };
+        let mut scored: Vec<((bool, i64), &Password)> = self.schema
             .passwords
             .iter()
-            .map(|p| p.name.to_lowercase())
-            .collect::<Vec<String>>();
-
-        let mut search_results = vec![];
-        // Check if each app name can be matched against the search query.
-        //
-        // It's fine if there are some characters left out in the query. For instance, you can
-        // search for the app "Facebook" with just "fcbk".
-        for app_name in keys.iter().map(|s| s.as_str()) {
-            let mut matches_query = true;
-            let mut last_i = 0;
-            for c in name.chars() {
-                match app_name[last_i..].find(c.to_lowercase().next().unwrap()) {
-                    // Query chars must be present in the app name in the right order.
-                    Some(ic) => {
-                        last_i += ic + 1;
-                    }
-                    // Query char is not present, no match.
-                    None => {
-                        matches_query = false;
-                        break;
-                    }
-                }
-            }
-
-            if matches_query {
-                search_results.push(app_name.to_owned());
-            }
-        }
-
-        let mut passwords = vec![];
-        for p in self.schema.passwords.iter() {
-            if search_results.contains(&p.name.to_lowercase()) {
-                passwords.push(p);
-            }
-        }
-
-        passwords.sort_by_key(|p| { return p.name.to_lowercase(); });
+            .filter(|p| !p.archived || self.include_archived)
+            .filter_map(|p| words_score(&words, p, &score).map(|total| (total, p)))
+            .collect();
+
+        let collation = self.collation();
+        scored.sort_by(|&(a_score, a), &(b_score, b)| {
+            b_score.cmp(&a_score).then(collation.compare(&a.name, &b.name))
+        });
+        scored.into_iter().map(|(_, p)| p).collect()
+    }
 
-        passwords
+    /// Every list of passwords is sorted here, so they all follow the same collation.
+    fn sort_by_name(&self, passwords: &mut Vec<&Password>) {
+        let collation = self.collation();
+        passwords.sort_by(|a, b| collation.compare(&a.name, &b.name));
     }
 
+    /// The password of the app with exactly that name, regardless of case.
+    ///
+    /// ```
+    /// # use rooster::password::v2::{Password, PasswordStore};
+    /// # use rooster::safe_string::SafeString;
+    /// # let master = SafeString::new("correct horse battery staple".to_owned());
+    /// # let mut store = PasswordStore::new(master).unwrap();
+    /// # store.add_password(Password::new("YouTube".to_owned(),
+    ///                                  "me@example.com".to_owned(),
+    ///                                  SafeString::new("hunter2".to_owned())))
+    /// #     .unwrap();
+    /// assert_eq!(store.get_password("youtube").unwrap().password.expose(), "hunter2");
+    /// assert!(store.get_password("you").is_none());
+    /// ```
     pub fn get_password(&self, name: &str) -> Option<Password> {
         'passwords_loop: for p in &self.schema.passwords {
             // Since the app name must be the same, we need the same length.
@@ -472,13 +1252,1249 @@ impl PasswordStore {
                            closure: &Fn(Password) -> Password)
                            -> Result<(), PasswordError> {
         let old_p = self.delete_password(app_name.deref())?;
+        let old_value = old_p.password.clone();
+
+        // Keep the previous value around, in case the new one didn't make it to the app.
+        let mut new_p = closure(old_p);
+        if new_p.password != old_value {
+            new_p.push_history(old_value, ffi::time(), self.history_depth);
+        }
+
+        self.add_password(new_p)
+    }
+
+    /// Groups of passwords that are probably for the same account: their names are the same
+    /// without regard to casing, or they have the same username and password. Groups are linked
+    /// transitively, and sorted by their first password in the order of the file.
+    pub fn find_duplicates(&self) -> Vec<Vec<PasswordIndex>> {
+        let passwords = &self.schema.passwords;
+        let mut parents: Vec<usize> = (0..passwords.len()).collect();
+        let mut by_name: HashMap<String, usize> = HashMap::new();
+        let mut by_credentials: HashMap<(&str, &str), usize> = HashMap::new();
+
+        for (i, p) in passwords.iter().enumerate() {
+            let mut same = vec![*by_name.entry(p.name.to_lowercase()).or_insert(i)];
+            // Empty passwords don't say anything about the account.
+            if !p.password.is_empty() {
+                same.push(*by_credentials.entry((p.username.as_str(), p.password.expose()))
+                    .or_insert(i));
+            }
+            for j in same {
+                let (root_i, root_j) = (find_root(&mut parents, i), find_root(&mut parents, j));
+                parents[root_i.max(root_j)] = root_i.min(root_j);
+            }
+        }
+
+        let mut groups: Vec<Vec<PasswordIndex>> = Vec::new();
+        let mut group_of_root: HashMap<usize, usize> = HashMap::new();
+        for i in 0..passwords.len() {
+            let root = find_root(&mut parents, i);
+            let next_group = groups.len();
+            let group = *group_of_root.entry(root).or_insert(next_group);
+            if group == groups.len() {
+                groups.push(Vec::new());
+            }
+            groups[group].push(PasswordIndex(i));
+        }
+        groups.retain(|g| g.len() > 1);
+        groups
+    }
 
-        self.add_password(closure(old_p))
+    /// Removes every password, keeping the master password and the settings.
+    pub fn clear_passwords(&mut self) -> Vec<Password> {
+        if !self.schema.passwords.is_empty() {
+            self.modified = true;
+        }
+        self.schema.passwords.drain(..).collect()
     }
 
+    /// Exchanges the names of two passwords, which keep everything else.
+    pub fn swap_names(&mut self, a: &str, b: &str) -> Result<(), PasswordError> {
+        let i = self.index_of(a).ok_or(PasswordError::NoSuchAppError)?.0;
+        let j = self.index_of(b).ok_or(PasswordError::NoSuchAppError)?.0;
+        if i == j {
+            return Ok(());
+        }
+
+        let now = ffi::time();
+        let name = self.schema.passwords[i].name.clone();
+        self.schema.passwords[i].name = mem::replace(&mut self.schema.passwords[j].name, name);
+        self.schema.passwords[i].updated_at = now;
+        self.schema.passwords[j].updated_at = now;
+        self.modified = true;
+        Ok(())
+    }
+
+    /// Gives a password a new name. A new name that only differs by its casing just changes the
+    /// casing. When another password has the new name, `on_collision` tells whether to fail,
+    /// overwrite it or merge it in. Both passwords are dealt with in one go, so nothing is lost
+    /// if the rename fails.
+    pub fn rename_password(&mut self,
+                           name: &str,
+                           new_name: &str,
+                           on_collision: RenameCollision)
+                           -> Result<(), PasswordError> {
+        let index = self.index_of(name).ok_or(PasswordError::NoSuchAppError)?;
+        let now = ffi::time();
+        let mut renamed = self.password_at(index).clone();
+        renamed.name = new_name.to_owned();
+        renamed.updated_at = now;
+
+        let deletions = match self.index_of(new_name) {
+            Some(other) if other != index => {
+                let other_password = self.password_at(other).clone();
+                match on_collision {
+                    RenameCollision::Fail => return Err(PasswordError::AppExistsError),
+                    RenameCollision::Overwrite => {
+                        if other_password.password != renamed.password {
+                            renamed.push_history(other_password.password, now, self.history_depth);
+                        }
+                    }
+                    RenameCollision::Merge => {
+                        renamed.merge_from(other_password, self.history_depth);
+                        renamed.updated_at = now;
+                    }
+                }
+                vec![other]
+            }
+            _ => vec![],
+        };
+
+        self.replace_and_delete(vec![(index, renamed)], deletions);
+        Ok(())
+    }
+
+    /// Position of the password with that name, without regard to casing.
+    pub fn index_of(&self, name: &str) -> Option<PasswordIndex> {
+        self.schema
+            .passwords
+            .iter()
+            .position(|p| p.name.to_lowercase() == name.to_lowercase())
+            .map(PasswordIndex)
+    }
+
+    pub fn password_at(&self, index: PasswordIndex) -> &Password {
+        &self.schema.passwords[index.0]
+    }
+
+    /// Replaces and deletes passwords given by their position in one go, so that deleting a
+    /// password doesn't move the ones that come after it before they're dealt with.
+    pub fn replace_and_delete(&mut self,
+                              replacements: Vec<(PasswordIndex, Password)>,
+                              mut deletions: Vec<PasswordIndex>) {
+        for (index, password) in replacements.into_iter() {
+            self.schema.passwords[index.0] = password;
+        }
+
+        deletions.sort();
+        deletions.dedup();
+        for index in deletions.into_iter().rev() {
+            self.schema.passwords.remove(index.0);
+        }
+        self.modified = true;
+    }
+
+    /// How many previous values of each password are kept from now on. Histories that are
+    /// already longer are cut the next time their password changes.
+    pub fn set_history_depth(&mut self, depth: usize) {
+        self.history_depth = depth;
+    }
+
+    pub fn history_depth(&self) -> usize {
+        self.history_depth
+    }
+
+    /// The matching mode to use: the override if any, then the saved setting.
+    pub fn matching(&self) -> Matching {
+        self.matching_override.unwrap_or(self.schema.settings.matching)
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.schema.settings
+    }
+
+    /// Changes a setting, which is saved with the passwords.
+    pub fn set_matching(&mut self, matching: Matching) {
+        self.schema.settings.matching = matching;
+        self.modified = true;
+    }
+
+    pub fn set_collation(&mut self, collation: Collation) {
+        self.schema.settings.collation = collation;
+        self.modified = true;
+    }
+
+    pub fn set_notify_done(&mut self, notify_done: bool) {
+        self.schema.settings.notify_done = notify_done;
+        self.modified = true;
+    }
+
+    pub fn decline_kdf_upgrade(&mut self) {
+        self.schema.settings.kdf_upgrade_declined = true;
+        self.modified = true;
+    }
+
+    /// Uses another matching mode until the store is dropped, without saving it.
+    pub fn override_matching(&mut self, matching: Matching) {
+        self.matching_override = Some(matching);
+    }
+
+    /// Searches with a regular expression instead of the query until the store is dropped, see
+    /// --regex.
+    pub fn search_with_regex(&mut self, regex: Regex) {
+        self.search_regex = Some(regex);
+    }
+
+    pub fn searches_with_regex(&self) -> bool {
+        self.search_regex.is_some()
+    }
+
+    /// The collation to use: the override if any, then the saved setting.
+    pub fn collation(&self) -> Collation {
+        self.collation_override.unwrap_or(self.schema.settings.collation)
+    }
+
+    /// Sorts with another collation until the store is dropped, without saving it.
+    pub fn override_collation(&mut self, collation: Collation) {
+        self.collation_override = Some(collation);
+    }
+
+    /// Makes searches find archived passwords too, until the store is dropped.
+    pub fn set_include_archived(&mut self, include: bool) {
+        self.include_archived = include;
+    }
+
+    pub fn includes_archived(&self) -> bool {
+        self.include_archived
+    }
+
+    pub fn kdf(&self) -> Kdf {
+        self.kdf
+    }
+
+    /// Derives the key with Argon2id from now on, which saves the store as a version 4 file.
+    /// The master password and the second factors stay the same.
+    pub fn upgrade_key_derivation(&mut self, master_password: &str) {
+        self.kdf = Kdf::Argon2id(v3::DEFAULT_PARAMS);
+        self.change_master_password(master_password);
+    }
+
+    /// Derives the key with `kdf` from now on, after checking that `master_password` is the
+    /// one the store was opened with.
+    pub fn change_key_derivation(&mut self,
+                                 master_password: &str,
+                                 kdf: Kdf)
+                                 -> Result<(), PasswordError> {
+        self.check_master_password(master_password)?;
+        self.kdf = kdf;
+        self.change_master_password(master_password);
+        Ok(())
+    }
+
+    /// Derives a new key from a new random salt, with the same master password and second
+    /// factors. The key file is needed again, since its digest depends on the salt. Saving the
+    /// store then encrypts everything with the new key, and a new IV as always.
+    pub fn rekey(&mut self,
+                 master_password: &str,
+                 factors: &SecondFactors)
+                 -> Result<(), PasswordError> {
+        self.check_master_password(master_password)?;
+        if factors.key_file.is_some() != self.key_file_digest.is_some() {
+            return Err(if self.key_file_digest.is_some() {
+                           PasswordError::KeyFileRequiredError
+                       } else {
+                           PasswordError::KeyFileNotUsedError
+                       });
+        }
+        if let (Some(digest), Some(key_file)) = (self.key_file_digest.as_ref(),
+                                                 factors.key_file.as_ref()) {
+            let check = key_file_check(&digest_key_file(key_file.expose(), &self.salt));
+            if !fixed_time_eq(&check, &key_file_check(digest)) {
+                return Err(PasswordError::WrongKeyFileError);
+            }
+        }
+        self.salt = generate_random_salt()?;
+        self.change_master_password_and_factors(master_password, factors);
+        Ok(())
+    }
+
+    /// Fails unless `master_password` is the one the store was opened with, since it isn't kept.
+    fn check_master_password(&self, master_password: &str) -> Result<(), PasswordError> {
+        let current_key = generate_encryption_key(&self.kdf,
+                                                  master_password,
+                                                  self.key_file_digest.as_ref(),
+                                                  self.yubikey
+                                                      .as_ref()
+                                                      .map(|yubikey| &yubikey.response),
+                                                  self.salt);
+        if !fixed_time_eq(current_key.expose(), self.key.expose()) {
+            return Err(PasswordError::WrongMasterPasswordError);
+        }
+        Ok(())
+    }
+
+    /// Changes the master password, and keeps the second factors if there are some.
     pub fn change_master_password(&mut self, master_password: &str) {
-        let scrypt_params =
-            scrypt::ScryptParams::new(self.scrypt_log2_n, self.scrypt_r, self.scrypt_p);
-        self.key = generate_encryption_key(scrypt_params, master_password, self.salt);
+        let yubikey_response = self.yubikey.as_ref().map(|yubikey| &yubikey.response);
+        let new_key = generate_encryption_key(&self.kdf,
+                                              master_password,
+                                              self.key_file_digest.as_ref(),
+                                              yubikey_response,
+                                              self.salt);
+        drop(self.replace_key(new_key));
+        self.modified = true;
+    }
+
+    /// Changes the master password, and the second factors, which the default value removes.
+    pub fn change_master_password_and_factors(&mut self,
+                                              master_password: &str,
+                                              factors: &SecondFactors) {
+        self.key_file_digest = factors.key_file
+            .as_ref()
+            .map(|key_file| digest_key_file(key_file.expose(), &self.salt));
+        self.yubikey = factors.yubikey.clone();
+        self.change_master_password(master_password);
+    }
+
+    pub fn has_key_file(&self) -> bool {
+        self.key_file_digest.is_some()
+    }
+
+    pub fn yubikey(&self) -> Option<&YubiKeyResponse> {
+        self.yubikey.as_ref()
+    }
+
+    pub fn is_modified(&self) -> bool {
+        self.modified
+    }
+
+    /// Called when the changes were written before the command is done, like by the checkpoints
+    /// of `rooster batch`, so that they aren't saved again at the end.
+    pub fn mark_saved(&mut self) {
+        self.modified = false;
+    }
+
+    /// Swaps the encryption key and returns the old one, already wiped.
+    fn replace_key(&mut self, key: SafeVec) -> SafeVec {
+        let mut old_key = mem::replace(&mut self.key, key);
+        old_key.wipe();
+        debug_assert!(old_key.is_wiped());
+        old_key
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{generate_yubikey_challenge, Collation, Header, Kdf, Matching, Password,
+                PasswordStore, RenameCollision, Schema, SecondFactors, YubiKeyResponse,
+                DEFAULT_SCRYPT};
+    use super::super::v3;
+    use super::super::PasswordError;
+    use regex::{Regex, RegexBuilder};
+    use std::env;
+    use std::fs;
+    use std::process;
+    use safe_string::SafeString;
+    use safe_vec::SafeVec;
+    use serde_json;
+    use std::cell::Cell;
+
+    thread_local!(pub static DERIVATIONS: Cell<usize> = Cell::new(0));
+
+    fn derivations() -> usize {
+        DERIVATIONS.with(|count| count.get())
+    }
+
+    #[test]
+    fn replace_key_wipes_old_key() {
+        let sentinel = 0xa5u8;
+        let mut store = PasswordStore::new(SafeString::new("old".to_owned())).unwrap();
+        store.key = SafeVec::new(vec![sentinel; 32]);
+
+        let old_key = store.replace_key(SafeVec::new(vec![1u8; 32]));
+
+        assert_eq!(old_key.expose().len(), 32);
+        assert!(old_key.is_wiped());
+        assert_eq!(store.key.expose(), &vec![1u8; 32][..]);
+    }
+
+    #[test]
+    fn change_master_password_changes_key() {
+        let mut store = PasswordStore::new(SafeString::new("old".to_owned())).unwrap();
+        let old_key = store.key.clone();
+
+        store.change_master_password("new");
+
+        assert!(store.key != old_key);
+        assert!(!store.key.is_wiped());
+    }
+
+    fn change_to(store: &mut PasswordStore, name: &str, value: &str) {
+        let value = SafeString::new(value.to_owned());
+        store.change_password(name,
+                             &|old| Password { password: value.clone(), ..old })
+            .unwrap();
+    }
+
+    #[test]
+    fn changes_are_kept_in_history() {
+        let mut store = PasswordStore::new(SafeString::new("master".to_owned())).unwrap();
+        store.set_history_depth(3);
+        store.add_password(Password::new("YouTube".to_owned(),
+                                         "me".to_owned(),
+                                         SafeString::new("v0".to_owned())))
+            .unwrap();
+
+        // Changes that leave the password alone don't go in the history.
+        store.change_password("YouTube",
+                             &|old| Password { username: "you".to_owned(), ..old })
+            .unwrap();
+        assert!(store.get_password("YouTube").unwrap().history.is_empty());
+
+        for value in ["v1", "v2", "v3", "v4"].iter() {
+            change_to(&mut store, "YouTube", value);
+        }
+        let p = store.get_password("YouTube").unwrap();
+        let history: Vec<&str> = p.history.iter().map(|h| h.password.expose()).collect();
+        assert_eq!(p.password.expose(), "v4");
+        assert_eq!(history, vec!["v3", "v2", "v1"]);
+    }
+
+    #[test]
+    fn history_is_encrypted_with_the_passwords() {
+        let path = env::temp_dir().join(format!("rooster-history-test-{}", process::id()));
+        let mut store = PasswordStore::new(SafeString::new("master".to_owned())).unwrap();
+        store.add_password(Password::new("YouTube".to_owned(),
+                                         "me".to_owned(),
+                                         SafeString::new("old-secret".to_owned())))
+            .unwrap();
+        change_to(&mut store, "YouTube", "new-secret");
+        store.sync(&path).unwrap();
+
+        let input = SafeVec::new(fs::read(&path).unwrap());
+        fs::remove_file(&path).unwrap();
+        assert!(!String::from_utf8_lossy(input.expose()).contains("old-secret"));
+
+        let store = PasswordStore::from_input(SafeString::new("master".to_owned()), input)
+            .unwrap();
+        let p = store.get_password("YouTube").unwrap();
+        assert_eq!(p.history.len(), 1);
+        assert_eq!(p.history[0].password.expose(), "old-secret");
+    }
+
+    #[test]
+    fn clearing_keeps_the_master_password_and_settings() {
+        let path = env::temp_dir().join(format!("rooster-clear-test-{}", process::id()));
+        let mut store = PasswordStore::new(SafeString::new("master".to_owned())).unwrap();
+        assert!(store.clear_passwords().is_empty());
+        assert!(!store.is_modified());
+
+        for name in ["YouTube", "GitHub"].iter() {
+            store.add_password(Password::new(name.to_string(),
+                                             "me".to_owned(),
+                                             SafeString::new("secret".to_owned())))
+                .unwrap();
+        }
+        store.set_matching(Matching::Exact);
+        let cleared = store.clear_passwords();
+        assert_eq!(cleared.len(), 2);
+        assert!(store.get_all_passwords().is_empty());
+        store.sync(&path).unwrap();
+
+        let input = SafeVec::new(fs::read(&path).unwrap());
+        fs::remove_file(&path).unwrap();
+
+        let store = PasswordStore::from_input(SafeString::new("master".to_owned()), input)
+            .unwrap();
+        assert!(store.get_all_passwords().is_empty());
+        assert_eq!(store.settings().matching, Matching::Exact);
+    }
+
+    #[test]
+    fn archived_passwords_are_only_found_on_demand() {
+        let mut store = PasswordStore::new(SafeString::new("master".to_owned())).unwrap();
+        let mut archived = Password::new("YouTube".to_owned(),
+                                         "me".to_owned(),
+                                         SafeString::new("secret".to_owned()));
+        archived.archived = true;
+        store.add_password(archived).unwrap();
+        store.add_password(Password::new("Yahoo".to_owned(),
+                                         "me".to_owned(),
+                                         SafeString::new("secret".to_owned())))
+            .unwrap();
+
+        let names = |store: &PasswordStore| -> Vec<String> {
+            store.search_passwords("y").iter().map(|p| p.name.clone()).collect()
+        };
+        assert_eq!(names(&store), vec!["Yahoo"]);
+        assert!(store.get_password("youtube").unwrap().archived);
+        assert_eq!(store.get_all_passwords().len(), 2);
+
+        store.set_include_archived(true);
+        assert_eq!(names(&store), vec!["Yahoo", "YouTube"]);
+
+        let json = serde_json::to_string(&store.get_all_passwords()).unwrap();
+        let exported: Vec<Password> = serde_json::from_str(&json).unwrap();
+        assert!(!exported[0].archived);
+        assert!(exported[1].archived);
+    }
+
+    #[test]
+    fn the_archived_tag_becomes_the_archived_field_on_load() {
+        let master = || SafeString::new("master".to_owned());
+        let mut store = PasswordStore::new(master()).unwrap();
+        let mut tagged = youtube();
+        tagged.add_tag("archived".to_owned());
+        tagged.add_tag("work".to_owned());
+        store.add_password(tagged).unwrap();
+        store.add_password(Password::new("Yahoo".to_owned(),
+                                         "me".to_owned(),
+                                         SafeString::new("secret".to_owned())))
+            .unwrap();
+
+        let store = PasswordStore::from_input(master(), saved(&store, "archived-tag")).unwrap();
+        let youtube = store.get_password("YouTube").unwrap();
+        assert!(youtube.archived);
+        assert_eq!(youtube.tags, vec!["work"]);
+        assert!(!store.get_password("Yahoo").unwrap().archived);
+        assert!(!store.modified);
+    }
+
+    #[test]
+    fn swapping_names_keeps_everything_else() {
+        let mut store = PasswordStore::new(SafeString::new("master".to_owned())).unwrap();
+        store.add_password(Password::new("Slack (work)".to_owned(),
+                                         "me@example.com".to_owned(),
+                                         SafeString::new("personal-secret".to_owned())))
+            .unwrap();
+        store.add_password(Password::new("Slack (personal)".to_owned(),
+                                         "me@example.com".to_owned(),
+                                         SafeString::new("work-secret".to_owned())))
+            .unwrap();
+
+        store.swap_names("slack (WORK)", "Slack (personal)").unwrap();
+        assert!(store.is_modified());
+        let work = store.get_password("Slack (work)").unwrap();
+        let personal = store.get_password("Slack (personal)").unwrap();
+        assert_eq!(work.password.expose(), "work-secret");
+        assert_eq!(personal.password.expose(), "personal-secret");
+        assert_eq!(store.get_all_passwords().len(), 2);
+
+        store.swap_names("Slack (work)", "Slack (work)").unwrap();
+        assert_eq!(store.get_password("Slack (work)").unwrap().password.expose(),
+                   "work-secret");
+
+        assert!(store.swap_names("Slack (work)", "Discord").is_err());
+        assert_eq!(store.get_password("Slack (work)").unwrap().password.expose(),
+                   "work-secret");
+    }
+
+    #[test]
+    fn renaming_onto_a_taken_name() {
+        let new_store = || {
+            let mut store = PasswordStore::new(SafeString::new("master".to_owned())).unwrap();
+            let mut gmail = Password::new("Gmail".to_owned(),
+                                          "me@gmail.com".to_owned(),
+                                          SafeString::new("gmail-secret".to_owned()));
+            gmail.url = Some("https://mail.google.com".to_owned());
+            store.add_password(gmail).unwrap();
+            let mut google = Password::new("Google".to_owned(),
+                                           "me@gmail.com".to_owned(),
+                                           SafeString::new("google-secret".to_owned()));
+            google.notes = Some(SafeString::new("recovery codes in the drawer".to_owned()));
+            store.add_password(google).unwrap();
+            store
+        };
+
+        let mut store = new_store();
+        assert!(store.rename_password("gmail", "GOOGLE", RenameCollision::Fail).is_err());
+        assert_eq!(store.get_all_passwords().len(), 2);
+        assert_eq!(store.get_password("Gmail").unwrap().password.expose(), "gmail-secret");
+        store.rename_password("gmail", "GMail", RenameCollision::Fail).unwrap();
+        assert_eq!(store.get_password("gmail").unwrap().name, "GMail");
+        assert!(store.rename_password("Outlook", "Hotmail", RenameCollision::Fail).is_err());
+
+        let mut store = new_store();
+        store.rename_password("Gmail", "Google", RenameCollision::Overwrite).unwrap();
+        assert_eq!(store.get_all_passwords().len(), 1);
+        let google = store.get_password("Google").unwrap();
+        assert_eq!(google.password.expose(), "gmail-secret");
+        assert_eq!(google.history[0].password.expose(), "google-secret");
+        assert!(google.notes.is_none());
+
+        let mut store = new_store();
+        store.rename_password("Gmail", "Google", RenameCollision::Merge).unwrap();
+        assert_eq!(store.get_all_passwords().len(), 1);
+        let google = store.get_password("Google").unwrap();
+        assert_eq!(google.password.expose(), "gmail-secret");
+        assert_eq!(google.history[0].password.expose(), "google-secret");
+        assert_eq!(google.url, Some("https://mail.google.com".to_owned()));
+        assert_eq!(google.notes.as_ref().unwrap().expose(), "recovery codes in the drawer");
+    }
+
+    #[test]
+    fn settings_are_only_saved_when_changed() {
+        let schema: Schema = serde_json::from_str("{\"passwords\":[]}").unwrap();
+        assert_eq!(schema.settings.matching, Matching::Fuzzy);
+        assert_eq!(serde_json::to_string(&schema).unwrap(), "{\"passwords\":[]}");
+
+        let schema: Schema =
+            serde_json::from_str("{\"passwords\":[],\"settings\":{\"matching\":\"exact\"}}")
+                .unwrap();
+        assert_eq!(schema.settings.matching, Matching::Exact);
+        assert!(!schema.settings.notify_done);
+
+        let mut schema = schema;
+        schema.settings.notify_done = true;
+        assert_eq!(serde_json::to_string(&schema.settings).unwrap(),
+                   "{\"matching\":\"exact\",\"notify_done\":true}");
+    }
+
+    #[test]
+    fn matching_override_is_not_saved() {
+        let mut store = PasswordStore::new(SafeString::new("master".to_owned())).unwrap();
+        store.override_matching(Matching::Exact);
+        assert_eq!(store.matching(), Matching::Exact);
+        assert_eq!(store.settings().matching, Matching::Fuzzy);
+        assert!(!store.is_modified());
+
+        store.set_matching(Matching::Exact);
+        store.override_matching(Matching::Fuzzy);
+        assert_eq!(store.matching(), Matching::Fuzzy);
+        assert!(store.is_modified());
+    }
+
+    #[test]
+    fn searches_are_ranked_by_match_quality() {
+        let mut store = PasswordStore::new(SafeString::new("master".to_owned())).unwrap();
+        for name in ["Google", "GitLab", "Gmail", "Eagle", "gl-admin", "GL", "Bugle", "Glacier",
+                     "Dropbox", "db"]
+            .iter() {
+            store.add_password(Password::new(name.to_string(),
+                                             "me".to_owned(),
+                                             SafeString::new("xxx".to_owned())))
+                .unwrap();
+        }
+        let names = |query: &str| -> Vec<String> {
+            store.search_passwords(query).iter().map(|p| p.name.clone()).collect()
+        };
+
+        // Exact, then prefixes, then substrings (earlier first), then the fewest gaps.
+        assert_eq!(names("gl"),
+                   vec!["GL", "gl-admin", "Glacier", "Bugle", "Eagle", "Google", "GitLab",
+                        "Gmail"]);
+        assert_eq!(names("db"), vec!["db", "Dropbox"]);
+        assert_eq!(names("gml"), vec!["Gmail"]);
+        assert_eq!(names("xyz"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn searches_with_a_regex_instead_of_the_query() {
+        let mut store = PasswordStore::new(SafeString::new("master".to_owned())).unwrap();
+        for &(name, username) in [("Work Mail", "me"),
+                                  ("GitLab", "me@work.com"),
+                                  ("Gmail", "me"),
+                                  ("Bank", "me@mail.com")]
+            .iter() {
+            store.add_password(Password::new(name.to_owned(),
+                                             username.to_owned(),
+                                             SafeString::new("xxx".to_owned())))
+                .unwrap();
+        }
+        store.search_with_regex(RegexBuilder::new("MAIL").case_insensitive(true)
+            .build()
+            .unwrap());
+        let names: Vec<String> = store.search_passwords("ignored")
+            .iter()
+            .map(|p| p.name.clone())
+            .collect();
+
+        // Names that match come first, then usernames, each sorted by name.
+        assert_eq!(names, vec!["Gmail", "Work Mail", "Bank"]);
+
+        store.search_with_regex(Regex::new("work").unwrap());
+        let names: Vec<String> = store.search_passwords("ignored")
+            .iter()
+            .map(|p| p.name.clone())
+            .collect();
+        assert_eq!(names, vec!["GitLab"]);
+    }
+
+    #[test]
+    fn usernames_are_searched_after_names() {
+        let mut store = PasswordStore::new(SafeString::new("master".to_owned())).unwrap();
+        for &(name, username) in [("Stripe", "billing@company.com"),
+                                  ("Bitbucket", "dev@company.com"),
+                                  ("Company Wiki", "me"),
+                                  ("Crème brûlée", "chef")]
+            .iter() {
+            store.add_password(Password::new(name.to_owned(),
+                                             username.to_owned(),
+                                             SafeString::new("xxx".to_owned())))
+                .unwrap();
+        }
+        let names = |query: &str| -> Vec<String> {
+            store.search_passwords(query).iter().map(|p| p.name.clone()).collect()
+        };
+
+        assert_eq!(names("billing@company.com"), vec!["Stripe"]);
+        assert_eq!(names("company"), vec!["Company Wiki", "Bitbucket", "Stripe"]);
+        assert_eq!(names("BIT"), vec!["Bitbucket"]);
+        assert_eq!(names("crmbrl"), vec!["Crème brûlée"]);
+        assert_eq!(names("èû"), vec!["Crème brûlée"]);
+        assert_eq!(names("ûè"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn every_word_of_a_query_has_to_match() {
+        let mut store = PasswordStore::new(SafeString::new("master".to_owned())).unwrap();
+        for &(name, username) in [("AWS prod", "me"),
+                                  ("AWS staging", "me"),
+                                  ("Prod DB", "aws-admin"),
+                                  ("Work Mail", "me")]
+            .iter() {
+            store.add_password(Password::new(name.to_owned(),
+                                             username.to_owned(),
+                                             SafeString::new("xxx".to_owned())))
+                .unwrap();
+        }
+        let names = |query: &str| -> Vec<String> {
+            store.search_passwords(query).iter().map(|p| p.name.clone()).collect()
+        };
+
+        assert_eq!(names("aws"), vec!["AWS prod", "AWS staging", "Prod DB"]);
+        assert_eq!(names("aws prod"), vec!["AWS prod", "Prod DB"]);
+        assert_eq!(names("  prod   AWS "), vec!["AWS prod", "Prod DB"]);
+        assert_eq!(names("mail work"), vec!["Work Mail"]);
+        assert_eq!(names("aws mail"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn lists_and_searches_follow_the_collation() {
+        let mut store = PasswordStore::new(SafeString::new("master".to_owned())).unwrap();
+        for name in ["zebra", "école", "Ecole 2"].iter() {
+            store.add_password(Password::new(name.to_string(),
+                                             "me".to_owned(),
+                                             SafeString::new("xxx".to_owned())))
+                .unwrap();
+        }
+        let names = |passwords: Vec<&Password>| -> Vec<String> {
+            passwords.iter().map(|p| p.name.clone()).collect()
+        };
+
+        assert_eq!(names(store.get_all_passwords()), vec!["Ecole 2", "zebra", "école"]);
+
+        store.override_collation(Collation::Locale);
+        assert_eq!(names(store.get_all_passwords()), vec!["école", "Ecole 2", "zebra"]);
+        assert_eq!(names(store.search_passwords("")), vec!["école", "Ecole 2", "zebra"]);
+        assert_eq!(store.settings().collation, Collation::Codepoint);
+    }
+
+    #[test]
+    fn only_changes_mark_the_store_as_modified() {
+        let mut store = PasswordStore::new(SafeString::new("master".to_owned())).unwrap();
+        assert!(!store.is_modified());
+
+        store.search_passwords("yt");
+        store.get_password("YouTube");
+        assert!(!store.is_modified());
+
+        store.add_password(Password::new("YouTube".to_owned(),
+                                         "me".to_owned(),
+                                         SafeString::new("xxx".to_owned())))
+            .unwrap();
+        assert!(store.is_modified());
+    }
+
+    #[test]
+    fn duplicates_are_grouped_and_deleted_together() {
+        let mut store = PasswordStore::new(SafeString::new("master".to_owned())).unwrap();
+        // Older files may have names that only differ in casing, add_password prevents it.
+        for &(name, username, password) in [("GitHub", "me", "a"),
+                                            ("Slack", "me", "b"),
+                                            ("github", "me", "c"),
+                                            ("gh", "me", "c"),
+                                            ("YouTube", "you", "b")]
+            .iter() {
+            store.schema.passwords.push(Password::new(name.to_owned(),
+                                                      username.to_owned(),
+                                                      SafeString::new(password.to_owned())));
+        }
+
+        let groups = store.find_duplicates();
+        let names: Vec<Vec<&str>> = groups.iter()
+            .map(|g| g.iter().map(|&i| store.password_at(i).name.as_str()).collect())
+            .collect();
+        assert_eq!(names, vec![vec!["GitHub", "github", "gh"]]);
+
+        let (keep, delete) = (groups[0][2], vec![groups[0][1], groups[0][0], groups[0][1]]);
+        let kept = Password { username: "us".to_owned(), ..store.password_at(keep).clone() };
+        store.replace_and_delete(vec![(keep, kept)], delete);
+        let names: Vec<(&str, &str)> = store.schema
+            .passwords
+            .iter()
+            .map(|p| (p.name.as_str(), p.username.as_str()))
+            .collect();
+        assert_eq!(names, vec![("Slack", "me"), ("gh", "us"), ("YouTube", "you")]);
+    }
+
+    #[test]
+    fn merged_passwords_keep_both_values() {
+        let mut old = Password::new("GitHub".to_owned(),
+                                    String::new(),
+                                    SafeString::new("old".to_owned()));
+        old.created_at = 10;
+        old.updated_at = 10;
+        old.url = Some("https://github.com".to_owned());
+        let mut new = Password::new("github.com".to_owned(),
+                                    "me".to_owned(),
+                                    SafeString::new("new".to_owned()));
+        new.created_at = 20;
+        new.updated_at = 30;
+        new.push_history(SafeString::new("older".to_owned()), 5, 5);
+
+        new.merge_from(old, 5);
+        assert_eq!(new.username, "me");
+        assert_eq!(new.url, Some("https://github.com".to_owned()));
+        assert_eq!((new.created_at, new.updated_at), (10, 30));
+        let history: Vec<&str> = new.history.iter().map(|h| h.password.expose()).collect();
+        assert_eq!(history, vec!["old", "older"]);
+    }
+
+    #[test]
+    fn password_without_notes_deserialization() {
+        let json = "{\"name\":\"YouTube\",\"username\":\"me\",\"password\":\"xxx\",\
+                    \"created_at\":1,\"updated_at\":2}";
+        let p: Password = serde_json::from_str(json).unwrap();
+        assert!(p.notes.is_none());
+        assert_eq!(serde_json::to_string(&p).unwrap(), json);
+    }
+
+    #[test]
+    fn favorite_defaults_to_false() {
+        let json = "{\"name\":\"YouTube\",\"username\":\"me\",\"password\":\"xxx\",\
+                    \"created_at\":1,\"updated_at\":2}";
+        let mut p: Password = serde_json::from_str(json).unwrap();
+        assert!(!p.favorite);
+
+        p.favorite = true;
+        let favorite_json = serde_json::to_string(&p).unwrap();
+        assert!(favorite_json.ends_with(",\"favorite\":true}"));
+        assert!(serde_json::from_str::<Password>(&favorite_json).unwrap().favorite);
+    }
+
+    #[test]
+    fn tags_are_normalized() {
+        assert_eq!(super::normalize_tag(" Work "), Ok("work".to_owned()));
+        assert!(super::normalize_tag("").is_err());
+        assert!(super::normalize_tag("my work").is_err());
+        assert!(super::normalize_tag("a,b").is_err());
+
+        let mut p: Password = serde_json::from_str("{\"name\":\"a\",\"username\":\"b\",\
+                                                     \"password\":\"c\",\"created_at\":1,\
+                                                     \"updated_at\":2}")
+            .unwrap();
+        assert!(p.tags.is_empty());
+        p.add_tag("work".to_owned());
+        p.add_tag("personal".to_owned());
+        p.add_tag("work".to_owned());
+        assert_eq!(p.tags, vec!["personal", "work"]);
+        assert!(serde_json::to_string(&p).unwrap().ends_with(",\"tags\":[\"personal\",\"work\"]}"));
+        p.remove_tag("personal");
+        assert_eq!(p.tags, vec!["work"]);
+    }
+
+    /// Saves the store and reads the file back.
+    fn saved(store: &PasswordStore, name: &str) -> SafeVec {
+        let path = env::temp_dir().join(format!("rooster-{}-test-{}", name, process::id()));
+        store.sync(&path).unwrap();
+
+        let input = SafeVec::new(fs::read(&path).unwrap());
+        fs::remove_file(&path).unwrap();
+        input
+    }
+
+    /// A store whose key is derived with scrypt, like the ones of Rooster before Argon2id.
+    fn scrypt_store(master_password: &str, factors: &SecondFactors) -> PasswordStore {
+        let mut store =
+            PasswordStore::new_with_factors(SafeString::new(master_password.to_owned()), factors)
+                .unwrap();
+        store.kdf = DEFAULT_SCRYPT;
+        store.change_master_password(master_password);
+        store
+    }
+
+    #[test]
+    fn saving_reuses_the_key() {
+        let store = PasswordStore::new(SafeString::new("master".to_owned())).unwrap();
+        let input = saved(&store, "reuse-key");
+
+        let before = derivations();
+        let mut store = PasswordStore::from_input(SafeString::new("master".to_owned()), input)
+            .unwrap();
+        assert_eq!(derivations(), before + 1);
+
+        store.add_password(Password::new("YouTube".to_owned(),
+                                         "me".to_owned(),
+                                         SafeString::new("secret".to_owned())))
+            .unwrap();
+        saved(&store, "reuse-key");
+        saved(&store, "reuse-key");
+        assert_eq!(derivations(), before + 1);
+
+        store.change_master_password("new");
+        saved(&store, "reuse-key");
+        assert_eq!(derivations(), before + 2);
+    }
+
+    fn with_key_file(key_file: &[u8]) -> SecondFactors {
+        SecondFactors { key_file: Some(SafeVec::new(key_file.to_vec())), yubikey: None }
+    }
+
+    #[test]
+    fn key_files_are_needed_to_open_the_store() {
+        let master = || SafeString::new("master".to_owned());
+        let store = scrypt_store("master", &with_key_file(b"usb stick"));
+        let input = saved(&store, "key-file");
+        assert_eq!(&input.expose()[..4], &[0, 0, 0, 3]);
+
+        match PasswordStore::from_input(master(), input.clone()) {
+            Err(PasswordError::KeyFileRequiredError) => {}
+            other => panic!("{:?}", other.err()),
+        }
+        match PasswordStore::from_input_with_factors(master(),
+                                                     input.clone(),
+                                                     &with_key_file(b"usb stick!")) {
+            Err(PasswordError::WrongKeyFileError) => {}
+            other => panic!("{:?}", other.err()),
+        }
+        match PasswordStore::from_input_with_factors(SafeString::new("wrong".to_owned()),
+                                                     input.clone(),
+                                                     &with_key_file(b"usb stick")) {
+            // A wrong key sometimes decrypts to garbage instead of failing.
+            Err(PasswordError::DecryptionError) |
+            Err(PasswordError::InvalidJsonError) => {}
+            other => panic!("{:?}", other.err()),
+        }
+        let mut store =
+            PasswordStore::from_input_with_factors(master(), input, &with_key_file(b"usb stick"))
+                .unwrap();
+        assert!(store.has_key_file());
+
+        // Without a key file, the file is version 2 again.
+        store.change_master_password_and_factors("master", &SecondFactors::default());
+        let input = saved(&store, "key-file");
+        assert_eq!(&input.expose()[..4], &[0, 0, 0, 2]);
+        match PasswordStore::from_input_with_factors(master(),
+                                                     input.clone(),
+                                                     &with_key_file(b"usb stick")) {
+            Err(PasswordError::KeyFileNotUsedError) => {}
+            other => panic!("{:?}", other.err()),
+        }
+        assert!(PasswordStore::from_input(master(), input).is_ok());
+    }
+
+    #[test]
+    fn yubikey_responses_are_mixed_into_the_key() {
+        let master = || SafeString::new("master".to_owned());
+        let challenge = generate_yubikey_challenge().unwrap();
+        let response = |bytes: &[u8]| {
+            SecondFactors {
+                key_file: None,
+                yubikey: Some(YubiKeyResponse {
+                    challenge: challenge,
+                    response: SafeVec::new(bytes.to_vec()),
+                }),
+            }
+        };
+        let store = PasswordStore::new_with_factors(master(), &response(b"right")).unwrap();
+        let input = saved(&store, "yubikey");
+
+        let header = Header::read(&mut &input.expose()[..]).unwrap();
+        assert!(!header.needs_key_file());
+        assert_eq!(&header.yubikey_challenge().unwrap()[..], &challenge[..]);
+
+        match PasswordStore::from_input(master(), input.clone()) {
+            Err(PasswordError::YubiKeyRequiredError) => {}
+            other => panic!("{:?}", other.err()),
+        }
+        match PasswordStore::from_input_with_factors(master(), input.clone(), &response(b"wrong")) {
+            Err(PasswordError::WrongMasterPasswordError) => {}
+            other => panic!("{:?}", other.err()),
+        }
+        let store =
+            PasswordStore::from_input_with_factors(master(), input, &response(b"right")).unwrap();
+        assert!(store.yubikey().is_some());
+    }
+
+    fn youtube() -> Password {
+        Password::new("YouTube".to_owned(), "me".to_owned(), SafeString::new("xxx".to_owned()))
+    }
+
+    #[test]
+    fn argon2_files_round_trip() {
+        let master = || SafeString::new("master".to_owned());
+        let mut store = PasswordStore::new(master()).unwrap();
+        store.add_password(youtube()).unwrap();
+        let input = saved(&store, "argon2");
+        assert_eq!(&input.expose()[..4], &[0, 0, 0, 4]);
+
+        let store = PasswordStore::from_input(master(), input).unwrap();
+        assert_eq!(store.kdf(), Kdf::Argon2id(v3::DEFAULT_PARAMS));
+        assert_eq!(store.get_password("YouTube").unwrap().password.expose(), "xxx");
+    }
+
+    #[test]
+    fn argon2_files_refuse_wrong_passwords() {
+        let store = PasswordStore::new(SafeString::new("master".to_owned())).unwrap();
+        let input = saved(&store, "argon2-wrong");
+
+        match PasswordStore::from_input(SafeString::new("wrong".to_owned()), input) {
+            Err(PasswordError::WrongMasterPasswordError) => {}
+            other => panic!("{:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn argon2_files_detect_tampering() {
+        let master = || SafeString::new("master".to_owned());
+        let mut store = PasswordStore::new(master()).unwrap();
+        store.add_password(youtube()).unwrap();
+        let input = saved(&store, "argon2-tamper");
+        let tampered = |offset: usize, value: u8| {
+            let mut bytes = input.clone();
+            bytes.inner_mut()[offset] ^= value;
+            PasswordStore::from_input(master(), bytes)
+        };
+
+        // Version, Argon2id params, salt, flags, key check at 49, IV at 81, signature at 97,
+        // then the encrypted passwords at 161.
+        for &offset in &[81, 97, 160, 161, input.expose().len() - 1] {
+            match tampered(offset, 1) {
+                Err(PasswordError::CorruptionError) => {}
+                other => panic!("offset {}: {:?}", offset, other.err()),
+            }
+        }
+        // Asking for too much memory is refused before the key derivation.
+        match tampered(4, 0xff) {
+            Err(PasswordError::CorruptionError) => {}
+            other => panic!("{:?}", other.err()),
+        }
+        // The key can't be told from a wrong one when what it is derived from was changed.
+        for &offset in &[11, 20, 50] {
+            match tampered(offset, 1) {
+                Err(PasswordError::WrongMasterPasswordError) => {}
+                other => panic!("offset {}: {:?}", offset, other.err()),
+            }
+        }
+    }
+
+    #[test]
+    fn truncated_files_are_corrupted() {
+        let master = || SafeString::new("master".to_owned());
+        let input = saved(&PasswordStore::new(master()).unwrap(), "truncated");
+
+        for &len in &[0, 3, 40, 96, 120, 161, input.expose().len() - 1] {
+            let truncated = SafeVec::new(input.expose()[..len].to_vec());
+            match PasswordStore::from_input(master(), truncated) {
+                Err(PasswordError::CorruptionError) => {}
+                other => panic!("length {}: {:?}", len, other.err()),
+            }
+        }
+    }
+
+    #[test]
+    fn scrypt_files_only_detect_some_tampering() {
+        let master = || SafeString::new("master".to_owned());
+        let mut store = scrypt_store("master", &SecondFactors::default());
+        store.add_password(youtube()).unwrap();
+        let input = saved(&store, "scrypt-tamper");
+        let tampered = |offset: usize| {
+            let mut bytes = input.clone();
+            bytes.inner_mut()[offset] ^= 1;
+            PasswordStore::from_input(master(), bytes)
+        };
+
+        // Version, scrypt params, salt, IV at 45, signature at 61, then the encrypted passwords
+        // at 125. Without a key check, only a signature that doesn't match passwords that
+        // decrypt tells that the file is corrupted.
+        for &offset in &[61, 124] {
+            match tampered(offset) {
+                Err(PasswordError::CorruptionError) => {}
+                other => panic!("offset {}: {:?}", offset, other.err()),
+            }
+        }
+        // Encrypted passwords that don't decrypt look like a wrong master password.
+        match tampered(input.expose().len() - 1) {
+            Err(PasswordError::DecryptionError) => {}
+            other => panic!("{:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn truncated_scrypt_files_are_corrupted_up_to_the_passwords() {
+        let master = || SafeString::new("master".to_owned());
+        let input = saved(&scrypt_store("master", &SecondFactors::default()), "scrypt-truncated");
+
+        for &len in &[0, 3, 12, 40, 60, 124] {
+            let truncated = SafeVec::new(input.expose()[..len].to_vec());
+            match PasswordStore::from_input(master(), truncated) {
+                Err(PasswordError::CorruptionError) => {}
+                other => panic!("length {}: {:?}", len, other.err()),
+            }
+        }
+        // Without a key check, passwords cut short look like a wrong master password.
+        let truncated = SafeVec::new(input.expose()[..input.expose().len() - 1].to_vec());
+        match PasswordStore::from_input(master(), truncated) {
+            Err(PasswordError::DecryptionError) => {}
+            other => panic!("{:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn scrypt_files_are_upgraded_to_argon2() {
+        let master = || SafeString::new("master".to_owned());
+        let mut store = scrypt_store("master", &with_key_file(b"usb stick"));
+        store.add_password(youtube()).unwrap();
+        let input = saved(&store, "upgrade");
+        let mut store = PasswordStore::from_input_with_factors(master(),
+                                                               input,
+                                                               &with_key_file(b"usb stick"))
+            .unwrap();
+        assert_eq!(store.kdf(), DEFAULT_SCRYPT);
+        assert!(!store.is_modified());
+
+        store.upgrade_key_derivation("master");
+        assert!(store.is_modified());
+        let input = saved(&store, "upgrade");
+        assert_eq!(&input.expose()[..4], &[0, 0, 0, 4]);
+
+        match PasswordStore::from_input(master(), input.clone()) {
+            Err(PasswordError::KeyFileRequiredError) => {}
+            other => panic!("{:?}", other.err()),
+        }
+        let store = PasswordStore::from_input_with_factors(master(),
+                                                           input,
+                                                           &with_key_file(b"usb stick"))
+            .unwrap();
+        assert_eq!(store.kdf(), Kdf::Argon2id(v3::DEFAULT_PARAMS));
+        assert_eq!(store.get_password("YouTube").unwrap().password.expose(), "xxx");
+    }
+
+    #[test]
+    fn files_keep_their_scrypt_params() {
+        let master = || SafeString::new("master".to_owned());
+        let mut store = PasswordStore::new(master()).unwrap();
+        store.add_password(youtube()).unwrap();
+        let kdf = Kdf::Scrypt { log2_n: 13, r: 4, p: 2 };
+        match store.change_key_derivation("wrong", kdf) {
+            Err(PasswordError::WrongMasterPasswordError) => {}
+            other => panic!("{:?}", other.err()),
+        }
+        assert_eq!(store.kdf(), Kdf::Argon2id(v3::DEFAULT_PARAMS));
+        store.change_key_derivation("master", kdf).unwrap();
+
+        let input = saved(&store, "scrypt-params");
+        assert_eq!(&input.expose()[..13], &[0, 0, 0, 2, 13, 0, 0, 0, 4, 0, 0, 0, 2]);
+        let store = PasswordStore::from_input(master(), input.clone()).unwrap();
+        assert_eq!(store.kdf(), kdf);
+        assert_eq!(store.get_password("YouTube").unwrap().password.expose(), "xxx");
+
+        // A header asking for absurd params is corrupted, rather than opened for minutes.
+        let mut bytes = input.clone();
+        bytes.inner_mut()[4] = 40;
+        match PasswordStore::from_input(master(), bytes) {
+            Err(PasswordError::CorruptionError) => {}
+            other => panic!("{:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn scrypt_params_are_checked() {
+        assert!(super::are_valid_scrypt_params(12, 8, 1));
+        assert!(super::are_valid_scrypt_params(24, 8, 16));
+        assert!(!super::are_valid_scrypt_params(25, 8, 1));
+        assert!(!super::are_valid_scrypt_params(12, 8, 17));
+        assert!(!super::are_valid_scrypt_params(0, 8, 1));
+        assert!(!super::are_valid_scrypt_params(12, 0, 1));
+        assert!(!super::are_valid_scrypt_params(16, 1, 1));
+        assert_eq!(super::scrypt_memory(12, 8), 4 * 1024 * 1024);
+    }
+
+    #[test]
+    fn rekeying_changes_the_salt_and_the_key() {
+        let master = || SafeString::new("master".to_owned());
+        let mut store = PasswordStore::new_with_factors(master(), &with_key_file(b"usb stick"))
+            .unwrap();
+        store.add_password(youtube()).unwrap();
+        let before = saved(&store, "rekey");
+        let key = store.key.clone();
+
+        match store.rekey("wrong", &with_key_file(b"usb stick")) {
+            Err(PasswordError::WrongMasterPasswordError) => {}
+            other => panic!("{:?}", other.err()),
+        }
+        match store.rekey("master", &with_key_file(b"usb stick!")) {
+            Err(PasswordError::WrongKeyFileError) => {}
+            other => panic!("{:?}", other.err()),
+        }
+        match store.rekey("master", &SecondFactors::default()) {
+            Err(PasswordError::KeyFileRequiredError) => {}
+            other => panic!("{:?}", other.err()),
+        }
+        assert_eq!(store.key, key);
+
+        store.rekey("master", &with_key_file(b"usb stick")).unwrap();
+        assert!(store.key != key);
+        let after = saved(&store, "rekey");
+        // Version, Argon2id params, then the salt.
+        assert!(before.expose()[16..48] != after.expose()[16..48]);
+
+        let store = PasswordStore::from_input_with_factors(master(),
+                                                           after,
+                                                           &with_key_file(b"usb stick"))
+            .unwrap();
+        assert_eq!(store.get_password("YouTube").unwrap().password.expose(), "xxx");
+    }
+
+    #[test]
+    fn serialization_is_deterministic() {
+        let master = || SafeString::new("master".to_owned());
+        let password = |name: &str| {
+            Password::new(name.to_owned(), "me".to_owned(), SafeString::new("x".to_owned()))
+        };
+        let mut store = PasswordStore::new(master()).unwrap();
+        let mut reversed = PasswordStore::new(master()).unwrap();
+        let names = ["youtube", "GitHub", "Bank", "github.io", "apple"];
+        for name in names.iter() {
+            store.add_password(password(name)).unwrap();
+        }
+        for name in names.iter().rev() {
+            reversed.add_password(password(name)).unwrap();
+        }
+
+        let json = serde_json::to_string(&store.schema).unwrap();
+        assert_eq!(json, serde_json::to_string(&reversed.schema).unwrap());
+        let mut found: Vec<(usize, &str)> = names.iter()
+            .map(|name| (json.find(&format!("\"name\":\"{}\"", name)).unwrap(), *name))
+            .collect();
+        found.sort();
+        let order: Vec<&str> = found.into_iter().map(|(_, name)| name).collect();
+        assert_eq!(order, vec!["apple", "Bank", "GitHub", "github.io", "youtube"]);
+
+        // Saving and opening the file again gives the same JSON, byte for byte.
+        let schema: Schema = serde_json::from_str(&json).unwrap();
+        assert_eq!(serde_json::to_string(&schema).unwrap(), json);
+        let store = PasswordStore::from_input(master(), saved(&store, "deterministic")).unwrap();
+        assert_eq!(serde_json::to_string(&store.schema).unwrap(), json);
+    }
+
+    #[test]
+    fn password_with_multiline_notes_round_trip() {
+        let json = "{\"name\":\"YouTube\",\"username\":\"me\",\"password\":\"xxx\",\
+                    \"created_at\":1,\"updated_at\":2,\"notes\":\"first\\nsecond\"}";
+        let p: Password = serde_json::from_str(json).unwrap();
+        assert_eq!(p.notes.as_ref().map(|n| n.expose()), Some("first\nsecond"));
+        assert_eq!(serde_json::to_string(&p).unwrap(), json);
     }
 }
@@ -0,0 +1,149 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! The key derivation of version 4 password files, which use Argon2id instead of scrypt.
+//!
+//! The rest of the format is the one of `v2`, which reads and writes both: see
+//! `v2::PasswordStore`. Version 3 was already taken by files that need second factors, so
+//! files of this format have version 4, and always have the flags of the second factors.
+
+use argon2;
+use safe_vec::SafeVec;
+use byteorder::{ReadBytesExt, WriteBytesExt, BigEndian};
+
+use super::PasswordError;
+use std::io::{Result as IoResult, Read, Write};
+
+/// The version of password files whose key is derived with Argon2id
+pub const VERSION: u32 = 4;
+
+/// Argon2id parameters of new password files: 64 MiB of memory, 3 passes over it, 4 lanes.
+pub const DEFAULT_PARAMS: Argon2Params = Argon2Params {
+    memory_kib: 64 * 1024,
+    iterations: 3,
+    parallelism: 4,
+};
+
+/// The most memory a password file may ask for, so that a tampered header can't make Rooster
+/// use all of it before the signature is checked.
+pub const MAX_MEMORY_KIB: u32 = 4 * 1024 * 1024;
+
+/// The most passes and lanes a password file may ask for, for the same reason.
+const MAX_ITERATIONS: u32 = 1024;
+const MAX_PARALLELISM: u32 = 64;
+
+/// How much memory and time it takes to derive the key, saved in the header of the file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Argon2Params {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl Argon2Params {
+    /// Reads the parameters, and refuses the ones Argon2 would reject or that are too costly.
+    pub fn read<R: Read>(reader: &mut R) -> Result<Argon2Params, PasswordError> {
+        let params = Argon2Params {
+            memory_kib: reader.read_u32::<BigEndian>()?,
+            iterations: reader.read_u32::<BigEndian>()?,
+            parallelism: reader.read_u32::<BigEndian>()?,
+        };
+        if !params.is_valid() {
+            return Err(PasswordError::CorruptionError);
+        }
+        Ok(params)
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> IoResult<()> {
+        writer.write_u32::<BigEndian>(self.memory_kib)?;
+        writer.write_u32::<BigEndian>(self.iterations)?;
+        writer.write_u32::<BigEndian>(self.parallelism)?;
+        Ok(())
+    }
+
+    /// Whether Argon2 accepts the parameters, and they are not more costly than the maximums.
+    pub fn is_valid(&self) -> bool {
+        self.parallelism >= 1 && self.parallelism <= MAX_PARALLELISM &&
+        self.iterations >= 1 && self.iterations <= MAX_ITERATIONS &&
+        self.memory_kib >= 8 * self.parallelism && self.memory_kib <= MAX_MEMORY_KIB
+    }
+}
+
+/// Derives a key of `key_len` bytes with Argon2id. The parameters must have been checked by
+/// `Argon2Params::read`, or be `DEFAULT_PARAMS`.
+pub fn derive_key(params: &Argon2Params, input: &[u8], salt: &[u8], key_len: usize) -> SafeVec {
+    let config = argon2::Config {
+        variant: argon2::Variant::Argon2id,
+        version: argon2::Version::Version13,
+        mem_cost: params.memory_kib,
+        time_cost: params.iterations,
+        lanes: params.parallelism,
+        thread_mode: argon2::ThreadMode::from_threads(params.parallelism),
+        secret: &[],
+        ad: &[],
+        hash_length: key_len as u32,
+    };
+    SafeVec::new(argon2::hash_raw(input, salt, &config).expect("invalid Argon2 parameters"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Argon2Params, DEFAULT_PARAMS, derive_key};
+    use std::io::Cursor;
+
+    #[test]
+    fn params_round_trip() {
+        let mut bytes = Vec::new();
+        DEFAULT_PARAMS.write(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), 12);
+        let read = Argon2Params::read(&mut Cursor::new(bytes)).unwrap();
+        assert_eq!(read, DEFAULT_PARAMS);
+    }
+
+    #[test]
+    fn refuses_params_argon2_would_reject_or_too_costly() {
+        for &(memory_kib, iterations, parallelism) in &[(64 * 1024, 0, 1),
+                                                        (64 * 1024, 1, 0),
+                                                        (7, 1, 1),
+                                                        (u32::max_value(), 1, 1),
+                                                        (64 * 1024, u32::max_value(), 1)] {
+            let mut bytes = Vec::new();
+            Argon2Params {
+                    memory_kib: memory_kib,
+                    iterations: iterations,
+                    parallelism: parallelism,
+                }
+                .write(&mut bytes)
+                .unwrap();
+            assert!(Argon2Params::read(&mut Cursor::new(bytes)).is_err());
+        }
+    }
+
+    #[test]
+    fn derive_key_depends_on_the_input_and_the_params() {
+        let params = Argon2Params {
+            memory_kib: 64,
+            iterations: 1,
+            parallelism: 1,
+        };
+        let salt = [7u8; 32];
+        let key = derive_key(&params, b"master", &salt, 32);
+        assert_eq!(key.expose().len(), 32);
+        assert_eq!(key, derive_key(&params, b"master", &salt, 32));
+        assert!(key != derive_key(&params, b"other", &salt, 32));
+        let more_passes = Argon2Params { iterations: 2, ..params };
+        assert!(key != derive_key(&more_passes, b"master", &salt, 32));
+    }
+}
@@ -0,0 +1,200 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! Profiles give names to password files, so that `rooster --profile work get vpn` opens the
+//! work one. They are kept in ~/.config/rooster/profiles.toml, one `name = "path"` per line.
+
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+
+const PROFILES_FILE: &'static str = "profiles.toml";
+
+#[derive(Debug, PartialEq)]
+pub struct Profiles {
+    entries: Vec<(String, String)>,
+}
+
+/// Where profiles are kept: $XDG_CONFIG_HOME/rooster/profiles.toml, or
+/// ~/.config/rooster/profiles.toml.
+pub fn profiles_path() -> Option<PathBuf> {
+    let config_dir = match env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => env::home_dir()?.join(".config"),
+    };
+    Some(config_dir.join("rooster").join(PROFILES_FILE))
+}
+
+/// Profile names are bare TOML keys, which keeps the file simple to read and to write.
+pub fn check_name(name: &str) -> Result<(), String> {
+    let valid = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+    if name.is_empty() || !name.chars().all(valid) {
+        return Err(format!("\"{}\" can't be the name of a profile, use letters, digits, - and _",
+                           name));
+    }
+    Ok(())
+}
+
+fn parse_string(value: &str) -> Option<String> {
+    if value.len() < 2 || !value.starts_with('"') || !value.ends_with('"') {
+        return None;
+    }
+    let mut string = String::new();
+    let mut chars = value[1..value.len() - 1].chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                match chars.next() {
+                    Some('\\') => string.push('\\'),
+                    Some('"') => string.push('"'),
+                    _ => return None,
+                }
+            }
+            '"' => return None,
+            c => string.push(c),
+        }
+    }
+    Some(string)
+}
+
+fn quote_string(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+impl Profiles {
+    pub fn new() -> Profiles {
+        Profiles { entries: Vec::new() }
+    }
+
+    /// Reads lines like `work = "/home/me/work/.passwords.rooster"`. Comments start with #.
+    pub fn parse(contents: &str) -> Result<Profiles, String> {
+        let mut profiles = Profiles::new();
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || format!("line {} should look like name = \"path\"", i + 1);
+            let equals = line.find('=').ok_or_else(&invalid)?;
+            let name = line[..equals].trim();
+            check_name(name).map_err(|_| invalid())?;
+            let path = parse_string(line[equals + 1..].trim()).ok_or_else(&invalid)?;
+            profiles.set(name, &path);
+        }
+        Ok(profiles)
+    }
+
+    pub fn to_toml(&self) -> String {
+        let mut toml = String::from("# Password files by profile, for rooster --profile <name>.\n");
+        for &(ref name, ref path) in self.entries.iter() {
+            toml.push_str(&format!("{} = {}\n", name, quote_string(path)));
+        }
+        toml
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.entries.iter().find(|entry| entry.0 == name).map(|entry| entry.1.as_str())
+    }
+
+    pub fn set(&mut self, name: &str, path: &str) {
+        match self.entries.iter_mut().find(|entry| entry.0 == name) {
+            Some(entry) => entry.1 = path.to_owned(),
+            None => self.entries.push((name.to_owned(), path.to_owned())),
+        }
+    }
+
+    /// Returns whether there was a profile with this name.
+    pub fn remove(&mut self, name: &str) -> bool {
+        let len = self.entries.len();
+        self.entries.retain(|entry| entry.0 != name);
+        self.entries.len() != len
+    }
+
+    pub fn entries(&self) -> &[(String, String)] {
+        &self.entries
+    }
+}
+
+/// Reads the profiles. There are none until the first one is added.
+pub fn load() -> Result<Profiles, String> {
+    let path = profiles_path().ok_or("I could not find your home directory".to_owned())?;
+    let mut contents = String::new();
+    match File::open(&path).and_then(|mut file| file.read_to_string(&mut contents)) {
+        Ok(_) => {}
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(Profiles::new()),
+        Err(err) => {
+            return Err(format!("I couldn't read {} (reason: {})", path.display(), err));
+        }
+    }
+    Profiles::parse(&contents).map_err(|err| format!("in {}, {}", path.display(), err))
+}
+
+pub fn save(profiles: &Profiles) -> Result<(), String> {
+    let path = profiles_path().ok_or("I could not find your home directory".to_owned())?;
+    path.parent()
+        .map_or(Ok(()), |dir| fs::create_dir_all(dir))
+        .and_then(|_| File::create(&path))
+        .and_then(|mut file| file.write_all(profiles.to_toml().as_bytes()))
+        .map_err(|err| format!("I couldn't write {} (reason: {})", path.display(), err))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{check_name, Profiles};
+
+    #[test]
+    fn profiles_are_read_back() {
+        let mut profiles = Profiles::new();
+        profiles.set("work", "/home/me/work/.passwords.rooster");
+        profiles.set("odd", "C:\\Users\\me \"quoted\".rooster");
+        profiles.set("work", "/home/me/job/.passwords.rooster");
+        assert_eq!(Profiles::parse(&profiles.to_toml()), Ok(profiles));
+    }
+
+    #[test]
+    fn comments_and_spaces_are_allowed() {
+        let profiles = Profiles::parse("# Mine\n\n  home =\"/home/me/.p\"  \nwork= \"/w\"\n")
+            .unwrap();
+        assert_eq!(profiles.get("home"), Some("/home/me/.p"));
+        assert_eq!(profiles.get("work"), Some("/w"));
+        assert_eq!(profiles.get("play"), None);
+    }
+
+    #[test]
+    fn wrong_lines_are_errors() {
+        assert!(Profiles::parse("work /w").is_err());
+        assert!(Profiles::parse("work = /w").is_err());
+        assert!(Profiles::parse("my work = \"/w\"").is_err());
+        assert!(Profiles::parse("work = \"/w\\n\"").is_err());
+    }
+
+    #[test]
+    fn removing_tells_if_there_was_a_profile() {
+        let mut profiles = Profiles::new();
+        profiles.set("work", "/w");
+        assert!(profiles.remove("work"));
+        assert!(!profiles.remove("work"));
+        assert!(profiles.entries().is_empty());
+    }
+
+    #[test]
+    fn names_are_bare_keys() {
+        assert!(check_name("work-2_old").is_ok());
+        assert!(check_name("").is_err());
+        assert!(check_name("my work").is_err());
+        assert!(check_name("w=x").is_err());
+    }
+}
@@ -0,0 +1,30 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! With --quiet, Rooster only prints errors and what was asked for, like a password with --show.
+//! Scripts can then rely on the exit code alone.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+pub fn set_enabled(enabled: bool) {
+    QUIET.store(enabled, Ordering::SeqCst);
+}
+
+/// Checked by the output macros, so that commands don't need to know about --quiet.
+pub fn is_enabled() -> bool {
+    QUIET.load(Ordering::SeqCst)
+}
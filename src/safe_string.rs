@@ -13,50 +13,136 @@
 // limitations under the License.
 
 use std::fmt;
-use std::ops::Drop;
-use std::ops::Deref;
+use std::ops::{Deref, DerefMut, Drop};
+use memory::{self, LockedRegion};
 use serde::ser::{Serialize, Serializer};
 use serde::de::{Deserialize, Deserializer, Visitor, Error};
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+/// A string holding a secret, wiped when dropped.
+///
+/// It can't be printed: `Display` isn't implemented and `Debug` only shows the length. The
+/// contents are only reachable through `expose` (and `inner_mut` to write them), so that every
+/// place a secret goes out can be found with grep.
+///
+/// The buffer is locked in memory, so that it isn't written to swap (see `memory`).
 pub struct SafeString {
-    pub inner: String,
+    inner: String,
+    region: LockedRegion,
 }
 
-struct StringVisitor;
+/// The contents of a `SafeString`, borrowed to be changed. If the buffer grew and moved, the
+/// lock moves with it when this is dropped.
+pub struct SafeStringMut<'a> {
+    inner: &'a mut String,
+    region: &'a mut LockedRegion,
+}
+
+/// Reads strings into a `SafeString`. Owned strings are taken as they are, without a copy.
+/// Borrowed strings come from the input, which the caller keeps in a `SafeVec`, or, when they
+/// had escapes, from a scratch buffer of serde_json that it frees as a plain `Vec`. Only the
+/// global allocator can wipe that one, see `memory`.
+struct SafeStringVisitor;
 
+impl<'de> Visitor<'de> for SafeStringVisitor {
+    type Value = SafeString;
 
-impl<'de> Visitor<'de> for StringVisitor {
     fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
         formatter.write_str("a string")
     }
-    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E> where
+
+    fn visit_str<E>(self, v: &str) -> Result<SafeString, E> where
         E: Error, {
-        Ok(String::from(v))
+        Ok(SafeString::new(v.to_owned()))
+    }
+
+    /// The default would copy the string and drop the original without wiping it.
+    fn visit_string<E>(self, v: String) -> Result<SafeString, E> where
+        E: Error, {
+        Ok(SafeString::new(v))
     }
-    type Value = String;
 }
 
 impl SafeString {
     pub fn new(inner: String) -> SafeString {
-        SafeString { inner: inner }
+        let region = LockedRegion::new(inner.as_ptr(), inner.capacity());
+        SafeString {
+            inner: inner,
+            region: region,
+        }
+    }
+
+    /// The secret itself. Callers should make sure it only goes where the user asked for it.
+    pub fn expose(&self) -> &str {
+        &self.inner
+    }
+
+    /// For filling the string in place, like `read_to_string(&mut safe.inner_mut())`.
+    pub fn inner_mut<'a>(&'a mut self) -> SafeStringMut<'a> {
+        SafeStringMut {
+            inner: &mut self.inner,
+            region: &mut self.region,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Overwrites the contents with zeros right away, without waiting for the drop.
+    pub fn wipe(&mut self) {
+        // Zero bytes are valid UTF-8, so the string stays valid.
+        unsafe {
+            memory::zero(self.inner.as_mut_ptr(), self.inner.len());
+        }
     }
 }
 
+impl Clone for SafeString {
+    fn clone(&self) -> SafeString {
+        SafeString::new(self.inner.clone())
+    }
+}
+
+impl PartialEq for SafeString {
+    fn eq(&self, other: &SafeString) -> bool {
+        self.inner == other.inner
+    }
+}
+
+impl Eq for SafeString {}
+
 impl Drop for SafeString {
     fn drop(&mut self) {
-        self.inner.clear();
-        for _ in 0..self.inner.capacity() {
-            self.inner.push('0');
+        // The whole capacity, as the contents may have been shorter once.
+        unsafe {
+            memory::zero(self.inner.as_mut_ptr(), self.inner.capacity());
         }
     }
 }
 
-impl Deref for SafeString {
-    type Target = str;
+impl<'a> Deref for SafeStringMut<'a> {
+    type Target = String;
+
+    fn deref(&self) -> &String {
+        self.inner
+    }
+}
 
-    fn deref(&self) -> &str {
-        self.inner.deref()
+impl<'a> DerefMut for SafeStringMut<'a> {
+    fn deref_mut(&mut self) -> &mut String {
+        self.inner
+    }
+}
+
+impl<'a> Drop for SafeStringMut<'a> {
+    fn drop(&mut self) {
+        self.region.relock(self.inner.as_ptr(), self.inner.capacity());
+    }
+}
+
+impl fmt::Debug for SafeString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SafeString(***, len={})", self.inner.len())
     }
 }
 
@@ -64,14 +150,14 @@ impl Serialize for SafeString {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
         where S: Serializer
     {
-        serializer.serialize_str(self.deref())
+        serializer.serialize_str(&self.inner)
     }
 }
 
 impl<'de> Deserialize<'de> for SafeString {
     fn deserialize<D>(deserializer: D) -> Result<SafeString, D::Error> where
         D: Deserializer<'de> {
-        deserializer.deserialize_string(StringVisitor).map(|parsed_value| SafeString { inner : parsed_value })
+        deserializer.deserialize_str(SafeStringVisitor)
     }
 }
 
@@ -80,12 +166,12 @@ mod test {
     use super::super::serde_json;
     use super::SafeString;
     use super::super::serde_json::Error;
+    use memory::test::{freed_zeroed, leaves_behind};
+    use std::mem;
 
     #[test]
     fn safe_string_serialization() {
-        let s = SafeString {
-          inner : String::from("blabla")
-        };
+        let s = SafeString::new(String::from("blabla"));
 
         match serde_json::to_string(&s) {
             Ok(json) => assert_eq!("\"blabla\"", json),
@@ -99,9 +185,7 @@ mod test {
     #[test]
     fn safe_string_within_struct_serialization() {
         let ts = TestStruct {
-            password : SafeString {
-                inner: String::from("blabla")
-            }
+            password : SafeString::new(String::from("blabla"))
         };
 
         match serde_json::to_string(&ts) {
@@ -117,7 +201,7 @@ mod test {
         let res : Result<SafeString, Error> = serde_json::from_str(s);
 
         match res {
-            Ok(ss) => assert_eq!(ss, SafeString { inner: String::from("blabla") }),
+            Ok(ss) => assert_eq!(ss, SafeString::new(String::from("blabla"))),
             Err(_) => panic!("Deserialization failed")
         }
     }
@@ -127,8 +211,56 @@ mod test {
         let json = "{\"password\":\"blabla\"}";
         let res : Result<TestStruct, Error> = serde_json::from_str(json);
         match res {
-            Ok(ts) => assert_eq!(ts, TestStruct{ password : SafeString { inner : String::from("blabla")}}),
+            Ok(ts) => assert_eq!(ts, TestStruct{ password : SafeString::new(String::from("blabla"))}),
             Err(_) => panic!("Deserialization failed")
         }
     }
+
+    #[test]
+    fn safe_string_round_trip_with_escapes() {
+        let s = SafeString::new("bla\"bla\\\n\u{e9}".to_owned());
+        let json = serde_json::to_string(&s).unwrap();
+        assert_eq!(json, "\"bla\\\"bla\\\\\\n\u{e9}\"");
+        assert_eq!(serde_json::from_str::<SafeString>(&json).unwrap(), s);
+    }
+
+    #[test]
+    fn unescaped_strings_are_not_left_behind() {
+        let json = "{\"password\":\"p4ss\\\"w0rd\"}";
+        assert!(!leaves_behind(b"p4ss\"w0rd", || {
+            let ts: TestStruct = serde_json::from_str(json).unwrap();
+            assert_eq!(ts.password.expose(), "p4ss\"w0rd");
+        }));
+    }
+
+    #[test]
+    fn owned_strings_are_taken_not_copied() {
+        let value = serde_json::Value::String("blabla".to_owned());
+        let ptr = value.as_str().unwrap().as_ptr();
+        let s: SafeString = serde_json::from_value(value).unwrap();
+        assert_eq!(s.expose().as_ptr(), ptr);
+        assert_eq!(s.expose(), "blabla");
+    }
+
+    #[test]
+    fn safe_string_debug_is_redacted() {
+        let s = SafeString::new("blabla".to_owned());
+        assert_eq!(format!("{:?}", s), "SafeString(***, len=6)");
+        assert_eq!(format!("{:?}", Some(s)), "Some(SafeString(***, len=6))");
+    }
+
+    #[test]
+    fn safe_string_is_zeroed_when_dropped() {
+        let s = SafeString::new("blabla".to_owned());
+        let ptr = s.expose().as_ptr();
+        assert_eq!(freed_zeroed(ptr, || mem::drop(s)), Some(true));
+    }
+
+    #[test]
+    fn safe_string_growth_leaves_no_copy_behind() {
+        let mut s = SafeString::new("blabla".to_owned());
+        let ptr = s.expose().as_ptr();
+        assert_eq!(freed_zeroed(ptr, || s.inner_mut().push_str(&"bla".repeat(100))), Some(true));
+        assert_eq!(s.expose().len(), 306);
+    }
 }
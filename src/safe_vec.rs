@@ -12,44 +12,151 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::ops::Drop;
-use std::ops::Deref;
-use std::ops::DerefMut;
+use std::fmt;
+use std::ops::{Deref, DerefMut, Drop};
+use memory::{self, LockedRegion};
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+/// Bytes holding a secret, like a key, wiped when dropped. Like `SafeString`, the contents are
+/// only reachable through `expose` and `inner_mut`, and `Debug` only shows the length.
+///
+/// The buffer is locked in memory, so that it isn't written to swap (see `memory`).
 pub struct SafeVec {
-    pub inner: Vec<u8>,
+    inner: Vec<u8>,
+    region: LockedRegion,
+}
+
+/// The contents of a `SafeVec`, borrowed to be changed. If the buffer grew and moved, the lock
+/// moves with it when this is dropped.
+pub struct SafeVecMut<'a> {
+    inner: &'a mut Vec<u8>,
+    region: &'a mut LockedRegion,
 }
 
 impl SafeVec {
     pub fn new(inner: Vec<u8>) -> SafeVec {
-        SafeVec { inner: inner }
+        let region = LockedRegion::new(inner.as_ptr(), inner.capacity());
+        SafeVec {
+            inner: inner,
+            region: region,
+        }
+    }
+
+    /// The secret itself. Callers should make sure it only goes where it's needed.
+    pub fn expose(&self) -> &[u8] {
+        &self.inner
+    }
+
+    pub fn inner_mut<'a>(&'a mut self) -> SafeVecMut<'a> {
+        SafeVecMut {
+            inner: &mut self.inner,
+            region: &mut self.region,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
     }
 
-    pub fn inner_mut(&mut self) -> &mut Vec<u8> {
-        &mut self.inner
+    /// Overwrites the contents with zeros right away, without waiting for the drop.
+    pub fn wipe(&mut self) {
+        unsafe {
+            memory::zero(self.inner.as_mut_ptr(), self.inner.len());
+        }
+    }
+
+    pub fn is_wiped(&self) -> bool {
+        self.inner.iter().all(|b| *b == 0u8)
+    }
+}
+
+impl Clone for SafeVec {
+    fn clone(&self) -> SafeVec {
+        SafeVec::new(self.inner.clone())
     }
 }
 
+impl PartialEq for SafeVec {
+    fn eq(&self, other: &SafeVec) -> bool {
+        self.inner == other.inner
+    }
+}
+
+impl Eq for SafeVec {}
+
 impl Drop for SafeVec {
     fn drop(&mut self) {
-        self.inner.clear();
-        for _ in 0..self.inner.capacity() {
-            self.inner.push(0u8);
+        // The whole capacity, as the contents may have been shorter once.
+        unsafe {
+            memory::zero(self.inner.as_mut_ptr(), self.inner.capacity());
         }
     }
 }
 
-impl Deref for SafeVec {
-    type Target = [u8];
+impl<'a> Deref for SafeVecMut<'a> {
+    type Target = Vec<u8>;
 
-    fn deref(&self) -> &[u8] {
-        self.inner.deref()
+    fn deref(&self) -> &Vec<u8> {
+        self.inner
     }
 }
 
-impl DerefMut for SafeVec {
-    fn deref_mut(&mut self) -> &mut [u8] {
-        self.inner.deref_mut()
+impl<'a> DerefMut for SafeVecMut<'a> {
+    fn deref_mut(&mut self) -> &mut Vec<u8> {
+        self.inner
+    }
+}
+
+impl<'a> Drop for SafeVecMut<'a> {
+    fn drop(&mut self) {
+        self.region.relock(self.inner.as_ptr(), self.inner.capacity());
+    }
+}
+
+impl fmt::Debug for SafeVec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SafeVec(***, len={})", self.inner.len())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SafeVec;
+    use memory::test::freed_zeroed;
+    use std::mem;
+
+    #[test]
+    fn safe_vec_wipe() {
+        let sentinel = 0xa5u8;
+        let mut v = SafeVec::new(vec![sentinel; 32]);
+        assert!(!v.is_wiped());
+
+        v.wipe();
+
+        assert_eq!(v.expose().len(), 32);
+        assert!(v.expose().iter().all(|b| *b != sentinel));
+        assert!(v.is_wiped());
+    }
+
+    #[test]
+    fn safe_vec_debug_is_redacted() {
+        assert_eq!(format!("{:?}", SafeVec::new(vec![1, 2, 3])), "SafeVec(***, len=3)");
+    }
+
+    #[test]
+    fn safe_vec_is_zeroed_when_dropped() {
+        let mut v = SafeVec::new(vec![0xa5u8; 32]);
+        v.inner_mut().truncate(16);
+        let ptr = v.expose().as_ptr();
+        assert_eq!(freed_zeroed(ptr, || mem::drop(v)), Some(true));
+    }
+
+    #[test]
+    fn safe_vec_growth_leaves_no_copy_behind() {
+        let mut v = SafeVec::new(vec![0xa5u8; 32]);
+        v.inner_mut().shrink_to_fit();
+        let ptr = v.expose().as_ptr();
+        assert_eq!(freed_zeroed(ptr, || v.inner_mut().extend_from_slice(&[0xa5u8; 1024])),
+                   Some(true));
+        assert_eq!(v.expose().len(), 1056);
     }
 }
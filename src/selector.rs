@@ -0,0 +1,396 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! Picks a password among several with the arrow keys, typing to narrow the list down.
+//!
+//! This needs a terminal on both stdin and stderr. When there isn't one, `choose` returns `None`
+//! and the numbered prompt of `list` is used instead.
+
+use answer;
+use color::Color;
+use sys;
+use glyphs::{self, Glyph};
+use password::v2::{match_score, words_score, Password};
+use std::env;
+use std::io::{stderr, Write};
+
+/// How many passwords are shown at once, the others are reached by scrolling.
+const MAX_ROWS: usize = 10;
+
+/// How long to wait for the rest of an escape sequence, to tell Esc from the arrow keys.
+const ESCAPE_TIMEOUT_MS: i32 = 50;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Key {
+    Up,
+    Down,
+    Enter,
+    Escape,
+    Backspace,
+    Char(char),
+    Other,
+}
+
+/// Decodes a key press, starting with its first byte. `next` returns the following bytes, or
+/// `None` if none come shortly after.
+fn decode_key<F>(first: u8, mut next: F) -> Key
+    where F: FnMut() -> Option<u8>
+{
+    match first {
+        b'\r' | b'\n' => Key::Enter,
+        // Ctrl-C and Ctrl-D, which can't stop Rooster in raw mode.
+        0x03 | 0x04 => Key::Escape,
+        // Ctrl-P and Ctrl-N, like in shells.
+        0x10 => Key::Up,
+        0x0e => Key::Down,
+        0x7f | 0x08 => Key::Backspace,
+        0x1b => {
+            match next() {
+                None => Key::Escape,
+                Some(b'[') | Some(b'O') => {
+                    match next() {
+                        Some(b'A') => Key::Up,
+                        Some(b'B') => Key::Down,
+                        _ => Key::Other,
+                    }
+                }
+                Some(_) => Key::Other,
+            }
+        }
+        byte if byte < 0x80 => {
+            match byte as char {
+                c if c.is_control() => Key::Other,
+                c => Key::Char(c),
+            }
+        }
+        byte => {
+            // The first byte of a UTF-8 character tells how many bytes follow.
+            let len = if byte >= 0xf0 {
+                4
+            } else if byte >= 0xe0 {
+                3
+            } else {
+                2
+            };
+            let mut bytes = vec![byte];
+            for _ in 1..len {
+                match next() {
+                    Some(byte) => bytes.push(byte),
+                    None => return Key::Other,
+                }
+            }
+            match String::from_utf8(bytes).ok().and_then(|s| s.chars().next()) {
+                Some(c) => Key::Char(c),
+                None => Key::Other,
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    Chosen(usize),
+    Aborted,
+}
+
+struct Selector<'a> {
+    passwords: &'a [&'a Password],
+    filter: String,
+    /// Indexes in `passwords` of those that match the filter, best first.
+    visible: Vec<usize>,
+    /// Index in `visible`.
+    highlighted: usize,
+}
+
+impl<'a> Selector<'a> {
+    fn new(passwords: &'a [&'a Password]) -> Selector<'a> {
+        let mut selector = Selector {
+            passwords: passwords,
+            filter: String::new(),
+            visible: Vec::new(),
+            highlighted: 0,
+        };
+        selector.refilter();
+        selector
+    }
+
+    /// Ranks the passwords like searches do: names first, then usernames, best matches first.
+    fn refilter(&mut self) {
+        let words: Vec<&str> = self.filter.split_whitespace().collect();
+        let mut scored: Vec<((bool, i64), usize)> = self.passwords
+            .iter()
+            .enumerate()
+            .filter_map(|(i, p)| words_score(&words, p, match_score).map(|score| (score, i)))
+            .collect();
+        // The sort is stable, so passwords that match equally well keep their order.
+        scored.sort_by(|&(a, _), &(b, _)| b.cmp(&a));
+        self.visible = scored.into_iter().map(|(_, i)| i).collect();
+        self.highlighted = 0;
+    }
+
+    fn handle(&mut self, key: Key) -> Option<Outcome> {
+        // j and k move until something is typed, type J or K to look for them.
+        let key = match key {
+            Key::Char('j') if self.filter.is_empty() => Key::Down,
+            Key::Char('k') if self.filter.is_empty() => Key::Up,
+            key => key,
+        };
+
+        match key {
+            Key::Up => self.highlighted = self.highlighted.saturating_sub(1),
+            Key::Down => {
+                if self.highlighted + 1 < self.visible.len() {
+                    self.highlighted += 1;
+                }
+            }
+            Key::Enter => {
+                if let Some(&index) = self.visible.get(self.highlighted) {
+                    return Some(Outcome::Chosen(index));
+                }
+            }
+            Key::Escape => return Some(Outcome::Aborted),
+            Key::Backspace => {
+                if self.filter.pop().is_some() {
+                    self.refilter();
+                }
+            }
+            Key::Char(c) => {
+                self.filter.push(c);
+                self.refilter();
+            }
+            Key::Other => {}
+        }
+        None
+    }
+
+    /// The lines to draw, none of them longer than `width` characters.
+    fn render(&self, prompt: &str, width: usize) -> Vec<String> {
+        let mut lines = vec![fit(prompt.trim_end(), width),
+                             fit(&format!("> {}", self.filter), width)];
+
+        // Scroll so that the highlighted password is always shown.
+        let first = if self.highlighted < MAX_ROWS {
+            0
+        } else {
+            self.highlighted + 1 - MAX_ROWS
+        };
+        let rows: Vec<(usize, &Password)> = self.visible
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(MAX_ROWS)
+            .map(|(row, &i)| (row, self.passwords[i]))
+            .collect();
+        let name_width = rows.iter().map(|&(_, p)| p.name.chars().count()).max().unwrap_or(0);
+
+        let any_favorite = self.passwords.iter().any(|p| p.favorite);
+
+        let charset = glyphs::charset();
+        for (row, password) in rows {
+            let pointer = if row == self.highlighted {
+                Glyph::Pointer.to_str(charset)
+            } else {
+                " "
+            };
+            let star = match (any_favorite, password.favorite) {
+                (false, _) => String::new(),
+                (true, true) => format!("{} ", Glyph::Star.to_str(charset)),
+                (true, false) => "  ".to_owned(),
+            };
+            let line = fit(&format!("{} {}{:name_width$}  {}",
+                                    pointer,
+                                    star,
+                                    password.name,
+                                    password.username,
+                                    name_width = name_width),
+                           width);
+            if row == self.highlighted {
+                lines.push(format!("{}{}{}",
+                                   Color::Cyan.to_color_code(),
+                                   line,
+                                   Color::Reset.to_color_code()));
+            } else {
+                lines.push(line);
+            }
+        }
+
+        if self.visible.is_empty() {
+            lines.push(fit("  Nothing matches, press Backspace to search again.", width));
+        } else if self.visible.len() > MAX_ROWS {
+            lines.push(fit(&format!("  ({} of {})", self.highlighted + 1, self.visible.len()),
+                           width));
+        }
+        lines.push(fit("Arrows or j/k to move, type to search, Enter to choose, Esc to cancel.",
+                       width));
+        lines
+    }
+}
+
+/// Cuts a line so that it doesn't wrap, which would throw off the redrawing.
+fn fit(line: &str, width: usize) -> String {
+    line.chars().take(width.saturating_sub(1)).collect()
+}
+
+/// Whether the picker can be shown. It needs to read keys from a terminal and to move the
+/// cursor of the terminal it draws on.
+fn is_supported() -> bool {
+    let term = env::var("TERM").unwrap_or_default();
+    sys::stdin_is_tty() && sys::stderr_is_tty() && !term.is_empty() && term != "dumb" &&
+    !answer::is_scripted()
+}
+
+/// Draws the lines over the `previous` ones.
+fn draw(lines: &[String], previous: usize) {
+    let mut output = String::from("\r");
+    if previous > 1 {
+        output.push_str(&format!("\x1b[{}A", previous - 1));
+    }
+    output.push_str("\x1b[J");
+    output.push_str(&lines.join("\n"));
+    let mut stderr = stderr();
+    let _ = stderr.write_all(output.as_bytes());
+    let _ = stderr.flush();
+}
+
+/// Lets the user pick one of the passwords, returns its index or `None` if they cancelled.
+///
+/// Returns `None` (the outer one) if there is no terminal to show the picker in.
+pub fn choose(passwords: &[&Password], prompt: &str) -> Option<Option<usize>> {
+    if !is_supported() {
+        return None;
+    }
+    let raw_mode = sys::RawMode::enter()?;
+
+    let width = sys::terminal_width().unwrap_or(80);
+    let mut selector = Selector::new(passwords);
+    let mut drawn = 0;
+    let outcome = loop {
+        let lines = selector.render(prompt, width);
+        draw(&lines, drawn);
+        drawn = lines.len();
+
+        let key = match raw_mode.read_byte(None) {
+            Some(first) => decode_key(first, || raw_mode.read_byte(Some(ESCAPE_TIMEOUT_MS))),
+            None => Key::Escape,
+        };
+        if let Some(outcome) = selector.handle(key) {
+            break outcome;
+        }
+    };
+
+    // Only leave the choice on the screen.
+    let summary = match outcome {
+        Outcome::Chosen(i) => format!("{} {}", prompt.trim_end(), passwords[i].name),
+        Outcome::Aborted => format!("{} (cancelled)", prompt.trim_end()),
+    };
+    draw(&[fit(&summary, width), String::new()], drawn);
+
+    match outcome {
+        Outcome::Chosen(i) => Some(Some(i)),
+        Outcome::Aborted => Some(None),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{decode_key, Key, Outcome, Selector};
+    use password::v2::Password;
+    use safe_string::SafeString;
+
+    fn decode(bytes: &[u8]) -> Key {
+        let mut rest = bytes[1..].iter().cloned();
+        decode_key(bytes[0], || rest.next())
+    }
+
+    fn password(name: &str, username: &str) -> Password {
+        Password::new(name.to_owned(), username.to_owned(), SafeString::new("xxx".to_owned()))
+    }
+
+    #[test]
+    fn decodes_keys() {
+        assert_eq!(decode(b"\x1b[A"), Key::Up);
+        assert_eq!(decode(b"\x1bOB"), Key::Down);
+        assert_eq!(decode(b"\x1b"), Key::Escape);
+        assert_eq!(decode(b"\x03"), Key::Escape);
+        assert_eq!(decode(b"\r"), Key::Enter);
+        assert_eq!(decode(b"\x7f"), Key::Backspace);
+        assert_eq!(decode(b"g"), Key::Char('g'));
+        assert_eq!(decode("é".as_bytes()), Key::Char('é'));
+        assert_eq!(decode(b"\x1b[C"), Key::Other);
+    }
+
+    #[test]
+    fn moves_filters_and_chooses() {
+        let passwords = vec![password("GitHub", "me"),
+                             password("Gmail", "me"),
+                             password("Bank", "john@gmail.com")];
+        let passwords: Vec<&Password> = passwords.iter().collect();
+        let mut selector = Selector::new(&passwords);
+
+        assert_eq!(selector.handle(Key::Char('j')), None);
+        assert_eq!(selector.handle(Key::Down), None);
+        assert_eq!(selector.handle(Key::Down), None);
+        assert_eq!(selector.highlighted, 2);
+        assert_eq!(selector.handle(Key::Char('k')), None);
+        assert_eq!(selector.highlighted, 1);
+
+        // Typing starts over from the best match, and j is then part of the search.
+        for c in "gmail".chars() {
+            assert_eq!(selector.handle(Key::Char(c)), None);
+        }
+        assert_eq!(selector.visible, vec![1, 2]);
+        assert_eq!(selector.handle(Key::Down), None);
+        assert_eq!(selector.handle(Key::Enter), Some(Outcome::Chosen(2)));
+
+        assert_eq!(selector.handle(Key::Char('j')), None);
+        assert_eq!(selector.visible, Vec::<usize>::new());
+        assert_eq!(selector.handle(Key::Enter), None);
+        assert_eq!(selector.handle(Key::Backspace), None);
+        assert_eq!(selector.visible, vec![1, 2]);
+        assert_eq!(selector.handle(Key::Escape), Some(Outcome::Aborted));
+    }
+
+    #[test]
+    fn filters_with_several_words_like_searches() {
+        let passwords = vec![password("AWS staging", "ops"),
+                             password("AWS prod", "ops"),
+                             password("Prod database", "aws")];
+        let passwords: Vec<&Password> = passwords.iter().collect();
+        let mut selector = Selector::new(&passwords);
+        for c in "aws prod".chars() {
+            selector.handle(Key::Char(c));
+        }
+        // Names that match every word come before those matched through their username.
+        assert_eq!(selector.visible, vec![1, 2]);
+    }
+
+    #[test]
+    fn lines_fit_in_the_terminal() {
+        let passwords: Vec<Password> = (0..30)
+            .map(|i| password(&format!("A rather long app name {}", i), "me@example.com"))
+            .collect();
+        let passwords: Vec<&Password> = passwords.iter().collect();
+        let mut selector = Selector::new(&passwords);
+        for _ in 0..15 {
+            selector.handle(Key::Down);
+        }
+
+        let lines = selector.render("Which one?", 20);
+        let without_colors = |line: &String| line.replace("\x1b[36m", "").replace("\x1b[39m", "");
+        assert!(lines.iter().all(|line| without_colors(line).chars().count() < 20));
+        // The prompt, the search, 10 passwords, the position and the help.
+        assert_eq!(lines.len(), 14);
+    }
+}
@@ -0,0 +1,447 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A rough idea of how hard a password is to guess, in the spirit of zxcvbn.
+//!
+//! The password is split into the cheapest patterns an attacker would try: words from the
+//! passphrase word list or the most common passwords, keyboard walks like "qwerty", sequences
+//! like "1234" and repeats like "aaa". The other characters are guessed one by one, assuming the
+//! attacker knows which kinds of characters the password is made of.
+
+use generate::wordlist;
+use memory;
+use safe_string::SafeString;
+use std::collections::HashSet;
+use std::mem;
+
+/// Master passwords shorter than this are weak, whatever they are made of.
+pub const MIN_LENGTH: usize = 10;
+
+/// Passwords of apps below this are only saved once the user confirms it.
+pub const MIN_VERDICT: Verdict = Verdict::Fair;
+
+/// Some of the passwords that leak the most often, which are tried first.
+const COMMON_PASSWORDS: &'static [&'static str] =
+    &["123456", "123456789", "12345678", "12345", "1234567", "1234567890", "123123", "111111",
+      "000000", "654321", "666666", "121212", "987654321", "qwerty", "qwerty123", "qwertyuiop",
+      "1q2w3e4r", "1q2w3e4r5t", "asdfghjkl", "zxcvbnm", "password", "password1", "password123",
+      "passw0rd", "p@ssw0rd", "iloveyou", "princess", "sunshine", "superman", "starwars",
+      "football", "baseball", "monkey", "dragon", "letmein", "welcome", "welcome1", "admin",
+      "administrator", "abc123", "master", "masterpassword", "trustno1", "whatever", "shadow",
+      "michael", "jennifer", "charlie", "freedom", "computer", "secret", "changeme",
+      "correcthorsebatterystaple", "correct horse battery staple"];
+
+/// Shorter words, walks and sequences are too likely to show up by chance.
+const MIN_PATTERN_LEN: usize = 4;
+
+/// The rows of a QWERTY keyboard, without shift.
+const KEYBOARD_ROWS: &'static [&'static str] =
+    &["`1234567890-=", "qwertyuiop[]\\", "asdfghjkl;'", "zxcvbnm,./"];
+
+/// A kind of characters, and how many characters of that kind there are.
+struct CharacterClass {
+    name: &'static str,
+    size: usize,
+    contains: fn(char) -> bool,
+}
+
+const CLASSES: &'static [CharacterClass] = &[
+    CharacterClass { name: "lowercase letters", size: 26, contains: is_lowercase },
+    CharacterClass { name: "uppercase letters", size: 26, contains: is_uppercase },
+    CharacterClass { name: "digits", size: 10, contains: is_digit },
+    CharacterClass { name: "symbols", size: 33, contains: is_symbol },
+    CharacterClass { name: "other characters", size: 100, contains: is_other },
+];
+
+fn is_lowercase(c: char) -> bool {
+    c.is_ascii_lowercase()
+}
+
+fn is_uppercase(c: char) -> bool {
+    c.is_ascii_uppercase()
+}
+
+fn is_digit(c: char) -> bool {
+    c.is_ascii_digit()
+}
+
+fn is_symbol(c: char) -> bool {
+    c.is_ascii_punctuation() || c == ' '
+}
+
+fn is_other(c: char) -> bool {
+    !c.is_ascii_alphanumeric() && !is_symbol(c)
+}
+
+/// How hard a password is to guess, from the number of guesses, like the score of zxcvbn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verdict {
+    /// Less than 10^6 guesses.
+    VeryWeak,
+    /// Less than 10^10 guesses.
+    Weak,
+    /// Less than 10^14 guesses.
+    Fair,
+    /// Less than 10^18 guesses.
+    Strong,
+    VeryStrong,
+}
+
+impl Verdict {
+    fn from_bits(bits: f64) -> Verdict {
+        let digits = bits * 2f64.log10();
+        if digits < 6.0 {
+            Verdict::VeryWeak
+        } else if digits < 10.0 {
+            Verdict::Weak
+        } else if digits < 14.0 {
+            Verdict::Fair
+        } else if digits < 18.0 {
+            Verdict::Strong
+        } else {
+            Verdict::VeryStrong
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Verdict::VeryWeak => "very weak",
+            Verdict::Weak => "weak",
+            Verdict::Fair => "fair",
+            Verdict::Strong => "strong",
+            Verdict::VeryStrong => "very strong",
+        }
+    }
+}
+
+/// A part of a password that is guessed as a whole. It holds that part, so it's kept safe.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    Word(SafeString),
+    KeyboardWalk(SafeString),
+    Sequence(SafeString),
+    /// The part that is repeated, like "ab" in "ababab".
+    Repeat(SafeString),
+}
+
+impl Pattern {
+    /// Like "contains the word 'tiger'".
+    pub fn describe(&self) -> String {
+        match *self {
+            Pattern::Word(ref part) => format!("contains the word '{}'", part.expose()),
+            Pattern::KeyboardWalk(ref part) => {
+                format!("contains the keyboard walk '{}'", part.expose())
+            }
+            Pattern::Sequence(ref part) => format!("contains the sequence '{}'", part.expose()),
+            Pattern::Repeat(ref part) => format!("repeats '{}'", part.expose()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Strength {
+    /// In characters, not bytes.
+    pub len: usize,
+    pub classes: Vec<&'static str>,
+    pub common: bool,
+    /// The patterns found in the password, in order.
+    pub patterns: Vec<Pattern>,
+    /// The base 2 logarithm of the number of guesses needed to find the password.
+    pub bits: f64,
+}
+
+impl Strength {
+    pub fn of(password: &str) -> Strength {
+        let chars = Chars(password.chars().collect());
+        let present: Vec<&CharacterClass> = CLASSES.iter()
+            .filter(|class| chars.0.iter().any(|c| (class.contains)(*c)))
+            .collect();
+        // Without a lowercase copy of the password, which would have to be wiped.
+        let common = COMMON_PASSWORDS.iter().any(|common| common.eq_ignore_ascii_case(password));
+
+        let pool: usize = present.iter().map(|class| class.size).sum();
+        let (bits, patterns) = if common {
+            ((COMMON_PASSWORDS.len() as f64).log2(), Vec::new())
+        } else {
+            cheapest_split(&chars.0, (pool.max(1) as f64).log2())
+        };
+
+        Strength {
+            len: chars.0.len(),
+            classes: present.iter().map(|class| class.name).collect(),
+            common: common,
+            patterns: patterns,
+            bits: bits,
+        }
+    }
+
+    /// Whether a master password is too weak. Unlike `verdict`, patterns don't count, only the
+    /// length and the most common passwords.
+    pub fn is_weak(&self) -> bool {
+        self.common || self.len < MIN_LENGTH
+    }
+
+    pub fn verdict(&self) -> Verdict {
+        Verdict::from_bits(self.bits)
+    }
+
+    /// Like "12 characters (lowercase letters, digits), about 10^18 guesses".
+    pub fn describe(&self) -> String {
+        format!("{} characters ({}), about 10^{:.0} guesses",
+                self.len,
+                self.classes.join(", "),
+                self.bits * 2f64.log10())
+    }
+
+    /// A one-line verdict, like "very weak: contains the word 'tiger', about 10^4 guesses".
+    pub fn summary(&self) -> String {
+        let mut reasons: Vec<String> = self.patterns.iter().map(Pattern::describe).collect();
+        if self.common {
+            reasons.push("one of the most common passwords".to_owned());
+        }
+
+        let mut summary = self.verdict().name().to_owned();
+        if !reasons.is_empty() {
+            summary.push_str(": ");
+            summary.push_str(&reasons.join(" and "));
+        }
+        summary.push_str(&format!(", about 10^{:.0} guesses", self.bits * 2f64.log10()));
+        summary
+    }
+
+    /// Why a master password is weak, if it is.
+    pub fn weaknesses(&self) -> Vec<String> {
+        let mut weaknesses = Vec::new();
+        if self.common {
+            weaknesses.push("it is one of the most common passwords".to_owned());
+        }
+        if self.len < MIN_LENGTH {
+            weaknesses.push(format!("it is shorter than {} characters", MIN_LENGTH));
+        }
+        weaknesses
+    }
+}
+
+/// The characters of a password, zeroed when dropped like a `SafeString`.
+struct Chars(Vec<char>);
+
+impl Drop for Chars {
+    fn drop(&mut self) {
+        unsafe {
+            memory::zero(self.0.as_mut_ptr() as *mut u8,
+                         self.0.capacity() * mem::size_of::<char>());
+        }
+    }
+}
+
+/// Splits the password into the patterns and single characters that are the cheapest to guess
+/// all together, given the bits needed to guess a single character.
+fn cheapest_split(chars: &[char], char_bits: f64) -> (f64, Vec<Pattern>) {
+    let dictionary = dictionary();
+
+    // For every prefix: the fewest bits to guess it, where its last part starts, and the
+    // pattern of that part, if it's not a single character.
+    let mut best: Vec<(f64, usize, Option<Pattern>)> = vec![(0.0, 0, None)];
+    for end in 1..chars.len() + 1 {
+        let mut choice = (best[end - 1].0 + char_bits, end - 1, None);
+        for start in 0..end {
+            let found = find_pattern(&chars[start..end], char_bits, &dictionary);
+            if let Some((bits, pattern)) = found {
+                if best[start].0 + bits < choice.0 {
+                    choice = (best[start].0 + bits, start, Some(pattern));
+                }
+            }
+        }
+        best.push(choice);
+    }
+
+    let bits = best[chars.len()].0;
+    let mut patterns = Vec::new();
+    let mut end = chars.len();
+    while end > 0 {
+        let (_, start, ref pattern) = best[end];
+        if let Some(ref pattern) = *pattern {
+            patterns.push(pattern.clone());
+        }
+        end = start;
+    }
+    patterns.reverse();
+    (bits, patterns)
+}
+
+fn dictionary() -> HashSet<&'static str> {
+    wordlist()
+        .into_iter()
+        .chain(COMMON_PASSWORDS.iter().cloned())
+        .filter(|word| word.chars().count() >= MIN_PATTERN_LEN)
+        .collect()
+}
+
+/// The cheapest pattern that `part` is as a whole, if any, and the bits to guess it.
+fn find_pattern(part: &[char],
+                char_bits: f64,
+                dictionary: &HashSet<&'static str>)
+                -> Option<(f64, Pattern)> {
+    let text = || SafeString::new(part.iter().collect());
+    let lower = SafeString::new(part.iter().map(|c| c.to_ascii_lowercase()).collect());
+    let len_bits = (part.len() as f64).log2();
+
+    let mut found: Vec<(f64, Pattern)> = Vec::new();
+    if part.len() >= MIN_PATTERN_LEN && dictionary.contains(lower.expose()) {
+        // Capitalizing the first letter or the whole word is tried, anything else is as hard
+        // as guessing the case of every letter.
+        let upper = part.iter().filter(|c| c.is_ascii_uppercase()).count();
+        let case_bits = if upper == 0 {
+            0.0
+        } else if upper == part.len() || (upper == 1 && part[0].is_ascii_uppercase()) {
+            1.0
+        } else {
+            part.len() as f64
+        };
+        let bits = (dictionary.len() as f64).log2() + case_bits;
+        found.push((bits, Pattern::Word(text())));
+    }
+    if part.len() >= MIN_PATTERN_LEN && is_keyboard_walk(lower.expose()) {
+        // Any key to start from, in either direction.
+        let keys: usize = KEYBOARD_ROWS.iter().map(|row| row.len()).sum();
+        found.push(((keys as f64 * 2.0).log2() + len_bits, Pattern::KeyboardWalk(text())));
+    }
+    if part.len() >= MIN_PATTERN_LEN && is_sequence(lower.expose()) {
+        // Any letter or digit to start from, in either direction.
+        found.push(((36.0f64 * 2.0).log2() + len_bits, Pattern::Sequence(text())));
+    }
+    if let Some(base) = repeated_base(part) {
+        let times = part.len() / base;
+        let bits = base as f64 * char_bits + (times as f64).log2();
+        found.push((bits, Pattern::Repeat(SafeString::new(part[..base].iter().collect()))));
+    }
+
+    found.into_iter().fold(None, |cheapest, (bits, pattern)| {
+        match cheapest {
+            Some((cheapest_bits, _)) if cheapest_bits <= bits => cheapest,
+            _ => Some((bits, pattern)),
+        }
+    })
+}
+
+fn is_keyboard_walk(lower: &str) -> bool {
+    let reversed = SafeString::new(lower.chars().rev().collect());
+    KEYBOARD_ROWS.iter().any(|row| row.contains(lower) || row.contains(reversed.expose()))
+}
+
+/// Letters or digits that go up or down one at a time, like "abcd" or "4321".
+fn is_sequence(lower: &str) -> bool {
+    let bytes = lower.as_bytes();
+    let same_kind = bytes.iter().all(|b| b.is_ascii_lowercase()) ||
+                    bytes.iter().all(|b| b.is_ascii_digit());
+    let steps: Vec<i16> = bytes.windows(2).map(|w| w[1] as i16 - w[0] as i16).collect();
+    same_kind && (steps.iter().all(|s| *s == 1) || steps.iter().all(|s| *s == -1))
+}
+
+/// The length of the shortest part that `part` is made of, repeated, like 2 for "ababab". A
+/// single character must be repeated at least 3 times.
+fn repeated_base(part: &[char]) -> Option<usize> {
+    (1..part.len() / 2 + 1)
+        .filter(|base| part.len() % base == 0)
+        .filter(|base| *base > 1 || part.len() >= 3)
+        .find(|base| part.chunks(*base).all(|chunk| chunk == &part[..*base]))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Pattern, Strength, Verdict, MIN_VERDICT};
+    use safe_string::SafeString;
+
+    fn part(s: &str) -> SafeString {
+        SafeString::new(s.to_owned())
+    }
+
+    #[test]
+    fn counts_classes_and_characters() {
+        let strength = Strength::of("xvq-Rzmt7kbj");
+        assert_eq!(strength.len, 12);
+        assert_eq!(strength.classes,
+                   vec!["lowercase letters", "uppercase letters", "digits", "symbols"]);
+        assert!(strength.patterns.is_empty());
+        assert!((strength.bits - 12.0 * 95f64.log2()).abs() < 1e-9);
+        assert!(!strength.is_weak());
+
+        let strength = Strength::of("épée épée épée");
+        assert_eq!(strength.len, 14);
+        assert_eq!(strength.classes, vec!["lowercase letters", "symbols", "other characters"]);
+    }
+
+    #[test]
+    fn short_and_common_passwords_are_weak() {
+        assert!(Strength::of("xK9!fz2Q").is_weak());
+        assert_eq!(Strength::of("xK9!fz2Q").weaknesses().len(), 1);
+
+        let strength = Strength::of("Password123");
+        assert!(strength.common);
+        assert!(strength.is_weak());
+        assert!(strength.bits < 10.0);
+        assert_eq!(strength.weaknesses(), vec!["it is one of the most common passwords"]);
+        assert_eq!(strength.verdict(), Verdict::VeryWeak);
+
+        assert_eq!(Strength::of("").weaknesses().len(), 1);
+    }
+
+    #[test]
+    fn describes_the_guesses() {
+        assert_eq!(Strength::of("0123456789").describe(),
+                   "10 characters (digits), about 10^3 guesses");
+        assert_eq!(Strength::of("password").summary(),
+                   "very weak: one of the most common passwords, about 10^2 guesses");
+        assert_eq!(Strength::of("tiger!!!").summary(),
+                   "weak: contains the word 'tiger' and repeats '!', about 10^6 guesses");
+    }
+
+    #[test]
+    fn finds_words_walks_sequences_and_repeats() {
+        assert_eq!(Strength::of("Tiger7").patterns, vec![Pattern::Word(part("Tiger"))]);
+        assert_eq!(Strength::of("x.asdfg").patterns, vec![Pattern::KeyboardWalk(part("asdfg"))]);
+        assert_eq!(Strength::of("=poiuy").patterns, vec![Pattern::KeyboardWalk(part("poiuy"))]);
+        assert_eq!(Strength::of("lmnop!").patterns, vec![Pattern::Sequence(part("lmnop"))]);
+        assert_eq!(Strength::of("x97531").patterns, vec![]);
+        assert_eq!(Strength::of("7zzzzzz").patterns, vec![Pattern::Repeat(part("z"))]);
+        assert_eq!(Strength::of("k3k3k3k3").patterns, vec![Pattern::Repeat(part("k3"))]);
+    }
+
+    #[test]
+    fn known_weak_passwords() {
+        for password in ["password", "Password1", "qwerty", "1q2w3e4r", "aaaaaaaaaaaa",
+                         "abcdefghijkl", "qwertyuiop123", "tigertiger", "Dragon1234",
+                         "iloveyou!!"]
+            .iter() {
+            let strength = Strength::of(password);
+            assert!(strength.verdict() < MIN_VERDICT,
+                    "{} is {}",
+                    password,
+                    strength.summary());
+        }
+    }
+
+    #[test]
+    fn known_strong_passwords() {
+        for password in ["tZ7#qL9!mW2$", "correct-lunar-pepper-gadget-91", "u8Kd3nQx0bVr7mPz",
+                         "Xq$9vL!e2#Rw"]
+            .iter() {
+            let strength = Strength::of(password);
+            assert!(strength.verdict() >= Verdict::Strong,
+                    "{} is {}",
+                    password,
+                    strength.summary());
+        }
+    }
+}
@@ -0,0 +1,326 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Calls into the system that only the command line needs: terminals, the locale, the
+//! environment and locking the password file. The library keeps the ones for time in `ffi`.
+
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::ptr;
+use libc;
+
+/// The character set of the locale configured by $LC_ALL, $LC_CTYPE and $LANG, as the C library
+/// understands it, like "UTF-8" or "ANSI_X3.4-1968".
+///
+/// Returns None if the locale isn't installed on this system. The locale of the process is left
+/// unchanged.
+#[cfg(unix)]
+pub fn locale_codeset() -> Option<String> {
+    use std::ffi::{CStr, CString};
+
+    unsafe {
+        let previous = libc::setlocale(libc::LC_CTYPE, ptr::null());
+        let previous = if previous.is_null() {
+            None
+        } else {
+            Some(CStr::from_ptr(previous).to_owned())
+        };
+
+        let codeset = if libc::setlocale(libc::LC_CTYPE, b"\0".as_ptr() as *const libc::c_char)
+            .is_null() {
+            None
+        } else {
+            let codeset = libc::nl_langinfo(libc::CODESET);
+            if codeset.is_null() {
+                None
+            } else {
+                Some(CStr::from_ptr(codeset).to_string_lossy().into_owned())
+            }
+        };
+
+        let previous = previous.unwrap_or_else(|| CString::new("C").unwrap());
+        libc::setlocale(libc::LC_CTYPE, previous.as_ptr());
+
+        codeset
+    }
+}
+
+#[cfg(not(unix))]
+pub fn locale_codeset() -> Option<String> {
+    None
+}
+
+/// Whether standard input is a terminal, rather than a pipe or a file.
+#[cfg(unix)]
+pub fn stdin_is_tty() -> bool {
+    unsafe { libc::isatty(libc::STDIN_FILENO) == 1 }
+}
+
+#[cfg(not(unix))]
+pub fn stdin_is_tty() -> bool {
+    true
+}
+
+/// Whether standard error is a terminal, where prompts and pickers are shown.
+#[cfg(unix)]
+pub fn stderr_is_tty() -> bool {
+    unsafe { libc::isatty(libc::STDERR_FILENO) == 1 }
+}
+
+#[cfg(not(unix))]
+pub fn stderr_is_tty() -> bool {
+    false
+}
+
+/// The width of the terminal on standard error, in columns.
+#[cfg(unix)]
+pub fn terminal_width() -> Option<usize> {
+    use std::mem;
+
+    unsafe {
+        let mut size: libc::winsize = mem::zeroed();
+        if libc::ioctl(libc::STDERR_FILENO, libc::TIOCGWINSZ, &mut size) != 0 || size.ws_col == 0 {
+            None
+        } else {
+            Some(size.ws_col as usize)
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub fn terminal_width() -> Option<usize> {
+    None
+}
+
+/// Keeps the terminal on standard input in raw mode: keys are read as soon as they are pressed,
+/// without being echoed, and Ctrl-C is read like any other key instead of killing Rooster.
+///
+/// The previous mode is restored when this is dropped, which also happens when unwinding from a
+/// panic.
+#[cfg(unix)]
+pub struct RawMode {
+    previous: libc::termios,
+}
+
+#[cfg(unix)]
+impl RawMode {
+    pub fn enter() -> Option<RawMode> {
+        use std::mem;
+
+        unsafe {
+            let mut previous: libc::termios = mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut previous) != 0 {
+                return None;
+            }
+            let mut raw = previous;
+            raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG | libc::IEXTEN);
+            raw.c_iflag &= !(libc::IXON | libc::ICRNL);
+            raw.c_cc[libc::VMIN] = 1;
+            raw.c_cc[libc::VTIME] = 0;
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) != 0 {
+                return None;
+            }
+            Some(RawMode { previous: previous })
+        }
+    }
+
+    /// Reads a byte, waiting at most `timeout_ms` milliseconds if given. `None` at the end of
+    /// the input, on errors and on timeouts.
+    pub fn read_byte(&self, timeout_ms: Option<i32>) -> Option<u8> {
+        unsafe {
+            if let Some(timeout_ms) = timeout_ms {
+                let mut fd = libc::pollfd {
+                    fd: libc::STDIN_FILENO,
+                    events: libc::POLLIN,
+                    revents: 0,
+                };
+                if libc::poll(&mut fd, 1, timeout_ms) != 1 {
+                    return None;
+                }
+            }
+            let mut byte = 0u8;
+            match libc::read(libc::STDIN_FILENO, &mut byte as *mut u8 as *mut libc::c_void, 1) {
+                1 => Some(byte),
+                _ => None,
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.previous);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub struct RawMode;
+
+#[cfg(not(unix))]
+impl RawMode {
+    pub fn enter() -> Option<RawMode> {
+        None
+    }
+
+    pub fn read_byte(&self, _timeout_ms: Option<i32>) -> Option<u8> {
+        None
+    }
+}
+
+/// Stops the terminal `tty` from echoing what is typed in it, so that a password typed there isn't
+/// shown. The echo is turned back on when this is dropped.
+#[cfg(unix)]
+pub struct NoEcho {
+    fd: libc::c_int,
+    previous: libc::termios,
+}
+
+#[cfg(unix)]
+impl NoEcho {
+    pub fn enter(tty: &File) -> Option<NoEcho> {
+        use std::mem;
+        use std::os::unix::io::AsRawFd;
+
+        let fd = tty.as_raw_fd();
+        unsafe {
+            let mut previous: libc::termios = mem::zeroed();
+            if libc::tcgetattr(fd, &mut previous) != 0 {
+                return None;
+            }
+            let mut no_echo = previous;
+            no_echo.c_lflag &= !libc::ECHO;
+            no_echo.c_lflag |= libc::ECHONL;
+            if libc::tcsetattr(fd, libc::TCSANOW, &no_echo) != 0 {
+                return None;
+            }
+            Some(NoEcho {
+                fd: fd,
+                previous: previous,
+            })
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for NoEcho {
+    fn drop(&mut self) {
+        unsafe {
+            libc::tcsetattr(self.fd, libc::TCSANOW, &self.previous);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub struct NoEcho;
+
+#[cfg(not(unix))]
+impl NoEcho {
+    pub fn enter(_tty: &File) -> Option<NoEcho> {
+        None
+    }
+}
+
+/// Overwrites the value of an environment variable with zeros where it is stored, and removes
+/// it. For the variables Rooster was started with, this memory is also what /proc/<pid>/environ
+/// shows. Must be called before any other thread is started.
+pub fn wipe_env_var(name: &str) {
+    use std::env;
+    use std::ffi::CString;
+
+    if let Ok(c_name) = CString::new(name) {
+        unsafe {
+            let value = libc::getenv(c_name.as_ptr());
+            if !value.is_null() {
+                ptr::write_bytes(value, 0, libc::strlen(value));
+            }
+        }
+    }
+    env::remove_var(name);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Lock {
+    /// Several processes may hold it at once, to read.
+    Shared,
+    /// Only one process may hold it, to write.
+    Exclusive,
+}
+
+/// Takes an advisory lock on the file, or converts the one held. Returns false if another
+/// process holds a lock in the way, or waits for it to be released with `wait`. Closing the
+/// file releases the lock.
+#[cfg(unix)]
+pub fn lock_file(file: &File, lock: Lock, wait: bool) -> io::Result<bool> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut operation = match lock {
+        Lock::Shared => libc::LOCK_SH,
+        Lock::Exclusive => libc::LOCK_EX,
+    };
+    if !wait {
+        operation |= libc::LOCK_NB;
+    }
+    loop {
+        if unsafe { libc::flock(file.as_raw_fd(), operation) } == 0 {
+            return Ok(true);
+        }
+        let err = io::Error::last_os_error();
+        match err.kind() {
+            io::ErrorKind::WouldBlock => return Ok(false),
+            io::ErrorKind::Interrupted => continue,
+            _ => return Err(err),
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub fn lock_file(_file: &File, _lock: Lock, _wait: bool) -> io::Result<bool> {
+    Err(io::Error::new(io::ErrorKind::Other, "not supported on this system"))
+}
+
+#[cfg(unix)]
+pub fn unlock_file(file: &File) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_UN) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn unlock_file(_file: &File) -> io::Result<()> {
+    Ok(())
+}
+
+/// Whether the open file is still the one at `path`, which may have been replaced since.
+#[cfg(unix)]
+pub fn is_same_file(file: &File, path: &Path) -> bool {
+    use std::fs;
+    use std::os::unix::fs::MetadataExt;
+
+    match (file.metadata(), fs::metadata(path)) {
+        (Ok(opened), Ok(current)) => opened.dev() == current.dev() && opened.ino() == current.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+pub fn is_same_file(_file: &File, _path: &Path) -> bool {
+    true
+}
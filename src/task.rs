@@ -0,0 +1,266 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Opening and saving a password store on a worker thread, for frontends that can't block
+//! their main thread while the master password goes through the key derivation function.
+//!
+//! Threading guarantees: `PasswordStore` is `Send` and may be moved to another thread, but it
+//! has no locking of its own. Every change goes through `&mut self`, so the compiler already
+//! rules out concurrent changes. The handles below take ownership of everything the worker
+//! needs (master password, file contents, store, path) and give it back through `wait`, so
+//! nothing is ever shared between the caller and the worker.
+//!
+//! Cancelling returns right away. The key derivation itself can't be interrupted, so the worker
+//! may keep running in the background for a moment. Its result is then dropped on the worker
+//! thread, which wipes the derived key and the master password.
+
+use password::PasswordError;
+use password::v2::PasswordStore;
+use safe_string::SafeString;
+use safe_vec::SafeVec;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+#[derive(Debug)]
+pub enum TaskError {
+    Failed(PasswordError),
+    /// The worker panicked, with the panic message.
+    Panicked(String),
+}
+
+/// A task running on a worker thread. Dropping the handle cancels the task.
+pub struct Handle<T> {
+    receiver: Receiver<Result<T, TaskError>>,
+    cancelled: Arc<AtomicBool>,
+    outcome: Option<Result<T, TaskError>>,
+}
+
+impl<T> Handle<T> {
+    /// Whether the task is done, without blocking. `wait` then returns right away.
+    pub fn poll(&mut self) -> bool {
+        if self.outcome.is_none() {
+            match self.receiver.try_recv() {
+                Ok(outcome) => self.outcome = Some(outcome),
+                Err(TryRecvError::Empty) => {}
+                Err(TryRecvError::Disconnected) => self.outcome = Some(Err(stopped_error())),
+            }
+        }
+        self.outcome.is_some()
+    }
+
+    /// Blocks until the task is done.
+    pub fn wait(mut self) -> Result<T, TaskError> {
+        match self.outcome.take() {
+            Some(outcome) => outcome,
+            None => self.receiver.recv().unwrap_or_else(|_| Err(stopped_error())),
+        }
+    }
+
+    /// Stops waiting for the task, see the module documentation.
+    pub fn cancel(self) {
+        // Dropping does the work.
+    }
+}
+
+impl<T> Drop for Handle<T> {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+}
+
+fn stopped_error() -> TaskError {
+    TaskError::Panicked("the worker stopped without a result".to_owned())
+}
+
+/// Runs `work` on a new thread. `work` may check the flag it's given to stop early once the
+/// task is cancelled.
+fn spawn<T, F>(work: F) -> Handle<T>
+    where T: Send + 'static,
+          F: FnOnce(&AtomicBool) -> Result<T, PasswordError> + Send + 'static
+{
+    let (sender, receiver) = mpsc::channel();
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let worker_cancelled = cancelled.clone();
+
+    thread::spawn(move || {
+        let outcome = match panic::catch_unwind(AssertUnwindSafe(|| work(&worker_cancelled))) {
+            Ok(Ok(value)) => Ok(value),
+            Ok(Err(err)) => Err(TaskError::Failed(err)),
+            Err(payload) => {
+                // panic!() gives a &str, or a String when it has arguments.
+                let message = payload.downcast_ref::<String>()
+                    .cloned()
+                    .or_else(|| payload.downcast_ref::<&'static str>().map(|s| (*s).to_owned()))
+                    .unwrap_or_else(|| "unknown panic".to_owned());
+                Err(TaskError::Panicked(message))
+            }
+        };
+
+        // If the handle is gone, the outcome comes back with the error and is dropped here.
+        let _ = sender.send(outcome);
+    });
+
+    Handle {
+        receiver: receiver,
+        cancelled: cancelled,
+        outcome: None,
+    }
+}
+
+pub type OpenHandle = Handle<PasswordStore>;
+
+/// Same as `PasswordStore::from_input`, on a worker thread.
+pub fn spawn_open(master_password: SafeString, input: SafeVec) -> OpenHandle {
+    spawn(move |cancelled| {
+        if cancelled.load(Ordering::SeqCst) {
+            // Both are wiped when dropped.
+            drop(master_password);
+            drop(input);
+            // Nobody is waiting for this error.
+            return Err(PasswordError::DecryptionError);
+        }
+        PasswordStore::from_input(master_password, input)
+    })
+}
+
+/// The store is given back after saving, whether it worked or not.
+pub struct Synced {
+    pub store: PasswordStore,
+    pub result: Result<(), PasswordError>,
+}
+
+pub type SyncHandle = Handle<Synced>;
+
+/// Same as `PasswordStore::sync`, on a worker thread. If the task is cancelled before the worker
+/// starts writing, the file is left untouched. Otherwise the file is written completely.
+pub fn spawn_sync(store: PasswordStore, path: PathBuf) -> SyncHandle {
+    spawn(move |cancelled| {
+        let result = if cancelled.load(Ordering::SeqCst) {
+            Ok(())
+        } else {
+            store.sync(&path)
+        };
+        Ok(Synced {
+            store: store,
+            result: result,
+        })
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{spawn, spawn_open, spawn_sync, TaskError};
+    use password::PasswordError;
+    use password::v2::{Password, PasswordStore};
+    use safe_string::SafeString;
+    use safe_vec::SafeVec;
+    use std::env;
+    use std::fs;
+    use std::process;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    fn master() -> SafeString {
+        SafeString::new("master".to_owned())
+    }
+
+    #[test]
+    fn open_and_sync_complete() {
+        let path = env::temp_dir().join(format!("rooster-task-test-{}", process::id()));
+        let mut store = PasswordStore::new(master()).unwrap();
+        store.add_password(Password::new("YouTube".to_owned(),
+                                         "me".to_owned(),
+                                         SafeString::new("xxx".to_owned())))
+            .unwrap();
+
+        let mut handle = spawn_sync(store, path.clone());
+        while !handle.poll() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        let synced = handle.wait().unwrap();
+        assert!(synced.result.is_ok());
+
+        let input = SafeVec::new(fs::read(&path).unwrap());
+        fs::remove_file(&path).unwrap();
+
+        let store = spawn_open(master(), input.clone()).wait().unwrap();
+        assert!(store.has_password("YouTube"));
+
+        match spawn_open(SafeString::new("wrong".to_owned()), input).wait() {
+            Err(TaskError::Failed(PasswordError::WrongMasterPasswordError)) => {}
+            other => panic!("unexpected outcome: {:?}", other.map(|_| ())),
+        }
+    }
+
+    /// Dropped on the worker thread when the result is thrown away.
+    struct DropFlag(Arc<AtomicBool>);
+
+    impl Drop for DropFlag {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn cancelling_returns_right_away() {
+        let (go_sender, go) = mpsc::channel::<()>();
+        let (done, done_receiver) = mpsc::channel();
+        let dropped = Arc::new(AtomicBool::new(false));
+        let worker_dropped = dropped.clone();
+
+        let handle = spawn(move |cancelled| {
+            // Stands for a slow key derivation.
+            go.recv().unwrap();
+            done.send(cancelled.load(Ordering::SeqCst)).unwrap();
+            Ok(DropFlag(worker_dropped))
+        });
+
+        // The worker is stuck, this would block forever if cancelling waited for it.
+        handle.cancel();
+        go_sender.send(()).unwrap();
+
+        assert!(done_receiver.recv().unwrap(), "the worker should see the cancellation");
+        for _ in 0..1000 {
+            if dropped.load(Ordering::SeqCst) {
+                return;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        panic!("the result of a cancelled task should be dropped");
+    }
+
+    #[test]
+    fn panics_become_errors() {
+        let handle = spawn(|_| -> Result<(), PasswordError> { panic!("boom") });
+        match handle.wait() {
+            Err(TaskError::Panicked(message)) => assert_eq!(message, "boom"),
+            _ => panic!("the panic should be reported"),
+        }
+
+        let handle = spawn(|_| -> Result<(), PasswordError> {
+            panic!("{} {}", "formatted", "boom")
+        });
+        match handle.wait() {
+            Err(TaskError::Panicked(message)) => assert_eq!(message, "formatted boom"),
+            _ => panic!("the panic should be reported"),
+        }
+    }
+}
@@ -0,0 +1,462 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Timestamps are stored and exported as seconds since the epoch, which don't depend on any
+//! timezone. They are read back from either epoch seconds or RFC3339 dates with any offset.
+//! Local time is only ever used to show timestamps to humans, see `to_local_string`.
+
+use ffi;
+use serde::de::{Deserializer, Error, Visitor};
+use std::fmt;
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// Number of days between 1970-01-01 and the given date of the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = (if year >= 0 { year } else { year - 399 }) / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Inverse of `days_from_civil`, returns (year, month, day).
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = (if days >= 0 { days } else { days - 146096 }) / 146097;
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 -
+                       day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400;
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
+
+fn is_leap_year(year: i64) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+fn parse_number(s: &str, what: &str) -> Result<i64, String> {
+    if s.is_empty() || !s.chars().all(|c| c.is_ascii_digit()) {
+        return Err(format!("invalid {} \"{}\"", what, s));
+    }
+    s.parse::<i64>().map_err(|_| format!("invalid {} \"{}\"", what, s))
+}
+
+fn to_time_t(seconds: i64) -> Result<ffi::time_t, String> {
+    if seconds < 0 || seconds > ffi::time_t::max_value() as i64 {
+        return Err(format!("{} is out of the supported range of dates", seconds));
+    }
+    Ok(seconds as ffi::time_t)
+}
+
+/// Parses "2021-03-04T10:20:30Z", "2021-03-04T19:20:30.5+09:00" or "1614853230".
+pub fn parse(s: &str) -> Result<ffi::time_t, String> {
+    let s = s.trim();
+
+    if !s.is_empty() && s.chars().all(|c| c.is_ascii_digit()) {
+        return to_time_t(parse_number(s, "timestamp")?);
+    }
+
+    if s.len() < 20 || !s.is_char_boundary(19) {
+        return Err(format!("\"{}\" is neither a number of seconds nor an RFC3339 date", s));
+    }
+    let (date_time, offset) = s.split_at(19);
+    let bytes = date_time.as_bytes();
+    if bytes[4] != b'-' || bytes[7] != b'-' || bytes[13] != b':' || bytes[16] != b':' ||
+       !(bytes[10] == b'T' || bytes[10] == b't' || bytes[10] == b' ') {
+        return Err(format!("\"{}\" is not an RFC3339 date", s));
+    }
+
+    let year = parse_number(&date_time[0..4], "year")?;
+    let month = parse_number(&date_time[5..7], "month")?;
+    let day = parse_number(&date_time[8..10], "day")?;
+    let hour = parse_number(&date_time[11..13], "hour")?;
+    let minute = parse_number(&date_time[14..16], "minute")?;
+    let second = parse_number(&date_time[17..19], "second")?;
+    if month < 1 || month > 12 || day < 1 || day > days_in_month(year, month) || hour > 23 ||
+       minute > 59 || second > 60 {
+        return Err(format!("\"{}\" is not a valid date", s));
+    }
+    // Leap seconds can't be represented in seconds since the epoch.
+    let second = if second == 60 { 59 } else { second };
+
+    // Fractions of seconds are dropped, we only store whole seconds.
+    let mut offset = offset;
+    if offset.starts_with('.') {
+        let digits = offset[1..].chars().take_while(|c| c.is_ascii_digit()).count();
+        if digits == 0 {
+            return Err(format!("\"{}\" has an invalid fraction of second", s));
+        }
+        offset = &offset[1 + digits..];
+    }
+
+    let offset_seconds = match offset {
+        "Z" | "z" => 0,
+        _ => {
+            if offset.len() != 6 || offset.as_bytes()[3] != b':' {
+                return Err(format!("\"{}\" has an invalid timezone offset", s));
+            }
+            let sign = match &offset[0..1] {
+                "+" => 1,
+                "-" => -1,
+                _ => return Err(format!("\"{}\" has an invalid timezone offset", s)),
+            };
+            let offset_hours = parse_number(&offset[1..3], "offset")?;
+            let offset_minutes = parse_number(&offset[4..6], "offset")?;
+            if offset_hours > 23 || offset_minutes > 59 {
+                return Err(format!("\"{}\" has an invalid timezone offset", s));
+            }
+            sign * (offset_hours * 3600 + offset_minutes * 60)
+        }
+    };
+
+    let local_seconds = days_from_civil(year, month, day) * SECONDS_PER_DAY + hour * 3600 +
+                        minute * 60 + second;
+    to_time_t(local_seconds - offset_seconds)
+}
+
+/// Parses a duration like "90d", "12w", "6m" or "1y" into seconds. Months are 30 days and years
+/// are 365 days, which is close enough for telling how old a password is.
+pub fn parse_duration(s: &str) -> Result<i64, String> {
+    let s = s.trim();
+    let unit = match s.chars().last() {
+        Some('d') => SECONDS_PER_DAY,
+        Some('w') => 7 * SECONDS_PER_DAY,
+        Some('m') => 30 * SECONDS_PER_DAY,
+        Some('y') => 365 * SECONDS_PER_DAY,
+        _ => {
+            return Err(format!("\"{}\" is not a duration, it should end with d, w, m or y", s))
+        }
+    };
+    let count = parse_number(&s[..s.len() - 1], "duration")?;
+    count.checked_mul(unit).ok_or_else(|| format!("\"{}\" is too long", s))
+}
+
+/// Parses a point in time given either as a date, like "2020-01-01" (at midnight UTC), as an
+/// RFC3339 date, or as a duration before `now`, like "90d".
+pub fn parse_date_or_duration(s: &str, now: ffi::time_t) -> Result<ffi::time_t, String> {
+    let s = s.trim();
+    let bytes = s.as_bytes();
+
+    if s.len() == 10 && s.is_ascii() && bytes[4] == b'-' && bytes[7] == b'-' {
+        let year = parse_number(&s[0..4], "year")?;
+        let month = parse_number(&s[5..7], "month")?;
+        let day = parse_number(&s[8..10], "day")?;
+        if month < 1 || month > 12 || day < 1 || day > days_in_month(year, month) {
+            return Err(format!("\"{}\" is not a valid date", s));
+        }
+        return to_time_t(days_from_civil(year, month, day) * SECONDS_PER_DAY);
+    }
+
+    if s.ends_with(|c: char| c.is_ascii_alphabetic()) && !s.ends_with(|c| c == 'Z' || c == 'z') {
+        let duration = parse_duration(s)?;
+        return to_time_t(now as i64 - duration);
+    }
+
+    if !s.is_empty() && s.chars().all(|c| c.is_ascii_digit()) {
+        return Err(format!("\"{}\" needs a unit, like {}d for days", s, s));
+    }
+
+    parse(s).map_err(|_| {
+        format!("\"{}\" is neither a date like 2020-01-01 nor a duration like 90d", s)
+    })
+}
+
+/// Where timestamps are shown: in the local timezone, as configured by $TZ, or at a fixed offset
+/// from UTC, in seconds, which doesn't depend on the environment.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Zone {
+    Local,
+    Offset(i64),
+}
+
+/// Year, month, day, hour and minute of a timestamp in `zone`, or in UTC if the local timezone
+/// is unknown (the last field is true then).
+fn fields(t: ffi::time_t, zone: Zone) -> (i64, i64, i64, i64, i64, bool) {
+    let tm = match zone {
+        Zone::Local => ffi::localtime(t),
+        Zone::Offset(_) => None,
+    };
+    match tm {
+        Some(tm) => {
+            (tm.tm_year as i64 + 1900,
+             tm.tm_mon as i64 + 1,
+             tm.tm_mday as i64,
+             tm.tm_hour as i64,
+             tm.tm_min as i64,
+             false)
+        }
+        None => {
+            let offset = match zone {
+                Zone::Local => 0,
+                Zone::Offset(offset) => offset,
+            };
+            let t = t as i64 + offset;
+            let (year, month, day) = civil_from_days(t.div_euclid(SECONDS_PER_DAY));
+            let seconds_of_day = t.rem_euclid(SECONDS_PER_DAY);
+            (year,
+             month,
+             day,
+             seconds_of_day / 3600,
+             seconds_of_day % 3600 / 60,
+             zone == Zone::Local)
+        }
+    }
+}
+
+/// Shows a timestamp in the local timezone, like "2021-03-04 10:20".
+///
+/// If the local timezone is unknown, the date is shown in UTC, with a "UTC" suffix.
+pub fn to_local_string(t: ffi::time_t) -> String {
+    to_string_in(t, Zone::Local)
+}
+
+/// Like `to_local_string`, in any zone.
+pub fn to_string_in(t: ffi::time_t, zone: Zone) -> String {
+    let (year, month, day, hour, minute, utc) = fields(t, zone);
+    format!("{:04}-{:02}-{:02} {:02}:{:02}{}",
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            if utc { " UTC" } else { "" })
+}
+
+/// Shows the day of a timestamp in the local timezone (or UTC), like "2021-03-04".
+pub fn to_local_date(t: ffi::time_t) -> String {
+    to_date_in(t, Zone::Local)
+}
+
+/// Like `to_local_date`, in any zone.
+pub fn to_date_in(t: ffi::time_t, zone: Zone) -> String {
+    let (year, month, day, _, _, _) = fields(t, zone);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Tells how long ago a timestamp was, roughly, like "3 months ago".
+pub fn to_relative_string(t: ffi::time_t, now: ffi::time_t) -> String {
+    let seconds = now as i64 - t as i64;
+    if seconds < 0 {
+        return "in the future".to_owned();
+    }
+
+    let units = [(365 * SECONDS_PER_DAY, "year"),
+                 (30 * SECONDS_PER_DAY, "month"),
+                 (7 * SECONDS_PER_DAY, "week"),
+                 (SECONDS_PER_DAY, "day"),
+                 (3600, "hour"),
+                 (60, "minute")];
+    for &(unit_seconds, unit) in units.iter() {
+        let count = seconds / unit_seconds;
+        if count > 0 {
+            return format!("{} {}{} ago", count, unit, if count == 1 { "" } else { "s" });
+        }
+    }
+    "just now".to_owned()
+}
+
+/// Shows the day of a timestamp in `zone` and how long ago it was, like
+/// "2021-03-04 (3 months ago)".
+pub fn to_date_and_age(t: ffi::time_t, now: ffi::time_t, zone: Zone) -> String {
+    format!("{} ({})", to_date_in(t, zone), to_relative_string(t, now))
+}
+
+struct TimestampVisitor;
+
+impl<'de> Visitor<'de> for TimestampVisitor {
+    type Value = ffi::time_t;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a number of seconds since the epoch or an RFC3339 date")
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E>
+        where E: Error
+    {
+        if v > ffi::time_t::max_value() as u64 {
+            return Err(E::custom(format!("{} is out of the supported range of dates", v)));
+        }
+        Ok(v as ffi::time_t)
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E>
+        where E: Error
+    {
+        to_time_t(v).map_err(E::custom)
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+        where E: Error
+    {
+        parse(v).map_err(E::custom)
+    }
+}
+
+/// For use with `#[serde(deserialize_with = "timestamp::deserialize")]`.
+pub fn deserialize<'de, D>(deserializer: D) -> Result<ffi::time_t, D::Error>
+    where D: Deserializer<'de>
+{
+    deserializer.deserialize_any(TimestampVisitor)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{civil_from_days, days_from_civil, parse, parse_date_or_duration, parse_duration,
+                to_date_and_age, to_date_in, to_relative_string, to_string_in, Zone};
+
+    #[test]
+    fn civil_days_round_trip() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2000, 3, 1), 11017);
+        for days in -1000..30000 {
+            let (y, m, d) = civil_from_days(days);
+            assert_eq!(days_from_civil(y, m, d), days);
+        }
+    }
+
+    #[test]
+    fn parse_epoch_seconds() {
+        assert_eq!(parse("0"), Ok(0));
+        assert_eq!(parse(" 1614853230 "), Ok(1614853230));
+        assert!(parse("-1").is_err());
+        assert!(parse("99999999999").is_err());
+    }
+
+    #[test]
+    fn parse_rfc3339_with_any_offset() {
+        assert_eq!(parse("2021-03-04T10:20:30Z"), Ok(1614853230));
+        assert_eq!(parse("2021-03-04t10:20:30z"), Ok(1614853230));
+        assert_eq!(parse("2021-03-04T19:20:30+09:00"), Ok(1614853230));
+        assert_eq!(parse("2021-03-04T05:50:30-04:30"), Ok(1614853230));
+        assert_eq!(parse("2021-03-04T10:20:30.999Z"), Ok(1614853230));
+        assert_eq!(parse("2020-02-29T00:00:00Z"), Ok(1582934400));
+    }
+
+    #[test]
+    fn parse_invalid_dates() {
+        assert!(parse("").is_err());
+        assert!(parse("2021-03-04").is_err());
+        assert!(parse("2021-03-04T10:20:30").is_err());
+        assert!(parse("2021-02-29T10:20:30Z").is_err());
+        assert!(parse("2021-13-01T10:20:30Z").is_err());
+        assert!(parse("2021-03-04T24:00:00Z").is_err());
+        assert!(parse("2021-03-04T10:20:30+0900").is_err());
+        assert!(parse("2021-03-04T10:20:30.Z").is_err());
+        assert!(parse("1969-12-31T23:59:59Z").is_err());
+        assert!(parse("2021-03-04T10:20:30Zé").is_err());
+    }
+
+    #[test]
+    fn parse_durations() {
+        assert_eq!(parse_duration("1d"), Ok(86400));
+        assert_eq!(parse_duration("90d"), Ok(90 * 86400));
+        assert_eq!(parse_duration("2w"), Ok(14 * 86400));
+        assert_eq!(parse_duration(" 6m "), Ok(180 * 86400));
+        assert_eq!(parse_duration("1y"), Ok(365 * 86400));
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("90").is_err());
+        assert!(parse_duration("d").is_err());
+        assert!(parse_duration("-1d").is_err());
+        assert!(parse_duration("1h").is_err());
+        assert!(parse_duration("1é").is_err());
+        assert!(parse_duration("99999999999999999y").is_err());
+    }
+
+    #[test]
+    fn parse_dates_or_durations() {
+        let now = 1614853230;
+        assert_eq!(parse_date_or_duration("2020-01-01", now), Ok(1577836800));
+        assert_eq!(parse_date_or_duration(" 2020-02-29 ", now), Ok(1582934400));
+        assert_eq!(parse_date_or_duration("90d", now), Ok(now - 90 * 86400));
+        assert_eq!(parse_date_or_duration("1y", now), Ok(now - 365 * 86400));
+        assert_eq!(parse_date_or_duration("2021-03-04T10:20:30Z", now), Ok(1614853230));
+
+        assert!(parse_date_or_duration("", now).is_err());
+        assert!(parse_date_or_duration("2021-02-29", now).is_err());
+        assert!(parse_date_or_duration("2021-13-01", now).is_err());
+        assert!(parse_date_or_duration("2021-1-01", now).is_err());
+        assert!(parse_date_or_duration("21-01-2021", now).is_err());
+        assert!(parse_date_or_duration("1969-12-31", now).is_err());
+        assert!(parse_date_or_duration("yesterday", now).is_err());
+        assert!(parse_date_or_duration("90", now).is_err());
+        assert!(parse_date_or_duration("2021-é-01", now).is_err());
+        assert!(parse_date_or_duration("-90d", now).is_err());
+        assert!(parse_date_or_duration("100y", now).is_err());
+        assert!(parse_date_or_duration("2021-03-0é", now).is_err());
+    }
+
+    #[test]
+    fn relative_strings() {
+        let now = 1614853230;
+        assert_eq!(to_relative_string(now, now), "just now");
+        assert_eq!(to_relative_string(now - 59, now), "just now");
+        assert_eq!(to_relative_string(now - 60, now), "1 minute ago");
+        assert_eq!(to_relative_string(now - 3 * 3600 - 5, now), "3 hours ago");
+        assert_eq!(to_relative_string(now - 86400, now), "1 day ago");
+        assert_eq!(to_relative_string(now - 13 * 86400, now), "1 week ago");
+        assert_eq!(to_relative_string(now - 95 * 86400, now), "3 months ago");
+        assert_eq!(to_relative_string(now - 800 * 86400, now), "2 years ago");
+        assert_eq!(to_relative_string(now + 10, now), "in the future");
+    }
+
+    #[test]
+    fn dates_with_their_age() {
+        let now = 1614859200;
+        let utc = Zone::Offset(0);
+        assert_eq!(to_date_and_age(now - 2 * 86400, now, utc), "2021-03-02 (2 days ago)");
+        assert_eq!(to_date_and_age(now, now, utc), "2021-03-04 (just now)");
+        assert_eq!(to_date_and_age(now, now, Zone::Offset(13 * 3600)), "2021-03-05 (just now)");
+    }
+
+    #[test]
+    fn timezones_only_change_the_rendering() {
+        use password::v2::Password;
+        use serde_json;
+
+        let json = "{\"name\":\"a\",\"username\":\"b\",\"password\":\"c\",\
+                    \"created_at\":\"2021-03-04T19:20:30+09:00\",\"updated_at\":1614853230}";
+        let expected = "{\"name\":\"a\",\"username\":\"b\",\"password\":\"c\",\
+                        \"created_at\":1614853230,\"updated_at\":1614853230}";
+
+        let p: Password = serde_json::from_str(json).unwrap();
+        assert_eq!(p.created_at, 1614853230);
+        assert_eq!(p.updated_at, 1614853230);
+        assert_eq!(serde_json::to_string(&p).unwrap(), expected);
+
+        let zones = [(0, "2021-03-04 10:20"),
+                     (9 * 3600, "2021-03-04 19:20"),
+                     (-5 * 3600, "2021-03-04 05:20"),
+                     (5 * 3600 + 45 * 60, "2021-03-04 16:05"),
+                     (-11 * 3600, "2021-03-03 23:20")];
+        for &(offset, rendered) in zones.iter() {
+            assert_eq!(to_string_in(p.created_at, Zone::Offset(offset)), rendered);
+            assert_eq!(to_date_in(p.created_at, Zone::Offset(offset)), &rendered[..10]);
+        }
+    }
+}
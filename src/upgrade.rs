@@ -0,0 +1,121 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Asking before upgrading old password files. The upgrades themselves are in `password`.
+
+use answer;
+use password::{self, PasswordError};
+use password::v2::{self, PasswordStore};
+use safe_string::SafeString;
+use safe_vec::SafeVec;
+use std::io::Write;
+
+pub fn upgrade(master_password: SafeString,
+               input: SafeVec)
+               -> Result<PasswordStore, PasswordError> {
+    // If we can't read v1 passwords, we have a hard error, because we previously tried
+    // to read the passwords as v2. Which failed. That means we can't upgrade.
+    let v1_passwords = password::v1::get_all_passwords(master_password.expose(), input.expose())?;
+
+    println_stderr!("Your Rooster file has version 1. You need to upgrade to version 2.");
+    println_stderr!("");
+    println_stderr!("WARNING: If in doubt, it could mean you've been hacked. Only");
+    println_stderr!("proceed if you recently upgraded your Rooster installation.");
+    println_stderr!("");
+    println_stderr!("Upgrade to version 2? [y/n]");
+    loop {
+        let mut line = String::new();
+        match answer::read_line(&mut line) {
+            Ok(_) => {
+                if line.starts_with('y') {
+                    break;
+                } else if line.starts_with('n') {
+                    return Err(PasswordError::WrongVersionError);
+                } else {
+                    println_stderr!("I did not get that. Upgrade from v1 to v2? [y/n]");
+                }
+            }
+            Err(io_err) => {
+                return Err(PasswordError::Io(io_err));
+            }
+        }
+    }
+
+    // Upgrade from v1 to v2 if we could read v1 passwords.
+    password::upgrade_v1_v2(&v1_passwords, master_password)
+}
+
+/// Offers to derive the key of a version 2 or 3 file with Argon2id from now on, which makes
+/// guessing the master password much slower. The file is upgraded in place when the store is
+/// saved, so the caller only offers it to commands that save, on a terminal. Old files keep
+/// working if the answer is no, and the answer is saved so that it isn't asked again.
+pub fn offer_upgrade_v2_v3(master_password: &SafeString, store: &mut PasswordStore) {
+    // Files with other scrypt params were set up with `rooster set-kdf`.
+    if store.kdf() != v2::DEFAULT_SCRYPT || store.settings().kdf_upgrade_declined ||
+       answer::is_non_interactive() {
+        return;
+    }
+
+    println_stderr!("Your Rooster file derives its key with scrypt, which is getting weak against");
+    println_stderr!("attackers with GPUs. Rooster can use Argon2id instead, but older versions of");
+    println_stderr!("Rooster won't be able to open the file anymore.");
+    println_stderr!("");
+    println_stderr!("Upgrade to Argon2id? [y/n]");
+    loop {
+        let mut line = String::new();
+        match answer::read_line(&mut line) {
+            Ok(_) if line.starts_with('y') => break,
+            Ok(_) if line.starts_with('n') => {
+                store.decline_kdf_upgrade();
+                println_stderr!("I won't ask again. You can still upgrade with `rooster set-kdf \
+                                 --argon2id`.");
+                return;
+            }
+            Ok(0) | Err(_) => return,
+            Ok(_) => println_stderr!("I did not get that. Upgrade to Argon2id? [y/n]"),
+        }
+    }
+
+    store.upgrade_key_derivation(master_password.expose());
+}
+
+#[cfg(test)]
+mod test {
+    use super::offer_upgrade_v2_v3;
+    use answer;
+    use commands::harness;
+    use console;
+    use password::v2;
+    use safe_string::SafeString;
+
+    #[test]
+    fn refusing_the_argon2id_upgrade_is_remembered() {
+        let master = SafeString::new("correct horse battery staple".to_owned());
+        let mut store = harness::store(&[]);
+        store.change_key_derivation(master.expose(), v2::DEFAULT_SCRYPT).unwrap();
+        assert!(!store.settings().kdf_upgrade_declined);
+
+        answer::script(Some(&["n"]));
+        let (_, captured) = console::capture(|| offer_upgrade_v2_v3(&master, &mut store));
+        assert!(captured.stderr.contains("Upgrade to Argon2id?"), "{}", captured.stderr);
+        assert!(store.settings().kdf_upgrade_declined);
+        assert!(store.is_modified());
+        assert_eq!(store.kdf(), v2::DEFAULT_SCRYPT);
+
+        answer::script(Some(&[]));
+        let (_, captured) = console::capture(|| offer_upgrade_v2_v3(&master, &mut store));
+        answer::script(None);
+        assert_eq!(captured.stderr, "");
+    }
+}
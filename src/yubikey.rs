@@ -0,0 +1,126 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! Challenge-response with the HMAC-SHA1 slot of a YubiKey, through `ykchalresp` from
+//! yubikey-personalization. Only built with the `yubikey` feature: otherwise, password files
+//! that need a YubiKey can't be opened.
+
+use safe_vec::SafeVec;
+
+/// The slot of the YubiKey set up for challenge-response, 2 unless told otherwise.
+pub const YUBIKEY_SLOT_ENV_VAR: &'static str = "ROOSTER_YUBIKEY_SLOT";
+
+#[cfg(feature = "yubikey")]
+const DEFAULT_SLOT: &'static str = "2";
+
+/// How long to wait for the YubiKey to be touched.
+#[cfg(feature = "yubikey")]
+const TOUCH_TIMEOUT_SECS: u64 = 30;
+
+pub fn is_supported() -> bool {
+    cfg!(feature = "yubikey")
+}
+
+/// Sends the challenge to the YubiKey, which answers once it is touched.
+#[cfg(feature = "yubikey")]
+pub fn challenge_response(challenge: &[u8]) -> Result<SafeVec, String> {
+    use askpass;
+    use quale::which;
+    use std::env;
+    use std::io::{Read, Write};
+    use std::process::{Command, Stdio};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    let program = which("ykchalresp").ok_or_else(|| {
+        "ykchalresp isn't installed, it comes with yubikey-personalization".to_owned()
+    })?;
+    let slot = env::var(YUBIKEY_SLOT_ENV_VAR).unwrap_or_else(|_| DEFAULT_SLOT.to_owned());
+    if slot != "1" && slot != "2" {
+        return Err(format!("${} can be 1 or 2, not \"{}\"", YUBIKEY_SLOT_ENV_VAR, slot));
+    }
+    let challenge: String = challenge.iter().map(|byte| format!("{:02x}", byte)).collect();
+
+    println_stderr_always!("Touch your YubiKey...");
+    let mut child = Command::new(program).arg(format!("-{}", slot))
+        .arg("-x")
+        .arg(challenge)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| format!("I couldn't run ykchalresp (reason: {})", err))?;
+
+    let started = Instant::now();
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if started.elapsed() >= Duration::from_secs(TOUCH_TIMEOUT_SECS) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err("the YubiKey wasn't touched in time".to_owned());
+            }
+            Ok(None) => thread::sleep(Duration::from_millis(100)),
+            Err(err) => return Err(format!("I couldn't wait for ykchalresp (reason: {})", err)),
+        }
+    };
+
+    if !status.success() {
+        let mut errors = String::new();
+        let _ = child.stderr.take().unwrap().read_to_string(&mut errors);
+        if errors.to_lowercase().contains("no yubikey present") {
+            return Err("no YubiKey is plugged in".to_owned());
+        }
+        return Err(format!("ykchalresp failed ({})", errors.trim()));
+    }
+
+    let response = askpass::read_secret_line(child.stdout.as_mut().unwrap())?;
+    decode_hex(response.expose().trim())
+        .ok_or_else(|| "ykchalresp gave an answer I don't understand".to_owned())
+}
+
+#[cfg(not(feature = "yubikey"))]
+pub fn challenge_response(_challenge: &[u8]) -> Result<SafeVec, String> {
+    Err("this Rooster was built without YubiKey support, rebuild it with `cargo install rooster \
+         --features yubikey`"
+        .to_owned())
+}
+
+/// Decodes the hexadecimal response of the YubiKey.
+#[cfg(feature = "yubikey")]
+fn decode_hex(hex: &str) -> Option<SafeVec> {
+    if hex.is_empty() || hex.len() % 2 != 0 {
+        return None;
+    }
+    let mut bytes = SafeVec::new(Vec::with_capacity(hex.len() / 2));
+    for i in (0..hex.len()).step_by(2) {
+        let byte = hex.get(i..i + 2).and_then(|pair| u8::from_str_radix(pair, 16).ok())?;
+        bytes.inner_mut().push(byte);
+    }
+    Some(bytes)
+}
+
+#[cfg(all(test, feature = "yubikey"))]
+mod test {
+    use super::decode_hex;
+
+    #[test]
+    fn decodes_responses() {
+        assert_eq!(decode_hex("00ff7a").unwrap().expose(), &[0, 255, 0x7a]);
+        assert!(decode_hex("0").is_none());
+        assert!(decode_hex("zz").is_none());
+        assert!(decode_hex("").is_none());
+    }
+}
@@ -0,0 +1,83 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! `rooster add-or-update` is meant to be run again and again by scripts, so running it twice
+//! with the same input must not touch the password file.
+
+mod common;
+
+use common::{create_password_file, password_file, rooster, test_dir, MASTER_PASSWORD};
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+
+fn add_or_update(dir: &Path, username: &str, password: &str) -> String {
+    let output = rooster(dir,
+                         &["add-or-update", "YouTube", username, "--password-stdin", "--json"],
+                         &format!("{}\n{}\n", MASTER_PASSWORD, password));
+    assert!(output.success, "{}", output.stderr);
+    output.stdout.trim().to_owned()
+}
+
+fn file_bytes(dir: &Path) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    File::open(password_file(dir)).unwrap().read_to_end(&mut bytes).unwrap();
+    bytes
+}
+
+#[test]
+fn created_then_unchanged_then_updated() {
+    let dir = test_dir("add-or-update");
+    create_password_file(&dir);
+
+    assert_eq!(add_or_update(&dir, "me@example.com", "first"),
+               r#"{"result":"created","changed":[]}"#);
+
+    let before = file_bytes(&dir);
+    assert_eq!(add_or_update(&dir, "me@example.com", "first"),
+               r#"{"result":"unchanged","changed":[]}"#);
+    assert!(file_bytes(&dir) == before, "an identical run rewrote the password file");
+
+    assert_eq!(add_or_update(&dir, "me@example.com", "second"),
+               r#"{"result":"updated","changed":["password"]}"#);
+    assert_eq!(add_or_update(&dir, "you@example.com", "third"),
+               r#"{"result":"updated","changed":["username","password"]}"#);
+
+    let output = rooster(&dir, &["get", "YouTube", "--show"], &format!("{}\n", MASTER_PASSWORD));
+    assert!(output.stderr.contains("third"), "{}", output.stderr);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn never_prompts() {
+    let dir = test_dir("add-or-update-no-prompt");
+    create_password_file(&dir);
+
+    // Without --password-stdin, the password would have to be asked.
+    let output = rooster(&dir,
+                         &["add-or-update", "YouTube", "me"],
+                         &format!("{}\nsecret\n", MASTER_PASSWORD));
+    assert!(!output.success);
+
+    // A missing password line fails instead of waiting.
+    let output = rooster(&dir,
+                         &["add-or-update", "YouTube", "me", "--password-stdin"],
+                         &format!("{}\n", MASTER_PASSWORD));
+    assert!(!output.success);
+    assert!(output.stderr.contains("no password on stdin"), "{}", output.stderr);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
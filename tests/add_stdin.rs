@@ -0,0 +1,92 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! When `rooster add` is piped, the password is the first line of stdin, kept as is, and the
+//! master password comes from anywhere but stdin.
+
+mod common;
+
+use common::{create_password_file, rooster, rooster_with_env, test_dir, MASTER_PASSWORD};
+use std::fs;
+
+#[test]
+fn stores_the_piped_line_verbatim() {
+    let dir = test_dir("add-stdin");
+    create_password_file(&dir);
+    let env = [("ROOSTER_MASTER_PASSWORD", MASTER_PASSWORD)];
+
+    let output = rooster_with_env(&dir,
+                                  &["add", "Deploy", "ci", "--show"],
+                                  "  spaced out \t\n\nignored\n",
+                                  &env);
+    assert!(output.success, "{}", output.stderr);
+
+    let output = rooster_with_env(&dir,
+                                  &["add", "Backup", "ci", "--password-stdin", "--show"],
+                                  "second\r\n",
+                                  &env);
+    assert!(output.success, "{}", output.stderr);
+
+    let output = rooster_with_env(&dir, &["get", "Deploy", "--no-newline"], "", &env);
+    assert_eq!(output.stdout, "  spaced out \t");
+    let output = rooster_with_env(&dir, &["get", "Backup", "--no-newline"], "", &env);
+    assert_eq!(output.stdout, "second");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn never_reads_the_master_password_from_stdin() {
+    let dir = test_dir("add-stdin-master");
+    create_password_file(&dir);
+
+    for args in &[&["--master-password-stdin", "add", "Deploy", "ci"][..],
+                  &["--master-password-file", "-", "add", "Deploy", "ci", "--password-stdin"]] {
+        let output = rooster(&dir, args, &format!("{}\nsecret\n", MASTER_PASSWORD));
+        assert_eq!(output.code, Some(2), "{}", output.stderr);
+        assert!(output.stderr.contains("can't come from there too"), "{}", output.stderr);
+    }
+
+    // Without a terminal to type it in, the master password has to be given some other way.
+    let output = rooster(&dir,
+                         &["--non-interactive", "add", "Deploy", "ci"],
+                         &format!("{}\nsecret\n", MASTER_PASSWORD));
+    assert_eq!(output.code, Some(3), "{}", output.stderr);
+    assert!(output.stderr.contains("would ask for the master password"), "{}", output.stderr);
+
+    let env = [("ROOSTER_MASTER_PASSWORD", MASTER_PASSWORD)];
+    let output = rooster_with_env(&dir, &["list"], "", &env);
+    assert!(!output.stdout.contains("Deploy"), "{}", output.stdout);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn refuses_an_empty_password() {
+    let dir = test_dir("add-stdin-empty");
+    create_password_file(&dir);
+    let env = [("ROOSTER_MASTER_PASSWORD", MASTER_PASSWORD)];
+
+    let output = rooster_with_env(&dir, &["add", "Deploy", "ci"], "\n", &env);
+    assert!(!output.success);
+    assert!(output.stderr.contains("no password on the first line of stdin"),
+            "{}",
+            output.stderr);
+
+    let output = rooster(&dir, &["add", "Deploy", "ci", "--password-stdin", "--generate"], "");
+    assert!(!output.success);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
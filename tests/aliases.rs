@@ -0,0 +1,59 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! Aliases like `ls` and `rm` run the commands they stand for.
+
+mod common;
+
+use common::{create_password_file, rooster, rooster_with_env, test_dir, MASTER_PASSWORD};
+
+#[test]
+fn aliases_run_their_command() {
+    let dir = test_dir("aliases");
+    create_password_file(&dir);
+    let output = rooster(&dir,
+                         &["add-or-update", "GitHub", "me", "--password-stdin"],
+                         &format!("{}\nsecret\n", MASTER_PASSWORD));
+    assert!(output.success, "{}", output.stderr);
+
+    let env = [("ROOSTER_MASTER_PASSWORD", MASTER_PASSWORD)];
+    let output = rooster_with_env(&dir, &["-q", "ls", "--porcelain"], "", &env);
+    assert_eq!(output.stdout, "GitHub\tme\n");
+    let output = rooster_with_env(&dir, &["-q", "show", "GitHub", "--stdout"], "", &env);
+    assert_eq!(output.stdout, "secret\n");
+    let output = rooster_with_env(&dir, &["-q", "rm", "GitHub"], "", &env);
+    assert!(output.success, "{}", output.stderr);
+    let output = rooster_with_env(&dir, &["-q", "ls", "--porcelain"], "", &env);
+    assert_eq!(output.stdout, "");
+}
+
+#[test]
+fn help_of_an_alias_is_the_help_of_its_command() {
+    let dir = test_dir("aliases-help");
+    let output = rooster(&dir, &["rm", "--help"], "");
+    assert!(output.success, "{}", output.stderr);
+    assert_eq!(output.stdout, rooster(&dir, &["delete", "--help"], "").stdout);
+
+    let output = rooster(&dir, &["--help"], "");
+    assert!(output.stdout.contains("delete (rm, del)"), "{}", output.stdout);
+}
+
+#[test]
+fn suggests_the_closest_command() {
+    let dir = test_dir("aliases-typo");
+    let output = rooster(&dir, &["lsit"], "");
+    assert!(!output.success);
+    assert!(output.stderr.contains("Did you mean `list`?"), "{}", output.stderr);
+}
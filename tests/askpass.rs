@@ -0,0 +1,115 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! Without a terminal (and here, without anything on stdin), the master password is asked with
+//! an askpass program or with pinentry.
+
+#![cfg(unix)]
+
+mod common;
+
+use common::{create_password_file, rooster, rooster_with_env, test_dir, MASTER_PASSWORD};
+use std::fs;
+use std::os::unix::fs::{symlink, PermissionsExt};
+use std::path::Path;
+
+fn script(path: &Path, content: &str) {
+    fs::write(path, format!("#!/bin/sh\n{}", content)).unwrap();
+    fs::set_permissions(path, fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+fn add_password(dir: &Path) {
+    create_password_file(dir);
+    let output = rooster(dir,
+                         &["add-or-update", "GitHub", "me", "--password-stdin"],
+                         &format!("{}\nsecret\n", MASTER_PASSWORD));
+    assert!(output.success, "{}", output.stderr);
+}
+
+#[test]
+fn asks_with_an_askpass_program() {
+    let dir = test_dir("askpass");
+    add_password(&dir);
+    let askpass = dir.join("askpass");
+    script(&askpass, &format!("echo '{}'\n", MASTER_PASSWORD));
+    let askpass = askpass.to_string_lossy().into_owned();
+
+    let output = rooster_with_env(&dir,
+                                  &["get", "GitHub", "--stdout"],
+                                  "",
+                                  &[("ROOSTER_ASKPASS", &askpass)]);
+    assert!(output.success, "{}", output.stderr);
+    assert_eq!(output.stdout, "secret\n");
+
+    let output = rooster_with_env(&dir,
+                                  &["get", "GitHub", "--stdout"],
+                                  "",
+                                  &[("SSH_ASKPASS", &askpass)]);
+    assert!(output.success, "{}", output.stderr);
+
+    let output = rooster_with_env(&dir,
+                                  &["--no-askpass", "get", "GitHub", "--stdout"],
+                                  "",
+                                  &[("ROOSTER_ASKPASS", &askpass)]);
+    assert!(!output.success);
+    assert_eq!(output.stdout, "");
+}
+
+#[test]
+fn fails_when_askpass_is_cancelled() {
+    let dir = test_dir("askpass-cancelled");
+    add_password(&dir);
+    let askpass = dir.join("askpass");
+    script(&askpass, "exit 1\n");
+    let askpass = askpass.to_string_lossy().into_owned();
+
+    let output = rooster_with_env(&dir,
+                                  &["get", "GitHub", "--stdout"],
+                                  "",
+                                  &[("ROOSTER_ASKPASS", &askpass)]);
+    assert!(!output.success);
+    assert!(output.stderr.contains("could not read your master password"),
+            "{}",
+            output.stderr);
+}
+
+#[test]
+fn asks_with_pinentry() {
+    let dir = test_dir("askpass-pinentry");
+    add_password(&dir);
+    let tools = dir.join("tools");
+    fs::create_dir_all(&tools).unwrap();
+    symlink("/bin/sh", tools.join("sh")).unwrap();
+    // Answers every command with OK, and GETPIN with an encoded master password.
+    let pin = MASTER_PASSWORD.replace('%', "%25");
+    script(&tools.join("pinentry"),
+           &format!("echo 'OK Pleased to meet you'\n\
+                     while read -r command rest; do\n\
+                     case \"$command\" in\n\
+                     GETPIN) echo '# a comment'; echo 'D {}'; echo OK ;;\n\
+                     BYE) echo OK; exit 0 ;;\n\
+                     *) echo OK ;;\n\
+                     esac\n\
+                     done\n",
+                    pin));
+    let path = tools.to_string_lossy().into_owned();
+
+    let output = rooster_with_env(&dir,
+                                  &["get", "GitHub", "--stdout"],
+                                  "",
+                                  &[("PATH", &path)]);
+    assert!(output.success, "{}", output.stderr);
+    assert_eq!(output.stdout, "secret\n");
+}
@@ -0,0 +1,96 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! `rooster batch` runs many commands with one unlock, and only writes the file once.
+
+mod common;
+
+use common::{create_password_file, password_file, rooster, rooster_with_env, test_dir,
+             MASTER_PASSWORD};
+use std::fs;
+use std::path::Path;
+
+fn names(dir: &Path) -> String {
+    let env = [("ROOSTER_MASTER_PASSWORD", MASTER_PASSWORD)];
+    rooster_with_env(dir, &["list", "--names-only"], "", &env).stdout
+}
+
+fn show(dir: &Path, name: &str) -> String {
+    let env = [("ROOSTER_MASTER_PASSWORD", MASTER_PASSWORD)];
+    rooster_with_env(dir, &["get", "--exact", name, "--stdout"], "", &env).stdout
+}
+
+#[test]
+fn runs_every_line_with_one_unlock() {
+    let dir = test_dir("batch");
+    create_password_file(&dir);
+    let input = format!("{}\n\
+                         # New hire\n\
+                         add GitHub alice 'correct horse'\n\
+                         \n\
+                         add-or-update \"My \\\"bank\\\"\" alice s3cr3t\n\
+                         generate Slack alice --length 12\n\
+                         tag GitHub +work\n",
+                        MASTER_PASSWORD);
+    let output = rooster(&dir, &["batch"], &input);
+    assert!(output.success, "{}", output.stderr);
+    assert!(output.stderr.contains("All 4 line(s) succeeded"), "{}", output.stderr);
+
+    assert_eq!(names(&dir), "GitHub\nMy \"bank\"\nSlack\n");
+    assert_eq!(show(&dir, "GitHub"), "correct horse\n");
+    assert_eq!(show(&dir, "My \"bank\""), "s3cr3t\n");
+    assert_eq!(show(&dir, "Slack").trim().len(), 12);
+}
+
+#[test]
+fn stops_at_the_first_failure_without_saving() {
+    let dir = test_dir("batch-abort");
+    create_password_file(&dir);
+    let before = fs::read(password_file(&dir)).unwrap();
+    let input = format!("{}\nadd GitHub alice one\nadd GitHub bob two\nadd Slack alice three\n",
+                        MASTER_PASSWORD);
+    let output = rooster(&dir, &["batch"], &input);
+    assert_eq!(output.code, Some(7), "{}", output.stderr);
+    assert!(output.stderr.contains("Lines that succeeded: 1."), "{}", output.stderr);
+    assert!(output.stderr.contains("Lines that failed: 2."), "{}", output.stderr);
+    assert!(output.stderr.contains("Lines that didn't run: 3."), "{}", output.stderr);
+    assert!(output.stderr.contains("Nothing was saved."), "{}", output.stderr);
+    assert_eq!(fs::read(password_file(&dir)).unwrap(), before);
+}
+
+#[test]
+fn keeps_going_and_saves_what_succeeded() {
+    let dir = test_dir("batch-keep-going");
+    create_password_file(&dir);
+    let input = format!("{}\nadd GitHub alice one\nfrobnicate\nadd 'unclosed\nadd Slack bob two\n",
+                        MASTER_PASSWORD);
+    let output = rooster(&dir, &["batch", "--keep-going", "--checkpoint", "1"], &input);
+    assert_eq!(output.code, Some(2), "{}", output.stderr);
+    assert!(output.stderr.contains("Lines that succeeded: 1, 4."), "{}", output.stderr);
+    assert!(output.stderr.contains("Lines that failed: 2, 3."), "{}", output.stderr);
+    assert_eq!(names(&dir), "GitHub\nSlack\n");
+}
+
+#[test]
+fn rejects_nested_batches_and_bad_checkpoints() {
+    let dir = test_dir("batch-args");
+    create_password_file(&dir);
+    let output = rooster(&dir, &["batch", "--checkpoint", "zero"], "");
+    assert_eq!(output.code, Some(2), "{}", output.stderr);
+
+    let input = format!("{}\nbatch\n", MASTER_PASSWORD);
+    let output = rooster(&dir, &["batch"], &input);
+    assert!(output.stderr.contains("batch can't be used in a batch"), "{}", output.stderr);
+}
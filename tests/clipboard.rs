@@ -0,0 +1,146 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! Which clipboard tool gets the password: wl-copy on Wayland, xsel or xclip otherwise. The
+//! tools are fake ones, which write down how they were called.
+
+#![cfg(unix)]
+
+mod common;
+
+use common::{create_password_file, rooster, rooster_with_env, test_dir, Output,
+             MASTER_PASSWORD};
+use std::fs;
+use std::os::unix::fs::{symlink, PermissionsExt};
+use std::path::Path;
+
+/// Puts a fake clipboard tool in `tools`, which logs its name, arguments and input.
+fn fake_tool(tools: &Path, name: &str) {
+    let path = tools.join(name);
+    let log = tools.join("log");
+    fs::write(&path,
+              format!("#!/bin/sh\nIFS= read -r input\nprintf '%s %s: %s\\n' {} \"$*\" \"$input\" \
+                       >> '{}'\n",
+                      name,
+                      log.display()))
+        .unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+/// Runs rooster with only the given tools installed, and returns what they logged.
+fn run_with(dir: &Path, args: &[&str], tools: &[&str], vars: &[(&str, &str)]) -> (Output, String) {
+    let tools_dir = dir.join("tools");
+    let _ = fs::remove_dir_all(&tools_dir);
+    fs::create_dir_all(&tools_dir).unwrap();
+    symlink("/bin/sh", tools_dir.join("sh")).unwrap();
+    for tool in tools {
+        fake_tool(&tools_dir, tool);
+    }
+
+    let path = tools_dir.to_string_lossy().into_owned();
+    let mut vars = vars.to_vec();
+    vars.push(("PATH", path.as_str()));
+    let output = rooster_with_env(dir, args, &format!("{}\n", MASTER_PASSWORD), &vars);
+    let log = fs::read_to_string(tools_dir.join("log")).unwrap_or_default();
+    (output, log)
+}
+
+/// Copies the password, and returns whether it worked and what the tools logged.
+fn copy_with(dir: &Path, tools: &[&str], vars: &[(&str, &str)]) -> (bool, String) {
+    let (output, log) = run_with(dir, &["get", "GitHub"], tools, vars);
+    (output.stderr.contains("You can paste your GitHub password anywhere"), log)
+}
+
+#[test]
+fn picks_the_clipboard_tool() {
+    let dir = test_dir("clipboard");
+    create_password_file(&dir);
+    let output = rooster(&dir,
+                         &["add-or-update", "GitHub", "me", "--password-stdin"],
+                         &format!("{}\nsecret\n", MASTER_PASSWORD));
+    assert!(output.success, "{}", output.stderr);
+    let wayland = [("WAYLAND_DISPLAY", "wayland-0")];
+
+    assert_eq!(copy_with(&dir, &["wl-copy", "xsel"], &wayland),
+               (true, "wl-copy : secret\n".to_owned()));
+    assert_eq!(copy_with(&dir, &["wl-copy", "xclip"], &[("WAYLAND_DISPLAY", "wayland-0"),
+                                                        ("ROOSTER_SELECTION", "primary")]),
+               (true, "wl-copy --primary: secret\n".to_owned()));
+
+    // Without Wayland, or without wl-copy, X11 tools are used.
+    assert_eq!(copy_with(&dir, &["wl-copy", "xsel"], &[("DISPLAY", ":0")]),
+               (true, "xsel -ib: secret\n".to_owned()));
+    assert_eq!(copy_with(&dir, &["xclip"], &wayland),
+               (true, "xclip -selection clipboard: secret\n".to_owned()));
+
+    // Inside tmux without a display, the password goes to a tmux buffer through stdin.
+    assert_eq!(copy_with(&dir, &["tmux", "xsel"], &[("TMUX", "/tmp/tmux-1000/default,1,0")]),
+               (false, "tmux load-buffer -b rooster -: secret\n".to_owned()));
+
+    // Without any tool, the password can still be shown.
+    assert_eq!(copy_with(&dir, &[], &wayland), (false, String::new()));
+    let output = rooster(&dir, &["get", "GitHub"], &format!("{}\n", MASTER_PASSWORD));
+    assert!(output.stderr.contains("rooster get 'GitHub' --show"), "{}", output.stderr);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn types_with_xdotool() {
+    let dir = test_dir("xdotool");
+    create_password_file(&dir);
+    let output = rooster(&dir,
+                         &["add-or-update", "GitHub", "me", "--password-stdin"],
+                         &format!("{}\nsecret\n", MASTER_PASSWORD));
+    assert!(output.success, "{}", output.stderr);
+
+    let (output, log) = run_with(&dir,
+                                 &["get", "GitHub", "--type", "--type-delay", "0"],
+                                 &["xdotool"],
+                                 &[]);
+    assert!(output.success, "{}", output.stderr);
+    assert_eq!(log, "xdotool type --clearmodifiers --file -: secret\n");
+
+    let (output, log) = run_with(&dir,
+                                 &["get", "GitHub", "--type", "--type-delay", "0",
+                                   "--press-enter"],
+                                 &["xdotool"],
+                                 &[]);
+    assert!(output.success, "{}", output.stderr);
+    assert_eq!(log,
+               "xdotool type --clearmodifiers --file -: secret\nxdotool key --clearmodifiers \
+                Return: \n");
+
+    let (output, _) = run_with(&dir, &["get", "GitHub", "--type", "--type-delay", "0"], &[], &[]);
+    assert!(!output.success);
+    assert!(output.stderr.contains("can't find it"), "{}", output.stderr);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn clears_every_selection() {
+    let dir = test_dir("clear");
+
+    let (output, log) = run_with(&dir, &["clear"], &["xsel", "tmux"], &[("TMUX", "tmux")]);
+    assert!(output.success, "{}", output.stderr);
+    assert_eq!(log, "xsel -ib: \nxsel -ip: \ntmux delete-buffer -b rooster: \n");
+
+    let (output, _) = run_with(&dir, &["clear"], &[], &[]);
+    assert!(!output.success);
+    assert!(output.stderr.contains("couldn't reach any clipboard"), "{}", output.stderr);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
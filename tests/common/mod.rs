@@ -0,0 +1,104 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! Helpers to run the `rooster` binary in integration tests.
+
+// Each test file only uses some of the helpers.
+#![allow(dead_code)]
+
+use std::env;
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+pub const MASTER_PASSWORD: &'static str = "correct horse battery staple";
+
+pub struct Output {
+    pub success: bool,
+    pub code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// A fresh directory for the password file, unique to this test and process.
+pub fn test_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("rooster-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+pub fn password_file(dir: &Path) -> PathBuf {
+    dir.join("passwords.rooster")
+}
+
+/// Runs rooster in `dir` with `input` on stdin. No clipboard tool can be found in $PATH.
+pub fn rooster(dir: &Path, args: &[&str], input: &str) -> Output {
+    rooster_with_env(dir, args, input, &[])
+}
+
+/// Like `rooster`, with more environment variables, which may override $PATH.
+pub fn rooster_with_env(dir: &Path, args: &[&str], input: &str, vars: &[(&str, &str)]) -> Output {
+    let mut child = rooster_command(dir, args, vars)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // Commands that fail early exit without reading their input.
+    if let Err(err) = child.stdin.take().unwrap().write_all(input.as_bytes()) {
+        assert_eq!(err.kind(), ErrorKind::BrokenPipe, "{}", err);
+    }
+
+    let output = child.wait_with_output().unwrap();
+    Output {
+        success: output.status.success(),
+        code: output.status.code(),
+        stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+    }
+}
+
+/// The command that `rooster_with_env` runs, to run it in the background.
+pub fn rooster_command(dir: &Path, args: &[&str], vars: &[(&str, &str)]) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_rooster"));
+    command.args(args)
+        .current_dir(dir)
+        .env("ROOSTER_FILE", password_file(dir))
+        .env("HOME", dir)
+        .env("PATH", dir.join("no-tools"))
+        .env_remove("ROOSTER_HISTORY_DEPTH")
+        .env_remove("ROOSTER_SELECTION")
+        .env_remove("WAYLAND_DISPLAY")
+        .env_remove("DISPLAY")
+        .env_remove("TMUX")
+        .env_remove("ROOSTER_ASKPASS")
+        .env_remove("SSH_ASKPASS")
+        .env_remove("ROOSTER_MASTER_PASSWORD")
+        .env_remove("ROOSTER_MASTER_PASSWORD_FILE")
+        .env_remove("ROOSTER_NON_INTERACTIVE")
+        .env_remove("ROOSTER_GIT");
+    for &(name, value) in vars {
+        command.env(name, value);
+    }
+    command
+}
+
+/// Creates an empty password file with `MASTER_PASSWORD`.
+pub fn create_password_file(dir: &Path) {
+    let output = rooster(dir, &["list"], &format!("y\n{}\n", MASTER_PASSWORD));
+    assert!(password_file(dir).exists(), "{}", output.stderr);
+}
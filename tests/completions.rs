@@ -0,0 +1,56 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! Shell completions, and the list of names they complete queries with.
+
+mod common;
+
+use common::{create_password_file, rooster, rooster_with_env, test_dir, MASTER_PASSWORD};
+
+#[test]
+fn prints_a_script_for_each_shell() {
+    let dir = test_dir("completions");
+    for shell in &["bash", "zsh", "fish"] {
+        let output = rooster(&dir, &["completions", shell], "");
+        assert!(output.success, "{}", output.stderr);
+        assert!(output.stdout.contains("set-master-password"), "{}", output.stdout);
+        assert!(output.stdout.contains("master-password-file"), "{}", output.stdout);
+        assert!(output.stdout.contains("list --names-only --porcelain"), "{}", output.stdout);
+    }
+    // No password file is needed.
+    assert!(!common::password_file(&dir).exists());
+
+    let output = rooster(&dir, &["completions", "powershell"], "");
+    assert!(!output.success);
+}
+
+#[test]
+fn lists_only_names_without_asking_anything() {
+    let dir = test_dir("completions-names");
+    create_password_file(&dir);
+    let output = rooster(&dir,
+                         &["add-or-update", "GitHub\twork", "me", "--password-stdin"],
+                         &format!("{}\nsecret\n", MASTER_PASSWORD));
+    assert!(output.success, "{}", output.stderr);
+
+    let args = ["--non-interactive", "--quiet", "list", "--names-only", "--porcelain"];
+    let output = rooster_with_env(&dir, &args, "", &[("ROOSTER_MASTER_PASSWORD", MASTER_PASSWORD)]);
+    assert!(output.success, "{}", output.stderr);
+    assert_eq!(output.stdout, "GitHub work\n");
+
+    let output = rooster(&dir, &args, "");
+    assert!(!output.success);
+    assert_eq!(output.stdout, "");
+}
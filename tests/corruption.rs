@@ -0,0 +1,132 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! A corrupted password file is told apart from a wrong master password, which is asked again
+//! when it is typed in a terminal.
+//! Only Argon2id files have a key check for this: scrypt files, which older versions of Rooster
+//! can open, only tell a damaged signature or a file that ends too early.
+
+mod common;
+
+use common::{create_password_file, password_file, rooster, rooster_with_env, test_dir,
+             MASTER_PASSWORD};
+use std::fs;
+use std::path::Path;
+
+/// Makes the password file derive its key with scrypt, like `rooster set-kdf --log-n 13`.
+fn use_scrypt(dir: &Path) {
+    let output = rooster_with_env(dir,
+                                  &["set-kdf", "--log-n", "13"],
+                                  "",
+                                  &[("ROOSTER_MASTER_PASSWORD", MASTER_PASSWORD)]);
+    assert!(output.success, "{}", output.stderr);
+    assert_eq!(&fs::read(password_file(dir)).unwrap()[..4], &[0, 0, 0, 2]);
+}
+
+#[test]
+fn wrong_piped_master_passwords_are_not_asked_again() {
+    let dir = test_dir("corruption-wrong");
+    create_password_file(&dir);
+
+    // The next line of stdin isn't another try, but what the command reads.
+    let output = rooster(&dir, &["list"], &format!("wrong\n{}\n", MASTER_PASSWORD));
+    assert_eq!(output.code, Some(4), "{}", output.stderr);
+    assert!(output.stderr.contains("the first line of standard input isn't the right master \
+                                    password"),
+            "{}",
+            output.stderr);
+    assert!(!output.stderr.contains("try again"), "{}", output.stderr);
+    assert!(!output.stderr.contains("corrupted"), "{}", output.stderr);
+}
+
+#[test]
+fn corrupted_files_are_not_asked_again() {
+    let dir = test_dir("corruption-flipped");
+    create_password_file(&dir);
+    let original = fs::read(password_file(&dir)).unwrap();
+
+    // The last byte of the encrypted passwords, and a byte of the signature.
+    for &offset in &[original.len() - 1, 100] {
+        let mut bytes = original.clone();
+        bytes[offset] ^= 1;
+        fs::write(password_file(&dir), &bytes).unwrap();
+
+        let output = rooster(&dir, &["list"], &format!("{}\n", MASTER_PASSWORD));
+        assert_eq!(output.code, Some(5), "{}", output.stderr);
+        assert!(output.stderr.contains("Your Rooster file is corrupted."),
+                "{}",
+                output.stderr);
+        assert!(!output.stderr.contains("try again"), "{}", output.stderr);
+    }
+}
+
+#[test]
+fn truncated_files_are_corrupted() {
+    let dir = test_dir("corruption-truncated");
+    create_password_file(&dir);
+    let original = fs::read(password_file(&dir)).unwrap();
+
+    for &len in &[2, 60, original.len() - 16] {
+        fs::write(password_file(&dir), &original[..len]).unwrap();
+
+        let output = rooster(&dir, &["list"], &format!("{}\n", MASTER_PASSWORD));
+        assert_eq!(output.code, Some(5), "{}", output.stderr);
+        assert!(!output.stderr.contains("try again"), "{}", output.stderr);
+    }
+}
+
+#[test]
+fn scrypt_files_only_tell_a_corrupted_signature() {
+    let dir = test_dir("corruption-scrypt-flipped");
+    create_password_file(&dir);
+    use_scrypt(&dir);
+    let original = fs::read(password_file(&dir)).unwrap();
+
+    // A byte of the signature, which starts at 61 in version 2 files.
+    let mut bytes = original.clone();
+    bytes[100] ^= 1;
+    fs::write(password_file(&dir), &bytes).unwrap();
+    let output = rooster(&dir, &["list"], &format!("{}\n", MASTER_PASSWORD));
+    assert_eq!(output.code, Some(5), "{}", output.stderr);
+    assert!(!output.stderr.contains("try again"), "{}", output.stderr);
+
+    // Without a key check, encrypted passwords that don't decrypt look like a wrong master
+    // password.
+    let mut bytes = original.clone();
+    let last = bytes.len() - 1;
+    bytes[last] ^= 1;
+    fs::write(password_file(&dir), &bytes).unwrap();
+    let output = rooster(&dir, &["list"], &format!("{}\n", MASTER_PASSWORD));
+    assert_eq!(output.code, Some(4), "{}", output.stderr);
+    assert!(output.stderr.contains("isn't the right master password"), "{}", output.stderr);
+    assert!(!output.stderr.contains("corrupted"), "{}", output.stderr);
+}
+
+#[test]
+fn truncated_scrypt_files_are_corrupted() {
+    let dir = test_dir("corruption-scrypt-truncated");
+    create_password_file(&dir);
+    use_scrypt(&dir);
+    let original = fs::read(password_file(&dir)).unwrap();
+
+    // The version, the scrypt params, the salt and the signature.
+    for &len in &[2, 10, 40, 100] {
+        fs::write(password_file(&dir), &original[..len]).unwrap();
+
+        let output = rooster(&dir, &["list"], &format!("{}\n", MASTER_PASSWORD));
+        assert_eq!(output.code, Some(5), "{}", output.stderr);
+        assert!(!output.stderr.contains("try again"), "{}", output.stderr);
+    }
+}
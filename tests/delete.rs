@@ -0,0 +1,81 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+
+//! `rooster delete` with several queries asks once before deleting, and with --strict, deletes
+//! nothing if a query matches no password.
+
+mod common;
+
+use common::{create_password_file, rooster, test_dir, MASTER_PASSWORD};
+use std::fs;
+use std::path::Path;
+
+fn add_passwords(dir: &Path) {
+    for name in &["YouTube", "GitHub", "GitLab"] {
+        let output = rooster(dir,
+                             &["add-or-update", name, "me@example.com", "--password-stdin"],
+                             &format!("{}\nsecret\n", MASTER_PASSWORD));
+        assert!(output.success, "{}", output.stderr);
+    }
+}
+
+fn names(dir: &Path) -> String {
+    rooster(dir, &["list", "--names-only"], &format!("{}\n", MASTER_PASSWORD)).stdout
+}
+
+#[test]
+fn deletes_several_passwords_once_confirmed() {
+    let dir = test_dir("delete-several");
+    create_password_file(&dir);
+    add_passwords(&dir);
+
+    let output = rooster(&dir,
+                         &["delete", "youtube", "github"],
+                         &format!("{}\nn\n", MASTER_PASSWORD));
+    assert!(output.success, "{}", output.stderr);
+    assert!(output.stderr.contains("I'm about to delete these 2 password(s):"),
+            "{}",
+            output.stderr);
+    assert!(output.stderr.contains("OK, I didn't delete anything."));
+    assert_eq!(names(&dir), "GitHub\nGitLab\nYouTube\n");
+
+    let output = rooster(&dir,
+                         &["delete", "youtube", "github", "zzz"],
+                         &format!("{}\ny\n", MASTER_PASSWORD));
+    assert!(output.success, "{}", output.stderr);
+    assert!(output.stderr.contains("Nothing matched: zzz"), "{}", output.stderr);
+    assert_eq!(names(&dir), "GitLab\n");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn strict_deletes_nothing_if_a_query_matches_nothing() {
+    let dir = test_dir("delete-strict");
+    create_password_file(&dir);
+    add_passwords(&dir);
+
+    let output = rooster(&dir,
+                         &["delete", "youtube", "zzz", "--strict"],
+                         &format!("{}\ny\n", MASTER_PASSWORD));
+    assert!(!output.success);
+    assert!(output.stderr.contains("Nothing was deleted, since --strict is on."),
+            "{}",
+            output.stderr);
+    assert_eq!(names(&dir), "GitHub\nGitLab\nYouTube\n");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,101 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! With --error-format json, failures are a single JSON object on stderr, and --format json
+//! prints what commands found as JSON on stdout.
+
+extern crate serde_json;
+
+mod common;
+
+use common::{create_password_file, rooster, rooster_with_env, test_dir, MASTER_PASSWORD};
+use serde_json::Value;
+
+fn error_of(stderr: &str) -> Value {
+    assert_eq!(stderr.lines().count(), 1, "{}", stderr);
+    serde_json::from_str(stderr).unwrap()
+}
+
+#[test]
+fn failures_are_one_json_object() {
+    let dir = test_dir("error-format");
+    create_password_file(&dir);
+    let output = rooster(&dir,
+                         &["add-or-update", "GitHub", "me", "--password-stdin"],
+                         &format!("{}\nsecret\n", MASTER_PASSWORD));
+    assert!(output.success, "{}", output.stderr);
+
+    let output = rooster(&dir, &["--error-format", "json", "--bogus"], "");
+    assert_eq!(output.code, Some(2));
+    assert_eq!(error_of(&output.stderr)["error"], "usage");
+
+    let env = [("ROOSTER_MASTER_PASSWORD", MASTER_PASSWORD)];
+    let output = rooster_with_env(&dir,
+                                  &["--error-format=json", "get", "--exact", "gitlab", "--stdout"],
+                                  "",
+                                  &env);
+    assert_eq!(output.code, Some(6));
+    let error = error_of(&output.stderr);
+    assert_eq!(error["error"], "no_such_app");
+    assert_eq!(error["app"], "gitlab");
+    assert!(error["message"].as_str().unwrap().contains("gitlab"), "{}", error);
+
+    let output = rooster_with_env(&dir,
+                                  &["--error-format", "json", "add", "GitHub", "me",
+                                    "--password-stdin"],
+                                  "other\n",
+                                  &env);
+    let error = error_of(&output.stderr);
+    assert_eq!(error["error"], "app_exists");
+    assert_eq!(error["app"], "GitHub");
+
+    let env = [("ROOSTER_MASTER_PASSWORD", "wrong")];
+    let output = rooster_with_env(&dir, &["--error-format", "json", "list"], "", &env);
+    assert_eq!(error_of(&output.stderr)["error"], "wrong_master_password");
+}
+
+#[test]
+fn errors_stay_sentences_by_default() {
+    let dir = test_dir("error-format-human");
+    let output = rooster(&dir, &["--bogus"], "");
+    assert!(serde_json::from_str::<Value>(&output.stderr).is_err());
+    assert!(output.stderr.contains("bogus"), "{}", output.stderr);
+
+    let output = rooster(&dir, &["--error-format", "xml", "list"], "");
+    assert_eq!(output.code, Some(2));
+}
+
+#[test]
+fn get_prints_json_with_format_json() {
+    let dir = test_dir("format-json");
+    create_password_file(&dir);
+    let output = rooster(&dir,
+                         &["add-or-update", "GitHub", "me", "--password-stdin"],
+                         &format!("{}\nsecret\n", MASTER_PASSWORD));
+    assert!(output.success, "{}", output.stderr);
+
+    let env = [("ROOSTER_MASTER_PASSWORD", MASTER_PASSWORD)];
+    let output = rooster_with_env(&dir, &["get", "github", "--format", "json"], "", &env);
+    assert!(output.success, "{}", output.stderr);
+    let found: Value = serde_json::from_str(&output.stdout).unwrap();
+    assert_eq!(found["name"], "GitHub");
+    assert_eq!(found["username"], "me");
+    assert_eq!(found["field"], "password");
+    assert_eq!(found["value"], "secret");
+
+    let output = rooster_with_env(&dir, &["list", "--format", "json"], "", &env);
+    let entries: Value = serde_json::from_str(&output.stdout).unwrap();
+    assert_eq!(entries[0]["name"], "GitHub");
+}
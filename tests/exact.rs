@@ -0,0 +1,57 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! With --exact, a query that isn't the name of a password fails, instead of offering close
+//! names to choose from.
+
+mod common;
+
+use common::{create_password_file, rooster, test_dir, MASTER_PASSWORD};
+use std::fs;
+
+#[test]
+fn only_accepts_exact_names() {
+    let dir = test_dir("exact");
+    create_password_file(&dir);
+    for name in &["Work Mail", "Home Mail"] {
+        let output = rooster(&dir,
+                             &["add-or-update", name, "me@example.com", "--password-stdin"],
+                             &format!("{}\nsecret\n", MASTER_PASSWORD));
+        assert!(output.success, "{}", output.stderr);
+    }
+    let input = format!("{}\n", MASTER_PASSWORD);
+
+    let output = rooster(&dir, &["get", "work MAIL", "--exact", "--stdout"], &input);
+    assert!(output.success, "{}", output.stderr);
+    assert_eq!(output.stdout, "secret\n");
+
+    for &command in &["get", "delete", "change", "rename", "transfer"] {
+        let mut args = vec![command, "mail", "--exact"];
+        if command == "rename" || command == "transfer" {
+            args.push("new");
+        }
+        let output = rooster(&dir, &args, &input);
+        assert!(!output.success, "{}", command);
+        assert!(output.stderr.contains("No exact match for \"mail\""),
+                "{}: {}",
+                command,
+                output.stderr);
+    }
+
+    let output = rooster(&dir, &["list", "--porcelain"], &input);
+    assert_eq!(output.stdout, "Home Mail\tme@example.com\nWork Mail\tme@example.com\n");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,59 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! Scripts can tell failures apart with the exit code.
+
+mod common;
+
+use common::{create_password_file, rooster, rooster_with_env, test_dir, MASTER_PASSWORD};
+
+#[test]
+fn wrong_options_exit_with_2() {
+    let dir = test_dir("exit-codes-usage");
+    assert_eq!(rooster(&dir, &["--no-such-option"], "").code, Some(2));
+    assert_eq!(rooster(&dir, &["no-such-command"], "").code, Some(2));
+    assert_eq!(rooster(&dir, &["get"], "").code, Some(2));
+}
+
+#[test]
+fn failures_have_their_own_code() {
+    let dir = test_dir("exit-codes");
+    create_password_file(&dir);
+    let output = rooster(&dir,
+                         &["add-or-update", "GitHub", "me", "--password-stdin"],
+                         &format!("{}\nsecret\n", MASTER_PASSWORD));
+    assert!(output.success, "{}", output.stderr);
+
+    let env = [("ROOSTER_MASTER_PASSWORD", "wrong")];
+    let output = rooster_with_env(&dir, &["get", "GitHub", "--stdout"], "", &env);
+    assert_eq!(output.code, Some(4), "{}", output.stderr);
+
+    let env = [("ROOSTER_MASTER_PASSWORD", MASTER_PASSWORD)];
+    let output = rooster_with_env(&dir, &["get", "--exact", "Nothing", "--stdout"], "", &env);
+    assert_eq!(output.code, Some(6), "{}", output.stderr);
+    let output = rooster_with_env(&dir,
+                                  &["add", "GitHub", "me", "--password-stdin"],
+                                  "other\n",
+                                  &env);
+    assert_eq!(output.code, Some(7), "{}", output.stderr);
+}
+
+#[test]
+fn help_lists_the_exit_codes() {
+    let dir = test_dir("exit-codes-help");
+    let output = rooster(&dir, &["--help"], "");
+    assert!(output.stdout.contains("Exit codes:"), "{}", output.stdout);
+    assert!(output.stdout.contains("    6   No password matches the query"), "{}", output.stdout);
+}
@@ -0,0 +1,90 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+
+//! The password file isn't overwritten when another program changed it after Rooster read it.
+
+mod common;
+
+use common::{create_password_file, password_file, rooster_with_env, test_dir, Output,
+             MASTER_PASSWORD};
+use std::fs;
+use std::path::Path;
+
+/// Edits the username of "Mail", then, the first time only, replaces the password file with
+/// other.rooster, like another Rooster or Dropbox would.
+const EDITOR: &'static str = "sed -i 's/^username: .*/username: edited/' \"$1\"
+if [ ! -e replaced ]; then touch replaced; cp other.rooster passwords.rooster; fi
+";
+
+fn edit_mail(dir: &Path, args: &[&str], input: &str) -> Output {
+    let editor = format!("sh {}", dir.join("editor.sh").display());
+    let env = [("PATH", "/usr/bin:/bin"),
+               ("EDITOR", editor.as_str()),
+               ("ROOSTER_MASTER_PASSWORD", MASTER_PASSWORD)];
+    let mut args = args.to_vec();
+    args.extend_from_slice(&["edit", "Mail"]);
+    rooster_with_env(dir, &args, input, &env)
+}
+
+fn list(dir: &Path) -> String {
+    let env = [("ROOSTER_MASTER_PASSWORD", MASTER_PASSWORD)];
+    rooster_with_env(dir, &["list"], "", &env).stdout
+}
+
+#[test]
+fn changes_by_others_are_not_overwritten() {
+    let dir = test_dir("file-changed");
+    create_password_file(&dir);
+    let add = |file: &Path, name: &str| {
+        let file = file.to_str().unwrap();
+        let vars = [("ROOSTER_MASTER_PASSWORD", MASTER_PASSWORD), ("ROOSTER_FILE", file)];
+        let output = rooster_with_env(&dir,
+                                      &["add-or-update", name, "me", "--password-stdin"],
+                                      "secret\n",
+                                      &vars);
+        assert!(output.success, "{}", output.stderr);
+    };
+    add(&password_file(&dir), "Mail");
+    fs::copy(password_file(&dir), dir.join("mine.rooster")).unwrap();
+    fs::copy(password_file(&dir), dir.join("other.rooster")).unwrap();
+    add(&dir.join("other.rooster"), "Other");
+    fs::write(dir.join("editor.sh"), EDITOR).unwrap();
+    let reset = || {
+        fs::copy(dir.join("mine.rooster"), password_file(&dir)).unwrap();
+        let _ = fs::remove_file(dir.join("replaced"));
+    };
+
+    // Not running again keeps what the other program wrote.
+    let output = edit_mail(&dir, &[], "n\n");
+    assert_eq!(output.code, Some(10), "{}", output.stderr);
+    assert!(output.stderr.contains("changed by another program"), "{}", output.stderr);
+    let passwords = list(&dir);
+    assert!(passwords.contains("Other") && !passwords.contains("edited"), "{}", passwords);
+
+    // Running again applies the edit to what the other program wrote.
+    reset();
+    let output = edit_mail(&dir, &[], "y\n");
+    assert!(output.success, "{}", output.stderr);
+    let passwords = list(&dir);
+    assert!(passwords.contains("Other") && passwords.contains("edited"), "{}", passwords);
+
+    // --force-write overwrites it.
+    reset();
+    let output = edit_mail(&dir, &["--force-write"], "");
+    assert!(output.success, "{}", output.stderr);
+    let passwords = list(&dir);
+    assert!(!passwords.contains("Other") && passwords.contains("edited"), "{}", passwords);
+}
@@ -0,0 +1,92 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+
+//! Only one Rooster at a time may change the password file.
+
+mod common;
+
+use common::{create_password_file, rooster_command, rooster_with_env, test_dir, MASTER_PASSWORD};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::Stdio;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Keeps `rooster edit` in the editor until the test creates "done".
+const EDITOR: &'static str = "sed -i 's/^username: .*/username: edited/' \"$1\"
+touch editing
+while [ ! -e done ]; do sleep 0.1; done
+";
+
+fn wait_for(path: &Path) {
+    let started = Instant::now();
+    while !path.exists() {
+        assert!(started.elapsed() < Duration::from_secs(30), "{:?} never appeared", path);
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+#[test]
+fn a_second_change_waits_for_the_first() {
+    let dir = test_dir("file-lock");
+    create_password_file(&dir);
+    fs::write(dir.join("editor.sh"), EDITOR).unwrap();
+    let editor = format!("sh {}", dir.join("editor.sh").display());
+    let env = [("PATH", "/usr/bin:/bin"),
+               ("EDITOR", editor.as_str()),
+               ("ROOSTER_MASTER_PASSWORD", MASTER_PASSWORD)];
+    let add = ["add-or-update", "Mail", "me", "--password-stdin"];
+    assert!(rooster_with_env(&dir, &add, "secret\n", &env).success);
+
+    let editing = rooster_command(&dir, &["edit", "Mail"], &env)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    wait_for(&dir.join("editing"));
+
+    let add = ["add-or-update", "Other", "me", "--password-stdin"];
+    let output = rooster_with_env(&dir, &add, "secret\n", &env);
+    assert_eq!(output.code, Some(11), "{}", output.stderr);
+    assert!(output.stderr.contains("another Rooster is changing"), "{}", output.stderr);
+    // Reading has to wait as well.
+    let output = rooster_with_env(&dir, &["list"], "", &env);
+    assert_eq!(output.code, Some(11), "{}", output.stderr);
+    // So do the commands that replace or delete it without opening it.
+    for args in [&["restore", "--previous"][..], &["init", "--force"], &["uninstall"]].iter() {
+        let output = rooster_with_env(&dir, args, "", &env);
+        assert_eq!(output.code, Some(11), "{:?}: {}", args, output.stderr);
+    }
+
+    let mut waiting = rooster_command(&dir, &["--wait", add[0], add[1], add[2], add[3]], &env)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    waiting.stdin.take().unwrap().write_all(b"secret\n").unwrap();
+    thread::sleep(Duration::from_millis(500));
+    fs::write(dir.join("done"), b"").unwrap();
+    assert!(editing.wait_with_output().unwrap().status.success());
+    assert!(waiting.wait().unwrap().success());
+
+    let output = rooster_with_env(&dir, &["list"], "", &env);
+    assert!(output.stdout.contains("Other") && output.stdout.contains("edited"),
+            "{}",
+            output.stdout);
+}
@@ -0,0 +1,49 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! --file picks the password file, whatever $ROOSTER_FILE says.
+
+mod common;
+
+use common::{create_password_file, password_file, rooster, test_dir, MASTER_PASSWORD};
+
+#[test]
+fn uses_the_given_file_relative_to_the_current_directory() {
+    let dir = test_dir("file-option");
+    create_password_file(&dir);
+
+    let output = rooster(&dir, &["--file", "backup.rooster", "list"],
+                         &format!("y\n{}\n", MASTER_PASSWORD));
+    assert!(output.success, "{}", output.stderr);
+    assert!(dir.join("backup.rooster").exists());
+
+    let output = rooster(&dir,
+                         &["-f", "backup.rooster", "add-or-update", "GitHub", "me",
+                           "--password-stdin"],
+                         &format!("{}\nsecret\n", MASTER_PASSWORD));
+    assert!(output.success, "{}", output.stderr);
+
+    let input = format!("{}\n", MASTER_PASSWORD);
+    let output = rooster(&dir, &["-f", "backup.rooster", "list", "--porcelain"], &input);
+    assert_eq!(output.stdout, "GitHub\tme\n");
+    let output = rooster(&dir, &["list", "--porcelain"], &input);
+    assert_eq!(output.stdout, "");
+    assert!(password_file(&dir).exists());
+
+    let output = rooster(&dir, &["--file", "backup.rooster", "--help"], "");
+    assert!(output.stdout.contains(&dir.join("backup.rooster").to_string_lossy().into_owned()),
+            "{}",
+            output.stdout);
+}
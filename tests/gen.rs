@@ -0,0 +1,44 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! `rooster gen` must work without a password file, and without asking for anything.
+
+mod common;
+
+use common::{password_file, rooster, test_dir};
+use std::fs;
+
+#[test]
+fn generates_without_a_password_file() {
+    let dir = test_dir("gen");
+
+    // Nothing on stdin: any prompt would fail.
+    let output = rooster(&dir, &["gen", "-l", "20", "--alnum", "--show"], "");
+    assert!(output.success, "{}", output.stderr);
+    let password = output.stdout.trim_end();
+    assert_eq!(password.len(), 20);
+    assert!(password.chars().all(|c| c.is_ascii_alphanumeric()), "{}", password);
+    assert!(!output.stderr.contains("master password"), "{}", output.stderr);
+    assert!(!password_file(&dir).exists());
+
+    let output = rooster(&dir, &["gen", "--words", "4", "--separator", " ", "--show"], "");
+    assert!(output.success, "{}", output.stderr);
+    assert_eq!(output.stdout.trim_end().split(' ').count(), 4);
+
+    let output = rooster(&dir, &["gen", "--similar-to", "github"], "");
+    assert!(!output.success);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,77 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! `rooster get --stdout` is used in scripts, so stdout must only hold the password, and an
+//! ambiguous query must fail rather than wait for an answer.
+
+mod common;
+
+use common::{create_password_file, rooster, test_dir, MASTER_PASSWORD};
+use std::fs;
+use std::path::Path;
+
+fn add(dir: &Path, name: &str, password: &str) {
+    let output = rooster(dir,
+                         &["add-or-update", name, "me@example.com", "--password-stdin"],
+                         &format!("{}\n{}\n", MASTER_PASSWORD, password));
+    assert!(output.success, "{}", output.stderr);
+}
+
+#[test]
+fn prints_only_the_password() {
+    let dir = test_dir("get-stdout");
+    create_password_file(&dir);
+    add(&dir, "Work Mail", "work secret");
+    add(&dir, "Home Mail", "home secret");
+    let input = format!("{}\n", MASTER_PASSWORD);
+
+    let output = rooster(&dir, &["get", "work mail", "--stdout"], &input);
+    assert!(output.success, "{}", output.stderr);
+    assert_eq!(output.stdout, "work secret\n");
+
+    let output = rooster(&dir, &["get", "Home Mail", "--no-newline"], &input);
+    assert!(output.success, "{}", output.stderr);
+    assert_eq!(output.stdout, "home secret");
+
+    let output = rooster(&dir, &["get", "Work Mail", "--stdout", "--username"], &input);
+    assert_eq!(output.stdout, "me@example.com\n");
+
+    // Every word has to match.
+    let output = rooster(&dir, &["get", "mail", "home", "--stdout"], &input);
+    assert!(output.success, "{}", output.stderr);
+    assert_eq!(output.stdout, "home secret\n");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn fails_instead_of_asking() {
+    let dir = test_dir("get-stdout-ambiguous");
+    create_password_file(&dir);
+    add(&dir, "Work Mail", "work secret");
+    add(&dir, "Home Mail", "home secret");
+    let input = format!("{}\n", MASTER_PASSWORD);
+
+    let output = rooster(&dir, &["get", "mail", "--stdout"], &input);
+    assert!(!output.success);
+    assert_eq!(output.stdout, "");
+    assert!(output.stderr.contains("matches several passwords"), "{}", output.stderr);
+
+    let output = rooster(&dir, &["get", "nothing", "--stdout"], &input);
+    assert!(!output.success);
+    assert_eq!(output.stdout, "");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
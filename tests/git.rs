@@ -0,0 +1,99 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+
+//! With $ROOSTER_GIT, changes to a password file in a git repository are committed.
+
+mod common;
+
+use common::{create_password_file, rooster_with_env, test_dir, MASTER_PASSWORD};
+use std::path::Path;
+use std::process::Command;
+
+const GIT_ENV: [(&'static str, &'static str); 6] = [("PATH", "/usr/bin:/bin"),
+                                                    ("GIT_AUTHOR_NAME", "Rooster"),
+                                                    ("GIT_AUTHOR_EMAIL", "rooster@example.com"),
+                                                    ("GIT_COMMITTER_NAME", "Rooster"),
+                                                    ("GIT_COMMITTER_EMAIL",
+                                                     "rooster@example.com"),
+                                                    ("GIT_CONFIG_NOSYSTEM", "1")];
+
+fn git(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .envs(GIT_ENV.iter().cloned())
+        .env("HOME", dir)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn add(dir: &Path, name: &str, vars: &[(&str, &str)]) -> common::Output {
+    let mut env = GIT_ENV.to_vec();
+    env.extend_from_slice(vars);
+    rooster_with_env(dir,
+                     &["add-or-update", name, "me", "--password-stdin"],
+                     &format!("{}\nsecret\n", MASTER_PASSWORD),
+                     &env)
+}
+
+#[test]
+fn changes_are_committed() {
+    let dir = test_dir("git-commit");
+    create_password_file(&dir);
+    git(&dir, &["init", "--quiet"]);
+
+    // Without $ROOSTER_GIT nothing is committed.
+    let output = add(&dir, "Before", &[]);
+    assert!(output.success, "{}", output.stderr);
+    assert_eq!(git(&dir, &["rev-list", "--all"]), "");
+
+    let output = add(&dir, "Secret Bank", &[("ROOSTER_GIT", "1")]);
+    assert!(output.success, "{}", output.stderr);
+    let log = git(&dir, &["log", "--format=%s"]);
+    assert_eq!(log, "rooster: add-or-update\n");
+    assert!(!log.contains("Secret Bank"));
+    assert_eq!(git(&dir, &["status", "--porcelain", "passwords.rooster"]), "");
+}
+
+#[test]
+fn git_sync_pushes() {
+    let dir = test_dir("git-sync");
+    let remote = dir.join("remote.git");
+    let local = dir.join("local");
+    std::fs::create_dir_all(&local).unwrap();
+    git(&dir, &["init", "--quiet", "--bare", remote.to_str().unwrap()]);
+    create_password_file(&local);
+    git(&local, &["init", "--quiet"]);
+    git(&local, &["add", "passwords.rooster"]);
+    git(&local, &["commit", "--quiet", "-m", "init"]);
+    git(&local, &["remote", "add", "origin", remote.to_str().unwrap()]);
+    git(&local, &["push", "--quiet", "-u", "origin", "HEAD"]);
+
+    let args = ["--git-sync", "add-or-update", "Mail", "me", "--password-stdin"];
+    let input = format!("{}\nsecret\n", MASTER_PASSWORD);
+    let output = rooster_with_env(&local, &args, &input, &GIT_ENV);
+    assert!(output.success, "{}", output.stderr);
+    assert_eq!(git(&remote, &["log", "--format=%s"]), "rooster: add-or-update\ninit\n");
+
+    // --git-sync outside of a repository is a usage error.
+    let elsewhere = dir.join("elsewhere");
+    std::fs::create_dir_all(&elsewhere).unwrap();
+    create_password_file(&elsewhere);
+    let output = rooster_with_env(&elsewhere, &args, &input, &GIT_ENV);
+    assert_eq!(output.code, Some(2), "{}", output.stderr);
+}
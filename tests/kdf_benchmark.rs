@@ -0,0 +1,54 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! The benchmark runs without a password file, and can apply what it finds to one.
+
+mod common;
+
+use common::{create_password_file, password_file, rooster, rooster_with_env, test_dir,
+             MASTER_PASSWORD};
+use std::fs;
+
+#[test]
+fn runs_without_a_password_file() {
+    let dir = test_dir("kdf-benchmark");
+    let output = rooster(&dir, &["kdf-benchmark", "--target-ms", "1", "--scrypt"], "");
+    assert!(output.success, "{}", output.stderr);
+    assert!(output.stdout.contains("Closest to 1 ms: scrypt (--log-n 12 --scrypt-r 8 \
+                                    --scrypt-p 1), which needs 4 MiB of memory."),
+            "{}",
+            output.stdout);
+    assert!(!password_file(&dir).exists());
+
+    let output = rooster(&dir, &["kdf-benchmark", "--target-ms", "soon"], "");
+    assert_eq!(output.code, Some(2), "{}", output.stderr);
+}
+
+#[test]
+fn apply_changes_the_key_derivation_of_the_file() {
+    let dir = test_dir("kdf-benchmark-apply");
+    create_password_file(&dir);
+    let env = [("ROOSTER_MASTER_PASSWORD", MASTER_PASSWORD)];
+    let output = rooster_with_env(&dir,
+                                  &["kdf-benchmark", "--target-ms", "1", "--scrypt", "--apply"],
+                                  "",
+                                  &env);
+    assert!(output.success, "{}", output.stderr);
+
+    // Version 2, with log2(N) = 12.
+    assert_eq!(&fs::read(password_file(&dir)).unwrap()[..5], &[0, 0, 0, 2, 12]);
+    let output = rooster_with_env(&dir, &["list"], "", &env);
+    assert!(output.success, "{}", output.stderr);
+}
@@ -0,0 +1,171 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! Runs commands against a password file whose secrets all contain a sentinel, and checks that
+//! the sentinel only shows up where the user asked for a secret.
+//!
+//! To cover a new command, add it to `CASES`.
+
+mod common;
+
+use common::{create_password_file, rooster, test_dir, MASTER_PASSWORD};
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+const SENTINEL: &'static str = "rooster-canary-5e1d";
+
+/// Where a case may print the sentinel.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Allowed {
+    Nowhere,
+    Stdout,
+    Stderr,
+}
+
+struct Case {
+    args: &'static [&'static str],
+    /// Typed after the master password.
+    input: &'static str,
+    allowed: Allowed,
+}
+
+const CASES: &'static [Case] = &[
+    Case { args: &["get", "YouTube", "--show"], input: "", allowed: Allowed::Stderr },
+    // No clipboard tool is in $PATH, so copying fails.
+    Case { args: &["get", "YouTube"], input: "", allowed: Allowed::Nowhere },
+    Case { args: &["get", "nothing"], input: "", allowed: Allowed::Nowhere },
+    Case { args: &["get", "YouTube", "--stdout"], input: "", allowed: Allowed::Stdout },
+    Case { args: &["get", "YouTube", "--field", "notes", "--show"],
+           input: "",
+           allowed: Allowed::Stdout },
+    Case { args: &["get", "YouTube", "--field", "url"], input: "", allowed: Allowed::Nowhere },
+    Case { args: &["get", "YouTube", "--field", "pin"], input: "", allowed: Allowed::Nowhere },
+    Case { args: &["get", "YouTube", "--username"], input: "", allowed: Allowed::Nowhere },
+    Case { args: &["get", "YouTube", "-u", "--show"], input: "", allowed: Allowed::Nowhere },
+    Case { args: &["list"], input: "", allowed: Allowed::Nowhere },
+    Case { args: &["--archived", "list"], input: "", allowed: Allowed::Nowhere },
+    Case { args: &["export"], input: "", allowed: Allowed::Stdout },
+    Case { args: &["note", "YouTube"], input: "", allowed: Allowed::Stdout },
+    Case { args: &["history", "YouTube"], input: "", allowed: Allowed::Nowhere },
+    Case { args: &["history", "YouTube", "--index", "1", "--show"],
+           input: "",
+           allowed: Allowed::Stderr },
+    Case { args: &["history", "YouTube", "--index", "9", "--show"],
+           input: "",
+           allowed: Allowed::Nowhere },
+    Case { args: &["tag", "YouTube", "+video"], input: "", allowed: Allowed::Nowhere },
+    Case { args: &["favorite", "YouTube"], input: "", allowed: Allowed::Nowhere },
+    Case { args: &["doctor"], input: "", allowed: Allowed::Nowhere },
+    Case { args: &["dedupe", "--dry-run"], input: "", allowed: Allowed::Nowhere },
+    Case { args: &["config"], input: "", allowed: Allowed::Nowhere },
+    Case { args: &["--similar-to", "YouTube", "generate", "Other", "me", "--show"],
+           input: "",
+           allowed: Allowed::Nowhere },
+];
+
+/// Every file in `dir`, to check that nothing was written in clear text.
+fn files_containing_sentinel(dir: &Path) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if !path.is_file() {
+            continue;
+        }
+        let mut contents = Vec::new();
+        File::open(&path).unwrap().read_to_end(&mut contents).unwrap();
+        if String::from_utf8_lossy(&contents).contains(SENTINEL) {
+            found.push(path);
+        }
+    }
+    found
+}
+
+fn create_fixture(dir: &Path) {
+    create_password_file(dir);
+
+    let fixture = dir.join("fixture.json");
+    File::create(&fixture)
+        .unwrap()
+        .write_all(format!("[{{\"name\": \"YouTube\", \"username\": \"me@example.com\", \
+                             \"password\": \"{0}-password\", \"notes\": \"{0}-note\", \
+                             \"created_at\": 1500000000, \"updated_at\": 1500000000, \
+                             \"history\": [{{\"password\": \"{0}-old\", \
+                             \"changed_at\": 1400000000}}]}}, \
+                            {{\"name\": \"Archived\", \"username\": \"me\", \
+                             \"password\": \"{0}-archived\", \"archived\": true, \
+                             \"created_at\": 1500000000, \"updated_at\": 1500000000}}]",
+                           SENTINEL)
+                       .as_bytes())
+        .unwrap();
+    let output = rooster(dir,
+                         &["import", fixture.to_str().unwrap()],
+                         &format!("{}\n", MASTER_PASSWORD));
+    assert!(output.stderr.contains("imported 2 password(s)"), "{}", output.stderr);
+    fs::remove_file(&fixture).unwrap();
+}
+
+#[test]
+fn secrets_only_go_where_they_are_asked_for() {
+    let dir = test_dir("leak-canary");
+    create_fixture(&dir);
+    assert!(files_containing_sentinel(&dir).is_empty());
+
+    let mut leaks = Vec::new();
+    for case in CASES.iter() {
+        let output = rooster(&dir, case.args, &format!("{}\n{}", MASTER_PASSWORD, case.input));
+
+        if case.allowed != Allowed::Stdout && output.stdout.contains(SENTINEL) {
+            leaks.push(format!("{:?} printed a secret on stdout", case.args));
+        }
+        if case.allowed != Allowed::Stderr && output.stderr.contains(SENTINEL) {
+            leaks.push(format!("{:?} printed a secret on stderr", case.args));
+        }
+        for path in files_containing_sentinel(&dir) {
+            leaks.push(format!("{:?} wrote a secret in clear text to {:?}", case.args, path));
+        }
+    }
+
+    // A wrong master password mustn't reveal anything either.
+    let output = rooster(&dir, &["get", "YouTube", "--show"], "wrong\n");
+    if output.stdout.contains(SENTINEL) || output.stderr.contains(SENTINEL) {
+        leaks.push("a wrong master password printed a secret".to_owned());
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(leaks.is_empty(), "{}", leaks.join("\n"));
+}
+
+#[test]
+fn allowed_outputs_do_show_the_secret() {
+    // Makes sure the harness would see a leak, by checking the outputs that must have one.
+    let dir = test_dir("leak-canary-check");
+    create_fixture(&dir);
+
+    for case in CASES.iter().filter(|c| c.allowed != Allowed::Nowhere) {
+        let output = rooster(&dir, case.args, &format!("{}\n{}", MASTER_PASSWORD, case.input));
+        let shown = match case.allowed {
+            Allowed::Stdout => &output.stdout,
+            Allowed::Stderr | Allowed::Nowhere => &output.stderr,
+        };
+        assert!(shown.contains(SENTINEL),
+                "{:?} should show a secret:\n{}\n{}",
+                case.args,
+                output.stdout,
+                output.stderr);
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
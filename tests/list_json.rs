@@ -0,0 +1,95 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! `rooster list --json` is read by other programs, so stdout must be valid JSON, and secrets
+//! must never be part of it.
+
+extern crate serde_json;
+
+mod common;
+
+use common::{create_password_file, rooster, test_dir, MASTER_PASSWORD};
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+
+fn add(dir: &Path, name: &str, password: &str) {
+    let output = rooster(dir,
+                         &["add-or-update", name, "me@example.com", "--password-stdin"],
+                         &format!("{}\n{}\n", MASTER_PASSWORD, password));
+    assert!(output.success, "{}", output.stderr);
+}
+
+#[test]
+fn prints_parseable_json_without_secrets() {
+    let dir = test_dir("list-json");
+    create_password_file(&dir);
+    let input = format!("{}\n", MASTER_PASSWORD);
+
+    let output = rooster(&dir, &["list", "--json"], &input);
+    assert!(output.success, "{}", output.stderr);
+    let entries: Value = serde_json::from_str(&output.stdout).unwrap();
+    assert_eq!(entries, Value::Array(vec![]));
+
+    add(&dir, "YouTube", "youtube secret");
+    add(&dir, "Slack \"work\"", "slack secret");
+    let output = rooster(&dir, &["list", "--json"], &input);
+    assert!(output.success, "{}", output.stderr);
+    assert!(!output.stdout.contains("secret"), "{}", output.stdout);
+
+    let entries: Value = serde_json::from_str(&output.stdout).unwrap();
+    let entries = entries.as_array().unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0]["name"], "Slack \"work\"");
+    assert_eq!(entries[1]["name"], "YouTube");
+    assert_eq!(entries[1]["username"], "me@example.com");
+    assert!(entries[1]["created_at"].is_u64());
+    assert!(entries[1]["updated_at"].is_u64());
+    assert!(entries[1].get("password").is_none());
+    assert!(entries[1].get("notes").is_none());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn filters_by_query() {
+    let dir = test_dir("list-json-query");
+    create_password_file(&dir);
+    add(&dir, "Work Mail", "x");
+    add(&dir, "Home Mail", "x");
+    add(&dir, "Mastodon", "x");
+    let input = format!("{}\n", MASTER_PASSWORD);
+    let names = |args: &[&str]| -> Vec<String> {
+        let output = rooster(&dir, args, &input);
+        assert!(output.success, "{}", output.stderr);
+        let entries: Value = serde_json::from_str(&output.stdout).unwrap();
+        entries.as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| entry["name"].as_str().unwrap().to_owned())
+            .collect()
+    };
+
+    assert_eq!(names(&["list", "ma", "--json"]), vec!["Home Mail", "Mastodon", "Work Mail"]);
+    assert_eq!(names(&["list", "wml", "--json"]), vec!["Work Mail"]);
+    assert_eq!(names(&["list", "MAIL", "--exact", "--json", "--sort", "name", "--reverse"]),
+               vec!["Work Mail", "Home Mail"]);
+
+    let output = rooster(&dir, &["list", "mal", "--exact"], &input);
+    assert!(!output.success);
+    assert!(output.stderr.contains("No entries match"), "{}", output.stderr);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,38 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! `rooster man` renders the help texts as a man page.
+
+mod common;
+
+use common::{password_file, rooster, test_dir};
+
+#[test]
+fn renders_the_help_of_every_command() {
+    let dir = test_dir("man");
+    let output = rooster(&dir, &["man"], "");
+    assert!(output.success, "{}", output.stderr);
+    assert!(output.stdout.starts_with(".TH ROOSTER 1 "), "{}", output.stdout);
+    for expected in &[".SH OPTIONS\n",
+                      "\\fB\\-f, \\-\\-file <path>\\fR\n",
+                      ".SS \"rooster get\"\n",
+                      ".SS \"rooster set\\-master\\-password\"\n",
+                      "\\fBROOSTER_FILE\\fR\n",
+                      ".SH \"EXIT STATUS\"\n"] {
+        assert!(output.stdout.contains(expected), "{}", expected);
+    }
+    assert!(!output.stdout.contains(".SS \"rooster man\""));
+    assert!(!password_file(&dir).exists());
+}
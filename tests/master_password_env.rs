@@ -0,0 +1,60 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! The master password can come from $ROOSTER_MASTER_PASSWORD, for automation.
+
+mod common;
+
+use common::{create_password_file, rooster, rooster_with_env, test_dir, MASTER_PASSWORD};
+
+#[test]
+fn reads_the_master_password_from_the_environment() {
+    let dir = test_dir("master-password-env");
+    create_password_file(&dir);
+    let output = rooster(&dir,
+                         &["add-or-update", "GitHub", "me", "--password-stdin"],
+                         &format!("{}\nsecret\n", MASTER_PASSWORD));
+    assert!(output.success, "{}", output.stderr);
+
+    let output = rooster_with_env(&dir,
+                                  &["get", "GitHub", "--stdout"],
+                                  "",
+                                  &[("ROOSTER_MASTER_PASSWORD", MASTER_PASSWORD)]);
+    assert!(output.success, "{}", output.stderr);
+    assert_eq!(output.stdout, "secret\n");
+    assert!(output.stderr.contains("/proc"), "{}", output.stderr);
+
+    let output = rooster_with_env(&dir,
+                                  &["--quiet", "get", "GitHub", "--stdout"],
+                                  "",
+                                  &[("ROOSTER_MASTER_PASSWORD", MASTER_PASSWORD)]);
+    assert!(output.success, "{}", output.stderr);
+    assert_eq!(output.stderr, "");
+}
+
+#[test]
+fn fails_right_away_with_a_wrong_master_password() {
+    let dir = test_dir("master-password-env-wrong");
+    create_password_file(&dir);
+
+    // The right password is on stdin, but it must not be asked for.
+    let output = rooster_with_env(&dir,
+                                  &["-q", "list"],
+                                  &format!("{}\n", MASTER_PASSWORD),
+                                  &[("ROOSTER_MASTER_PASSWORD", "wrong")]);
+    assert!(!output.success);
+    assert!(output.stderr.contains("isn't the right master password"), "{}", output.stderr);
+    assert!(!output.stderr.contains("try again"), "{}", output.stderr);
+}
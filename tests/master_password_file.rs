@@ -0,0 +1,87 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! The master password can be read from a file with --master-password-file or
+//! $ROOSTER_MASTER_PASSWORD_FILE.
+
+#![cfg(unix)]
+
+mod common;
+
+use common::{create_password_file, rooster, rooster_with_env, test_dir, MASTER_PASSWORD};
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+
+#[test]
+fn reads_the_master_password_from_a_private_file() {
+    let dir = test_dir("master-password-file");
+    create_password_file(&dir);
+    let file = dir.join("master-password");
+    fs::write(&file, format!("{}\nignored\n", MASTER_PASSWORD)).unwrap();
+    fs::set_permissions(&file, fs::Permissions::from_mode(0o600)).unwrap();
+    let path = file.to_string_lossy().into_owned();
+
+    let output = rooster(&dir, &["--master-password-file", &path, "list"], "");
+    assert!(output.success, "{}", output.stderr);
+
+    let output = rooster_with_env(&dir,
+                                  &["list"],
+                                  "",
+                                  &[("ROOSTER_MASTER_PASSWORD_FILE", &path)]);
+    assert!(output.success, "{}", output.stderr);
+
+    // Only reading is checked: a file that others may only write to is accepted.
+    fs::set_permissions(&file, fs::Permissions::from_mode(0o620)).unwrap();
+    let output = rooster(&dir, &["--master-password-file", &path, "list"], "");
+    assert!(output.success, "{}", output.stderr);
+
+    // Other users must not be able to read it.
+    fs::set_permissions(&file, fs::Permissions::from_mode(0o640)).unwrap();
+    let output = rooster(&dir, &["--master-password-file", &path, "list"], "");
+    assert!(!output.success);
+    fs::set_permissions(&file, fs::Permissions::from_mode(0o644)).unwrap();
+    let output = rooster(&dir, &["--master-password-file", &path, "list"], "");
+    assert!(!output.success);
+    assert!(output.stderr.contains("chmod 600"), "{}", output.stderr);
+
+    let output = rooster(&dir,
+                         &["--master-password-file",
+                           &path,
+                           "--insecure-master-password-file",
+                           "list"],
+                         "");
+    assert!(output.success, "{}", output.stderr);
+}
+
+#[test]
+fn reads_the_master_password_from_stdin() {
+    let dir = test_dir("master-password-file-stdin");
+    create_password_file(&dir);
+
+    let output = rooster(&dir,
+                         &["--master-password-file", "-", "list"],
+                         &format!("{}\n", MASTER_PASSWORD));
+    assert!(output.success, "{}", output.stderr);
+
+    // A wrong password isn't asked again.
+    let output = rooster(&dir,
+                         &["--master-password-file", "-", "list"],
+                         &format!("wrong\n{}\n", MASTER_PASSWORD));
+    assert!(!output.success);
+    assert!(output.stderr.contains("the first line of standard input isn't the right master \
+                                    password"),
+            "{}",
+            output.stderr);
+}
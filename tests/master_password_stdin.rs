@@ -0,0 +1,48 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! With --master-password-stdin, the master password is the first line of stdin, and the
+//! passwords that commands read from stdin come on the next lines.
+
+mod common;
+
+use common::{create_password_file, rooster, test_dir, MASTER_PASSWORD};
+
+#[test]
+fn reads_the_master_password_then_the_entry_password() {
+    let dir = test_dir("master-password-stdin");
+    create_password_file(&dir);
+
+    let output = rooster(&dir,
+                         &["--master-password-stdin",
+                           "add-or-update",
+                           "GitHub",
+                           "me",
+                           "--password-stdin"],
+                         &format!("{}\nsecret\n", MASTER_PASSWORD));
+    assert!(output.success, "{}", output.stderr);
+
+    let output = rooster(&dir,
+                         &["--master-password-stdin", "get", "GitHub", "--stdout"],
+                         &format!("{}\n", MASTER_PASSWORD));
+    assert!(output.success, "{}", output.stderr);
+    assert_eq!(output.stdout, "secret\n");
+
+    let output = rooster(&dir,
+                         &["--master-password-stdin", "--master-password-file", "-", "list"],
+                         &format!("{}\n", MASTER_PASSWORD));
+    assert!(!output.success);
+    assert!(output.stderr.contains("can't be used together"), "{}", output.stderr);
+}
@@ -0,0 +1,80 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! With --non-interactive or $ROOSTER_NON_INTERACTIVE, Rooster fails instead of asking.
+
+mod common;
+
+use common::{create_password_file, rooster, rooster_with_env, test_dir, MASTER_PASSWORD};
+
+const EXIT_CODE: i32 = 3;
+
+#[test]
+fn fails_instead_of_asking_for_the_master_password() {
+    let dir = test_dir("non-interactive-master-password");
+    create_password_file(&dir);
+
+    let output = rooster(&dir, &["--non-interactive", "list"], &format!("{}\n", MASTER_PASSWORD));
+    assert_eq!(output.code, Some(EXIT_CODE), "{}", output.stderr);
+    assert_eq!(output.stderr, "rooster: non-interactive: would ask for the master password\n");
+
+    let output = rooster_with_env(&dir,
+                                  &["list"],
+                                  &format!("{}\n", MASTER_PASSWORD),
+                                  &[("ROOSTER_NON_INTERACTIVE", "1")]);
+    assert_eq!(output.code, Some(EXIT_CODE), "{}", output.stderr);
+
+    let output = rooster_with_env(&dir,
+                                  &["-q", "--non-interactive", "list"],
+                                  "",
+                                  &[("ROOSTER_MASTER_PASSWORD", MASTER_PASSWORD)]);
+    assert!(output.success, "{}", output.stderr);
+}
+
+#[test]
+fn fails_instead_of_creating_a_password_file() {
+    let dir = test_dir("non-interactive-new-file");
+    let output = rooster(&dir, &["--non-interactive", "list"], "y\n");
+    assert_eq!(output.code, Some(EXIT_CODE), "{}", output.stderr);
+    assert_eq!(output.stderr,
+               "rooster: non-interactive: would ask for whether to create a password file\n");
+    assert!(!common::password_file(&dir).exists());
+}
+
+#[test]
+fn fails_instead_of_asking_for_confirmation() {
+    let dir = test_dir("non-interactive-confirmation");
+    create_password_file(&dir);
+    let output = rooster_with_env(&dir,
+                                  &["add", "GitHub", "me", "--password-stdin"],
+                                  "secret\n",
+                                  &[("ROOSTER_MASTER_PASSWORD", MASTER_PASSWORD)]);
+    assert!(output.success, "{}", output.stderr);
+
+    let output = rooster_with_env(&dir,
+                                  &["-q", "--non-interactive", "delete", "GitHub", "GitHub"],
+                                  "y\n",
+                                  &[("ROOSTER_MASTER_PASSWORD", MASTER_PASSWORD)]);
+    assert_eq!(output.code, Some(EXIT_CODE), "{}", output.stderr);
+    assert!(output.stderr.ends_with("rooster: non-interactive: would ask for an answer\n"),
+            "{}",
+            output.stderr);
+
+    let output = rooster_with_env(&dir,
+                                  &["-q", "get", "GitHub", "--stdout"],
+                                  "",
+                                  &[("ROOSTER_MASTER_PASSWORD", MASTER_PASSWORD)]);
+    assert_eq!(output.stdout, "secret\n");
+}
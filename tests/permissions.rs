@@ -0,0 +1,70 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+
+//! The password file is private, and Rooster says so when it isn't.
+
+#![cfg(unix)]
+
+mod common;
+
+use common::{create_password_file, password_file, rooster_with_env, test_dir, MASTER_PASSWORD};
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+fn mode(path: &Path) -> u32 {
+    fs::metadata(path).unwrap().permissions().mode() & 0o777
+}
+
+#[test]
+fn readable_password_files_are_fixed() {
+    let dir = test_dir("permissions");
+    create_password_file(&dir);
+    assert_eq!(mode(&password_file(&dir)), 0o600);
+    let env = [("ROOSTER_MASTER_PASSWORD", MASTER_PASSWORD)];
+
+    fs::set_permissions(password_file(&dir), fs::Permissions::from_mode(0o644)).unwrap();
+    let output = rooster_with_env(&dir, &["--non-interactive", "list"], "", &env);
+    assert!(output.success, "{}", output.stderr);
+    assert!(output.stderr.contains("other users can read your password file"),
+            "{}",
+            output.stderr);
+    assert_eq!(mode(&password_file(&dir)), 0o644);
+
+    // Stdin isn't a terminal, so the answer could belong to the command: it is left alone.
+    let output = rooster_with_env(&dir, &["list"], "y\n", &env);
+    assert!(output.success, "{}", output.stderr);
+    assert!(output.stderr.contains("--fix-permissions"), "{}", output.stderr);
+    assert_eq!(mode(&password_file(&dir)), 0o644);
+
+    fs::set_permissions(password_file(&dir), fs::Permissions::from_mode(0o640)).unwrap();
+    let args = ["--non-interactive", "--fix-permissions", "list"];
+    let output = rooster_with_env(&dir, &args, "", &env);
+    assert!(output.success, "{}", output.stderr);
+    assert_eq!(mode(&password_file(&dir)), 0o600);
+    let output = rooster_with_env(&dir, &["list"], "", &env);
+    assert!(!output.stderr.contains("other users can read"), "{}", output.stderr);
+}
+
+#[test]
+fn world_writable_directories_are_told() {
+    let dir = test_dir("permissions-dir");
+    create_password_file(&dir);
+    fs::set_permissions(&dir, fs::Permissions::from_mode(0o777)).unwrap();
+    let env = [("ROOSTER_MASTER_PASSWORD", MASTER_PASSWORD)];
+    let output = rooster_with_env(&dir, &["list"], "", &env);
+    assert!(output.stderr.contains("any user can change"), "{}", output.stderr);
+}
@@ -0,0 +1,56 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! The output of --porcelain is documented as stable, scripts split it on tabs.
+
+mod common;
+
+use common::{create_password_file, rooster, test_dir, MASTER_PASSWORD};
+use std::fs;
+use std::path::Path;
+
+fn add(dir: &Path, name: &str, username: &str) {
+    let output = rooster(dir,
+                         &["add-or-update", name, username, "--password-stdin"],
+                         &format!("{}\nsecret\n", MASTER_PASSWORD));
+    assert!(output.success, "{}", output.stderr);
+}
+
+#[test]
+fn lists_tab_separated_lines_without_colors() {
+    let dir = test_dir("porcelain");
+    create_password_file(&dir);
+    let input = format!("{}\n", MASTER_PASSWORD);
+
+    let output = rooster(&dir, &["list", "--porcelain"], &input);
+    assert!(output.success, "{}", output.stderr);
+    assert_eq!(output.stdout, "");
+
+    add(&dir, "YouTube", "me@example.com");
+    add(&dir, "GitHub", "octocat");
+    let output = rooster(&dir, &["favorite", "YouTube"], &input);
+    assert!(output.success, "{}", output.stderr);
+
+    let output = rooster(&dir, &["list", "--porcelain"], &input);
+    assert!(output.success, "{}", output.stderr);
+    assert_eq!(output.stdout, "GitHub\toctocat\nYouTube\tme@example.com\n");
+    assert!(!output.stderr.contains('\x1b'), "{}", output.stderr);
+
+    let output = rooster(&dir, &["list", "--porcelain", "--json"], &input);
+    assert!(!output.success);
+    assert_eq!(output.stdout, "");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,73 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! `rooster --profile <name>` opens the password file that the profile names.
+
+mod common;
+
+use common::{create_password_file, password_file, rooster_with_env, test_dir, MASTER_PASSWORD};
+use std::fs;
+
+#[test]
+fn profiles_pick_the_password_file() {
+    let dir = test_dir("profiles");
+    create_password_file(&dir);
+    let work = dir.join("work");
+    fs::create_dir_all(&work).unwrap();
+    fs::copy(password_file(&dir), work.join("work.rooster")).unwrap();
+    let config = dir.join("config");
+    let config = config.to_str().unwrap();
+    let env = [("XDG_CONFIG_HOME", config), ("ROOSTER_MASTER_PASSWORD", MASTER_PASSWORD)];
+
+    let output = rooster_with_env(&dir, &["profile", "add", "work", "work/work.rooster"], "", &env);
+    assert!(output.success, "{}", output.stderr);
+    let profiles = fs::read_to_string(dir.join("config/rooster/profiles.toml")).unwrap();
+    assert!(profiles.contains(&format!("work = \"{}\"", work.join("work.rooster").display())),
+            "{}",
+            profiles);
+
+    let output = rooster_with_env(&dir,
+                                  &["--profile", "work", "add-or-update", "VPN", "me",
+                                    "--password-stdin"],
+                                  "secret\n",
+                                  &env);
+    assert!(output.success, "{}", output.stderr);
+    let output = rooster_with_env(&dir, &["--profile", "work", "list", "--names-only"], "", &env);
+    assert_eq!(output.stdout, "VPN\n");
+    // The default password file is left alone.
+    let output = rooster_with_env(&dir, &["list", "--names-only"], "", &env);
+    assert_eq!(output.stdout, "");
+
+    let output = rooster_with_env(&dir, &["profile"], "", &env);
+    assert_eq!(output.stdout, format!("work\t{}\n", work.join("work.rooster").display()));
+
+    let output = rooster_with_env(&dir, &["--profile", "work", "--help"], "", &env);
+    assert!(output.stdout.contains("(profile work)"), "{}", output.stdout);
+}
+
+#[test]
+fn unknown_profiles_are_errors() {
+    let dir = test_dir("profiles-unknown");
+    let config = dir.join("config");
+    let env = [("XDG_CONFIG_HOME", config.to_str().unwrap())];
+    let output = rooster_with_env(&dir, &["--profile", "play", "list"], "", &env);
+    assert_eq!(output.code, Some(2));
+    assert!(output.stderr.contains("no profile called \"play\""), "{}", output.stderr);
+
+    let output = rooster_with_env(&dir, &["profile", "remove", "play"], "", &env);
+    assert!(!output.success);
+    let output = rooster_with_env(&dir, &["profile", "add", "my play", "p.rooster"], "", &env);
+    assert_eq!(output.code, Some(2));
+}
@@ -0,0 +1,61 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! With --quiet, only errors and what was asked for are printed.
+
+mod common;
+
+use common::{create_password_file, rooster_with_env, test_dir, Output, MASTER_PASSWORD};
+use std::path::Path;
+
+fn quiet(dir: &Path, args: &[&str], input: &str) -> Output {
+    let mut quiet_args = vec!["--quiet"];
+    quiet_args.extend_from_slice(args);
+    rooster_with_env(dir, &quiet_args, input, &[("ROOSTER_MASTER_PASSWORD", MASTER_PASSWORD)])
+}
+
+#[test]
+fn only_prints_errors_and_requested_output() {
+    let dir = test_dir("quiet");
+    create_password_file(&dir);
+
+    // No clipboard tool is in $PATH, and failing to copy is an error.
+    let output = quiet(&dir, &["add", "GitHub", "me", "--password-stdin"], "secret\n");
+    assert!(output.stderr.contains("something went wrong"), "{}", output.stderr);
+
+    let output = quiet(&dir, &["tag", "GitHub", "+work"], "");
+    assert!(output.success, "{}", output.stderr);
+    assert_eq!(output.stderr, "");
+    assert_eq!(output.stdout, "");
+
+    let output = quiet(&dir, &["get", "GitHub", "--show"], "");
+    assert!(output.success, "{}", output.stderr);
+    assert!(output.stderr.contains("secret"), "{}", output.stderr);
+
+    let output = quiet(&dir, &["get", "GitHub", "--stdout"], "");
+    assert_eq!(output.stdout, "secret\n");
+    assert_eq!(output.stderr, "");
+
+    let output = quiet(&dir, &["delete", "GitLab"], "");
+    assert!(!output.success);
+    assert!(output.stderr.contains("GitLab"), "{}", output.stderr);
+
+    // Why an archived password isn't found is part of the error.
+    let output = quiet(&dir, &["archive", "GitHub"], "");
+    assert!(output.success, "{}", output.stderr);
+    let output = quiet(&dir, &["get", "GitHub", "--show"], "");
+    assert!(!output.success);
+    assert!(output.stderr.contains("use --archived"), "{}", output.stderr);
+}
@@ -0,0 +1,45 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+
+//! Commands that only read passwords leave the password file as it is.
+
+mod common;
+
+use common::{create_password_file, password_file, rooster_with_env, test_dir, MASTER_PASSWORD};
+use std::fs;
+
+#[test]
+fn reading_does_not_rewrite_the_file() {
+    let dir = test_dir("read-only");
+    create_password_file(&dir);
+    let env = [("ROOSTER_MASTER_PASSWORD", MASTER_PASSWORD)];
+    let output = rooster_with_env(&dir,
+                                  &["add-or-update", "YouTube", "me", "--password-stdin"],
+                                  "secret\n",
+                                  &env);
+    assert!(output.success, "{}", output.stderr);
+    let before = fs::read(password_file(&dir)).unwrap();
+    let modified = fs::metadata(password_file(&dir)).unwrap().modified().unwrap();
+    let previous = fs::read(dir.join("passwords.rooster.prev")).unwrap();
+
+    for args in [&["list"][..], &["get", "YouTube", "--stdout"], &["export"]].iter() {
+        let output = rooster_with_env(&dir, args, "", &env);
+        assert!(output.success, "{:?}: {}", args, output.stderr);
+        assert_eq!(fs::read(password_file(&dir)).unwrap(), before, "{:?}", args);
+    }
+    assert_eq!(fs::metadata(password_file(&dir)).unwrap().modified().unwrap(), modified);
+    assert_eq!(fs::read(dir.join("passwords.rooster.prev")).unwrap(), previous);
+}
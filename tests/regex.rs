@@ -0,0 +1,62 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! With --regex, the query is a regular expression, and a bad one is reported before anything
+//! else happens.
+
+mod common;
+
+use common::{create_password_file, rooster, test_dir, MASTER_PASSWORD};
+use std::fs;
+
+#[test]
+fn searches_with_a_regex() {
+    let dir = test_dir("regex");
+    create_password_file(&dir);
+    for name in &["Work Mail", "Home Mail", "Workbench"] {
+        let output = rooster(&dir,
+                             &["add-or-update", name, "me@example.com", "--password-stdin"],
+                             &format!("{}\nsecret\n", MASTER_PASSWORD));
+        assert!(output.success, "{}", output.stderr);
+    }
+    let input = format!("{}\n", MASTER_PASSWORD);
+
+    let output = rooster(&dir, &["list", "^(WORK|home) mail$", "--regex", "--porcelain"], &input);
+    assert!(output.success, "{}", output.stderr);
+    assert_eq!(output.stdout, "Home Mail\tme@example.com\nWork Mail\tme@example.com\n");
+
+    let output = rooster(&dir, &["get", "bench$", "--regex", "--stdout"], &input);
+    assert!(output.success, "{}", output.stderr);
+    assert_eq!(output.stdout, "secret\n");
+
+    let output = rooster(&dir, &["list", "^mail", "--regex"], &input);
+    assert!(!output.success);
+
+    let output = rooster(&dir, &["get", "^mail", "--regex", "--stdout"], &input);
+    assert!(!output.success);
+    assert!(output.stderr.contains("can't find any passwords matching"), "{}", output.stderr);
+
+    // Other commands pick passwords by their name.
+    let output = rooster(&dir, &["delete", "^work", "--regex"], &input);
+    assert_eq!(output.code, Some(2), "{}", output.stderr);
+    assert!(output.stderr.contains("only works with get and list"), "{}", output.stderr);
+
+    // Nothing is read, not even the master password.
+    let output = rooster(&dir, &["get", "(work", "--regex"], "");
+    assert!(!output.success);
+    assert!(output.stderr.contains("isn't a valid regular expression"), "{}", output.stderr);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,80 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! Rekeying gives the password file a new salt and a new key, and it still opens.
+
+mod common;
+
+use common::{create_password_file, password_file, rooster, rooster_with_env, test_dir,
+             MASTER_PASSWORD};
+use std::fs;
+
+fn fingerprint<'a>(stdout: &'a str, which: &str) -> &'a str {
+    let prefix = format!("{} fingerprint: ", which);
+    stdout.lines()
+        .find(|line| line.starts_with(&prefix))
+        .map(|line| &line[prefix.len()..])
+        .unwrap_or_else(|| panic!("{}", stdout))
+}
+
+#[test]
+fn rekey_changes_the_salt_and_keeps_the_passwords() {
+    let dir = test_dir("rekey");
+    create_password_file(&dir);
+    let env = [("ROOSTER_MASTER_PASSWORD", MASTER_PASSWORD)];
+    let output = rooster_with_env(&dir,
+                                  &["add", "GitHub", "me", "--password-stdin"],
+                                  "secret\n",
+                                  &env);
+    assert!(output.success, "{}", output.stderr);
+    let before = fs::read(password_file(&dir)).unwrap();
+
+    let output = rooster_with_env(&dir, &["rekey"], "", &env);
+    assert!(output.success, "{}", output.stderr);
+    let (old, new) = (fingerprint(&output.stdout, "Old"), fingerprint(&output.stdout, "New"));
+    assert_eq!(old.len(), 64);
+    assert!(old != new, "{}", output.stdout);
+
+    // Version and Argon2id params are the same, then comes the salt.
+    let after = fs::read(password_file(&dir)).unwrap();
+    assert_eq!(&before[..16], &after[..16]);
+    assert!(before[16..48] != after[16..48]);
+
+    let output = rooster_with_env(&dir, &["get", "GitHub", "--stdout"], "", &env);
+    assert!(output.stdout.contains("secret"), "{}", output.stderr);
+}
+
+#[test]
+fn rekey_checks_the_master_password_again() {
+    let dir = test_dir("rekey-wrong");
+    create_password_file(&dir);
+    let before = fs::read(password_file(&dir)).unwrap();
+
+    let output = rooster(&dir, &["rekey"], &format!("{}\nwrong\n", MASTER_PASSWORD));
+    assert_eq!(output.code, Some(4), "{}", output.stderr);
+    assert_eq!(fs::read(password_file(&dir)).unwrap(), before);
+}
+
+#[test]
+fn rekey_in_a_batch_shows_the_new_fingerprint() {
+    let dir = test_dir("rekey-batch");
+    create_password_file(&dir);
+    let env = [("ROOSTER_MASTER_PASSWORD", MASTER_PASSWORD)];
+
+    let output = rooster_with_env(&dir, &["batch"], "rekey\n", &env);
+    assert!(output.success, "{}", output.stderr);
+    let (old, new) = (fingerprint(&output.stdout, "Old"), fingerprint(&output.stdout, "New"));
+    assert!(old != new, "{}", output.stdout);
+}
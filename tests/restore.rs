@@ -0,0 +1,99 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+
+//! Every save keeps the version it replaces, for `rooster restore --previous`.
+
+mod common;
+
+use common::{create_password_file, password_file, rooster_with_env, test_dir, MASTER_PASSWORD};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const ENV: [(&'static str, &'static str); 1] = [("ROOSTER_MASTER_PASSWORD", MASTER_PASSWORD)];
+
+fn previous_file(dir: &Path) -> PathBuf {
+    dir.join("passwords.rooster.prev")
+}
+
+fn add(dir: &Path, args: &[&str], name: &str, vars: &[(&str, &str)]) {
+    let mut args = args.to_vec();
+    args.extend_from_slice(&["add-or-update", name, "me", "--password-stdin"]);
+    let output = rooster_with_env(dir, &args, "secret\n", vars);
+    assert!(output.success, "{}", output.stderr);
+}
+
+fn names(dir: &Path) -> String {
+    rooster_with_env(dir, &["list", "--names-only"], "", &ENV).stdout
+}
+
+#[test]
+fn restoring_swaps_with_the_previous_version() {
+    let dir = test_dir("restore");
+    create_password_file(&dir);
+    add(&dir, &[], "First", &ENV);
+    add(&dir, &[], "Second", &ENV);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(previous_file(&dir)).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    let output = rooster_with_env(&dir, &["restore", "--previous"], "n\n", &ENV);
+    assert!(output.success, "{}", output.stderr);
+    assert_eq!(names(&dir), "First\nSecond\n");
+
+    let output = rooster_with_env(&dir, &["restore", "--previous"], "y\n", &ENV);
+    assert!(output.success, "{}", output.stderr);
+    assert_eq!(names(&dir), "First\n");
+    // The version that was replaced can be brought back the same way.
+    let output = rooster_with_env(&dir, &["restore", "--previous"], "y\n", &ENV);
+    assert!(output.success, "{}", output.stderr);
+    assert_eq!(names(&dir), "First\nSecond\n");
+
+    let output = rooster_with_env(&dir, &["restore"], "y\n", &ENV);
+    assert_eq!(output.code, Some(2), "{}", output.stderr);
+}
+
+#[test]
+fn the_previous_version_must_open() {
+    let dir = test_dir("restore-wrong-password");
+    create_password_file(&dir);
+    add(&dir, &[], "First", &ENV);
+    add(&dir, &[], "Second", &ENV);
+    let before = fs::read(password_file(&dir)).unwrap();
+
+    let env = [("ROOSTER_MASTER_PASSWORD", "wrong")];
+    let output = rooster_with_env(&dir, &["restore", "--previous"], "y\n", &env);
+    assert_eq!(output.code, Some(4), "{}", output.stderr);
+    assert_eq!(fs::read(password_file(&dir)).unwrap(), before);
+}
+
+#[test]
+fn backups_can_be_turned_off() {
+    let dir = test_dir("restore-no-backup");
+    create_password_file(&dir);
+    add(&dir, &["--no-backup"], "First", &ENV);
+    assert!(!previous_file(&dir).exists());
+    add(&dir,
+        &[],
+        "Second",
+        &[("ROOSTER_MASTER_PASSWORD", MASTER_PASSWORD), ("ROOSTER_NO_BACKUP", "1")]);
+    assert!(!previous_file(&dir).exists());
+
+    let output = rooster_with_env(&dir, &["restore", "--previous"], "y\n", &ENV);
+    assert_eq!(output.code, Some(6), "{}", output.stderr);
+}
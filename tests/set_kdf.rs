@@ -0,0 +1,94 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! The key derivation of a password file can be changed, and is kept in the file.
+
+mod common;
+
+use common::{create_password_file, password_file, rooster, rooster_with_env, test_dir,
+             MASTER_PASSWORD};
+use std::fs;
+
+fn header(dir: &std::path::Path) -> Vec<u8> {
+    fs::read(password_file(dir)).unwrap()[..13].to_vec()
+}
+
+#[test]
+fn scrypt_params_are_kept_in_the_file() {
+    let dir = test_dir("set-kdf");
+    create_password_file(&dir);
+    let env = [("ROOSTER_MASTER_PASSWORD", MASTER_PASSWORD)];
+    let output = rooster_with_env(&dir,
+                                  &["add", "GitHub", "me", "--password-stdin"],
+                                  "secret\n",
+                                  &env);
+    assert!(output.success, "{}", output.stderr);
+
+    let output = rooster_with_env(&dir,
+                                  &["set-kdf", "--log-n", "13", "--scrypt-r", "4", "--scrypt-p",
+                                    "2"],
+                                  "",
+                                  &env);
+    assert!(output.success, "{}", output.stderr);
+    assert_eq!(header(&dir), vec![0, 0, 0, 2, 13, 0, 0, 0, 4, 0, 0, 0, 2]);
+
+    let output = rooster_with_env(&dir, &["get", "GitHub", "--stdout"], "", &env);
+    assert!(output.success, "{}", output.stderr);
+    assert!(output.stdout.contains("secret"), "{}", output.stdout);
+
+    let output = rooster_with_env(&dir, &["set-kdf", "--argon2id"], "", &env);
+    assert!(output.success, "{}", output.stderr);
+    assert_eq!(&header(&dir)[..4], &[0, 0, 0, 4]);
+    let output = rooster_with_env(&dir, &["get", "GitHub", "--stdout"], "", &env);
+    assert!(output.stdout.contains("secret"), "{}", output.stderr);
+}
+
+#[test]
+fn absurd_params_are_refused() {
+    let dir = test_dir("set-kdf-absurd");
+    create_password_file(&dir);
+    let before = fs::read(password_file(&dir)).unwrap();
+
+    let output = rooster(&dir, &["set-kdf", "--log-n", "25"], "");
+    assert_eq!(output.code, Some(2), "{}", output.stderr);
+    assert!(output.stderr.contains("32768 MiB of memory"), "{}", output.stderr);
+
+    let output = rooster(&dir, &["set-kdf", "--log-n", "12", "--scrypt-p", "17"], "");
+    assert_eq!(output.code, Some(2), "{}", output.stderr);
+    assert!(output.stderr.contains("17 times as long"), "{}", output.stderr);
+
+    for args in &[&["set-kdf"][..],
+                  &["set-kdf", "--log-n", "twelve"],
+                  &["set-kdf", "--log-n", "12", "--argon2id"],
+                  &["set-kdf", "--argon2id", "--memory-mib", "0"],
+                  &["set-kdf", "--log-n", "12", "--memory-mib", "64"]] {
+        let output = rooster(&dir, args, "");
+        assert_eq!(output.code, Some(2), "{}", output.stderr);
+    }
+    assert_eq!(fs::read(password_file(&dir)).unwrap(), before);
+}
+
+#[test]
+fn the_master_password_is_checked_again() {
+    let dir = test_dir("set-kdf-wrong");
+    create_password_file(&dir);
+    let before = fs::read(password_file(&dir)).unwrap();
+
+    let output = rooster(&dir,
+                         &["set-kdf", "--log-n", "13"],
+                         &format!("{}\nwrong\n", MASTER_PASSWORD));
+    assert_eq!(output.code, Some(4), "{}", output.stderr);
+    assert_eq!(fs::read(password_file(&dir)).unwrap(), before);
+}
@@ -0,0 +1,119 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! A typo or a weak choice in the new master password must not lock anyone out.
+
+mod common;
+
+use common::{create_password_file, rooster, test_dir, MASTER_PASSWORD};
+use std::fs;
+use std::path::Path;
+
+const NEW_MASTER_PASSWORD: &'static str = "purple monkey dishwasher";
+
+fn master_password_works(dir: &Path, master_password: &str) -> bool {
+    rooster(dir, &["list"], &format!("{}\n", master_password)).success
+}
+
+#[test]
+fn refuses_a_mismatched_confirmation() {
+    let dir = test_dir("set-master-mismatch");
+    create_password_file(&dir);
+
+    let input = format!("{}\n{}\n{}x\n", MASTER_PASSWORD, NEW_MASTER_PASSWORD, NEW_MASTER_PASSWORD);
+    let output = rooster(&dir, &["set-master-password"], &input);
+    assert!(!output.success);
+    assert!(output.stderr.contains("did not match"), "{}", output.stderr);
+    assert!(master_password_works(&dir, MASTER_PASSWORD));
+
+    let input = format!("{}\n{}\n{}\n", MASTER_PASSWORD, NEW_MASTER_PASSWORD, NEW_MASTER_PASSWORD);
+    let output = rooster(&dir, &["set-master-password"], &input);
+    assert!(output.success, "{}", output.stderr);
+    assert!(output.stderr.contains("about 10^"), "{}", output.stderr);
+    assert!(!output.stderr.contains("weak"), "{}", output.stderr);
+    assert!(master_password_works(&dir, NEW_MASTER_PASSWORD));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn asks_before_using_a_weak_password() {
+    let dir = test_dir("set-master-weak");
+    create_password_file(&dir);
+
+    let input = format!("{}\nletmein\nletmein\nn\n", MASTER_PASSWORD);
+    let output = rooster(&dir, &["set-master-password"], &input);
+    assert!(!output.success);
+    assert!(output.stderr.contains("most common passwords"), "{}", output.stderr);
+    assert!(output.stderr.contains("shorter than 10 characters"), "{}", output.stderr);
+    assert!(master_password_works(&dir, MASTER_PASSWORD));
+
+    let input = format!("{}\nletmein\nletmein\ny\n", MASTER_PASSWORD);
+    let output = rooster(&dir, &["set-master-password"], &input);
+    assert!(output.success, "{}", output.stderr);
+    assert!(master_password_works(&dir, "letmein"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn adds_and_removes_a_key_file() {
+    let dir = test_dir("set-master-key-file");
+    create_password_file(&dir);
+    let key_file = dir.join("usb.key");
+    fs::write(&key_file, "random bytes from a usb stick").unwrap();
+    let key_file = key_file.to_string_lossy().into_owned();
+    let other_key_file = dir.join("other.key");
+    fs::write(&other_key_file, "random bytes from a usb stick!").unwrap();
+    let other_key_file = other_key_file.to_string_lossy().into_owned();
+
+    let input = format!("{}\n{}\n{}\n", MASTER_PASSWORD, NEW_MASTER_PASSWORD, NEW_MASTER_PASSWORD);
+    let output = rooster(&dir, &["set-master-password", "--new-key-file", &key_file], &input);
+    assert!(output.success, "{}", output.stderr);
+
+    let list = |args: &[&str]| {
+        let mut args = args.to_vec();
+        args.push("list");
+        rooster(&dir, &args, &format!("{}\n", NEW_MASTER_PASSWORD))
+    };
+    let output = list(&[]);
+    assert!(!output.success);
+    assert!(output.stderr.contains("needs its key file"), "{}", output.stderr);
+    let output = list(&["--key-file", &other_key_file]);
+    assert!(!output.success);
+    assert!(output.stderr.contains("isn't the key file"), "{}", output.stderr);
+    assert!(list(&["--key-file", &key_file]).success);
+
+    // The key file is kept with a new master password, until it is removed.
+    // The test master password is a famous weak one.
+    let input = format!("{}\n{}\n{}\ny\n", NEW_MASTER_PASSWORD, MASTER_PASSWORD, MASTER_PASSWORD);
+    let output = rooster(&dir, &["--key-file", &key_file, "set-master-password"], &input);
+    assert!(output.success, "{}", output.stderr);
+    assert!(!master_password_works(&dir, MASTER_PASSWORD));
+    let input = format!("{}\n{}\n{}\ny\n", MASTER_PASSWORD, MASTER_PASSWORD, MASTER_PASSWORD);
+    let output = rooster(&dir,
+                         &["--key-file", &key_file, "set-master-password", "--no-key-file"],
+                         &input);
+    assert!(output.success, "{}", output.stderr);
+    assert!(master_password_works(&dir, MASTER_PASSWORD));
+
+    let output = rooster(&dir,
+                         &["--key-file", &key_file, "list"],
+                         &format!("{}\n", MASTER_PASSWORD));
+    assert!(!output.success);
+    assert!(output.stderr.contains("doesn't use a key file"), "{}", output.stderr);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
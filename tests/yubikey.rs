@@ -0,0 +1,109 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! Password files that need a YubiKey, with a fake ykchalresp. Without the yubikey feature,
+//! they can't be set up.
+
+#![cfg(unix)]
+
+mod common;
+
+use common::{create_password_file, rooster_with_env, test_dir, Output, MASTER_PASSWORD};
+use std::fs;
+use std::os::unix::fs::{symlink, PermissionsExt};
+use std::path::Path;
+
+const NEW_MASTER_PASSWORD: &'static str = "purple monkey dishwasher";
+
+/// Runs rooster with a fake ykchalresp, which runs `script`.
+fn run_with_ykchalresp(dir: &Path, args: &[&str], input: &str, script: Option<&str>) -> Output {
+    let tools = dir.join("tools");
+    let _ = fs::remove_dir_all(&tools);
+    fs::create_dir_all(&tools).unwrap();
+    symlink("/bin/sh", tools.join("sh")).unwrap();
+    if let Some(script) = script {
+        let path = tools.join("ykchalresp");
+        fs::write(&path, format!("#!/bin/sh\n{}", script)).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    let path = tools.to_string_lossy().into_owned();
+    rooster_with_env(dir, args, input, &[("PATH", &path)])
+}
+
+#[cfg(feature = "yubikey")]
+#[test]
+fn needs_the_yubikey_once_set_up() {
+    let dir = test_dir("yubikey");
+    create_password_file(&dir);
+    // Slot 2, the challenge in hex, and a response of 20 bytes.
+    let yubikey = "[ \"$1\" = -2 ] && [ \"$2\" = -x ] && [ ${#3} = 128 ] || exit 2\n\
+                   echo 0123456789abcdef0123456789abcdef01234567\n";
+
+    let input = format!("{}\n{}\n{}\n", MASTER_PASSWORD, NEW_MASTER_PASSWORD, NEW_MASTER_PASSWORD);
+    let output = run_with_ykchalresp(&dir,
+                                     &["set-master-password", "--yubikey"],
+                                     &input,
+                                     Some(yubikey));
+    assert!(output.success, "{}", output.stderr);
+    assert!(output.stderr.contains("Touch your YubiKey"), "{}", output.stderr);
+
+    let input = format!("{}\n", NEW_MASTER_PASSWORD);
+    let output = run_with_ykchalresp(&dir, &["list"], &input, Some(yubikey));
+    assert!(output.success, "{}", output.stderr);
+
+    let output = run_with_ykchalresp(&dir, &["list"], &input, None);
+    assert!(!output.success);
+    assert!(output.stderr.contains("ykchalresp isn't installed"), "{}", output.stderr);
+
+    let absent = "echo 'Yubikey core error: no yubikey present' >&2\nexit 1\n";
+    let output = run_with_ykchalresp(&dir, &["list"], &input, Some(absent));
+    assert!(!output.success);
+    assert!(output.stderr.contains("no YubiKey is plugged in"), "{}", output.stderr);
+
+    let other = "echo 76543210fedcba9876543210fedcba9876543210\n";
+    let output = run_with_ykchalresp(&dir, &["list"], &input, Some(other));
+    assert!(!output.success);
+    assert!(output.stderr.contains("not the right password"), "{}", output.stderr);
+
+    let input = format!("{}\n{0}\n{0}\n", NEW_MASTER_PASSWORD);
+    let output = run_with_ykchalresp(&dir,
+                                     &["set-master-password", "--no-yubikey"],
+                                     &input,
+                                     Some(yubikey));
+    assert!(output.success, "{}", output.stderr);
+    let output = run_with_ykchalresp(&dir, &["list"], &format!("{}\n", NEW_MASTER_PASSWORD), None);
+    assert!(output.success, "{}", output.stderr);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(not(feature = "yubikey"))]
+#[test]
+fn needs_the_yubikey_feature() {
+    let dir = test_dir("yubikey-unsupported");
+    create_password_file(&dir);
+
+    let input = format!("{}\n{}\n{}\n", MASTER_PASSWORD, NEW_MASTER_PASSWORD, NEW_MASTER_PASSWORD);
+    let output = run_with_ykchalresp(&dir,
+                                     &["set-master-password", "--yubikey"],
+                                     &input,
+                                     Some("echo 00\n"));
+    assert!(!output.success);
+    assert!(output.stderr.contains("built without YubiKey support"), "{}", output.stderr);
+    let output = run_with_ykchalresp(&dir, &["list"], &format!("{}\n", MASTER_PASSWORD), None);
+    assert!(output.success, "{}", output.stderr);
+
+    fs::remove_dir_all(&dir).unwrap();
+}